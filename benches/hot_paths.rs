@@ -0,0 +1,155 @@
+//! Benchmarks for the per-command hot paths: parsing requests, serializing replies, and
+//! dispatching commands through the handler. Inputs are built up front, so only the path
+//! itself is measured. Run with `cargo bench`, or e.g. `cargo bench -- dispatch` for one group.
+
+use criterion::{black_box, criterion_group, criterion_main, BatchSize, Criterion, Throughput};
+use std::collections::HashMap;
+use std::io::Cursor;
+use std::sync::{Arc, RwLock};
+
+use redis_from_scratch::config::Config;
+use redis_from_scratch::handler::CommandHandler;
+use redis_from_scratch::resp::{Resp, RespData};
+
+fn command(args: &[&str]) -> RespData {
+    RespData::Array(
+        args.iter()
+            .map(|arg| RespData::BulkString(arg.to_string()))
+            .collect(),
+    )
+}
+
+fn encode(values: &[RespData]) -> Vec<u8> {
+    let mut bytes = Vec::new();
+    for value in values {
+        value.write(&mut bytes).unwrap();
+    }
+    bytes
+}
+
+fn bench_parse(c: &mut Criterion) {
+    let pipeline: Vec<RespData> = (0..100)
+        .map(|i| command(&["SET", &format!("key:{i}"), "value"]))
+        .collect();
+    let inputs = [
+        ("command", vec![command(&["SET", "key", "value"])]),
+        ("pipeline of 100", pipeline),
+        (
+            "64KB bulk string",
+            vec![command(&["SET", "key", &"x".repeat(64 * 1024)])],
+        ),
+        (
+            "nested reply",
+            vec![RespData::Array(
+                (0..10)
+                    .map(|i| {
+                        RespData::Array(vec![
+                            RespData::Integer(i),
+                            RespData::SimpleString("OK".to_string()),
+                            RespData::BulkString(format!("value:{i}")),
+                            RespData::Null,
+                        ])
+                    })
+                    .collect(),
+            )],
+        ),
+    ];
+
+    let mut group = c.benchmark_group("parse");
+    for (name, values) in inputs {
+        let bytes = encode(&values);
+        group.throughput(Throughput::Bytes(bytes.len() as u64));
+        group.bench_function(name, |b| {
+            b.iter(|| {
+                let mut reader = Resp::new(Cursor::new(black_box(&bytes)));
+                for _ in 0..values.len() {
+                    black_box(reader.read().unwrap());
+                }
+            })
+        });
+    }
+    group.finish();
+}
+
+fn bench_serialize(c: &mut Criterion) {
+    let replies = [
+        ("status", RespData::SimpleString("OK".to_string())),
+        ("integer", RespData::Integer(1234567)),
+        ("1KB bulk string", RespData::BulkString("x".repeat(1024))),
+        (
+            "array of 100",
+            RespData::Array(
+                (0..100)
+                    .map(|i| RespData::BulkString(format!("field:{i}")))
+                    .collect(),
+            ),
+        ),
+    ];
+
+    let mut group = c.benchmark_group("serialize");
+    for (name, reply) in replies {
+        let mut buffer = Vec::new();
+        reply.write(&mut buffer).unwrap();
+        group.throughput(Throughput::Bytes(buffer.len() as u64));
+        group.bench_function(name, |b| {
+            b.iter(|| {
+                buffer.clear();
+                black_box(&reply).write(&mut buffer).unwrap();
+            })
+        });
+    }
+    group.finish();
+}
+
+/// A handler holding 10,000 string keys and a 100-field hash.
+fn populated_handler() -> CommandHandler {
+    let mut handler = CommandHandler::new(HashMap::new(), Arc::new(RwLock::new(Config::default())));
+    for i in 0..10_000 {
+        handler.handle(&command(&["SET", &format!("key:{i}"), "value"]));
+    }
+    for i in 0..100 {
+        handler.handle(&command(&["HSET", "hash", &format!("field:{i}"), "value"]));
+    }
+    handler
+}
+
+fn bench_dispatch(c: &mut Criterion) {
+    let commands = [
+        ("GET hit", command(&["GET", "key:5000"])),
+        ("GET miss", command(&["GET", "missing"])),
+        ("SET overwrite", command(&["SET", "key:5000", "other"])),
+        (
+            "HSET existing field",
+            command(&["HSET", "hash", "field:50", "other"]),
+        ),
+        ("HGETALL 100 fields", command(&["HGETALL", "hash"])),
+    ];
+
+    let mut group = c.benchmark_group("dispatch");
+    let mut handler = populated_handler();
+    for (name, request) in &commands {
+        group.bench_function(*name, |b| {
+            b.iter(|| black_box(handler.handle(black_box(request))))
+        });
+    }
+    // Fresh keys into an empty handler measure inserting, including the keyspace growing.
+    let inserts: Vec<RespData> = (0..100)
+        .map(|i| command(&["SET", &format!("new:{i}"), "value"]))
+        .collect();
+    group.throughput(Throughput::Elements(inserts.len() as u64));
+    group.bench_function("SET 100 new keys", |b| {
+        b.iter_batched_ref(
+            || CommandHandler::new(HashMap::new(), Arc::new(RwLock::new(Config::default()))),
+            |handler| {
+                for request in &inserts {
+                    black_box(handler.handle(request));
+                }
+            },
+            BatchSize::SmallInput,
+        )
+    });
+    group.finish();
+}
+
+criterion_group!(benches, bench_parse, bench_serialize, bench_dispatch);
+criterion_main!(benches);
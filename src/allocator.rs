@@ -0,0 +1,69 @@
+//! The optional jemalloc global allocator and the statistics it exposes.
+
+#[cfg(feature = "jemalloc")]
+#[global_allocator]
+static GLOBAL: tikv_jemallocator::Jemalloc = tikv_jemallocator::Jemalloc;
+
+/// Byte counts reported by the allocator.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct AllocatorStats {
+    /// Bytes handed out to the application.
+    pub allocated: u64,
+    /// Bytes in pages holding live allocations.
+    pub active: u64,
+    /// Bytes of physical memory the allocator is holding on to.
+    pub resident: u64,
+}
+
+/// The allocator in use, as reported by INFO memory's `mem_allocator`.
+pub fn name() -> String {
+    #[cfg(feature = "jemalloc")]
+    {
+        let version = tikv_jemalloc_ctl::version::read().unwrap_or("unknown");
+        // jemalloc reports e.g. "5.3.0-0-g54eaed1d8b56b1aa528be3bdd1877e59c56fa90c".
+        let version = version.split('-').next().unwrap_or(version);
+        format!("jemalloc-{version}")
+    }
+    #[cfg(not(feature = "jemalloc"))]
+    {
+        "libc".to_string()
+    }
+}
+
+/// Current allocator statistics, or `None` on the system allocator, which doesn't keep any.
+pub fn stats() -> Option<AllocatorStats> {
+    #[cfg(feature = "jemalloc")]
+    {
+        use tikv_jemalloc_ctl::{epoch, stats};
+        // jemalloc caches its statistics until the epoch is advanced.
+        epoch::advance().ok()?;
+        Some(AllocatorStats {
+            allocated: stats::allocated::read().ok()? as u64,
+            active: stats::active::read().ok()? as u64,
+            resident: stats::resident::read().ok()? as u64,
+        })
+    }
+    #[cfg(not(feature = "jemalloc"))]
+    {
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_stats() {
+        let stats = stats();
+        if cfg!(feature = "jemalloc") {
+            let stats = stats.unwrap();
+            assert!(stats.allocated > 0);
+            assert!(stats.active >= stats.allocated);
+            assert!(name().starts_with("jemalloc-"));
+        } else {
+            assert_eq!(stats, None);
+            assert_eq!(name(), "libc");
+        }
+    }
+}
@@ -0,0 +1,576 @@
+//! A redis-benchmark for this server: every test opens a number of connections that send
+//! pipelined batches of one command until the requested total has been sent, then reports the
+//! throughput and the latency distribution.
+
+use std::collections::hash_map::RandomState;
+use std::env;
+use std::hash::{BuildHasher, Hasher};
+use std::io::{self, BufWriter, Write};
+use std::net::TcpStream;
+use std::process;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use redis_from_scratch::resp::{Resp, RespData};
+
+const DEFAULT_HOST: &str = "127.0.0.1";
+const DEFAULT_PORT: u16 = 6379;
+
+/// Every test, in the order they run. The server has no lists, so LPUSH only runs when asked
+/// for with -t.
+const TESTS: [&str; 6] = ["ping", "set", "get", "incr", "hset", "lpush"];
+const DEFAULT_TESTS: [&str; 5] = ["ping", "set", "get", "incr", "hset"];
+
+const USAGE: &str = "\
+Usage: benchmark [OPTIONS]
+  -h <hostname>      Server hostname (default 127.0.0.1)
+  -p <port>          Server port (default 6379)
+  -a <password>      Password for the server
+  -c <clients>       Number of parallel connections (default 50)
+  -n <requests>      Total number of requests (default 100000)
+  -d <size>          Data size of SET/GET/HSET/LPUSH values in bytes (default 3)
+  -P <numreq>        Pipeline <numreq> requests. Default 1 (no pipeline).
+  -r <keyspacelen>   Use random keys for SET/GET/INCR and random fields for HSET.
+                     Keys are picked from 0 to keyspacelen-1, so the benchmark
+                     hits that many distinct keys.
+  -t <tests>         Only run the comma separated list of tests, out of
+                     ping, set, get, incr, hset and lpush (default all but lpush).
+  -q                 Quiet. Just show the requests per second and p50 latency.
+  --help             Output this help and exit.";
+
+#[derive(Debug, PartialEq)]
+struct Options {
+    host: String,
+    port: u16,
+    password: Option<String>,
+    clients: u64,
+    requests: u64,
+    data_size: usize,
+    pipeline: u64,
+    /// How many distinct random keys to use, or `None` to use one fixed key.
+    keyspace: Option<u64>,
+    tests: Vec<&'static str>,
+    quiet: bool,
+}
+
+impl Default for Options {
+    fn default() -> Self {
+        Self {
+            host: DEFAULT_HOST.to_string(),
+            port: DEFAULT_PORT,
+            password: None,
+            clients: 50,
+            requests: 100_000,
+            data_size: 3,
+            pipeline: 1,
+            keyspace: None,
+            tests: DEFAULT_TESTS.to_vec(),
+            quiet: false,
+        }
+    }
+}
+
+/// Parses the arguments after the program name.
+fn parse_options(args: impl IntoIterator<Item = String>) -> Result<Options, String> {
+    let mut options = Options::default();
+    let mut args = args.into_iter();
+    while let Some(arg) = args.next() {
+        let mut value = || {
+            args.next()
+                .ok_or_else(|| format!("Missing value for {arg}"))
+        };
+        // A count that must be at least 1.
+        let positive = |value: String| {
+            value
+                .parse()
+                .ok()
+                .filter(|&n| n > 0)
+                .ok_or_else(|| format!("Invalid value '{value}' for {arg}"))
+        };
+        match arg.as_str() {
+            "-h" => options.host = value()?,
+            "-p" => {
+                let port = value()?;
+                options.port = port.parse().map_err(|_| format!("Invalid port '{port}'"))?;
+            }
+            "-a" => options.password = Some(value()?),
+            "-c" => options.clients = positive(value()?)?,
+            "-n" => options.requests = positive(value()?)?,
+            "-d" => options.data_size = positive(value()?)? as usize,
+            "-P" => options.pipeline = positive(value()?)?,
+            "-r" => options.keyspace = Some(positive(value()?)?),
+            "-t" => {
+                let tests = value()?.to_ascii_lowercase();
+                options.tests = TESTS
+                    .into_iter()
+                    .filter(|test| tests.split(',').any(|t| t == *test))
+                    .collect();
+                if options.tests.is_empty() {
+                    return Err(format!("No known tests in '{tests}'"));
+                }
+            }
+            "-q" => options.quiet = true,
+            "--help" => return Err(USAGE.to_string()),
+            _ => {
+                return Err(format!(
+                    "Unrecognized option or bad number of args for: '{arg}'"
+                ))
+            }
+        }
+    }
+    Ok(options)
+}
+
+/// xorshift64*, seeded per connection, for picking random keys.
+struct Rng(u64);
+
+impl Rng {
+    fn new() -> Self {
+        // A hasher's keys are random, and a zero state would stay zero.
+        Self(RandomState::new().build_hasher().finish() | 1)
+    }
+
+    fn next(&mut self) -> u64 {
+        let mut x = self.0;
+        x ^= x >> 12;
+        x ^= x << 25;
+        x ^= x >> 27;
+        self.0 = x;
+        x.wrapping_mul(0x2545_f491_4f6c_dd1d)
+    }
+}
+
+/// Builds the commands for one test, choosing a new random key for each when a keyspace
+/// length was given.
+struct CommandBuilder<'a> {
+    test: &'a str,
+    data: String,
+    keyspace: Option<u64>,
+    rng: Rng,
+}
+
+impl<'a> CommandBuilder<'a> {
+    fn new(test: &'a str, options: &Options, rng: Rng) -> Self {
+        Self {
+            test,
+            data: "x".repeat(options.data_size),
+            keyspace: options.keyspace,
+            rng,
+        }
+    }
+
+    /// The random part of a key: a zero-padded number below the keyspace length, or the
+    /// same placeholder redis-benchmark uses when there is no keyspace.
+    fn random(&mut self) -> String {
+        match self.keyspace {
+            Some(len) => format!("{:012}", self.rng.next() % len),
+            None => "__rand_int__".to_string(),
+        }
+    }
+
+    fn next(&mut self) -> Vec<String> {
+        match self.test {
+            "ping" => vec!["PING".to_string()],
+            "set" => vec![
+                "SET".to_string(),
+                format!("key:{}", self.random()),
+                self.data.clone(),
+            ],
+            "get" => vec!["GET".to_string(), format!("key:{}", self.random())],
+            "incr" => vec!["INCR".to_string(), format!("counter:{}", self.random())],
+            "hset" => vec![
+                "HSET".to_string(),
+                "myhash".to_string(),
+                format!("element:{}", self.random()),
+                self.data.clone(),
+            ],
+            "lpush" => vec!["LPUSH".to_string(), "mylist".to_string(), self.data.clone()],
+            test => unreachable!("unknown test {test}"),
+        }
+    }
+}
+
+/// What one connection saw during a test.
+#[derive(Default)]
+struct ClientResult {
+    /// The latency of every request, in microseconds.
+    latencies: Vec<u64>,
+    errors: u64,
+    first_error: Option<String>,
+}
+
+struct Connection {
+    reader: Resp<TcpStream>,
+    writer: BufWriter<TcpStream>,
+}
+
+impl Connection {
+    fn open(options: &Options) -> io::Result<Self> {
+        let stream = TcpStream::connect((options.host.as_str(), options.port))?;
+        stream.set_nodelay(true)?;
+        let mut connection = Self {
+            reader: Resp::new(stream.try_clone()?),
+            writer: BufWriter::new(stream),
+        };
+        if let Some(password) = &options.password {
+            connection.send(&["AUTH".to_string(), password.clone()])?;
+            connection.writer.flush()?;
+            if let RespData::Error(e) = connection.reader.read()? {
+                return Err(io::Error::other(format!(
+                    "AUTH failed: {}",
+                    e.trim_start_matches('-')
+                )));
+            }
+        }
+        Ok(connection)
+    }
+
+    fn send(&mut self, args: &[String]) -> io::Result<()> {
+        let command = RespData::Array(args.iter().cloned().map(RespData::BulkString).collect());
+        command.write(&mut self.writer)
+    }
+}
+
+/// Runs one connection's share of a test: batches of up to `pipeline` commands, until
+/// `issued` reaches the total. Every request in a batch is charged the batch's round trip.
+fn run_client(options: &Options, test: &str, issued: &AtomicU64) -> io::Result<ClientResult> {
+    let mut connection = Connection::open(options)?;
+    let mut commands = CommandBuilder::new(test, options, Rng::new());
+    let mut result = ClientResult::default();
+    loop {
+        let start = issued.fetch_add(options.pipeline, Ordering::Relaxed);
+        if start >= options.requests {
+            return Ok(result);
+        }
+        let batch = options.pipeline.min(options.requests - start);
+
+        let sent_at = Instant::now();
+        for _ in 0..batch {
+            connection.send(&commands.next())?;
+        }
+        connection.writer.flush()?;
+        for _ in 0..batch {
+            if let RespData::Error(e) = connection.reader.read()? {
+                result.errors += 1;
+                // Errors read off the wire keep their leading `-`.
+                result
+                    .first_error
+                    .get_or_insert_with(|| e.trim_start_matches('-').to_string());
+            }
+        }
+        let latency = sent_at.elapsed().as_micros() as u64;
+        result.latencies.extend((0..batch).map(|_| latency));
+    }
+}
+
+/// The `p`th percentile of sorted latencies, by nearest rank.
+fn percentile(sorted: &[u64], p: f64) -> u64 {
+    if sorted.is_empty() {
+        return 0;
+    }
+    let rank = (p / 100.0 * sorted.len() as f64).ceil() as usize;
+    sorted[rank.clamp(1, sorted.len()) - 1]
+}
+
+/// Microseconds as milliseconds, the unit redis-benchmark reports latency in.
+fn msec(us: u64) -> String {
+    format!("{:.3}", us as f64 / 1000.0)
+}
+
+#[derive(Debug, PartialEq)]
+struct Report {
+    requests: u64,
+    elapsed: Duration,
+    /// avg, min, p50, p95, p99 and max, in microseconds.
+    latency: [u64; 6],
+    errors: u64,
+    first_error: Option<String>,
+}
+
+impl Report {
+    fn new(results: Vec<ClientResult>, elapsed: Duration) -> Self {
+        let mut latencies = Vec::new();
+        let mut errors = 0;
+        let mut first_error = None;
+        for result in results {
+            latencies.extend(result.latencies);
+            errors += result.errors;
+            first_error = first_error.or(result.first_error);
+        }
+        latencies.sort_unstable();
+        let avg = latencies.iter().sum::<u64>() / (latencies.len() as u64).max(1);
+        Self {
+            requests: latencies.len() as u64,
+            elapsed,
+            latency: [
+                avg,
+                latencies.first().copied().unwrap_or(0),
+                percentile(&latencies, 50.0),
+                percentile(&latencies, 95.0),
+                percentile(&latencies, 99.0),
+                latencies.last().copied().unwrap_or(0),
+            ],
+            errors,
+            first_error,
+        }
+    }
+
+    fn requests_per_second(&self) -> f64 {
+        self.requests as f64 / self.elapsed.as_secs_f64().max(f64::EPSILON)
+    }
+
+    /// The report, in redis-benchmark's format.
+    fn format(&self, test: &str, options: &Options) -> String {
+        let name = test.to_uppercase();
+        if options.quiet {
+            return format!(
+                "{name}: {:.2} requests per second, p50={} msec\n",
+                self.requests_per_second(),
+                msec(self.latency[2])
+            );
+        }
+        let mut out = format!("====== {name} ======\n");
+        out.push_str(&format!(
+            "  {} requests completed in {:.2} seconds\n",
+            self.requests,
+            self.elapsed.as_secs_f64()
+        ));
+        out.push_str(&format!("  {} parallel clients\n", options.clients));
+        out.push_str(&format!("  {} bytes payload\n", options.data_size));
+        out.push_str("  keep alive: 1\n\n");
+        if self.errors > 0 {
+            out.push_str(&format!(
+                "  {} errors, the first was: {}\n\n",
+                self.errors,
+                self.first_error.as_deref().unwrap_or_default()
+            ));
+        }
+        out.push_str("Summary:\n");
+        out.push_str(&format!(
+            "  throughput summary: {:.2} requests per second\n",
+            self.requests_per_second()
+        ));
+        out.push_str("  latency summary (msec):\n");
+        out.push_str(&format!(
+            "  {:>9} {:>9} {:>9} {:>9} {:>9} {:>9}\n",
+            "avg", "min", "p50", "p95", "p99", "max"
+        ));
+        let row: Vec<String> = self
+            .latency
+            .iter()
+            .map(|&us| format!("{:>9}", msec(us)))
+            .collect();
+        out.push_str(&format!("  {}\n\n", row.join(" ")));
+        out
+    }
+}
+
+/// Runs a test across all the connections at once.
+fn run_test(options: &Options, test: &str) -> io::Result<Report> {
+    let issued = AtomicU64::new(0);
+    let start = Instant::now();
+    let results = thread::scope(|scope| {
+        let clients: Vec<_> = (0..options.clients)
+            .map(|_| scope.spawn(|| run_client(options, test, &issued)))
+            .collect();
+        clients
+            .into_iter()
+            .map(|client| client.join().expect("benchmark client panicked"))
+            .collect::<io::Result<Vec<_>>>()
+    })?;
+    Ok(Report::new(results, start.elapsed()))
+}
+
+fn main() {
+    let options = parse_options(env::args().skip(1)).unwrap_or_else(|e| {
+        eprintln!("{e}");
+        process::exit(1);
+    });
+    for test in &options.tests {
+        match run_test(&options, test) {
+            Ok(report) => print!("{}", report.format(test, &options)),
+            Err(e) => {
+                eprintln!(
+                    "{} failed against {}:{}: {e}",
+                    test.to_uppercase(),
+                    options.host,
+                    options.port
+                );
+                process::exit(1);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn strings(args: &[&str]) -> Vec<String> {
+        args.iter().map(|arg| arg.to_string()).collect()
+    }
+
+    #[test]
+    fn test_parse_options() {
+        let test_cases = [
+            ("Defaults", vec![], Ok(Options::default())),
+            (
+                "Everything",
+                vec![
+                    "-h",
+                    "example.com",
+                    "-p",
+                    "7000",
+                    "-c",
+                    "4",
+                    "-n",
+                    "1000",
+                    "-d",
+                    "64",
+                    "-P",
+                    "16",
+                    "-r",
+                    "100",
+                    "-t",
+                    "GET,lpush,nope",
+                    "-q",
+                ],
+                Ok(Options {
+                    host: "example.com".to_string(),
+                    port: 7000,
+                    clients: 4,
+                    requests: 1000,
+                    data_size: 64,
+                    pipeline: 16,
+                    keyspace: Some(100),
+                    tests: vec!["get", "lpush"],
+                    quiet: true,
+                    ..Options::default()
+                }),
+            ),
+            (
+                "Zero clients",
+                vec!["-c", "0"],
+                Err("Invalid value '0' for -c".to_string()),
+            ),
+            (
+                "No known tests",
+                vec!["-t", "lrange"],
+                Err("No known tests in 'lrange'".to_string()),
+            ),
+            (
+                "Missing value",
+                vec!["-n"],
+                Err("Missing value for -n".to_string()),
+            ),
+            (
+                "Unknown flag",
+                vec!["-x"],
+                Err("Unrecognized option or bad number of args for: '-x'".to_string()),
+            ),
+        ];
+
+        for (name, args, expected) in test_cases {
+            assert_eq!(parse_options(strings(&args)), expected, "{}", name);
+        }
+    }
+
+    #[test]
+    fn test_command_builder() {
+        let fixed = Options::default();
+        let random = Options {
+            keyspace: Some(10),
+            data_size: 5,
+            ..Options::default()
+        };
+
+        let test_cases = [
+            ("Ping", "ping", &fixed, strings(&["PING"])),
+            (
+                "Fixed key",
+                "set",
+                &fixed,
+                strings(&["SET", "key:__rand_int__", "xxx"]),
+            ),
+            (
+                "Counter",
+                "incr",
+                &fixed,
+                strings(&["INCR", "counter:__rand_int__"]),
+            ),
+            (
+                "Payload size",
+                "lpush",
+                &random,
+                strings(&["LPUSH", "mylist", "xxxxx"]),
+            ),
+        ];
+
+        for (name, test, options, expected) in test_cases {
+            let mut builder = CommandBuilder::new(test, options, Rng::new());
+            assert_eq!(builder.next(), expected, "{}", name);
+        }
+
+        let mut builder = CommandBuilder::new("hset", &random, Rng::new());
+        for _ in 0..100 {
+            let args = builder.next();
+            let n: u64 = args[2].strip_prefix("element:").unwrap().parse().unwrap();
+            assert_eq!(args[2].len(), "element:".len() + 12);
+            assert!(n < 10);
+        }
+    }
+
+    #[test]
+    fn test_percentile() {
+        let latencies: Vec<u64> = (1..=100).collect();
+        let test_cases = [
+            ("Median", 50.0, 50),
+            ("p95", 95.0, 95),
+            ("p99", 99.0, 99),
+            ("Lowest", 0.0, 1),
+            ("Highest", 100.0, 100),
+        ];
+
+        for (name, p, expected) in test_cases {
+            assert_eq!(percentile(&latencies, p), expected, "{}", name);
+        }
+        assert_eq!(percentile(&[], 50.0), 0);
+    }
+
+    #[test]
+    fn test_report() {
+        let results = vec![
+            ClientResult {
+                latencies: vec![100, 300],
+                errors: 0,
+                first_error: None,
+            },
+            ClientResult {
+                latencies: vec![200, 400],
+                errors: 2,
+                first_error: Some("ERR unknown command 'LPUSH'".to_string()),
+            },
+        ];
+        let report = Report::new(results, Duration::from_secs(2));
+        assert_eq!(report.requests, 4);
+        assert_eq!(report.latency, [250, 100, 200, 400, 400, 400]);
+        assert_eq!(report.requests_per_second(), 2.0);
+
+        let quiet = Options {
+            quiet: true,
+            ..Options::default()
+        };
+        assert_eq!(
+            report.format("set", &quiet),
+            "SET: 2.00 requests per second, p50=0.200 msec\n"
+        );
+        let full = report.format("set", &Options::default());
+        assert!(full.starts_with("====== SET ======\n  4 requests completed in 2.00 seconds\n"));
+        assert!(full.contains("  2 errors, the first was: ERR unknown command 'LPUSH'\n"));
+        assert!(full.contains(
+            "        avg       min       p50       p95       p99       max\n      \
+             0.250     0.100     0.200     0.400     0.400     0.400\n"
+        ));
+    }
+}
@@ -0,0 +1,474 @@
+//! The analysis modes: listing keys, finding the biggest keys, rolling server stats and
+//! round-trip latency. Like redis-cli's, they only use ordinary commands, so they work against
+//! any server the client can reach.
+
+use super::{error_message, Connection, Options};
+use redis_from_scratch::resp::RespData;
+use std::collections::HashMap;
+use std::fmt;
+use std::io::{self, IsTerminal, Write};
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// SCAN calls made between sleeps when an interval is given.
+const SCANS_PER_SLEEP: u64 = 100;
+/// How often --stat and --latency report when no interval is given.
+const DEFAULT_INTERVAL: Duration = Duration::from_secs(1);
+/// Rows of --stat output between repeats of the header.
+const STAT_HEADER_EVERY: u64 = 20;
+/// The pause between PINGs in --latency mode.
+const LATENCY_SAMPLE_RATE: Duration = Duration::from_millis(10);
+
+const STAT_HEADER: &str = "\
+------- data ------ --------------------- load --------------------
+keys       mem      clients blocked requests            connections";
+
+/// The key types --bigkeys knows how to measure, in the order the summary lists them.
+const TYPES: [&str; 2] = ["string", "hash"];
+
+/// The command that measures a key of `type_name`, and the unit it counts in.
+fn size_command(type_name: &str) -> Option<(&'static str, &'static str)> {
+    match type_name {
+        "string" => Some(("STRLEN", "bytes")),
+        "hash" => Some(("HLEN", "fields")),
+        _ => None,
+    }
+}
+
+fn args(args: &[&str]) -> Vec<String> {
+    args.iter().map(|arg| arg.to_string()).collect()
+}
+
+/// Turns an error reply into an error, so a mode stops at the first one.
+pub(super) fn check(reply: RespData) -> io::Result<RespData> {
+    match reply {
+        RespData::Error(e) => Err(io::Error::other(error_message(&e).to_string())),
+        reply => Ok(reply),
+    }
+}
+
+pub(super) fn unexpected(reply: &RespData) -> io::Error {
+    io::Error::new(
+        io::ErrorKind::InvalidData,
+        format!("Unexpected reply: {reply:?}"),
+    )
+}
+
+/// The next cursor and the batch of keys in a SCAN reply.
+fn parse_scan_reply(reply: RespData) -> io::Result<(u64, Vec<String>)> {
+    let RespData::Array(items) = &reply else {
+        return Err(unexpected(&reply));
+    };
+    let [RespData::BulkString(cursor), RespData::Array(keys)] = items.as_slice() else {
+        return Err(unexpected(&reply));
+    };
+    let Ok(cursor) = cursor.parse() else {
+        return Err(unexpected(&reply));
+    };
+    let keys = keys
+        .iter()
+        .map(|key| match key {
+            RespData::BulkString(key) => Some(key.clone()),
+            _ => None,
+        })
+        .collect::<Option<Vec<_>>>()
+        .ok_or_else(|| unexpected(&reply))?;
+    Ok((cursor, keys))
+}
+
+/// Walks the whole keyspace with SCAN, handing every batch of keys to `visit`.
+pub(super) fn scan_keys(
+    conn: &mut Connection,
+    options: &Options,
+    mut visit: impl FnMut(&mut Connection, Vec<String>) -> io::Result<()>,
+) -> io::Result<()> {
+    let mut cursor = 0;
+    let mut calls = 0;
+    loop {
+        let mut command = args(&["SCAN", &cursor.to_string()]);
+        if let Some(pattern) = &options.pattern {
+            command.extend(args(&["MATCH", pattern]));
+        }
+        if let Some(count) = options.count {
+            command.extend(args(&["COUNT", &count.to_string()]));
+        }
+        let (next, keys) = parse_scan_reply(check(conn.run(&command)?)?)?;
+        visit(conn, keys)?;
+        if next == 0 {
+            return Ok(());
+        }
+        cursor = next;
+
+        calls += 1;
+        if let Some(interval) = options.interval {
+            if calls % SCANS_PER_SLEEP == 0 {
+                thread::sleep(interval);
+            }
+        }
+    }
+}
+
+/// The `field:value` lines of an INFO reply.
+fn parse_info(info: &str) -> HashMap<&str, &str> {
+    info.lines()
+        .filter(|line| !line.starts_with('#'))
+        .filter_map(|line| line.split_once(':'))
+        .collect()
+}
+
+fn info_number(info: &HashMap<&str, &str>, field: &str) -> u64 {
+    info.get(field)
+        .and_then(|value| value.parse().ok())
+        .unwrap_or(0)
+}
+
+/// The number of keys across every database in the keyspace section.
+fn keyspace_keys(info: &HashMap<&str, &str>) -> u64 {
+    info.iter()
+        .filter(|(field, _)| field.starts_with("db"))
+        .filter_map(|(_, value)| value.split(',').find_map(|kv| kv.strip_prefix("keys=")))
+        .filter_map(|keys| keys.parse::<u64>().ok())
+        .sum()
+}
+
+fn fetch_info(conn: &mut Connection, section: Option<&str>) -> io::Result<String> {
+    let mut command = args(&["INFO"]);
+    command.extend(section.map(str::to_string));
+    match check(conn.run(&command)?)? {
+        RespData::BulkString(info) => Ok(info),
+        reply => Err(unexpected(&reply)),
+    }
+}
+
+/// --scan: prints every key, or every key matching --pattern.
+pub(super) fn scan(conn: &mut Connection, options: &Options) -> io::Result<()> {
+    let mut stdout = io::stdout().lock();
+    scan_keys(conn, options, |_, keys| {
+        for key in keys {
+            writeln!(stdout, "{key}")?;
+        }
+        Ok(())
+    })
+}
+
+#[derive(Default)]
+struct TypeStats {
+    keys: u64,
+    total_size: u64,
+    biggest: Option<(String, u64)>,
+}
+
+/// The running totals behind --bigkeys.
+#[derive(Default)]
+struct BigKeys {
+    sampled: u64,
+    key_bytes: u64,
+    types: [TypeStats; TYPES.len()],
+}
+
+impl BigKeys {
+    /// Records a key, returning whether it is the biggest of its type so far.
+    fn add(&mut self, key: &str, type_name: &str, size: u64) -> bool {
+        let Some(index) = TYPES.iter().position(|name| *name == type_name) else {
+            return false;
+        };
+        self.sampled += 1;
+        self.key_bytes += key.len() as u64;
+        let stats = &mut self.types[index];
+        stats.keys += 1;
+        stats.total_size += size;
+        if stats
+            .biggest
+            .as_ref()
+            .is_some_and(|(_, biggest)| size <= *biggest)
+        {
+            return false;
+        }
+        stats.biggest = Some((key.to_string(), size));
+        true
+    }
+
+    /// The summary printed once the scan is done, in the same format as the server's own
+    /// report.
+    fn summary(&self) -> String {
+        let ratio = |part: u64, whole: u64| {
+            if whole == 0 {
+                0.0
+            } else {
+                part as f64 / whole as f64
+            }
+        };
+        let mut out = String::from("-------- summary -------\n\n");
+        out.push_str(&format!("Sampled {} keys in the keyspace!\n", self.sampled));
+        out.push_str(&format!(
+            "Total key length in bytes is {} (avg len {:.2})\n\n",
+            self.key_bytes,
+            ratio(self.key_bytes, self.sampled)
+        ));
+        for (type_name, stats) in TYPES.iter().zip(&self.types) {
+            if let Some((key, size)) = &stats.biggest {
+                let (_, unit) = size_command(type_name).unwrap();
+                out.push_str(&format!(
+                    "Biggest {type_name:>6} found '{key}' has {size} {unit}\n"
+                ));
+            }
+        }
+        out.push('\n');
+        for (type_name, stats) in TYPES.iter().zip(&self.types) {
+            let (_, unit) = size_command(type_name).unwrap();
+            out.push_str(&format!(
+                "{} {type_name}s with {} {unit} ({:.2}% of keys, avg size {:.2})\n",
+                stats.keys,
+                stats.total_size,
+                ratio(stats.keys, self.sampled) * 100.0,
+                ratio(stats.total_size, stats.keys)
+            ));
+        }
+        out
+    }
+}
+
+/// --bigkeys: scans the keyspace measuring every key, reporting each new biggest key of a type
+/// as it is found and a summary at the end.
+pub(super) fn big_keys(conn: &mut Connection, options: &Options) -> io::Result<()> {
+    let total_keys = keyspace_keys(&parse_info(&fetch_info(conn, Some("keyspace"))?));
+    println!();
+    println!("# Scanning the entire keyspace to find biggest keys as well as");
+    println!("# average sizes per key type.  You can use -i 0.1 to sleep 0.1 sec");
+    println!("# per 100 SCAN commands (not usually needed).");
+    println!();
+
+    let mut report = BigKeys::default();
+    scan_keys(conn, options, |conn, keys| {
+        let types = conn.run_all(keys.iter().map(|key| args(&["TYPE", key])))?;
+        let mut measured = Vec::new();
+        for (key, reply) in keys.iter().zip(types) {
+            let RespData::SimpleString(type_name) = check(reply)? else {
+                return Err(unexpected(&RespData::Null));
+            };
+            if let Some((command, unit)) = size_command(&type_name) {
+                measured.push((key, type_name, command, unit));
+            }
+        }
+        let sizes = conn.run_all(
+            measured
+                .iter()
+                .map(|(key, _, command, _)| args(&[command, key])),
+        )?;
+        for ((key, type_name, _, unit), size) in measured.into_iter().zip(sizes) {
+            // The key may have been deleted or replaced since TYPE.
+            let RespData::Integer(size) = size else {
+                continue;
+            };
+            if report.add(key, &type_name, size as u64) {
+                let progress =
+                    (report.sampled as f64 / total_keys.max(1) as f64 * 100.0).min(100.0);
+                println!(
+                    "[{progress:05.2}%] Biggest {type_name:>6} found so far '{key}' with {size} \
+                     {unit}"
+                );
+            }
+        }
+        Ok(())
+    })?;
+
+    println!();
+    print!("{}", report.summary());
+    Ok(())
+}
+
+/// A row of --stat output. `previous_requests` is the command count at the previous row.
+fn stat_row(info: &HashMap<&str, &str>, previous_requests: Option<u64>) -> String {
+    let requests = info_number(info, "total_commands_processed");
+    let delta = requests.saturating_sub(previous_requests.unwrap_or(requests));
+    format!(
+        "{:<11}{:<9}{:<8}{:<8}{:<20}{}",
+        keyspace_keys(info),
+        info.get("used_memory_human").unwrap_or(&"0B"),
+        info_number(info, "connected_clients"),
+        info_number(info, "blocked_clients"),
+        format!("{requests} (+{delta})"),
+        info_number(info, "total_connections_received"),
+    )
+}
+
+/// --stat: polls INFO every interval and prints a row of the key numbers, until interrupted.
+pub(super) fn stat(conn: &mut Connection, options: &Options) -> io::Result<()> {
+    let interval = options.interval.unwrap_or(DEFAULT_INTERVAL);
+    let mut previous_requests = None;
+    let mut rows = 0;
+    loop {
+        let info = fetch_info(conn, None)?;
+        let info = parse_info(&info);
+        if rows % STAT_HEADER_EVERY == 0 {
+            println!("{STAT_HEADER}");
+        }
+        println!("{}", stat_row(&info, previous_requests));
+        previous_requests = Some(info_number(&info, "total_commands_processed"));
+        rows += 1;
+        thread::sleep(interval);
+    }
+}
+
+/// Round-trip times seen so far.
+#[derive(Default)]
+struct LatencyStats {
+    min: Duration,
+    max: Duration,
+    total: Duration,
+    samples: u32,
+}
+
+impl LatencyStats {
+    fn record(&mut self, latency: Duration) {
+        if self.samples == 0 || latency < self.min {
+            self.min = latency;
+        }
+        self.max = self.max.max(latency);
+        self.total += latency;
+        self.samples += 1;
+    }
+}
+
+impl fmt::Display for LatencyStats {
+    /// Milliseconds, like redis-cli: whole ones for the extremes and fractions for the average.
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let avg = if self.samples == 0 {
+            0.0
+        } else {
+            self.total.as_secs_f64() * 1000.0 / self.samples as f64
+        };
+        write!(
+            f,
+            "min: {}, max: {}, avg: {avg:.2} ({} samples)",
+            self.min.as_millis(),
+            self.max.as_millis(),
+            self.samples
+        )
+    }
+}
+
+/// --latency: PINGs the server continuously, until interrupted. On a terminal the stats are
+/// updated in place; otherwise a line is printed every interval.
+pub(super) fn latency(conn: &mut Connection, options: &Options) -> io::Result<()> {
+    let interval = options.interval.unwrap_or(DEFAULT_INTERVAL);
+    let interactive = io::stdout().is_terminal();
+    let ping = args(&["PING"]);
+    let mut stats = LatencyStats::default();
+    let mut last_report = Instant::now();
+    loop {
+        let start = Instant::now();
+        check(conn.run(&ping)?)?;
+        stats.record(start.elapsed());
+        if interactive {
+            print!("\x1b[0G\x1b[2K{stats}");
+            io::stdout().flush()?;
+        } else if last_report.elapsed() >= interval {
+            println!("{stats}");
+            last_report = Instant::now();
+        }
+        thread::sleep(LATENCY_SAMPLE_RATE);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_scan_reply() {
+        let bulk = |s: &str| RespData::BulkString(s.to_string());
+        let test_cases = [
+            (
+                "Keys and a cursor",
+                RespData::Array(vec![
+                    bulk("17"),
+                    RespData::Array(vec![bulk("a"), bulk("b")]),
+                ]),
+                Some((17, args(&["a", "b"]))),
+            ),
+            (
+                "Done",
+                RespData::Array(vec![bulk("0"), RespData::Array(vec![])]),
+                Some((0, vec![])),
+            ),
+            (
+                "Bad cursor",
+                RespData::Array(vec![bulk("x"), RespData::Array(vec![])]),
+                None,
+            ),
+            ("Not an array", bulk("0"), None),
+        ];
+
+        for (name, reply, expected) in test_cases {
+            assert_eq!(parse_scan_reply(reply).ok(), expected, "{}", name);
+        }
+    }
+
+    #[test]
+    fn test_big_keys() {
+        let mut report = BigKeys::default();
+        let test_cases = [
+            ("First string", "short", "string", 3, true),
+            ("Bigger string", "long", "string", 100, true),
+            ("Smaller string", "mid", "string", 50, false),
+            ("First hash", "h", "hash", 2, true),
+            ("Unknown type", "l", "list", 7, false),
+        ];
+        for (name, key, type_name, size, expected) in test_cases {
+            assert_eq!(report.add(key, type_name, size), expected, "{}", name);
+        }
+
+        let expected = "\
+-------- summary -------
+
+Sampled 4 keys in the keyspace!
+Total key length in bytes is 13 (avg len 3.25)
+
+Biggest string found 'long' has 100 bytes
+Biggest   hash found 'h' has 2 fields
+
+3 strings with 153 bytes (75.00% of keys, avg size 51.00)
+1 hashs with 2 fields (25.00% of keys, avg size 2.00)
+";
+        assert_eq!(report.summary(), expected);
+    }
+
+    #[test]
+    fn test_stat_row() {
+        let info = "# Clients\r\nconnected_clients:3\r\nblocked_clients:0\r\n\r\n# Memory\r\n\
+                    used_memory_human:1.50M\r\n\r\n# Stats\r\ntotal_connections_received:12\r\n\
+                    total_commands_processed:250\r\n\r\n# Keyspace\r\n\
+                    db0:keys=42,expires=1,avg_ttl=100\r\n";
+        let info = parse_info(info);
+        assert_eq!(keyspace_keys(&info), 42);
+
+        let test_cases = [
+            (
+                "First row",
+                None,
+                "42         1.50M    3       0       250 (+0)            12",
+            ),
+            (
+                "Later row",
+                Some(200),
+                "42         1.50M    3       0       250 (+50)           12",
+            ),
+        ];
+
+        for (name, previous, expected) in test_cases {
+            assert_eq!(stat_row(&info, previous), expected, "{}", name);
+        }
+    }
+
+    #[test]
+    fn test_latency_stats() {
+        let mut stats = LatencyStats::default();
+        assert_eq!(stats.to_string(), "min: 0, max: 0, avg: 0.00 (0 samples)");
+
+        for ms in [3, 1, 5] {
+            stats.record(Duration::from_millis(ms));
+        }
+        assert_eq!(stats.to_string(), "min: 1, max: 5, avg: 3.00 (3 samples)");
+    }
+}
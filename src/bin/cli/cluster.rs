@@ -0,0 +1,323 @@
+//! -c: cluster mode. A command answered with a MOVED or ASK error is sent again to the node
+//! the error names, after ASKING for an ASK. The slots learned from MOVED are remembered, so
+//! later commands for them go straight to the right node. The key a command is routed by is
+//! taken to be its first argument, which holds for most commands; a wrong guess only costs a
+//! redirect.
+
+use super::{connect, Connection};
+use redis_from_scratch::resp::RespData;
+use std::collections::HashMap;
+use std::fmt;
+use std::io;
+
+/// The number of hash slots keys are spread over.
+const SLOTS: u16 = 16384;
+
+/// Redirects followed for one command before giving up.
+const MAX_REDIRECTS: usize = 16;
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub(super) struct Addr {
+    host: String,
+    port: u16,
+}
+
+impl fmt::Display for Addr {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}:{}", self.host, self.port)
+    }
+}
+
+/// A MOVED or ASK error: the slot and the node serving it.
+#[derive(Debug, PartialEq)]
+struct Redirect {
+    ask: bool,
+    slot: u16,
+    addr: Addr,
+}
+
+/// Parses `-MOVED <slot> <host>:<port>` or `-ASK <slot> <host>:<port>`. The host may be an
+/// IPv6 address, so the port is what follows the last colon.
+fn parse_redirect(error: &str) -> Option<Redirect> {
+    let mut words = error.strip_prefix('-').unwrap_or(error).split(' ');
+    let ask = match words.next()? {
+        "MOVED" => false,
+        "ASK" => true,
+        _ => return None,
+    };
+    let slot = words.next()?.parse().ok().filter(|slot| *slot < SLOTS)?;
+    let (host, port) = words.next()?.rsplit_once(':')?;
+    if words.next().is_some() {
+        return None;
+    }
+    Some(Redirect {
+        ask,
+        slot,
+        addr: Addr {
+            host: host.to_string(),
+            port: port.parse().ok()?,
+        },
+    })
+}
+
+/// CRC16-CCITT (XMODEM), the checksum Redis Cluster hashes keys with.
+fn crc16(data: &[u8]) -> u16 {
+    let mut crc: u16 = 0;
+    for &byte in data {
+        crc ^= (byte as u16) << 8;
+        for _ in 0..8 {
+            crc = if crc & 0x8000 != 0 {
+                (crc << 1) ^ 0x1021
+            } else {
+                crc << 1
+            };
+        }
+    }
+    crc
+}
+
+/// The slot of `key`. Only the part between the first `{` and the next `}` is hashed if it
+/// isn't empty, so related keys can be kept on one node.
+fn key_slot(key: &str) -> u16 {
+    let hashed = key
+        .split_once('{')
+        .and_then(|(_, rest)| rest.split_once('}'))
+        .map(|(tag, _)| tag)
+        .filter(|tag| !tag.is_empty())
+        .unwrap_or(key);
+    crc16(hashed.as_bytes()) % SLOTS
+}
+
+/// Connections to the nodes of a cluster, opened as redirects lead to them.
+pub(super) struct Cluster {
+    password: Option<String>,
+    nodes: HashMap<Addr, Connection>,
+    /// The node commands go to when their slot's node isn't known, which is the last one a
+    /// MOVED pointed at.
+    current: Addr,
+    slots: HashMap<u16, Addr>,
+}
+
+impl Cluster {
+    /// Starts from the node `connection` is connected to at `host`:`port`.
+    pub(super) fn new(
+        connection: Connection,
+        host: &str,
+        port: u16,
+        password: Option<String>,
+    ) -> Self {
+        let current = Addr {
+            host: host.to_string(),
+            port,
+        };
+        Self {
+            password,
+            nodes: HashMap::from([(current.clone(), connection)]),
+            current,
+            slots: HashMap::new(),
+        }
+    }
+
+    pub(super) fn current(&self) -> &Addr {
+        &self.current
+    }
+
+    /// Sends a command to the node serving its key, following redirects, and returns the
+    /// reply.
+    pub(super) fn run(&mut self, args: &[String]) -> io::Result<RespData> {
+        let mut addr = args
+            .get(1)
+            .and_then(|key| self.slots.get(&key_slot(key)))
+            .unwrap_or(&self.current)
+            .clone();
+        let mut asking = false;
+        for _ in 0..MAX_REDIRECTS {
+            let node = self.node(&addr)?;
+            let reply = if asking {
+                let mut replies = node.run_all([vec!["ASKING".to_string()], args.to_vec()])?;
+                match replies.remove(0) {
+                    RespData::Error(e) => RespData::Error(e),
+                    _ => replies.remove(0),
+                }
+            } else {
+                node.run(args)?
+            };
+            let RespData::Error(e) = &reply else {
+                return Ok(reply);
+            };
+            let Some(redirect) = parse_redirect(e) else {
+                return Ok(reply);
+            };
+            eprintln!(
+                "-> Redirected to slot [{}] located at {}",
+                redirect.slot, redirect.addr
+            );
+            if !redirect.ask {
+                self.slots.insert(redirect.slot, redirect.addr.clone());
+                self.current = redirect.addr.clone();
+            }
+            asking = redirect.ask;
+            addr = redirect.addr;
+        }
+        Err(io::Error::other("Too many cluster redirects"))
+    }
+
+    /// The connection to `addr`, opened and authenticated the first time it is needed.
+    fn node(&mut self, addr: &Addr) -> io::Result<&mut Connection> {
+        if !self.nodes.contains_key(addr) {
+            let connection = connect(&addr.host, addr.port, self.password.as_deref())
+                .ok_or_else(|| io::Error::other(format!("Could not connect to {addr}")))?;
+            self.nodes.insert(addr.clone(), connection);
+        }
+        Ok(self.nodes.get_mut(addr).unwrap())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use redis_from_scratch::resp::Resp;
+    use std::io::Write;
+    use std::net::TcpListener;
+    use std::sync::Arc;
+    use std::thread;
+
+    fn addr(host: &str, port: u16) -> Addr {
+        Addr {
+            host: host.to_string(),
+            port,
+        }
+    }
+
+    fn strings(args: &[&str]) -> Vec<String> {
+        args.iter().map(|arg| arg.to_string()).collect()
+    }
+
+    /// Listens on a free port, answering each command with `reply`.
+    fn fake_node(reply: impl Fn(&[String]) -> String + Send + Sync + 'static) -> u16 {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let reply = Arc::new(reply);
+        thread::spawn(move || {
+            for stream in listener.incoming() {
+                let stream = stream.unwrap();
+                let reply = Arc::clone(&reply);
+                thread::spawn(move || {
+                    let mut reader = Resp::new(stream.try_clone().unwrap());
+                    let mut writer = stream;
+                    while let Ok(RespData::Array(args)) = reader.read() {
+                        let args: Vec<String> = args
+                            .into_iter()
+                            .map(|arg| match arg {
+                                RespData::BulkString(arg) => arg,
+                                _ => String::new(),
+                            })
+                            .collect();
+                        writer.write_all(reply(&args).as_bytes()).unwrap();
+                    }
+                });
+            }
+        });
+        port
+    }
+
+    #[test]
+    fn test_parse_redirect() {
+        let test_cases = [
+            (
+                "Moved",
+                "-MOVED 3999 127.0.0.1:6381",
+                Some(Redirect {
+                    ask: false,
+                    slot: 3999,
+                    addr: addr("127.0.0.1", 6381),
+                }),
+            ),
+            (
+                "Ask to an IPv6 node",
+                "-ASK 0 ::1:7000",
+                Some(Redirect {
+                    ask: true,
+                    slot: 0,
+                    addr: addr("::1", 7000),
+                }),
+            ),
+            ("Other error", "-ERR unknown command", None),
+            ("Slot out of range", "-MOVED 16384 127.0.0.1:6381", None),
+            ("No port", "-MOVED 1 localhost", None),
+        ];
+
+        for (name, error, expected) in test_cases {
+            assert_eq!(parse_redirect(error), expected, "{}", name);
+        }
+    }
+
+    #[test]
+    fn test_key_slot() {
+        let test_cases = [
+            ("Plain key", "foo", 12182),
+            ("CRC16 check value", "123456789", 0x31c3),
+            (
+                "Hash tag",
+                "{user1000}.following",
+                crc16(b"user1000") % SLOTS,
+            ),
+            (
+                "Empty hash tag",
+                "{}.following",
+                crc16(b"{}.following") % SLOTS,
+            ),
+            (
+                "Unclosed hash tag",
+                "{user1000",
+                crc16(b"{user1000") % SLOTS,
+            ),
+        ];
+
+        for (name, key, expected) in test_cases {
+            assert_eq!(key_slot(key), expected, "{}", name);
+        }
+    }
+
+    #[test]
+    fn test_follows_redirects() {
+        let owner = fake_node(|args| match args[0].as_str() {
+            "ASKING" => "+OK\r\n".to_string(),
+            _ => format!("${}\r\n{}\r\n", args[1].len(), args[1]),
+        });
+        let moved = format!("-MOVED 12182 127.0.0.1:{owner}\r\n");
+        let first = fake_node(move |_| moved.clone());
+        let mut cluster = Cluster::new(
+            connect("127.0.0.1", first, None).unwrap(),
+            "127.0.0.1",
+            first,
+            None,
+        );
+
+        assert_eq!(
+            cluster.run(&strings(&["GET", "foo"])).unwrap(),
+            RespData::bulk("foo")
+        );
+        assert_eq!(cluster.current(), &addr("127.0.0.1", owner));
+        // The slot is cached, so this goes straight to the owner.
+        assert_eq!(
+            cluster.run(&strings(&["GET", "foo"])).unwrap(),
+            RespData::bulk("foo")
+        );
+
+        let ask = format!("-ASK 42 127.0.0.1:{owner}\r\n");
+        let migrating = fake_node(move |_| ask.clone());
+        let mut cluster = Cluster::new(
+            connect("127.0.0.1", migrating, None).unwrap(),
+            "127.0.0.1",
+            migrating,
+            None,
+        );
+        assert_eq!(
+            cluster.run(&strings(&["GET", "bar"])).unwrap(),
+            RespData::bulk("bar")
+        );
+        // An ASK is a one-off, so the current node stays the same.
+        assert_eq!(cluster.current(), &addr("127.0.0.1", migrating));
+    }
+}
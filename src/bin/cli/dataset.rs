@@ -0,0 +1,426 @@
+//! --export and --import: the keyspace as NDJSON, one key per line with its type, value and
+//! remaining TTL, sorted by key so two exports diff cleanly. Importing replaces the keys in
+//! the file and leaves every other key alone.
+
+use super::analysis::{check, scan_keys, unexpected};
+use super::json::{self, Json};
+use super::pipe::{self, PipeStats};
+use super::{Connection, Options};
+use redis_from_scratch::resp::RespData;
+use std::fs::{self, File};
+use std::io::{self, BufWriter, Read, Write};
+
+#[derive(Debug, PartialEq)]
+enum Value {
+    String(String),
+    /// Fields sorted by name, so exports are stable.
+    Hash(Vec<(String, String)>),
+}
+
+/// A key as exported.
+#[derive(Debug, PartialEq)]
+struct Record {
+    key: String,
+    value: Value,
+    /// Milliseconds until the key expires, if it has a TTL.
+    ttl_ms: Option<i64>,
+}
+
+fn args(args: &[&str]) -> Vec<String> {
+    args.iter().map(|arg| arg.to_string()).collect()
+}
+
+impl Record {
+    fn to_json(&self) -> String {
+        let (type_name, value) = match &self.value {
+            Value::String(s) => ("string", json::quote(s)),
+            Value::Hash(fields) => {
+                let members: Vec<String> = fields
+                    .iter()
+                    .map(|(field, value)| format!("{}:{}", json::quote(field), json::quote(value)))
+                    .collect();
+                ("hash", format!("{{{}}}", members.join(",")))
+            }
+        };
+        let mut out = format!(
+            "{{\"key\":{},\"type\":\"{type_name}\",\"value\":{value}",
+            json::quote(&self.key)
+        );
+        if let Some(ttl) = self.ttl_ms {
+            out.push_str(&format!(",\"ttl_ms\":{ttl}"));
+        }
+        out.push('}');
+        out
+    }
+
+    fn from_json(line: &str) -> Result<Self, String> {
+        let record = json::parse(line)?;
+        let Some(Json::String(key)) = record.get("key") else {
+            return Err("missing \"key\"".to_string());
+        };
+        let value = match (record.get("type"), record.get("value")) {
+            (Some(Json::String(t)), Some(Json::String(s))) if t == "string" => {
+                Value::String(s.clone())
+            }
+            (Some(Json::String(t)), Some(Json::Object(members))) if t == "hash" => {
+                let fields = members
+                    .iter()
+                    .map(|(field, value)| match value {
+                        Json::String(value) => Ok((field.clone(), value.clone())),
+                        _ => Err(format!("hash field \"{field}\" is not a string")),
+                    })
+                    .collect::<Result<_, _>>()?;
+                Value::Hash(fields)
+            }
+            (Some(Json::String(t)), _) if t == "string" || t == "hash" => {
+                return Err(format!("\"value\" doesn't match the type {t}"));
+            }
+            (Some(Json::String(t)), _) => return Err(format!("unsupported type \"{t}\"")),
+            (Some(_), _) => return Err("\"type\" must be a string".to_string()),
+            (None, _) => return Err("missing \"type\"".to_string()),
+        };
+        let ttl_ms = match record.get("ttl_ms") {
+            None | Some(Json::Null) => None,
+            Some(Json::Number(ttl)) if *ttl > 0 => Some(*ttl),
+            Some(_) => return Err("\"ttl_ms\" must be a positive integer".to_string()),
+        };
+        Ok(Self {
+            key: key.clone(),
+            value,
+            ttl_ms,
+        })
+    }
+
+    /// The commands that recreate the key, replacing whatever is there now.
+    fn commands(&self) -> Vec<Vec<String>> {
+        let ttl = self.ttl_ms.map(|ttl| ttl.to_string());
+        match &self.value {
+            Value::String(s) => {
+                let mut set = args(&["SET", &self.key, s]);
+                if let Some(ttl) = &ttl {
+                    set.extend(args(&["PX", ttl]));
+                }
+                vec![set]
+            }
+            Value::Hash(fields) => {
+                let mut commands = vec![args(&["DEL", &self.key])];
+                // A hash can't be empty, so an empty one just means the key is gone.
+                if !fields.is_empty() {
+                    let mut hset = args(&["HSET", &self.key]);
+                    for (field, value) in fields {
+                        hset.extend([field.clone(), value.clone()]);
+                    }
+                    commands.push(hset);
+                    if let Some(ttl) = &ttl {
+                        commands.push(args(&["PEXPIRE", &self.key, ttl]));
+                    }
+                }
+                commands
+            }
+        }
+    }
+}
+
+/// The record for `key` from its value and PTTL replies, or None if it is gone by now.
+fn record(key: &str, value: RespData, pttl: RespData) -> io::Result<Option<Record>> {
+    // An error means the key was replaced by one of another type since TYPE ran.
+    let value = match value {
+        RespData::Null | RespData::Error(_) => return Ok(None),
+        RespData::BulkString(s) => Value::String(s),
+        RespData::Array(items) => {
+            let mut fields = Vec::new();
+            let mut items = items.into_iter();
+            while let (Some(RespData::BulkString(field)), Some(RespData::BulkString(value))) =
+                (items.next(), items.next())
+            {
+                fields.push((field, value));
+            }
+            if fields.is_empty() {
+                return Ok(None);
+            }
+            fields.sort();
+            Value::Hash(fields)
+        }
+        reply => return Err(unexpected(&reply)),
+    };
+    let ttl_ms = match check(pttl)? {
+        RespData::Integer(-2) => return Ok(None),
+        RespData::Integer(ttl) if ttl >= 0 => Some(ttl.max(1)),
+        RespData::Integer(_) => None,
+        reply => return Err(unexpected(&reply)),
+    };
+    Ok(Some(Record {
+        key: key.to_string(),
+        value,
+        ttl_ms,
+    }))
+}
+
+/// Reads the records for a batch of keys, skipping any deleted in the meantime.
+fn fetch_records(conn: &mut Connection, keys: &[String]) -> io::Result<Vec<Record>> {
+    let types = conn.run_all(keys.iter().map(|key| args(&["TYPE", key])))?;
+    let mut readable = Vec::new();
+    for (key, reply) in keys.iter().zip(types) {
+        match check(reply)? {
+            RespData::SimpleString(t) if t == "string" => readable.push((key, "GET")),
+            RespData::SimpleString(t) if t == "hash" => readable.push((key, "HGETALL")),
+            RespData::SimpleString(t) if t == "none" => {}
+            RespData::SimpleString(t) => eprintln!("Skipping key {key} of unsupported type {t}"),
+            reply => return Err(unexpected(&reply)),
+        }
+    }
+
+    let replies = conn.run_all(
+        readable
+            .iter()
+            .flat_map(|(key, read)| [args(&[read, key]), args(&["PTTL", key])]),
+    )?;
+    let mut replies = replies.into_iter();
+    let mut records = Vec::new();
+    for (key, _) in readable {
+        let (Some(value), Some(pttl)) = (replies.next(), replies.next()) else {
+            break;
+        };
+        records.extend(record(key, value, pttl)?);
+    }
+    Ok(records)
+}
+
+/// Writes every key, or those matching --pattern, to `path`, or to stdout if it is `-`.
+pub(super) fn export(conn: &mut Connection, options: &Options, path: &str) -> io::Result<()> {
+    let mut records = Vec::new();
+    scan_keys(conn, options, |conn, keys| {
+        records.extend(fetch_records(conn, &keys)?);
+        Ok(())
+    })?;
+    records.sort_by(|a, b| a.key.cmp(&b.key));
+
+    let mut out: Box<dyn Write> = if path == "-" {
+        Box::new(io::stdout().lock())
+    } else {
+        Box::new(BufWriter::new(File::create(path)?))
+    };
+    for record in &records {
+        writeln!(out, "{}", record.to_json())?;
+    }
+    out.flush()?;
+    eprintln!("Exported {} keys", records.len());
+    Ok(())
+}
+
+/// Parses every line of an export, so a bad file is rejected before anything is sent.
+fn parse_records(text: &str) -> Result<Vec<Record>, String> {
+    text.lines()
+        .enumerate()
+        .filter(|(_, line)| !line.trim().is_empty())
+        .map(|(i, line)| Record::from_json(line).map_err(|e| format!("line {}: {e}", i + 1)))
+        .collect()
+}
+
+/// Loads the export at `path`, or stdin if it is `-`, into the server.
+pub(super) fn import(connection: Connection, path: &str) -> io::Result<PipeStats> {
+    let text = if path == "-" {
+        let mut text = String::new();
+        io::stdin().read_to_string(&mut text)?;
+        text
+    } else {
+        fs::read_to_string(path)?
+    };
+    let records = parse_records(&text)
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, format!("{path}: {e}")))?;
+
+    let Connection { mut reader, writer } = connection;
+    let send = |writer: &mut _| {
+        for command in records.iter().flat_map(Record::commands) {
+            RespData::Array(command.into_iter().map(RespData::BulkString).collect())
+                .write(writer)?;
+        }
+        Ok(())
+    };
+    let stats = pipe::pipe(send, writer, &mut reader, &pipe::random_marker())?;
+    println!(
+        "Imported {} keys, errors: {}, replies: {}",
+        records.len(),
+        stats.errors,
+        stats.replies
+    );
+    Ok(stats)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn hash(fields: &[(&str, &str)]) -> Value {
+        Value::Hash(
+            fields
+                .iter()
+                .map(|(f, v)| (f.to_string(), v.to_string()))
+                .collect(),
+        )
+    }
+
+    #[test]
+    fn test_record_json() {
+        let test_cases = [
+            (
+                "String without a TTL",
+                Record {
+                    key: "greeting".to_string(),
+                    value: Value::String("hello \"world\"".to_string()),
+                    ttl_ms: None,
+                },
+                r#"{"key":"greeting","type":"string","value":"hello \"world\""}"#,
+            ),
+            (
+                "Hash with a TTL",
+                Record {
+                    key: "user:1".to_string(),
+                    value: hash(&[("age", "42"), ("name", "ada")]),
+                    ttl_ms: Some(5000),
+                },
+                r#"{"key":"user:1","type":"hash","value":{"age":"42","name":"ada"},"ttl_ms":5000}"#,
+            ),
+        ];
+
+        for (name, record, expected) in test_cases {
+            assert_eq!(record.to_json(), expected, "{}", name);
+            assert_eq!(Record::from_json(expected), Ok(record), "{}", name);
+        }
+    }
+
+    #[test]
+    fn test_from_json_errors() {
+        let test_cases = [
+            ("Not JSON", "key", "unexpected 'k'"),
+            (
+                "Missing key",
+                r#"{"type":"string","value":"v"}"#,
+                "missing \"key\"",
+            ),
+            (
+                "Unsupported type",
+                r#"{"key":"k","type":"list","value":[]}"#,
+                "unsupported type \"list\"",
+            ),
+            (
+                "Value of the wrong type",
+                r#"{"key":"k","type":"hash","value":"v"}"#,
+                "\"value\" doesn't match the type hash",
+            ),
+            (
+                "Hash field that isn't a string",
+                r#"{"key":"k","type":"hash","value":{"f":1}}"#,
+                "hash field \"f\" is not a string",
+            ),
+            (
+                "Negative TTL",
+                r#"{"key":"k","type":"string","value":"v","ttl_ms":-1}"#,
+                "\"ttl_ms\" must be a positive integer",
+            ),
+        ];
+
+        for (name, input, expected) in test_cases {
+            assert_eq!(
+                Record::from_json(input),
+                Err(expected.to_string()),
+                "{}",
+                name
+            );
+        }
+
+        assert_eq!(
+            parse_records("\n{\"key\":\"k\",\"type\":\"string\",\"value\":\"v\"}\n{}\n")
+                .map(|records| records.len()),
+            Err("line 3: missing \"key\"".to_string())
+        );
+    }
+
+    #[test]
+    fn test_commands() {
+        let test_cases = [
+            (
+                "String",
+                Record {
+                    key: "k".to_string(),
+                    value: Value::String("v".to_string()),
+                    ttl_ms: None,
+                },
+                vec![args(&["SET", "k", "v"])],
+            ),
+            (
+                "String with a TTL",
+                Record {
+                    key: "k".to_string(),
+                    value: Value::String("v".to_string()),
+                    ttl_ms: Some(100),
+                },
+                vec![args(&["SET", "k", "v", "PX", "100"])],
+            ),
+            (
+                "Hash with a TTL",
+                Record {
+                    key: "h".to_string(),
+                    value: hash(&[("a", "1"), ("b", "2")]),
+                    ttl_ms: Some(100),
+                },
+                vec![
+                    args(&["DEL", "h"]),
+                    args(&["HSET", "h", "a", "1", "b", "2"]),
+                    args(&["PEXPIRE", "h", "100"]),
+                ],
+            ),
+            (
+                "Empty hash",
+                Record {
+                    key: "h".to_string(),
+                    value: hash(&[]),
+                    ttl_ms: None,
+                },
+                vec![args(&["DEL", "h"])],
+            ),
+        ];
+
+        for (name, record, expected) in test_cases {
+            assert_eq!(record.commands(), expected, "{}", name);
+        }
+    }
+
+    #[test]
+    fn test_record_from_replies() {
+        let bulk = |s: &str| RespData::BulkString(s.to_string());
+        let test_cases = [
+            (
+                "String with a TTL",
+                bulk("v"),
+                RespData::Integer(250),
+                Some(Record {
+                    key: "k".to_string(),
+                    value: Value::String("v".to_string()),
+                    ttl_ms: Some(250),
+                }),
+            ),
+            (
+                "Hash fields are sorted",
+                RespData::Array(vec![bulk("b"), bulk("2"), bulk("a"), bulk("1")]),
+                RespData::Integer(-1),
+                Some(Record {
+                    key: "k".to_string(),
+                    value: hash(&[("a", "1"), ("b", "2")]),
+                    ttl_ms: None,
+                }),
+            ),
+            ("Deleted", RespData::Null, RespData::Integer(-2), None),
+            (
+                "Replaced with another type",
+                RespData::Error("WRONGTYPE".to_string()),
+                RespData::Integer(-1),
+                None,
+            ),
+        ];
+
+        for (name, value, pttl, expected) in test_cases {
+            assert_eq!(record("k", value, pttl).unwrap(), expected, "{}", name);
+        }
+    }
+}
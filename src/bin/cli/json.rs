@@ -0,0 +1,283 @@
+//! Just enough JSON for dataset export and import: the full syntax, but numbers are integers
+//! only, since the exported records never hold anything else.
+
+use std::fmt::Write;
+
+#[derive(Debug, PartialEq)]
+pub(super) enum Json {
+    Null,
+    Bool(bool),
+    Number(i64),
+    String(String),
+    Array(Vec<Json>),
+    /// Members in the order they appear.
+    Object(Vec<(String, Json)>),
+}
+
+impl Json {
+    /// The member called `name`, if this is an object that has one.
+    pub(super) fn get(&self, name: &str) -> Option<&Json> {
+        match self {
+            Json::Object(members) => members.iter().find(|(n, _)| n == name).map(|(_, v)| v),
+            _ => None,
+        }
+    }
+}
+
+/// `s` as a quoted JSON string.
+pub(super) fn quote(s: &str) -> String {
+    let mut out = String::with_capacity(s.len() + 2);
+    out.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            c if c.is_control() => write!(out, "\\u{:04x}", c as u32).unwrap(),
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+    out
+}
+
+/// Parses a complete JSON document.
+pub(super) fn parse(input: &str) -> Result<Json, String> {
+    let mut parser = Parser {
+        chars: input.char_indices().peekable(),
+    };
+    let value = parser.value()?;
+    parser.skip_whitespace();
+    match parser.chars.next() {
+        None => Ok(value),
+        Some((pos, _)) => Err(format!("unexpected data at offset {pos}")),
+    }
+}
+
+struct Parser<'a> {
+    chars: std::iter::Peekable<std::str::CharIndices<'a>>,
+}
+
+impl Parser<'_> {
+    fn skip_whitespace(&mut self) {
+        while self
+            .chars
+            .next_if(|(_, c)| c.is_ascii_whitespace())
+            .is_some()
+        {}
+    }
+
+    fn next(&mut self) -> Result<char, String> {
+        self.chars
+            .next()
+            .map(|(_, c)| c)
+            .ok_or_else(|| "unexpected end of input".to_string())
+    }
+
+    fn expect(&mut self, expected: char) -> Result<(), String> {
+        match self.next()? {
+            c if c == expected => Ok(()),
+            c => Err(format!("expected '{expected}', got '{c}'")),
+        }
+    }
+
+    fn literal(&mut self, word: &str, value: Json) -> Result<Json, String> {
+        for expected in word.chars() {
+            self.expect(expected)?;
+        }
+        Ok(value)
+    }
+
+    fn value(&mut self) -> Result<Json, String> {
+        self.skip_whitespace();
+        let Some(&(_, c)) = self.chars.peek() else {
+            return Err("unexpected end of input".to_string());
+        };
+        match c {
+            'n' => self.literal("null", Json::Null),
+            't' => self.literal("true", Json::Bool(true)),
+            'f' => self.literal("false", Json::Bool(false)),
+            '"' => self.string().map(Json::String),
+            '[' => self.array(),
+            '{' => self.object(),
+            '-' | '0'..='9' => self.number(),
+            c => Err(format!("unexpected '{c}'")),
+        }
+    }
+
+    fn number(&mut self) -> Result<Json, String> {
+        let mut digits = String::new();
+        if let Some((_, '-')) = self.chars.next_if(|(_, c)| *c == '-') {
+            digits.push('-');
+        }
+        while let Some((_, c)) = self.chars.next_if(|(_, c)| c.is_ascii_digit()) {
+            digits.push(c);
+        }
+        if let Some(&(_, c @ ('.' | 'e' | 'E'))) = self.chars.peek() {
+            return Err(format!("unexpected '{c}', only integers are supported"));
+        }
+        digits
+            .parse()
+            .map(Json::Number)
+            .map_err(|_| format!("invalid number '{digits}'"))
+    }
+
+    fn hex_escape(&mut self) -> Result<u32, String> {
+        let hex: String = (0..4).map(|_| self.next()).collect::<Result<_, _>>()?;
+        u32::from_str_radix(&hex, 16).map_err(|_| format!("invalid escape '\\u{hex}'"))
+    }
+
+    fn string(&mut self) -> Result<String, String> {
+        self.expect('"')?;
+        let mut out = String::new();
+        loop {
+            match self.next()? {
+                '"' => return Ok(out),
+                '\\' => match self.next()? {
+                    '"' => out.push('"'),
+                    '\\' => out.push('\\'),
+                    '/' => out.push('/'),
+                    'b' => out.push('\x08'),
+                    'f' => out.push('\x0c'),
+                    'n' => out.push('\n'),
+                    'r' => out.push('\r'),
+                    't' => out.push('\t'),
+                    'u' => {
+                        let mut code = self.hex_escape()?;
+                        // Characters outside the BMP are escaped as a surrogate pair.
+                        if (0xD800..0xDC00).contains(&code) {
+                            self.expect('\\')?;
+                            self.expect('u')?;
+                            let low = self.hex_escape()?;
+                            if !(0xDC00..0xE000).contains(&low) {
+                                return Err("invalid surrogate pair".to_string());
+                            }
+                            code = 0x10000 + ((code - 0xD800) << 10) + (low - 0xDC00);
+                        }
+                        out.push(
+                            char::from_u32(code)
+                                .ok_or_else(|| format!("invalid character {code:#x}"))?,
+                        );
+                    }
+                    c => return Err(format!("invalid escape '\\{c}'")),
+                },
+                c if c.is_control() => return Err("control character in string".to_string()),
+                c => out.push(c),
+            }
+        }
+    }
+
+    /// The elements of an array or object, each read by `element`, up to `close`.
+    fn elements<T>(
+        &mut self,
+        close: char,
+        mut element: impl FnMut(&mut Self) -> Result<T, String>,
+    ) -> Result<Vec<T>, String> {
+        let mut items = Vec::new();
+        self.skip_whitespace();
+        if self.chars.next_if(|(_, c)| *c == close).is_some() {
+            return Ok(items);
+        }
+        loop {
+            items.push(element(self)?);
+            self.skip_whitespace();
+            match self.next()? {
+                ',' => {}
+                c if c == close => return Ok(items),
+                c => return Err(format!("expected ',' or '{close}', got '{c}'")),
+            }
+        }
+    }
+
+    fn array(&mut self) -> Result<Json, String> {
+        self.expect('[')?;
+        self.elements(']', Self::value).map(Json::Array)
+    }
+
+    fn object(&mut self) -> Result<Json, String> {
+        self.expect('{')?;
+        let members = self.elements('}', |parser| {
+            parser.skip_whitespace();
+            let name = parser.string()?;
+            parser.skip_whitespace();
+            parser.expect(':')?;
+            Ok((name, parser.value()?))
+        })?;
+        Ok(Json::Object(members))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_quote() {
+        let test_cases = [
+            ("Plain", "abc", r#""abc""#),
+            ("Quotes and backslashes", r#"a"b\c"#, r#""a\"b\\c""#),
+            ("Control characters", "a\nb\t\x01", r#""a\nb\t\u0001""#),
+            ("Unicode is kept", "héllo ☃", "\"héllo ☃\""),
+        ];
+
+        for (name, input, expected) in test_cases {
+            assert_eq!(quote(input), expected, "{}", name);
+            assert_eq!(
+                parse(expected),
+                Ok(Json::String(input.to_string())),
+                "{}",
+                name
+            );
+        }
+    }
+
+    #[test]
+    fn test_parse() {
+        let test_cases = [
+            ("Null", "null", Ok(Json::Null)),
+            ("Negative number", " -42 ", Ok(Json::Number(-42))),
+            (
+                "Surrogate pair",
+                r#""\ud83d\ude00""#,
+                Ok(Json::String("😀".to_string())),
+            ),
+            (
+                "Nested",
+                r#"{"a": [1, true, {}], "b": "x"}"#,
+                Ok(Json::Object(vec![
+                    (
+                        "a".to_string(),
+                        Json::Array(vec![
+                            Json::Number(1),
+                            Json::Bool(true),
+                            Json::Object(vec![]),
+                        ]),
+                    ),
+                    ("b".to_string(), Json::String("x".to_string())),
+                ])),
+            ),
+            (
+                "Float",
+                "1.5",
+                Err("unexpected '.', only integers are supported".to_string()),
+            ),
+            ("Trailing comma", "[1,]", Err("unexpected ']'".to_string())),
+            (
+                "Unterminated",
+                r#"{"a": "b"#,
+                Err("unexpected end of input".to_string()),
+            ),
+            (
+                "Trailing data",
+                "{} x",
+                Err("unexpected data at offset 3".to_string()),
+            ),
+        ];
+
+        for (name, input, expected) in test_cases {
+            assert_eq!(parse(input), expected, "{}", name);
+        }
+    }
+}
@@ -0,0 +1,745 @@
+//! A redis-cli for this server, speaking RESP through the same protocol module the server uses.
+//!
+//! With a command on the command line it runs it and exits; otherwise it reads commands from
+//! stdin, with a prompt when stdin is a terminal. Both follow cluster redirects with -c, as
+//! `cluster` does. The analysis modes (--scan, --bigkeys,
+//! --stat and --latency) live in `analysis`, mass insertion with --pipe in `pipe`, AOF
+//! replay with --replay-aof in `replay`, and JSON export and import in `dataset`.
+
+mod analysis;
+mod cluster;
+mod dataset;
+mod json;
+mod pipe;
+mod replay;
+
+use std::env;
+use std::io::{self, BufRead, BufWriter, IsTerminal, Write};
+use std::net::TcpStream;
+use std::process;
+use std::time::Duration;
+
+use cluster::Cluster;
+use redis_from_scratch::resp::{Resp, RespData};
+
+const DEFAULT_HOST: &str = "127.0.0.1";
+const DEFAULT_PORT: u16 = 6379;
+
+const USAGE: &str = "\
+Usage: cli [OPTIONS] [cmd [arg [arg ...]]]
+  -h <hostname>      Server hostname (default: 127.0.0.1).
+  -p <port>          Server port (default: 6379).
+  -a <password>      Password to use when connecting to the server.
+  -c                 Enable cluster mode (follow -ASK and -MOVED redirections).
+  --raw              Use raw formatting for replies (default when STDOUT is
+                     not a tty).
+  --no-raw           Force formatted output even when STDOUT is not a tty.
+  -i <interval>      Seconds between --stat rows and --latency reports, or to
+                     sleep every 100 SCAN calls with --scan and --bigkeys.
+                     Fractions like -i 0.1 are allowed.
+  --stat             Print rolling stats about the server: keys, memory,
+                     clients, requests and connections.
+  --latency          Continuously sample the latency of PING round trips.
+  --bigkeys          Sample keys looking for the biggest of each type.
+  --scan             List all keys using the SCAN command.
+  --pipe             Transfer raw RESP protocol from stdin to the server.
+  --replay-aof <file>
+                     Replay the commands in an append only file against the
+                     server.
+  --export <file>    Dump every key, with its type, value and TTL, to <file>
+                     as JSON, one key per line. Use - for stdout.
+  --import <file>    Load keys from a file written by --export, replacing
+                     keys with the same names. Use - for stdin.
+  --rate <n>         Replay at most <n> commands per second (default: as fast
+                     as the server takes them).
+  --pattern <pat>    Keys pattern when using the --scan, --bigkeys or --export
+                     options (default: *).
+  --count <count>    Count option when using the --scan, --bigkeys or --export
+                     options (default: 10).
+  --help             Output this help and exit.";
+
+/// What the client does once connected.
+#[derive(Debug, PartialEq)]
+enum Mode {
+    /// Runs the command given on the command line, or the REPL if there is none.
+    Command,
+    Scan,
+    BigKeys,
+    Stat,
+    Latency,
+    Pipe,
+    /// Replays the AOF at the given path.
+    ReplayAof(String),
+    /// Writes the dataset to the given path as NDJSON.
+    Export(String),
+    /// Loads the NDJSON dataset at the given path.
+    Import(String),
+}
+
+#[derive(Debug, PartialEq)]
+struct Options {
+    host: String,
+    port: u16,
+    password: Option<String>,
+    /// Follow MOVED and ASK redirects to other cluster nodes.
+    cluster: bool,
+    /// Print replies as they are instead of the annotated, quoted format.
+    raw: bool,
+    /// The command to run instead of starting the REPL, if any.
+    command: Vec<String>,
+    mode: Mode,
+    /// MATCH for the SCAN calls of --scan, --bigkeys and --export.
+    pattern: Option<String>,
+    /// COUNT for the SCAN calls of --scan, --bigkeys and --export.
+    count: Option<u64>,
+    interval: Option<Duration>,
+    /// Commands per second for --replay-aof.
+    rate: Option<u64>,
+}
+
+/// Parses the arguments after the program name. `raw` is the default output mode.
+fn parse_options(args: impl IntoIterator<Item = String>, raw: bool) -> Result<Options, String> {
+    let mut options = Options {
+        host: DEFAULT_HOST.to_string(),
+        port: DEFAULT_PORT,
+        password: None,
+        cluster: false,
+        raw,
+        command: Vec::new(),
+        mode: Mode::Command,
+        pattern: None,
+        count: None,
+        interval: None,
+        rate: None,
+    };
+    let mut args = args.into_iter();
+    while let Some(arg) = args.next() {
+        let mut value = || {
+            args.next()
+                .ok_or_else(|| format!("Missing value for {arg}"))
+        };
+        match arg.as_str() {
+            "-h" => options.host = value()?,
+            "-p" => {
+                let port = value()?;
+                options.port = port.parse().map_err(|_| format!("Invalid port '{port}'"))?;
+            }
+            "-a" => options.password = Some(value()?),
+            "-c" => options.cluster = true,
+            "--raw" => options.raw = true,
+            "--no-raw" => options.raw = false,
+            "-i" => {
+                let interval = value()?;
+                options.interval = Some(
+                    interval
+                        .parse()
+                        .ok()
+                        .and_then(|secs| Duration::try_from_secs_f64(secs).ok())
+                        .ok_or_else(|| format!("Invalid interval '{interval}'"))?,
+                );
+            }
+            "--scan" => options.mode = Mode::Scan,
+            "--bigkeys" => options.mode = Mode::BigKeys,
+            "--stat" => options.mode = Mode::Stat,
+            "--latency" => options.mode = Mode::Latency,
+            "--pipe" => options.mode = Mode::Pipe,
+            "--replay-aof" => options.mode = Mode::ReplayAof(value()?),
+            "--export" => options.mode = Mode::Export(value()?),
+            "--import" => options.mode = Mode::Import(value()?),
+            "--rate" => {
+                let rate = value()?;
+                options.rate = Some(
+                    rate.parse()
+                        .ok()
+                        .filter(|&rate| rate > 0)
+                        .ok_or_else(|| format!("Invalid rate '{rate}'"))?,
+                );
+            }
+            "--pattern" => options.pattern = Some(value()?),
+            "--count" => {
+                let count = value()?;
+                options.count = Some(
+                    count
+                        .parse()
+                        .ok()
+                        .filter(|&count| count > 0)
+                        .ok_or_else(|| format!("Invalid count '{count}'"))?,
+                );
+            }
+            "--help" => return Err(USAGE.to_string()),
+            flag if flag.starts_with('-') => {
+                return Err(format!(
+                    "Unrecognized option or bad number of args for: '{flag}'"
+                ))
+            }
+            _ => {
+                options.command.push(arg);
+                options.command.extend(args);
+                break;
+            }
+        }
+    }
+    Ok(options)
+}
+
+/// Splits a line typed at the prompt into arguments. Arguments may be quoted with `"`, which
+/// understands backslash escapes, or with `'`, which takes everything literally.
+fn split_args(line: &str) -> Result<Vec<String>, String> {
+    let mut args = Vec::new();
+    let mut chars = line.chars().peekable();
+    loop {
+        while chars.next_if(|c| c.is_whitespace()).is_some() {}
+        let Some(&first) = chars.peek() else {
+            return Ok(args);
+        };
+        let mut arg = String::new();
+        if first == '"' || first == '\'' {
+            chars.next();
+            loop {
+                match chars.next() {
+                    None => return Err("Invalid argument(s)".to_string()),
+                    Some(c) if c == first => break,
+                    Some('\\') if first == '"' => match chars.next() {
+                        Some('n') => arg.push('\n'),
+                        Some('r') => arg.push('\r'),
+                        Some('t') => arg.push('\t'),
+                        Some('a') => arg.push('\x07'),
+                        Some('b') => arg.push('\x08'),
+                        Some('x') => {
+                            let hex: String = chars.by_ref().take(2).collect();
+                            let byte = u8::from_str_radix(&hex, 16)
+                                .map_err(|_| "Invalid argument(s)".to_string())?;
+                            arg.push(byte as char);
+                        }
+                        Some(c) => arg.push(c),
+                        None => return Err("Invalid argument(s)".to_string()),
+                    },
+                    Some(c) => arg.push(c),
+                }
+            }
+            // A closing quote must be followed by a space or the end of the line.
+            if chars.peek().is_some_and(|c| !c.is_whitespace()) {
+                return Err("Invalid argument(s)".to_string());
+            }
+        } else {
+            while let Some(c) = chars.next_if(|c| !c.is_whitespace()) {
+                arg.push(c);
+            }
+        }
+        args.push(arg);
+    }
+}
+
+/// Quotes a string the way redis-cli shows bulk strings, escaping anything unprintable.
+fn quote(s: &str) -> String {
+    let mut quoted = String::from("\"");
+    for c in s.chars() {
+        match c {
+            '\\' => quoted.push_str("\\\\"),
+            '"' => quoted.push_str("\\\""),
+            '\n' => quoted.push_str("\\n"),
+            '\r' => quoted.push_str("\\r"),
+            '\t' => quoted.push_str("\\t"),
+            '\x07' => quoted.push_str("\\a"),
+            '\x08' => quoted.push_str("\\b"),
+            c if c.is_control() => quoted.push_str(&format!("\\x{:02x}", c as u32)),
+            c => quoted.push(c),
+        }
+    }
+    quoted.push('"');
+    quoted
+}
+
+/// The error message without the `-` that marks it as carrying its own code.
+fn error_message(e: &str) -> &str {
+    e.strip_prefix('-').unwrap_or(e)
+}
+
+/// A reply in redis-cli's annotated format, each line ending in a newline. Lines after the
+/// first are indented by `indent`, so nested arrays line up under their index.
+fn format_formatted(reply: &RespData, indent: usize) -> String {
+    match reply {
+        RespData::SimpleString(s) => format!("{s}\n"),
+        RespData::Error(e) => format!("(error) {}\n", error_message(e)),
+        RespData::Integer(n) => format!("(integer) {n}\n"),
+        RespData::BulkString(s) => format!("{}\n", quote(s)),
+        RespData::Null => "(nil)\n".to_string(),
+        RespData::Array(items) | RespData::Push(items) if items.is_empty() => {
+            "(empty array)\n".to_string()
+        }
+        RespData::Map(entries) if entries.is_empty() => "(empty hash)\n".to_string(),
+        RespData::Map(entries) => {
+            let width = entries.len().to_string().len();
+            let mut out = String::new();
+            for (i, (key, value)) in entries.iter().enumerate() {
+                if i > 0 {
+                    out.push_str(&" ".repeat(indent));
+                }
+                let label = format!("{:>width$}# ", i + 1);
+                let key = format_formatted(key, indent + label.len());
+                out.push_str(&label);
+                out.push_str(key.trim_end_matches('\n'));
+                out.push_str(" => ");
+                out.push_str(&format_formatted(value, indent + label.len()));
+            }
+            out
+        }
+        RespData::Array(items) | RespData::Push(items) => {
+            let width = items.len().to_string().len();
+            let mut out = String::new();
+            for (i, item) in items.iter().enumerate() {
+                if i > 0 {
+                    out.push_str(&" ".repeat(indent));
+                }
+                let label = format!("{:>width$}) ", i + 1);
+                out.push_str(&label);
+                out.push_str(&format_formatted(item, indent + label.len()));
+            }
+            out
+        }
+    }
+}
+
+/// A reply as plain text, array elements on separate lines.
+fn format_raw(reply: &RespData) -> String {
+    match reply {
+        RespData::SimpleString(s) | RespData::BulkString(s) => s.clone(),
+        RespData::Error(e) => error_message(e).to_string(),
+        RespData::Integer(n) => n.to_string(),
+        RespData::Null => String::new(),
+        RespData::Array(items) | RespData::Push(items) => {
+            items.iter().map(format_raw).collect::<Vec<_>>().join("\n")
+        }
+        RespData::Map(entries) => entries
+            .iter()
+            .flat_map(|(key, value)| [format_raw(key), format_raw(value)])
+            .collect::<Vec<_>>()
+            .join("\n"),
+    }
+}
+
+fn format_reply(reply: &RespData, raw: bool) -> String {
+    if raw {
+        format!("{}\n", format_raw(reply))
+    } else {
+        format_formatted(reply, 0)
+    }
+}
+
+struct Connection {
+    reader: Resp<TcpStream>,
+    writer: BufWriter<TcpStream>,
+}
+
+impl Connection {
+    fn open(host: &str, port: u16) -> io::Result<Self> {
+        let stream = TcpStream::connect((host, port))?;
+        Ok(Self {
+            reader: Resp::new(stream.try_clone()?),
+            writer: BufWriter::new(stream),
+        })
+    }
+
+    /// Sends a command and waits for its reply.
+    fn run(&mut self, args: &[String]) -> io::Result<RespData> {
+        self.send(args)?;
+        self.writer.flush()?;
+        self.reader.read()
+    }
+
+    /// Sends all the commands in one go, then waits for all their replies.
+    fn run_all(
+        &mut self,
+        commands: impl IntoIterator<Item = Vec<String>>,
+    ) -> io::Result<Vec<RespData>> {
+        let mut sent = 0;
+        for args in commands {
+            self.send(&args)?;
+            sent += 1;
+        }
+        self.writer.flush()?;
+        (0..sent).map(|_| self.reader.read()).collect()
+    }
+
+    fn send(&mut self, args: &[String]) -> io::Result<()> {
+        let command = RespData::Array(args.iter().cloned().map(RespData::BulkString).collect());
+        command.write(&mut self.writer)
+    }
+}
+
+/// Whether `e` is just the server closing the connection after a successful SHUTDOWN.
+fn is_shutdown(args: &[String], e: &io::Error) -> bool {
+    args[0].eq_ignore_ascii_case("shutdown") && e.kind() == io::ErrorKind::UnexpectedEof
+}
+
+/// Connects and authenticates, reporting failures to stderr.
+fn connect(host: &str, port: u16, password: Option<&str>) -> Option<Connection> {
+    let mut connection = match Connection::open(host, port) {
+        Ok(connection) => connection,
+        Err(e) => {
+            eprintln!("Could not connect to Redis at {host}:{port}: {e}");
+            return None;
+        }
+    };
+    if let Some(password) = password {
+        match connection.run(&["AUTH".to_string(), password.to_string()]) {
+            Ok(RespData::Error(e)) => eprintln!("AUTH failed: {}", error_message(&e)),
+            Ok(_) => {}
+            Err(e) => {
+                eprintln!("AUTH failed: {e}");
+                return None;
+            }
+        }
+    }
+    Some(connection)
+}
+
+/// Where the commands typed at the prompt or given on the command line go: the node
+/// connected to, or with -c whichever node of the cluster serves their key.
+enum Session {
+    Node(Connection),
+    Cluster(Cluster),
+}
+
+impl Session {
+    fn open(options: &Options) -> Option<Self> {
+        let connection = connect(&options.host, options.port, options.password.as_deref())?;
+        Some(if options.cluster {
+            Session::Cluster(Cluster::new(
+                connection,
+                &options.host,
+                options.port,
+                options.password.clone(),
+            ))
+        } else {
+            Session::Node(connection)
+        })
+    }
+
+    fn run(&mut self, args: &[String]) -> io::Result<RespData> {
+        match self {
+            Session::Node(connection) => connection.run(args),
+            Session::Cluster(cluster) => cluster.run(args),
+        }
+    }
+
+    fn prompt(&self, options: &Options) -> String {
+        match self {
+            Session::Node(_) => format!("{}:{}> ", options.host, options.port),
+            Session::Cluster(cluster) => format!("{}> ", cluster.current()),
+        }
+    }
+}
+
+/// Reads commands from stdin until it is closed or the user quits, reconnecting as needed.
+fn repl(options: &Options) {
+    let interactive = io::stdin().is_terminal();
+    let mut session = Session::open(options);
+    let mut lines = io::stdin().lock().lines();
+    loop {
+        if interactive {
+            match &session {
+                Some(session) => print!("{}", session.prompt(options)),
+                None => print!("not connected> "),
+            }
+            let _ = io::stdout().flush();
+        }
+        let Some(Ok(line)) = lines.next() else {
+            return;
+        };
+        let args = match split_args(&line) {
+            Ok(args) if args.is_empty() => continue,
+            Ok(args) => args,
+            Err(e) => {
+                println!("{e}");
+                continue;
+            }
+        };
+        if args[0].eq_ignore_ascii_case("quit") || args[0].eq_ignore_ascii_case("exit") {
+            return;
+        }
+
+        if session.is_none() {
+            session = Session::open(options);
+        }
+        let Some(current) = session.as_mut() else {
+            continue;
+        };
+        match current.run(&args) {
+            Ok(reply) => print!("{}", format_reply(&reply, options.raw)),
+            Err(e) if is_shutdown(&args, &e) => return,
+            Err(e) => {
+                eprintln!("Error: {e}");
+                session = None;
+            }
+        }
+    }
+}
+
+fn main() {
+    let raw = !io::stdout().is_terminal();
+    let options = parse_options(env::args().skip(1), raw).unwrap_or_else(|e| {
+        eprintln!("{e}");
+        process::exit(1);
+    });
+    if options.password.is_some() {
+        eprintln!(
+            "Warning: Using a password with '-a' option on the command line interface may not \
+             be safe."
+        );
+    }
+
+    if options.mode == Mode::Command && options.command.is_empty() {
+        repl(&options);
+        return;
+    }
+    if options.mode == Mode::Command {
+        let Some(mut session) = Session::open(&options) else {
+            process::exit(1);
+        };
+        match session.run(&options.command) {
+            Ok(reply) => print!("{}", format_reply(&reply, options.raw)),
+            Err(e) if is_shutdown(&options.command, &e) => {}
+            Err(e) => {
+                eprintln!("Error: {e}");
+                process::exit(1);
+            }
+        }
+        return;
+    }
+    let Some(mut connection) = connect(&options.host, options.port, options.password.as_deref())
+    else {
+        process::exit(1);
+    };
+    if let Mode::Pipe | Mode::ReplayAof(_) | Mode::Import(_) = options.mode {
+        let result = match &options.mode {
+            Mode::ReplayAof(path) => replay::replay_aof(connection, path, options.rate),
+            Mode::Import(path) => dataset::import(connection, path),
+            _ => pipe::pipe_stdin(connection),
+        };
+        match result {
+            Ok(stats) if stats.errors > 0 => process::exit(1),
+            Ok(_) => {}
+            Err(e) => {
+                eprintln!("Error: {e}");
+                process::exit(1);
+            }
+        }
+        return;
+    }
+    let analysis = match &options.mode {
+        Mode::Command | Mode::Pipe | Mode::ReplayAof(_) | Mode::Import(_) => None,
+        Mode::Scan => Some(analysis::scan(&mut connection, &options)),
+        Mode::BigKeys => Some(analysis::big_keys(&mut connection, &options)),
+        Mode::Stat => Some(analysis::stat(&mut connection, &options)),
+        Mode::Latency => Some(analysis::latency(&mut connection, &options)),
+        Mode::Export(path) => Some(dataset::export(&mut connection, &options, path)),
+    };
+    if let Some(Err(e)) = analysis {
+        eprintln!("Error: {e}");
+        process::exit(1);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn strings(args: &[&str]) -> Vec<String> {
+        args.iter().map(|arg| arg.to_string()).collect()
+    }
+
+    #[test]
+    fn test_parse_options() {
+        let defaults = || parse_options(Vec::new(), false).unwrap();
+        assert_eq!(defaults().host, DEFAULT_HOST);
+        assert_eq!(defaults().port, DEFAULT_PORT);
+
+        let test_cases = [
+            (
+                "Connection flags and a command",
+                vec!["-h", "example.com", "-p", "7000", "-a", "pw", "GET", "-p"],
+                Ok(Options {
+                    host: "example.com".to_string(),
+                    port: 7000,
+                    password: Some("pw".to_string()),
+                    command: strings(&["GET", "-p"]),
+                    ..defaults()
+                }),
+            ),
+            (
+                "Raw output",
+                vec!["--raw", "PING"],
+                Ok(Options {
+                    raw: true,
+                    command: strings(&["PING"]),
+                    ..defaults()
+                }),
+            ),
+            (
+                "Cluster mode",
+                vec!["-c", "-p", "7000", "GET", "foo"],
+                Ok(Options {
+                    port: 7000,
+                    cluster: true,
+                    command: strings(&["GET", "foo"]),
+                    ..defaults()
+                }),
+            ),
+            (
+                "Analysis mode",
+                vec![
+                    "--bigkeys",
+                    "--pattern",
+                    "user:*",
+                    "--count",
+                    "100",
+                    "-i",
+                    "0.1",
+                ],
+                Ok(Options {
+                    mode: Mode::BigKeys,
+                    pattern: Some("user:*".to_string()),
+                    count: Some(100),
+                    interval: Some(Duration::from_millis(100)),
+                    ..defaults()
+                }),
+            ),
+            (
+                "AOF replay",
+                vec!["--replay-aof", "appendonly.aof", "--rate", "500"],
+                Ok(Options {
+                    mode: Mode::ReplayAof("appendonly.aof".to_string()),
+                    rate: Some(500),
+                    ..defaults()
+                }),
+            ),
+            (
+                "Export",
+                vec!["--export", "-", "--pattern", "session:*"],
+                Ok(Options {
+                    mode: Mode::Export("-".to_string()),
+                    pattern: Some("session:*".to_string()),
+                    ..defaults()
+                }),
+            ),
+            (
+                "Bad interval",
+                vec!["--stat", "-i", "-1"],
+                Err("Invalid interval '-1'".to_string()),
+            ),
+            (
+                "Bad count",
+                vec!["--scan", "--count", "0"],
+                Err("Invalid count '0'".to_string()),
+            ),
+            (
+                "Bad port",
+                vec!["-p", "port"],
+                Err("Invalid port 'port'".to_string()),
+            ),
+            (
+                "Missing value",
+                vec!["-h"],
+                Err("Missing value for -h".to_string()),
+            ),
+            (
+                "Unknown flag",
+                vec!["--nope"],
+                Err("Unrecognized option or bad number of args for: '--nope'".to_string()),
+            ),
+        ];
+
+        for (name, args, expected) in test_cases {
+            assert_eq!(parse_options(strings(&args), false), expected, "{}", name);
+        }
+    }
+
+    #[test]
+    fn test_split_args() {
+        let test_cases = [
+            (
+                "Plain words",
+                "set  key value ",
+                Ok(strings(&["set", "key", "value"])),
+            ),
+            ("Empty line", "   ", Ok(vec![])),
+            (
+                "Double quotes with escapes",
+                r#"set key "a \"b\"\n\x41""#,
+                Ok(strings(&["set", "key", "a \"b\"\nA"])),
+            ),
+            (
+                "Single quotes are literal",
+                r"echo 'a\nb' ''",
+                Ok(strings(&["echo", r"a\nb", ""])),
+            ),
+            (
+                "Unterminated quote",
+                r#"echo "abc"#,
+                Err("Invalid argument(s)".to_string()),
+            ),
+            (
+                "Text after a quote",
+                r#"echo "a"b"#,
+                Err("Invalid argument(s)".to_string()),
+            ),
+        ];
+
+        for (name, line, expected) in test_cases {
+            assert_eq!(split_args(line), expected, "{}", name);
+        }
+    }
+
+    #[test]
+    fn test_format_reply() {
+        let bulk = |s: &str| RespData::BulkString(s.to_string());
+        let nested = RespData::Array(vec![
+            bulk("a"),
+            RespData::Array(vec![RespData::Integer(1), RespData::Null]),
+        ]);
+        let long = RespData::Array((0..10).map(|i| bulk(&i.to_string())).collect());
+
+        let test_cases = [
+            (
+                "Status",
+                RespData::SimpleString("OK".to_string()),
+                "OK\n",
+                "OK\n",
+            ),
+            (
+                "Error",
+                RespData::Error("-ERR unknown".to_string()),
+                "(error) ERR unknown\n",
+                "ERR unknown\n",
+            ),
+            ("Integer", RespData::Integer(7), "(integer) 7\n", "7\n"),
+            ("Bulk string", bulk("a\"b\n"), "\"a\\\"b\\n\"\n", "a\"b\n\n"),
+            ("Nil", RespData::Null, "(nil)\n", "\n"),
+            (
+                "Empty array",
+                RespData::Array(vec![]),
+                "(empty array)\n",
+                "\n",
+            ),
+            (
+                "Nested array",
+                nested,
+                "1) \"a\"\n2) 1) (integer) 1\n   2) (nil)\n",
+                "a\n1\n\n",
+            ),
+            (
+                "Indexes are aligned",
+                long,
+                " 1) \"0\"\n 2) \"1\"\n 3) \"2\"\n 4) \"3\"\n 5) \"4\"\n 6) \"5\"\n 7) \"6\"\n \
+                 8) \"7\"\n 9) \"8\"\n10) \"9\"\n",
+                "0\n1\n2\n3\n4\n5\n6\n7\n8\n9\n",
+            ),
+        ];
+
+        for (name, reply, formatted, raw) in test_cases {
+            assert_eq!(format_reply(&reply, false), formatted, "{}", name);
+            assert_eq!(format_reply(&reply, true), raw, "{}: raw", name);
+        }
+    }
+}
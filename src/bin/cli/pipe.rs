@@ -0,0 +1,123 @@
+//! --pipe: mass insertion. The RESP stream on stdin is written to the server as fast as it will
+//! take it while the replies are read concurrently, so there is no round trip per command.
+//! An ECHO of a random marker is sent after the last command; once it comes back every reply
+//! has been read.
+
+use super::{error_message, Connection};
+use redis_from_scratch::resp::{Resp, RespData};
+use std::collections::hash_map::RandomState;
+use std::hash::{BuildHasher, Hasher};
+use std::io::{self, Read, Write};
+use std::thread;
+
+#[derive(Debug, Default, PartialEq)]
+pub(super) struct PipeStats {
+    pub(super) replies: u64,
+    pub(super) errors: u64,
+}
+
+/// A marker no real reply will match.
+pub(super) fn random_marker() -> String {
+    (0..2)
+        .map(|_| format!("{:016x}", RandomState::new().build_hasher().finish()))
+        .collect()
+}
+
+/// Writes commands to `writer` with `send`, followed by ECHO `marker`, while counting the
+/// replies read from `reader` up to the marker's echo. Error replies are printed as they
+/// arrive. The marker is sent even if `send` fails, so the replies to what was sent are
+/// still read.
+pub(super) fn pipe<R: Read, W: Write + Send>(
+    send: impl FnOnce(&mut W) -> io::Result<()> + Send,
+    mut writer: W,
+    reader: &mut Resp<R>,
+    marker: &str,
+) -> io::Result<PipeStats> {
+    thread::scope(|scope| {
+        let sender = scope.spawn(move || -> io::Result<()> {
+            let sent = send(&mut writer);
+            let echo = RespData::Array(vec![
+                RespData::BulkString("ECHO".to_string()),
+                RespData::BulkString(marker.to_string()),
+            ]);
+            echo.write(&mut writer)?;
+            writer.flush()?;
+            if sent.is_ok() {
+                println!("All data transferred. Waiting for the last reply...");
+            }
+            sent
+        });
+
+        let mut stats = PipeStats::default();
+        loop {
+            match reader.read()? {
+                RespData::BulkString(s) if s == marker => break,
+                RespData::Error(e) => {
+                    println!("{}", error_message(&e));
+                    stats.errors += 1;
+                }
+                _ => {}
+            }
+            stats.replies += 1;
+        }
+        sender.join().expect("pipe sender panicked")?;
+        Ok(stats)
+    })
+}
+
+/// Sends stdin to the server, printing the totals once the last reply has arrived.
+pub(super) fn pipe_stdin(connection: Connection) -> io::Result<PipeStats> {
+    let Connection { mut reader, writer } = connection;
+    let send = |writer: &mut _| io::copy(&mut io::stdin(), writer).map(drop);
+    let stats = pipe(send, writer, &mut reader, &random_marker())?;
+    println!("Last reply received from server.");
+    println!("errors: {}, replies: {}", stats.errors, stats.replies);
+    Ok(stats)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+
+    #[test]
+    fn test_pipe() {
+        let input = "*3\r\n$3\r\nSET\r\n$1\r\na\r\n$1\r\n1\r\n*1\r\n$4\r\nNOPE\r\n";
+        let marker = random_marker();
+        let replies = format!(
+            "+OK\r\n-ERR unknown command 'NOPE'\r\n${}\r\n{marker}\r\n",
+            marker.len()
+        );
+
+        let mut written = Vec::new();
+        let mut reader = Resp::new(Cursor::new(replies.into_bytes()));
+        let copy = |writer: &mut _| io::copy(&mut input.as_bytes(), writer).map(drop);
+        let stats = pipe(copy, &mut written, &mut reader, &marker).unwrap();
+        assert_eq!(
+            stats,
+            PipeStats {
+                replies: 2,
+                errors: 1
+            }
+        );
+        assert_eq!(
+            String::from_utf8(written).unwrap(),
+            format!(
+                "{input}*2\r\n$4\r\nECHO\r\n${}\r\n{marker}\r\n",
+                marker.len()
+            )
+        );
+
+        // The server closing the connection early is an error, not a hang.
+        let mut reader = Resp::new(Cursor::new(b"+OK\r\n".to_vec()));
+        let copy = |writer: &mut _| io::copy(&mut input.as_bytes(), writer).map(drop);
+        assert!(pipe(copy, io::sink(), &mut reader, &marker).is_err());
+
+        // So is a failure to send, once the replies to what was sent have been read.
+        let fail = |_: &mut _| Err(io::Error::other("bad input"));
+        let echo = format!("${}\r\n{marker}\r\n", marker.len());
+        let mut reader = Resp::new(Cursor::new(echo.into_bytes()));
+        let e = pipe(fail, io::sink(), &mut reader, &marker).unwrap_err();
+        assert_eq!(e.to_string(), "bad input");
+    }
+}
@@ -0,0 +1,141 @@
+//! --replay-aof: sends the commands recorded in an append only file to a running server,
+//! optionally at a fixed rate, to reproduce production traffic against another instance.
+//! Replies are counted the same way --pipe counts them.
+
+use super::pipe::{self, PipeStats};
+use super::Connection;
+use redis_from_scratch::resp::{Resp, RespData};
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::thread;
+use std::time::{Duration, Instant};
+
+fn invalid(message: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+/// Writes every command in `aof` to `writer`, at most `rate` per second if given. Returns the
+/// number of commands sent.
+fn send_commands<R: Read>(
+    aof: &mut Resp<R>,
+    rate: Option<u64>,
+    writer: &mut impl Write,
+) -> io::Result<u64> {
+    let start = Instant::now();
+    let mut sent = 0;
+    loop {
+        let command = match aof.read() {
+            Ok(command) => command,
+            // Nothing buffered means the file ended between commands.
+            Err(e) if e.kind() == io::ErrorKind::UnexpectedEof && aof.raw_data.is_empty() => {
+                return Ok(sent);
+            }
+            Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => {
+                return Err(invalid(format!(
+                    "the AOF ends in the middle of command {}, check it with --check-aof",
+                    sent + 1
+                )));
+            }
+            Err(e) => return Err(e),
+        };
+        if !matches!(&command, RespData::Array(args) if !args.is_empty()) {
+            return Err(invalid(format!(
+                "command {} in the AOF is not a RESP command",
+                sent + 1
+            )));
+        }
+
+        if let Some(rate) = rate {
+            let due = start + Duration::from_secs_f64(sent as f64 / rate as f64);
+            if let Some(wait) = due.checked_duration_since(Instant::now()) {
+                // Whatever is buffered is due now, not after the pause.
+                writer.flush()?;
+                thread::sleep(wait);
+            }
+        }
+        command.write(writer)?;
+        sent += 1;
+    }
+}
+
+/// Replays the AOF at `path`, printing the totals once the last reply has arrived.
+pub(super) fn replay_aof(
+    connection: Connection,
+    path: &str,
+    rate: Option<u64>,
+) -> io::Result<PipeStats> {
+    let mut file = BufReader::new(File::open(path)?);
+    if file.fill_buf()?.starts_with(b"REDIS") {
+        return Err(invalid(
+            "AOF files with an RDB preamble can't be replayed".to_string(),
+        ));
+    }
+    let mut aof = Resp::new(file);
+
+    let Connection { mut reader, writer } = connection;
+    let start = Instant::now();
+    let mut sent = 0;
+    let send = |writer: &mut _| {
+        sent = send_commands(&mut aof, rate, writer)?;
+        Ok(())
+    };
+    let stats = pipe::pipe(send, writer, &mut reader, &pipe::random_marker())?;
+    let elapsed = start.elapsed().as_secs_f64();
+    println!(
+        "Replayed {sent} commands in {elapsed:.2} seconds ({:.2} per second)",
+        sent as f64 / elapsed.max(f64::EPSILON)
+    );
+    println!("errors: {}, replies: {}", stats.errors, stats.replies);
+    Ok(stats)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+
+    #[test]
+    fn test_send_commands() {
+        let commands = "*3\r\n$3\r\nSET\r\n$1\r\na\r\n$1\r\n1\r\n*2\r\n$4\r\nINCR\r\n$1\r\na\r\n";
+
+        let test_cases = [
+            ("Whole file", commands.to_string(), Ok(2)),
+            ("Empty file", String::new(), Ok(0)),
+            (
+                "Truncated",
+                format!("{commands}*2\r\n$3\r\nGET"),
+                Err("the AOF ends in the middle of command 3, check it with --check-aof"),
+            ),
+            (
+                "Not a command",
+                format!("{commands}+OK\r\n"),
+                Err("command 3 in the AOF is not a RESP command"),
+            ),
+        ];
+
+        for (name, input, expected) in test_cases {
+            let mut aof = Resp::new(Cursor::new(input.into_bytes()));
+            let result = send_commands(&mut aof, None, &mut io::sink());
+            assert_eq!(
+                result.map_err(|e| e.to_string()),
+                expected.map_err(str::to_string),
+                "{}",
+                name
+            );
+        }
+
+        let mut written = Vec::new();
+        let mut aof = Resp::new(Cursor::new(commands.as_bytes().to_vec()));
+        send_commands(&mut aof, None, &mut written).unwrap();
+        assert_eq!(String::from_utf8(written).unwrap(), commands);
+
+        // Six commands at 100 per second are spread over at least 50ms.
+        let mut aof = Resp::new(Cursor::new(commands.repeat(3).into_bytes()));
+        let start = Instant::now();
+        assert_eq!(
+            send_commands(&mut aof, Some(100), &mut io::sink()).unwrap(),
+            6
+        );
+        assert!(start.elapsed() >= Duration::from_millis(50));
+    }
+}
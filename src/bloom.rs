@@ -0,0 +1,290 @@
+//! Scalable Bloom filters for the BF.* commands, laid out like RedisBloom's: a chain of fixed
+//! size layers, where a new one is added once the last is full. Each layer has `expansion`
+//! times the capacity of the one before and half its error rate, which keeps the error rate
+//! of the whole chain bounded however far it grows.
+
+use std::f64::consts::LN_2;
+
+/// Each new layer's error rate relative to the one before.
+const TIGHTENING_RATIO: f64 = 0.5;
+
+/// The largest single allocation, in bytes, of a Bloom filter layer or of a sketch's table,
+/// the same as the largest string Redis stores.
+pub(crate) const MAX_TABLE_BYTES: u64 = 512 * 1024 * 1024;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct BloomFilter {
+    error_rate: f64,
+    /// How many times larger each new layer is, or `None` for a filter that never grows.
+    expansion: Option<u32>,
+    layers: Vec<Layer>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+struct Layer {
+    bits: Vec<u64>,
+    hashes: u32,
+    capacity: u64,
+    count: u64,
+}
+
+impl Layer {
+    fn new(capacity: u64, error_rate: f64) -> Result<Layer, String> {
+        let bits = (capacity as f64 * -error_rate.ln() / (LN_2 * LN_2)).ceil();
+        if bits / 8.0 > MAX_TABLE_BYTES as f64 {
+            return Err("filter would be too large".to_string());
+        }
+        Ok(Layer {
+            bits: vec![0; (bits as usize).div_ceil(64).max(1)],
+            hashes: (-error_rate.log2()).ceil().max(1.0) as u32,
+            capacity,
+            count: 0,
+        })
+    }
+
+    /// The bits an item with the hashes `(h1, h2)` maps to, by double hashing.
+    fn positions(&self, (h1, h2): (u64, u64)) -> impl Iterator<Item = usize> {
+        let size = self.bits.len() as u64 * 64;
+        (0..self.hashes as u64).map(move |i| (h1.wrapping_add(i.wrapping_mul(h2)) % size) as usize)
+    }
+
+    fn contains(&self, hash: (u64, u64)) -> bool {
+        self.positions(hash)
+            .all(|bit| self.bits[bit / 64] & (1 << (bit % 64)) != 0)
+    }
+
+    fn insert(&mut self, hash: (u64, u64)) {
+        for bit in self.positions(hash).collect::<Vec<_>>() {
+            self.bits[bit / 64] |= 1 << (bit % 64);
+        }
+        self.count += 1;
+    }
+}
+
+impl BloomFilter {
+    /// An empty filter holding `capacity` items at `error_rate` before it first grows.
+    pub fn new(error_rate: f64, capacity: u64, expansion: Option<u32>) -> Result<Self, String> {
+        Ok(BloomFilter {
+            error_rate,
+            expansion,
+            layers: vec![Layer::new(capacity, error_rate)?],
+        })
+    }
+
+    /// Whether `item` was probably added. False positives happen at about the error rate,
+    /// false negatives never.
+    pub fn contains(&self, item: &[u8]) -> bool {
+        let hash = hash(item);
+        self.layers.iter().any(|layer| layer.contains(hash))
+    }
+
+    /// Adds `item`, returning false if it was probably there already. Fails if the filter is
+    /// full and can't grow.
+    pub fn insert(&mut self, item: &[u8]) -> Result<bool, String> {
+        let hash = hash(item);
+        if self.layers.iter().any(|layer| layer.contains(hash)) {
+            return Ok(false);
+        }
+        let last = self.layers.last().unwrap();
+        if last.count >= last.capacity {
+            let Some(expansion) = self.expansion else {
+                return Err("non scaling filter is full".to_string());
+            };
+            let error_rate = self.error_rate * TIGHTENING_RATIO.powi(self.layers.len() as i32);
+            let capacity = last.capacity.saturating_mul(expansion as u64);
+            self.layers.push(Layer::new(capacity, error_rate)?);
+        }
+        self.layers.last_mut().unwrap().insert(hash);
+        Ok(true)
+    }
+
+    /// How many items were added.
+    pub fn len(&self) -> u64 {
+        self.layers.iter().map(|layer| layer.count).sum()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// How many layers the filter has grown to.
+    pub fn layer_count(&self) -> usize {
+        self.layers.len()
+    }
+
+    /// Roughly how many bytes the filter takes up in memory.
+    pub fn allocated_size(&self) -> usize {
+        self.layers
+            .iter()
+            .map(|layer| std::mem::size_of::<Layer>() + layer.bits.len() * 8)
+            .sum()
+    }
+
+    /// The filter in the binary form RDB snapshots store it in.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut out = Vec::with_capacity(self.allocated_size() + 16);
+        out.extend(self.error_rate.to_le_bytes());
+        out.extend(self.expansion.unwrap_or(0).to_le_bytes());
+        out.extend((self.layers.len() as u32).to_le_bytes());
+        for layer in &self.layers {
+            out.extend(layer.capacity.to_le_bytes());
+            out.extend(layer.count.to_le_bytes());
+            out.extend(layer.hashes.to_le_bytes());
+            out.extend((layer.bits.len() as u64).to_le_bytes());
+            for word in &layer.bits {
+                out.extend(word.to_le_bytes());
+            }
+        }
+        out
+    }
+
+    /// Reads a filter written by `to_bytes`.
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, String> {
+        let mut input = Input(bytes);
+        let error_rate = f64::from_le_bytes(input.take()?);
+        let expansion = Some(u32::from_le_bytes(input.take()?)).filter(|&n| n > 0);
+        let layers = u32::from_le_bytes(input.take()?);
+
+        let mut filter = BloomFilter {
+            error_rate,
+            expansion,
+            layers: Vec::new(),
+        };
+        for _ in 0..layers {
+            let capacity = u64::from_le_bytes(input.take()?);
+            let count = u64::from_le_bytes(input.take()?);
+            let hashes = u32::from_le_bytes(input.take()?);
+            let words = u64::from_le_bytes(input.take()?);
+            let bits = input
+                .slice(words.saturating_mul(8))?
+                .chunks_exact(8)
+                .map(|word| u64::from_le_bytes(word.try_into().unwrap()))
+                .collect::<Vec<_>>();
+            if bits.is_empty() || hashes == 0 {
+                return Err("invalid Bloom filter layer".to_string());
+            }
+            filter.layers.push(Layer {
+                bits,
+                hashes,
+                capacity,
+                count,
+            });
+        }
+        if filter.layers.is_empty() || !input.0.is_empty() {
+            return Err("invalid Bloom filter".to_string());
+        }
+        Ok(filter)
+    }
+}
+
+/// What's left of the bytes `from_bytes` reads, here and for the sketches.
+pub(crate) struct Input<'a>(pub &'a [u8]);
+
+impl<'a> Input<'a> {
+    pub fn slice(&mut self, len: u64) -> Result<&'a [u8], String> {
+        if (self.0.len() as u64) < len {
+            return Err("truncated value".to_string());
+        }
+        let (taken, rest) = self.0.split_at(len as usize);
+        self.0 = rest;
+        Ok(taken)
+    }
+
+    pub fn take<const N: usize>(&mut self) -> Result<[u8; N], String> {
+        self.slice(N as u64).map(|taken| taken.try_into().unwrap())
+    }
+
+    /// A UTF-8 string after its `u32` length.
+    pub fn string(&mut self) -> Result<String, String> {
+        let len = u32::from_le_bytes(self.take()?);
+        String::from_utf8(self.slice(len as u64)?.to_vec())
+            .map_err(|_| "non UTF-8 string".to_string())
+    }
+}
+
+/// The two hashes of an item, seeded the way RedisBloom seeds them.
+fn hash(item: &[u8]) -> (u64, u64) {
+    let h1 = murmur64a(item, 0xc6a4_a793_5bd1_e995);
+    (h1, murmur64a(item, h1))
+}
+
+/// MurmurHash64A, which RedisBloom hashes items with.
+pub(crate) fn murmur64a(data: &[u8], seed: u64) -> u64 {
+    const M: u64 = 0xc6a4_a793_5bd1_e995;
+    const R: u32 = 47;
+    let mut h = seed ^ (data.len() as u64).wrapping_mul(M);
+    let mut chunks = data.chunks_exact(8);
+    for chunk in &mut chunks {
+        let mut k = u64::from_le_bytes(chunk.try_into().unwrap());
+        k = k.wrapping_mul(M);
+        k ^= k >> R;
+        k = k.wrapping_mul(M);
+        h ^= k;
+        h = h.wrapping_mul(M);
+    }
+    let tail = chunks.remainder();
+    if !tail.is_empty() {
+        let mut buf = [0; 8];
+        buf[..tail.len()].copy_from_slice(tail);
+        h ^= u64::from_le_bytes(buf);
+        h = h.wrapping_mul(M);
+    }
+    h ^= h >> R;
+    h = h.wrapping_mul(M);
+    h ^= h >> R;
+    h
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_error_rate() {
+        let mut filter = BloomFilter::new(0.01, 1000, Some(2)).unwrap();
+        for i in 0..1000 {
+            assert!(filter.insert(format!("item:{i}").as_bytes()).unwrap());
+        }
+        assert_eq!(filter.len(), 1000);
+        assert_eq!(filter.layer_count(), 1);
+        for i in 0..1000 {
+            assert!(filter.contains(format!("item:{i}").as_bytes()));
+        }
+        let false_positives = (0..10_000)
+            .filter(|i| filter.contains(format!("other:{i}").as_bytes()))
+            .count();
+        assert!(false_positives < 200, "{false_positives} false positives");
+    }
+
+    #[test]
+    fn test_scaling() {
+        let mut filter = BloomFilter::new(0.01, 10, Some(2)).unwrap();
+        for i in 0..100 {
+            filter.insert(&[i]).unwrap();
+        }
+        // Layers of 10, 20, 40 and 80 items.
+        assert_eq!(filter.layer_count(), 4);
+        assert!((0..100).all(|i| filter.contains(&[i])));
+
+        let mut filter = BloomFilter::new(0.01, 2, None).unwrap();
+        assert_eq!(filter.insert(b"a"), Ok(true));
+        assert_eq!(filter.insert(b"a"), Ok(false));
+        assert_eq!(filter.insert(b"b"), Ok(true));
+        assert_eq!(
+            filter.insert(b"c"),
+            Err("non scaling filter is full".to_string())
+        );
+    }
+
+    #[test]
+    fn test_bytes_round_trip() {
+        let mut filter = BloomFilter::new(0.001, 5, Some(3)).unwrap();
+        for i in 0..20 {
+            filter.insert(&[i]).unwrap();
+        }
+        let bytes = filter.to_bytes();
+        assert_eq!(BloomFilter::from_bytes(&bytes), Ok(filter));
+        assert!(BloomFilter::from_bytes(&bytes[..bytes.len() - 1]).is_err());
+        assert!(BloomFilter::from_bytes(&[]).is_err());
+    }
+}
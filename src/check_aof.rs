@@ -0,0 +1,399 @@
+//! `--check-aof`, after redis-check-aof: validates an append only file, which is a stream of
+//! commands in RESP, optionally after an RDB preamble. A file cut short by a crash can be
+//! repaired with `--fix`, which truncates it after the last complete command.
+
+use crate::check_rdb::CountingReader;
+use crate::config::Config;
+use crate::handler::{CommandHandler, ListpackLimits};
+use crate::rdb;
+use crate::resp::RespData;
+use std::collections::HashMap;
+use std::fs::{self, OpenOptions};
+use std::io::{self, BufRead, Write};
+use std::path::Path;
+use std::sync::{Arc, RwLock};
+
+/// How much of the file is valid, and the commands in that part.
+#[derive(Debug, PartialEq)]
+struct Analysis {
+    ok_up_to: usize,
+    ok_up_to_line: usize,
+    /// What is wrong with the rest of the file, if anything.
+    error: Option<String>,
+    commands: Vec<Vec<String>>,
+}
+
+/// Reads a line ending in `\r\n` at `pos`, advancing past it.
+fn read_line<'a>(data: &'a [u8], pos: &mut usize, lines: &mut usize) -> Result<&'a str, String> {
+    let rest = &data[*pos..];
+    let end = rest
+        .windows(2)
+        .position(|w| w == b"\r\n")
+        .ok_or("Unexpected EOF")?;
+    let line = std::str::from_utf8(&rest[..end]).map_err(|_| "Invalid line".to_string())?;
+    *pos += end + 2;
+    *lines += 1;
+    Ok(line)
+}
+
+/// Reads a `*<count>` or `$<length>` line.
+fn read_number(
+    data: &[u8],
+    pos: &mut usize,
+    lines: &mut usize,
+    prefix: char,
+) -> Result<usize, String> {
+    let line = read_line(data, pos, lines)?;
+    let Some(number) = line.strip_prefix(prefix) else {
+        return Err(format!(
+            "Expected prefix '{prefix}', got: '{}'",
+            line.chars().next().unwrap_or(' ')
+        ));
+    };
+    number
+        .parse()
+        .map_err(|_| format!("Invalid number '{number}'"))
+}
+
+/// Reads the command at `pos`, advancing past it.
+fn read_command(data: &[u8], pos: &mut usize, lines: &mut usize) -> Result<Vec<String>, String> {
+    let argc = read_number(data, pos, lines, '*')?;
+    let mut args = Vec::new();
+    for _ in 0..argc {
+        let len = read_number(data, pos, lines, '$')?;
+        let rest = &data[*pos..];
+        if rest.len() < len + 2 {
+            return Err("Unexpected EOF".to_string());
+        }
+        if &rest[len..len + 2] != b"\r\n" {
+            return Err(format!(
+                "Expected \\r\\n, got: {:02x}{:02x}",
+                rest[len],
+                rest[len + 1]
+            ));
+        }
+        args.push(String::from_utf8_lossy(&rest[..len]).into_owned());
+        *pos += len + 2;
+        *lines += 1;
+    }
+    Ok(args)
+}
+
+/// Reads commands from `start` until the end of the file or the first damaged one. A MULTI
+/// without its EXEC isn't valid, since the transaction never ran in full.
+fn analyze(data: &[u8], start: usize) -> Analysis {
+    let mut analysis = Analysis {
+        ok_up_to: start,
+        ok_up_to_line: 0,
+        error: None,
+        commands: Vec::new(),
+    };
+    let mut pos = start;
+    let mut lines = 0;
+    let mut transaction: Option<Vec<Vec<String>>> = None;
+    while pos < data.len() {
+        let args = match read_command(data, &mut pos, &mut lines) {
+            Ok(args) => args,
+            Err(e) => {
+                analysis.error = Some(e);
+                break;
+            }
+        };
+        let name = args.first().map(|name| name.to_ascii_lowercase());
+        match (name.as_deref(), &mut transaction) {
+            (Some("multi"), None) => transaction = Some(Vec::new()),
+            (Some("exec"), Some(_)) => {
+                analysis.commands.extend(transaction.take().unwrap());
+            }
+            (_, Some(queued)) => queued.push(args),
+            (_, None) => analysis.commands.push(args),
+        }
+        if transaction.is_none() {
+            analysis.ok_up_to = pos;
+            analysis.ok_up_to_line = lines;
+        }
+    }
+    if transaction.is_some() && analysis.error.is_none() {
+        analysis.error = Some("Reached EOF before reading EXEC for MULTI".to_string());
+    }
+    analysis
+}
+
+/// Loads the preamble's keys and replays the commands, then prints what the file holds.
+fn print_summary(
+    entries: Vec<rdb::Entry>,
+    commands: &[Vec<String>],
+    out: &mut impl Write,
+) -> io::Result<()> {
+    let db: HashMap<_, _> = entries.into_iter().map(|e| (e.key, e.value)).collect();
+    let mut handler = CommandHandler::new(db, Arc::new(RwLock::new(Config::default())));
+    let failed = commands
+        .iter()
+        .filter(|args| {
+            let command = RespData::Array(
+                args.iter()
+                    .map(|arg| RespData::BulkString(arg.clone()))
+                    .collect(),
+            );
+            matches!(handler.handle(&command), RespData::Error(_))
+        })
+        .count();
+    writeln!(
+        out,
+        "[info] {} commands replayed, {failed} of them failed",
+        commands.len()
+    )?;
+    write!(out, "{}", handler.big_keys_report())
+}
+
+/// Checks the AOF at `path`, returning whether it is valid once done. With `fix`, a damaged
+/// file is truncated to its valid part after confirmation is read from `confirm`.
+pub fn check_aof(
+    path: &Path,
+    fix: bool,
+    confirm: &mut impl BufRead,
+    out: &mut impl Write,
+) -> io::Result<bool> {
+    let name = path.display();
+    let data = match fs::read(path) {
+        Ok(data) => data,
+        Err(e) => {
+            writeln!(out, "Cannot open file {name}: {e}")?;
+            return Ok(false);
+        }
+    };
+
+    let mut entries = Vec::new();
+    let mut start = 0;
+    if data.starts_with(b"REDIS") {
+        writeln!(out, "The AOF appears to start with an RDB preamble.")?;
+        let mut input = CountingReader::new(data.as_slice());
+        match rdb::load(&mut input, ListpackLimits::default()) {
+            Ok(preamble) => {
+                writeln!(out, "RDB preamble is OK, proceeding with AOF tail...")?;
+                entries = preamble;
+                start = input.offset as usize;
+            }
+            Err(e) => {
+                writeln!(out, "[offset {}] {e}", input.offset)?;
+                writeln!(out, "RDB preamble of AOF file is not sane, aborting.")?;
+                return Ok(false);
+            }
+        }
+    }
+
+    let analysis = analyze(&data, start);
+    let diff = data.len() - analysis.ok_up_to;
+    if let Some(error) = &analysis.error {
+        writeln!(out, "0x{:>16x}: {error}", analysis.ok_up_to)?;
+    }
+    writeln!(
+        out,
+        "AOF analyzed: filename={name}, size={}, ok_up_to={}, ok_up_to_line={}, diff={diff}",
+        data.len(),
+        analysis.ok_up_to,
+        analysis.ok_up_to_line
+    )?;
+    if diff == 0 {
+        writeln!(out, "AOF {name} is valid")?;
+        print_summary(entries, &analysis.commands, out)?;
+        return Ok(true);
+    }
+    if !fix {
+        writeln!(
+            out,
+            "AOF {name} is not valid. Use the --fix option to try fixing it."
+        )?;
+        return Ok(false);
+    }
+
+    writeln!(
+        out,
+        "This will shrink the AOF {name} from {} bytes, with {diff} bytes, to {} bytes",
+        data.len(),
+        analysis.ok_up_to
+    )?;
+    write!(out, "Continue? [y/N]: ")?;
+    out.flush()?;
+    let mut answer = String::new();
+    confirm.read_line(&mut answer)?;
+    if !answer.trim_start().starts_with(['y', 'Y']) {
+        writeln!(out, "Aborting...")?;
+        return Ok(false);
+    }
+    OpenOptions::new()
+        .write(true)
+        .open(path)?
+        .set_len(analysis.ok_up_to as u64)?;
+    writeln!(out, "Successfully truncated AOF {name}")?;
+    print_summary(entries, &analysis.commands, out)?;
+    Ok(true)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::handler::RedisValue;
+    use std::process;
+
+    fn resp(commands: &[&[&str]]) -> String {
+        commands
+            .iter()
+            .map(|args| {
+                let mut out = format!("*{}\r\n", args.len());
+                for arg in *args {
+                    out.push_str(&format!("${}\r\n{arg}\r\n", arg.len()));
+                }
+                out
+            })
+            .collect()
+    }
+
+    fn strings(args: &[&str]) -> Vec<String> {
+        args.iter().map(|arg| arg.to_string()).collect()
+    }
+
+    #[test]
+    fn test_analyze() {
+        let valid = resp(&[&["SET", "a", "1"], &["HSET", "h", "f", "v"]]);
+        let transaction = resp(&[&["MULTI"], &["INCR", "a"], &["EXEC"]]);
+        let open_transaction = resp(&[&["MULTI"], &["INCR", "a"]]);
+
+        let test_cases = [
+            (
+                "Valid",
+                valid.clone(),
+                Analysis {
+                    ok_up_to: valid.len(),
+                    ok_up_to_line: 16,
+                    error: None,
+                    commands: vec![
+                        strings(&["SET", "a", "1"]),
+                        strings(&["HSET", "h", "f", "v"]),
+                    ],
+                },
+            ),
+            (
+                "Truncated",
+                format!("{valid}*3\r\n$3\r\nSET\r\n$1\r\nb"),
+                Analysis {
+                    ok_up_to: valid.len(),
+                    ok_up_to_line: 16,
+                    error: Some("Unexpected EOF".to_string()),
+                    commands: vec![
+                        strings(&["SET", "a", "1"]),
+                        strings(&["HSET", "h", "f", "v"]),
+                    ],
+                },
+            ),
+            (
+                "Garbage",
+                format!("{valid}hello\r\n"),
+                Analysis {
+                    ok_up_to: valid.len(),
+                    ok_up_to_line: 16,
+                    error: Some("Expected prefix '*', got: 'h'".to_string()),
+                    commands: vec![
+                        strings(&["SET", "a", "1"]),
+                        strings(&["HSET", "h", "f", "v"]),
+                    ],
+                },
+            ),
+            (
+                "Bad terminator",
+                "*1\r\n$4\r\nPINGxx".to_string(),
+                Analysis {
+                    ok_up_to: 0,
+                    ok_up_to_line: 0,
+                    error: Some("Expected \\r\\n, got: 7878".to_string()),
+                    commands: vec![],
+                },
+            ),
+            (
+                "Transaction",
+                transaction.clone(),
+                Analysis {
+                    ok_up_to: transaction.len(),
+                    ok_up_to_line: 11,
+                    error: None,
+                    commands: vec![strings(&["INCR", "a"])],
+                },
+            ),
+            (
+                "MULTI without EXEC",
+                format!("{valid}{open_transaction}"),
+                Analysis {
+                    ok_up_to: valid.len(),
+                    ok_up_to_line: 16,
+                    error: Some("Reached EOF before reading EXEC for MULTI".to_string()),
+                    commands: vec![
+                        strings(&["SET", "a", "1"]),
+                        strings(&["HSET", "h", "f", "v"]),
+                    ],
+                },
+            ),
+        ];
+
+        for (name, data, expected) in test_cases {
+            assert_eq!(analyze(data.as_bytes(), 0), expected, "{}", name);
+        }
+    }
+
+    #[test]
+    fn test_check_aof() {
+        let dir = std::env::temp_dir().join(format!("redis-check-aof-{}", process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("appendonly.aof");
+        let valid = resp(&[
+            &["SET", "a", "1"],
+            &["SET", "b", "22"],
+            &["LPUSH", "l", "x"],
+        ]);
+        let truncated = format!("{valid}*2\r\n$3\r\nDEL");
+
+        let run = |fix: bool, answer: &str| {
+            let mut out = Vec::new();
+            let ok = check_aof(&path, fix, &mut answer.as_bytes(), &mut out).unwrap();
+            (ok, String::from_utf8(out).unwrap())
+        };
+
+        fs::write(&path, &valid).unwrap();
+        let (ok, out) = run(false, "");
+        assert!(ok, "{out}");
+        assert!(out.contains("diff=0\nAOF "), "{out}");
+        assert!(
+            out.contains("[info] 3 commands replayed, 1 of them failed"),
+            "{out}"
+        );
+        assert!(out.contains("Sampled 2 keys in the keyspace!"), "{out}");
+
+        fs::write(&path, &truncated).unwrap();
+        let (ok, out) = run(false, "");
+        assert!(!ok);
+        assert!(out.contains("Use the --fix option"), "{out}");
+
+        let (ok, out) = run(true, "n\n");
+        assert!(!ok);
+        assert!(out.ends_with("Aborting...\n"), "{out}");
+        assert_eq!(fs::read(&path).unwrap(), truncated.as_bytes());
+
+        let (ok, out) = run(true, "y\n");
+        assert!(ok, "{out}");
+        assert!(out.contains("Successfully truncated AOF"), "{out}");
+        assert_eq!(fs::read(&path).unwrap(), valid.as_bytes());
+
+        // An RDB preamble is checked as a snapshot, then the commands after it.
+        let key = "from-rdb".to_string();
+        let value = RedisValue::String("v".into());
+        let mut data = Vec::new();
+        rdb::dump([(&key, &value, None)].into_iter(), &mut data).unwrap();
+        data.extend_from_slice(valid.as_bytes());
+        fs::write(&path, &data).unwrap();
+        let (ok, out) = run(false, "");
+        assert!(ok, "{out}");
+        assert!(out.contains("RDB preamble is OK"), "{out}");
+        assert!(out.contains("Sampled 3 keys in the keyspace!"), "{out}");
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,137 @@
+//! `--check-rdb`, after redis-check-rdb: reads a snapshot without starting the server and
+//! reports where it is damaged, or what it holds if it is intact.
+
+use crate::config::Config;
+use crate::handler::{CommandHandler, ListpackLimits};
+use crate::rdb::{self, Entry};
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{self, BufReader, Read, Write};
+use std::path::Path;
+use std::sync::{Arc, RwLock};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Counts the bytes read through it, so errors can be reported with their offset.
+pub struct CountingReader<R> {
+    inner: R,
+    pub offset: u64,
+}
+
+impl<R: Read> CountingReader<R> {
+    pub fn new(inner: R) -> Self {
+        Self { inner, offset: 0 }
+    }
+}
+
+impl<R: Read> Read for CountingReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let read = self.inner.read(buf)?;
+        self.offset += read as u64;
+        Ok(read)
+    }
+}
+
+/// Prints how many keys and expires the snapshot holds, then the big keys report for the keys
+/// that haven't expired yet.
+pub fn print_summary(entries: Vec<Entry>, out: &mut impl Write) -> io::Result<()> {
+    let now_ms = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_millis() as u64;
+    let keys = entries.len();
+    let expires = entries.iter().filter(|e| e.expire_at_ms.is_some()).count();
+    let live: HashMap<_, _> = entries
+        .into_iter()
+        .filter(|e| e.expire_at_ms.is_none_or(|ms| ms > now_ms))
+        .map(|e| (e.key, e.value))
+        .collect();
+    writeln!(out, "[info] {keys} keys read")?;
+    writeln!(out, "[info] {expires} expires")?;
+    writeln!(out, "[info] {} already expired", keys - live.len())?;
+
+    let handler = CommandHandler::new(live, Arc::new(RwLock::new(Config::default())));
+    write!(out, "{}", handler.big_keys_report())
+}
+
+/// Checks the snapshot at `path`, returning whether it is intact.
+pub fn check_rdb(path: &Path, out: &mut impl Write) -> io::Result<bool> {
+    writeln!(out, "[offset 0] Checking RDB file {}", path.display())?;
+    let file = match File::open(path) {
+        Ok(file) => file,
+        Err(e) => {
+            writeln!(out, "Cannot check RDB file {}: {e}", path.display())?;
+            return Ok(false);
+        }
+    };
+    let mut input = CountingReader::new(BufReader::new(file));
+    match rdb::load(&mut input, ListpackLimits::default()) {
+        Ok(entries) => {
+            writeln!(out, "[offset {}] \\o/ RDB looks OK! \\o/", input.offset)?;
+            print_summary(entries, out)?;
+            Ok(true)
+        }
+        Err(e) => {
+            writeln!(out, "--- RDB ERROR DETECTED ---")?;
+            writeln!(out, "[offset {}] {e}", input.offset)?;
+            Ok(false)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::handler::RedisValue;
+    use std::fs;
+    use std::process;
+
+    #[test]
+    fn test_check_rdb() {
+        let dir = std::env::temp_dir().join(format!("redis-check-rdb-{}", process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("dump.rdb");
+
+        let keys = ["a".to_string(), "b".to_string(), "gone".to_string()];
+        let value = RedisValue::String("value".into());
+        let mut snapshot = Vec::new();
+        rdb::dump(
+            [
+                (&keys[0], &value, None),
+                (&keys[1], &value, Some(u64::MAX)),
+                (&keys[2], &value, Some(1)),
+            ]
+            .into_iter(),
+            &mut snapshot,
+        )
+        .unwrap();
+
+        fs::write(&path, &snapshot).unwrap();
+        let mut out = Vec::new();
+        assert!(check_rdb(&path, &mut out).unwrap());
+        let out = String::from_utf8(out).unwrap();
+        let expected_lines = [
+            format!("[offset {}] \\o/ RDB looks OK! \\o/", snapshot.len()),
+            "[info] 3 keys read".to_string(),
+            "[info] 2 expires".to_string(),
+            "[info] 1 already expired".to_string(),
+            "Sampled 2 keys in the keyspace!".to_string(),
+        ];
+        for line in expected_lines {
+            assert!(out.lines().any(|l| l == line), "{line}\n{out}");
+        }
+
+        fs::write(&path, &snapshot[..snapshot.len() - 4]).unwrap();
+        let mut out = Vec::new();
+        assert!(!check_rdb(&path, &mut out).unwrap());
+        let out = String::from_utf8(out).unwrap();
+        assert!(out.contains("--- RDB ERROR DETECTED ---"), "{out}");
+        assert!(
+            out.contains(&format!("[offset {}] ", snapshot.len() - 4)),
+            "{out}"
+        );
+
+        let mut out = Vec::new();
+        assert!(!check_rdb(&dir.join("missing.rdb"), &mut out).unwrap());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,171 @@
+//! A `tokio_util` codec for RESP, so async clients and proxies can frame `RespData` on a
+//! socket with `Framed`. Decoding goes through the same parser as the server's connections.
+
+use crate::resp::{Framer, Resp, RespData, DEFAULT_MAX_BULK_LEN};
+use bytes::{Buf, BufMut, BytesMut};
+use std::io;
+use tokio_util::codec::{Decoder, Encoder};
+
+/// Decodes and encodes `RespData` values.
+#[derive(Debug, Clone)]
+pub struct RespCodec {
+    /// Bulk strings declared longer than this are rejected before they are buffered.
+    max_bulk_len: u64,
+    /// How much of the value at the front of the buffer has been framed.
+    framer: Framer,
+}
+
+impl RespCodec {
+    pub fn new() -> Self {
+        Self::with_max_bulk_len(DEFAULT_MAX_BULK_LEN)
+    }
+
+    /// A codec rejecting bulk strings longer than `max`, like `proto-max-bulk-len`.
+    pub fn with_max_bulk_len(max: u64) -> Self {
+        Self {
+            max_bulk_len: max,
+            framer: Framer::default(),
+        }
+    }
+}
+
+impl Default for RespCodec {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Decoder for RespCodec {
+    type Item = RespData;
+    type Error = io::Error;
+
+    fn decode(&mut self, src: &mut BytesMut) -> io::Result<Option<RespData>> {
+        let Some(len) = self.framer.frame_len(src, self.max_bulk_len)? else {
+            return Ok(None);
+        };
+        let mut resp = Resp::new(&src[..len]);
+        resp.set_max_bulk_len(self.max_bulk_len);
+        let value = resp.read()?;
+        src.advance(len);
+        Ok(Some(value))
+    }
+}
+
+impl Encoder<RespData> for RespCodec {
+    type Error = io::Error;
+
+    fn encode(&mut self, item: RespData, dst: &mut BytesMut) -> io::Result<()> {
+        self.encode(&item, dst)
+    }
+}
+
+impl Encoder<&RespData> for RespCodec {
+    type Error = io::Error;
+
+    fn encode(&mut self, item: &RespData, dst: &mut BytesMut) -> io::Result<()> {
+        item.write(&mut dst.writer())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn command(args: &[&str]) -> RespData {
+        RespData::array(args.iter().copied())
+    }
+
+    #[test]
+    fn test_decode_in_pieces() {
+        let mut codec = RespCodec::new();
+        let mut encoded = BytesMut::new();
+        let values = [
+            command(&["SET", "key", "a\r\nb"]),
+            RespData::Map(vec![(
+                RespData::SimpleString("server".to_string()),
+                RespData::BulkString("redis".to_string()),
+            )]),
+            RespData::Push(vec![
+                RespData::BulkString("invalidate".to_string()),
+                RespData::Array(vec![RespData::BulkString("key".to_string())]),
+            ]),
+            RespData::Error("-WRONGTYPE bad".to_string()),
+            RespData::Null,
+            RespData::Integer(7),
+        ];
+        for value in &values {
+            codec.encode(value, &mut encoded).unwrap();
+        }
+
+        // Fed a byte at a time, each value comes out once its last byte is in.
+        let mut src = BytesMut::new();
+        let mut decoded = Vec::new();
+        for byte in encoded {
+            src.put_u8(byte);
+            while let Some(value) = codec.decode(&mut src).unwrap() {
+                decoded.push(value);
+            }
+        }
+        assert_eq!(decoded, values);
+        assert!(src.is_empty());
+    }
+
+    #[test]
+    fn test_decode_resp3_in_pieces() {
+        let mut codec = RespCodec::new();
+        let encoded = b"|1\r\n+ttl\r\n:3\r\n~2\r\n,1.5\r\n=7\r\ntxt:abc\r\n_\r\n";
+        let mut src = BytesMut::new();
+        let mut decoded = Vec::new();
+        for &byte in encoded {
+            src.put_u8(byte);
+            while let Some(value) = codec.decode(&mut src).unwrap() {
+                decoded.push(value);
+            }
+        }
+        assert_eq!(
+            decoded,
+            [
+                RespData::Array(vec![
+                    RespData::BulkString("1.5".to_string()),
+                    RespData::BulkString("abc".to_string()),
+                ]),
+                RespData::Null,
+            ]
+        );
+    }
+
+    #[test]
+    fn test_decode_errors() {
+        let test_cases = [
+            ("Unknown type", &b"?what\r\n"[..], "invalid RESP type"),
+            ("Bad length", &b"*x\r\n"[..], "invalid length"),
+            (
+                "Bulk string over the limit",
+                &b"$6\r\n"[..],
+                "invalid bulk length",
+            ),
+            (
+                "Nested bulk string over the limit",
+                &b"*2\r\n$1\r\na\r\n$100\r\n"[..],
+                "invalid bulk length",
+            ),
+            (
+                "Huge declared array",
+                &b"*9223372036854775807\r\n"[..],
+                "invalid multibulk length",
+            ),
+            (
+                "Huge declared arrays nested",
+                &b"*9223372036854775807\r\n*9223372036854775807\r\n*9223372036854775807\r\n"[..],
+                "invalid multibulk length",
+            ),
+        ];
+
+        for (name, input, message) in test_cases {
+            let mut codec = RespCodec::with_max_bulk_len(5);
+            let error = codec.decode(&mut BytesMut::from(input)).unwrap_err();
+            assert_eq!(error.kind(), io::ErrorKind::InvalidData, "{}", name);
+            assert_eq!(error.to_string(), message, "{}", name);
+        }
+    }
+}
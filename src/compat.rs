@@ -0,0 +1,343 @@
+//! Compatibility diffing: runs scripted command sequences against a `TestServer` and a
+//! reference server, compares the raw replies byte for byte, and reports per command how many
+//! of them match.
+//!
+//! The reference is the real Redis at REDIS_COMPAT_ADDR (e.g. `127.0.0.1:6379`) when that is
+//! set, and another `TestServer` otherwise, which only checks the scripts are deterministic.
+//! Every script starts with FLUSHALL, so never point REDIS_COMPAT_ADDR at a server whose data
+//! matters. Run `cargo test compat -- --nocapture` to see the report.
+
+use crate::resp::{Resp, RespData};
+use crate::test_server::TestServer;
+use std::collections::BTreeMap;
+use std::env;
+use std::fmt;
+use std::io::{self, BufWriter, Write};
+use std::net::TcpStream;
+
+/// The scripts, one command per line with its arguments separated by spaces. Lines starting
+/// with `#` are comments. A command starting with `~` has only the type of its reply compared,
+/// for replies that legitimately differ between runs, like TTLs and SCAN cursors.
+const SCRIPTS: &[(&str, &str)] = &[
+    (
+        "strings",
+        "SET greeting hello
+        GET greeting
+        GET missing
+        APPEND greeting _world
+        APPEND fresh abc
+        STRLEN greeting
+        STRLEN missing
+        GETRANGE greeting 0 4
+        GETRANGE greeting -5 -1
+        GETRANGE greeting 100 200
+        SETRANGE greeting 6 there
+        SETRANGE padded 3 x
+        GET padded
+        SET greeting replaced NX
+        SET other value XX
+        SET greeting kept XX GET
+        SET greeting v EX 0
+        SET greeting v EX 10 PX 10
+        SET",
+    ),
+    (
+        "counters",
+        "INCR counter
+        INCRBY counter 41
+        DECR counter
+        DECRBY counter -10
+        INCRBY counter notanumber
+        SET text abc
+        INCR text
+        SET big 9223372036854775807
+        INCR big
+        SET spaced \" 1\"
+        INCR spaced",
+    ),
+    (
+        "hashes",
+        "HSET user name ada
+        HSET user name grace lang rust
+        HSET user odd
+        HGET user name
+        HGET user missing
+        HGET missing field
+        HLEN user
+        HLEN missing
+        HGETALL missing
+        HSET single field value
+        HGETALL single
+        ~HGETALL user
+        SET string value
+        HGET string field
+        HSET string field value
+        HLEN string",
+    ),
+    (
+        "expiry",
+        "SET session token
+        TTL session
+        PTTL session
+        TTL missing
+        EXPIRE session 100
+        ~TTL session
+        ~PTTL session
+        PERSIST session
+        PERSIST session
+        TTL session
+        PEXPIRE session 100000
+        ~PTTL session
+        EXPIRE session notanumber
+        EXPIRE missing 10
+        EXPIRE session -1
+        GET session
+        SET volatile v EX 100
+        SET volatile w
+        TTL volatile",
+    ),
+    (
+        "keyspace",
+        "SET a 1
+        SET b 2
+        HSET h f v
+        TYPE a
+        TYPE h
+        TYPE missing
+        DEL a missing
+        UNLINK b
+        DEL
+        ~SCAN 0
+        ~SCAN 0 MATCH h* COUNT 100
+        SCAN notacursor
+        FLUSHDB
+        TYPE h",
+    ),
+    (
+        "connection",
+        "PING
+        PING hello
+        PING a b
+        ECHO message
+        ECHO
+        ~TIME
+        NOSUCHCOMMAND arg
+        GET a b",
+    ),
+];
+
+/// A connection that returns each reply exactly as the server sent it.
+struct RawClient {
+    reader: Resp<TcpStream>,
+    writer: BufWriter<TcpStream>,
+}
+
+impl RawClient {
+    fn connect(addr: &str) -> io::Result<Self> {
+        let stream = TcpStream::connect(addr)?;
+        Ok(Self {
+            reader: Resp::new(stream.try_clone()?),
+            writer: BufWriter::new(stream),
+        })
+    }
+
+    fn run(&mut self, args: &[&str]) -> io::Result<String> {
+        let command = args
+            .iter()
+            .map(|arg| RespData::BulkString(arg.to_string()))
+            .collect();
+        RespData::Array(command).write(&mut self.writer)?;
+        self.writer.flush()?;
+        self.reader.read()?;
+        Ok(self.reader.raw_data.clone())
+    }
+}
+
+/// Splits a script line into arguments. A double quoted argument may contain spaces.
+pub(crate) fn split_line(line: &str) -> Vec<String> {
+    let mut args = Vec::new();
+    let mut rest = line.trim();
+    while !rest.is_empty() {
+        let (arg, next) = match rest.strip_prefix('"') {
+            Some(quoted) => quoted.split_once('"').unwrap_or((quoted, "")),
+            None => rest.split_once(' ').unwrap_or((rest, "")),
+        };
+        args.push(arg.to_string());
+        rest = next.trim_start();
+    }
+    args
+}
+
+/// A reply from our server that isn't what the reference sent.
+#[derive(Debug, PartialEq)]
+struct Diff {
+    script: &'static str,
+    line: usize,
+    command: String,
+    ours: String,
+    reference: String,
+}
+
+#[derive(Debug, Default, PartialEq)]
+struct CommandStats {
+    replies: usize,
+    diffs: Vec<Diff>,
+}
+
+/// The results of every script, by lowercased command name.
+#[derive(Debug, Default, PartialEq)]
+struct Report {
+    commands: BTreeMap<String, CommandStats>,
+}
+
+impl Report {
+    fn record(&mut self, name: &str, diff: Option<Diff>) {
+        let stats = self.commands.entry(name.to_lowercase()).or_default();
+        stats.replies += 1;
+        stats.diffs.extend(diff);
+    }
+
+    fn diffs(&self) -> usize {
+        self.commands.values().map(|stats| stats.diffs.len()).sum()
+    }
+}
+
+impl fmt::Display for Report {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let mut replies = 0;
+        for (name, stats) in &self.commands {
+            let matched = stats.replies - stats.diffs.len();
+            let status = if stats.diffs.is_empty() { "ok" } else { "DIFF" };
+            writeln!(f, "{name:<16} {matched:>3}/{:<3} {status}", stats.replies)?;
+            for diff in &stats.diffs {
+                writeln!(f, "  {}:{} {}", diff.script, diff.line, diff.command)?;
+                writeln!(f, "    reference: {:?}", diff.reference)?;
+                writeln!(f, "    ours:      {:?}", diff.ours)?;
+            }
+            replies += stats.replies;
+        }
+        write!(f, "{} of {replies} replies match", replies - self.diffs())
+    }
+}
+
+/// Runs `script` on both servers, recording every reply in `report`.
+fn run_script(
+    name: &'static str,
+    script: &str,
+    ours: &mut RawClient,
+    reference: &mut RawClient,
+    report: &mut Report,
+) -> io::Result<()> {
+    ours.run(&["FLUSHALL"])?;
+    reference.run(&["FLUSHALL"])?;
+    for (i, line) in script.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let (type_only, line) = match line.strip_prefix('~') {
+            Some(line) => (true, line),
+            None => (false, line),
+        };
+        let args = split_line(line);
+        let args: Vec<&str> = args.iter().map(String::as_str).collect();
+        let ours_reply = ours.run(&args)?;
+        let reference_reply = reference.run(&args)?;
+
+        let matches = if type_only {
+            ours_reply.as_bytes().first() == reference_reply.as_bytes().first()
+        } else {
+            ours_reply == reference_reply
+        };
+        let diff = (!matches).then(|| Diff {
+            script: name,
+            line: i + 1,
+            command: line.to_string(),
+            ours: ours_reply,
+            reference: reference_reply,
+        });
+        report.record(args[0], diff);
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_split_line() {
+        let test_cases = [
+            ("Plain", "SET k v", vec!["SET", "k", "v"]),
+            ("Extra spaces", "  GET   k ", vec!["GET", "k"]),
+            (
+                "Quoted",
+                "SET k \" 1\" EX 1",
+                vec!["SET", "k", " 1", "EX", "1"],
+            ),
+            ("Empty quotes", "ECHO \"\"", vec!["ECHO", ""]),
+        ];
+
+        for (name, input, expected) in test_cases {
+            assert_eq!(split_line(input), expected, "{}", name);
+        }
+    }
+
+    #[test]
+    fn test_report() {
+        let mut report = Report::default();
+        report.record("GET", None);
+        report.record("get", None);
+        report.record(
+            "HGET",
+            Some(Diff {
+                script: "hashes",
+                line: 3,
+                command: "HGET string field".to_string(),
+                ours: "-ERR wrong\r\n".to_string(),
+                reference: "-WRONGTYPE right\r\n".to_string(),
+            }),
+        );
+
+        assert_eq!(report.diffs(), 1);
+        assert_eq!(
+            report.to_string(),
+            "\
+get                2/2   ok
+hget               0/1   DIFF
+  hashes:3 HGET string field
+    reference: \"-WRONGTYPE right\\r\\n\"
+    ours:      \"-ERR wrong\\r\\n\"
+2 of 3 replies match"
+        );
+    }
+
+    #[test]
+    fn test_compatibility() {
+        let server = TestServer::start();
+        let mut ours = RawClient::connect(&server.addr.to_string()).unwrap();
+
+        let reference_addr = env::var("REDIS_COMPAT_ADDR").ok();
+        let fallback;
+        let addr = match &reference_addr {
+            Some(addr) => addr.clone(),
+            None => {
+                fallback = TestServer::start();
+                fallback.addr.to_string()
+            }
+        };
+        let mut reference = RawClient::connect(&addr).unwrap();
+
+        let mut report = Report::default();
+        for (name, script) in SCRIPTS {
+            run_script(name, script, &mut ours, &mut reference, &mut report).unwrap();
+        }
+        println!("Compatibility with {addr}:\n{report}");
+
+        // Against itself every reply must match, or a script depends on timing or ordering
+        // and needs a `~`.
+        if reference_addr.is_none() {
+            assert_eq!(report.diffs(), 0, "{report}");
+        }
+    }
+}
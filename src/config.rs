@@ -0,0 +1,1165 @@
+//! Server configuration: the `Config` read from a redis.conf-style file, its defaults, and
+//! the parameters CONFIG GET and CONFIG SET expose.
+
+use std::collections::BTreeMap;
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Levels are ordered from the most to the least verbose.
+#[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Clone, Copy)]
+pub enum LogLevel {
+    Debug,
+    Verbose,
+    Notice,
+    Warning,
+}
+
+impl LogLevel {
+    fn parse(value: &str) -> Option<Self> {
+        match value.to_lowercase().as_str() {
+            "debug" => Some(LogLevel::Debug),
+            "verbose" => Some(LogLevel::Verbose),
+            "notice" => Some(LogLevel::Notice),
+            "warning" => Some(LogLevel::Warning),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for LogLevel {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            LogLevel::Debug => "debug",
+            LogLevel::Verbose => "verbose",
+            LogLevel::Notice => "notice",
+            LogLevel::Warning => "warning",
+        };
+        write!(f, "{name}")
+    }
+}
+
+/// Which keys are evicted when the dataset grows past `maxmemory`.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum MaxmemoryPolicy {
+    /// Never evict; writes that need memory are refused instead.
+    NoEviction,
+    /// Evict the least recently used keys among all keys.
+    AllkeysLru,
+    /// Evict the least recently used keys among the ones with a TTL.
+    VolatileLru,
+    /// Evict the least frequently used keys among all keys.
+    AllkeysLfu,
+    /// Evict the least frequently used keys among the ones with a TTL.
+    VolatileLfu,
+    /// Evict random keys.
+    AllkeysRandom,
+    /// Evict random keys among the ones with a TTL.
+    VolatileRandom,
+    /// Evict the keys with a TTL that are closest to expiring.
+    VolatileTtl,
+}
+
+impl MaxmemoryPolicy {
+    pub fn parse(value: &str) -> Option<Self> {
+        match value.to_lowercase().as_str() {
+            "noeviction" => Some(MaxmemoryPolicy::NoEviction),
+            "allkeys-lru" => Some(MaxmemoryPolicy::AllkeysLru),
+            "volatile-lru" => Some(MaxmemoryPolicy::VolatileLru),
+            "allkeys-lfu" => Some(MaxmemoryPolicy::AllkeysLfu),
+            "volatile-lfu" => Some(MaxmemoryPolicy::VolatileLfu),
+            "allkeys-random" => Some(MaxmemoryPolicy::AllkeysRandom),
+            "volatile-random" => Some(MaxmemoryPolicy::VolatileRandom),
+            "volatile-ttl" => Some(MaxmemoryPolicy::VolatileTtl),
+            _ => None,
+        }
+    }
+
+    /// Whether only keys with a TTL are eviction candidates.
+    pub fn is_volatile(self) -> bool {
+        matches!(
+            self,
+            MaxmemoryPolicy::VolatileLru
+                | MaxmemoryPolicy::VolatileLfu
+                | MaxmemoryPolicy::VolatileRandom
+                | MaxmemoryPolicy::VolatileTtl
+        )
+    }
+
+    /// Whether keys are picked by access frequency rather than recency.
+    pub fn is_lfu(self) -> bool {
+        matches!(
+            self,
+            MaxmemoryPolicy::AllkeysLfu | MaxmemoryPolicy::VolatileLfu
+        )
+    }
+}
+
+impl fmt::Display for MaxmemoryPolicy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            MaxmemoryPolicy::NoEviction => "noeviction",
+            MaxmemoryPolicy::AllkeysLru => "allkeys-lru",
+            MaxmemoryPolicy::VolatileLru => "volatile-lru",
+            MaxmemoryPolicy::AllkeysLfu => "allkeys-lfu",
+            MaxmemoryPolicy::VolatileLfu => "volatile-lfu",
+            MaxmemoryPolicy::AllkeysRandom => "allkeys-random",
+            MaxmemoryPolicy::VolatileRandom => "volatile-random",
+            MaxmemoryPolicy::VolatileTtl => "volatile-ttl",
+        };
+        write!(f, "{name}")
+    }
+}
+
+/// How the server tells a supervisor such as systemd that it is ready or stopping.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum Supervised {
+    No,
+    Systemd,
+    /// Systemd when started by it, which sets `NOTIFY_SOCKET`.
+    Auto,
+}
+
+impl Supervised {
+    fn parse(value: &str) -> Option<Self> {
+        match value.to_lowercase().as_str() {
+            "no" => Some(Supervised::No),
+            "systemd" => Some(Supervised::Systemd),
+            "auto" => Some(Supervised::Auto),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for Supervised {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            Supervised::No => "no",
+            Supervised::Systemd => "systemd",
+            Supervised::Auto => "auto",
+        };
+        write!(f, "{name}")
+    }
+}
+
+/// Who may run a command that is dangerous enough to be off by default, such as DEBUG.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum ProtectedAccess {
+    No,
+    Yes,
+    /// Only clients connecting from the loopback interface.
+    Local,
+}
+
+impl ProtectedAccess {
+    fn parse(value: &str) -> Option<Self> {
+        match value.to_lowercase().as_str() {
+            "no" => Some(ProtectedAccess::No),
+            "yes" => Some(ProtectedAccess::Yes),
+            "local" => Some(ProtectedAccess::Local),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for ProtectedAccess {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            ProtectedAccess::No => "no",
+            ProtectedAccess::Yes => "yes",
+            ProtectedAccess::Local => "local",
+        };
+        write!(f, "{name}")
+    }
+}
+
+/// A `save <seconds> <changes>` snapshot trigger.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub struct SavePoint {
+    pub seconds: u64,
+    pub changes: u64,
+}
+
+#[derive(Debug)]
+pub enum ConfigError {
+    Io(std::io::Error),
+    Parse { line: usize, message: String },
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ConfigError::Io(e) => write!(f, "{e}"),
+            ConfigError::Parse { line, message } => write!(f, "line {line}: {message}"),
+        }
+    }
+}
+
+impl From<std::io::Error> for ConfigError {
+    fn from(e: std::io::Error) -> Self {
+        ConfigError::Io(e)
+    }
+}
+
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct Config {
+    pub path: Option<PathBuf>,
+    /// Addresses to listen on. `*` and `::*` mean every IPv4 and IPv6 interface, and a leading
+    /// `-` marks an address that may be skipped if it is not available.
+    pub bind: Vec<String>,
+    /// TCP port to listen on, or 0 to not listen on TCP at all.
+    pub port: u16,
+    /// Port serving health checks, Prometheus metrics and INFO as JSON over HTTP on the same
+    /// addresses, or 0 to not serve them.
+    pub metrics_port: u16,
+    /// Length of the queue of connections waiting to be accepted.
+    pub tcp_backlog: i32,
+    /// Seconds between TCP keepalive probes on client connections, or 0 to disable them.
+    pub tcp_keepalive: u64,
+    /// Disable Nagle's algorithm on client connections.
+    pub tcp_nodelay: bool,
+    /// Set SO_REUSEPORT on the listening sockets.
+    pub tcp_reuseport: bool,
+    /// Maximum number of simultaneous client connections.
+    pub maxclients: usize,
+    /// Threads doing socket I/O. Above 1, a pool of this many threads reads and parses every
+    /// connection's commands and writes its replies, while the commands still run one at a
+    /// time.
+    pub io_threads: usize,
+    /// Commands a second each connection may send, or 0 for no limit.
+    pub client_rate_limit: u64,
+    /// Commands a second all connections from one IP address may send together, or 0 for no
+    /// limit.
+    pub ip_rate_limit: u64,
+    /// Seconds a client may stay idle before it is disconnected, or 0 to never time out.
+    pub timeout: u64,
+    /// How many times a second the periodic background tasks run.
+    pub hz: u32,
+    /// Detach from the terminal and run in the background.
+    pub daemonize: bool,
+    /// File the server's PID is written to, or `None` for none unless daemonized.
+    pub pidfile: Option<PathBuf>,
+    pub supervised: Supervised,
+    pub loglevel: LogLevel,
+    /// File the log is appended to, or `None` to log to stdout.
+    pub logfile: Option<PathBuf>,
+    /// File every write command is recorded in, or `None` to not keep an audit log.
+    pub audit_log: Option<PathBuf>,
+    pub maxmemory: u64,
+    /// Memory for values before the rest spill to a file in `dir`, or 0 to keep everything in
+    /// memory. See `TieredStorage`.
+    pub tiered_storage_memory: u64,
+    /// Longest bulk string a client may send, and longest string a command may create.
+    pub proto_max_bulk_len: u64,
+    pub maxmemory_policy: MaxmemoryPolicy,
+    /// Number of keys sampled per eviction; more samples approximate true LRU more closely.
+    pub maxmemory_samples: usize,
+    /// Free evicted values in the background.
+    pub lazyfree_lazy_eviction: bool,
+    /// Free values replaced by an overwrite in the background.
+    pub lazyfree_lazy_server_del: bool,
+    /// Make DEL behave like UNLINK.
+    pub lazyfree_lazy_user_del: bool,
+    /// Make FLUSHALL and FLUSHDB default to ASYNC.
+    pub lazyfree_lazy_user_flush: bool,
+    /// How many hits it takes to saturate the LFU counter; higher means more hits.
+    pub lfu_log_factor: u32,
+    /// Minutes after which an idle key's LFU counter is decremented, or 0 to never decay.
+    pub lfu_decay_time: u64,
+    /// Hashes with at most this many fields, none longer than `hash_max_listpack_value`
+    /// bytes, are listpack encoded and keep their fields in insertion order. Bigger ones
+    /// become hashtable encoded when written or loaded.
+    pub hash_max_listpack_entries: usize,
+    pub hash_max_listpack_value: usize,
+    pub save: Vec<SavePoint>,
+    /// Minimum duration in milliseconds for an event to be recorded by the latency monitor,
+    /// or 0 to disable it.
+    pub latency_monitor_threshold: u64,
+    /// Working directory where snapshots are written and read.
+    pub dir: PathBuf,
+    pub dbfilename: String,
+    /// Directory the RDB snapshot is copied to every `backup_interval`, or `None` to not keep
+    /// backups.
+    pub backup_dir: Option<PathBuf>,
+    /// Seconds between backups. A snapshot that hasn't changed since the last backup is not
+    /// copied again.
+    pub backup_interval: u64,
+    /// How many backups to keep, the oldest being deleted first, or 0 to keep them all.
+    pub backup_retention: usize,
+    /// Shell command run after each backup, with the backup's path as `$1`.
+    pub backup_hook: Option<String>,
+    /// Password clients must send with AUTH before running other commands.
+    pub requirepass: Option<String>,
+    /// File the users are loaded from at startup and by ACL LOAD, and written to by ACL SAVE.
+    pub aclfile: Option<PathBuf>,
+    /// Lowercased command names mapped to the name they are reachable under, or to an empty
+    /// string if the command is disabled.
+    pub rename_commands: BTreeMap<String, String>,
+    pub enable_debug_command: ProtectedAccess,
+    /// OTLP/HTTP endpoint to export command and connection traces to, e.g.
+    /// `http://localhost:4318/v1/traces`. Needs the `otel` feature.
+    pub otlp_endpoint: Option<String>,
+    /// Seed for every random choice the server makes and for key hashing, so that a run can
+    /// be reproduced. Taken from the clock when not set.
+    pub seed: Option<u64>,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            path: None,
+            bind: vec!["*".to_string(), "-::*".to_string()],
+            port: 6379,
+            metrics_port: 0,
+            tcp_backlog: 511,
+            tcp_keepalive: 300,
+            tcp_nodelay: true,
+            tcp_reuseport: false,
+            maxclients: 10000,
+            io_threads: 1,
+            client_rate_limit: 0,
+            ip_rate_limit: 0,
+            timeout: 0,
+            hz: 10,
+            daemonize: false,
+            pidfile: None,
+            supervised: Supervised::No,
+            loglevel: LogLevel::Notice,
+            logfile: None,
+            audit_log: None,
+            maxmemory: 0,
+            tiered_storage_memory: 0,
+            proto_max_bulk_len: 512 * 1024 * 1024,
+            maxmemory_policy: MaxmemoryPolicy::NoEviction,
+            maxmemory_samples: 5,
+            lazyfree_lazy_eviction: false,
+            lazyfree_lazy_server_del: false,
+            lazyfree_lazy_user_del: false,
+            lazyfree_lazy_user_flush: false,
+            lfu_log_factor: 10,
+            lfu_decay_time: 1,
+            hash_max_listpack_entries: DEFAULT_HASH_MAX_LISTPACK_ENTRIES,
+            hash_max_listpack_value: DEFAULT_HASH_MAX_LISTPACK_VALUE,
+            save: vec![
+                SavePoint {
+                    seconds: 3600,
+                    changes: 1,
+                },
+                SavePoint {
+                    seconds: 300,
+                    changes: 100,
+                },
+                SavePoint {
+                    seconds: 60,
+                    changes: 10000,
+                },
+            ],
+            latency_monitor_threshold: 0,
+            dir: PathBuf::from("."),
+            dbfilename: "dump.rdb".to_string(),
+            backup_dir: None,
+            backup_interval: 3600,
+            backup_retention: 24,
+            backup_hook: None,
+            requirepass: None,
+            aclfile: None,
+            rename_commands: BTreeMap::new(),
+            enable_debug_command: ProtectedAccess::No,
+            otlp_endpoint: None,
+            seed: None,
+        }
+    }
+}
+
+impl Config {
+    pub fn load(path: &Path) -> Result<Self, ConfigError> {
+        let contents = fs::read_to_string(path)?;
+        let mut config = Self::parse(&contents)?;
+        config.path = Some(path.to_path_buf());
+        Ok(config)
+    }
+
+    pub fn parse(contents: &str) -> Result<Self, ConfigError> {
+        let mut config = Self::default();
+        let mut save_seen = false;
+
+        for (idx, line) in contents.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+
+            let args: Vec<&str> = line.split_whitespace().collect();
+            let err = |message: &str| ConfigError::Parse {
+                line: idx + 1,
+                message: message.to_string(),
+            };
+
+            let directive = args[0].to_lowercase();
+            if directive == "save" {
+                // The first `save` line replaces the defaults rather than appending to them.
+                if !save_seen {
+                    config.save.clear();
+                    save_seen = true;
+                }
+                config.save.extend(
+                    parse_save_points(&args[1..]).ok_or_else(|| err("Invalid save parameters"))?,
+                );
+                continue;
+            }
+            config
+                .apply(&directive, &args[1..])
+                .map_err(|message| err(&message))?;
+        }
+
+        Ok(config)
+    }
+
+    /// Applies a single directive, as found in the config file or given to CONFIG SET.
+    fn apply(&mut self, directive: &str, args: &[&str]) -> Result<(), String> {
+        let err = |message: &str| message.to_string();
+        match (directive, args) {
+            ("bind", addrs) if !addrs.is_empty() => {
+                self.bind = addrs.iter().map(|addr| addr.to_string()).collect();
+            }
+            ("port", [port]) => {
+                self.port = port.parse().map_err(|_| err("Invalid port"))?;
+            }
+            ("metrics-port", [port]) => {
+                self.metrics_port = port.parse().map_err(|_| err("Invalid metrics port"))?;
+            }
+            ("tcp-backlog", [backlog]) => {
+                self.tcp_backlog = backlog
+                    .parse()
+                    .ok()
+                    .filter(|backlog| *backlog > 0)
+                    .ok_or_else(|| err("Invalid backlog value"))?;
+            }
+            ("tcp-keepalive", [seconds]) => {
+                self.tcp_keepalive = seconds
+                    .parse()
+                    .map_err(|_| err("Invalid tcp-keepalive value"))?;
+            }
+            ("tcp-nodelay", [value]) => {
+                self.tcp_nodelay =
+                    parse_yes_no(value).ok_or_else(|| err("argument must be 'yes' or 'no'"))?;
+            }
+            ("tcp-reuseport", [value]) => {
+                self.tcp_reuseport =
+                    parse_yes_no(value).ok_or_else(|| err("argument must be 'yes' or 'no'"))?;
+            }
+            ("maxclients", [max]) => {
+                self.maxclients = max
+                    .parse()
+                    .ok()
+                    .filter(|max| *max > 0)
+                    .ok_or_else(|| err("Invalid max clients limit"))?;
+            }
+            ("io-threads", [threads]) => {
+                self.io_threads = threads
+                    .parse()
+                    .ok()
+                    .filter(|threads| (1..=MAX_IO_THREADS).contains(threads))
+                    .ok_or_else(|| err("Invalid number of io threads"))?;
+            }
+            ("client-rate-limit", [limit]) => {
+                self.client_rate_limit = limit
+                    .parse()
+                    .map_err(|_| err("Invalid client rate limit"))?;
+            }
+            ("ip-rate-limit", [limit]) => {
+                self.ip_rate_limit = limit.parse().map_err(|_| err("Invalid IP rate limit"))?;
+            }
+            ("timeout", [seconds]) => {
+                self.timeout = seconds.parse().map_err(|_| err("Invalid timeout value"))?;
+            }
+            ("hz", [hz]) => {
+                // Like Redis, out of range values are clamped rather than rejected.
+                let hz: u32 = hz.parse().map_err(|_| err("Invalid hz value"))?;
+                self.hz = hz.clamp(MIN_HZ, MAX_HZ);
+            }
+            ("daemonize", [value]) => {
+                self.daemonize =
+                    parse_yes_no(value).ok_or_else(|| err("argument must be 'yes' or 'no'"))?;
+            }
+            ("pidfile", [path]) => {
+                self.pidfile = match *path {
+                    "" | "\"\"" => None,
+                    path => Some(PathBuf::from(path)),
+                };
+            }
+            ("supervised", [value]) => {
+                self.supervised = Supervised::parse(value)
+                    .ok_or_else(|| err("argument must be one of 'no', 'systemd' or 'auto'"))?;
+            }
+            ("loglevel", [level]) => {
+                self.loglevel = LogLevel::parse(level).ok_or_else(|| {
+                    err("Invalid log level. Must be one of debug, verbose, notice, warning")
+                })?;
+            }
+            ("logfile", [path]) => {
+                self.logfile = match *path {
+                    "" | "\"\"" => None,
+                    path => Some(PathBuf::from(path)),
+                };
+            }
+            ("audit-log", [path]) => {
+                self.audit_log = match *path {
+                    "" | "\"\"" => None,
+                    path => Some(PathBuf::from(path)),
+                };
+            }
+            ("maxmemory", [size]) => {
+                self.maxmemory =
+                    parse_memory(size).ok_or_else(|| err("Invalid maxmemory value"))?;
+            }
+            ("tiered-storage-memory", [size]) => {
+                self.tiered_storage_memory =
+                    parse_memory(size).ok_or_else(|| err("Invalid tiered-storage-memory value"))?;
+            }
+            ("proto-max-bulk-len", [size]) => {
+                self.proto_max_bulk_len = parse_memory(size)
+                    .filter(|size| *size >= MIN_PROTO_MAX_BULK_LEN)
+                    .ok_or_else(|| err("Invalid proto-max-bulk-len value"))?;
+            }
+            ("maxmemory-policy", [policy]) => {
+                self.maxmemory_policy = MaxmemoryPolicy::parse(policy)
+                    .ok_or_else(|| err("Invalid maxmemory policy"))?;
+            }
+            ("maxmemory-samples", [samples]) => {
+                self.maxmemory_samples = samples
+                    .parse()
+                    .ok()
+                    .filter(|samples| *samples > 0)
+                    .ok_or_else(|| err("Invalid maxmemory-samples value"))?;
+            }
+            ("lazyfree-lazy-eviction", [value]) => {
+                self.lazyfree_lazy_eviction =
+                    parse_yes_no(value).ok_or_else(|| err("argument must be 'yes' or 'no'"))?;
+            }
+            ("lazyfree-lazy-server-del", [value]) => {
+                self.lazyfree_lazy_server_del =
+                    parse_yes_no(value).ok_or_else(|| err("argument must be 'yes' or 'no'"))?;
+            }
+            ("lazyfree-lazy-user-del", [value]) => {
+                self.lazyfree_lazy_user_del =
+                    parse_yes_no(value).ok_or_else(|| err("argument must be 'yes' or 'no'"))?;
+            }
+            ("lazyfree-lazy-user-flush", [value]) => {
+                self.lazyfree_lazy_user_flush =
+                    parse_yes_no(value).ok_or_else(|| err("argument must be 'yes' or 'no'"))?;
+            }
+            ("lfu-log-factor", [factor]) => {
+                self.lfu_log_factor = factor
+                    .parse()
+                    .map_err(|_| err("Invalid lfu-log-factor value"))?;
+            }
+            ("lfu-decay-time", [minutes]) => {
+                self.lfu_decay_time = minutes
+                    .parse()
+                    .map_err(|_| err("Invalid lfu-decay-time value"))?;
+            }
+            ("hash-max-listpack-entries", [entries]) => {
+                self.hash_max_listpack_entries = entries
+                    .parse()
+                    .map_err(|_| err("Invalid hash-max-listpack-entries value"))?;
+            }
+            ("hash-max-listpack-value", [bytes]) => {
+                self.hash_max_listpack_value = parse_memory(bytes)
+                    .and_then(|bytes| usize::try_from(bytes).ok())
+                    .ok_or_else(|| err("Invalid hash-max-listpack-value value"))?;
+            }
+            ("latency-monitor-threshold", [ms]) => {
+                self.latency_monitor_threshold = ms
+                    .parse()
+                    .map_err(|_| err("Invalid latency-monitor-threshold"))?;
+            }
+            ("dir", [dir]) => self.dir = PathBuf::from(dir),
+            ("backup-dir", [dir]) => {
+                self.backup_dir = match *dir {
+                    "" | "\"\"" => None,
+                    dir => Some(PathBuf::from(dir)),
+                };
+            }
+            ("backup-interval", [seconds]) => {
+                self.backup_interval = seconds
+                    .parse()
+                    .map_err(|_| err("Invalid backup-interval value"))?;
+            }
+            ("backup-retention", [count]) => {
+                self.backup_retention = count
+                    .parse()
+                    .map_err(|_| err("Invalid backup-retention value"))?;
+            }
+            // The file isn't split on quotes, so the command is every word after the name.
+            ("backup-hook", [""] | ["\"\""]) => self.backup_hook = None,
+            ("backup-hook", words) if !words.is_empty() => {
+                self.backup_hook = Some(words.join(" "));
+            }
+            ("dbfilename", [name]) => {
+                if name.contains('/') {
+                    return Err(err("dbfilename can't be a path, just a filename"));
+                }
+                self.dbfilename = name.to_string();
+            }
+            ("requirepass", [password]) => {
+                self.requirepass = match *password {
+                    "" | "\"\"" => None,
+                    password => Some(password.to_string()),
+                };
+            }
+            ("aclfile", [path]) => {
+                self.aclfile = match *path {
+                    "" | "\"\"" => None,
+                    path => Some(PathBuf::from(path)),
+                };
+            }
+            ("rename-command", [command, new_name]) => {
+                let new_name = match *new_name {
+                    "\"\"" => "",
+                    new_name => new_name,
+                };
+                self.rename_commands
+                    .insert(command.to_lowercase(), new_name.to_lowercase());
+            }
+            ("enable-debug-command", [value]) => {
+                self.enable_debug_command = ProtectedAccess::parse(value)
+                    .ok_or_else(|| err("argument must be one of 'no', 'yes' or 'local'"))?;
+            }
+            ("otlp-endpoint", [endpoint]) => self.otlp_endpoint = Some(endpoint.to_string()),
+            ("seed", [seed]) => {
+                self.seed = Some(seed.parse().map_err(|_| err("Invalid seed"))?);
+            }
+            _ => return Err(err("Bad directive or wrong number of arguments")),
+        }
+        Ok(())
+    }
+
+    /// Current value of a parameter, formatted the way CONFIG GET reports it.
+    pub fn get(&self, name: &str) -> Option<String> {
+        let value = match name {
+            "bind" => self.bind.join(" "),
+            "port" => self.port.to_string(),
+            "metrics-port" => self.metrics_port.to_string(),
+            "tcp-backlog" => self.tcp_backlog.to_string(),
+            "tcp-keepalive" => self.tcp_keepalive.to_string(),
+            "tcp-nodelay" => yes_no(self.tcp_nodelay),
+            "tcp-reuseport" => yes_no(self.tcp_reuseport),
+            "maxclients" => self.maxclients.to_string(),
+            "io-threads" => self.io_threads.to_string(),
+            "client-rate-limit" => self.client_rate_limit.to_string(),
+            "ip-rate-limit" => self.ip_rate_limit.to_string(),
+            "timeout" => self.timeout.to_string(),
+            "hz" => self.hz.to_string(),
+            "daemonize" => yes_no(self.daemonize),
+            "pidfile" => self
+                .pidfile
+                .as_ref()
+                .map(|path| path.display().to_string())
+                .unwrap_or_default(),
+            "supervised" => self.supervised.to_string(),
+            "loglevel" => self.loglevel.to_string(),
+            "logfile" => self
+                .logfile
+                .as_ref()
+                .map(|path| path.display().to_string())
+                .unwrap_or_default(),
+            "audit-log" => self
+                .audit_log
+                .as_ref()
+                .map(|path| path.display().to_string())
+                .unwrap_or_default(),
+            "maxmemory" => self.maxmemory.to_string(),
+            "tiered-storage-memory" => self.tiered_storage_memory.to_string(),
+            "proto-max-bulk-len" => self.proto_max_bulk_len.to_string(),
+            "maxmemory-policy" => self.maxmemory_policy.to_string(),
+            "maxmemory-samples" => self.maxmemory_samples.to_string(),
+            "lazyfree-lazy-eviction" => yes_no(self.lazyfree_lazy_eviction),
+            "lazyfree-lazy-server-del" => yes_no(self.lazyfree_lazy_server_del),
+            "lazyfree-lazy-user-del" => yes_no(self.lazyfree_lazy_user_del),
+            "lazyfree-lazy-user-flush" => yes_no(self.lazyfree_lazy_user_flush),
+            "lfu-log-factor" => self.lfu_log_factor.to_string(),
+            "lfu-decay-time" => self.lfu_decay_time.to_string(),
+            "hash-max-listpack-entries" => self.hash_max_listpack_entries.to_string(),
+            "hash-max-listpack-value" => self.hash_max_listpack_value.to_string(),
+            "latency-monitor-threshold" => self.latency_monitor_threshold.to_string(),
+            "dir" => self.dir.display().to_string(),
+            "dbfilename" => self.dbfilename.clone(),
+            "backup-dir" => self
+                .backup_dir
+                .as_ref()
+                .map(|dir| dir.display().to_string())
+                .unwrap_or_default(),
+            "backup-interval" => self.backup_interval.to_string(),
+            "backup-retention" => self.backup_retention.to_string(),
+            "backup-hook" => self.backup_hook.clone().unwrap_or_default(),
+            "requirepass" => self.requirepass.clone().unwrap_or_default(),
+            "aclfile" => self
+                .aclfile
+                .as_ref()
+                .map(|path| path.display().to_string())
+                .unwrap_or_default(),
+            "enable-debug-command" => self.enable_debug_command.to_string(),
+            "otlp-endpoint" => self.otlp_endpoint.clone().unwrap_or_default(),
+            "seed" => self.seed.map(|seed| seed.to_string()).unwrap_or_default(),
+            "save" => self
+                .save
+                .iter()
+                .map(|point| format!("{} {}", point.seconds, point.changes))
+                .collect::<Vec<_>>()
+                .join(" "),
+            _ => return None,
+        };
+        Some(value)
+    }
+
+    /// Changes a parameter at runtime, as CONFIG SET does. Fails for parameters that can only
+    /// be set at startup.
+    pub fn set(&mut self, name: &str, value: &str) -> Result<(), String> {
+        if !MUTABLE_PARAMETERS.contains(&name) {
+            return Err("can't set immutable config".to_string());
+        }
+        if name == "save" {
+            let params: Vec<&str> = match value.trim() {
+                "" => vec![""],
+                value => value.split_whitespace().collect(),
+            };
+            self.save = parse_save_points(&params).ok_or("Invalid save parameters")?;
+            return Ok(());
+        }
+        self.apply(name, &[value])
+    }
+
+    /// Re-reads the config file and applies the parameters that can change at runtime.
+    /// On error the current configuration is left untouched.
+    pub fn reload(&mut self) -> Result<(), ConfigError> {
+        let Some(path) = self.path.clone() else {
+            return Ok(());
+        };
+        let fresh = Self::load(&path)?;
+        // Through the same path as CONFIG SET, so the two can't disagree on what may change.
+        let mut updated = self.clone();
+        for name in MUTABLE_PARAMETERS {
+            let value = fresh.get(name).unwrap_or_default();
+            updated
+                .set(name, &value)
+                .map_err(|message| ConfigError::Parse { line: 0, message })?;
+        }
+        *self = updated;
+        Ok(())
+    }
+
+    /// Location of the RDB snapshot.
+    pub fn rdb_path(&self) -> PathBuf {
+        self.dir.join(&self.dbfilename)
+    }
+
+    /// Location of the file values spill to with `tiered-storage-memory`.
+    pub fn tiered_storage_path(&self) -> PathBuf {
+        self.dir.join("tiered-storage.dat")
+    }
+}
+
+/// Smallest `proto-max-bulk-len` Redis accepts.
+const MIN_PROTO_MAX_BULK_LEN: u64 = 1024 * 1024;
+
+/// Most `io-threads` Redis accepts.
+const MAX_IO_THREADS: usize = 128;
+
+/// Bounds `hz` is clamped to.
+const MIN_HZ: u32 = 1;
+const MAX_HZ: u32 = 500;
+
+/// The default `hash-max-listpack-entries` and `hash-max-listpack-value`.
+pub const DEFAULT_HASH_MAX_LISTPACK_ENTRIES: usize = 128;
+pub const DEFAULT_HASH_MAX_LISTPACK_VALUE: usize = 64;
+
+/// Every parameter CONFIG GET knows about.
+pub const PARAMETERS: [&str; 45] = [
+    "bind",
+    "port",
+    "metrics-port",
+    "tcp-backlog",
+    "tcp-keepalive",
+    "tcp-nodelay",
+    "tcp-reuseport",
+    "maxclients",
+    "io-threads",
+    "client-rate-limit",
+    "ip-rate-limit",
+    "timeout",
+    "hz",
+    "daemonize",
+    "pidfile",
+    "supervised",
+    "loglevel",
+    "logfile",
+    "audit-log",
+    "maxmemory",
+    "tiered-storage-memory",
+    "proto-max-bulk-len",
+    "maxmemory-policy",
+    "maxmemory-samples",
+    "lazyfree-lazy-eviction",
+    "lazyfree-lazy-server-del",
+    "lazyfree-lazy-user-del",
+    "lazyfree-lazy-user-flush",
+    "lfu-log-factor",
+    "lfu-decay-time",
+    "hash-max-listpack-entries",
+    "hash-max-listpack-value",
+    "latency-monitor-threshold",
+    "dir",
+    "dbfilename",
+    "backup-dir",
+    "backup-interval",
+    "backup-retention",
+    "backup-hook",
+    "requirepass",
+    "aclfile",
+    "enable-debug-command",
+    "otlp-endpoint",
+    "seed",
+    "save",
+];
+
+/// Parameters CONFIG SET may change while the server is running.
+const MUTABLE_PARAMETERS: [&str; 24] = [
+    "tcp-keepalive",
+    "maxclients",
+    "client-rate-limit",
+    "ip-rate-limit",
+    "timeout",
+    "hz",
+    "loglevel",
+    "maxmemory",
+    "proto-max-bulk-len",
+    "maxmemory-policy",
+    "maxmemory-samples",
+    "lazyfree-lazy-eviction",
+    "lazyfree-lazy-server-del",
+    "lazyfree-lazy-user-del",
+    "lazyfree-lazy-user-flush",
+    "lfu-log-factor",
+    "lfu-decay-time",
+    "hash-max-listpack-entries",
+    "hash-max-listpack-value",
+    "latency-monitor-threshold",
+    "backup-interval",
+    "backup-retention",
+    "requirepass",
+    "save",
+];
+
+/// Parses a memory size such as `100`, `1k`, `512mb` or `2GB` into bytes.
+pub fn parse_memory(value: &str) -> Option<u64> {
+    let value = value.to_lowercase();
+    let split = value
+        .find(|c: char| !c.is_ascii_digit())
+        .unwrap_or(value.len());
+    let (digits, unit) = value.split_at(split);
+    let multiplier = match unit {
+        "" | "b" => 1,
+        "k" => 1000,
+        "kb" => 1024,
+        "m" => 1000 * 1000,
+        "mb" => 1024 * 1024,
+        "g" => 1000 * 1000 * 1000,
+        "gb" => 1024 * 1024 * 1024,
+        _ => return None,
+    };
+    digits.parse::<u64>().ok()?.checked_mul(multiplier)
+}
+
+fn parse_yes_no(value: &str) -> Option<bool> {
+    match value.to_lowercase().as_str() {
+        "yes" => Some(true),
+        "no" => Some(false),
+        _ => None,
+    }
+}
+
+fn yes_no(value: bool) -> String {
+    if value { "yes" } else { "no" }.to_string()
+}
+
+fn parse_save_points(params: &[&str]) -> Option<Vec<SavePoint>> {
+    // `save ""` disables snapshotting entirely.
+    if let [""] | ["\"\""] = params {
+        return Some(vec![]);
+    }
+    if params.is_empty() || !params.len().is_multiple_of(2) {
+        return None;
+    }
+    params
+        .chunks_exact(2)
+        .map(|pair| {
+            Some(SavePoint {
+                seconds: pair[0].parse().ok()?,
+                changes: pair[1].parse().ok()?,
+            })
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_memory() {
+        let test_cases = [
+            ("Plain bytes", "100", Some(100)),
+            ("Kilobytes", "1k", Some(1000)),
+            ("Kibibytes", "1kb", Some(1024)),
+            ("Megabytes uppercase", "2MB", Some(2 * 1024 * 1024)),
+            ("Gigabytes", "1gb", Some(1024 * 1024 * 1024)),
+            ("Unknown unit", "1tb", None),
+            ("Not a number", "lots", None),
+        ];
+
+        for (name, input, expected) in test_cases {
+            assert_eq!(parse_memory(input), expected, "{}", name);
+        }
+    }
+
+    #[test]
+    fn test_parse() {
+        let config = Config::parse(
+            "# comment\n\
+             bind 127.0.0.1 -::1\n\
+             port 7000\n\
+             metrics-port 9121\n\
+             tcp-backlog 1024\n\
+             tcp-keepalive 60\n\
+             tcp-nodelay no\n\
+             tcp-reuseport yes\n\
+             maxclients 128\n\
+             io-threads 4\n\
+             client-rate-limit 1000\n\
+             ip-rate-limit 5000\n\
+             timeout 300\n\
+             hz 50\n\
+             daemonize yes\n\
+             pidfile /var/run/redis_7000.pid\n\
+             supervised systemd\n\
+             loglevel warning\n\
+             logfile /var/log/redis.log\n\
+             audit-log /var/log/redis-audit.log\n\
+             maxmemory 100mb\n\
+             tiered-storage-memory 1gb\n\
+             maxmemory-policy allkeys-lru\n\
+             maxmemory-samples 10\n\
+             lazyfree-lazy-user-del yes\n\
+             latency-monitor-threshold 100\n\
+             dir /var/lib/redis\n\
+             dbfilename snapshot.rdb\n\
+             backup-dir /var/backups/redis\n\
+             backup-interval 900\n\
+             backup-retention 48\n\
+             backup-hook upload-backup --bucket nightly\n\
+             requirepass s3cret\n\
+             aclfile /etc/redis/users.acl\n\
+             rename-command CONFIG b840fc02d524045429941cc15f59e41cb7be6c52\n\
+             rename-command FLUSHALL \"\"\n\
+             enable-debug-command local\n\
+             otlp-endpoint http://localhost:4318/v1/traces\n\
+             seed 42\n\
+             save 900 1\n\
+             save 300 10\n",
+        )
+        .unwrap();
+
+        assert_eq!(config.bind, vec!["127.0.0.1", "-::1"]);
+        assert_eq!(config.port, 7000);
+        assert_eq!(config.metrics_port, 9121);
+        assert_eq!(config.tcp_backlog, 1024);
+        assert_eq!(config.tcp_keepalive, 60);
+        assert!(!config.tcp_nodelay);
+        assert!(config.tcp_reuseport);
+        assert_eq!(config.maxclients, 128);
+        assert_eq!(config.io_threads, 4);
+        assert_eq!(config.client_rate_limit, 1000);
+        assert_eq!(config.ip_rate_limit, 5000);
+        assert_eq!(config.timeout, 300);
+        assert_eq!(config.hz, 50);
+        assert!(config.daemonize);
+        assert_eq!(
+            config.pidfile.as_deref(),
+            Some(Path::new("/var/run/redis_7000.pid"))
+        );
+        assert_eq!(config.supervised, Supervised::Systemd);
+        assert_eq!(config.loglevel, LogLevel::Warning);
+        assert_eq!(
+            config.logfile.as_deref(),
+            Some(Path::new("/var/log/redis.log"))
+        );
+        assert_eq!(
+            config.audit_log.as_deref(),
+            Some(Path::new("/var/log/redis-audit.log"))
+        );
+        assert_eq!(config.maxmemory, 100 * 1024 * 1024);
+        assert_eq!(config.tiered_storage_memory, 1024 * 1024 * 1024);
+        assert_eq!(config.maxmemory_policy, MaxmemoryPolicy::AllkeysLru);
+        assert_eq!(config.maxmemory_samples, 10);
+        assert!(config.lazyfree_lazy_user_del);
+        assert!(!config.lazyfree_lazy_eviction);
+        assert_eq!(config.latency_monitor_threshold, 100);
+        assert_eq!(config.rdb_path(), Path::new("/var/lib/redis/snapshot.rdb"));
+        assert_eq!(
+            config.backup_dir.as_deref(),
+            Some(Path::new("/var/backups/redis"))
+        );
+        assert_eq!(config.backup_interval, 900);
+        assert_eq!(config.backup_retention, 48);
+        assert_eq!(
+            config.backup_hook.as_deref(),
+            Some("upload-backup --bucket nightly")
+        );
+        assert_eq!(config.requirepass.as_deref(), Some("s3cret"));
+        assert_eq!(
+            config.aclfile.as_deref(),
+            Some(Path::new("/etc/redis/users.acl"))
+        );
+        assert_eq!(
+            config.rename_commands["config"],
+            "b840fc02d524045429941cc15f59e41cb7be6c52"
+        );
+        assert_eq!(config.rename_commands["flushall"], "");
+        assert_eq!(config.enable_debug_command, ProtectedAccess::Local);
+        assert_eq!(
+            config.otlp_endpoint.as_deref(),
+            Some("http://localhost:4318/v1/traces")
+        );
+        assert_eq!(config.seed, Some(42));
+        assert_eq!(
+            config.save,
+            vec![
+                SavePoint {
+                    seconds: 900,
+                    changes: 1
+                },
+                SavePoint {
+                    seconds: 300,
+                    changes: 10
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        let test_cases = [
+            ("Unknown directive", "foo bar"),
+            ("Invalid log level", "loglevel loud"),
+            ("Missing argument", "maxmemory"),
+            ("Not a yes/no value", "tcp-nodelay maybe"),
+            ("Zero backlog", "tcp-backlog 0"),
+            ("Too many io threads", "io-threads 129"),
+            (
+                "Unknown eviction policy",
+                "maxmemory-policy evict-everything",
+            ),
+            ("Zero eviction samples", "maxmemory-samples 0"),
+            ("Odd save parameters", "save 900"),
+            ("Path as dbfilename", "dbfilename data/dump.rdb"),
+            ("Unknown protection", "enable-debug-command sometimes"),
+            ("Unknown supervisor", "supervised upstart"),
+            ("Negative seed", "seed -1"),
+            ("Negative backup retention", "backup-retention -1"),
+        ];
+
+        for (name, input) in test_cases {
+            assert!(Config::parse(input).is_err(), "{}", name);
+        }
+    }
+
+    #[test]
+    fn test_get_and_set() {
+        let mut config = Config::default();
+
+        let test_cases = [
+            ("maxmemory", "1mb", Ok(()), "1048576"),
+            ("maxmemory-policy", "ALLKEYS-LFU", Ok(()), "allkeys-lfu"),
+            ("loglevel", "warning", Ok(()), "warning"),
+            ("proto-max-bulk-len", "1mb", Ok(()), "1048576"),
+            (
+                "proto-max-bulk-len",
+                "1kb",
+                Err("Invalid proto-max-bulk-len value".to_string()),
+                "1048576",
+            ),
+            ("client-rate-limit", "100", Ok(()), "100"),
+            (
+                "ip-rate-limit",
+                "-1",
+                Err("Invalid IP rate limit".to_string()),
+                "0",
+            ),
+            ("hz", "100", Ok(()), "100"),
+            ("hash-max-listpack-entries", "512", Ok(()), "512"),
+            ("hash-max-listpack-value", "1kb", Ok(()), "1024"),
+            (
+                "hash-max-listpack-entries",
+                "-1",
+                Err("Invalid hash-max-listpack-entries value".to_string()),
+                "512",
+            ),
+            ("hz", "1000", Ok(()), "500"),
+            ("hz", "0", Ok(()), "1"),
+            ("save", "900 1 300 10", Ok(()), "900 1 300 10"),
+            ("save", "", Ok(()), ""),
+            ("requirepass", "", Ok(()), ""),
+            (
+                "timeout",
+                "soon",
+                Err("Invalid timeout value".to_string()),
+                "0",
+            ),
+            (
+                "port",
+                "7000",
+                Err("can't set immutable config".to_string()),
+                "6379",
+            ),
+        ];
+
+        for (name, value, expected, after) in test_cases {
+            assert_eq!(config.set(name, value), expected, "{}", name);
+            assert_eq!(config.get(name).unwrap(), after, "{}", name);
+        }
+        assert_eq!(config.get("tcp-nodelay").unwrap(), "yes");
+        assert_eq!(config.get("nope"), None);
+    }
+
+    #[test]
+    fn test_reload() {
+        let path = std::env::temp_dir().join(format!("redis-reload-{}.conf", std::process::id()));
+        fs::write(&path, "loglevel notice\nmaxmemory 1mb\n").unwrap();
+        let mut config = Config::load(&path).unwrap();
+
+        fs::write(&path, "loglevel debug\nmaxmemory 2mb\nsave \"\"\n").unwrap();
+        config.reload().unwrap();
+        assert_eq!(config.loglevel, LogLevel::Debug);
+        assert_eq!(config.maxmemory, 2 * 1024 * 1024);
+        assert!(config.save.is_empty());
+
+        fs::write(&path, "loglevel nonsense\n").unwrap();
+        assert!(config.reload().is_err());
+        assert_eq!(config.loglevel, LogLevel::Debug);
+
+        fs::write(&path, "maxclients 50\n").unwrap();
+        config.reload().unwrap();
+        assert_eq!(config.maxclients, 50);
+
+        fs::write(&path, "tcp-keepalive 60\n").unwrap();
+        config.reload().unwrap();
+        assert_eq!(config.tcp_keepalive, 60);
+
+        fs::write(&path, "latency-monitor-threshold 100\n").unwrap();
+        config.reload().unwrap();
+        assert_eq!(config.latency_monitor_threshold, 100);
+
+        fs::remove_file(&path).unwrap();
+    }
+}
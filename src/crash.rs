@@ -0,0 +1,181 @@
+//! A crash report logged when the server panics, modelled on the Redis bug report, so a crash
+//! in the field can be diagnosed from the log alone. The process aborts right after, since a
+//! panic while holding the handler lock would leave every other connection stuck.
+//!
+//! The panicking thread may hold the handler lock, so the report only reads state kept here.
+
+use crate::resp::RespData;
+use std::backtrace::Backtrace;
+use std::cell::RefCell;
+use std::fmt::Write;
+use std::panic::{self, PanicHookInfo};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::OnceLock;
+use std::time::{Duration, Instant};
+
+static STARTED: OnceLock<Instant> = OnceLock::new();
+
+static CONNECTED_CLIENTS: AtomicUsize = AtomicUsize::new(0);
+
+thread_local! {
+    /// The last command run on this thread's connection.
+    static LAST_COMMAND: RefCell<String> = const { RefCell::new(String::new()) };
+}
+
+/// Replaces the default panic hook with one logging a crash report and aborting.
+pub fn install() {
+    STARTED.get_or_init(Instant::now);
+    panic::set_hook(Box::new(|info| {
+        let report = report(
+            &describe_panic(info),
+            &Backtrace::force_capture().to_string(),
+            STARTED.get().map(Instant::elapsed).unwrap_or_default(),
+            CONNECTED_CLIENTS.load(Ordering::Relaxed),
+            &LAST_COMMAND.with_borrow(String::clone),
+        );
+        for line in report.lines() {
+            log_warning!("{line}");
+        }
+        std::process::abort();
+    }));
+}
+
+pub fn client_connected() {
+    CONNECTED_CLIENTS.fetch_add(1, Ordering::Relaxed);
+}
+
+pub fn client_disconnected() {
+    CONNECTED_CLIENTS.fetch_sub(1, Ordering::Relaxed);
+}
+
+/// Remembers `command` as the last one run on this thread. Only the name and the number of
+/// arguments are kept, since arguments may hold passwords or user data.
+pub fn record_command(command: &RespData) {
+    LAST_COMMAND.with_borrow_mut(|last| {
+        last.clear();
+        match command {
+            RespData::Array(args) => {
+                if let Some(RespData::BulkString(name)) = args.first() {
+                    last.push_str(name);
+                }
+                let _ = write!(last, " argc={}", args.len());
+            }
+            _ => last.push_str("(not a command array)"),
+        }
+    });
+}
+
+/// The panic message and where it happened.
+fn describe_panic(info: &PanicHookInfo) -> String {
+    let payload = info.payload();
+    let message = payload
+        .downcast_ref::<&str>()
+        .copied()
+        .or_else(|| payload.downcast_ref::<String>().map(String::as_str))
+        .unwrap_or("Box<dyn Any>");
+    match info.location() {
+        Some(location) => format!("{message} at {location}"),
+        None => message.to_string(),
+    }
+}
+
+fn report(
+    panic: &str,
+    backtrace: &str,
+    uptime: Duration,
+    connected_clients: usize,
+    last_command: &str,
+) -> String {
+    let mut out = String::new();
+    let thread = std::thread::current();
+    let thread = thread.name().unwrap_or("unnamed");
+    out.push_str("=== REDIS BUG REPORT START: Cut & paste starting from here ===\n");
+    let _ = writeln!(out, "Panicked in thread {thread}: {panic}");
+    out.push_str("\n------ STACK TRACE ------\n");
+    let _ = writeln!(out, "{}", backtrace.trim_end());
+    out.push_str("\n------ INFO OUTPUT ------\n");
+    let _ = writeln!(out, "redis_version:{}", env!("CARGO_PKG_VERSION"));
+    let _ = writeln!(out, "uptime_in_seconds:{}", uptime.as_secs());
+    let _ = writeln!(out, "connected_clients:{connected_clients}");
+    out.push_str("\n------ CURRENT CLIENT INFO ------\n");
+    let last_command = if last_command.is_empty() {
+        "none"
+    } else {
+        last_command
+    };
+    let _ = writeln!(out, "last_command:{last_command}");
+    out.push_str("\n=== REDIS BUG REPORT END. Make sure to include from START to END. ===\n");
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn command(args: &[&str]) -> RespData {
+        RespData::array(args.iter().copied())
+    }
+
+    #[test]
+    fn test_record_command() {
+        let test_cases = [
+            ("No arguments", command(&["PING"]), "PING argc=1"),
+            (
+                "Arguments left out",
+                command(&["AUTH", "s3cret"]),
+                "AUTH argc=2",
+            ),
+            (
+                "Not an array",
+                RespData::SimpleString("PING".to_string()),
+                "(not a command array)",
+            ),
+        ];
+
+        for (name, input, expected) in test_cases {
+            record_command(&input);
+            assert_eq!(
+                LAST_COMMAND.with_borrow(String::clone),
+                expected,
+                "{}",
+                name
+            );
+        }
+    }
+
+    #[test]
+    fn test_report() {
+        let report = report(
+            "boom at src/main.rs:1:1",
+            "0: main\n",
+            Duration::from_secs(42),
+            3,
+            "",
+        );
+        let lines: Vec<&str> = report.lines().collect();
+
+        assert!(lines[0].starts_with("=== REDIS BUG REPORT START"));
+        assert!(
+            lines[1].ends_with(": boom at src/main.rs:1:1"),
+            "{}",
+            lines[1]
+        );
+        let test_cases = [
+            ("Backtrace", "0: main"),
+            (
+                "Version",
+                &*format!("redis_version:{}", env!("CARGO_PKG_VERSION")),
+            ),
+            ("Uptime", "uptime_in_seconds:42"),
+            ("Clients", "connected_clients:3"),
+            ("No command yet", "last_command:none"),
+        ];
+        for (name, expected) in test_cases {
+            assert!(lines.contains(&expected), "{}", name);
+        }
+        assert!(lines
+            .last()
+            .unwrap()
+            .starts_with("=== REDIS BUG REPORT END"));
+    }
+}
@@ -0,0 +1,153 @@
+//! Running as a classic daemon: detaching from the terminal with `daemonize yes`, and keeping
+//! the process ID in `pidfile` for init scripts, removed again on a clean shutdown. Under
+//! `supervised systemd` the server instead reports its state with the sd_notify protocol, so
+//! units can use `Type=notify`.
+
+use crate::config::Supervised;
+use std::fs;
+use std::io;
+use std::os::unix::io::AsRawFd;
+use std::os::unix::net::{SocketAddr, UnixDatagram};
+use std::path::{Path, PathBuf};
+use std::sync::OnceLock;
+
+/// Where a daemonized server writes its PID when `pidfile` isn't set, like Redis.
+pub const DEFAULT_PIDFILE: &str = "/var/run/redis.pid";
+
+static PIDFILE: OnceLock<PathBuf> = OnceLock::new();
+
+/// The systemd notification socket, when supervised by systemd.
+static NOTIFY_SOCKET: OnceLock<String> = OnceLock::new();
+
+/// Forks into the background, leaving the parent to exit, and detaches the child from the
+/// terminal. Must run before any thread is started, since only the calling thread survives a
+/// fork.
+pub fn daemonize() -> io::Result<()> {
+    // SAFETY: no other thread exists yet, so the child starts from a consistent state.
+    match unsafe { libc::fork() } {
+        -1 => return Err(io::Error::last_os_error()),
+        0 => {}
+        _ => std::process::exit(0),
+    }
+    // SAFETY: setsid has no memory safety requirements.
+    if unsafe { libc::setsid() } == -1 {
+        return Err(io::Error::last_os_error());
+    }
+    // Nothing is left to read from or print to; logs only survive if they go to a logfile.
+    let null = fs::OpenOptions::new()
+        .read(true)
+        .write(true)
+        .open("/dev/null")?;
+    for fd in [libc::STDIN_FILENO, libc::STDOUT_FILENO, libc::STDERR_FILENO] {
+        // SAFETY: both descriptors are open, and dup2 only replaces the standard one.
+        if unsafe { libc::dup2(null.as_raw_fd(), fd) } == -1 {
+            return Err(io::Error::last_os_error());
+        }
+    }
+    Ok(())
+}
+
+/// Writes this process's ID to `path`, which `remove_pidfile` deletes on shutdown.
+pub fn write_pidfile(path: &Path) -> io::Result<()> {
+    fs::write(path, format!("{}\n", std::process::id()))?;
+    let _ = PIDFILE.set(path.to_path_buf());
+    Ok(())
+}
+
+/// Deletes the file written by `write_pidfile`, if any. Called before the process exits.
+pub fn remove_pidfile() {
+    if let Some(path) = PIDFILE.get() {
+        let _ = fs::remove_file(path);
+    }
+}
+
+/// Looks for the socket systemd passes in `NOTIFY_SOCKET`, which later notifications go to.
+pub fn supervise(mode: Supervised) {
+    if mode == Supervised::No {
+        return;
+    }
+    match std::env::var("NOTIFY_SOCKET") {
+        Ok(socket) if !socket.is_empty() => {
+            log_notice!("Supervised by systemd. Please make sure you set appropriate values for TimeoutStartSec and TimeoutStopSec in your service unit.");
+            let _ = NOTIFY_SOCKET.set(socket);
+        }
+        _ if mode == Supervised::Systemd => {
+            log_warning!("systemd supervision requested, but NOTIFY_SOCKET not found!");
+        }
+        _ => {}
+    }
+}
+
+/// Tells systemd the dataset is loaded and connections are being accepted.
+pub fn notify_ready() {
+    notify("STATUS=Ready to accept connections\nREADY=1\n");
+}
+
+/// Tells systemd the server is shutting down.
+pub fn notify_stopping() {
+    notify("STOPPING=1\n");
+}
+
+fn notify(state: &str) {
+    let Some(socket) = NOTIFY_SOCKET.get() else {
+        return;
+    };
+    if let Err(e) = send_notification(socket, state) {
+        log_warning!("Failed to notify systemd: {e}");
+    }
+}
+
+/// Sends `state` to `socket`, a path or, with a leading `@`, an abstract socket name.
+fn send_notification(socket: &str, state: &str) -> io::Result<()> {
+    let address = match socket.strip_prefix('@') {
+        #[cfg(target_os = "linux")]
+        Some(name) => {
+            use std::os::linux::net::SocketAddrExt;
+            SocketAddr::from_abstract_name(name)?
+        }
+        #[cfg(not(target_os = "linux"))]
+        Some(_) => {
+            return Err(io::Error::new(
+                io::ErrorKind::Unsupported,
+                "abstract sockets are Linux only",
+            ))
+        }
+        None => SocketAddr::from_pathname(socket)?,
+    };
+    UnixDatagram::unbound()?.send_to_addr(state.as_bytes(), &address)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_send_notification() {
+        let path = std::env::temp_dir().join(format!("redis-notify-{}.sock", std::process::id()));
+        let _ = fs::remove_file(&path);
+        let systemd = UnixDatagram::bind(&path).unwrap();
+        let mut buf = [0; 64];
+
+        send_notification(path.to_str().unwrap(), "READY=1\n").unwrap();
+        let len = systemd.recv(&mut buf).unwrap();
+        assert_eq!(&buf[..len], b"READY=1\n");
+
+        fs::remove_file(&path).unwrap();
+        assert!(send_notification(path.to_str().unwrap(), "STOPPING=1\n").is_err());
+    }
+
+    #[test]
+    fn test_pidfile() {
+        let path = std::env::temp_dir().join(format!("redis-pid-test-{}.pid", std::process::id()));
+
+        write_pidfile(&path).unwrap();
+        assert_eq!(
+            fs::read_to_string(&path).unwrap(),
+            format!("{}\n", std::process::id())
+        );
+
+        remove_pidfile();
+        assert!(!path.exists());
+    }
+}
@@ -0,0 +1,690 @@
+//! A hash table that resizes incrementally, like Redis's dict.
+//!
+//! std's `HashMap` moves every entry at once when it grows, which on a keyspace with millions of
+//! keys stalls the server for hundreds of milliseconds. `Dict` instead keeps both the old and
+//! the new table while resizing, and moves a bucket over on every write plus whatever
+//! `rehash_for` gets through when it is called periodically.
+
+use std::borrow::Borrow;
+use std::collections::hash_map::{DefaultHasher, RandomState};
+use std::hash::{BuildHasher, Hash, Hasher};
+use std::mem;
+use std::time::{Duration, Instant};
+
+const INITIAL_SIZE: usize = 4;
+/// The table shrinks once fewer than one in this many buckets would be in use.
+const MIN_FILL_RATIO: usize = 8;
+/// How many empty buckets a rehash step may skip per bucket it is asked to move, bounding the
+/// time a single step takes.
+const EMPTY_VISITS_PER_STEP: usize = 10;
+/// Buckets moved per round of `rehash_for`, between checks of the clock.
+const BUCKETS_PER_ROUND: usize = 100;
+
+struct Entry<K, V> {
+    key: K,
+    value: V,
+    next: Option<Box<Entry<K, V>>>,
+}
+
+type Bucket<K, V> = Option<Box<Entry<K, V>>>;
+
+struct Table<K, V> {
+    buckets: Vec<Bucket<K, V>>,
+    used: usize,
+}
+
+impl<K, V> Table<K, V> {
+    fn with_size(size: usize) -> Self {
+        Self {
+            buckets: (0..size).map(|_| None).collect(),
+            used: 0,
+        }
+    }
+
+    fn empty() -> Self {
+        Self::with_size(0)
+    }
+
+    fn index(&self, hash: u64) -> usize {
+        hash as usize & (self.buckets.len() - 1)
+    }
+}
+
+/// Hashes keys with SipHash, keyed by a seed. Unlike std's `RandomState` the seed can be
+/// chosen, so the bucket a key lands in, and with it sampling and SCAN order, is reproducible.
+#[derive(Clone, Copy)]
+struct SeededState(u64);
+
+impl BuildHasher for SeededState {
+    type Hasher = DefaultHasher;
+
+    fn build_hasher(&self) -> DefaultHasher {
+        let mut hasher = DefaultHasher::new();
+        hasher.write_u64(self.0);
+        hasher
+    }
+}
+
+pub struct Dict<K, V> {
+    /// The live table and, while resizing, the table entries are being moved into.
+    tables: [Table<K, V>; 2],
+    /// The next bucket of `tables[0]` to move while resizing; every bucket before it is empty.
+    rehash_index: Option<usize>,
+    hasher: SeededState,
+}
+
+impl<K: Hash + Eq, V> Dict<K, V> {
+    pub fn new() -> Self {
+        Self::with_seed(RandomState::new().hash_one(0))
+    }
+
+    /// A dict whose keys hash the same way in every run with the same seed.
+    pub fn with_seed(seed: u64) -> Self {
+        Self {
+            tables: [Table::empty(), Table::empty()],
+            rehash_index: None,
+            hasher: SeededState(seed),
+        }
+    }
+
+    /// Takes every entry, leaving an empty dict with the same seed behind.
+    pub fn take(&mut self) -> Self {
+        mem::replace(self, Self::with_seed(self.hasher.0))
+    }
+
+    pub fn len(&self) -> usize {
+        self.tables[0].used + self.tables[1].used
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn is_rehashing(&self) -> bool {
+        self.rehash_index.is_some()
+    }
+
+    pub fn get<Q>(&self, key: &Q) -> Option<&V>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        let (table, index) = self.locate(key)?;
+        let mut entry = self.tables[table].buckets[index].as_deref();
+        while let Some(current) = entry {
+            if current.key.borrow() == key {
+                return Some(&current.value);
+            }
+            entry = current.next.as_deref();
+        }
+        None
+    }
+
+    pub fn get_mut<Q>(&mut self, key: &Q) -> Option<&mut V>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        self.rehash_step();
+        self.find_mut(key)
+    }
+
+    pub fn contains_key<Q>(&self, key: &Q) -> bool
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        self.get(key).is_some()
+    }
+
+    /// Inserts a value, returning the one it replaced.
+    pub fn insert(&mut self, key: K, value: V) -> Option<V> {
+        self.rehash_step();
+        if let Some(existing) = self.find_mut(&key) {
+            return Some(mem::replace(existing, value));
+        }
+        self.expand_if_needed();
+        // While resizing, new entries go straight into the new table.
+        let table = &mut self.tables[usize::from(self.rehash_index.is_some())];
+        let index = table.index(self.hasher.hash_one(&key));
+        let next = table.buckets[index].take();
+        table.buckets[index] = Some(Box::new(Entry { key, value, next }));
+        table.used += 1;
+        None
+    }
+
+    pub fn remove<Q>(&mut self, key: &Q) -> Option<V>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        self.rehash_step();
+        let (table, index) = self.locate(key)?;
+        let table = &mut self.tables[table];
+        let mut link = &mut table.buckets[index];
+        loop {
+            let found = match link.as_deref() {
+                None => return None,
+                Some(entry) => entry.key.borrow() == key,
+            };
+            if found {
+                let mut entry = link.take().unwrap();
+                *link = entry.next.take();
+                table.used -= 1;
+                self.shrink_if_needed();
+                return Some(entry.value);
+            }
+            link = &mut link.as_mut().unwrap().next;
+        }
+    }
+
+    pub fn clear(&mut self) {
+        self.tables = [Table::empty(), Table::empty()];
+        self.rehash_index = None;
+    }
+
+    pub fn iter(&self) -> Iter<'_, K, V> {
+        Iter {
+            tables: [&self.tables[0].buckets, &self.tables[1].buckets],
+            table: 0,
+            bucket: 0,
+            entry: None,
+            remaining: self.len(),
+        }
+    }
+
+    pub fn keys(&self) -> impl Iterator<Item = &K> {
+        self.iter().map(|(key, _)| key)
+    }
+
+    pub fn values(&self) -> impl Iterator<Item = &V> {
+        self.iter().map(|(_, value)| value)
+    }
+
+    /// A random entry, picked by probing random buckets until a non-empty one turns up, then
+    /// taking a random entry of its chain. Cheap, but entries in long chains are slightly less
+    /// likely to be picked.
+    pub fn random_entry(&self, mut random: impl FnMut() -> u64) -> Option<(&K, &V)> {
+        if self.is_empty() {
+            return None;
+        }
+        let first_live_bucket = self.rehash_index.unwrap_or(0);
+        let old_buckets = self.tables[0].buckets.len() - first_live_bucket;
+        let span = old_buckets + self.tables[1].buckets.len();
+        loop {
+            let slot = (random() % span as u64) as usize;
+            let bucket = if slot < old_buckets {
+                &self.tables[0].buckets[first_live_bucket + slot]
+            } else {
+                &self.tables[1].buckets[slot - old_buckets]
+            };
+            let Some(head) = bucket.as_deref() else {
+                continue;
+            };
+            let chain_len =
+                std::iter::successors(Some(head), |entry| entry.next.as_deref()).count();
+            let position = (random() % chain_len as u64) as usize;
+            let entry = std::iter::successors(Some(head), |entry| entry.next.as_deref())
+                .nth(position)
+                .unwrap();
+            return Some((&entry.key, &entry.value));
+        }
+    }
+
+    /// Visits the entries of one bucket, or of the few buckets that cover it while resizing,
+    /// and returns the cursor to continue from; 0 once every bucket has been visited.
+    ///
+    /// Like Redis's SCAN, the cursor is incremented from its high bits down, so a full scan
+    /// sees every entry that was present throughout at least once, even if the table is
+    /// resized between calls. Entries may be seen more than once after a shrink.
+    pub fn scan(&self, cursor: u64, mut visit: impl FnMut(&K, &V)) -> u64 {
+        if self.is_empty() {
+            return 0;
+        }
+        let mut visit_bucket = |table: &Table<K, V>, index: u64| {
+            let mut entry = table.buckets[index as usize].as_deref();
+            while let Some(current) = entry {
+                visit(&current.key, &current.value);
+                entry = current.next.as_deref();
+            }
+        };
+        // Sets the bits outside `mask` so incrementing the reversed cursor carries through them.
+        let next_cursor = |cursor: u64, mask: u64| {
+            ((cursor | !mask).reverse_bits().wrapping_add(1)).reverse_bits()
+        };
+
+        let mut cursor = cursor;
+        if !self.is_rehashing() {
+            let mask = self.tables[0].buckets.len() as u64 - 1;
+            visit_bucket(&self.tables[0], cursor & mask);
+            return next_cursor(cursor, mask);
+        }
+
+        let (small, large) = if self.tables[0].buckets.len() <= self.tables[1].buckets.len() {
+            (&self.tables[0], &self.tables[1])
+        } else {
+            (&self.tables[1], &self.tables[0])
+        };
+        let small_mask = small.buckets.len() as u64 - 1;
+        let large_mask = large.buckets.len() as u64 - 1;
+        visit_bucket(small, cursor & small_mask);
+        // Then every bucket of the larger table that the small table's bucket expands into.
+        loop {
+            visit_bucket(large, cursor & large_mask);
+            cursor = next_cursor(cursor, large_mask);
+            if cursor & (small_mask ^ large_mask) == 0 {
+                return cursor;
+            }
+        }
+    }
+
+    /// Moves buckets to the new table for up to `budget`, first starting a shrink if deletes
+    /// left the table mostly empty. Returns whether a resize is still in progress.
+    pub fn rehash_for(&mut self, budget: Duration) -> bool {
+        let start = Instant::now();
+        loop {
+            self.shrink_if_needed();
+            if !self.rehash(BUCKETS_PER_ROUND) {
+                // Finishing one shrink may reveal the table could shrink further still.
+                if !self.shrink_needed() {
+                    return false;
+                }
+            }
+            if start.elapsed() >= budget {
+                return self.is_rehashing();
+            }
+        }
+    }
+
+    /// The table and bucket `key` lives in, if the dict has any buckets at all.
+    fn locate<Q>(&self, key: &Q) -> Option<(usize, usize)>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        if self.is_empty() {
+            return None;
+        }
+        let hash = self.hasher.hash_one(key);
+        let old_index = self.tables[0].index(hash);
+        match self.rehash_index {
+            // Buckets before the rehash index have already moved to the new table.
+            Some(rehash_index) if old_index < rehash_index => Some((1, self.tables[1].index(hash))),
+            Some(_) => {
+                let in_old =
+                    std::iter::successors(self.tables[0].buckets[old_index].as_deref(), |entry| {
+                        entry.next.as_deref()
+                    })
+                    .any(|entry| entry.key.borrow() == key);
+                if in_old {
+                    Some((0, old_index))
+                } else {
+                    Some((1, self.tables[1].index(hash)))
+                }
+            }
+            None => Some((0, old_index)),
+        }
+    }
+
+    fn find_mut<Q>(&mut self, key: &Q) -> Option<&mut V>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        let (table, index) = self.locate(key)?;
+        let mut entry = self.tables[table].buckets[index].as_deref_mut();
+        while let Some(current) = entry {
+            if current.key.borrow() == key {
+                return Some(&mut current.value);
+            }
+            entry = current.next.as_deref_mut();
+        }
+        None
+    }
+
+    /// Moves a single bucket along if a resize is in progress, spreading its cost over writes.
+    fn rehash_step(&mut self) {
+        if self.is_rehashing() {
+            self.rehash(1);
+        }
+    }
+
+    fn expand_if_needed(&mut self) {
+        if self.is_rehashing() {
+            return;
+        }
+        let table = &self.tables[0];
+        if table.buckets.is_empty() {
+            self.tables[0] = Table::with_size(INITIAL_SIZE);
+        } else if table.used >= table.buckets.len() {
+            self.start_resize(table.used * 2);
+        }
+    }
+
+    fn shrink_needed(&self) -> bool {
+        let table = &self.tables[0];
+        !self.is_rehashing()
+            && table.buckets.len() > INITIAL_SIZE
+            && table.used * MIN_FILL_RATIO < table.buckets.len()
+    }
+
+    fn shrink_if_needed(&mut self) {
+        if self.shrink_needed() {
+            self.start_resize(self.tables[0].used);
+        }
+    }
+
+    fn start_resize(&mut self, min_size: usize) {
+        let size = min_size.max(INITIAL_SIZE).next_power_of_two();
+        if size == self.tables[0].buckets.len() {
+            return;
+        }
+        self.tables[1] = Table::with_size(size);
+        self.rehash_index = Some(0);
+    }
+
+    /// Moves up to `buckets` non-empty buckets into the new table. Returns whether there is
+    /// more left to move.
+    fn rehash(&mut self, buckets: usize) -> bool {
+        let Some(mut index) = self.rehash_index else {
+            return false;
+        };
+        let [old, new] = &mut self.tables;
+        let mut empty_visits = buckets * EMPTY_VISITS_PER_STEP;
+        for _ in 0..buckets {
+            if old.used == 0 {
+                break;
+            }
+            while old.buckets[index].is_none() {
+                index += 1;
+                empty_visits -= 1;
+                if empty_visits == 0 {
+                    self.rehash_index = Some(index);
+                    return true;
+                }
+            }
+            let mut chain = old.buckets[index].take();
+            while let Some(mut entry) = chain {
+                chain = entry.next.take();
+                let new_index = new.index(self.hasher.hash_one(&entry.key));
+                entry.next = new.buckets[new_index].take();
+                new.buckets[new_index] = Some(entry);
+                old.used -= 1;
+                new.used += 1;
+            }
+            index += 1;
+        }
+
+        if old.used > 0 {
+            self.rehash_index = Some(index);
+            return true;
+        }
+        self.tables[0] = mem::replace(&mut self.tables[1], Table::empty());
+        self.rehash_index = None;
+        false
+    }
+}
+
+impl<K: Hash + Eq, V> Default for Dict<K, V> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<K: Hash + Eq, V> FromIterator<(K, V)> for Dict<K, V> {
+    fn from_iter<I: IntoIterator<Item = (K, V)>>(iter: I) -> Self {
+        let mut dict = Self::new();
+        for (key, value) in iter {
+            dict.insert(key, value);
+        }
+        dict
+    }
+}
+
+impl<'a, K: Hash + Eq, V> IntoIterator for &'a Dict<K, V> {
+    type Item = (&'a K, &'a V);
+    type IntoIter = Iter<'a, K, V>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
+
+pub struct Iter<'a, K, V> {
+    tables: [&'a [Bucket<K, V>]; 2],
+    table: usize,
+    bucket: usize,
+    entry: Option<&'a Entry<K, V>>,
+    remaining: usize,
+}
+
+impl<'a, K, V> Iterator for Iter<'a, K, V> {
+    type Item = (&'a K, &'a V);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            if let Some(entry) = self.entry {
+                self.entry = entry.next.as_deref();
+                self.remaining -= 1;
+                return Some((&entry.key, &entry.value));
+            }
+            let buckets = self.tables.get(self.table)?;
+            match buckets.get(self.bucket) {
+                Some(bucket) => {
+                    self.entry = bucket.as_deref();
+                    self.bucket += 1;
+                }
+                None => {
+                    self.table += 1;
+                    self.bucket = 0;
+                }
+            }
+        }
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.remaining, Some(self.remaining))
+    }
+}
+
+impl<K, V> ExactSizeIterator for Iter<'_, K, V> {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashSet;
+
+    fn dict_with(keys: usize) -> Dict<String, usize> {
+        (0..keys).map(|i| (i.to_string(), i)).collect()
+    }
+
+    #[test]
+    fn test_insert_get_remove() {
+        let mut dict = Dict::new();
+
+        assert_eq!(dict.insert("a".to_string(), 1), None);
+        assert_eq!(dict.insert("b".to_string(), 2), None);
+        assert_eq!(dict.insert("a".to_string(), 3), Some(1));
+        assert_eq!(dict.get("a"), Some(&3));
+        assert_eq!(dict.len(), 2);
+
+        *dict.get_mut("b").unwrap() += 10;
+        assert_eq!(dict.get("b"), Some(&12));
+        assert_eq!(dict.remove("b"), Some(12));
+        assert_eq!(dict.remove("b"), None);
+        assert!(!dict.contains_key("b"));
+        assert_eq!(dict.len(), 1);
+
+        dict.clear();
+        assert!(dict.is_empty());
+        assert_eq!(dict.get("a"), None);
+    }
+
+    #[test]
+    fn test_incremental_growth() {
+        let mut dict = dict_with(INITIAL_SIZE);
+        assert!(!dict.is_rehashing());
+
+        // Filling the table starts a resize that only moves one bucket per write.
+        dict.insert("trigger".to_string(), 0);
+        assert!(dict.is_rehashing());
+        assert_eq!(dict.tables[1].buckets.len(), INITIAL_SIZE * 2);
+
+        for i in 0..1000 {
+            dict.insert(format!("key{i}"), i);
+            // Every key stays reachable in the middle of a resize.
+            assert_eq!(dict.get(&format!("key{i}")), Some(&i));
+            assert_eq!(dict.get("0"), Some(&0));
+        }
+        assert_eq!(dict.len(), INITIAL_SIZE + 1001);
+
+        assert!(!dict.rehash_for(Duration::from_secs(1)));
+        assert!(!dict.is_rehashing());
+        assert!(dict.tables[0].buckets.len() >= dict.len());
+        assert!(dict.tables[1].buckets.is_empty());
+    }
+
+    #[test]
+    fn test_shrink() {
+        let mut dict = dict_with(1000);
+        dict.rehash_for(Duration::from_secs(1));
+        for i in 0..995 {
+            dict.remove(&i.to_string());
+        }
+        dict.rehash_for(Duration::from_secs(1));
+
+        assert_eq!(dict.len(), 5);
+        assert!(dict.tables[0].buckets.len() <= 16);
+        for i in 995..1000 {
+            assert_eq!(dict.get(&i.to_string()), Some(&i));
+        }
+    }
+
+    #[test]
+    fn test_iter_during_rehash() {
+        let mut dict = dict_with(64);
+        // Filling the table exactly starts a resize.
+        dict.insert("extra".to_string(), 64);
+
+        let test_cases = [("Resizing", true), ("Resized", false)];
+        for (name, rehashing) in test_cases {
+            if !rehashing {
+                dict.rehash_for(Duration::from_secs(1));
+            }
+            assert_eq!(dict.is_rehashing(), rehashing, "{}", name);
+            let keys: HashSet<&String> = dict.keys().collect();
+            assert_eq!(keys.len(), 65, "{}", name);
+            assert_eq!(dict.iter().len(), 65, "{}", name);
+            assert_eq!(dict.values().sum::<usize>(), (0..=64).sum(), "{}", name);
+        }
+    }
+
+    #[test]
+    fn test_scan() {
+        let scan_all = |dict: &Dict<String, usize>| {
+            let mut seen = HashSet::new();
+            let mut cursor = 0;
+            loop {
+                cursor = dict.scan(cursor, |key, _| {
+                    seen.insert(key.clone());
+                });
+                if cursor == 0 {
+                    return seen;
+                }
+            }
+        };
+
+        let empty: Dict<String, usize> = Dict::new();
+        assert_eq!(empty.scan(0, |_, _| panic!("empty dict has no entries")), 0);
+
+        let mut dict = dict_with(64);
+        assert_eq!(scan_all(&dict).len(), 64);
+        dict.insert("extra".to_string(), 64);
+        assert!(dict.is_rehashing());
+        assert_eq!(scan_all(&dict).len(), 65);
+
+        // A resize between calls must not make the scan skip entries.
+        let test_cases = [("Growing", 8, 1000), ("Shrinking", 1000, 8)];
+        for (name, before, after) in test_cases {
+            let mut dict = dict_with(before);
+            dict.rehash_for(Duration::from_secs(1));
+            let mut seen = HashSet::new();
+            let mut cursor = dict.scan(0, |key, _| {
+                seen.insert(key.clone());
+            });
+            let keep: HashSet<String> = (0..before.min(after)).map(|i| i.to_string()).collect();
+            for i in before.min(after)..before.max(after) {
+                if before < after {
+                    dict.insert(i.to_string(), i);
+                } else {
+                    dict.remove(&i.to_string());
+                }
+            }
+            dict.rehash_for(Duration::from_secs(1));
+            while cursor != 0 {
+                cursor = dict.scan(cursor, |key, _| {
+                    seen.insert(key.clone());
+                });
+            }
+            assert!(keep.is_subset(&seen), "{}", name);
+        }
+    }
+
+    #[test]
+    fn test_random_entry() {
+        let mut state = 0x9e37_79b9_7f4a_7c15u64;
+        let mut random = || {
+            state ^= state << 13;
+            state ^= state >> 7;
+            state ^= state << 17;
+            state
+        };
+
+        let empty: Dict<String, usize> = Dict::new();
+        assert_eq!(empty.random_entry(&mut random), None);
+
+        let mut dict = dict_with(64);
+        dict.insert("extra".to_string(), 64);
+        assert!(dict.is_rehashing());
+        let mut seen = HashSet::new();
+        for _ in 0..5000 {
+            let (key, value) = dict.random_entry(&mut random).unwrap();
+            assert_eq!(dict.get(key), Some(value));
+            seen.insert(key.clone());
+        }
+        assert_eq!(seen.len(), 65);
+    }
+
+    #[test]
+    fn test_seed() {
+        let scan_order = |dict: &Dict<String, usize>| {
+            let mut keys = Vec::new();
+            let mut cursor = 0;
+            loop {
+                cursor = dict.scan(cursor, |key, _| keys.push(key.clone()));
+                if cursor == 0 {
+                    return keys;
+                }
+            }
+        };
+        let seeded = |seed| {
+            let mut dict = Dict::with_seed(seed);
+            for i in 0..100 {
+                dict.insert(i.to_string(), i);
+            }
+            dict
+        };
+
+        assert_eq!(scan_order(&seeded(42)), scan_order(&seeded(42)));
+        assert_ne!(scan_order(&seeded(42)), scan_order(&seeded(43)));
+
+        let mut dict = seeded(42);
+        let taken = dict.take();
+        assert_eq!(taken.len(), 100);
+        assert!(dict.is_empty());
+        for i in 0..100 {
+            dict.insert(i.to_string(), i);
+        }
+        assert_eq!(scan_order(&dict), scan_order(&seeded(42)));
+    }
+}
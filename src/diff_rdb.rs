@@ -0,0 +1,203 @@
+//! `--diff-rdb`: compares two snapshots offline, e.g. to verify a migration or that a replica
+//! holds what its primary does. Reports keys found in only one of them, keys whose type,
+//! value or expiry differ, and how many keys matched.
+//!
+//! Only the first snapshot is held in memory. For datasets too big for that, a sample of the
+//! keys can be compared instead; whether a key is sampled depends only on its name, so both
+//! sides sample the same keys.
+
+use crate::handler::{ListpackLimits, RedisValue};
+use crate::rdb::{self, RdbError};
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{self, BufReader, Write};
+use std::path::Path;
+
+/// The share of the keys compared, in percent.
+pub const FULL_SAMPLE: u8 = 100;
+
+/// Whether `key` is among the `sample` percent of keys compared.
+fn sampled(key: &str, sample: u8) -> bool {
+    sample >= FULL_SAMPLE || rdb::crc64(0, key.as_bytes()) % 100 < sample as u64
+}
+
+/// Reads the sampled keys of the snapshot at `path`, calling `visit` for each.
+fn load_sample(
+    path: &Path,
+    sample: u8,
+    mut visit: impl FnMut(String, RedisValue, Option<u64>),
+) -> Result<(), RdbError> {
+    let mut input = BufReader::new(File::open(path)?);
+    rdb::load_each(&mut input, ListpackLimits::default(), |entry| {
+        if sampled(&entry.key, sample) {
+            visit(entry.key, entry.value, entry.expire_at_ms);
+        }
+    })
+}
+
+fn ttl(expire_at_ms: Option<u64>) -> String {
+    expire_at_ms.map_or("none".to_string(), |ms| ms.to_string())
+}
+
+/// Compares the `sample` percent of keys of the snapshots at `first` and `second`, writing
+/// every difference to `out`. Returns whether they hold the same keys, which they don't if
+/// either couldn't be read.
+pub fn diff_rdb(first: &Path, second: &Path, sample: u8, out: &mut impl Write) -> io::Result<bool> {
+    let mut keys = HashMap::new();
+    if let Err(e) = load_sample(first, sample, |key, value, expire_at_ms| {
+        keys.insert(key, (value, expire_at_ms));
+    }) {
+        writeln!(out, "Cannot read RDB file {}: {e}", first.display())?;
+        return Ok(false);
+    }
+
+    let (mut compared, mut differences) = (0, 0);
+    let mut result = Ok(());
+    let loaded = load_sample(second, sample, |key, value, expire_at_ms| {
+        compared += 1;
+        let difference = match keys.remove(&key) {
+            None => Some(format!("only in {}", second.display())),
+            Some((first_value, _)) if first_value.type_name() != value.type_name() => Some(
+                format!("type {} vs {}", first_value.type_name(), value.type_name()),
+            ),
+            Some((first_value, _)) if first_value != value => Some("value differs".to_string()),
+            Some((_, first_expire)) if first_expire != expire_at_ms => Some(format!(
+                "expiry {} vs {}",
+                ttl(first_expire),
+                ttl(expire_at_ms)
+            )),
+            Some(_) => None,
+        };
+        if let Some(difference) = difference {
+            differences += 1;
+            if result.is_ok() {
+                result = writeln!(out, "[diff] {key}: {difference}");
+            }
+        }
+    });
+    result?;
+    if let Err(e) = loaded {
+        writeln!(out, "Cannot read RDB file {}: {e}", second.display())?;
+        return Ok(false);
+    }
+
+    let mut only_first: Vec<String> = keys.into_keys().collect();
+    only_first.sort_unstable();
+    for key in &only_first {
+        writeln!(out, "[diff] {key}: only in {}", first.display())?;
+    }
+    compared += only_first.len();
+    differences += only_first.len();
+
+    if sample < FULL_SAMPLE {
+        writeln!(out, "[info] {compared} keys compared ({sample}% sample)")?;
+    } else {
+        writeln!(out, "[info] {compared} keys compared")?;
+    }
+    writeln!(out, "[info] {differences} differences")?;
+    Ok(differences == 0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use std::process;
+
+    fn write_snapshot(path: &Path, entries: &[(&str, RedisValue, Option<u64>)]) {
+        let keys: Vec<String> = entries.iter().map(|(key, _, _)| key.to_string()).collect();
+        let mut snapshot = Vec::new();
+        rdb::dump(
+            entries
+                .iter()
+                .zip(&keys)
+                .map(|((_, value, expire_at_ms), key)| (key, value, *expire_at_ms)),
+            &mut snapshot,
+        )
+        .unwrap();
+        fs::write(path, snapshot).unwrap();
+    }
+
+    fn string(value: &str) -> RedisValue {
+        RedisValue::String(value.into())
+    }
+
+    fn hash(field: &str, value: &str) -> RedisValue {
+        RedisValue::Hash(
+            [(field.to_string(), value.to_string())]
+                .into_iter()
+                .collect(),
+        )
+    }
+
+    #[test]
+    fn test_sampled() {
+        let keys: Vec<String> = (0..1000).map(|i| format!("key:{i}")).collect();
+        let count = |sample| keys.iter().filter(|key| sampled(key, sample)).count();
+
+        assert_eq!(count(FULL_SAMPLE), 1000);
+        assert_eq!(count(0), 0);
+        assert!((50..150).contains(&count(10)));
+        assert!(keys
+            .iter()
+            .filter(|key| sampled(key, 10))
+            .all(|key| sampled(key, 20)));
+    }
+
+    #[test]
+    fn test_diff_rdb() {
+        let dir = std::env::temp_dir().join(format!("redis-diff-rdb-{}", process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let (first, second) = (dir.join("first.rdb"), dir.join("second.rdb"));
+        write_snapshot(
+            &first,
+            &[
+                ("same", string("v"), Some(u64::MAX)),
+                ("gone", string("v"), None),
+                ("retyped", string("v"), None),
+                ("changed", hash("f", "1"), None),
+                ("ttl", string("v"), None),
+            ],
+        );
+        write_snapshot(
+            &second,
+            &[
+                ("same", string("v"), Some(u64::MAX)),
+                ("new", string("v"), None),
+                ("retyped", hash("f", "v"), None),
+                ("changed", hash("f", "2"), None),
+                ("ttl", string("v"), Some(42)),
+            ],
+        );
+
+        let mut out = Vec::new();
+        assert!(diff_rdb(&first, &first, FULL_SAMPLE, &mut out).unwrap());
+        let out = String::from_utf8(out).unwrap();
+        assert_eq!(out, "[info] 5 keys compared\n[info] 0 differences\n");
+
+        let mut out = Vec::new();
+        assert!(!diff_rdb(&first, &second, FULL_SAMPLE, &mut out).unwrap());
+        let out = String::from_utf8(out).unwrap();
+        let expected = [
+            format!("[diff] new: only in {}", second.display()),
+            "[diff] retyped: type string vs hash".to_string(),
+            "[diff] changed: value differs".to_string(),
+            "[diff] ttl: expiry none vs 42".to_string(),
+            format!("[diff] gone: only in {}", first.display()),
+            "[info] 6 keys compared".to_string(),
+            "[info] 5 differences".to_string(),
+        ];
+        assert_eq!(out.lines().collect::<Vec<_>>(), expected);
+
+        let mut out = Vec::new();
+        assert!(diff_rdb(&first, &second, 0, &mut out).unwrap());
+        let out = String::from_utf8(out).unwrap();
+        assert!(out.contains("[info] 0 keys compared (0% sample)"), "{out}");
+
+        let mut out = Vec::new();
+        let missing = dir.join("missing.rdb");
+        assert!(!diff_rdb(&first, &missing, FULL_SAMPLE, &mut out).unwrap());
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
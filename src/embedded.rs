@@ -0,0 +1,257 @@
+//! An in-process client for applications embedding the server. Commands go straight into the
+//! command handler, taking its lock like a connection would, so there is no socket and no RESP
+//! encoding in between.
+//!
+//! The client is trusted: it isn't a connection, so it never has to authenticate and has no
+//! per-connection state such as a name or a protocol version. It does wait out CLIENT PAUSE,
+//! and a SHUTDOWN it sends ends the process as it would over TCP.
+
+use crate::handler::CommandHandler;
+use crate::rdb::RdbError;
+use crate::resp::RespData;
+use crate::server;
+use std::error::Error;
+use std::fmt;
+use std::sync::{Arc, Mutex};
+
+/// An error reply to a command, as a client would read it, e.g. "ERR syntax error".
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct ReplyError(pub String);
+
+impl ReplyError {
+    fn new(message: String) -> Self {
+        // Like the RESP encoder, an error starting with '-' carries its own code instead of ERR.
+        match message.strip_prefix('-') {
+            Some(message) => Self(message.to_string()),
+            None => Self(format!("ERR {message}")),
+        }
+    }
+}
+
+impl fmt::Display for ReplyError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+impl Error for ReplyError {}
+
+/// A handle running commands on a `CommandHandler` in this process. Cloning it is cheap, and
+/// clones may be used from different threads.
+#[derive(Clone)]
+pub struct EmbeddedClient {
+    handler: Arc<Mutex<CommandHandler>>,
+}
+
+impl EmbeddedClient {
+    /// A client for `handler`, which needn't be served over TCP at all.
+    pub fn new(handler: Arc<Mutex<CommandHandler>>) -> Self {
+        Self { handler }
+    }
+
+    /// Runs a command, given as the array of bulk strings a connection would send, and
+    /// returns the reply.
+    pub fn execute(&self, command: &RespData) -> RespData {
+        server::run_command(&self.handler, command, |handler| handler.handle(command))
+    }
+
+    /// Runs the command made of `args`, like `execute`.
+    pub fn command(&self, args: &[&str]) -> RespData {
+        self.execute(&RespData::array(args.iter().copied()))
+    }
+
+    /// GET key: the value, or `None` if the key doesn't exist.
+    pub fn get(&self, key: &str) -> Result<Option<String>, ReplyError> {
+        match self.command(&["GET", key]) {
+            RespData::BulkString(value) => Ok(Some(value)),
+            reply => none_or_error(reply),
+        }
+    }
+
+    /// SET key value
+    pub fn set(&self, key: &str, value: &str) -> Result<(), ReplyError> {
+        ok_or_error(self.command(&["SET", key, value]))
+    }
+
+    /// DEL key [key ...]: how many of the keys existed.
+    pub fn del(&self, keys: &[&str]) -> Result<i64, ReplyError> {
+        let mut args = vec!["DEL"];
+        args.extend(keys);
+        integer_or_error(self.command(&args))
+    }
+
+    /// INCR key: the value after the increment.
+    pub fn incr(&self, key: &str) -> Result<i64, ReplyError> {
+        integer_or_error(self.command(&["INCR", key]))
+    }
+
+    /// EXPIRE key seconds: whether the key existed to be given the TTL.
+    pub fn expire(&self, key: &str, seconds: u64) -> Result<bool, ReplyError> {
+        integer_or_error(self.command(&["EXPIRE", key, &seconds.to_string()])).map(|set| set == 1)
+    }
+
+    /// HSET key field value: whether the field is new.
+    pub fn hset(&self, key: &str, field: &str, value: &str) -> Result<bool, ReplyError> {
+        integer_or_error(self.command(&["HSET", key, field, value])).map(|added| added == 1)
+    }
+
+    /// HGET key field: the value, or `None` if the key or field doesn't exist.
+    pub fn hget(&self, key: &str, field: &str) -> Result<Option<String>, ReplyError> {
+        match self.command(&["HGET", key, field]) {
+            RespData::BulkString(value) => Ok(Some(value)),
+            reply => none_or_error(reply),
+        }
+    }
+
+    /// The dataset as an RDB snapshot, to checkpoint it without touching the filesystem.
+    pub fn snapshot(&self) -> Vec<u8> {
+        self.handler.lock().unwrap().snapshot()
+    }
+
+    /// Replaces the dataset with one taken by `snapshot`, returning how many keys it holds. A
+    /// snapshot that doesn't load leaves the dataset as it was.
+    pub fn restore(&self, snapshot: &[u8]) -> Result<usize, RdbError> {
+        self.handler.lock().unwrap().restore(snapshot)
+    }
+}
+
+fn ok_or_error(reply: RespData) -> Result<(), ReplyError> {
+    match reply {
+        RespData::Error(message) => Err(ReplyError::new(message)),
+        _ => Ok(()),
+    }
+}
+
+fn none_or_error<T>(reply: RespData) -> Result<Option<T>, ReplyError> {
+    match reply {
+        RespData::Error(message) => Err(ReplyError::new(message)),
+        _ => Ok(None),
+    }
+}
+
+fn integer_or_error(reply: RespData) -> Result<i64, ReplyError> {
+    match reply {
+        RespData::Integer(n) => Ok(n),
+        RespData::Error(message) => Err(ReplyError::new(message)),
+        reply => Err(ReplyError::new(format!("unexpected reply {reply:?}"))),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+    use std::thread;
+
+    fn client() -> EmbeddedClient {
+        EmbeddedClient::new(Arc::new(Mutex::new(CommandHandler::from(HashMap::new()))))
+    }
+
+    #[test]
+    fn test_command() {
+        let client = client();
+        let test_cases = [
+            (
+                "Set",
+                client.command(&["SET", "k", "v"]),
+                RespData::SimpleString("OK".to_string()),
+            ),
+            (
+                "Get",
+                client.command(&["GET", "k"]),
+                RespData::BulkString("v".to_string()),
+            ),
+            (
+                "No authentication needed",
+                client.command(&["CONFIG", "SET", "requirepass", "secret"]),
+                RespData::SimpleString("OK".to_string()),
+            ),
+            (
+                "Still trusted",
+                client.execute(&RespData::Array(vec![
+                    RespData::BulkString("GET".to_string()),
+                    RespData::BulkString("k".to_string()),
+                ])),
+                RespData::BulkString("v".to_string()),
+            ),
+        ];
+
+        for (name, actual, expected) in test_cases {
+            assert_eq!(actual, expected, "{}", name);
+        }
+    }
+
+    #[test]
+    fn test_typed_commands() {
+        let client = client();
+        let wrong_type = Err(ReplyError(
+            "WRONGTYPE Operation against a key holding the wrong kind of value".to_string(),
+        ));
+
+        assert_eq!(client.get("k"), Ok(None));
+        assert_eq!(client.set("k", "v"), Ok(()));
+        assert_eq!(client.get("k"), Ok(Some("v".to_string())));
+        assert_eq!(client.incr("n"), Ok(1));
+        assert_eq!(
+            client.incr("k"),
+            Err(ReplyError(
+                "ERR value is not an integer or out of range".to_string()
+            ))
+        );
+        assert_eq!(client.hset("h", "f", "1"), Ok(true));
+        assert_eq!(client.hset("h", "f", "2"), Ok(false));
+        assert_eq!(client.hget("h", "f"), Ok(Some("2".to_string())));
+        assert_eq!(client.hget("h", "missing"), Ok(None));
+        assert_eq!(client.hget("k", "f"), wrong_type);
+        assert_eq!(client.get("h"), wrong_type);
+        assert_eq!(client.expire("k", 100), Ok(true));
+        assert_eq!(client.expire("missing", 100), Ok(false));
+        assert_eq!(client.del(&["k", "n", "missing"]), Ok(2));
+    }
+
+    #[test]
+    fn test_snapshot_and_restore() {
+        let other = client();
+        let client = client();
+        client.set("k", "v").unwrap();
+        client.hset("h", "f", "1").unwrap();
+        client.expire("k", 100).unwrap();
+        let snapshot = client.snapshot();
+
+        client.set("k", "changed").unwrap();
+        client.set("new", "v").unwrap();
+        assert!(client.restore(&snapshot[..snapshot.len() - 1]).is_err());
+        assert_eq!(client.get("k"), Ok(Some("changed".to_string())));
+
+        assert_eq!(client.restore(&snapshot).unwrap(), 2);
+        assert_eq!(client.get("k"), Ok(Some("v".to_string())));
+        assert_eq!(client.hget("h", "f"), Ok(Some("1".to_string())));
+        assert_eq!(client.get("new"), Ok(None));
+        assert!(matches!(
+            client.command(&["TTL", "k"]),
+            RespData::Integer(99 | 100)
+        ));
+
+        assert_eq!(other.restore(&snapshot).unwrap(), 2);
+        assert_eq!(other.snapshot().len(), snapshot.len());
+    }
+
+    #[test]
+    fn test_shared_between_threads() {
+        let client = client();
+        let threads: Vec<_> = (0..4)
+            .map(|_| {
+                let client = client.clone();
+                thread::spawn(move || {
+                    for _ in 0..100 {
+                        client.incr("counter").unwrap();
+                    }
+                })
+            })
+            .collect();
+        for thread in threads {
+            thread.join().unwrap();
+        }
+        assert_eq!(client.get("counter"), Ok(Some("400".to_string())));
+    }
+}
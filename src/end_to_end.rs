@@ -0,0 +1,450 @@
+//! End-to-end tests: a `TestServer` driven through the `redis` client crate, so the whole
+//! path from the socket through the protocol to the handler and back is covered.
+
+use crate::io_threads::MAX_INPUT_SLACK;
+use crate::resp::{Resp, RespData};
+use crate::test_server::{free_port, TestServer};
+use redis::{Commands, ErrorKind, Value};
+use std::collections::HashMap;
+use std::io::{Read, Write};
+use std::net::TcpStream;
+use std::thread;
+use std::time::Duration;
+
+#[test]
+fn test_strings() {
+    let server = TestServer::start();
+    let mut con = server.connection();
+
+    let _: () = con.set("greeting", "hello").unwrap();
+    assert_eq!(con.get::<_, String>("greeting").unwrap(), "hello");
+    assert_eq!(con.append::<_, _, i64>("greeting", " world").unwrap(), 11);
+    assert_eq!(con.strlen::<_, i64>("greeting").unwrap(), 11);
+    assert_eq!(
+        con.getrange::<_, String>("greeting", 0, 4).unwrap(),
+        "hello"
+    );
+    assert_eq!(con.get::<_, Option<String>>("missing").unwrap(), None);
+
+    assert_eq!(con.incr::<_, _, i64>("counter", 1).unwrap(), 1);
+    assert_eq!(con.incr::<_, _, i64>("counter", 41).unwrap(), 42);
+    assert_eq!(con.decr::<_, _, i64>("counter", 2).unwrap(), 40);
+
+    let value = "ünïcødé ☃ and a \r\n in the middle";
+    let _: () = con.set("unicode", value).unwrap();
+    assert_eq!(con.get::<_, String>("unicode").unwrap(), value);
+
+    let big = "x".repeat(1 << 20);
+    let _: () = con.set("big", &big).unwrap();
+    assert_eq!(con.get::<_, String>("big").unwrap(), big);
+}
+
+#[test]
+fn test_hashes() {
+    let server = TestServer::start();
+    let mut con = server.connection();
+
+    let added: i64 = redis::cmd("HSET")
+        .arg("user:1")
+        .arg(&["name", "ada", "lang", "rust"])
+        .query(&mut con)
+        .unwrap();
+    assert_eq!(added, 2);
+    assert_eq!(con.hset::<_, _, _, i64>("user:1", "lang", "c").unwrap(), 0);
+    assert_eq!(
+        con.hset::<_, _, _, i64>("user:1", "lang", "rust").unwrap(),
+        0
+    );
+    assert_eq!(con.hget::<_, _, String>("user:1", "name").unwrap(), "ada");
+    assert_eq!(
+        con.hget::<_, _, Option<String>>("user:1", "missing")
+            .unwrap(),
+        None
+    );
+    assert_eq!(con.hlen::<_, i64>("user:1").unwrap(), 2);
+    // A small hash replies in the order its fields were added.
+    let all: Vec<(String, String)> = con.hgetall("user:1").unwrap();
+    assert_eq!(
+        all,
+        [
+            ("name".to_string(), "ada".to_string()),
+            ("lang".to_string(), "rust".to_string()),
+        ]
+    );
+    let type_name: String = redis::cmd("TYPE").arg("user:1").query(&mut con).unwrap();
+    assert_eq!(type_name, "hash");
+}
+
+#[test]
+fn test_big_hgetall() {
+    let server = TestServer::start();
+    let mut con = server.connection();
+
+    let fields: Vec<(String, String)> = (0..5000)
+        .map(|i| (format!("field{i}"), format!("value{i}")))
+        .collect();
+    redis::cmd("HSET")
+        .arg("big")
+        .arg(&fields)
+        .query::<()>(&mut con)
+        .unwrap();
+    let all: HashMap<String, String> = con.hgetall("big").unwrap();
+    assert_eq!(all, fields.into_iter().collect());
+    // The connection is still in step after a streamed reply.
+    assert_eq!(con.hlen::<_, i64>("big").unwrap(), 5000);
+}
+
+#[test]
+fn test_expiry() {
+    let server = TestServer::start();
+    let mut con = server.connection();
+
+    let _: () = redis::cmd("SET")
+        .arg("session")
+        .arg("token")
+        .arg("EX")
+        .arg(100)
+        .query(&mut con)
+        .unwrap();
+    let ttl: i64 = con.ttl("session").unwrap();
+    assert!((99..=100).contains(&ttl), "{ttl}");
+    assert!(con.persist::<_, bool>("session").unwrap());
+    assert_eq!(con.ttl::<_, i64>("session").unwrap(), -1);
+    assert_eq!(con.ttl::<_, i64>("missing").unwrap(), -2);
+
+    assert!(con.pexpire::<_, bool>("session", 20).unwrap());
+    thread::sleep(Duration::from_millis(50));
+    assert_eq!(con.get::<_, Option<String>>("session").unwrap(), None);
+}
+
+#[test]
+fn test_keyspace() {
+    let server = TestServer::start();
+    let mut con = server.connection();
+
+    for i in 0..50 {
+        let _: () = con.set(format!("key:{i}"), i).unwrap();
+    }
+    let _: () = con.set("other", 0).unwrap();
+    let mut keys: Vec<String> = con.scan_match("key:*").unwrap().collect();
+    keys.sort();
+    let mut expected: Vec<String> = (0..50).map(|i| format!("key:{i}")).collect();
+    expected.sort();
+    assert_eq!(keys, expected);
+
+    assert_eq!(con.del::<_, i64>(&["key:0", "key:1", "nope"]).unwrap(), 2);
+    let _: () = redis::cmd("FLUSHDB").query(&mut con).unwrap();
+    assert_eq!(con.scan::<String>().unwrap().count(), 0);
+}
+
+#[test]
+fn test_errors() {
+    let server = TestServer::start();
+    let mut con = server.connection();
+
+    let _: () = con.set("string", "value").unwrap();
+    let err = con.hget::<_, _, String>("string", "field").unwrap_err();
+    assert_eq!(err.code(), Some("WRONGTYPE"));
+
+    let _: () = con.set("text", "abc").unwrap();
+    let err = con.incr::<_, _, i64>("text", 1).unwrap_err();
+    assert_eq!(err.kind(), ErrorKind::ResponseError);
+    assert_eq!(
+        err.detail(),
+        Some("value is not an integer or out of range")
+    );
+
+    let err = redis::cmd("NOSUCHCOMMAND")
+        .arg("a")
+        .query::<Value>(&mut con)
+        .unwrap_err();
+    assert_eq!(err.kind(), ErrorKind::ResponseError);
+    assert!(
+        err.detail()
+            .unwrap()
+            .starts_with("unknown command 'NOSUCHCOMMAND'"),
+        "{err}"
+    );
+
+    let err = redis::cmd("GET").query::<Value>(&mut con).unwrap_err();
+    assert_eq!(
+        err.detail(),
+        Some("wrong number of arguments for 'get' command")
+    );
+
+    // The connection is still usable after errors.
+    assert_eq!(con.get::<_, String>("string").unwrap(), "value");
+}
+
+#[test]
+fn test_pipelining() {
+    let server = TestServer::start();
+    let mut con = server.connection();
+
+    let mut pipe = redis::pipe();
+    for _ in 0..100 {
+        pipe.incr("counter", 1);
+    }
+    pipe.get("counter");
+    let replies: Vec<i64> = pipe.query(&mut con).unwrap();
+    assert_eq!(replies.len(), 101);
+    assert_eq!(replies[..100], (1..=100).collect::<Vec<_>>());
+    assert_eq!(replies[100], 100);
+}
+
+#[test]
+fn test_pipelining_with_io_threads() {
+    let server = TestServer::with_config(|config| config.io_threads = 4);
+    let mut con = server.connection();
+
+    let mut pipe = redis::pipe();
+    for i in 0..1000 {
+        pipe.hset("hash", format!("field{i}"), "x".repeat(1000))
+            .ignore();
+    }
+    pipe.hlen("hash").cmd("QUIT").ignore();
+    let replies: Vec<i64> = pipe.query(&mut con).unwrap();
+    assert_eq!(replies, vec![1000]);
+    assert_eq!(server.connection().hlen::<_, i64>("hash").unwrap(), 1000);
+}
+
+#[test]
+fn test_io_threads() {
+    let server = TestServer::with_config(|config| config.io_threads = 2);
+    let client = server.client(None);
+    let threads: Vec<_> = (0..4)
+        .map(|_| {
+            let mut con = client.get_connection().unwrap();
+            thread::spawn(move || {
+                for _ in 0..100 {
+                    con.incr::<_, _, i64>("counter", 1).unwrap();
+                }
+            })
+        })
+        .collect();
+    for thread in threads {
+        thread.join().unwrap();
+    }
+    let mut con = server.connection();
+    assert_eq!(con.get::<_, i64>("counter").unwrap(), 400);
+
+    // A reply bigger than a socket takes at once is written out in pieces.
+    let fields: Vec<(String, String)> = (0..5000)
+        .map(|i| (format!("field{i}"), "x".repeat(100)))
+        .collect();
+    redis::cmd("HSET")
+        .arg("big")
+        .arg(&fields)
+        .query::<()>(&mut con)
+        .unwrap();
+    let all: HashMap<String, String> = con.hgetall("big").unwrap();
+    assert_eq!(all, fields.into_iter().collect());
+}
+
+#[test]
+fn test_io_threads_input_limit() {
+    let server = TestServer::with_config(|config| {
+        config.io_threads = 2;
+        config.proto_max_bulk_len = 1000;
+    });
+    // A command too long to ever be parsed is cut off once it is this long. Sending exactly
+    // that much means the connection is closed with nothing left unread.
+    let max_input = 1000 + MAX_INPUT_SLACK;
+    let mut input = b"*100000\r\n".to_vec();
+    while input.len() < max_input {
+        input.extend_from_slice(format!("$1000\r\n{}\r\n", "x".repeat(1000)).as_bytes());
+    }
+    input.truncate(max_input);
+
+    let mut stream = TcpStream::connect(server.addr).unwrap();
+    stream
+        .set_read_timeout(Some(Duration::from_secs(5)))
+        .unwrap();
+    let mut writer = stream.try_clone().unwrap();
+    let sender = thread::spawn(move || writer.write_all(&input).unwrap());
+    let mut output = String::new();
+    stream.read_to_string(&mut output).unwrap();
+    sender.join().unwrap();
+    assert_eq!(output, "-ERR Protocol error: too big request\r\n");
+}
+
+#[test]
+fn test_closing_connections() {
+    let test_cases = [
+        (
+            "QUIT",
+            "*1\r\n$4\r\nPING\r\n*1\r\n$4\r\nQUIT\r\n*1\r\n$4\r\nPING\r\n".to_string(),
+            "+PONG\r\n+OK\r\n",
+        ),
+        (
+            "Protocol error",
+            "*1\r\n$4\r\nPING\r\n?bad\r\n".to_string(),
+            "+PONG\r\n-ERR Protocol error: invalid RESP type\r\n",
+        ),
+        (
+            "Aggregate too long",
+            "*9223372036854775807\r\n*9223372036854775807\r\n*9223372036854775807\r\n".to_string(),
+            "-ERR Protocol error: invalid multibulk length\r\n",
+        ),
+        (
+            // Exactly as long as a line may get, so it is all read before the error.
+            "Count line too long",
+            format!("*{}", "1".repeat(64 * 1024 - 1)),
+            "-ERR Protocol error: too big mbulk count string\r\n",
+        ),
+    ];
+
+    for io_threads in [1, 2] {
+        let server = TestServer::with_config(|config| config.io_threads = io_threads);
+        for (name, input, expected) in &test_cases {
+            let mut stream = TcpStream::connect(server.addr).unwrap();
+            stream
+                .set_read_timeout(Some(Duration::from_secs(5)))
+                .unwrap();
+            stream.write_all(input.as_bytes()).unwrap();
+            // Everything is written before the connection is closed.
+            let mut output = String::new();
+            stream.read_to_string(&mut output).unwrap();
+            assert_eq!(output, *expected, "{} with io-threads {}", name, io_threads);
+        }
+        // No bad input takes the server down with it.
+        let pong: String = redis::cmd("PING").query(&mut server.connection()).unwrap();
+        assert_eq!(pong, "PONG", "io-threads {}", io_threads);
+    }
+}
+
+#[test]
+fn test_invalidation_reaches_idle_clients() {
+    for io_threads in [1, 4] {
+        let server = TestServer::with_config(|config| config.io_threads = io_threads);
+        let stream = TcpStream::connect(server.addr).unwrap();
+        stream
+            .set_read_timeout(Some(Duration::from_secs(5)))
+            .unwrap();
+        let mut replies = Resp::new(&stream);
+        for args in [
+            &["HELLO", "3"][..],
+            &["CLIENT", "TRACKING", "on"],
+            &["GET", "key"],
+        ] {
+            RespData::array(args.iter().copied())
+                .write(&mut &stream)
+                .unwrap();
+            replies.read().unwrap();
+        }
+
+        // The tracking client sends nothing more, yet hears about the write.
+        let _: () = server.connection().set("key", "value").unwrap();
+        assert_eq!(
+            replies.read().unwrap(),
+            RespData::Push(vec![RespData::bulk("invalidate"), RespData::array(["key"]),]),
+            "io-threads {io_threads}"
+        );
+    }
+}
+
+#[test]
+fn test_concurrent_clients() {
+    let server = TestServer::start();
+    let client = server.client(None);
+
+    let threads: Vec<_> = (0..8)
+        .map(|_| {
+            let mut con = client.get_connection().unwrap();
+            thread::spawn(move || {
+                for _ in 0..100 {
+                    con.incr::<_, _, i64>("counter", 1).unwrap();
+                }
+            })
+        })
+        .collect();
+    for thread in threads {
+        thread.join().unwrap();
+    }
+    assert_eq!(server.connection().get::<_, i64>("counter").unwrap(), 800);
+}
+
+#[test]
+fn test_auth() {
+    let server = TestServer::with_config(|config| config.requirepass = Some("secret".to_string()));
+
+    let mut con = server.connection();
+    let err = con.get::<_, Option<String>>("key").unwrap_err();
+    assert_eq!(err.code(), Some("NOAUTH"));
+
+    let Err(err) = server.client(Some("wrong")).get_connection() else {
+        panic!("connected with the wrong password");
+    };
+    assert_eq!(err.kind(), ErrorKind::AuthenticationFailed);
+
+    let mut con = server.client(Some("secret")).get_connection().unwrap();
+    let _: () = con.set("key", "value").unwrap();
+    assert_eq!(con.get::<_, String>("key").unwrap(), "value");
+}
+
+#[test]
+fn test_servers_are_isolated() {
+    let first = TestServer::start();
+    let second = TestServer::start();
+    assert_ne!(first.addr, second.addr);
+
+    let _: () = first.connection().set("key", "first").unwrap();
+    assert_eq!(
+        second.connection().get::<_, Option<String>>("key").unwrap(),
+        None
+    );
+}
+
+#[test]
+fn test_metrics() {
+    let server = TestServer::with_config(|config| config.metrics_port = free_port());
+    let mut con = server.connection();
+    let _: () = con.set("key", "value").unwrap();
+    let _: Option<String> = con.get("missing").unwrap();
+
+    let scrape = |request: &str| {
+        let mut stream = TcpStream::connect(server.metrics_addr.unwrap()).unwrap();
+        stream.write_all(request.as_bytes()).unwrap();
+        let mut response = String::new();
+        stream.read_to_string(&mut response).unwrap();
+        response
+    };
+    let response = scrape("GET /metrics HTTP/1.1\r\nHost: localhost\r\n\r\n");
+    let (head, body) = response.split_once("\r\n\r\n").unwrap();
+    assert!(head.starts_with("HTTP/1.1 200 OK\r\n"), "{head}");
+    assert!(
+        head.contains(&format!("Content-Length: {}", body.len())),
+        "{head}"
+    );
+    for line in [
+        "redis_connected_clients 1",
+        "redis_keys 1",
+        "redis_keyspace_misses_total 1",
+        "redis_commands_total{cmd=\"set\"} 1",
+    ] {
+        assert!(
+            body.lines().any(|l| l == line),
+            "{line} missing from\n{body}"
+        );
+    }
+
+    let response = scrape("GET /healthz HTTP/1.1\r\n\r\n");
+    assert!(response.starts_with("HTTP/1.1 200 OK\r\n"), "{response}");
+    assert!(response.ends_with("\r\n\r\nOK\n"), "{response}");
+
+    let response = scrape("GET /info HTTP/1.1\r\n\r\n");
+    assert!(
+        response.contains("Content-Type: application/json\r\n"),
+        "{response}"
+    );
+    assert!(
+        response.contains("\"db0\":\"keys=1,expires=0,avg_ttl=0\""),
+        "{response}"
+    );
+
+    let response = scrape("GET / HTTP/1.1\r\n\r\n");
+    assert!(
+        response.starts_with("HTTP/1.1 404 Not Found\r\n"),
+        "{response}"
+    );
+}
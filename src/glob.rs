@@ -0,0 +1,121 @@
+//! Redis-style glob matching, as used by KEYS, SCAN MATCH and ACL patterns.
+
+/// Matches `string` against `pattern`, where `*` matches any sequence, `?` any single
+/// character, `[abc]`, `[^abc]` and `[a-z]` character classes, and `\` escapes the next
+/// character.
+pub fn glob_match(pattern: &str, string: &str) -> bool {
+    let pattern: Vec<char> = pattern.chars().collect();
+    let string: Vec<char> = string.chars().collect();
+    match_from(&pattern, &string)
+}
+
+fn match_from(pattern: &[char], string: &[char]) -> bool {
+    let (mut p, mut s) = (0, 0);
+    while p < pattern.len() {
+        match pattern[p] {
+            '*' => {
+                while p + 1 < pattern.len() && pattern[p + 1] == '*' {
+                    p += 1;
+                }
+                if p + 1 == pattern.len() {
+                    return true;
+                }
+                return (s..=string.len())
+                    .any(|start| match_from(&pattern[p + 1..], &string[start..]));
+            }
+            '?' => {
+                if s == string.len() {
+                    return false;
+                }
+                s += 1;
+            }
+            '[' => {
+                let Some(&c) = string.get(s) else {
+                    return false;
+                };
+                let (matched, next) = match_class(pattern, p + 1, c);
+                if !matched {
+                    return false;
+                }
+                p = next;
+                s += 1;
+                continue;
+            }
+            '\\' if p + 1 < pattern.len() => {
+                p += 1;
+                if string.get(s) != Some(&pattern[p]) {
+                    return false;
+                }
+                s += 1;
+            }
+            literal => {
+                if string.get(s) != Some(&literal) {
+                    return false;
+                }
+                s += 1;
+            }
+        }
+        p += 1;
+    }
+    s == string.len()
+}
+
+/// Matches `c` against the character class starting at `start` (just past the `[`). Returns
+/// whether it matched and the index just past the closing `]`.
+fn match_class(pattern: &[char], start: usize, c: char) -> (bool, usize) {
+    let mut p = start;
+    let negate = pattern.get(p) == Some(&'^');
+    if negate {
+        p += 1;
+    }
+    let mut matched = false;
+    while p < pattern.len() && pattern[p] != ']' {
+        if pattern[p] == '\\' && p + 1 < pattern.len() {
+            p += 1;
+            matched |= pattern[p] == c;
+        } else if p + 2 < pattern.len() && pattern[p + 1] == '-' && pattern[p + 2] != ']' {
+            let (low, high) = if pattern[p] <= pattern[p + 2] {
+                (pattern[p], pattern[p + 2])
+            } else {
+                (pattern[p + 2], pattern[p])
+            };
+            matched |= (low..=high).contains(&c);
+            p += 2;
+        } else {
+            matched |= pattern[p] == c;
+        }
+        p += 1;
+    }
+    (matched != negate, p + 1)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_glob_match() {
+        let test_cases = [
+            ("Exact match", "hello", "hello", true),
+            ("Exact mismatch", "hello", "hallo", false),
+            ("Star matches everything", "*", "anything", true),
+            ("Star matches empty", "user:*", "user:", true),
+            ("Star in the middle", "h*o", "hello", true),
+            ("Multiple stars", "*:*:id", "user:42:id", true),
+            ("Question mark", "h?llo", "hallo", true),
+            ("Question mark needs a character", "hello?", "hello", false),
+            ("Class", "h[ae]llo", "hello", true),
+            ("Class mismatch", "h[ae]llo", "hillo", false),
+            ("Negated class", "h[^e]llo", "hallo", true),
+            ("Negated class mismatch", "h[^e]llo", "hello", false),
+            ("Range", "key[0-9]", "key7", true),
+            ("Range mismatch", "key[0-9]", "keyx", false),
+            ("Escaped star", "a\\*b", "a*b", true),
+            ("Escaped star is literal", "a\\*b", "axb", false),
+        ];
+
+        for (name, pattern, string, expected) in test_cases {
+            assert_eq!(glob_match(pattern, string), expected, "{}", name);
+        }
+    }
+}
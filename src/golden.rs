@@ -0,0 +1,210 @@
+//! Golden transcript tests: every file in testdata/transcripts is a conversation with a fresh
+//! `TestServer`, and the server has to answer it byte for byte as recorded.
+//!
+//! `> args...` sends a command, with arguments split as in the compat scripts, and `>raw bytes`
+//! sends the bytes as they are. `< bytes` expects the next reply, and `< EOF` expects the
+//! server to close the connection. Bytes are escaped like in Rust strings: `\r`, `\n`, `\t`,
+//! `\\` and `\xNN`. Lines starting with `#` are comments.
+//!
+//! Run the tests with GOLDEN_RECORD=1 to rewrite every `<` line with what the server sends now,
+//! e.g. to fill in the empty `<` lines of a new transcript.
+
+use crate::compat::split_line;
+use crate::resp::{Resp, RespData};
+use crate::test_server::TestServer;
+use std::fmt::Write as _;
+use std::fs;
+use std::io::{self, Write};
+use std::net::TcpStream;
+use std::path::Path;
+
+const EOF: &str = "EOF";
+
+fn escape(bytes: &[u8]) -> String {
+    let mut out = String::new();
+    for &b in bytes {
+        match b {
+            b'\r' => out.push_str("\\r"),
+            b'\n' => out.push_str("\\n"),
+            b'\t' => out.push_str("\\t"),
+            b'\\' => out.push_str("\\\\"),
+            b' '..=b'~' => out.push(b as char),
+            b => write!(out, "\\x{b:02x}").unwrap(),
+        }
+    }
+    out
+}
+
+fn unescape(s: &str) -> Result<Vec<u8>, String> {
+    let mut out = Vec::new();
+    let mut chars = s.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            let mut buf = [0; 4];
+            out.extend(c.encode_utf8(&mut buf).as_bytes());
+            continue;
+        }
+        match chars.next() {
+            Some('r') => out.push(b'\r'),
+            Some('n') => out.push(b'\n'),
+            Some('t') => out.push(b'\t'),
+            Some('\\') => out.push(b'\\'),
+            Some('x') => {
+                let hex: String = chars.by_ref().take(2).collect();
+                let byte = u8::from_str_radix(&hex, 16)
+                    .map_err(|_| format!("invalid escape '\\x{hex}'"))?;
+                out.push(byte);
+            }
+            Some(c) => return Err(format!("invalid escape '\\{c}'")),
+            None => return Err("trailing backslash".to_string()),
+        }
+    }
+    Ok(out)
+}
+
+/// The bytes a `>` line sends.
+fn request(line: &str) -> Result<Vec<u8>, String> {
+    if let Some(raw) = line.strip_prefix(">raw ") {
+        return unescape(raw);
+    }
+    let args = split_line(&line[1..])
+        .iter()
+        .map(|arg| unescape(arg).map(|arg| String::from_utf8_lossy(&arg).into_owned()))
+        .collect::<Result<Vec<_>, _>>()?;
+    if args.is_empty() {
+        return Err("empty command".to_string());
+    }
+    let mut bytes = Vec::new();
+    RespData::Array(args.into_iter().map(RespData::BulkString).collect())
+        .write(&mut bytes)
+        .unwrap();
+    Ok(bytes)
+}
+
+/// The next reply, escaped, or EOF once the server has closed the connection.
+fn next_reply(reader: &mut Resp<TcpStream>) -> io::Result<String> {
+    match reader.read() {
+        Ok(_) => Ok(escape(reader.raw_data.as_bytes())),
+        Err(e)
+            if reader.raw_data.is_empty()
+                && matches!(
+                    e.kind(),
+                    io::ErrorKind::UnexpectedEof | io::ErrorKind::ConnectionReset
+                ) =>
+        {
+            Ok(EOF.to_string())
+        }
+        Err(e) => Err(e),
+    }
+}
+
+/// Replays `transcript` against a fresh server. Returns the transcript with its `<` lines
+/// replaced by the actual replies, and a message for every reply that didn't match.
+fn replay(transcript: &str) -> Result<(String, Vec<String>), String> {
+    let server = TestServer::start();
+    let stream = TcpStream::connect(server.addr).map_err(|e| e.to_string())?;
+    let mut reader = Resp::new(stream.try_clone().map_err(|e| e.to_string())?);
+    let mut writer = stream;
+
+    let mut recorded = String::new();
+    let mut mismatches = Vec::new();
+    for (i, line) in transcript.lines().enumerate() {
+        let line_error = |e: String| format!("line {}: {e}", i + 1);
+        if line.starts_with('>') {
+            let bytes = request(line).map_err(line_error)?;
+            // A server that has hung up may refuse the write; the next `<` expects EOF then.
+            let _ = writer.write_all(&bytes);
+        } else if let Some(expected) = line.strip_prefix('<') {
+            let expected = expected.trim_start();
+            let actual = next_reply(&mut reader).map_err(|e| line_error(e.to_string()))?;
+            if actual != expected {
+                mismatches.push(line_error(format!("expected {expected:?}, got {actual:?}")));
+            }
+            writeln!(recorded, "< {actual}").unwrap();
+            continue;
+        } else if !line.trim().is_empty() && !line.starts_with('#') {
+            return Err(line_error(format!("unrecognized line {line:?}")));
+        }
+        writeln!(recorded, "{line}").unwrap();
+    }
+    Ok((recorded, mismatches))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_escape() {
+        let test_cases = [
+            ("Printable", &b"+OK"[..], "+OK"),
+            ("Line endings", b"$1\r\na\r\n", "$1\\r\\na\\r\\n"),
+            ("Backslash", b"a\\b", "a\\\\b"),
+            (
+                "Binary and UTF-8",
+                b"\x00\xff\xc3\xa9",
+                "\\x00\\xff\\xc3\\xa9",
+            ),
+        ];
+
+        for (name, input, expected) in test_cases {
+            assert_eq!(escape(input), expected, "{}", name);
+            assert_eq!(unescape(expected).unwrap(), input, "{}", name);
+        }
+        assert_eq!(unescape("é").unwrap(), "é".as_bytes());
+        assert_eq!(unescape("\\q"), Err("invalid escape '\\q'".to_string()));
+        assert_eq!(unescape("\\xzz"), Err("invalid escape '\\xzz'".to_string()));
+    }
+
+    #[test]
+    fn test_request() {
+        let test_cases = [
+            (
+                "Command",
+                "> GET k",
+                Ok(&b"*2\r\n$3\r\nGET\r\n$1\r\nk\r\n"[..]),
+            ),
+            (
+                "Quoted and escaped",
+                "> ECHO \"a b\\r\\n\"",
+                Ok(b"*2\r\n$4\r\nECHO\r\n$5\r\na b\r\n\r\n"),
+            ),
+            ("Raw", ">raw PING\\r\\n", Ok(b"PING\r\n")),
+            ("Empty", ">", Err("empty command".to_string())),
+        ];
+
+        for (name, input, expected) in test_cases {
+            assert_eq!(request(input), expected.map(<[u8]>::to_vec), "{}", name);
+        }
+    }
+
+    #[test]
+    fn test_transcripts() {
+        let dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("testdata/transcripts");
+        let record = std::env::var_os("GOLDEN_RECORD").is_some();
+        let mut paths: Vec<_> = fs::read_dir(&dir)
+            .unwrap()
+            .map(|entry| entry.unwrap().path())
+            .collect();
+        paths.sort();
+        assert!(!paths.is_empty(), "no transcripts in {}", dir.display());
+
+        let mut failures = Vec::new();
+        for path in paths {
+            let transcript = fs::read_to_string(&path).unwrap();
+            let name = path.file_name().unwrap().to_string_lossy().into_owned();
+            match replay(&transcript) {
+                Ok((recorded, _)) if record => fs::write(&path, recorded).unwrap(),
+                Ok((_, mismatches)) => {
+                    failures.extend(mismatches.into_iter().map(|m| format!("{name}: {m}")))
+                }
+                Err(e) => failures.push(format!("{name}: {e}")),
+            }
+        }
+        assert!(
+            failures.is_empty(),
+            "{}\nRun with GOLDEN_RECORD=1 if the new output is right.",
+            failures.join("\n")
+        );
+    }
+}
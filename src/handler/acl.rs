@@ -0,0 +1,928 @@
+use super::client::Client;
+use super::command_table::{self, CommandSpec, ACL_CATEGORIES};
+use super::errors;
+use super::CommandHandler;
+use crate::glob::glob_match;
+use crate::resp::RespData;
+use sha2::{Digest, Sha256};
+use std::collections::{BTreeMap, BTreeSet};
+use std::fs::{self, File};
+use std::io::{self, Write};
+use std::path::Path;
+use std::process;
+
+/// The keys a command will access, for checking them against the user's key patterns.
+fn command_keys<'a>(spec: &CommandSpec, args: &'a [RespData]) -> Vec<&'a str> {
+    if spec.first_key == 0 {
+        return vec![];
+    }
+    let count = if spec.variadic_keys { args.len() } else { 1 };
+    args.iter()
+        .skip(spec.first_key)
+        .take(count)
+        .filter_map(|arg| match arg {
+            RespData::BulkString(key) => Some(key.as_str()),
+            _ => None,
+        })
+        .collect()
+}
+
+/// The keys of `args`, a complete call of the command `name`, looking into its subcommand
+/// if it has them.
+pub(super) fn keys_of<'a>(name: &str, args: &'a [RespData]) -> Vec<&'a str> {
+    let Some(spec) = command_table::lookup(name) else {
+        return vec![];
+    };
+    let spec = match args.get(1) {
+        Some(RespData::BulkString(sub)) => spec.subcommand(&sub.to_lowercase()).unwrap_or(spec),
+        _ => spec,
+    };
+    command_keys(spec, args)
+}
+
+/// Whether `name` is a known command, or a known `command|subcommand` pair.
+fn command_exists(name: &str) -> bool {
+    let mut parts = name.splitn(2, '|');
+    let Some(spec) = parts.next().and_then(command_table::lookup) else {
+        return false;
+    };
+    parts
+        .next()
+        .is_none_or(|sub| spec.subcommand(sub).is_some())
+}
+
+fn hash_password(password: &str) -> String {
+    Sha256::digest(password.as_bytes())
+        .iter()
+        .map(|byte| format!("{byte:02x}"))
+        .collect()
+}
+
+/// A user and the permissions granted to it by its ACL rules.
+#[derive(Clone)]
+pub struct User {
+    pub name: String,
+    enabled: bool,
+    nopass: bool,
+    /// SHA-256 hex digests of the accepted passwords.
+    passwords: BTreeSet<String>,
+    /// Whether commands are allowed unless a rule below says otherwise (`+@all` vs `-@all`).
+    all_commands: bool,
+    /// Per command or `command|subcommand` exceptions to `all_commands`.
+    command_rules: BTreeMap<String, bool>,
+    key_patterns: Vec<String>,
+    channel_patterns: Vec<String>,
+}
+
+impl User {
+    fn new(name: &str) -> Self {
+        Self {
+            name: name.to_string(),
+            enabled: false,
+            nopass: false,
+            passwords: BTreeSet::new(),
+            all_commands: false,
+            command_rules: BTreeMap::new(),
+            key_patterns: Vec::new(),
+            channel_patterns: Vec::new(),
+        }
+    }
+
+    /// The `default` user as it exists on startup: `on nopass ~* &* +@all`.
+    fn default_user() -> Self {
+        Self {
+            enabled: true,
+            nopass: true,
+            all_commands: true,
+            key_patterns: vec!["*".to_string()],
+            channel_patterns: vec!["*".to_string()],
+            ..Self::new("default")
+        }
+    }
+
+    /// Applies a single ACL SETUSER rule such as `on`, `>password`, `~key:*` or `+@read`.
+    fn apply_rule(&mut self, rule: &str) -> Result<(), &'static str> {
+        match rule.to_lowercase().as_str() {
+            "on" => self.enabled = true,
+            "off" => self.enabled = false,
+            "nopass" => {
+                self.nopass = true;
+                self.passwords.clear();
+            }
+            "resetpass" => {
+                self.nopass = false;
+                self.passwords.clear();
+            }
+            "allkeys" => self.key_patterns = vec!["*".to_string()],
+            "resetkeys" => self.key_patterns.clear(),
+            "allchannels" => self.channel_patterns = vec!["*".to_string()],
+            "resetchannels" => self.channel_patterns.clear(),
+            "allcommands" | "+@all" => self.set_all_commands(true),
+            "nocommands" | "-@all" => self.set_all_commands(false),
+            "reset" => *self = Self::new(&self.name),
+            _ => return self.apply_argument_rule(rule),
+        }
+        Ok(())
+    }
+
+    /// Rules that carry an argument after their first character.
+    fn apply_argument_rule(&mut self, rule: &str) -> Result<(), &'static str> {
+        let Some(op) = rule.chars().next() else {
+            return Err("Syntax error");
+        };
+        let arg = &rule[op.len_utf8()..];
+        match op {
+            '>' => {
+                self.passwords.insert(hash_password(arg));
+                self.nopass = false;
+            }
+            '<' => {
+                if !self.passwords.remove(&hash_password(arg)) {
+                    return Err("no such password");
+                }
+            }
+            '#' => {
+                if arg.len() != 64 || !arg.chars().all(|c| c.is_ascii_hexdigit()) {
+                    return Err("The password hash must be exactly 64 characters and contain only lowercase hexadecimal characters");
+                }
+                self.passwords.insert(arg.to_lowercase());
+                self.nopass = false;
+            }
+            '!' => {
+                if !self.passwords.remove(&arg.to_lowercase()) {
+                    return Err("no such password");
+                }
+            }
+            '~' => self.key_patterns.push(arg.to_string()),
+            '&' => self.channel_patterns.push(arg.to_string()),
+            '+' | '-' => {
+                let allow = op == '+';
+                let arg = arg.to_lowercase();
+                if let Some(category) = arg.strip_prefix('@') {
+                    if !ACL_CATEGORIES.contains(&category) {
+                        return Err("Unknown command or category name in ACL");
+                    }
+                    for command in command_table::commands_in_category(category) {
+                        self.set_command(&command, allow);
+                    }
+                } else {
+                    if !command_exists(&arg) {
+                        return Err("Unknown command or category name in ACL");
+                    }
+                    self.set_command(&arg, allow);
+                }
+            }
+            _ => return Err("Syntax error"),
+        }
+        Ok(())
+    }
+
+    fn set_all_commands(&mut self, allow: bool) {
+        self.all_commands = allow;
+        self.command_rules.clear();
+    }
+
+    /// Allows or denies a command, overriding any earlier rules for its subcommands.
+    fn set_command(&mut self, command: &str, allow: bool) {
+        if !command.contains('|') {
+            let prefix = format!("{command}|");
+            self.command_rules
+                .retain(|rule, _| !rule.starts_with(&prefix));
+        }
+        self.command_rules.insert(command.to_string(), allow);
+    }
+
+    fn check_password(&self, password: &str) -> bool {
+        self.nopass || self.passwords.contains(&hash_password(password))
+    }
+
+    /// Whether the user can connect without sending AUTH.
+    fn passwordless(&self) -> bool {
+        self.enabled && self.nopass
+    }
+
+    fn can_run(&self, command: &str, subcommand: Option<&str>) -> bool {
+        subcommand
+            .and_then(|sub| self.command_rules.get(&format!("{command}|{sub}")))
+            .or_else(|| self.command_rules.get(command))
+            .copied()
+            .unwrap_or(self.all_commands)
+    }
+
+    fn can_access_key(&self, key: &str) -> bool {
+        self.key_patterns
+            .iter()
+            .any(|pattern| glob_match(pattern, key))
+    }
+
+    fn describe_commands(&self) -> String {
+        let mut description = if self.all_commands { "+@all" } else { "-@all" }.to_string();
+        for (command, allow) in &self.command_rules {
+            description.push(' ');
+            description.push(if *allow { '+' } else { '-' });
+            description.push_str(command);
+        }
+        description
+    }
+
+    fn describe_keys(&self) -> String {
+        self.key_patterns
+            .iter()
+            .map(|pattern| format!("~{pattern}"))
+            .collect::<Vec<_>>()
+            .join(" ")
+    }
+
+    fn describe_channels(&self) -> String {
+        self.channel_patterns
+            .iter()
+            .map(|pattern| format!("&{pattern}"))
+            .collect::<Vec<_>>()
+            .join(" ")
+    }
+
+    /// The user as a line of ACL rules, in the ACL LIST format.
+    fn describe(&self) -> String {
+        let mut parts = vec![
+            "user".to_string(),
+            self.name.clone(),
+            if self.enabled { "on" } else { "off" }.to_string(),
+        ];
+        if self.nopass {
+            parts.push("nopass".to_string());
+        }
+        parts.extend(self.passwords.iter().map(|hash| format!("#{hash}")));
+        if !self.key_patterns.is_empty() {
+            parts.push(self.describe_keys());
+        }
+        if self.channel_patterns.is_empty() {
+            parts.push("resetchannels".to_string());
+        } else {
+            parts.push(self.describe_channels());
+        }
+        parts.push(self.describe_commands());
+        parts.join(" ")
+    }
+}
+
+/// Parses an ACL file, one `user <name> <rules>...` line per user as ACL LIST writes them.
+/// Every line is checked, so the error lists all of the file's problems and not just the first.
+fn parse_acl_file(path: &Path, contents: &str) -> Result<BTreeMap<String, User>, String> {
+    let mut users = BTreeMap::new();
+    let mut errors = Vec::new();
+    for (number, line) in contents.lines().enumerate() {
+        let mut error = |reason: String| {
+            errors.push(format!("{}:{}: {reason}", path.display(), number + 1));
+        };
+        let words: Vec<&str> = line.split_whitespace().collect();
+        let (name, rules) = match words.as_slice() {
+            [] => continue,
+            ["user", name, rules @ ..] => (*name, rules),
+            _ => {
+                error("should start with user keyword followed by the username".to_string());
+                continue;
+            }
+        };
+        if users.contains_key(name) {
+            error(format!("Duplicate user '{name}' found"));
+            continue;
+        }
+        let mut user = User::new(name);
+        for rule in rules {
+            if let Err(reason) = user.apply_rule(rule) {
+                error(format!("Error in ACL modifier '{rule}': {reason}"));
+            }
+        }
+        users.insert(name.to_string(), user);
+    }
+    if errors.is_empty() {
+        Ok(users)
+    } else {
+        Err(errors.join(". "))
+    }
+}
+
+/// The set of users known to the server.
+pub struct Acl {
+    users: BTreeMap<String, User>,
+    /// The `requirepass` value last applied to the default user.
+    requirepass: Option<String>,
+}
+
+impl Default for Acl {
+    fn default() -> Self {
+        let mut users = BTreeMap::new();
+        users.insert("default".to_string(), User::default_user());
+        Self {
+            users,
+            requirepass: None,
+        }
+    }
+}
+
+impl Acl {
+    /// Checks a username/password pair, returning whether the user may log in.
+    pub fn authenticate(&self, username: &str, password: &str) -> bool {
+        self.users
+            .get(username)
+            .is_some_and(|user| user.enabled && user.check_password(password))
+    }
+}
+
+impl CommandHandler {
+    /// Keeps the default user's password in line with `requirepass`, which can change when the
+    /// configuration is reloaded.
+    pub(super) fn sync_requirepass(&mut self) {
+        let requirepass = self.config.read().unwrap().requirepass.clone();
+        if requirepass == self.acl.requirepass {
+            return;
+        }
+        if let Some(user) = self.acl.users.get_mut("default") {
+            let rule = match &requirepass {
+                Some(password) => format!(">{password}"),
+                None => "nopass".to_string(),
+            };
+            user.passwords.clear();
+            let _ = user.apply_rule(&rule);
+        }
+        self.acl.requirepass = requirepass;
+    }
+
+    /// Whether connections that have not authenticated are logged in as `default`.
+    pub(super) fn default_user_passwordless(&self) -> bool {
+        self.acl
+            .users
+            .get("default")
+            .is_some_and(|user| user.passwordless())
+    }
+
+    /// Whether the client is logged in as a user that may run every @admin command.
+    pub(super) fn client_is_admin(&self, client: &Client) -> bool {
+        if !client.authenticated && !self.default_user_passwordless() {
+            return false;
+        }
+        let Some(user) = self.acl.users.get(&client.user) else {
+            return false;
+        };
+        command_table::commands_in_category("admin")
+            .iter()
+            .all(|command| {
+                let mut parts = command.splitn(2, '|');
+                let name = parts.next().unwrap_or_default();
+                user.can_run(name, parts.next())
+            })
+    }
+
+    /// Whether the client may keep a slot reserved for admins. It has to have logged in as an
+    /// admin with a password, or else every client could take one when no password is set.
+    pub(super) fn client_may_use_reserved_slot(&self, client: &Client) -> bool {
+        client.authenticated
+            && self
+                .acl
+                .users
+                .get(&client.user)
+                .is_some_and(|user| !user.passwordless())
+            && self.client_is_admin(client)
+    }
+
+    /// Checks the current client's permissions for a command, returning the NOPERM error to
+    /// reply with if it is not allowed. A client whose user no longer exists may run nothing.
+    pub(super) fn acl_denied(&self, name: &str, resp: &RespData) -> Option<RespData> {
+        let client = self.current_client()?;
+        let Some(user) = self.acl.users.get(&client.user) else {
+            return Some(RespData::Error(format!(
+                "-NOPERM User {} has no permissions to run the '{name}' command",
+                client.user
+            )));
+        };
+        let args = match resp {
+            RespData::Array(arr) => arr.as_slice(),
+            _ => &[],
+        };
+        let subcommand = match args.get(1) {
+            Some(RespData::BulkString(sub)) => Some(sub.to_lowercase()),
+            _ => None,
+        };
+
+        if !user.can_run(name, subcommand.as_deref()) {
+            return Some(RespData::Error(format!(
+                "-NOPERM User {} has no permissions to run the '{name}' command",
+                user.name
+            )));
+        }
+        if keys_of(name, args)
+            .iter()
+            .any(|key| !user.can_access_key(key))
+        {
+            return Some(RespData::Error(
+                "-NOPERM No permissions to access a key".to_string(),
+            ));
+        }
+        None
+    }
+
+    pub(super) fn acl(&mut self, resp: &RespData) -> RespData {
+        let RespData::Array(arr) = resp else {
+            return errors::syntax_error();
+        };
+        let Some(RespData::BulkString(subcommand)) = arr.get(1) else {
+            return errors::wrong_arity("acl");
+        };
+        let args: Vec<&str> = arr[2..]
+            .iter()
+            .filter_map(|arg| match arg {
+                RespData::BulkString(arg) => Some(arg.as_str()),
+                _ => None,
+            })
+            .collect();
+        let wrong_arity = || errors::wrong_arity(&format!("acl|{}", subcommand.to_lowercase()));
+
+        match (subcommand.to_uppercase().as_str(), args.as_slice()) {
+            ("SETUSER", [username, rules @ ..]) => self.acl_setuser(username, rules),
+            ("GETUSER", [username]) => self.acl_getuser(username),
+            ("DELUSER", usernames) if !usernames.is_empty() => self.acl_deluser(usernames),
+            ("LIST", []) => RespData::Array(
+                self.acl
+                    .users
+                    .values()
+                    .map(|user| RespData::BulkString(user.describe()))
+                    .collect(),
+            ),
+            ("USERS", []) => RespData::Array(
+                self.acl
+                    .users
+                    .keys()
+                    .map(|name| RespData::BulkString(name.clone()))
+                    .collect(),
+            ),
+            ("WHOAMI", []) => RespData::BulkString(
+                self.current_client()
+                    .map_or("default", |client| client.user.as_str())
+                    .to_string(),
+            ),
+            ("LOAD", []) => self.acl_file_command(true),
+            ("SAVE", []) => self.acl_file_command(false),
+            ("CAT", []) => RespData::Array(
+                ACL_CATEGORIES
+                    .iter()
+                    .map(|name| RespData::BulkString(name.to_string()))
+                    .collect(),
+            ),
+            ("CAT", [category]) => {
+                let category = category.to_lowercase();
+                if !ACL_CATEGORIES.contains(&category.as_str()) {
+                    return RespData::Error(format!("Unknown category '{category}'"));
+                }
+                RespData::Array(
+                    command_table::commands_in_category(&category)
+                        .into_iter()
+                        .map(RespData::BulkString)
+                        .collect(),
+                )
+            }
+            (
+                "SETUSER" | "GETUSER" | "DELUSER" | "LIST" | "USERS" | "WHOAMI" | "LOAD" | "SAVE"
+                | "CAT",
+                _,
+            ) => wrong_arity(),
+            _ => errors::unknown_subcommand(subcommand, "acl"),
+        }
+    }
+
+    /// Applies all rules to a copy of the user so a bad rule leaves it unchanged.
+    fn acl_setuser(&mut self, username: &str, rules: &[&str]) -> RespData {
+        let mut user = self
+            .acl
+            .users
+            .get(username)
+            .cloned()
+            .unwrap_or_else(|| User::new(username));
+        for rule in rules {
+            if let Err(reason) = user.apply_rule(rule) {
+                return RespData::Error(format!(
+                    "Error in ACL SETUSER modifier '{rule}': {reason}"
+                ));
+            }
+        }
+        self.acl.users.insert(username.to_string(), user);
+        RespData::SimpleString("OK".to_string())
+    }
+
+    fn acl_getuser(&self, username: &str) -> RespData {
+        let Some(user) = self.acl.users.get(username) else {
+            return RespData::Null;
+        };
+        let mut flags = vec![RespData::BulkString(
+            if user.enabled { "on" } else { "off" }.to_string(),
+        )];
+        if user.nopass {
+            flags.push(RespData::BulkString("nopass".to_string()));
+        }
+        RespData::Array(vec![
+            RespData::BulkString("flags".to_string()),
+            RespData::Array(flags),
+            RespData::BulkString("passwords".to_string()),
+            RespData::Array(
+                user.passwords
+                    .iter()
+                    .map(|hash| RespData::BulkString(hash.clone()))
+                    .collect(),
+            ),
+            RespData::BulkString("commands".to_string()),
+            RespData::BulkString(user.describe_commands()),
+            RespData::BulkString("keys".to_string()),
+            RespData::BulkString(user.describe_keys()),
+            RespData::BulkString("channels".to_string()),
+            RespData::BulkString(user.describe_channels()),
+            RespData::BulkString("selectors".to_string()),
+            RespData::Array(vec![]),
+        ])
+    }
+
+    /// Replaces the users with the ones in `aclfile`, if it is set. A file with any error
+    /// leaves the users as they were. Clients logged in as a user the file doesn't define are
+    /// disconnected.
+    pub fn load_acl_file(&mut self) -> Result<(), String> {
+        let (path, requirepass) = {
+            let config = self.config.read().unwrap();
+            (config.aclfile.clone(), config.requirepass.clone())
+        };
+        let Some(path) = path else {
+            return Ok(());
+        };
+        let contents = fs::read_to_string(&path)
+            .map_err(|e| format!("Error loading ACLs, opening file '{}': {e}", path.display()))?;
+        let mut users = parse_acl_file(&path, &contents)?;
+        // A default user defined in the file takes precedence over requirepass. Otherwise it
+        // starts out as on startup, and requirepass is applied to it again.
+        self.acl.requirepass = if users.contains_key("default") {
+            requirepass
+        } else {
+            users.insert("default".to_string(), User::default_user());
+            None
+        };
+        let removed: BTreeSet<String> = self
+            .acl
+            .users
+            .keys()
+            .filter(|name| !users.contains_key(*name))
+            .cloned()
+            .collect();
+        self.acl.users = users;
+        self.kill_clients(|client| removed.contains(&client.user));
+        Ok(())
+    }
+
+    /// Writes the users to `aclfile`, going through a temporary file so a failed save never
+    /// leaves it half written.
+    fn save_acl_file(&self, path: &Path) -> io::Result<()> {
+        let tmp = path.with_file_name(format!("temp-{}.acl", process::id()));
+        let result = File::create(&tmp)
+            .and_then(|mut file| {
+                for user in self.acl.users.values() {
+                    writeln!(file, "{}", user.describe())?;
+                }
+                file.sync_all()
+            })
+            .and_then(|()| fs::rename(&tmp, path));
+        if result.is_err() {
+            let _ = fs::remove_file(&tmp);
+        }
+        result
+    }
+
+    /// ACL LOAD and ACL SAVE, which need `aclfile` to be set.
+    fn acl_file_command(&mut self, load: bool) -> RespData {
+        let Some(path) = self.config.read().unwrap().aclfile.clone() else {
+            return RespData::Error(
+                "This Redis instance is not configured to use an ACL file. You may want to specify users via the ACL SETUSER command and then issue a CONFIG REWRITE (assuming you have a Redis configuration file set) in order to store users in the Redis configuration.".to_string(),
+            );
+        };
+        if load {
+            if let Err(e) = self.load_acl_file() {
+                return RespData::Error(e);
+            }
+        } else if let Err(e) = self.save_acl_file(&path) {
+            log_warning!("Saving ACLs to {} failed: {e}", path.display());
+            return RespData::Error(
+                "There was an error trying to save the ACLs. Please check the server logs for more information".to_string(),
+            );
+        }
+        RespData::SimpleString("OK".to_string())
+    }
+
+    /// Deletes users and disconnects every client authenticated as one of them.
+    fn acl_deluser(&mut self, usernames: &[&str]) -> RespData {
+        if usernames.contains(&"default") {
+            return RespData::Error("The 'default' user cannot be removed".to_string());
+        }
+        let mut deleted = 0;
+        for username in usernames {
+            if self.acl.users.remove(*username).is_some() {
+                deleted += 1;
+                self.kill_clients(|client| client.user == *username);
+            }
+        }
+        RespData::Integer(deleted)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::Config;
+    use std::collections::HashMap;
+    use std::sync::{Arc, RwLock};
+
+    fn command(args: &[&str]) -> RespData {
+        RespData::array(args.iter().copied())
+    }
+
+    fn create_handler_with_client() -> (CommandHandler, u64) {
+        let mut handler = CommandHandler::from(HashMap::new());
+        let id = handler.register_client(
+            "127.0.0.1:5001".parse().unwrap(),
+            "127.0.0.1:6379".parse().unwrap(),
+            7,
+            None,
+        );
+        (handler, id)
+    }
+
+    #[test]
+    fn test_acl_setuser_and_getuser() {
+        let (mut handler, _) = create_handler_with_client();
+
+        let test_cases = [
+            (
+                "Create user with rules",
+                command(&[
+                    "ACL", "SETUSER", "alice", "on", ">p1", ">p2", "~cache:*", "&news", "+@read",
+                    "-hgetall",
+                ]),
+                RespData::SimpleString("OK".to_string()),
+            ),
+            (
+                "Remove a password",
+                command(&["ACL", "SETUSER", "alice", "<p2"]),
+                RespData::SimpleString("OK".to_string()),
+            ),
+            (
+                "Remove a missing password",
+                command(&["ACL", "SETUSER", "alice", "<p2"]),
+                RespData::Error("Error in ACL SETUSER modifier '<p2': no such password".to_string()),
+            ),
+            (
+                "Unknown command",
+                command(&["ACL", "SETUSER", "alice", "+nope"]),
+                RespData::Error(
+                    "Error in ACL SETUSER modifier '+nope': Unknown command or category name in ACL"
+                        .to_string(),
+                ),
+            ),
+            (
+                "Unknown rule",
+                command(&["ACL", "SETUSER", "alice", "sometimes"]),
+                RespData::Error(
+                    "Error in ACL SETUSER modifier 'sometimes': Syntax error".to_string(),
+                ),
+            ),
+            (
+                "Missing user",
+                command(&["ACL", "GETUSER", "nobody"]),
+                RespData::Null,
+            ),
+            (
+                "Delete default user",
+                command(&["ACL", "DELUSER", "default"]),
+                RespData::Error("The 'default' user cannot be removed".to_string()),
+            ),
+            (
+                "Unknown subcommand",
+                command(&["ACL", "NOPE"]),
+                RespData::Error("unknown subcommand 'NOPE'. Try ACL HELP.".to_string()),
+            ),
+        ];
+
+        for (name, input, expected) in test_cases {
+            assert_eq!(handler.acl(&input), expected, "{}", name);
+        }
+
+        let RespData::Array(user) = handler.acl(&command(&["ACL", "GETUSER", "alice"])) else {
+            panic!("GETUSER did not return an array");
+        };
+        assert_eq!(
+            user[1],
+            RespData::Array(vec![RespData::BulkString("on".to_string())])
+        );
+        assert_eq!(
+            user[3],
+            RespData::Array(vec![RespData::BulkString(hash_password("p1"))])
+        );
+        assert_eq!(
+            user[5],
+            RespData::BulkString(
+                "-@all +bf.exists +bf.mexists +cms.query +ft.search +get +getrange +hget -hgetall +hlen +json.get +json.type +lolwut +memory|usage +object|encoding +object|freq +object|idletime +object|refcount +pttl +scan +strlen +topk.list +topk.query +ts.mrange +ts.range +ttl +type"
+                    .to_string()
+            )
+        );
+        assert_eq!(user[7], RespData::BulkString("~cache:*".to_string()));
+        assert_eq!(user[9], RespData::BulkString("&news".to_string()));
+
+        assert_eq!(
+            handler.acl(&command(&["ACL", "LIST"])),
+            RespData::Array(vec![
+                RespData::BulkString(format!(
+                    "user alice on #{} ~cache:* &news -@all +bf.exists +bf.mexists +cms.query +ft.search +get +getrange +hget -hgetall +hlen +json.get +json.type +lolwut +memory|usage +object|encoding +object|freq +object|idletime +object|refcount +pttl +scan +strlen +topk.list +topk.query +ts.mrange +ts.range +ttl +type",
+                    hash_password("p1")
+                )),
+                RespData::BulkString("user default on nopass ~* &* +@all".to_string()),
+            ])
+        );
+        assert_eq!(
+            handler.acl(&command(&["ACL", "DELUSER", "alice", "bob"])),
+            RespData::Integer(1)
+        );
+    }
+
+    #[test]
+    fn test_acl_enforcement() {
+        let (mut handler, id) = create_handler_with_client();
+        handler.handle_client(
+            id,
+            &command(&[
+                "ACL",
+                "SETUSER",
+                "reader",
+                "on",
+                ">secret",
+                "~cache:*",
+                "+@read",
+                "+del",
+                "+auth",
+                "+acl|whoami",
+            ]),
+        );
+
+        let test_cases = [
+            (
+                "Log in as the new user",
+                command(&["AUTH", "reader", "secret"]),
+                RespData::SimpleString("OK".to_string()),
+            ),
+            (
+                "Who am I",
+                command(&["ACL", "WHOAMI"]),
+                RespData::BulkString("reader".to_string()),
+            ),
+            (
+                "Allowed command and key",
+                command(&["GET", "cache:1"]),
+                RespData::Null,
+            ),
+            (
+                "Key outside the patterns",
+                command(&["GET", "secret:1"]),
+                RespData::Error("-NOPERM No permissions to access a key".to_string()),
+            ),
+            (
+                "Every key of a variadic command is checked",
+                command(&["DEL", "cache:1", "secret:1"]),
+                RespData::Error("-NOPERM No permissions to access a key".to_string()),
+            ),
+            (
+                "Command outside the rules",
+                command(&["SET", "cache:1", "value"]),
+                RespData::Error(
+                    "-NOPERM User reader has no permissions to run the 'set' command".to_string(),
+                ),
+            ),
+            (
+                "Subcommand outside the rules",
+                command(&["ACL", "LIST"]),
+                RespData::Error(
+                    "-NOPERM User reader has no permissions to run the 'acl' command".to_string(),
+                ),
+            ),
+            (
+                "Unknown user cannot log in",
+                command(&["AUTH", "nobody", "secret"]),
+                errors::wrong_pass(),
+            ),
+        ];
+
+        for (name, input, expected) in test_cases {
+            assert_eq!(
+                handler.handle_client(id, &input),
+                Some(expected),
+                "{}",
+                name
+            );
+        }
+
+        // A client left logged in as a user that is gone is denied everything.
+        handler.clients.get_mut(&id).unwrap().user = "ghost".to_string();
+        assert_eq!(
+            handler.handle_client(id, &command(&["GET", "cache:1"])),
+            Some(RespData::Error(
+                "-NOPERM User ghost has no permissions to run the 'get' command".to_string()
+            ))
+        );
+    }
+
+    #[test]
+    fn test_acl_file() {
+        let dir = std::env::temp_dir().join(format!("redis-aclfile-{}", process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("users.acl");
+        fs::write(&path, "user alice on nopass ~* +@all\n\nuser bob off\n").unwrap();
+        let config = Config {
+            aclfile: Some(path.clone()),
+            ..Config::default()
+        };
+        let mut handler = CommandHandler::new(HashMap::new(), Arc::new(RwLock::new(config)));
+        handler.load_acl_file().unwrap();
+        let ok = RespData::SimpleString("OK".to_string());
+
+        let test_cases = [
+            (
+                "Users from the file, and the default one",
+                command(&["ACL", "USERS"]),
+                RespData::array(["alice", "bob", "default"]),
+            ),
+            (
+                "New user",
+                command(&["ACL", "SETUSER", "carol", "on", "~cache:*", "+get"]),
+                ok.clone(),
+            ),
+            ("Save", command(&["ACL", "SAVE"]), ok.clone()),
+            ("Load what was saved", command(&["ACL", "LOAD"]), ok.clone()),
+            (
+                "Saved users are kept",
+                command(&["ACL", "USERS"]),
+                RespData::array(["alice", "bob", "carol", "default"]),
+            ),
+            (
+                "Too many arguments",
+                command(&["ACL", "LOAD", "now"]),
+                RespData::Error("wrong number of arguments for 'acl|load' command".to_string()),
+            ),
+        ];
+
+        for (name, input, expected) in test_cases {
+            assert_eq!(handler.handle(&input), expected, "{}", name);
+        }
+        let RespData::Array(list) = handler.handle(&command(&["ACL", "LIST"])) else {
+            panic!("ACL LIST did not return an array");
+        };
+        let saved = fs::read_to_string(&path).unwrap();
+        assert_eq!(saved.lines().map(RespData::bulk).collect::<Vec<_>>(), list);
+
+        // A file with errors is reported in full and changes nothing.
+        fs::write(&path, "user alice on +nosuchcmd\nuser alice\nalice on\n").unwrap();
+        let display = path.display();
+        assert_eq!(
+            handler.handle(&command(&["ACL", "LOAD"])),
+            RespData::Error(format!(
+                "{display}:1: Error in ACL modifier '+nosuchcmd': Unknown command or category name in ACL. \
+                 {display}:2: Duplicate user 'alice' found. \
+                 {display}:3: should start with user keyword followed by the username"
+            ))
+        );
+        assert_eq!(
+            handler.handle(&command(&["ACL", "USERS"])),
+            RespData::array(["alice", "bob", "carol", "default"])
+        );
+
+        let mut handler = CommandHandler::from(HashMap::new());
+        assert!(matches!(
+            handler.handle(&command(&["ACL", "SAVE"])),
+            RespData::Error(e) if e.starts_with("This Redis instance is not configured to use an ACL file.")
+        ));
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_acl_cat() {
+        let (mut handler, _) = create_handler_with_client();
+
+        let RespData::Array(categories) = handler.acl(&command(&["ACL", "CAT"])) else {
+            panic!("ACL CAT did not return an array");
+        };
+        assert_eq!(categories.len(), ACL_CATEGORIES.len());
+        assert_eq!(
+            handler.acl(&command(&["ACL", "CAT", "hash"])),
+            RespData::Array(vec![
+                RespData::BulkString("hset".to_string()),
+                RespData::BulkString("hget".to_string()),
+                RespData::BulkString("hlen".to_string()),
+                RespData::BulkString("hgetall".to_string()),
+            ])
+        );
+        assert_eq!(
+            handler.acl(&command(&["ACL", "CAT", "nope"])),
+            RespData::Error("Unknown category 'nope'".to_string())
+        );
+    }
+}
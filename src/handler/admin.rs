@@ -0,0 +1,230 @@
+use super::errors;
+use super::CommandHandler;
+use crate::config::PARAMETERS;
+use crate::glob::glob_match;
+use crate::logging;
+use crate::resp::RespData;
+
+impl CommandHandler {
+    pub(super) fn config(&mut self, resp: &RespData) -> RespData {
+        let RespData::Array(arr) = resp else {
+            return errors::syntax_error();
+        };
+
+        let Some(RespData::BulkString(subcommand)) = arr.get(1) else {
+            return errors::wrong_arity("config");
+        };
+
+        match subcommand.to_uppercase().as_str() {
+            "RESETSTAT" => {
+                if arr.len() != 2 {
+                    return errors::wrong_arity("config|resetstat");
+                }
+                self.stats.reset();
+                RespData::SimpleString("OK".to_string())
+            }
+            "GET" => self.config_get(&arr[2..]),
+            "SET" => self.config_set(&arr[2..]),
+            _ => errors::unknown_subcommand(subcommand, "config"),
+        }
+    }
+
+    /// CONFIG GET pattern [pattern ...]: the name and value of every parameter matching one
+    /// of the glob patterns.
+    fn config_get(&self, patterns: &[RespData]) -> RespData {
+        if patterns.is_empty() {
+            return errors::wrong_arity("config|get");
+        }
+        let mut patterns_lower = Vec::with_capacity(patterns.len());
+        for pattern in patterns {
+            let RespData::BulkString(pattern) = pattern else {
+                return errors::syntax_error();
+            };
+            patterns_lower.push(pattern.to_lowercase());
+        }
+
+        let config = self.config.read().unwrap();
+        let mut reply = Vec::new();
+        for name in PARAMETERS {
+            if patterns_lower
+                .iter()
+                .any(|pattern| glob_match(pattern, name))
+            {
+                reply.push(RespData::BulkString(name.to_string()));
+                reply.push(RespData::BulkString(config.get(name).unwrap_or_default()));
+            }
+        }
+        RespData::Array(reply)
+    }
+
+    /// CONFIG SET parameter value [parameter value ...]. Either every parameter is changed or,
+    /// if any of them is rejected, none are.
+    fn config_set(&mut self, args: &[RespData]) -> RespData {
+        if args.is_empty() || !args.len().is_multiple_of(2) {
+            return errors::wrong_arity("config|set");
+        }
+        let mut config = self.config.read().unwrap().clone();
+        for pair in args.chunks_exact(2) {
+            let (RespData::BulkString(name), RespData::BulkString(value)) = (&pair[0], &pair[1])
+            else {
+                return errors::syntax_error();
+            };
+            let name = name.to_lowercase();
+            if !PARAMETERS.contains(&name.as_str()) {
+                return RespData::Error(format!(
+                    "Unknown option or number of arguments for CONFIG SET - '{name}'"
+                ));
+            }
+            if let Err(message) = config.set(&name, value) {
+                return RespData::Error(format!(
+                    "CONFIG SET failed (possibly related to argument '{name}') - {message}"
+                ));
+            }
+        }
+        logging::set_level(config.loglevel);
+        *self.config.write().unwrap() = config;
+        RespData::SimpleString("OK".to_string())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+
+    #[test]
+    fn test_config_resetstat() {
+        let mut handler = CommandHandler::from(HashMap::new());
+        handler.handle(&RespData::Array(vec![RespData::BulkString(
+            "PING".to_string(),
+        )]));
+        handler.handle(&RespData::Array(vec![RespData::BulkString(
+            "NOPE".to_string(),
+        )]));
+        assert_eq!(handler.stats.commands["ping"].calls, 1);
+        assert_eq!(handler.stats.errors["ERR"], 1);
+
+        let test_cases = [
+            (
+                "Valid CONFIG RESETSTAT",
+                vec!["CONFIG", "RESETSTAT"],
+                RespData::SimpleString("OK".to_string()),
+            ),
+            (
+                "Too many arguments",
+                vec!["CONFIG", "RESETSTAT", "extra"],
+                RespData::Error(
+                    "wrong number of arguments for 'config|resetstat' command".to_string(),
+                ),
+            ),
+            (
+                "Unknown subcommand",
+                vec!["CONFIG", "NOPE"],
+                RespData::Error("unknown subcommand 'NOPE'. Try CONFIG HELP.".to_string()),
+            ),
+        ];
+
+        for (name, args, expected) in test_cases {
+            let input = RespData::array(args);
+            assert_eq!(handler.config(&input), expected, "{}", name);
+        }
+        assert!(handler.stats.commands.is_empty());
+        assert!(handler.stats.errors.is_empty());
+    }
+
+    #[test]
+    fn test_config_get_and_set() {
+        let mut handler = CommandHandler::from(HashMap::new());
+        let bulk = |s: &str| RespData::BulkString(s.to_string());
+
+        let test_cases = [
+            (
+                "Get a single parameter",
+                vec!["CONFIG", "GET", "maxmemory-policy"],
+                RespData::Array(vec![bulk("maxmemory-policy"), bulk("noeviction")]),
+            ),
+            (
+                "Get with a glob pattern",
+                vec!["CONFIG", "GET", "lfu-*"],
+                RespData::Array(vec![
+                    bulk("lfu-log-factor"),
+                    bulk("10"),
+                    bulk("lfu-decay-time"),
+                    bulk("1"),
+                ]),
+            ),
+            (
+                "Unknown parameter",
+                vec!["CONFIG", "GET", "nope"],
+                RespData::Array(vec![]),
+            ),
+            (
+                "Set several parameters",
+                vec![
+                    "CONFIG",
+                    "SET",
+                    "maxmemory-policy",
+                    "allkeys-lfu",
+                    "MAXMEMORY",
+                    "1000",
+                ],
+                RespData::SimpleString("OK".to_string()),
+            ),
+            (
+                "Get the new values",
+                vec!["CONFIG", "GET", "maxmemory", "maxmemory-policy"],
+                RespData::Array(vec![
+                    bulk("maxmemory"),
+                    bulk("1000"),
+                    bulk("maxmemory-policy"),
+                    bulk("allkeys-lfu"),
+                ]),
+            ),
+            (
+                "Invalid value",
+                vec!["CONFIG", "SET", "maxmemory-policy", "sometimes"],
+                RespData::Error(
+                    "CONFIG SET failed (possibly related to argument 'maxmemory-policy') - \
+                     Invalid maxmemory policy"
+                        .to_string(),
+                ),
+            ),
+            (
+                "Nothing changes if one parameter is invalid",
+                vec!["CONFIG", "SET", "maxmemory", "5", "maxmemory-samples", "0"],
+                RespData::Error(
+                    "CONFIG SET failed (possibly related to argument 'maxmemory-samples') - \
+                     Invalid maxmemory-samples value"
+                        .to_string(),
+                ),
+            ),
+            (
+                "Immutable parameter",
+                vec!["CONFIG", "SET", "port", "7000"],
+                RespData::Error(
+                    "CONFIG SET failed (possibly related to argument 'port') - \
+                     can't set immutable config"
+                        .to_string(),
+                ),
+            ),
+            (
+                "Unknown parameter",
+                vec!["CONFIG", "SET", "nope", "1"],
+                RespData::Error(
+                    "Unknown option or number of arguments for CONFIG SET - 'nope'".to_string(),
+                ),
+            ),
+            (
+                "Missing value",
+                vec!["CONFIG", "SET", "maxmemory"],
+                RespData::Error("wrong number of arguments for 'config|set' command".to_string()),
+            ),
+        ];
+
+        for (name, args, expected) in test_cases {
+            let input = RespData::Array(args.into_iter().map(bulk).collect());
+            assert_eq!(handler.config(&input), expected, "{}", name);
+        }
+        assert_eq!(handler.config.read().unwrap().maxmemory, 1000);
+    }
+}
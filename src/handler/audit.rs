@@ -0,0 +1,198 @@
+//! The audit log: one line per write command, with when it ran, which client and ACL user sent
+//! it, the keys it touched and how it ended. Enabled by `audit-log`, for deployments that must
+//! be able to tell who changed what.
+
+use super::acl;
+use super::stats::error_prefix;
+use super::CommandHandler;
+use crate::resp::RespData;
+use std::fmt::Write as _;
+use std::fs::OpenOptions;
+use std::io::{self, Write};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+pub struct AuditLog {
+    out: Box<dyn Write + Send>,
+}
+
+impl CommandHandler {
+    /// Opens the file configured with `audit-log`, if any, appending to what is there.
+    pub fn open_audit_log(&mut self) -> io::Result<()> {
+        let Some(path) = self.config.read().unwrap().audit_log.clone() else {
+            return Ok(());
+        };
+        let file = OpenOptions::new().create(true).append(true).open(path)?;
+        self.audit_log = Some(AuditLog {
+            out: Box::new(file),
+        });
+        Ok(())
+    }
+
+    /// Records a write command that ran, with its `reply`.
+    pub(super) fn audit(&mut self, name: &str, resp: &RespData, reply: &RespData) {
+        let Some(audit_log) = &mut self.audit_log else {
+            return;
+        };
+        let (client, user) = match self.current_client.and_then(|id| self.clients.get(&id)) {
+            Some(client) => (client.addr.to_string(), client.user.as_str()),
+            None => ("-".to_string(), "-"),
+        };
+        let keys = match resp {
+            RespData::Array(args) => acl::keys_of(name, args),
+            _ => vec![],
+        };
+        let result = error_prefix(reply).unwrap_or_else(|| "OK".to_string());
+        let line = format_entry(SystemTime::now(), &client, user, name, &keys, &result);
+        // A full disk shouldn't stop the server, but it mustn't go unnoticed either.
+        if let Err(e) = audit_log.out.write_all(line.as_bytes()) {
+            log_warning!("Failed to write to the audit log: {e}");
+        }
+    }
+}
+
+/// An entry like `2026-10-16T09:41:07.123Z client=127.0.0.1:50000 user="default" cmd=set
+/// keys=["a"] result=OK`. The user and keys are quoted and escaped, since clients choose them.
+fn format_entry(
+    time: SystemTime,
+    client: &str,
+    user: &str,
+    name: &str,
+    keys: &[&str],
+    result: &str,
+) -> String {
+    let mut line = timestamp(time);
+    write!(
+        line,
+        " client={client} user={user:?} cmd={name} keys={keys:?} result={result}"
+    )
+    .unwrap();
+    line.push('\n');
+    line
+}
+
+/// `time` in UTC, in RFC 3339 format with milliseconds.
+fn timestamp(time: SystemTime) -> String {
+    let since_epoch = time.duration_since(UNIX_EPOCH).unwrap_or_default();
+    let seconds = since_epoch.as_secs() as libc::time_t;
+    // SAFETY: gmtime_r only writes to the tm it is given.
+    let tm = unsafe {
+        let mut tm = std::mem::zeroed::<libc::tm>();
+        libc::gmtime_r(&seconds, &mut tm);
+        tm
+    };
+    format!(
+        "{}-{:02}-{:02}T{:02}:{:02}:{:02}.{:03}Z",
+        tm.tm_year + 1900,
+        tm.tm_mon + 1,
+        tm.tm_mday,
+        tm.tm_hour,
+        tm.tm_min,
+        tm.tm_sec,
+        since_epoch.subsec_millis()
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+    use std::sync::{Arc, Mutex};
+    use std::time::Duration;
+
+    /// Keeps what is written where the test can still read it.
+    #[derive(Clone, Default)]
+    struct Buffer(Arc<Mutex<Vec<u8>>>);
+
+    impl Write for Buffer {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.0.lock().unwrap().extend_from_slice(buf);
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    fn command(args: &[&str]) -> RespData {
+        RespData::array(args.iter().copied())
+    }
+
+    #[test]
+    fn test_audit() {
+        let mut handler = CommandHandler::from(HashMap::new());
+        let buffer = Buffer::default();
+        handler.audit_log = Some(AuditLog {
+            out: Box::new(buffer.clone()),
+        });
+        let id = handler.register_client(
+            "127.0.0.1:50000".parse().unwrap(),
+            "127.0.0.1:6379".parse().unwrap(),
+            7,
+            None,
+        );
+
+        let test_cases = [
+            (
+                "Write",
+                command(&["SET", "a", "1"]),
+                Some("client=127.0.0.1:50000 user=\"default\" cmd=set keys=[\"a\"] result=OK"),
+            ),
+            (
+                "Failed write",
+                command(&["HSET", "a", "f", "v"]),
+                Some("client=127.0.0.1:50000 user=\"default\" cmd=hset keys=[\"a\"] result=WRONGTYPE"),
+            ),
+            ("Read", command(&["GET", "a"]), None),
+            (
+                "Several keys",
+                command(&["DEL", "a", "b"]),
+                Some("client=127.0.0.1:50000 user=\"default\" cmd=del keys=[\"a\", \"b\"] result=OK"),
+            ),
+        ];
+
+        for (name, input, expected) in test_cases {
+            buffer.0.lock().unwrap().clear();
+            handler.handle_client(id, &input);
+            let written = String::from_utf8(buffer.0.lock().unwrap().clone()).unwrap();
+            match expected {
+                Some(expected) => assert_eq!(
+                    written.split_once(' ').map(|(_, entry)| entry),
+                    Some(format!("{expected}\n").as_str()),
+                    "{}",
+                    name
+                ),
+                None => assert_eq!(written, "", "{}", name),
+            }
+        }
+    }
+
+    #[test]
+    fn test_format_entry() {
+        let time = UNIX_EPOCH + Duration::from_millis(1_700_000_000_042);
+        let test_cases = [
+            (
+                "No client",
+                format_entry(time, "-", "-", "flushall", &[], "OK"),
+                "2023-11-14T22:13:20.042Z client=- user=\"-\" cmd=flushall keys=[] result=OK\n",
+            ),
+            (
+                "Escaped key",
+                format_entry(
+                    time,
+                    "[::1]:6000",
+                    "alice",
+                    "set",
+                    &["a \"b\"\n"],
+                    "WRONGTYPE",
+                ),
+                "2023-11-14T22:13:20.042Z client=[::1]:6000 user=\"alice\" cmd=set \
+                 keys=[\"a \\\"b\\\"\\n\"] result=WRONGTYPE\n",
+            ),
+        ];
+
+        for (name, entry, expected) in test_cases {
+            assert_eq!(entry, expected, "{}", name);
+        }
+    }
+}
@@ -0,0 +1,174 @@
+use super::command_table::{self, NO_AUTH};
+use super::errors;
+use super::CommandHandler;
+use crate::resp::RespData;
+
+/// Whether `command` may run before the client has authenticated.
+fn is_no_auth_command(command: &str) -> bool {
+    command_table::lookup(command).is_some_and(|spec| spec.flags & NO_AUTH != 0)
+}
+
+impl CommandHandler {
+    /// Whether the current client has to authenticate before it can run `command`.
+    pub(super) fn auth_required(&mut self, command: &str) -> bool {
+        !is_no_auth_command(command) && !self.client_authenticated()
+    }
+
+    /// Whether the current client has authenticated, or doesn't need to.
+    pub(super) fn client_authenticated(&mut self) -> bool {
+        self.sync_requirepass();
+        let passwordless = self.default_user_passwordless();
+        !self
+            .current_client()
+            .is_some_and(|client| !client.authenticated && !passwordless)
+    }
+
+    /// Whether the ACL check can be skipped for `command`.
+    pub(super) fn acl_exempt(command: &str) -> bool {
+        is_no_auth_command(command)
+    }
+
+    /// AUTH [username] password. Without a username the client logs in as `default`.
+    pub(super) fn auth(&mut self, resp: &RespData) -> RespData {
+        let RespData::Array(arr) = resp else {
+            return errors::syntax_error();
+        };
+        let (username, password) = match arr.as_slice() {
+            [_, RespData::BulkString(password)] => (None, password),
+            [_, RespData::BulkString(username), RespData::BulkString(password)] => {
+                (Some(username), password)
+            }
+            _ => return errors::wrong_arity("auth"),
+        };
+
+        self.sync_requirepass();
+        if username.is_none() && self.default_user_passwordless() {
+            return RespData::Error(
+                "AUTH <password> called without any password configured for the default user. \
+                 Are you sure your configuration is correct?"
+                    .to_string(),
+            );
+        }
+        let username = username.map_or("default", |username| username.as_str());
+        if !self.acl.authenticate(username, password) {
+            return errors::wrong_pass();
+        }
+
+        if let Some(client) = self.current_client_mut() {
+            client.user = username.to_string();
+            client.authenticated = true;
+        }
+        RespData::SimpleString("OK".to_string())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::Config;
+    use std::collections::HashMap;
+    use std::sync::{Arc, RwLock};
+
+    fn command(args: &[&str]) -> RespData {
+        RespData::array(args.iter().copied())
+    }
+
+    #[test]
+    fn test_auth() {
+        let config = Config {
+            requirepass: Some("s3cret".to_string()),
+            ..Config::default()
+        };
+        let mut handler = CommandHandler::new(HashMap::new(), Arc::new(RwLock::new(config)));
+        let id = handler.register_client(
+            "127.0.0.1:5001".parse().unwrap(),
+            "127.0.0.1:6379".parse().unwrap(),
+            7,
+            None,
+        );
+
+        let test_cases = [
+            (
+                "Command before AUTH",
+                command(&["GET", "key"]),
+                errors::no_auth(),
+            ),
+            (
+                "Wrong password",
+                command(&["AUTH", "guess"]),
+                errors::wrong_pass(),
+            ),
+            (
+                "Unknown user",
+                command(&["AUTH", "admin", "s3cret"]),
+                errors::wrong_pass(),
+            ),
+            (
+                "Too many arguments",
+                command(&["AUTH", "default", "s3cret", "extra"]),
+                RespData::Error("wrong number of arguments for 'auth' command".to_string()),
+            ),
+            (
+                "Correct password",
+                command(&["AUTH", "s3cret"]),
+                RespData::SimpleString("OK".to_string()),
+            ),
+            (
+                "Command after AUTH",
+                command(&["GET", "key"]),
+                RespData::Null,
+            ),
+            (
+                "RESET drops authentication",
+                command(&["RESET"]),
+                RespData::SimpleString("RESET".to_string()),
+            ),
+            (
+                "Command after RESET",
+                command(&["GET", "key"]),
+                errors::no_auth(),
+            ),
+            (
+                "Default user with password",
+                command(&["AUTH", "default", "s3cret"]),
+                RespData::SimpleString("OK".to_string()),
+            ),
+        ];
+
+        for (name, input, expected) in test_cases {
+            assert_eq!(
+                handler.handle_client(id, &input),
+                Some(expected),
+                "{}",
+                name
+            );
+        }
+        assert_eq!(handler.stats.errors["NOAUTH"], 2);
+    }
+
+    #[test]
+    fn test_auth_without_password() {
+        let mut handler = CommandHandler::from(HashMap::new());
+
+        let test_cases = [
+            (
+                "Password only",
+                command(&["AUTH", "anything"]),
+                RespData::Error(
+                    "AUTH <password> called without any password configured for the default \
+                     user. Are you sure your configuration is correct?"
+                        .to_string(),
+                ),
+            ),
+            (
+                "Default user accepts any password",
+                command(&["AUTH", "default", "anything"]),
+                RespData::SimpleString("OK".to_string()),
+            ),
+        ];
+
+        for (name, input, expected) in test_cases {
+            assert_eq!(handler.auth(&input), expected, "{}", name);
+        }
+    }
+}
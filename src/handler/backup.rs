@@ -0,0 +1,298 @@
+//! Scheduled backups. Every `backup-interval` seconds the RDB snapshot, if it changed since the
+//! last backup, is copied into `backup-dir` under a timestamped name. The oldest copies beyond
+//! `backup-retention` are then deleted and `backup-hook` is run on the new one, all on a
+//! thread of its own so a big snapshot doesn't hold up commands.
+
+use super::CommandHandler;
+use std::fs::{self, File};
+use std::io;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::sync::{Arc, Mutex};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+#[derive(Default)]
+pub(super) struct Backups {
+    /// When the last backup was started.
+    last_started: Option<SystemTime>,
+    /// The modification time of the snapshot last backed up successfully, so an unchanged
+    /// snapshot isn't copied again.
+    last_snapshot: Arc<Mutex<Option<SystemTime>>>,
+    running: Option<JoinHandle<()>>,
+}
+
+/// What one backup needs, taken from the config when it starts.
+struct BackupJob {
+    snapshot: PathBuf,
+    modified: SystemTime,
+    dir: PathBuf,
+    /// The start of every backup's file name, the snapshot's name without its extension.
+    prefix: String,
+    retention: usize,
+    hook: Option<String>,
+}
+
+impl CommandHandler {
+    /// Starts a backup if `backup-dir` is set, the interval has passed since the last one
+    /// started, none is still running, and the snapshot has changed since the last one.
+    pub(super) fn backup_if_needed(&mut self) {
+        let config = self.config.read().unwrap();
+        let Some(dir) = config.backup_dir.clone() else {
+            return;
+        };
+        let interval = Duration::from_secs(config.backup_interval);
+        let backups = &mut self.backups;
+        if backups
+            .last_started
+            .is_some_and(|started| started.elapsed().unwrap_or_default() < interval)
+            || backups
+                .running
+                .as_ref()
+                .is_some_and(|job| !job.is_finished())
+        {
+            return;
+        }
+        let snapshot = config.rdb_path();
+        let Ok(modified) = fs::metadata(&snapshot).and_then(|meta| meta.modified()) else {
+            return;
+        };
+        if *backups.last_snapshot.lock().unwrap() == Some(modified) {
+            return;
+        }
+        let job = BackupJob {
+            prefix: Path::new(&config.dbfilename)
+                .file_stem()
+                .map(|stem| stem.to_string_lossy().into_owned())
+                .unwrap_or_default(),
+            snapshot,
+            modified,
+            dir,
+            retention: config.backup_retention,
+            hook: config.backup_hook.clone(),
+        };
+        backups.last_started = Some(SystemTime::now());
+        let last_snapshot = Arc::clone(&backups.last_snapshot);
+        backups.running = Some(thread::spawn(move || match job.run() {
+            Ok(path) => {
+                log_notice!("Backup written to {}", path.display());
+                *last_snapshot.lock().unwrap() = Some(job.modified);
+            }
+            Err(e) => log_warning!("Backup failed: {e}"),
+        }));
+    }
+}
+
+impl BackupJob {
+    /// Copies the snapshot, going through a temporary file so a backup that fails half way
+    /// never looks complete, then prunes and runs the hook. Returns the backup's path.
+    fn run(&self) -> io::Result<PathBuf> {
+        fs::create_dir_all(&self.dir)?;
+        let name = format!("{}-{}.rdb", self.prefix, timestamp(SystemTime::now()));
+        let path = self.dir.join(&name);
+        let tmp = self.dir.join(format!("temp-{name}"));
+        let result = fs::copy(&self.snapshot, &tmp)
+            .and_then(|_| File::open(&tmp)?.sync_all())
+            .and_then(|()| fs::rename(&tmp, &path));
+        if result.is_err() {
+            let _ = fs::remove_file(&tmp);
+        }
+        result?;
+
+        for old in prune(&self.dir, &self.prefix, self.retention)? {
+            log_verbose!("Deleted old backup {}", old.display());
+        }
+        if let Some(hook) = &self.hook {
+            // The hook's failure is reported, but the backup itself is done.
+            match Command::new("sh")
+                .arg("-c")
+                .arg(hook)
+                .arg("sh")
+                .arg(&path)
+                .status()
+            {
+                Ok(status) if status.success() => {}
+                Ok(status) => log_warning!("Backup hook failed: {status}"),
+                Err(e) => log_warning!("Can't run the backup hook: {e}"),
+            }
+        }
+        Ok(path)
+    }
+}
+
+/// Deletes all but the `retention` newest backups in `dir`, returning their paths. Backups
+/// are told apart from other files by name, and their timestamps sort in time order.
+fn prune(dir: &Path, prefix: &str, retention: usize) -> io::Result<Vec<PathBuf>> {
+    if retention == 0 {
+        return Ok(Vec::new());
+    }
+    let mut backups = Vec::new();
+    for entry in fs::read_dir(dir)? {
+        let name = entry?.file_name().to_string_lossy().into_owned();
+        let is_backup = name
+            .strip_prefix(prefix)
+            .and_then(|rest| rest.strip_prefix('-'))
+            .and_then(|rest| rest.strip_suffix(".rdb"))
+            .is_some_and(|stamp| stamp.len() == TIMESTAMP_LEN);
+        if is_backup {
+            backups.push(name);
+        }
+    }
+    backups.sort_unstable();
+    let excess = backups.len().saturating_sub(retention);
+    let mut deleted = Vec::new();
+    for name in &backups[..excess] {
+        let path = dir.join(name);
+        fs::remove_file(&path)?;
+        deleted.push(path);
+    }
+    Ok(deleted)
+}
+
+/// The length of a `timestamp`.
+const TIMESTAMP_LEN: usize = "20261016T094107.123Z".len();
+
+/// `time` in UTC, like `20261016T094107.123Z`, so file names sort in time order.
+fn timestamp(time: SystemTime) -> String {
+    let since_epoch = time.duration_since(UNIX_EPOCH).unwrap_or_default();
+    let seconds = since_epoch.as_secs() as libc::time_t;
+    // SAFETY: gmtime_r only writes to the tm it is given.
+    let tm = unsafe {
+        let mut tm = std::mem::zeroed::<libc::tm>();
+        libc::gmtime_r(&seconds, &mut tm);
+        tm
+    };
+    format!(
+        "{}{:02}{:02}T{:02}{:02}{:02}.{:03}Z",
+        tm.tm_year + 1900,
+        tm.tm_mon + 1,
+        tm.tm_mday,
+        tm.tm_hour,
+        tm.tm_min,
+        tm.tm_sec,
+        since_epoch.subsec_millis()
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::Config;
+    use crate::resp::RespData;
+    use std::collections::HashMap;
+    use std::process;
+    use std::sync::RwLock;
+
+    fn command(args: &[&str]) -> RespData {
+        RespData::array(args.iter().copied())
+    }
+
+    /// The backups in `dir`, oldest first.
+    fn backups(dir: &Path) -> Vec<String> {
+        let mut names: Vec<String> = fs::read_dir(dir)
+            .unwrap()
+            .map(|entry| entry.unwrap().file_name().to_string_lossy().into_owned())
+            .filter(|name| name.starts_with("dump-"))
+            .collect();
+        names.sort_unstable();
+        names
+    }
+
+    fn wait_for_backup(handler: &mut CommandHandler) {
+        if let Some(job) = handler.backups.running.take() {
+            job.join().unwrap();
+        }
+    }
+
+    #[test]
+    fn test_timestamp() {
+        let time = UNIX_EPOCH + Duration::from_millis(1_791_971_267_123);
+        assert_eq!(timestamp(time), "20261014T094747.123Z");
+        assert_eq!(timestamp(time).len(), TIMESTAMP_LEN);
+    }
+
+    #[test]
+    fn test_prune() {
+        let dir = std::env::temp_dir().join(format!("redis-prune-{}", process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let names = [
+            "dump-20261014T000000.000Z.rdb",
+            "dump-20261015T000000.000Z.rdb",
+            "dump-20261016T000000.000Z.rdb",
+            "dump.rdb",
+            "dump-notes.rdb",
+            "other-20261013T000000.000Z.rdb",
+        ];
+        for name in names {
+            File::create(dir.join(name)).unwrap();
+        }
+
+        let test_cases = [
+            ("Keep everything", 0, vec![]),
+            ("Under the limit", 3, vec![]),
+            (
+                "Oldest go first",
+                1,
+                vec![
+                    "dump-20261014T000000.000Z.rdb",
+                    "dump-20261015T000000.000Z.rdb",
+                ],
+            ),
+        ];
+
+        for (name, retention, expected) in test_cases {
+            let deleted = prune(&dir, "dump", retention).unwrap();
+            let expected: Vec<PathBuf> = expected.iter().map(|name| dir.join(name)).collect();
+            assert_eq!(deleted, expected, "{}", name);
+        }
+        assert!(dir.join("dump.rdb").exists());
+        assert!(dir.join("dump-notes.rdb").exists());
+        assert!(dir.join("other-20261013T000000.000Z.rdb").exists());
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_scheduled_backups() {
+        let dir = std::env::temp_dir().join(format!("redis-backup-{}", process::id()));
+        let backup_dir = dir.join("backups");
+        fs::create_dir_all(&dir).unwrap();
+        let config = Config {
+            dir: dir.clone(),
+            backup_dir: Some(backup_dir.clone()),
+            backup_interval: 0,
+            backup_retention: 2,
+            backup_hook: Some(format!("echo \"$1\" >> {}", dir.join("hook.log").display())),
+            ..Config::default()
+        };
+        let mut handler = CommandHandler::new(HashMap::new(), Arc::new(RwLock::new(config)));
+
+        // Nothing to back up before the first snapshot.
+        handler.backup_if_needed();
+        wait_for_backup(&mut handler);
+        assert!(!backup_dir.exists());
+
+        for i in 0..3 {
+            handler.handle(&command(&["SET", "counter", &i.to_string()]));
+            handler.handle(&command(&["SAVE"]));
+            handler.backup_if_needed();
+            wait_for_backup(&mut handler);
+            // An unchanged snapshot isn't backed up again.
+            handler.backup_if_needed();
+            wait_for_backup(&mut handler);
+        }
+
+        let kept = backups(&backup_dir);
+        assert_eq!(kept.len(), 2);
+        let newest = fs::read(backup_dir.join(&kept[1])).unwrap();
+        assert_eq!(newest, fs::read(dir.join("dump.rdb")).unwrap());
+        let hook_log = fs::read_to_string(dir.join("hook.log")).unwrap();
+        assert_eq!(hook_log.lines().count(), 3);
+        assert_eq!(
+            hook_log.lines().last().unwrap(),
+            backup_dir.join(&kept[1]).display().to_string()
+        );
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
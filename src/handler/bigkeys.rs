@@ -0,0 +1,188 @@
+//! The big keys report: walks the keyspace with the SCAN cursor and finds the largest keys of
+//! each type, by element count and by memory, like `redis-cli --bigkeys` and `--memkeys` do
+//! from the outside.
+
+use super::memory;
+use super::{CommandHandler, RedisValue};
+use std::fmt::Write;
+
+/// Every type, in the order the summary lists them.
+const TYPES: [&str; 7] = [
+    "string",
+    "hash",
+    "ReJSON-RL",
+    "MBbloom--",
+    "CMSk-TYPE",
+    "TopK-TYPE",
+    "TSDB-TYPE",
+];
+
+/// What a type's size is counted in.
+fn size_unit(type_name: &str) -> &'static str {
+    match type_name {
+        "string" | "ReJSON-RL" => "bytes",
+        "MBbloom--" | "TopK-TYPE" => "items",
+        "CMSk-TYPE" => "counters",
+        "TSDB-TYPE" => "samples",
+        _ => "fields",
+    }
+}
+
+/// The size of a value in its type's unit.
+fn element_count(value: &RedisValue) -> u64 {
+    match value {
+        RedisValue::String(s) => s.len() as u64,
+        RedisValue::Hash(map) => map.len() as u64,
+        RedisValue::Json(doc) => doc.to_string().len() as u64,
+        RedisValue::Bloom(filter) => filter.len(),
+        RedisValue::CountMin(sketch) => sketch.counters() as u64,
+        RedisValue::TopK(topk) => topk.len() as u64,
+        RedisValue::TimeSeries(series) => series.len() as u64,
+    }
+}
+
+#[derive(Default)]
+struct TypeSummary {
+    keys: u64,
+    total_size: u64,
+    biggest: Option<(String, u64)>,
+    biggest_memory: Option<(String, u64)>,
+}
+
+impl TypeSummary {
+    fn add(&mut self, key: &str, size: u64, memory: u64) {
+        self.keys += 1;
+        self.total_size += size;
+        if self
+            .biggest
+            .as_ref()
+            .is_none_or(|(_, biggest)| size > *biggest)
+        {
+            self.biggest = Some((key.to_string(), size));
+        }
+        if self
+            .biggest_memory
+            .as_ref()
+            .is_none_or(|(_, biggest)| memory > *biggest)
+        {
+            self.biggest_memory = Some((key.to_string(), memory));
+        }
+    }
+}
+
+impl CommandHandler {
+    /// A summary of the largest keys of each type and the average sizes, in the format of
+    /// `redis-cli --bigkeys`.
+    pub fn big_keys_report(&self) -> String {
+        let mut summaries: Vec<TypeSummary> =
+            TYPES.iter().map(|_| TypeSummary::default()).collect();
+        let mut keys = 0u64;
+        let mut key_bytes = 0u64;
+        let mut cursor = 0;
+        loop {
+            cursor = self.db.scan(cursor, &mut |key, value| {
+                keys += 1;
+                key_bytes += key.len() as u64;
+                let type_index = TYPES
+                    .iter()
+                    .position(|name| *name == value.type_name())
+                    .unwrap();
+                summaries[type_index].add(
+                    key,
+                    element_count(value),
+                    memory::entry_size(key, value),
+                );
+            });
+            if cursor == 0 {
+                break;
+            }
+        }
+
+        let ratio = |part: u64, whole: u64| {
+            if whole == 0 {
+                0.0
+            } else {
+                part as f64 / whole as f64
+            }
+        };
+        let mut out = String::new();
+        writeln!(out, "-------- summary -------").unwrap();
+        writeln!(out).unwrap();
+        writeln!(out, "Sampled {keys} keys in the keyspace!").unwrap();
+        writeln!(
+            out,
+            "Total key length in bytes is {key_bytes} (avg len {:.2})",
+            ratio(key_bytes, keys)
+        )
+        .unwrap();
+        writeln!(out).unwrap();
+        for (type_name, summary) in TYPES.iter().zip(&summaries) {
+            if let Some((key, size)) = &summary.biggest {
+                writeln!(
+                    out,
+                    "Biggest {type_name:>6} found '{key}' has {size} {}",
+                    size_unit(type_name)
+                )
+                .unwrap();
+            }
+            if let Some((key, bytes)) = &summary.biggest_memory {
+                writeln!(
+                    out,
+                    "Biggest {type_name:>6} by memory found '{key}' using {bytes} bytes"
+                )
+                .unwrap();
+            }
+        }
+        writeln!(out).unwrap();
+        for (type_name, summary) in TYPES.iter().zip(&summaries) {
+            writeln!(
+                out,
+                "{} {type_name}s with {} {} ({:.2}% of keys, avg size {:.2})",
+                summary.keys,
+                summary.total_size,
+                size_unit(type_name),
+                ratio(summary.keys, keys) * 100.0,
+                ratio(summary.total_size, summary.keys)
+            )
+            .unwrap();
+        }
+        out
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::resp::RespData;
+    use std::collections::HashMap;
+
+    fn command(args: &[&str]) -> RespData {
+        RespData::array(args.iter().copied())
+    }
+
+    #[test]
+    fn test_big_keys_report() {
+        let mut handler = CommandHandler::from(HashMap::new());
+        handler.handle(&command(&["SET", "short", "abc"]));
+        handler.handle(&command(&["SET", "long", &"x".repeat(100)]));
+        handler.handle(&command(&["HSET", "h", "a", "1", "b", "2"]));
+
+        let report = handler.big_keys_report();
+        let long_memory = memory::entry_size("long", handler.db.get("long").unwrap());
+        let expected_lines = [
+            "Sampled 3 keys in the keyspace!".to_string(),
+            "Total key length in bytes is 10 (avg len 3.33)".to_string(),
+            "Biggest string found 'long' has 100 bytes".to_string(),
+            format!("Biggest string by memory found 'long' using {long_memory} bytes"),
+            "Biggest   hash found 'h' has 2 fields".to_string(),
+            "2 strings with 103 bytes (66.67% of keys, avg size 51.50)".to_string(),
+            "1 hashs with 2 fields (33.33% of keys, avg size 2.00)".to_string(),
+        ];
+        for line in expected_lines {
+            assert!(report.lines().any(|l| l == line), "{line}\n{report}");
+        }
+
+        let empty = CommandHandler::from(HashMap::new()).big_keys_report();
+        assert!(empty.contains("0 strings with 0 bytes (0.00% of keys, avg size 0.00)"));
+    }
+}
@@ -0,0 +1,224 @@
+//! The Bloom filter data type and its RedisBloom commands, for cheaply checking whether an
+//! item was probably seen before.
+
+use super::errors;
+use super::events::KeyEventKind;
+use super::{CommandHandler, RedisValue};
+use crate::bloom::BloomFilter;
+use crate::resp::RespData;
+
+/// The filter BF.ADD and BF.MADD create for a missing key, with RedisBloom's defaults.
+const DEFAULT_ERROR_RATE: f64 = 0.01;
+const DEFAULT_CAPACITY: u64 = 100;
+pub(super) const DEFAULT_EXPANSION: u32 = 2;
+
+impl CommandHandler {
+    /// BF.RESERVE key error_rate capacity [EXPANSION expansion | NONSCALING]: creates an
+    /// empty filter. An `expansion` of `None` never grows.
+    pub(super) fn bf_reserve(
+        &mut self,
+        key: &str,
+        error_rate: f64,
+        capacity: u64,
+        expansion: Option<u32>,
+    ) -> RespData {
+        self.expire_if_needed(key);
+        if self.db.get(key).is_some() {
+            return RespData::Error("item exists".to_string());
+        }
+        let filter = match BloomFilter::new(error_rate, capacity, expansion) {
+            Ok(filter) => filter,
+            Err(e) => return RespData::Error(e),
+        };
+        let before = self.key_memory(key);
+        self.db.insert(key.to_string(), RedisValue::Bloom(filter));
+        self.touch_key(key);
+        self.account_key_change(key, before);
+        self.notify_key_event(KeyEventKind::Set, key);
+        RespData::SimpleString("OK".to_string())
+    }
+
+    /// BF.ADD and BF.MADD: adds the items, creating a default filter if there is none. Each
+    /// reply is 1 if the item is new, 0 if it was probably there already, or an error if
+    /// the filter is full.
+    pub(super) fn bf_add(
+        &mut self,
+        key: &str,
+        items: &[String],
+    ) -> Result<Vec<RespData>, RespData> {
+        self.expire_if_needed(key);
+        let before = self.key_memory(key);
+        let created = match self.db.get(key) {
+            Some(RedisValue::Bloom(_)) => false,
+            Some(_) => return Err(errors::wrong_type()),
+            None => {
+                let filter = BloomFilter::new(
+                    DEFAULT_ERROR_RATE,
+                    DEFAULT_CAPACITY,
+                    Some(DEFAULT_EXPANSION),
+                )
+                .map_err(RespData::Error)?;
+                self.db.insert(key.to_string(), RedisValue::Bloom(filter));
+                true
+            }
+        };
+        let Some(RedisValue::Bloom(filter)) = self.db.get_mut(key) else {
+            unreachable!("the key holds a filter");
+        };
+        let replies: Vec<_> = items
+            .iter()
+            .map(|item| match filter.insert(item.as_bytes()) {
+                Ok(added) => RespData::Integer(added as i64),
+                Err(e) => RespData::Error(e),
+            })
+            .collect();
+
+        self.touch_key(key);
+        if created || replies.contains(&RespData::Integer(1)) {
+            self.account_key_change(key, before);
+            self.notify_key_event(KeyEventKind::Set, key);
+        }
+        Ok(replies)
+    }
+
+    /// BF.EXISTS and BF.MEXISTS: 1 for each item that was probably added, 0 for the rest.
+    pub(super) fn bf_exists(
+        &mut self,
+        key: &str,
+        items: &[String],
+    ) -> Result<Vec<RespData>, RespData> {
+        let filter = match self.lookup_key_read(key) {
+            Some(RedisValue::Bloom(filter)) => Some(filter),
+            Some(_) => return Err(errors::wrong_type()),
+            None => None,
+        };
+        Ok(items
+            .iter()
+            .map(|item| {
+                let found = filter.is_some_and(|filter| filter.contains(item.as_bytes()));
+                RespData::Integer(found as i64)
+            })
+            .collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+
+    fn command(args: &[&str]) -> RespData {
+        RespData::array(args.iter().copied())
+    }
+
+    fn error(message: &str) -> RespData {
+        RespData::Error(message.to_string())
+    }
+
+    #[test]
+    fn test_bloom_commands() {
+        let mut handler = CommandHandler::from(HashMap::new());
+        handler.handle(&command(&["SET", "string", "x"]));
+
+        let test_cases = [
+            (
+                "Reserve a filter",
+                command(&["BF.RESERVE", "small", "0.01", "2", "NONSCALING"]),
+                RespData::SimpleString("OK".to_string()),
+            ),
+            (
+                "Reserve an existing key",
+                command(&["BF.RESERVE", "small", "0.01", "2"]),
+                error("item exists"),
+            ),
+            (
+                "Error rate out of range",
+                command(&["BF.RESERVE", "other", "1.5", "100"]),
+                error("(0 < error rate range < 1)"),
+            ),
+            (
+                "Zero capacity",
+                command(&["BF.RESERVE", "other", "0.01", "0"]),
+                error("(capacity should be larger than 0)"),
+            ),
+            (
+                "Nonscaling with an expansion",
+                command(&[
+                    "BF.RESERVE",
+                    "other",
+                    "0.01",
+                    "10",
+                    "EXPANSION",
+                    "4",
+                    "NONSCALING",
+                ]),
+                error("Nonscaling filters cannot expand"),
+            ),
+            (
+                "Add a new item",
+                command(&["BF.ADD", "small", "a"]),
+                RespData::Integer(1),
+            ),
+            (
+                "Add it again",
+                command(&["BF.ADD", "small", "a"]),
+                RespData::Integer(0),
+            ),
+            (
+                "Fill the filter",
+                command(&["BF.MADD", "small", "b", "c"]),
+                RespData::Array(vec![
+                    RespData::Integer(1),
+                    error("non scaling filter is full"),
+                ]),
+            ),
+            (
+                "Add to a full filter",
+                command(&["BF.ADD", "small", "d"]),
+                error("non scaling filter is full"),
+            ),
+            (
+                "Check several items",
+                command(&["BF.MEXISTS", "small", "a", "b", "c"]),
+                RespData::Array(vec![
+                    RespData::Integer(1),
+                    RespData::Integer(1),
+                    RespData::Integer(0),
+                ]),
+            ),
+            (
+                "Add creates a filter",
+                command(&["BF.MADD", "seen", "x", "y", "x"]),
+                RespData::Array(vec![
+                    RespData::Integer(1),
+                    RespData::Integer(1),
+                    RespData::Integer(0),
+                ]),
+            ),
+            (
+                "Check an item",
+                command(&["BF.EXISTS", "seen", "y"]),
+                RespData::Integer(1),
+            ),
+            (
+                "Check a missing key",
+                command(&["BF.EXISTS", "missing", "y"]),
+                RespData::Integer(0),
+            ),
+            (
+                "Another type",
+                command(&["BF.ADD", "string", "y"]),
+                errors::wrong_type(),
+            ),
+            (
+                "Type name",
+                command(&["TYPE", "seen"]),
+                RespData::SimpleString("MBbloom--".to_string()),
+            ),
+        ];
+
+        for (name, input, expected) in test_cases {
+            assert_eq!(handler.handle(&input), expected, "{}", name);
+        }
+    }
+}
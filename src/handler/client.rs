@@ -0,0 +1,1191 @@
+use super::command_table;
+use super::errors;
+use super::ratelimit::TokenBucket;
+use super::tracking::Tracking;
+use super::{command_name, is_write_command, CommandHandler};
+use crate::resp::RespData;
+use std::fmt::Write;
+use std::net::{Shutdown, SocketAddr, TcpStream};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+pub type ClientId = u64;
+
+/// What is to be written to a client's connection: its replies, and messages the handler
+/// pushes without it asking, like key invalidations, in the order they were queued. The
+/// handler never writes to a socket itself, so a client that stops reading can't hold it up.
+#[derive(Clone, Default)]
+pub struct ClientOutput(Arc<Outbox>);
+
+#[derive(Default)]
+struct Outbox {
+    queued: Mutex<Vec<u8>>,
+    /// Lets the connection's thread know there is something to write.
+    wake: Option<Box<dyn Fn() + Send + Sync>>,
+}
+
+impl ClientOutput {
+    /// An output that calls `wake` each time a message is queued.
+    pub fn new(wake: impl Fn() + Send + Sync + 'static) -> Self {
+        Self(Arc::new(Outbox {
+            queued: Mutex::new(Vec::new()),
+            wake: Some(Box::new(wake)),
+        }))
+    }
+
+    /// Queues `message` to be written between the client's replies, right away.
+    pub fn push(&self, message: &RespData) {
+        self.reply(message);
+        self.wake();
+    }
+
+    /// Queues a reply, written once the connection runs out of commands to run.
+    pub fn reply(&self, reply: &RespData) {
+        let _ = reply.write(&mut *self.0.queued.lock().unwrap());
+    }
+
+    /// Queues a reply that is already serialized. With nothing else queued, `bytes` is
+    /// queued as is rather than copied.
+    pub fn append(&self, bytes: Vec<u8>) {
+        let mut queued = self.0.queued.lock().unwrap();
+        if queued.is_empty() {
+            *queued = bytes;
+        } else {
+            queued.extend_from_slice(&bytes);
+        }
+    }
+
+    /// Lets the connection's thread know there is something to write.
+    pub fn wake(&self) {
+        if let Some(wake) = &self.0.wake {
+            wake();
+        }
+    }
+
+    /// Takes everything queued so far.
+    pub fn take(&self) -> Vec<u8> {
+        std::mem::take(&mut *self.0.queued.lock().unwrap())
+    }
+}
+
+/// Whether replies are sent back to a client, set with CLIENT REPLY.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum ReplyMode {
+    On,
+    Off,
+    /// Suppress the reply to the next command only.
+    Skip,
+}
+
+/// A server-wide pause started with CLIENT PAUSE.
+pub struct ClientPause {
+    pub deadline: Instant,
+    pub writes_only: bool,
+}
+
+/// Connections accepted beyond `maxclients` as long as they authenticate as an admin.
+const RESERVED_ADMIN_SLOTS: usize = 4;
+
+/// Bookkeeping for a connected client, as reported by CLIENT LIST and CLIENT INFO.
+pub struct Client {
+    pub id: ClientId,
+    pub addr: SocketAddr,
+    pub laddr: SocketAddr,
+    pub fd: i32,
+    pub name: Option<String>,
+    pub created: Instant,
+    pub last_interaction: Instant,
+    pub last_command: String,
+    pub resp: u8,
+    pub reply_mode: ReplyMode,
+    /// Exempt from client eviction (CLIENT NO-EVICT).
+    pub no_evict: bool,
+    /// Reads do not update key access metadata (CLIENT NO-TOUCH).
+    pub no_touch: bool,
+    /// The ACL user the client runs commands as.
+    pub user: String,
+    /// Whether the client has passed AUTH; only relevant when a password is required.
+    pub authenticated: bool,
+    /// Admitted past `maxclients` into a slot reserved for admins, so it may only authenticate
+    /// until it is known to be one.
+    pub reserved_slot: bool,
+    /// Set by QUIT so the connection is closed once the reply is written.
+    pub close_after_reply: bool,
+    /// Handle to the client's socket, used to disconnect it from another connection.
+    pub stream: Option<TcpStream>,
+    /// Commands the client may still send under `client-rate-limit`.
+    pub rate_limit: Option<TokenBucket>,
+    /// Set while the client has CLIENT TRACKING on.
+    pub tracking: Option<Tracking>,
+    pub output: Option<ClientOutput>,
+    /// A reply serialized by the last command, waiting for `take_streamed_reply`.
+    pub streamed_reply: Vec<u8>,
+}
+
+impl Client {
+    pub fn new(
+        id: ClientId,
+        addr: SocketAddr,
+        laddr: SocketAddr,
+        fd: i32,
+        stream: Option<TcpStream>,
+    ) -> Self {
+        let now = Instant::now();
+        Self {
+            id,
+            addr,
+            laddr,
+            fd,
+            name: None,
+            created: now,
+            last_interaction: now,
+            last_command: "NULL".to_string(),
+            resp: 2,
+            reply_mode: ReplyMode::On,
+            no_evict: false,
+            no_touch: false,
+            user: "default".to_string(),
+            authenticated: false,
+            reserved_slot: false,
+            close_after_reply: false,
+            stream,
+            rate_limit: None,
+            tracking: None,
+            output: None,
+            streamed_reply: Vec::new(),
+        }
+    }
+
+    /// Flag characters shown in CLIENT LIST, `N` when none are set.
+    fn flags(&self) -> String {
+        let mut flags = String::new();
+        if self.no_evict {
+            flags.push('e');
+        }
+        if self.no_touch {
+            flags.push('T');
+        }
+        if self.tracking.is_some() {
+            flags.push('t');
+        }
+        if flags.is_empty() {
+            flags.push('N');
+        }
+        flags
+    }
+
+    /// Single line description in the CLIENT LIST format.
+    fn describe(&self) -> String {
+        let mut line = String::new();
+        write!(
+            line,
+            "id={} addr={} laddr={} fd={} name={} age={} idle={} flags={} db=0 sub=0 psub=0 \
+             multi=-1 cmd={} user={} resp={}",
+            self.id,
+            self.addr,
+            self.laddr,
+            self.fd,
+            self.name.as_deref().unwrap_or(""),
+            self.created.elapsed().as_secs(),
+            self.last_interaction.elapsed().as_secs(),
+            self.flags(),
+            self.last_command,
+            self.user,
+            self.resp,
+        )
+        .unwrap();
+        line
+    }
+}
+
+/// The error for a name CLIENT SETNAME or HELLO SETNAME can't give a client, if any.
+pub(super) fn invalid_client_name(name: &str) -> Option<RespData> {
+    name.chars().any(|c| !('!'..='~').contains(&c)).then(|| {
+        RespData::Error(
+            "Client names cannot contain spaces, newlines or special characters.".to_string(),
+        )
+    })
+}
+
+impl CommandHandler {
+    /// Whether another connection can be accepted, counting it as rejected if not. Beyond
+    /// `maxclients`, a few extra slots are kept for admins.
+    pub fn accepting_clients(&mut self) -> bool {
+        let maxclients = self.config.read().unwrap().maxclients;
+        if self.clients.len() < maxclients + RESERVED_ADMIN_SLOTS {
+            return true;
+        }
+        self.stats.rejected_connections += 1;
+        false
+    }
+
+    /// Adds a newly accepted connection to the client registry and returns its ID.
+    pub fn register_client(
+        &mut self,
+        addr: SocketAddr,
+        laddr: SocketAddr,
+        fd: i32,
+        stream: Option<TcpStream>,
+    ) -> ClientId {
+        self.next_client_id += 1;
+        let id = self.next_client_id;
+        let mut client = Client::new(id, addr, laddr, fd, stream);
+        client.reserved_slot = self.clients.len() >= self.config.read().unwrap().maxclients;
+        self.clients.insert(id, client);
+        self.stats.total_connections_received += 1;
+        id
+    }
+
+    pub fn unregister_client(&mut self, id: ClientId) {
+        if let Some(client) = self.clients.remove(&id) {
+            self.release_ip_rate_limit(client.addr.ip());
+        }
+        self.disable_tracking(id);
+    }
+
+    /// Sets where messages pushed to the client are queued.
+    pub fn set_client_output(&mut self, id: ClientId, output: ClientOutput) {
+        if let Some(client) = self.clients.get_mut(&id) {
+            client.output = Some(output);
+        }
+    }
+
+    /// Runs a command on behalf of a registered client. Returns `None` when the client has
+    /// asked for the reply to be suppressed with CLIENT REPLY, or when the reply was streamed
+    /// instead, to be taken with `take_streamed_reply`.
+    pub fn handle_client(&mut self, id: ClientId, resp: &RespData) -> Option<RespData> {
+        if let Some(reply) = self.check_reserved_slot(id, resp) {
+            return Some(reply);
+        }
+        if let Some(reply) = self.rate_limited(id) {
+            return Some(self.reject(None, reply));
+        }
+        let mode_before = self.clients.get(&id).map(|client| client.reply_mode);
+
+        self.current_client = Some(id);
+        self.reply_streaming = self.reply_streaming_for(id);
+        let reply = self.handle(resp);
+        let streamed = self.write_streamed_reply(id);
+        self.current_client = None;
+
+        let Some(client) = self.clients.get_mut(&id) else {
+            return Some(reply);
+        };
+        let mode_after = client.reply_mode;
+        if mode_before == Some(ReplyMode::Skip) && mode_after == ReplyMode::Skip {
+            client.reply_mode = ReplyMode::On;
+        }
+        let suppressed = mode_before == Some(ReplyMode::Skip)
+            || matches!(mode_after, ReplyMode::Off | ReplyMode::Skip);
+        (!suppressed && !streamed).then_some(reply)
+    }
+
+    /// Lets a client in a reserved slot run AUTH, and turns it into a regular client once it
+    /// has logged in as an admin. Any other command disconnects it with the max clients error.
+    fn check_reserved_slot(&mut self, id: ClientId, resp: &RespData) -> Option<RespData> {
+        let client = self.clients.get(&id)?;
+        if !client.reserved_slot {
+            return None;
+        }
+        if self.client_may_use_reserved_slot(client) {
+            self.clients.get_mut(&id)?.reserved_slot = false;
+            return None;
+        }
+        if command_name(resp).is_some_and(|name| name.eq_ignore_ascii_case("auth")) {
+            return None;
+        }
+        self.clients.get_mut(&id)?.close_after_reply = true;
+        Some(self.reject(
+            None,
+            RespData::Error("max number of clients reached".to_string()),
+        ))
+    }
+
+    /// How much longer `resp` has to wait because of CLIENT PAUSE, if at all.
+    pub fn pause_remaining(&mut self, resp: &RespData) -> Option<Duration> {
+        let pause = self.pause.as_ref()?;
+        let remaining = pause.deadline.saturating_duration_since(Instant::now());
+        if remaining.is_zero() {
+            self.pause = None;
+            return None;
+        }
+
+        let name = command_name(resp)?.to_lowercase();
+        // CLIENT itself keeps working so that a paused server can be unpaused.
+        if name == "client" || (pause.writes_only && !is_write_command(&name)) {
+            return None;
+        }
+        Some(remaining)
+    }
+
+    /// Updates the idle time and last command of the client running the current command. Like
+    /// Redis, a known subcommand is recorded along with its command, as in `client|list`.
+    pub(super) fn touch_current_client(&mut self, command: &str, resp: &RespData) {
+        let subcommand = match resp {
+            RespData::Array(arr) => match arr.get(1) {
+                Some(RespData::BulkString(sub)) => Some(sub.to_lowercase()),
+                _ => None,
+            },
+            _ => None,
+        };
+        let full_name = match subcommand {
+            Some(sub)
+                if command_table::lookup(command)
+                    .is_some_and(|spec| spec.subcommand(&sub).is_some()) =>
+            {
+                format!("{command}|{sub}")
+            }
+            _ => command.to_string(),
+        };
+        if let Some(client) = self.current_client_mut() {
+            client.last_interaction = Instant::now();
+            client.last_command = full_name;
+        }
+    }
+
+    pub(super) fn client(&mut self, resp: &RespData) -> RespData {
+        let RespData::Array(arr) = resp else {
+            return errors::syntax_error();
+        };
+
+        let Some(RespData::BulkString(subcommand)) = arr.get(1) else {
+            return errors::wrong_arity("client");
+        };
+        let args = &arr[2..];
+        let wrong_arity = || errors::wrong_arity(&format!("client|{}", subcommand.to_lowercase()));
+
+        match subcommand.to_uppercase().as_str() {
+            "ID" => {
+                if !args.is_empty() {
+                    return wrong_arity();
+                }
+                match self.current_client {
+                    Some(id) => RespData::Integer(id as i64),
+                    None => RespData::Null,
+                }
+            }
+            "GETNAME" => {
+                if !args.is_empty() {
+                    return wrong_arity();
+                }
+                match self.current_client().and_then(|c| c.name.clone()) {
+                    Some(name) => RespData::BulkString(name),
+                    None => RespData::Null,
+                }
+            }
+            "SETNAME" => {
+                let [RespData::BulkString(name)] = args else {
+                    return wrong_arity();
+                };
+                if let Some(error) = invalid_client_name(name) {
+                    return error;
+                }
+                if let Some(client) = self.current_client_mut() {
+                    client.name = (!name.is_empty()).then(|| name.clone());
+                }
+                RespData::SimpleString("OK".to_string())
+            }
+            "INFO" => {
+                if !args.is_empty() {
+                    return wrong_arity();
+                }
+                match self.current_client() {
+                    Some(client) => RespData::BulkString(format!("{}\n", client.describe())),
+                    None => RespData::Null,
+                }
+            }
+            "LIST" => self.client_list(args),
+            "KILL" => self.client_kill(args),
+            "PAUSE" => {
+                let (timeout, mode) = match args {
+                    [RespData::BulkString(timeout)] => (timeout, "ALL".to_string()),
+                    [RespData::BulkString(timeout), RespData::BulkString(mode)] => {
+                        (timeout, mode.to_uppercase())
+                    }
+                    _ => return wrong_arity(),
+                };
+                let Ok(timeout) = timeout.parse::<u64>() else {
+                    return RespData::Error(
+                        "timeout is not an integer or out of range".to_string(),
+                    );
+                };
+                let writes_only = match mode.as_str() {
+                    "ALL" => false,
+                    "WRITE" => true,
+                    _ => return errors::syntax_error(),
+                };
+                self.pause = Some(ClientPause {
+                    deadline: Instant::now() + Duration::from_millis(timeout),
+                    writes_only,
+                });
+                RespData::SimpleString("OK".to_string())
+            }
+            "UNPAUSE" => {
+                if !args.is_empty() {
+                    return wrong_arity();
+                }
+                self.pause = None;
+                RespData::SimpleString("OK".to_string())
+            }
+            flag @ ("NO-EVICT" | "NO-TOUCH") => {
+                let [RespData::BulkString(value)] = args else {
+                    return wrong_arity();
+                };
+                let enabled = match value.to_uppercase().as_str() {
+                    "ON" => true,
+                    "OFF" => false,
+                    _ => return errors::syntax_error(),
+                };
+                if let Some(client) = self.current_client_mut() {
+                    if flag == "NO-EVICT" {
+                        client.no_evict = enabled;
+                    } else {
+                        client.no_touch = enabled;
+                    }
+                }
+                RespData::SimpleString("OK".to_string())
+            }
+            "REPLY" => {
+                let [RespData::BulkString(mode)] = args else {
+                    return wrong_arity();
+                };
+                let mode = match mode.to_uppercase().as_str() {
+                    "ON" => ReplyMode::On,
+                    "OFF" => ReplyMode::Off,
+                    "SKIP" => ReplyMode::Skip,
+                    _ => return errors::syntax_error(),
+                };
+                if let Some(client) = self.current_client_mut() {
+                    client.reply_mode = mode;
+                }
+                RespData::SimpleString("OK".to_string())
+            }
+            "TRACKING" => self.client_tracking(args),
+            "CACHING" => self.client_caching(args),
+            "GETREDIR" => {
+                if !args.is_empty() {
+                    return wrong_arity();
+                }
+                let redirect = self.current_client().map_or(-1, |client| {
+                    client.tracking.as_ref().map_or(-1, |tracking| {
+                        tracking.redirect.map_or(0, |redirect| redirect as i64)
+                    })
+                });
+                RespData::Integer(redirect)
+            }
+            _ => errors::unknown_subcommand(subcommand, "client"),
+        }
+    }
+
+    pub(super) fn current_client(&self) -> Option<&Client> {
+        self.current_client.and_then(|id| self.clients.get(&id))
+    }
+
+    pub(super) fn current_client_mut(&mut self) -> Option<&mut Client> {
+        self.current_client.and_then(|id| self.clients.get_mut(&id))
+    }
+
+    /// Whether the connection should be closed after its last reply has been written.
+    pub fn client_closing(&self, id: ClientId) -> bool {
+        self.clients
+            .get(&id)
+            .is_none_or(|client| client.close_after_reply)
+    }
+
+    pub(super) fn current_client_no_touch(&self) -> bool {
+        self.current_client().is_some_and(|client| client.no_touch)
+    }
+
+    /// CLIENT LIST [TYPE normal|master|replica|pubsub] [ID client-id ...]
+    fn client_list(&self, args: &[RespData]) -> RespData {
+        let mut type_filter = None;
+        let mut id_filter = None;
+
+        let mut args = args.iter();
+        while let Some(arg) = args.next() {
+            let RespData::BulkString(option) = arg else {
+                return errors::syntax_error();
+            };
+            match option.to_uppercase().as_str() {
+                "TYPE" => {
+                    let Some(RespData::BulkString(client_type)) = args.next() else {
+                        return errors::syntax_error();
+                    };
+                    let client_type = client_type.to_lowercase();
+                    if !["normal", "master", "replica", "slave", "pubsub"]
+                        .contains(&client_type.as_str())
+                    {
+                        return RespData::Error(format!("Unknown client type '{client_type}'"));
+                    }
+                    type_filter = Some(client_type);
+                }
+                "ID" => {
+                    let mut ids = Vec::new();
+                    for arg in args.by_ref() {
+                        let RespData::BulkString(id) = arg else {
+                            return errors::syntax_error();
+                        };
+                        match id.parse::<ClientId>() {
+                            Ok(id) if id > 0 => ids.push(id),
+                            _ => return RespData::Error("Invalid client ID".to_string()),
+                        }
+                    }
+                    if ids.is_empty() {
+                        return errors::syntax_error();
+                    }
+                    id_filter = Some(ids);
+                }
+                _ => return errors::syntax_error(),
+            }
+        }
+
+        let mut output = String::new();
+        for client in self.clients.values() {
+            // Replication and pub/sub are not supported, so every client is a normal one.
+            if type_filter.as_ref().is_some_and(|t| t != "normal") {
+                continue;
+            }
+            if id_filter
+                .as_ref()
+                .is_some_and(|ids| !ids.contains(&client.id))
+            {
+                continue;
+            }
+            output.push_str(&client.describe());
+            output.push('\n');
+        }
+        RespData::BulkString(output)
+    }
+
+    /// CLIENT KILL addr:port, or CLIENT KILL followed by filter/value pairs:
+    /// ID, ADDR, LADDR, USER, TYPE, MAXAGE and SKIPME.
+    fn client_kill(&mut self, args: &[RespData]) -> RespData {
+        let mut strings = Vec::with_capacity(args.len());
+        for arg in args {
+            let RespData::BulkString(arg) = arg else {
+                return errors::syntax_error();
+            };
+            strings.push(arg.as_str());
+        }
+
+        // The legacy form takes a single address and replies OK or an error.
+        if let [addr] = strings.as_slice() {
+            let killed = self.kill_clients(|client| client.addr.to_string() == *addr);
+            return if killed == 0 {
+                RespData::Error("No such client".to_string())
+            } else {
+                RespData::SimpleString("OK".to_string())
+            };
+        }
+        if strings.is_empty() || !strings.len().is_multiple_of(2) {
+            return errors::syntax_error();
+        }
+
+        let mut filter = KillFilter::default();
+        for pair in strings.chunks_exact(2) {
+            let value = pair[1];
+            match pair[0].to_uppercase().as_str() {
+                "ID" => match value.parse::<ClientId>() {
+                    Ok(id) if id > 0 => filter.id = Some(id),
+                    _ => return RespData::Error("client-id should be greater than 0".to_string()),
+                },
+                "ADDR" => filter.addr = Some(value.to_string()),
+                "LADDR" => filter.laddr = Some(value.to_string()),
+                "USER" => filter.user = Some(value.to_string()),
+                "TYPE" => {
+                    let client_type = value.to_lowercase();
+                    if !["normal", "master", "replica", "slave", "pubsub"]
+                        .contains(&client_type.as_str())
+                    {
+                        return RespData::Error(format!("Unknown client type '{value}'"));
+                    }
+                    filter.client_type = Some(client_type);
+                }
+                "MAXAGE" => match value.parse::<u64>() {
+                    Ok(seconds) => filter.max_age = Some(Duration::from_secs(seconds)),
+                    _ => return errors::not_an_integer(),
+                },
+                "SKIPME" => match value.to_lowercase().as_str() {
+                    "yes" => filter.skip_me = true,
+                    "no" => filter.skip_me = false,
+                    _ => return errors::syntax_error(),
+                },
+                _ => return errors::syntax_error(),
+            }
+        }
+
+        let current = self.current_client;
+        let killed = self.kill_clients(|client| {
+            !(filter.skip_me && Some(client.id) == current) && filter.matches(client)
+        });
+        RespData::Integer(killed as i64)
+    }
+
+    /// Disconnects clients that have been idle for longer than the `timeout` setting, returning
+    /// how many were closed.
+    pub fn close_idle_clients(&mut self) -> usize {
+        let timeout = self.config.read().unwrap().timeout;
+        if timeout == 0 {
+            return 0;
+        }
+        let timeout = Duration::from_secs(timeout);
+        self.kill_clients(|client| client.last_interaction.elapsed() > timeout)
+    }
+
+    /// Disconnects every client, e.g. when the server is stopped from within the process.
+    pub fn disconnect_clients(&mut self) -> usize {
+        self.kill_clients(|_| true)
+    }
+
+    /// Disconnects and deregisters every client matching `predicate`, returning how many were
+    /// killed.
+    pub(super) fn kill_clients(&mut self, predicate: impl Fn(&Client) -> bool) -> usize {
+        let ids: Vec<ClientId> = self
+            .clients
+            .values()
+            .filter(|client| predicate(client))
+            .map(|client| client.id)
+            .collect();
+        for id in &ids {
+            if let Some(client) = self.clients.remove(id) {
+                if let Some(stream) = client.stream {
+                    // Shutting the socket down wakes the connection thread, which then exits.
+                    let _ = stream.shutdown(Shutdown::Both);
+                }
+            }
+        }
+        ids.len()
+    }
+}
+
+struct KillFilter {
+    id: Option<ClientId>,
+    addr: Option<String>,
+    laddr: Option<String>,
+    user: Option<String>,
+    client_type: Option<String>,
+    max_age: Option<Duration>,
+    skip_me: bool,
+}
+
+impl Default for KillFilter {
+    fn default() -> Self {
+        Self {
+            id: None,
+            addr: None,
+            laddr: None,
+            user: None,
+            client_type: None,
+            max_age: None,
+            skip_me: true,
+        }
+    }
+}
+
+impl KillFilter {
+    fn matches(&self, client: &Client) -> bool {
+        self.id.is_none_or(|id| id == client.id)
+            && self
+                .addr
+                .as_ref()
+                .is_none_or(|addr| *addr == client.addr.to_string())
+            && self
+                .laddr
+                .as_ref()
+                .is_none_or(|laddr| *laddr == client.laddr.to_string())
+            && self.user.as_ref().is_none_or(|user| user == "default")
+            && self
+                .client_type
+                .as_ref()
+                .is_none_or(|client_type| client_type == "normal")
+            && self
+                .max_age
+                .is_none_or(|max_age| client.created.elapsed() >= max_age)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::Config;
+    use std::collections::HashMap;
+    use std::sync::{Arc, RwLock};
+
+    fn command(args: &[&str]) -> RespData {
+        RespData::array(args.iter().copied())
+    }
+
+    fn create_handler_with_clients() -> (CommandHandler, ClientId, ClientId) {
+        let mut handler = CommandHandler::from(HashMap::new());
+        let laddr = "127.0.0.1:6379".parse().unwrap();
+        let first = handler.register_client("127.0.0.1:5001".parse().unwrap(), laddr, 7, None);
+        let second = handler.register_client("127.0.0.1:5002".parse().unwrap(), laddr, 8, None);
+        (handler, first, second)
+    }
+
+    #[test]
+    fn test_client_id() {
+        let (mut handler, first, second) = create_handler_with_clients();
+
+        assert_eq!(
+            handler
+                .handle_client(first, &command(&["CLIENT", "ID"]))
+                .unwrap(),
+            RespData::Integer(first as i64)
+        );
+        assert_eq!(
+            handler
+                .handle_client(second, &command(&["CLIENT", "ID"]))
+                .unwrap(),
+            RespData::Integer(second as i64)
+        );
+    }
+
+    #[test]
+    fn test_client_setname_getname() {
+        let (mut handler, first, second) = create_handler_with_clients();
+
+        let test_cases = [
+            (
+                "Name is unset by default",
+                command(&["CLIENT", "GETNAME"]),
+                RespData::Null,
+            ),
+            (
+                "Valid SETNAME",
+                command(&["CLIENT", "SETNAME", "worker-1"]),
+                RespData::SimpleString("OK".to_string()),
+            ),
+            (
+                "GETNAME returns the new name",
+                command(&["CLIENT", "GETNAME"]),
+                RespData::BulkString("worker-1".to_string()),
+            ),
+            (
+                "Name with a space",
+                command(&["CLIENT", "SETNAME", "worker 1"]),
+                RespData::Error(
+                    "Client names cannot contain spaces, newlines or special characters."
+                        .to_string(),
+                ),
+            ),
+            (
+                "Not enough arguments",
+                command(&["CLIENT", "SETNAME"]),
+                RespData::Error(
+                    "wrong number of arguments for 'client|setname' command".to_string(),
+                ),
+            ),
+        ];
+
+        for (name, input, expected_output) in test_cases {
+            let result = handler.handle_client(first, &input).unwrap();
+            assert_eq!(result, expected_output, "{}", name);
+        }
+        assert_eq!(
+            handler
+                .handle_client(second, &command(&["CLIENT", "GETNAME"]))
+                .unwrap(),
+            RespData::Null
+        );
+    }
+
+    #[test]
+    fn test_client_list() {
+        let (mut handler, first, second) = create_handler_with_clients();
+        handler
+            .handle_client(first, &command(&["CLIENT", "SETNAME", "first"]))
+            .unwrap();
+
+        let list = |handler: &mut CommandHandler, args: &[&str]| {
+            let RespData::BulkString(output) =
+                handler.handle_client(second, &command(args)).unwrap()
+            else {
+                panic!("CLIENT LIST did not return a bulk string");
+            };
+            output
+                .lines()
+                .map(|line| line.to_string())
+                .collect::<Vec<_>>()
+        };
+
+        let all = list(&mut handler, &["CLIENT", "LIST"]);
+        assert_eq!(all.len(), 2);
+        assert!(all[0].starts_with(&format!(
+            "id={first} addr=127.0.0.1:5001 laddr=127.0.0.1:6379 fd=7 name=first age="
+        )));
+        assert!(all[0].contains(" cmd=client|setname "));
+        assert!(all[1].starts_with(&format!("id={second} addr=127.0.0.1:5002")));
+        assert!(all[1].contains(" cmd=client|list "));
+
+        assert_eq!(
+            list(&mut handler, &["CLIENT", "LIST", "TYPE", "normal"]).len(),
+            2
+        );
+        assert!(list(&mut handler, &["CLIENT", "LIST", "TYPE", "pubsub"]).is_empty());
+        let by_id = list(&mut handler, &["CLIENT", "LIST", "ID", &second.to_string()]);
+        assert_eq!(by_id.len(), 1);
+        assert!(by_id[0].starts_with(&format!("id={second} ")));
+
+        assert_eq!(
+            handler
+                .handle_client(first, &command(&["CLIENT", "LIST", "TYPE", "bogus"]))
+                .unwrap(),
+            RespData::Error("Unknown client type 'bogus'".to_string())
+        );
+
+        handler.unregister_client(first);
+        assert_eq!(list(&mut handler, &["CLIENT", "LIST"]).len(), 1);
+    }
+
+    #[test]
+    fn test_client_info() {
+        let (mut handler, first, _) = create_handler_with_clients();
+
+        let RespData::BulkString(info) = handler
+            .handle_client(first, &command(&["CLIENT", "INFO"]))
+            .unwrap()
+        else {
+            panic!("CLIENT INFO did not return a bulk string");
+        };
+        assert!(info.starts_with(&format!("id={first} ")));
+        assert!(info.ends_with("resp=2\n"));
+    }
+
+    #[test]
+    fn test_client_kill() {
+        let (mut handler, first, second) = create_handler_with_clients();
+        let third = handler.register_client(
+            "10.0.0.1:6000".parse().unwrap(),
+            "10.0.0.2:6379".parse().unwrap(),
+            9,
+            None,
+        );
+
+        let test_cases = [
+            (
+                "Unknown legacy address",
+                command(&["CLIENT", "KILL", "127.0.0.1:9999"]),
+                RespData::Error("No such client".to_string()),
+            ),
+            (
+                "Killing yourself is skipped by default",
+                command(&["CLIENT", "KILL", "ID", &first.to_string()]),
+                RespData::Integer(0),
+            ),
+            (
+                "Filters must all match",
+                command(&["CLIENT", "KILL", "LADDR", "10.0.0.2:6379", "USER", "nobody"]),
+                RespData::Integer(0),
+            ),
+            (
+                "Kill by local address",
+                command(&["CLIENT", "KILL", "LADDR", "10.0.0.2:6379"]),
+                RespData::Integer(1),
+            ),
+            (
+                "Clients younger than MAXAGE survive",
+                command(&["CLIENT", "KILL", "MAXAGE", "100"]),
+                RespData::Integer(0),
+            ),
+            (
+                "Legacy address form",
+                command(&["CLIENT", "KILL", "127.0.0.1:5002"]),
+                RespData::SimpleString("OK".to_string()),
+            ),
+            (
+                "Invalid ID",
+                command(&["CLIENT", "KILL", "ID", "0"]),
+                RespData::Error("client-id should be greater than 0".to_string()),
+            ),
+            (
+                "Dangling filter",
+                command(&["CLIENT", "KILL", "ID", "1", "SKIPME"]),
+                RespData::Error("syntax error".to_string()),
+            ),
+            (
+                "Kill yourself with SKIPME no",
+                command(&["CLIENT", "KILL", "USER", "default", "SKIPME", "no"]),
+                RespData::Integer(1),
+            ),
+        ];
+
+        for (name, input, expected_output) in test_cases {
+            let result = handler.handle_client(first, &input).unwrap();
+            assert_eq!(result, expected_output, "{}", name);
+        }
+        for id in [first, second, third] {
+            assert!(!handler.clients.contains_key(&id));
+        }
+    }
+
+    #[test]
+    fn test_client_kill_closes_socket() {
+        use std::io::Read;
+        use std::net::TcpListener;
+
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let mut peer = TcpStream::connect(listener.local_addr().unwrap()).unwrap();
+        let (stream, addr) = listener.accept().unwrap();
+
+        let mut handler = CommandHandler::from(HashMap::new());
+        let victim = handler.register_client(addr, stream.local_addr().unwrap(), 0, Some(stream));
+
+        assert_eq!(
+            handler.handle(&command(&["CLIENT", "KILL", "ID", &victim.to_string()])),
+            RespData::Integer(1)
+        );
+        let mut buf = [0; 1];
+        assert_eq!(peer.read(&mut buf).unwrap(), 0);
+    }
+
+    #[test]
+    fn test_client_pause() {
+        let (mut handler, first, _) = create_handler_with_clients();
+        let get = command(&["GET", "key"]);
+        let set = command(&["SET", "key", "value"]);
+
+        assert_eq!(
+            handler
+                .handle_client(first, &command(&["CLIENT", "PAUSE", "10000", "WRITE"]))
+                .unwrap(),
+            RespData::SimpleString("OK".to_string())
+        );
+        assert_eq!(handler.pause_remaining(&get), None);
+        assert!(handler.pause_remaining(&set).is_some());
+
+        handler.handle_client(first, &command(&["CLIENT", "PAUSE", "10000"]));
+        assert!(handler.pause_remaining(&get).is_some());
+        assert_eq!(
+            handler.pause_remaining(&command(&["CLIENT", "UNPAUSE"])),
+            None
+        );
+
+        handler.handle_client(first, &command(&["CLIENT", "UNPAUSE"]));
+        assert_eq!(handler.pause_remaining(&set), None);
+
+        handler.handle_client(first, &command(&["CLIENT", "PAUSE", "0"]));
+        assert_eq!(handler.pause_remaining(&set), None);
+
+        assert_eq!(
+            handler
+                .handle_client(first, &command(&["CLIENT", "PAUSE", "-1"]))
+                .unwrap(),
+            RespData::Error("timeout is not an integer or out of range".to_string())
+        );
+        assert_eq!(
+            handler
+                .handle_client(first, &command(&["CLIENT", "PAUSE", "10", "READ"]))
+                .unwrap(),
+            RespData::Error("syntax error".to_string())
+        );
+    }
+
+    #[test]
+    fn test_client_reply() {
+        let (mut handler, first, _) = create_handler_with_clients();
+        let ping = command(&["PING"]);
+
+        let test_cases = [
+            ("Replies are on by default", ping.clone(), true),
+            (
+                "REPLY OFF is not answered",
+                command(&["CLIENT", "REPLY", "OFF"]),
+                false,
+            ),
+            ("No replies while off", ping.clone(), false),
+            (
+                "REPLY ON is answered",
+                command(&["CLIENT", "REPLY", "ON"]),
+                true,
+            ),
+            (
+                "REPLY SKIP is not answered",
+                command(&["CLIENT", "REPLY", "SKIP"]),
+                false,
+            ),
+            ("The next reply is skipped", ping.clone(), false),
+            ("Replies resume after the skip", ping.clone(), true),
+        ];
+
+        for (name, input, replied) in test_cases {
+            let result = handler.handle_client(first, &input);
+            assert_eq!(result.is_some(), replied, "{}", name);
+        }
+    }
+
+    #[test]
+    fn test_client_no_evict_and_no_touch() {
+        let (mut handler, first, second) = create_handler_with_clients();
+        handler.handle_client(second, &command(&["SET", "key", "value"]));
+        let written = handler.access_times.get("key").copied().unwrap();
+
+        let test_cases = [
+            (
+                "Enable NO-EVICT",
+                command(&["CLIENT", "NO-EVICT", "on"]),
+                RespData::SimpleString("OK".to_string()),
+            ),
+            (
+                "Enable NO-TOUCH",
+                command(&["CLIENT", "NO-TOUCH", "ON"]),
+                RespData::SimpleString("OK".to_string()),
+            ),
+            (
+                "Invalid value",
+                command(&["CLIENT", "NO-TOUCH", "maybe"]),
+                RespData::Error("syntax error".to_string()),
+            ),
+            (
+                "Missing value",
+                command(&["CLIENT", "NO-EVICT"]),
+                RespData::Error(
+                    "wrong number of arguments for 'client|no-evict' command".to_string(),
+                ),
+            ),
+        ];
+
+        for (name, input, expected_output) in test_cases {
+            let result = handler.handle_client(first, &input).unwrap();
+            assert_eq!(result, expected_output, "{}", name);
+        }
+
+        let RespData::BulkString(info) = handler
+            .handle_client(first, &command(&["CLIENT", "INFO"]))
+            .unwrap()
+        else {
+            panic!("CLIENT INFO did not return a bulk string");
+        };
+        assert!(info.contains(" flags=eT "));
+
+        handler.handle_client(first, &command(&["GET", "key"]));
+        assert_eq!(handler.access_times.get("key").copied().unwrap(), written);
+        handler.handle_client(second, &command(&["GET", "key"]));
+        assert!(handler.access_times.get("key").copied().unwrap() > written);
+    }
+
+    #[test]
+    fn test_maxclients() {
+        let config = Config {
+            maxclients: 1,
+            requirepass: Some("pw".to_string()),
+            ..Config::default()
+        };
+        let mut handler = CommandHandler::new(HashMap::new(), Arc::new(RwLock::new(config)));
+        let laddr = "127.0.0.1:6379".parse().unwrap();
+        let connect = |handler: &mut CommandHandler, port: u16| {
+            assert!(handler.accepting_clients());
+            let addr = format!("127.0.0.1:{port}").parse().unwrap();
+            handler.register_client(addr, laddr, port as i32, None)
+        };
+        let regular = connect(&mut handler, 5001);
+        let intruder = connect(&mut handler, 5002);
+        let admin = connect(&mut handler, 5003);
+        assert!(!handler.clients[&regular].reserved_slot);
+        assert!(handler.clients[&intruder].reserved_slot);
+
+        let test_cases = [
+            (
+                "Regular slot is not limited",
+                regular,
+                command(&["AUTH", "pw"]),
+                RespData::SimpleString("OK".to_string()),
+            ),
+            (
+                "Reserved slot without AUTH",
+                intruder,
+                command(&["PING"]),
+                RespData::Error("max number of clients reached".to_string()),
+            ),
+            (
+                "Reserved slot may authenticate",
+                admin,
+                command(&["AUTH", "pw"]),
+                RespData::SimpleString("OK".to_string()),
+            ),
+            (
+                "Reserved slot after authenticating as an admin",
+                admin,
+                command(&["PING"]),
+                RespData::SimpleString("PONG".to_string()),
+            ),
+        ];
+
+        for (name, id, input, expected) in test_cases {
+            assert_eq!(
+                handler.handle_client(id, &input),
+                Some(expected),
+                "{}",
+                name
+            );
+        }
+        assert!(handler.client_closing(intruder));
+        assert!(!handler.client_closing(admin));
+
+        for port in 5004..5006 {
+            connect(&mut handler, port);
+        }
+        assert!(!handler.accepting_clients());
+        assert_eq!(handler.stats.rejected_connections, 1);
+    }
+
+    #[test]
+    fn test_maxclients_without_password() {
+        let config = Config {
+            maxclients: 1,
+            ..Config::default()
+        };
+        let mut handler = CommandHandler::new(HashMap::new(), Arc::new(RwLock::new(config)));
+        let laddr = "127.0.0.1:6379".parse().unwrap();
+        let mut connect = |port: u16| {
+            let addr = format!("127.0.0.1:{port}").parse().unwrap();
+            handler.register_client(addr, laddr, port as i32, None)
+        };
+        let regular = connect(5001);
+        let extra = connect(5002);
+
+        assert_eq!(
+            handler.handle_client(regular, &command(&["PING"])),
+            Some(RespData::SimpleString("PONG".to_string()))
+        );
+        assert_eq!(
+            handler.handle_client(extra, &command(&["AUTH", "default", "anything"])),
+            Some(RespData::SimpleString("OK".to_string()))
+        );
+        assert_eq!(
+            handler.handle_client(extra, &command(&["PING"])),
+            Some(RespData::Error("max number of clients reached".to_string()))
+        );
+        assert!(handler.client_closing(extra));
+    }
+
+    #[test]
+    fn test_close_idle_clients() {
+        let (mut handler, first, second) = create_handler_with_clients();
+        let long_ago = Instant::now() - Duration::from_secs(10);
+        handler.clients.get_mut(&first).unwrap().last_interaction = long_ago;
+
+        assert_eq!(handler.close_idle_clients(), 0);
+        handler.config.write().unwrap().timeout = 5;
+        assert_eq!(handler.close_idle_clients(), 1);
+        assert!(!handler.clients.contains_key(&first));
+        assert!(handler.clients.contains_key(&second));
+    }
+
+    #[test]
+    fn test_client_output_append() {
+        let test_cases = [
+            ("Nothing queued", None, "*0\r\n"),
+            ("After a reply", Some(RespData::Integer(1)), ":1\r\n*0\r\n"),
+        ];
+
+        for (name, before, expected) in test_cases {
+            let output = ClientOutput::default();
+            if let Some(reply) = before {
+                output.reply(&reply);
+            }
+            output.append(b"*0\r\n".to_vec());
+            assert_eq!(output.take(), expected.as_bytes(), "{}", name);
+        }
+    }
+}
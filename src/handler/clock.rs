@@ -0,0 +1,43 @@
+//! The time source for everything that ages keys: expiry deadlines, TTLs and idle times. Tests
+//! swap in a `MockClock` to move time forward without sleeping.
+
+use std::time::Instant;
+
+pub trait Clock: Send + Sync {
+    fn now(&self) -> Instant;
+}
+
+/// The real monotonic clock.
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> Instant {
+        Instant::now()
+    }
+}
+
+/// A clock that only moves when told to.
+#[cfg(test)]
+pub struct MockClock {
+    now: std::sync::Mutex<Instant>,
+}
+
+#[cfg(test)]
+impl MockClock {
+    pub fn new() -> Self {
+        Self {
+            now: std::sync::Mutex::new(Instant::now()),
+        }
+    }
+
+    pub fn advance(&self, by: std::time::Duration) {
+        *self.now.lock().unwrap() += by;
+    }
+}
+
+#[cfg(test)]
+impl Clock for MockClock {
+    fn now(&self) -> Instant {
+        *self.now.lock().unwrap()
+    }
+}
@@ -0,0 +1,1947 @@
+//! The data commands as a typed `Command`, parsed from RESP in one place so that the code
+//! running them gets checked, plain values. The server administration commands with their
+//! many subcommands, like CONFIG or CLIENT, still parse their own arguments.
+
+use super::bloom::DEFAULT_EXPANSION;
+use super::errors;
+use super::timeseries::tsdb_error;
+use super::{command_name, CommandHandler};
+use crate::json::{Json, Number, Path};
+use crate::resp::RespData;
+use crate::search::{DistanceMetric, SchemaField, TextField, VectorAlgorithm, VectorField};
+use crate::timeseries::{Aggregation, DuplicatePolicy, LabelFilter};
+use std::iter::Peekable;
+use std::slice;
+use std::time::Duration;
+
+/// TOPK.RESERVE's defaults, as in RedisBloom.
+const TOPK_WIDTH: u32 = 8;
+const TOPK_DEPTH: u32 = 7;
+const TOPK_DECAY: f64 = 0.9;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Command {
+    Ping,
+    Echo {
+        message: String,
+    },
+    Get {
+        key: String,
+    },
+    Set {
+        key: String,
+        value: String,
+        options: SetOptions,
+    },
+    Strlen {
+        key: String,
+    },
+    Append {
+        key: String,
+        value: String,
+    },
+    GetRange {
+        key: String,
+        start: i64,
+        end: i64,
+    },
+    SetRange {
+        key: String,
+        offset: usize,
+        value: String,
+    },
+    Incr {
+        key: String,
+    },
+    Decr {
+        key: String,
+    },
+    IncrBy {
+        key: String,
+        increment: i64,
+    },
+    DecrBy {
+        key: String,
+        decrement: i64,
+    },
+    HSet {
+        key: String,
+        fields: Vec<(String, String)>,
+    },
+    HGet {
+        key: String,
+        field: String,
+    },
+    HLen {
+        key: String,
+    },
+    HGetAll {
+        key: String,
+    },
+    Expire {
+        key: String,
+        seconds: i64,
+    },
+    PExpire {
+        key: String,
+        milliseconds: i64,
+    },
+    Ttl {
+        key: String,
+    },
+    PTtl {
+        key: String,
+    },
+    Persist {
+        key: String,
+    },
+    Del {
+        keys: Vec<String>,
+    },
+    Unlink {
+        keys: Vec<String>,
+    },
+    Type {
+        key: String,
+    },
+    Scan {
+        cursor: u64,
+        options: ScanOptions,
+    },
+    FlushDb {
+        mode: Option<FlushMode>,
+    },
+    FlushAll {
+        mode: Option<FlushMode>,
+    },
+    JsonSet {
+        key: String,
+        path: Path,
+        value: Json,
+        condition: Option<SetCondition>,
+    },
+    JsonGet {
+        key: String,
+        paths: Vec<Path>,
+    },
+    JsonDel {
+        key: String,
+        path: Path,
+    },
+    JsonType {
+        key: String,
+        path: Path,
+    },
+    JsonNumIncrBy {
+        key: String,
+        path: Path,
+        increment: Number,
+    },
+    JsonArrAppend {
+        key: String,
+        path: Path,
+        values: Vec<Json>,
+    },
+    BfReserve {
+        key: String,
+        error_rate: f64,
+        capacity: u64,
+        /// `None` for NONSCALING.
+        expansion: Option<u32>,
+    },
+    BfAdd {
+        key: String,
+        item: String,
+    },
+    BfMAdd {
+        key: String,
+        items: Vec<String>,
+    },
+    BfExists {
+        key: String,
+        item: String,
+    },
+    BfMExists {
+        key: String,
+        items: Vec<String>,
+    },
+    CmsInitByDim {
+        key: String,
+        width: u32,
+        depth: u32,
+    },
+    CmsIncrBy {
+        key: String,
+        items: Vec<(String, u64)>,
+    },
+    CmsQuery {
+        key: String,
+        items: Vec<String>,
+    },
+    TopkReserve {
+        key: String,
+        k: u32,
+        width: u32,
+        depth: u32,
+        decay: f64,
+    },
+    TopkAdd {
+        key: String,
+        items: Vec<String>,
+    },
+    TopkList {
+        key: String,
+        with_count: bool,
+    },
+    TopkQuery {
+        key: String,
+        items: Vec<String>,
+    },
+    TsCreate {
+        key: String,
+        options: TsOptions,
+    },
+    TsAdd {
+        key: String,
+        /// `None` for `*`, the current time.
+        timestamp: Option<u64>,
+        value: f64,
+        options: TsOptions,
+        on_duplicate: Option<DuplicatePolicy>,
+    },
+    TsRange {
+        key: String,
+        from: u64,
+        to: u64,
+        options: RangeOptions,
+    },
+    TsMRange {
+        from: u64,
+        to: u64,
+        options: RangeOptions,
+        filters: Vec<LabelFilter>,
+    },
+    FtCreate {
+        index: String,
+        /// The key prefixes of the hashes to index, all of them if empty.
+        prefixes: Vec<String>,
+        fields: Vec<SchemaField>,
+    },
+    FtSearch {
+        index: String,
+        query: String,
+        options: SearchOptions,
+    },
+}
+
+/// The options of SET.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct SetOptions {
+    /// The TTL given with EX or PX.
+    pub expire: Option<Duration>,
+    /// KEEPTTL: keep the key's TTL instead of removing it.
+    pub keep_ttl: bool,
+}
+
+/// The options of SCAN.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ScanOptions {
+    pub pattern: Option<String>,
+    pub count: Option<usize>,
+    pub type_name: Option<String>,
+}
+
+/// The options of FT.SEARCH.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct SearchOptions {
+    /// NOCONTENT: reply with the keys only, not the fields.
+    pub no_content: bool,
+    pub with_scores: bool,
+    /// LIMIT: the offset and number of results.
+    pub limit: Option<(usize, usize)>,
+    /// PARAMS: the values of the query's `$name` parameters.
+    pub params: Vec<(String, String)>,
+}
+
+/// How FLUSHDB and FLUSHALL free the keys; without one, `lazyfree-lazy-user-flush` decides.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FlushMode {
+    Async,
+    Sync,
+}
+
+/// The options of TS.CREATE, and of TS.ADD for the series it creates.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct TsOptions {
+    /// RETENTION: how long to keep samples for, in milliseconds.
+    pub retention: Option<u64>,
+    pub duplicate_policy: Option<DuplicatePolicy>,
+    pub labels: Vec<(String, String)>,
+}
+
+/// The options of TS.RANGE and TS.MRANGE.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct RangeOptions {
+    pub count: Option<usize>,
+    /// AGGREGATION: how to combine samples, and the size of the buckets in milliseconds.
+    pub aggregation: Option<(Aggregation, u64)>,
+    /// WITHLABELS, for TS.MRANGE only.
+    pub with_labels: bool,
+}
+
+/// JSON.SET's NX or XX, to only set a path that doesn't or does exist yet.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SetCondition {
+    Nx,
+    Xx,
+}
+
+impl Command {
+    /// The command's lowercase name.
+    pub fn name(&self) -> &'static str {
+        match self {
+            Command::Ping => "ping",
+            Command::Echo { .. } => "echo",
+            Command::Get { .. } => "get",
+            Command::Set { .. } => "set",
+            Command::Strlen { .. } => "strlen",
+            Command::Append { .. } => "append",
+            Command::GetRange { .. } => "getrange",
+            Command::SetRange { .. } => "setrange",
+            Command::Incr { .. } => "incr",
+            Command::Decr { .. } => "decr",
+            Command::IncrBy { .. } => "incrby",
+            Command::DecrBy { .. } => "decrby",
+            Command::HSet { .. } => "hset",
+            Command::HGet { .. } => "hget",
+            Command::HLen { .. } => "hlen",
+            Command::HGetAll { .. } => "hgetall",
+            Command::Expire { .. } => "expire",
+            Command::PExpire { .. } => "pexpire",
+            Command::Ttl { .. } => "ttl",
+            Command::PTtl { .. } => "pttl",
+            Command::Persist { .. } => "persist",
+            Command::Del { .. } => "del",
+            Command::Unlink { .. } => "unlink",
+            Command::Type { .. } => "type",
+            Command::Scan { .. } => "scan",
+            Command::FlushDb { .. } => "flushdb",
+            Command::FlushAll { .. } => "flushall",
+            Command::JsonSet { .. } => "json.set",
+            Command::JsonGet { .. } => "json.get",
+            Command::JsonDel { .. } => "json.del",
+            Command::JsonType { .. } => "json.type",
+            Command::JsonNumIncrBy { .. } => "json.numincrby",
+            Command::JsonArrAppend { .. } => "json.arrappend",
+            Command::BfReserve { .. } => "bf.reserve",
+            Command::BfAdd { .. } => "bf.add",
+            Command::BfMAdd { .. } => "bf.madd",
+            Command::BfExists { .. } => "bf.exists",
+            Command::BfMExists { .. } => "bf.mexists",
+            Command::CmsInitByDim { .. } => "cms.initbydim",
+            Command::CmsIncrBy { .. } => "cms.incrby",
+            Command::CmsQuery { .. } => "cms.query",
+            Command::TopkReserve { .. } => "topk.reserve",
+            Command::TopkAdd { .. } => "topk.add",
+            Command::TopkList { .. } => "topk.list",
+            Command::TopkQuery { .. } => "topk.query",
+            Command::TsCreate { .. } => "ts.create",
+            Command::TsAdd { .. } => "ts.add",
+            Command::TsRange { .. } => "ts.range",
+            Command::TsMRange { .. } => "ts.mrange",
+            Command::FtCreate { .. } => "ft.create",
+            Command::FtSearch { .. } => "ft.search",
+        }
+    }
+
+    /// Parses the command called `name`, lowercased, from its arguments, failing with the
+    /// error reply. `None` if `name` isn't one of the commands here.
+    pub(super) fn parse(name: &str, args: &[RespData]) -> Option<Result<Self, RespData>> {
+        let strings: Option<Vec<&str>> = args
+            .iter()
+            .map(|arg| match arg {
+                RespData::BulkString(arg) => Some(arg.as_str()),
+                _ => None,
+            })
+            .collect();
+        match strings {
+            Some(strings) => parse_strings(name, &strings),
+            // Parsing no arguments tells whether `name` is a command here at all.
+            None => parse_strings(name, &[]).map(|_| Err(errors::syntax_error())),
+        }
+    }
+}
+
+fn parse_strings(name: &str, args: &[&str]) -> Option<Result<Command, RespData>> {
+    let owned = |arg: &str| arg.to_string();
+    let command = match (name, args) {
+        // PING ignores any arguments instead of echoing one back.
+        ("ping", _) => Command::Ping,
+        ("echo", [message]) => Command::Echo {
+            message: owned(message),
+        },
+        ("get", [k]) => Command::Get { key: owned(k) },
+        ("set", [k, value, options @ ..]) => {
+            return Some(parse_set_options(options).map(|options| Command::Set {
+                key: owned(k),
+                value: owned(value),
+                options,
+            }))
+        }
+        ("strlen", [k]) => Command::Strlen { key: owned(k) },
+        ("append", [k, value]) => Command::Append {
+            key: owned(k),
+            value: owned(value),
+        },
+        ("getrange", [k, start, end]) => {
+            let (Ok(start), Ok(end)) = (start.parse(), end.parse()) else {
+                return Some(Err(errors::not_an_integer()));
+            };
+            Command::GetRange {
+                key: owned(k),
+                start,
+                end,
+            }
+        }
+        ("setrange", [k, offset, value]) => {
+            let Ok(offset) = offset.parse::<i64>() else {
+                return Some(Err(errors::not_an_integer()));
+            };
+            let Ok(offset) = usize::try_from(offset) else {
+                return Some(Err(RespData::Error("offset is out of range".to_string())));
+            };
+            Command::SetRange {
+                key: owned(k),
+                offset,
+                value: owned(value),
+            }
+        }
+        ("incr", [k]) => Command::Incr { key: owned(k) },
+        ("decr", [k]) => Command::Decr { key: owned(k) },
+        ("incrby", [k, increment]) => match increment.parse() {
+            Ok(increment) => Command::IncrBy {
+                key: owned(k),
+                increment,
+            },
+            Err(_) => return Some(Err(errors::not_an_integer())),
+        },
+        ("decrby", [k, decrement]) => match decrement.parse() {
+            Ok(decrement) => Command::DecrBy {
+                key: owned(k),
+                decrement,
+            },
+            Err(_) => return Some(Err(errors::not_an_integer())),
+        },
+        ("hset", [k, fields @ ..]) if !fields.is_empty() && fields.len() % 2 == 0 => {
+            Command::HSet {
+                key: owned(k),
+                fields: fields
+                    .chunks_exact(2)
+                    .map(|pair| (owned(pair[0]), owned(pair[1])))
+                    .collect(),
+            }
+        }
+        ("hget", [k, field]) => Command::HGet {
+            key: owned(k),
+            field: owned(field),
+        },
+        ("hlen", [k]) => Command::HLen { key: owned(k) },
+        ("hgetall", [k]) => Command::HGetAll { key: owned(k) },
+        ("expire", [k, seconds]) => match seconds.parse() {
+            Ok(seconds) => Command::Expire {
+                key: owned(k),
+                seconds,
+            },
+            Err(_) => return Some(Err(errors::not_an_integer())),
+        },
+        ("pexpire", [k, milliseconds]) => match milliseconds.parse() {
+            Ok(milliseconds) => Command::PExpire {
+                key: owned(k),
+                milliseconds,
+            },
+            Err(_) => return Some(Err(errors::not_an_integer())),
+        },
+        ("ttl", [k]) => Command::Ttl { key: owned(k) },
+        ("pttl", [k]) => Command::PTtl { key: owned(k) },
+        ("persist", [k]) => Command::Persist { key: owned(k) },
+        ("del", [_, ..]) => Command::Del {
+            keys: args.iter().map(|k| owned(k)).collect(),
+        },
+        ("unlink", [_, ..]) => Command::Unlink {
+            keys: args.iter().map(|k| owned(k)).collect(),
+        },
+        ("type", [k]) => Command::Type { key: owned(k) },
+        ("scan", [cursor, options @ ..]) => {
+            let Ok(cursor) = cursor.parse() else {
+                return Some(Err(RespData::Error("invalid cursor".to_string())));
+            };
+            return Some(
+                parse_scan_options(options).map(|options| Command::Scan { cursor, options }),
+            );
+        }
+        ("flushdb" | "flushall", mode) if mode.len() <= 1 => {
+            let mode = match mode.first() {
+                None => None,
+                Some(mode) if mode.eq_ignore_ascii_case("ASYNC") => Some(FlushMode::Async),
+                Some(mode) if mode.eq_ignore_ascii_case("SYNC") => Some(FlushMode::Sync),
+                Some(_) => return Some(Err(errors::syntax_error())),
+            };
+            if name == "flushdb" {
+                Command::FlushDb { mode }
+            } else {
+                Command::FlushAll { mode }
+            }
+        }
+        ("json.set", [k, path, value, condition @ ..]) if condition.len() <= 1 => {
+            let condition = match condition.first() {
+                None => None,
+                Some(c) if c.eq_ignore_ascii_case("NX") => Some(SetCondition::Nx),
+                Some(c) if c.eq_ignore_ascii_case("XX") => Some(SetCondition::Xx),
+                Some(_) => return Some(Err(errors::syntax_error())),
+            };
+            return Some(parse_path(path).and_then(|path| {
+                Ok(Command::JsonSet {
+                    key: owned(k),
+                    path,
+                    value: parse_json(value)?,
+                    condition,
+                })
+            }));
+        }
+        ("json.get", [k, paths @ ..]) => {
+            return Some(
+                paths
+                    .iter()
+                    .map(|path| parse_path(path))
+                    .collect::<Result<_, _>>()
+                    .map(|paths| Command::JsonGet {
+                        key: owned(k),
+                        paths,
+                    }),
+            )
+        }
+        ("json.del" | "json.type", [k, path @ ..]) if path.len() <= 1 => {
+            let path = match path.first() {
+                Some(path) => parse_path(path),
+                None => Ok(Path::root()),
+            };
+            return Some(path.map(|path| {
+                if name == "json.del" {
+                    Command::JsonDel {
+                        key: owned(k),
+                        path,
+                    }
+                } else {
+                    Command::JsonType {
+                        key: owned(k),
+                        path,
+                    }
+                }
+            }));
+        }
+        ("json.numincrby", [k, path, increment]) => {
+            return Some(
+                parse_path(path).and_then(|path| match parse_json(increment)? {
+                    Json::Number(increment) => Ok(Command::JsonNumIncrBy {
+                        key: owned(k),
+                        path,
+                        increment,
+                    }),
+                    _ => Err(RespData::Error("increment must be a number".to_string())),
+                }),
+            )
+        }
+        ("json.arrappend", [k, path, values @ ..]) if !values.is_empty() => {
+            return Some(parse_path(path).and_then(|path| {
+                Ok(Command::JsonArrAppend {
+                    key: owned(k),
+                    path,
+                    values: values
+                        .iter()
+                        .map(|value| parse_json(value))
+                        .collect::<Result<_, _>>()?,
+                })
+            }))
+        }
+        ("bf.reserve", [k, error_rate, capacity, options @ ..]) => {
+            let error_rate = match error_rate.parse::<f64>() {
+                Ok(rate) if rate > 0.0 && rate < 1.0 => rate,
+                Ok(_) => {
+                    return Some(Err(RespData::Error(
+                        "(0 < error rate range < 1)".to_string(),
+                    )))
+                }
+                Err(_) => return Some(Err(RespData::Error("bad error rate".to_string()))),
+            };
+            let capacity = match capacity.parse::<i64>() {
+                Ok(capacity) if capacity > 0 => capacity as u64,
+                Ok(_) => {
+                    return Some(Err(RespData::Error(
+                        "(capacity should be larger than 0)".to_string(),
+                    )))
+                }
+                Err(_) => return Some(Err(RespData::Error("bad capacity".to_string()))),
+            };
+            return Some(
+                parse_bloom_expansion(options).map(|expansion| Command::BfReserve {
+                    key: owned(k),
+                    error_rate,
+                    capacity,
+                    expansion,
+                }),
+            );
+        }
+        ("bf.add", [k, item]) => Command::BfAdd {
+            key: owned(k),
+            item: owned(item),
+        },
+        ("bf.exists", [k, item]) => Command::BfExists {
+            key: owned(k),
+            item: owned(item),
+        },
+        ("bf.madd" | "bf.mexists", [k, items @ ..]) if !items.is_empty() => {
+            let items = items.iter().map(|item| owned(item)).collect();
+            if name == "bf.madd" {
+                Command::BfMAdd {
+                    key: owned(k),
+                    items,
+                }
+            } else {
+                Command::BfMExists {
+                    key: owned(k),
+                    items,
+                }
+            }
+        }
+        ("cms.initbydim", [k, width, depth]) => {
+            let width = parse_dimension(width, "CMS: invalid width");
+            let depth = parse_dimension(depth, "CMS: invalid depth");
+            return Some(width.and_then(|width| {
+                Ok(Command::CmsInitByDim {
+                    key: owned(k),
+                    width,
+                    depth: depth?,
+                })
+            }));
+        }
+        ("cms.incrby", [k, items @ ..]) if !items.is_empty() && items.len() % 2 == 0 => {
+            let items = items
+                .chunks_exact(2)
+                .map(|pair| match pair[1].parse::<i64>() {
+                    Ok(increment) if increment >= 0 => Ok((owned(pair[0]), increment as u64)),
+                    _ => Err(RespData::Error("CMS: Cannot parse number".to_string())),
+                })
+                .collect::<Result<_, _>>();
+            return Some(items.map(|items| Command::CmsIncrBy {
+                key: owned(k),
+                items,
+            }));
+        }
+        ("topk.reserve", [k, top, options @ ..]) if matches!(options.len(), 0 | 3) => {
+            let top = parse_dimension(top, "TopK: invalid k");
+            let (width, depth, decay) = match options {
+                [width, depth, decay] => (
+                    parse_dimension(width, "TopK: invalid width"),
+                    parse_dimension(depth, "TopK: invalid depth"),
+                    match decay.parse::<f64>() {
+                        Ok(decay) if decay > 0.0 && decay <= 1.0 => Ok(decay),
+                        _ => Err(RespData::Error(
+                            "TopK: invalid decay value. must be '<= 1' & '> 0'".to_string(),
+                        )),
+                    },
+                ),
+                _ => (Ok(TOPK_WIDTH), Ok(TOPK_DEPTH), Ok(TOPK_DECAY)),
+            };
+            return Some(top.and_then(|top| {
+                Ok(Command::TopkReserve {
+                    key: owned(k),
+                    k: top,
+                    width: width?,
+                    depth: depth?,
+                    decay: decay?,
+                })
+            }));
+        }
+        ("topk.list", [k, option @ ..]) if option.len() <= 1 => {
+            let with_count = match option.first() {
+                None => false,
+                Some(option) if option.eq_ignore_ascii_case("WITHCOUNT") => true,
+                Some(_) => return Some(Err(errors::syntax_error())),
+            };
+            Command::TopkList {
+                key: owned(k),
+                with_count,
+            }
+        }
+        ("cms.query" | "topk.add" | "topk.query", [k, items @ ..]) if !items.is_empty() => {
+            let (key, items) = (owned(k), items.iter().map(|item| owned(item)).collect());
+            match name {
+                "cms.query" => Command::CmsQuery { key, items },
+                "topk.add" => Command::TopkAdd { key, items },
+                _ => Command::TopkQuery { key, items },
+            }
+        }
+        ("ts.create", [k, options @ ..]) => {
+            return Some(
+                parse_ts_options(options, false).map(|(options, _)| Command::TsCreate {
+                    key: owned(k),
+                    options,
+                }),
+            )
+        }
+        ("ts.add", [k, timestamp, value, options @ ..]) => {
+            let timestamp = match *timestamp {
+                "*" => None,
+                timestamp => match timestamp.parse() {
+                    Ok(timestamp) => Some(timestamp),
+                    Err(_) => return Some(Err(tsdb_error("invalid timestamp"))),
+                },
+            };
+            let value = match value.parse::<f64>() {
+                Ok(value) if !value.is_nan() => value,
+                _ => return Some(Err(tsdb_error("invalid value"))),
+            };
+            return Some(
+                parse_ts_options(options, true).map(|(options, on_duplicate)| Command::TsAdd {
+                    key: owned(k),
+                    timestamp,
+                    value,
+                    options,
+                    on_duplicate,
+                }),
+            );
+        }
+        ("ts.range", [k, from, to, options @ ..]) => {
+            return Some((|| {
+                Ok(Command::TsRange {
+                    key: owned(k),
+                    from: parse_timestamp(from, "fromTimestamp")?,
+                    to: parse_timestamp(to, "toTimestamp")?,
+                    options: parse_range_options(options, false)?,
+                })
+            })())
+        }
+        ("ts.mrange", [from, to, rest @ ..]) => {
+            let Some(at) = rest
+                .iter()
+                .position(|arg| arg.eq_ignore_ascii_case("FILTER"))
+            else {
+                return Some(Err(tsdb_error("missing FILTER argument")));
+            };
+            return Some((|| {
+                let filters = rest[at + 1..]
+                    .iter()
+                    .map(|filter| {
+                        LabelFilter::parse(filter)
+                            .ok_or_else(|| tsdb_error("failed parsing labels"))
+                    })
+                    .collect::<Result<Vec<_>, _>>()?;
+                if !filters.iter().any(LabelFilter::is_positive) {
+                    return Err(tsdb_error("please provide at least one matcher"));
+                }
+                Ok(Command::TsMRange {
+                    from: parse_timestamp(from, "fromTimestamp")?,
+                    to: parse_timestamp(to, "toTimestamp")?,
+                    options: parse_range_options(&rest[..at], true)?,
+                    filters,
+                })
+            })());
+        }
+        ("ft.create", [index, args @ ..]) => {
+            return Some(
+                parse_index_schema(args).map(|(prefixes, fields)| Command::FtCreate {
+                    index: owned(index),
+                    prefixes,
+                    fields,
+                }),
+            )
+        }
+        ("ft.search", [index, query, options @ ..]) => {
+            return Some(
+                parse_search_options(options).map(|options| Command::FtSearch {
+                    index: owned(index),
+                    query: owned(query),
+                    options,
+                }),
+            )
+        }
+        (
+            "echo" | "get" | "set" | "strlen" | "append" | "getrange" | "setrange" | "incr"
+            | "decr" | "incrby" | "decrby" | "hset" | "hget" | "hlen" | "hgetall" | "expire"
+            | "pexpire" | "ttl" | "pttl" | "persist" | "del" | "unlink" | "type" | "scan"
+            | "flushdb" | "flushall" | "json.set" | "json.get" | "json.del" | "json.type"
+            | "json.numincrby" | "json.arrappend" | "bf.reserve" | "bf.add" | "bf.madd"
+            | "bf.exists" | "bf.mexists" | "cms.initbydim" | "cms.incrby" | "cms.query"
+            | "topk.reserve" | "topk.add" | "topk.list" | "topk.query" | "ts.create" | "ts.add"
+            | "ts.range" | "ts.mrange" | "ft.create" | "ft.search",
+            _,
+        ) => return Some(Err(errors::wrong_arity(name))),
+        _ => return None,
+    };
+    Some(Ok(command))
+}
+
+fn parse_path(path: &str) -> Result<Path, RespData> {
+    Path::parse(path).map_err(RespData::Error)
+}
+
+fn parse_json(text: &str) -> Result<Json, RespData> {
+    Json::parse(text).map_err(|e| RespData::Error(format!("invalid JSON: {e}")))
+}
+
+/// SET's [EX seconds | PX milliseconds | KEEPTTL].
+fn parse_set_options(args: &[&str]) -> Result<SetOptions, RespData> {
+    let mut options = SetOptions::default();
+    let mut args = args.iter();
+    while let Some(option) = args.next() {
+        match option.to_uppercase().as_str() {
+            unit @ ("EX" | "PX") if options.expire.is_none() && !options.keep_ttl => {
+                let amount = args.next().ok_or_else(errors::syntax_error)?;
+                let amount = amount
+                    .parse::<i64>()
+                    .map_err(|_| errors::not_an_integer())?;
+                if amount <= 0 {
+                    return Err(errors::invalid_expire_time("set"));
+                }
+                options.expire = Some(if unit == "EX" {
+                    Duration::from_secs(amount as u64)
+                } else {
+                    Duration::from_millis(amount as u64)
+                });
+            }
+            "KEEPTTL" if options.expire.is_none() => options.keep_ttl = true,
+            _ => return Err(errors::syntax_error()),
+        }
+    }
+    Ok(options)
+}
+
+/// BF.RESERVE's [EXPANSION expansion] [NONSCALING], as the expansion or `None` for
+/// NONSCALING.
+fn parse_bloom_expansion(args: &[&str]) -> Result<Option<u32>, RespData> {
+    let mut expansion = None;
+    let mut nonscaling = false;
+    let mut args = args.iter();
+    while let Some(option) = args.next() {
+        match option.to_uppercase().as_str() {
+            "EXPANSION" => {
+                let value = args.next().ok_or_else(errors::syntax_error)?;
+                match value.parse::<u32>() {
+                    Ok(n) if n >= 1 => expansion = Some(n),
+                    _ => {
+                        return Err(RespData::Error(
+                            "expansion should be greater or equal to 1".to_string(),
+                        ))
+                    }
+                }
+            }
+            "NONSCALING" => nonscaling = true,
+            _ => return Err(errors::syntax_error()),
+        }
+    }
+    match (nonscaling, expansion) {
+        (true, Some(_)) => Err(RespData::Error(
+            "Nonscaling filters cannot expand".to_string(),
+        )),
+        (true, None) => Ok(None),
+        (false, expansion) => Ok(Some(expansion.unwrap_or(DEFAULT_EXPANSION))),
+    }
+}
+
+/// A sketch dimension, which must be a positive integer.
+fn parse_dimension(arg: &str, error: &str) -> Result<u32, RespData> {
+    match arg.parse::<u32>() {
+        Ok(n) if n > 0 => Ok(n),
+        _ => Err(RespData::Error(error.to_string())),
+    }
+}
+
+/// TS.CREATE's and TS.ADD's [RETENTION ms] [DUPLICATE_POLICY policy] [LABELS label value ...],
+/// with TS.ADD's [ON_DUPLICATE policy] if `on_duplicate` is allowed.
+fn parse_ts_options(
+    args: &[&str],
+    allow_on_duplicate: bool,
+) -> Result<(TsOptions, Option<DuplicatePolicy>), RespData> {
+    let mut options = TsOptions::default();
+    let mut on_duplicate = None;
+    let mut args = args.iter();
+    while let Some(option) = args.next() {
+        let option = option.to_uppercase();
+        if option == "LABELS" {
+            let labels = args.as_slice();
+            if labels.is_empty() || !labels.len().is_multiple_of(2) {
+                return Err(tsdb_error("failed parsing labels"));
+            }
+            options.labels = labels
+                .chunks_exact(2)
+                .map(|pair| (pair[0].to_string(), pair[1].to_string()))
+                .collect();
+            break;
+        }
+        let value = args.next().ok_or_else(errors::syntax_error)?;
+        match option.as_str() {
+            "RETENTION" => {
+                let retention = value
+                    .parse()
+                    .map_err(|_| tsdb_error("Couldn't parse RETENTION"))?;
+                options.retention = Some(retention);
+            }
+            "DUPLICATE_POLICY" | "ON_DUPLICATE" => {
+                let policy = DuplicatePolicy::parse(value)
+                    .ok_or_else(|| tsdb_error("Unknown DUPLICATE_POLICY"))?;
+                match option.as_str() {
+                    "DUPLICATE_POLICY" => options.duplicate_policy = Some(policy),
+                    _ if allow_on_duplicate => on_duplicate = Some(policy),
+                    _ => return Err(errors::syntax_error()),
+                }
+            }
+            _ => return Err(errors::syntax_error()),
+        }
+    }
+    Ok((options, on_duplicate))
+}
+
+/// A range query's timestamp, where `-` and `+` are the earliest and latest possible.
+fn parse_timestamp(arg: &str, name: &str) -> Result<u64, RespData> {
+    match arg {
+        "-" => Ok(0),
+        "+" => Ok(u64::MAX),
+        arg => arg
+            .parse()
+            .map_err(|_| tsdb_error(&format!("wrong {name}"))),
+    }
+}
+
+/// TS.RANGE's and TS.MRANGE's [COUNT count] [AGGREGATION aggregator bucket], with
+/// TS.MRANGE's [WITHLABELS] if `multi`.
+fn parse_range_options(args: &[&str], multi: bool) -> Result<RangeOptions, RespData> {
+    let mut options = RangeOptions::default();
+    let mut args = args.iter();
+    while let Some(option) = args.next() {
+        match option.to_uppercase().as_str() {
+            "WITHLABELS" if multi => options.with_labels = true,
+            "COUNT" => match args.next().map(|count| count.parse::<usize>()) {
+                Some(Ok(count)) if count > 0 => options.count = Some(count),
+                Some(_) => return Err(tsdb_error("Couldn't parse COUNT")),
+                None => return Err(errors::syntax_error()),
+            },
+            "AGGREGATION" => {
+                let (Some(aggregation), Some(bucket)) = (args.next(), args.next()) else {
+                    return Err(errors::syntax_error());
+                };
+                let aggregation = Aggregation::parse(aggregation)
+                    .ok_or_else(|| tsdb_error("Unknown aggregation type"))?;
+                match bucket.parse::<u64>() {
+                    Ok(bucket) if bucket > 0 => options.aggregation = Some((aggregation, bucket)),
+                    _ => return Err(tsdb_error("bucketDuration must be greater than zero")),
+                }
+            }
+            _ => return Err(errors::syntax_error()),
+        }
+    }
+    Ok(options)
+}
+
+/// FT.CREATE's [ON HASH] [PREFIX count prefix ...] SCHEMA field {TEXT [WEIGHT weight] |
+/// VECTOR algorithm count attribute value ...} ..., as the prefixes and the fields.
+fn parse_index_schema(args: &[&str]) -> Result<(Vec<String>, Vec<SchemaField>), RespData> {
+    let missing_fields = || RespData::Error("Fields arguments are missing".to_string());
+    let mut prefixes = Vec::new();
+    let mut args = args.iter().peekable();
+    loop {
+        let option = args.next().ok_or_else(missing_fields)?;
+        match option.to_uppercase().as_str() {
+            "ON" => match args.next() {
+                Some(on) if on.eq_ignore_ascii_case("HASH") => {}
+                Some(_) => {
+                    return Err(RespData::Error(
+                        "Only HASH indexes are supported".to_string(),
+                    ))
+                }
+                None => return Err(errors::syntax_error()),
+            },
+            "PREFIX" => {
+                let count = args.next().ok_or_else(errors::syntax_error)?;
+                let count = count
+                    .parse::<usize>()
+                    .map_err(|_| errors::not_an_integer())?;
+                for _ in 0..count {
+                    prefixes.push(args.next().ok_or_else(errors::syntax_error)?.to_string());
+                }
+            }
+            "SCHEMA" => break,
+            _ => return Err(errors::syntax_error()),
+        }
+    }
+
+    let mut fields: Vec<SchemaField> = Vec::new();
+    while let Some(name) = args.next() {
+        if fields.iter().any(|field| field.name() == *name) {
+            return Err(RespData::Error(format!(
+                "Duplicate field in schema - {name}"
+            )));
+        }
+        let kind = args.next().ok_or_else(errors::syntax_error)?;
+        let field = match kind.to_uppercase().as_str() {
+            "TEXT" => {
+                let mut weight = 1.0;
+                if args
+                    .next_if(|arg| arg.eq_ignore_ascii_case("WEIGHT"))
+                    .is_some()
+                {
+                    weight = match args.next().map(|weight| weight.parse::<f64>()) {
+                        Some(Ok(weight)) if weight.is_finite() && weight > 0.0 => weight,
+                        _ => return Err(RespData::Error("Bad arguments for WEIGHT".to_string())),
+                    };
+                }
+                SchemaField::Text(TextField {
+                    name: name.to_string(),
+                    weight,
+                })
+            }
+            "VECTOR" => SchemaField::Vector(parse_vector_field(name, &mut args)?),
+            _ => {
+                return Err(RespData::Error(format!(
+                    "Invalid field type for field `{name}`"
+                )))
+            }
+        };
+        fields.push(field);
+    }
+    if fields.is_empty() {
+        return Err(missing_fields());
+    }
+    Ok((prefixes, fields))
+}
+
+/// A VECTOR field's FLAT|HNSW count attribute value ..., where the attributes are TYPE
+/// FLOAT32, DIM and DISTANCE_METRIC. The tuning attributes of the two algorithms are accepted
+/// and ignored, since vectors are searched exhaustively either way.
+fn parse_vector_field(
+    name: &str,
+    args: &mut Peekable<slice::Iter<&str>>,
+) -> Result<VectorField, RespData> {
+    let algorithm = args.next().ok_or_else(errors::syntax_error)?;
+    let algorithm = VectorAlgorithm::parse(algorithm).ok_or_else(|| {
+        RespData::Error(format!(
+            "Bad arguments for vector similarity algorithm: {algorithm}"
+        ))
+    })?;
+    let count = args.next().ok_or_else(errors::syntax_error)?;
+    let count = count
+        .parse::<usize>()
+        .map_err(|_| errors::not_an_integer())?;
+    let attributes: Vec<&str> = args.by_ref().take(count).copied().collect();
+    if attributes.len() != count || !count.is_multiple_of(2) {
+        return Err(errors::syntax_error());
+    }
+
+    let bad = |reason: &str| {
+        RespData::Error(format!(
+            "Bad arguments for vector similarity {} index: {reason}",
+            algorithm.name()
+        ))
+    };
+    let (mut float32, mut dim, mut metric) = (false, None, None);
+    for pair in attributes.chunks_exact(2) {
+        let (attribute, value) = (pair[0], pair[1]);
+        match attribute.to_uppercase().as_str() {
+            "TYPE" if value.eq_ignore_ascii_case("FLOAT32") => float32 = true,
+            "TYPE" => return Err(bad("only FLOAT32 vectors are supported")),
+            "DIM" => match value.parse::<usize>() {
+                Ok(value) if value > 0 => dim = Some(value),
+                _ => return Err(bad("invalid DIM")),
+            },
+            "DISTANCE_METRIC" => {
+                metric = Some(
+                    DistanceMetric::parse(value).ok_or_else(|| bad("invalid DISTANCE_METRIC"))?,
+                )
+            }
+            "M" | "EF_CONSTRUCTION" | "EF_RUNTIME" | "EPSILON" | "INITIAL_CAP" | "BLOCK_SIZE" => {}
+            _ => return Err(bad(&format!("unknown attribute `{attribute}`"))),
+        }
+    }
+    let missing = |attribute: &str| {
+        RespData::Error(format!(
+            "Missing mandatory parameter: cannot create {} index without specifying {attribute} argument",
+            algorithm.name()
+        ))
+    };
+    if !float32 {
+        return Err(missing("TYPE"));
+    }
+    Ok(VectorField {
+        name: name.to_string(),
+        algorithm,
+        dim: dim.ok_or_else(|| missing("DIM"))?,
+        metric: metric.ok_or_else(|| missing("DISTANCE_METRIC"))?,
+    })
+}
+
+/// FT.SEARCH's [NOCONTENT] [WITHSCORES] [LIMIT offset num] [PARAMS count name value ...]
+/// [DIALECT dialect]. There is only the one query syntax, so the dialect is ignored.
+fn parse_search_options(args: &[&str]) -> Result<SearchOptions, RespData> {
+    let mut options = SearchOptions::default();
+    let mut args = args.iter();
+    while let Some(option) = args.next() {
+        match option.to_uppercase().as_str() {
+            "NOCONTENT" => options.no_content = true,
+            "WITHSCORES" => options.with_scores = true,
+            "LIMIT" => {
+                let (Some(offset), Some(num)) = (args.next(), args.next()) else {
+                    return Err(errors::syntax_error());
+                };
+                match (offset.parse(), num.parse()) {
+                    (Ok(offset), Ok(num)) => options.limit = Some((offset, num)),
+                    _ => return Err(errors::not_an_integer()),
+                }
+            }
+            "PARAMS" => {
+                let count = args.next().ok_or_else(errors::syntax_error)?;
+                let count = count
+                    .parse::<usize>()
+                    .map_err(|_| errors::not_an_integer())?;
+                let params: Vec<&str> = args.by_ref().take(count).copied().collect();
+                if params.len() != count || !count.is_multiple_of(2) {
+                    return Err(errors::syntax_error());
+                }
+                options.params = params
+                    .chunks_exact(2)
+                    .map(|pair| (pair[0].to_string(), pair[1].to_string()))
+                    .collect();
+            }
+            "DIALECT" => {
+                let dialect = args.next().ok_or_else(errors::syntax_error)?;
+                dialect
+                    .parse::<u32>()
+                    .map_err(|_| errors::not_an_integer())?;
+            }
+            _ => return Err(errors::syntax_error()),
+        }
+    }
+    Ok(options)
+}
+
+/// SCAN's [MATCH pattern] [COUNT count] [TYPE type].
+fn parse_scan_options(args: &[&str]) -> Result<ScanOptions, RespData> {
+    let mut options = ScanOptions::default();
+    let mut args = args.iter();
+    while let Some(option) = args.next() {
+        let value = args.next().ok_or_else(errors::syntax_error)?;
+        match option.to_uppercase().as_str() {
+            "MATCH" => options.pattern = Some(value.to_string()),
+            "COUNT" => match value.parse::<i64>() {
+                Ok(n) if n >= 1 => options.count = Some(n as usize),
+                Ok(_) => return Err(errors::syntax_error()),
+                Err(_) => return Err(errors::not_an_integer()),
+            },
+            "TYPE" => options.type_name = Some(value.to_lowercase()),
+            _ => return Err(errors::syntax_error()),
+        }
+    }
+    Ok(options)
+}
+
+impl TryFrom<&RespData> for Command {
+    type Error = RespData;
+
+    /// Fails with the error reply the server would give, such as for a wrong number of
+    /// arguments or a command that isn't one of these.
+    fn try_from(resp: &RespData) -> Result<Self, RespData> {
+        let Some(name) = command_name(resp) else {
+            return Err(errors::unknown_command("", &[]));
+        };
+        let args = match resp {
+            RespData::Array(arr) => &arr[1..],
+            _ => &[],
+        };
+        Command::parse(&name.to_lowercase(), args)
+            .unwrap_or_else(|| Err(errors::unknown_command(name, args)))
+    }
+}
+
+impl TryFrom<RespData> for Command {
+    type Error = RespData;
+
+    fn try_from(resp: RespData) -> Result<Self, RespData> {
+        Command::try_from(&resp)
+    }
+}
+
+/// The command as a request, to be sent to a server or passed to `CommandHandler::handle`.
+impl From<Command> for RespData {
+    fn from(command: Command) -> Self {
+        let mut args = vec![command.name().to_uppercase()];
+        match command {
+            Command::Ping => {}
+            Command::Echo { message } => args.push(message),
+            Command::Get { key }
+            | Command::Strlen { key }
+            | Command::Incr { key }
+            | Command::Decr { key }
+            | Command::HLen { key }
+            | Command::HGetAll { key }
+            | Command::Ttl { key }
+            | Command::PTtl { key }
+            | Command::Persist { key }
+            | Command::Type { key } => args.push(key),
+            Command::Set {
+                key,
+                value,
+                options,
+            } => {
+                args.extend([key, value]);
+                if let Some(expire) = options.expire {
+                    args.extend(["PX".to_string(), expire.as_millis().to_string()]);
+                }
+                if options.keep_ttl {
+                    args.push("KEEPTTL".to_string());
+                }
+            }
+            Command::Append { key, value } => args.extend([key, value]),
+            Command::GetRange { key, start, end } => {
+                args.extend([key, start.to_string(), end.to_string()])
+            }
+            Command::SetRange { key, offset, value } => {
+                args.extend([key, offset.to_string(), value])
+            }
+            Command::IncrBy { key, increment } => args.extend([key, increment.to_string()]),
+            Command::DecrBy { key, decrement } => args.extend([key, decrement.to_string()]),
+            Command::HSet { key, fields } => {
+                args.push(key);
+                for (field, value) in fields {
+                    args.extend([field, value]);
+                }
+            }
+            Command::HGet { key, field } => args.extend([key, field]),
+            Command::Expire { key, seconds } => args.extend([key, seconds.to_string()]),
+            Command::PExpire { key, milliseconds } => args.extend([key, milliseconds.to_string()]),
+            Command::Del { keys } | Command::Unlink { keys } => args.extend(keys),
+            Command::Scan { cursor, options } => {
+                args.push(cursor.to_string());
+                if let Some(pattern) = options.pattern {
+                    args.extend(["MATCH".to_string(), pattern]);
+                }
+                if let Some(count) = options.count {
+                    args.extend(["COUNT".to_string(), count.to_string()]);
+                }
+                if let Some(type_name) = options.type_name {
+                    args.extend(["TYPE".to_string(), type_name]);
+                }
+            }
+            Command::FlushDb { mode } | Command::FlushAll { mode } => match mode {
+                Some(FlushMode::Async) => args.push("ASYNC".to_string()),
+                Some(FlushMode::Sync) => args.push("SYNC".to_string()),
+                None => {}
+            },
+            Command::JsonSet {
+                key,
+                path,
+                value,
+                condition,
+            } => {
+                args.extend([key, path.to_string(), value.to_string()]);
+                match condition {
+                    Some(SetCondition::Nx) => args.push("NX".to_string()),
+                    Some(SetCondition::Xx) => args.push("XX".to_string()),
+                    None => {}
+                }
+            }
+            Command::JsonGet { key, paths } => {
+                args.push(key);
+                args.extend(paths.iter().map(Path::to_string));
+            }
+            Command::JsonDel { key, path } | Command::JsonType { key, path } => {
+                args.extend([key, path.to_string()])
+            }
+            Command::JsonNumIncrBy {
+                key,
+                path,
+                increment,
+            } => args.extend([key, path.to_string(), increment.to_string()]),
+            Command::JsonArrAppend { key, path, values } => {
+                args.extend([key, path.to_string()]);
+                args.extend(values.iter().map(Json::to_string));
+            }
+            Command::BfReserve {
+                key,
+                error_rate,
+                capacity,
+                expansion,
+            } => {
+                args.extend([key, error_rate.to_string(), capacity.to_string()]);
+                match expansion {
+                    Some(expansion) => {
+                        args.extend(["EXPANSION".to_string(), expansion.to_string()])
+                    }
+                    None => args.push("NONSCALING".to_string()),
+                }
+            }
+            Command::BfAdd { key, item } | Command::BfExists { key, item } => {
+                args.extend([key, item])
+            }
+            Command::BfMAdd { key, items }
+            | Command::BfMExists { key, items }
+            | Command::CmsQuery { key, items }
+            | Command::TopkAdd { key, items }
+            | Command::TopkQuery { key, items } => {
+                args.push(key);
+                args.extend(items);
+            }
+            Command::CmsInitByDim { key, width, depth } => {
+                args.extend([key, width.to_string(), depth.to_string()])
+            }
+            Command::CmsIncrBy { key, items } => {
+                args.push(key);
+                for (item, increment) in items {
+                    args.extend([item, increment.to_string()]);
+                }
+            }
+            Command::TopkReserve {
+                key,
+                k,
+                width,
+                depth,
+                decay,
+            } => args.extend([
+                key,
+                k.to_string(),
+                width.to_string(),
+                depth.to_string(),
+                decay.to_string(),
+            ]),
+            Command::TopkList { key, with_count } => {
+                args.push(key);
+                if with_count {
+                    args.push("WITHCOUNT".to_string());
+                }
+            }
+            Command::TsCreate { key, options } => {
+                args.push(key);
+                push_ts_options(&mut args, options, None);
+            }
+            Command::TsAdd {
+                key,
+                timestamp,
+                value,
+                options,
+                on_duplicate,
+            } => {
+                let timestamp = timestamp.map_or("*".to_string(), |t| t.to_string());
+                args.extend([key, timestamp, value.to_string()]);
+                push_ts_options(&mut args, options, on_duplicate);
+            }
+            Command::TsRange {
+                key,
+                from,
+                to,
+                options,
+            } => {
+                args.extend([key, from.to_string(), to.to_string()]);
+                push_range_options(&mut args, options);
+            }
+            Command::TsMRange {
+                from,
+                to,
+                options,
+                filters,
+            } => {
+                args.extend([from.to_string(), to.to_string()]);
+                push_range_options(&mut args, options);
+                args.push("FILTER".to_string());
+                args.extend(filters.iter().map(LabelFilter::to_string));
+            }
+            Command::FtCreate {
+                index,
+                prefixes,
+                fields,
+            } => {
+                args.push(index);
+                if !prefixes.is_empty() {
+                    args.extend(["PREFIX".to_string(), prefixes.len().to_string()]);
+                    args.extend(prefixes);
+                }
+                args.push("SCHEMA".to_string());
+                for field in fields {
+                    match field {
+                        SchemaField::Text(field) => {
+                            args.extend([field.name, "TEXT".to_string()]);
+                            if field.weight != 1.0 {
+                                args.extend(["WEIGHT".to_string(), field.weight.to_string()]);
+                            }
+                        }
+                        SchemaField::Vector(field) => args.extend([
+                            field.name,
+                            "VECTOR".to_string(),
+                            field.algorithm.name().to_string(),
+                            "6".to_string(),
+                            "TYPE".to_string(),
+                            "FLOAT32".to_string(),
+                            "DIM".to_string(),
+                            field.dim.to_string(),
+                            "DISTANCE_METRIC".to_string(),
+                            field.metric.name().to_string(),
+                        ]),
+                    }
+                }
+            }
+            Command::FtSearch {
+                index,
+                query,
+                options,
+            } => {
+                args.extend([index, query]);
+                if options.no_content {
+                    args.push("NOCONTENT".to_string());
+                }
+                if options.with_scores {
+                    args.push("WITHSCORES".to_string());
+                }
+                if let Some((offset, num)) = options.limit {
+                    args.extend(["LIMIT".to_string(), offset.to_string(), num.to_string()]);
+                }
+                if !options.params.is_empty() {
+                    args.extend(["PARAMS".to_string(), (options.params.len() * 2).to_string()]);
+                    for (name, value) in options.params {
+                        args.extend([name, value]);
+                    }
+                }
+            }
+        }
+        RespData::array(args)
+    }
+}
+
+fn push_ts_options(
+    args: &mut Vec<String>,
+    options: TsOptions,
+    on_duplicate: Option<DuplicatePolicy>,
+) {
+    if let Some(retention) = options.retention {
+        args.extend(["RETENTION".to_string(), retention.to_string()]);
+    }
+    if let Some(policy) = options.duplicate_policy {
+        args.extend(["DUPLICATE_POLICY".to_string(), policy.name().to_string()]);
+    }
+    if let Some(policy) = on_duplicate {
+        args.extend(["ON_DUPLICATE".to_string(), policy.name().to_string()]);
+    }
+    if !options.labels.is_empty() {
+        args.push("LABELS".to_string());
+        for (name, value) in options.labels {
+            args.extend([name, value]);
+        }
+    }
+}
+
+fn push_range_options(args: &mut Vec<String>, options: RangeOptions) {
+    if options.with_labels {
+        args.push("WITHLABELS".to_string());
+    }
+    if let Some(count) = options.count {
+        args.extend(["COUNT".to_string(), count.to_string()]);
+    }
+    if let Some((aggregation, bucket)) = options.aggregation {
+        args.extend([
+            "AGGREGATION".to_string(),
+            aggregation.name().to_string(),
+            bucket.to_string(),
+        ]);
+    }
+}
+
+impl CommandHandler {
+    /// Runs a parsed command and returns its reply. Unlike `handle`, this is only the
+    /// command itself, without the authentication, ACL checks, stats and hooks around it.
+    pub fn run(&mut self, command: Command) -> RespData {
+        match command {
+            Command::Ping => RespData::SimpleString("PONG".to_string()),
+            Command::Echo { message } => RespData::BulkString(message),
+            Command::Get { key } => self.get(&key),
+            Command::Set {
+                key,
+                value,
+                options,
+            } => self.set(&key, &value, options),
+            Command::Strlen { key } => self.strlen(&key),
+            Command::Append { key, value } => self.append(&key, &value),
+            Command::GetRange { key, start, end } => self.getrange(&key, start, end),
+            Command::SetRange { key, offset, value } => self.setrange(&key, offset, &value),
+            Command::Incr { key } => self.incrby(&key, 1),
+            Command::Decr { key } => self.incrby(&key, -1),
+            Command::IncrBy { key, increment } => self.incrby(&key, increment),
+            Command::DecrBy { key, decrement } => self.decrby(&key, decrement),
+            Command::HSet { key, fields } => self.hset(&key, fields),
+            Command::HGet { key, field } => self.hget(&key, &field),
+            Command::HLen { key } => self.hlen(&key),
+            Command::HGetAll { key } => self.hgetall(&key),
+            Command::Expire { key, seconds } => {
+                self.generic_expire("expire", &key, seconds, Duration::from_secs)
+            }
+            Command::PExpire { key, milliseconds } => {
+                self.generic_expire("pexpire", &key, milliseconds, Duration::from_millis)
+            }
+            Command::Ttl { key } => self.ttl(&key),
+            Command::PTtl { key } => self.pttl(&key),
+            Command::Persist { key } => self.persist(&key),
+            Command::Del { keys } => self.del(&keys),
+            Command::Unlink { keys } => self.unlink(&keys),
+            Command::Type { key } => self.type_of(&key),
+            Command::Scan { cursor, options } => self.scan(cursor, options),
+            Command::FlushDb { mode } | Command::FlushAll { mode } => self.flush(mode),
+            Command::JsonSet {
+                key,
+                path,
+                value,
+                condition,
+            } => self.json_set(&key, path, value, condition),
+            Command::JsonGet { key, paths } => self.json_get(&key, &paths),
+            Command::JsonDel { key, path } => self.json_del(&key, &path),
+            Command::JsonType { key, path } => self.json_type(&key, &path),
+            Command::JsonNumIncrBy {
+                key,
+                path,
+                increment,
+            } => self.json_numincrby(&key, &path, increment),
+            Command::JsonArrAppend { key, path, values } => {
+                self.json_arrappend(&key, &path, &values)
+            }
+            Command::BfReserve {
+                key,
+                error_rate,
+                capacity,
+                expansion,
+            } => self.bf_reserve(&key, error_rate, capacity, expansion),
+            Command::BfAdd { key, item } => match self.bf_add(&key, &[item]) {
+                Ok(mut replies) => replies.pop().unwrap(),
+                Err(e) => e,
+            },
+            Command::BfMAdd { key, items } => self
+                .bf_add(&key, &items)
+                .map_or_else(|e| e, RespData::Array),
+            Command::BfExists { key, item } => match self.bf_exists(&key, &[item]) {
+                Ok(mut replies) => replies.pop().unwrap(),
+                Err(e) => e,
+            },
+            Command::BfMExists { key, items } => self
+                .bf_exists(&key, &items)
+                .map_or_else(|e| e, RespData::Array),
+            Command::CmsInitByDim { key, width, depth } => self.cms_initbydim(&key, width, depth),
+            Command::CmsIncrBy { key, items } => self.cms_incrby(&key, &items),
+            Command::CmsQuery { key, items } => self.cms_query(&key, &items),
+            Command::TopkReserve {
+                key,
+                k,
+                width,
+                depth,
+                decay,
+            } => self.topk_reserve(&key, k, width, depth, decay),
+            Command::TopkAdd { key, items } => self.topk_add(&key, &items),
+            Command::TopkList { key, with_count } => self.topk_list(&key, with_count),
+            Command::TopkQuery { key, items } => self.topk_query(&key, &items),
+            Command::TsCreate { key, options } => self.ts_create(&key, options),
+            Command::TsAdd {
+                key,
+                timestamp,
+                value,
+                options,
+                on_duplicate,
+            } => self.ts_add(&key, timestamp, value, options, on_duplicate),
+            Command::TsRange {
+                key,
+                from,
+                to,
+                options,
+            } => self.ts_range(&key, from, to, &options),
+            Command::TsMRange {
+                from,
+                to,
+                options,
+                filters,
+            } => self.ts_mrange(from, to, &options, &filters),
+            Command::FtCreate {
+                index,
+                prefixes,
+                fields,
+            } => self.ft_create(&index, prefixes, fields),
+            Command::FtSearch {
+                index,
+                query,
+                options,
+            } => self.ft_search(&index, &query, &options),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+
+    fn command(args: &[&str]) -> RespData {
+        RespData::array(args.iter().copied())
+    }
+
+    #[test]
+    fn test_parse() {
+        let test_cases = [
+            (
+                "Lowercase name",
+                command(&["get", "key"]),
+                Ok(Command::Get {
+                    key: "key".to_string(),
+                }),
+            ),
+            (
+                "SET with conflicting options",
+                command(&["SET", "key", "value", "ex", "10", "KEEPTTL"]),
+                Err(errors::syntax_error()),
+            ),
+            (
+                "SET with an expiry",
+                command(&["SET", "key", "value", "PX", "1500"]),
+                Ok(Command::Set {
+                    key: "key".to_string(),
+                    value: "value".to_string(),
+                    options: SetOptions {
+                        expire: Some(Duration::from_millis(1500)),
+                        keep_ttl: false,
+                    },
+                }),
+            ),
+            (
+                "HSET with fields",
+                command(&["HSET", "hash", "a", "1", "b", "2"]),
+                Ok(Command::HSet {
+                    key: "hash".to_string(),
+                    fields: vec![
+                        ("a".to_string(), "1".to_string()),
+                        ("b".to_string(), "2".to_string()),
+                    ],
+                }),
+            ),
+            (
+                "HSET with a field missing its value",
+                command(&["HSET", "hash", "a", "1", "b"]),
+                Err(errors::wrong_arity("hset")),
+            ),
+            (
+                "Not an integer",
+                command(&["INCRBY", "counter", "one"]),
+                Err(errors::not_an_integer()),
+            ),
+            (
+                "Negative offset",
+                command(&["SETRANGE", "key", "-1", "x"]),
+                Err(RespData::Error("offset is out of range".to_string())),
+            ),
+            (
+                "SCAN with options",
+                command(&[
+                    "SCAN", "0", "MATCH", "user:*", "COUNT", "100", "TYPE", "HASH",
+                ]),
+                Ok(Command::Scan {
+                    cursor: 0,
+                    options: ScanOptions {
+                        pattern: Some("user:*".to_string()),
+                        count: Some(100),
+                        type_name: Some("hash".to_string()),
+                    },
+                }),
+            ),
+            (
+                "Bad flush mode",
+                command(&["FLUSHALL", "LATER"]),
+                Err(errors::syntax_error()),
+            ),
+            (
+                "JSON.DEL without a path",
+                command(&["JSON.DEL", "doc"]),
+                Ok(Command::JsonDel {
+                    key: "doc".to_string(),
+                    path: Path::root(),
+                }),
+            ),
+            (
+                "JSON.SET with invalid JSON",
+                command(&["JSON.SET", "doc", "$", "[1,"]),
+                Err(RespData::Error(
+                    "invalid JSON: expected value at offset 3".to_string(),
+                )),
+            ),
+            (
+                "JSON.SET with a bad condition",
+                command(&["JSON.SET", "doc", "$", "1", "EX"]),
+                Err(errors::syntax_error()),
+            ),
+            (
+                "BF.RESERVE with the default expansion",
+                command(&["BF.RESERVE", "seen", "0.001", "1000"]),
+                Ok(Command::BfReserve {
+                    key: "seen".to_string(),
+                    error_rate: 0.001,
+                    capacity: 1000,
+                    expansion: Some(DEFAULT_EXPANSION),
+                }),
+            ),
+            (
+                "TOPK.RESERVE with the default dimensions",
+                command(&["TOPK.RESERVE", "top", "10"]),
+                Ok(Command::TopkReserve {
+                    key: "top".to_string(),
+                    k: 10,
+                    width: TOPK_WIDTH,
+                    depth: TOPK_DEPTH,
+                    decay: TOPK_DECAY,
+                }),
+            ),
+            (
+                "TOPK.RESERVE with only a width",
+                command(&["TOPK.RESERVE", "top", "10", "50"]),
+                Err(errors::wrong_arity("topk.reserve")),
+            ),
+            (
+                "TS.RANGE with WITHLABELS",
+                command(&["TS.RANGE", "temp", "-", "+", "WITHLABELS"]),
+                Err(errors::syntax_error()),
+            ),
+            (
+                "Argument that isn't a bulk string",
+                RespData::Array(vec![RespData::bulk("DEL"), RespData::Integer(1)]),
+                Err(errors::syntax_error()),
+            ),
+            (
+                "No arguments",
+                command(&["DEL"]),
+                Err(errors::wrong_arity("del")),
+            ),
+            (
+                "Bare command name",
+                RespData::SimpleString("PING".to_string()),
+                Ok(Command::Ping),
+            ),
+            (
+                "Command that isn't typed",
+                command(&["CONFIG", "GET", "port"]),
+                Err(errors::unknown_command(
+                    "CONFIG",
+                    &[RespData::bulk("GET"), RespData::bulk("port")],
+                )),
+            ),
+        ];
+
+        for (name, input, expected) in test_cases {
+            assert_eq!(Command::try_from(input), expected, "{}", name);
+        }
+    }
+
+    #[test]
+    fn test_to_resp() {
+        let commands = [
+            Command::Ping,
+            Command::Set {
+                key: "key".to_string(),
+                value: "value".to_string(),
+                options: SetOptions {
+                    expire: Some(Duration::from_secs(10)),
+                    keep_ttl: false,
+                },
+            },
+            Command::HSet {
+                key: "hash".to_string(),
+                fields: vec![("a".to_string(), "1".to_string())],
+            },
+            Command::DecrBy {
+                key: "counter".to_string(),
+                decrement: -5,
+            },
+            Command::Del {
+                keys: vec!["a".to_string(), "b".to_string()],
+            },
+            Command::Scan {
+                cursor: 17,
+                options: ScanOptions {
+                    pattern: Some("*".to_string()),
+                    count: None,
+                    type_name: Some("string".to_string()),
+                },
+            },
+            Command::FlushDb {
+                mode: Some(FlushMode::Async),
+            },
+            Command::JsonSet {
+                key: "doc".to_string(),
+                path: Path::parse("$.tags[-1]").unwrap(),
+                value: Json::parse(r#"{"a": [1, 2.5, "x"]}"#).unwrap(),
+                condition: Some(SetCondition::Xx),
+            },
+            Command::JsonGet {
+                key: "doc".to_string(),
+                paths: vec![Path::parse("name").unwrap(), Path::parse("$..a").unwrap()],
+            },
+            Command::JsonNumIncrBy {
+                key: "doc".to_string(),
+                path: Path::parse(".count").unwrap(),
+                increment: Number::Float(-0.5),
+            },
+            Command::BfReserve {
+                key: "seen".to_string(),
+                error_rate: 0.001,
+                capacity: 1000,
+                expansion: None,
+            },
+            Command::BfMExists {
+                key: "seen".to_string(),
+                items: vec!["a".to_string(), "b".to_string()],
+            },
+            Command::CmsIncrBy {
+                key: "counts".to_string(),
+                items: vec![("a".to_string(), 5), ("b".to_string(), 0)],
+            },
+            Command::TopkReserve {
+                key: "top".to_string(),
+                k: 10,
+                width: 50,
+                depth: 4,
+                decay: 0.925,
+            },
+            Command::TopkList {
+                key: "top".to_string(),
+                with_count: true,
+            },
+            Command::TsAdd {
+                key: "temp".to_string(),
+                timestamp: None,
+                value: -1.5,
+                options: TsOptions {
+                    retention: Some(60_000),
+                    duplicate_policy: Some(DuplicatePolicy::Last),
+                    labels: vec![("area".to_string(), "eu".to_string())],
+                },
+                on_duplicate: Some(DuplicatePolicy::Sum),
+            },
+            Command::TsMRange {
+                from: 0,
+                to: u64::MAX,
+                options: RangeOptions {
+                    count: Some(10),
+                    aggregation: Some((Aggregation::Avg, 1000)),
+                    with_labels: true,
+                },
+                filters: vec![
+                    LabelFilter::parse("area=(eu,us)").unwrap(),
+                    LabelFilter::parse("host!=").unwrap(),
+                ],
+            },
+            Command::FtCreate {
+                index: "posts".to_string(),
+                prefixes: vec!["post:".to_string(), "draft:".to_string()],
+                fields: vec![
+                    SchemaField::Text(TextField {
+                        name: "title".to_string(),
+                        weight: 2.5,
+                    }),
+                    SchemaField::Text(TextField {
+                        name: "body".to_string(),
+                        weight: 1.0,
+                    }),
+                    SchemaField::Vector(VectorField {
+                        name: "embedding".to_string(),
+                        algorithm: VectorAlgorithm::Hnsw,
+                        dim: 3,
+                        metric: DistanceMetric::Cosine,
+                    }),
+                ],
+            },
+            Command::FtSearch {
+                index: "posts".to_string(),
+                query: "@title:hello wor*".to_string(),
+                options: SearchOptions {
+                    no_content: true,
+                    with_scores: true,
+                    limit: Some((10, 20)),
+                    params: vec![("v".to_string(), "0.5,1,-2".to_string())],
+                },
+            },
+        ];
+
+        for command in commands {
+            let resp = RespData::from(command.clone());
+            assert_eq!(
+                Command::try_from(resp),
+                Ok(command.clone()),
+                "{:?}",
+                command
+            );
+        }
+        assert_eq!(
+            RespData::from(Command::Get {
+                key: "key".to_string()
+            }),
+            command(&["GET", "key"])
+        );
+    }
+
+    #[test]
+    fn test_run() {
+        let mut handler = CommandHandler::from(HashMap::new());
+        let key = || "counter".to_string();
+
+        let test_cases = [
+            (
+                "Ping",
+                Command::Ping,
+                RespData::SimpleString("PONG".to_string()),
+            ),
+            (
+                "Echo",
+                Command::Echo {
+                    message: "hello".to_string(),
+                },
+                RespData::bulk("hello"),
+            ),
+            ("Incr", Command::Incr { key: key() }, RespData::Integer(1)),
+            (
+                "IncrBy",
+                Command::IncrBy {
+                    key: key(),
+                    increment: 10,
+                },
+                RespData::Integer(11),
+            ),
+            (
+                "DecrBy",
+                Command::DecrBy {
+                    key: key(),
+                    decrement: 20,
+                },
+                RespData::Integer(-9),
+            ),
+            (
+                "DecrBy overflowing",
+                Command::DecrBy {
+                    key: key(),
+                    decrement: i64::MIN,
+                },
+                RespData::Error("decrement would overflow".to_string()),
+            ),
+            ("Get", Command::Get { key: key() }, RespData::bulk("-9")),
+            (
+                "Type",
+                Command::Type { key: key() },
+                RespData::SimpleString("string".to_string()),
+            ),
+            (
+                "Del",
+                Command::Del {
+                    keys: vec![key(), "missing".to_string()],
+                },
+                RespData::Integer(1),
+            ),
+        ];
+
+        for (name, command, expected) in test_cases {
+            assert_eq!(handler.run(command), expected, "{}", name);
+        }
+    }
+}
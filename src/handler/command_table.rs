@@ -0,0 +1,314 @@
+//! Static metadata about every command: flags, ACL categories and where its keys are.
+
+/// The command modifies the dataset.
+pub(super) const WRITE: u32 = 1 << 0;
+/// The command only reads the dataset.
+pub(super) const READONLY: u32 = 1 << 1;
+/// An administrative command, implying the @admin and @dangerous categories.
+pub(super) const ADMIN: u32 = 1 << 2;
+/// Runs in constant or logarithmic time; everything else is @slow.
+pub(super) const FAST: u32 = 1 << 3;
+/// May grow memory usage, so it is refused when over maxmemory.
+pub(super) const DENYOOM: u32 = 1 << 4;
+pub(super) const PUBSUB: u32 = 1 << 5;
+/// Allowed before the client has authenticated.
+pub(super) const NO_AUTH: u32 = 1 << 6;
+/// Refused unless `enable-debug-command` allows the client to run it.
+pub(super) const PROTECTED: u32 = 1 << 7;
+/// Allowed while the dataset is being loaded at startup.
+pub(super) const LOADING: u32 = 1 << 8;
+
+/// Every ACL category, in the order ACL CAT lists them.
+pub(super) const ACL_CATEGORIES: [&str; 27] = [
+    "keyspace",
+    "read",
+    "write",
+    "set",
+    "sortedset",
+    "list",
+    "hash",
+    "string",
+    "bitmap",
+    "hyperloglog",
+    "geo",
+    "stream",
+    "pubsub",
+    "admin",
+    "fast",
+    "slow",
+    "blocking",
+    "dangerous",
+    "connection",
+    "transaction",
+    "scripting",
+    "json",
+    "bloom",
+    "cms",
+    "topk",
+    "timeseries",
+    "search",
+];
+
+pub(super) struct CommandSpec {
+    pub name: &'static str,
+    pub flags: u32,
+    /// Categories on top of the ones implied by `flags`.
+    pub categories: &'static [&'static str],
+    /// Position of the first key argument, or 0 if the command takes no keys.
+    pub first_key: usize,
+    /// Whether every argument from `first_key` on is a key, rather than just that one.
+    pub variadic_keys: bool,
+    pub subcommands: &'static [CommandSpec],
+}
+
+impl CommandSpec {
+    const fn new(name: &'static str, flags: u32, categories: &'static [&'static str]) -> Self {
+        Self {
+            name,
+            flags,
+            categories,
+            first_key: 0,
+            variadic_keys: false,
+            subcommands: &[],
+        }
+    }
+
+    const fn key_at(mut self, position: usize) -> Self {
+        self.first_key = position;
+        self
+    }
+
+    /// Marks every argument from `position` on as a key.
+    const fn keys_from(mut self, position: usize) -> Self {
+        self.first_key = position;
+        self.variadic_keys = true;
+        self
+    }
+
+    const fn with_subcommands(mut self, subcommands: &'static [CommandSpec]) -> Self {
+        self.subcommands = subcommands;
+        self
+    }
+
+    pub fn is_write(&self) -> bool {
+        self.flags & WRITE != 0
+    }
+
+    /// Whether the command belongs to `category`, either explicitly or through its flags.
+    pub fn in_category(&self, category: &str) -> bool {
+        let implied = match category {
+            "write" => self.flags & WRITE != 0,
+            "read" => self.flags & READONLY != 0,
+            "admin" | "dangerous" => self.flags & ADMIN != 0,
+            "pubsub" => self.flags & PUBSUB != 0,
+            "fast" => self.flags & FAST != 0,
+            "slow" => self.flags & FAST == 0,
+            _ => false,
+        };
+        implied || self.categories.contains(&category)
+    }
+
+    pub fn subcommand(&self, name: &str) -> Option<&CommandSpec> {
+        self.subcommands.iter().find(|sub| sub.name == name)
+    }
+}
+
+const CLIENT_SUBCOMMANDS: [CommandSpec; 15] = [
+    CommandSpec::new("id", 0, &["connection"]),
+    CommandSpec::new("getname", 0, &["connection"]),
+    CommandSpec::new("setname", 0, &["connection"]),
+    CommandSpec::new("info", 0, &["connection"]),
+    CommandSpec::new("list", ADMIN, &["connection"]),
+    CommandSpec::new("kill", ADMIN, &["connection"]),
+    CommandSpec::new("pause", ADMIN, &["connection"]),
+    CommandSpec::new("unpause", ADMIN, &["connection"]),
+    CommandSpec::new("no-evict", ADMIN, &["connection"]),
+    CommandSpec::new("no-touch", 0, &["connection"]),
+    CommandSpec::new("reply", 0, &["connection"]),
+    CommandSpec::new("tracking", 0, &["connection"]),
+    CommandSpec::new("getredir", 0, &["connection"]),
+    CommandSpec::new("caching", 0, &["connection"]),
+    CommandSpec::new("help", 0, &["connection"]),
+];
+
+const CONFIG_SUBCOMMANDS: [CommandSpec; 4] = [
+    CommandSpec::new("resetstat", ADMIN, &[]),
+    CommandSpec::new("get", ADMIN, &[]),
+    CommandSpec::new("set", ADMIN, &[]),
+    CommandSpec::new("help", 0, &[]),
+];
+
+const OBJECT_SUBCOMMANDS: [CommandSpec; 5] = [
+    CommandSpec::new("encoding", READONLY, &["keyspace"]).key_at(2),
+    CommandSpec::new("freq", READONLY, &["keyspace"]).key_at(2),
+    CommandSpec::new("idletime", READONLY, &["keyspace"]).key_at(2),
+    CommandSpec::new("refcount", READONLY, &["keyspace"]).key_at(2),
+    CommandSpec::new("help", 0, &["keyspace"]),
+];
+
+const MEMORY_SUBCOMMANDS: [CommandSpec; 4] = [
+    CommandSpec::new("usage", READONLY, &[]).key_at(2),
+    CommandSpec::new("stats", 0, &[]),
+    CommandSpec::new("doctor", 0, &[]),
+    CommandSpec::new("help", 0, &[]),
+];
+
+const LATENCY_SUBCOMMANDS: [CommandSpec; 6] = [
+    CommandSpec::new("histogram", ADMIN, &[]),
+    CommandSpec::new("latest", ADMIN, &[]),
+    CommandSpec::new("history", ADMIN, &[]),
+    CommandSpec::new("reset", ADMIN, &[]),
+    CommandSpec::new("doctor", ADMIN, &[]),
+    CommandSpec::new("help", 0, &[]),
+];
+
+const ACL_SUBCOMMANDS: [CommandSpec; 10] = [
+    CommandSpec::new("setuser", ADMIN, &[]),
+    CommandSpec::new("getuser", ADMIN, &[]),
+    CommandSpec::new("deluser", ADMIN, &[]),
+    CommandSpec::new("list", ADMIN, &[]),
+    CommandSpec::new("users", ADMIN, &[]),
+    CommandSpec::new("load", ADMIN, &[]),
+    CommandSpec::new("save", ADMIN, &[]),
+    CommandSpec::new("whoami", 0, &[]),
+    CommandSpec::new("cat", 0, &[]),
+    CommandSpec::new("help", 0, &[]),
+];
+
+pub(super) const COMMANDS: [CommandSpec; 70] = [
+    CommandSpec::new("ping", FAST | LOADING, &["connection"]),
+    CommandSpec::new("echo", FAST | LOADING, &["connection"]),
+    CommandSpec::new("auth", FAST | NO_AUTH | LOADING, &["connection"]),
+    CommandSpec::new("hello", FAST | NO_AUTH | LOADING, &["connection"]),
+    CommandSpec::new("time", FAST | LOADING, &[]),
+    CommandSpec::new("quit", FAST | NO_AUTH | LOADING, &["connection"]),
+    CommandSpec::new("reset", FAST | NO_AUTH | LOADING, &["connection"]),
+    CommandSpec::new("lolwut", READONLY | FAST, &[]),
+    CommandSpec::new("readonly", FAST | LOADING, &["connection"]),
+    CommandSpec::new("readwrite", FAST | LOADING, &["connection"]),
+    CommandSpec::new("cluster", LOADING, &[]),
+    CommandSpec::new("set", WRITE | DENYOOM, &["string"]).key_at(1),
+    CommandSpec::new("get", READONLY | FAST, &["string"]).key_at(1),
+    CommandSpec::new("strlen", READONLY | FAST, &["string"]).key_at(1),
+    CommandSpec::new("append", WRITE | DENYOOM | FAST, &["string"]).key_at(1),
+    CommandSpec::new("getrange", READONLY, &["string"]).key_at(1),
+    CommandSpec::new("setrange", WRITE | DENYOOM, &["string"]).key_at(1),
+    CommandSpec::new("incr", WRITE | DENYOOM | FAST, &["string"]).key_at(1),
+    CommandSpec::new("decr", WRITE | DENYOOM | FAST, &["string"]).key_at(1),
+    CommandSpec::new("incrby", WRITE | DENYOOM | FAST, &["string"]).key_at(1),
+    CommandSpec::new("decrby", WRITE | DENYOOM | FAST, &["string"]).key_at(1),
+    CommandSpec::new("hset", WRITE | DENYOOM | FAST, &["hash"]).key_at(1),
+    CommandSpec::new("hget", READONLY | FAST, &["hash"]).key_at(1),
+    CommandSpec::new("hlen", READONLY | FAST, &["hash"]).key_at(1),
+    CommandSpec::new("hgetall", READONLY, &["hash"]).key_at(1),
+    CommandSpec::new("json.set", WRITE | DENYOOM, &["json"]).key_at(1),
+    CommandSpec::new("json.get", READONLY, &["json"]).key_at(1),
+    CommandSpec::new("json.del", WRITE, &["json"]).key_at(1),
+    CommandSpec::new("json.type", READONLY | FAST, &["json"]).key_at(1),
+    CommandSpec::new("json.numincrby", WRITE, &["json"]).key_at(1),
+    CommandSpec::new("json.arrappend", WRITE | DENYOOM, &["json"]).key_at(1),
+    CommandSpec::new("bf.reserve", WRITE | DENYOOM, &["bloom"]).key_at(1),
+    CommandSpec::new("bf.add", WRITE | DENYOOM, &["bloom"]).key_at(1),
+    CommandSpec::new("bf.madd", WRITE | DENYOOM, &["bloom"]).key_at(1),
+    CommandSpec::new("bf.exists", READONLY, &["bloom"]).key_at(1),
+    CommandSpec::new("bf.mexists", READONLY, &["bloom"]).key_at(1),
+    CommandSpec::new("cms.initbydim", WRITE | DENYOOM, &["cms"]).key_at(1),
+    CommandSpec::new("cms.incrby", WRITE | DENYOOM, &["cms"]).key_at(1),
+    CommandSpec::new("cms.query", READONLY, &["cms"]).key_at(1),
+    CommandSpec::new("topk.reserve", WRITE | DENYOOM, &["topk"]).key_at(1),
+    CommandSpec::new("topk.add", WRITE | DENYOOM, &["topk"]).key_at(1),
+    CommandSpec::new("topk.list", READONLY, &["topk"]).key_at(1),
+    CommandSpec::new("topk.query", READONLY, &["topk"]).key_at(1),
+    CommandSpec::new("ts.create", WRITE | DENYOOM, &["timeseries"]).key_at(1),
+    CommandSpec::new("ts.add", WRITE | DENYOOM, &["timeseries"]).key_at(1),
+    CommandSpec::new("ts.range", READONLY, &["timeseries"]).key_at(1),
+    CommandSpec::new("ts.mrange", READONLY, &["timeseries"]),
+    CommandSpec::new("ft.create", WRITE | DENYOOM, &["search"]),
+    CommandSpec::new("ft.search", READONLY, &["search"]),
+    CommandSpec::new("info", LOADING, &["dangerous"]),
+    CommandSpec::new("expire", WRITE | FAST, &["keyspace"]).key_at(1),
+    CommandSpec::new("pexpire", WRITE | FAST, &["keyspace"]).key_at(1),
+    CommandSpec::new("ttl", READONLY | FAST, &["keyspace"]).key_at(1),
+    CommandSpec::new("pttl", READONLY | FAST, &["keyspace"]).key_at(1),
+    CommandSpec::new("persist", WRITE | FAST, &["keyspace"]).key_at(1),
+    CommandSpec::new("del", WRITE, &["keyspace"]).keys_from(1),
+    CommandSpec::new("unlink", WRITE | FAST, &["keyspace"]).keys_from(1),
+    CommandSpec::new("scan", READONLY, &["keyspace"]),
+    CommandSpec::new("type", READONLY | FAST, &["keyspace"]).key_at(1),
+    CommandSpec::new("flushdb", WRITE, &["keyspace", "dangerous"]),
+    CommandSpec::new("flushall", WRITE, &["keyspace", "dangerous"]),
+    CommandSpec::new("object", 0, &[]).with_subcommands(&OBJECT_SUBCOMMANDS),
+    CommandSpec::new("client", LOADING, &["connection"]).with_subcommands(&CLIENT_SUBCOMMANDS),
+    CommandSpec::new("config", LOADING, &[]).with_subcommands(&CONFIG_SUBCOMMANDS),
+    CommandSpec::new("memory", 0, &[]).with_subcommands(&MEMORY_SUBCOMMANDS),
+    CommandSpec::new("latency", LOADING, &[]).with_subcommands(&LATENCY_SUBCOMMANDS),
+    CommandSpec::new("acl", 0, &[]).with_subcommands(&ACL_SUBCOMMANDS),
+    CommandSpec::new("debug", ADMIN | PROTECTED | LOADING, &[]),
+    CommandSpec::new("save", ADMIN, &[]),
+    CommandSpec::new("shutdown", ADMIN | LOADING, &[]),
+];
+
+/// Looks up a command by its lowercased name.
+pub(super) fn lookup(name: &str) -> Option<&'static CommandSpec> {
+    COMMANDS.iter().find(|spec| spec.name == name)
+}
+
+/// Every command and subcommand in `category`, with subcommands named `command|subcommand`.
+pub(super) fn commands_in_category(category: &str) -> Vec<String> {
+    let mut names = Vec::new();
+    for spec in &COMMANDS {
+        if spec.in_category(category) {
+            names.push(spec.name.to_string());
+        }
+        for sub in spec.subcommands {
+            if sub.in_category(category) {
+                names.push(format!("{}|{}", spec.name, sub.name));
+            }
+        }
+    }
+    names
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_in_category() {
+        let test_cases = [
+            ("Write flag implies @write", "set", "write", true),
+            ("Write command is not @read", "set", "read", false),
+            ("Readonly flag implies @read", "get", "read", true),
+            ("Fast flag implies @fast", "get", "fast", true),
+            ("No fast flag implies @slow", "hgetall", "slow", true),
+            (
+                "Admin flag implies @dangerous",
+                "shutdown",
+                "dangerous",
+                true,
+            ),
+            ("Explicit category", "ttl", "keyspace", true),
+            ("Unrelated category", "ping", "hash", false),
+        ];
+
+        for (name, command, category, expected) in test_cases {
+            let spec = lookup(command).unwrap();
+            assert_eq!(spec.in_category(category), expected, "{}", name);
+        }
+    }
+
+    #[test]
+    fn test_commands_in_category() {
+        let admin = commands_in_category("admin");
+        assert!(admin.contains(&"client|kill".to_string()));
+        assert!(admin.contains(&"config|resetstat".to_string()));
+        assert!(!admin.contains(&"client".to_string()));
+        assert_eq!(
+            commands_in_category("string"),
+            vec![
+                "set", "get", "strlen", "append", "getrange", "setrange", "incr", "decr", "incrby",
+                "decrby"
+            ]
+        );
+    }
+}
@@ -0,0 +1,479 @@
+use super::client::{self, ReplyMode};
+use super::errors;
+use super::CommandHandler;
+use crate::resp::RespData;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+const LOLWUT_WIDTH: usize = 66;
+const LOLWUT_SQUARES_PER_ROW: usize = 8;
+const LOLWUT_ROWS: usize = 12;
+
+impl CommandHandler {
+    pub(super) fn time(&mut self, resp: &RespData) -> RespData {
+        if matches!(resp, RespData::Array(arr) if arr.len() != 1) {
+            return errors::wrong_arity("time");
+        }
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default();
+        RespData::Array(vec![
+            RespData::BulkString(now.as_secs().to_string()),
+            RespData::BulkString(now.subsec_micros().to_string()),
+        ])
+    }
+
+    /// Replies OK and marks the connection to be closed once the reply has been written.
+    pub(super) fn quit(&mut self) -> RespData {
+        if let Some(client) = self.current_client_mut() {
+            client.close_after_reply = true;
+        }
+        RespData::SimpleString("OK".to_string())
+    }
+
+    /// HELLO [protover [AUTH username password] [SETNAME clientname]]: switches the protocol
+    /// version, optionally logging in and naming the connection too, and replies with facts
+    /// about the server, as a map under RESP3.
+    pub(super) fn hello(&mut self, resp: &RespData) -> RespData {
+        let RespData::Array(arr) = resp else {
+            return errors::syntax_error();
+        };
+        let mut args = arr[1..].iter();
+        let protover = match args.next() {
+            Some(RespData::BulkString(protover)) => match protover.parse::<i64>() {
+                Ok(protover @ (2 | 3)) => Some(protover as u8),
+                Ok(_) => {
+                    return RespData::Error("-NOPROTO unsupported protocol version".to_string())
+                }
+                Err(_) => {
+                    return RespData::Error(
+                        "Protocol version is not an integer or out of range".to_string(),
+                    )
+                }
+            },
+            Some(_) => return errors::syntax_error(),
+            None => None,
+        };
+
+        let mut credentials = None;
+        let mut name = None;
+        while let Some(option) = args.next() {
+            let RespData::BulkString(option) = option else {
+                return errors::syntax_error();
+            };
+            match (option.to_uppercase().as_str(), args.next()) {
+                ("AUTH", Some(username @ RespData::BulkString(_))) => {
+                    let Some(password @ RespData::BulkString(_)) = args.next() else {
+                        return RespData::Error(format!("Syntax error in HELLO option '{option}'"));
+                    };
+                    credentials = Some((username, password));
+                }
+                ("SETNAME", Some(RespData::BulkString(value))) => {
+                    if let Some(error) = client::invalid_client_name(value) {
+                        return error;
+                    }
+                    name = Some(value);
+                }
+                _ => return RespData::Error(format!("Syntax error in HELLO option '{option}'")),
+            }
+        }
+
+        match credentials {
+            Some((username, password)) => {
+                let auth = RespData::Array(vec![
+                    RespData::BulkString("AUTH".to_string()),
+                    username.clone(),
+                    password.clone(),
+                ]);
+                if let error @ RespData::Error(_) = self.auth(&auth) {
+                    return error;
+                }
+            }
+            None if !self.client_authenticated() => {
+                return errors::hello_no_auth();
+            }
+            None => {}
+        }
+        let (id, proto) = match self.current_client_mut() {
+            Some(client) => {
+                if let Some(name) = name {
+                    client.name = (!name.is_empty()).then(|| name.clone());
+                }
+                client.resp = protover.unwrap_or(client.resp);
+                (client.id, client.resp)
+            }
+            None => (0, protover.unwrap_or(2)),
+        };
+
+        let fields = [
+            ("server", RespData::BulkString("redis".to_string())),
+            (
+                "version",
+                RespData::BulkString(env!("CARGO_PKG_VERSION").to_string()),
+            ),
+            ("proto", RespData::Integer(proto as i64)),
+            ("id", RespData::Integer(id as i64)),
+            ("mode", RespData::BulkString("standalone".to_string())),
+            ("role", RespData::BulkString("master".to_string())),
+            ("modules", RespData::Array(vec![])),
+        ];
+        let fields = fields
+            .into_iter()
+            .map(|(field, value)| (RespData::BulkString(field.to_string()), value));
+        if proto == 3 {
+            RespData::Map(fields.collect())
+        } else {
+            RespData::Array(fields.flat_map(|(field, value)| [field, value]).collect())
+        }
+    }
+
+    /// Returns the connection to the state it had right after connecting.
+    pub(super) fn reset(&mut self, resp: &RespData) -> RespData {
+        if matches!(resp, RespData::Array(arr) if arr.len() != 1) {
+            return errors::wrong_arity("reset");
+        }
+        if let Some(client) = self.current_client_mut() {
+            client.name = None;
+            client.resp = 2;
+            client.reply_mode = ReplyMode::On;
+            client.no_evict = false;
+            client.no_touch = false;
+            client.user = "default".to_string();
+            client.authenticated = false;
+        }
+        if let Some(id) = self.current_client {
+            self.disable_tracking(id);
+        }
+        RespData::SimpleString("RESET".to_string())
+    }
+
+    /// READONLY and READWRITE, which let a cluster replica serve reads for its master's slots.
+    /// There is no cluster mode, so like a standalone Redis they always fail.
+    pub(super) fn cluster_read_mode(&mut self, name: &str, resp: &RespData) -> RespData {
+        if matches!(resp, RespData::Array(arr) if arr.len() != 1) {
+            return errors::wrong_arity(name);
+        }
+        errors::cluster_disabled()
+    }
+
+    /// CLUSTER subcommand [arg ...], such as CLUSTER FAILOVER to promote a replica. There is
+    /// no cluster mode, so like a standalone Redis every subcommand fails, HELP included.
+    pub(super) fn cluster(&mut self, resp: &RespData) -> RespData {
+        if matches!(resp, RespData::Array(arr) if arr.len() < 2) {
+            return errors::wrong_arity("cluster");
+        }
+        errors::cluster_disabled()
+    }
+
+    /// LOLWUT [VERSION version]: a rendition of Georg Nees' "Schotter" and the server version.
+    pub(super) fn lolwut(&mut self, resp: &RespData) -> RespData {
+        let RespData::Array(arr) = resp else {
+            return errors::syntax_error();
+        };
+        match &arr[1..] {
+            [] => {}
+            [RespData::BulkString(option), RespData::BulkString(version)]
+                if option.eq_ignore_ascii_case("VERSION") =>
+            {
+                if version.parse::<u32>().is_err() {
+                    return errors::not_an_integer();
+                }
+            }
+            _ => return errors::syntax_error(),
+        }
+
+        let mut output = schotter();
+        output.push_str(&format!(
+            "\nGeorg Nees - schotter, plotter on paper, 1968. Redis ver. {}\n",
+            env!("CARGO_PKG_VERSION")
+        ));
+        RespData::BulkString(output)
+    }
+}
+
+/// Draws rows of squares that get increasingly displaced towards the bottom, like Schotter.
+fn schotter() -> String {
+    const SHAPES: [char; 4] = ['#', '%', '@', '&'];
+    let square_width = LOLWUT_WIDTH / LOLWUT_SQUARES_PER_ROW;
+    // Fixed seed so the piece is the same on every call.
+    let mut seed: u32 = 0x2545_f491;
+    let mut next = move || {
+        seed ^= seed << 13;
+        seed ^= seed >> 17;
+        seed ^= seed << 5;
+        seed
+    };
+
+    let mut output = String::new();
+    for row in 0..LOLWUT_ROWS {
+        let disorder = row * 2 / LOLWUT_ROWS + row / 4;
+        let mut line = vec![' '; LOLWUT_WIDTH + disorder * 2];
+        for square in 0..LOLWUT_SQUARES_PER_ROW {
+            let offset = if disorder == 0 {
+                0
+            } else {
+                next() as usize % (disorder * 2 + 1)
+            };
+            let shape = if disorder == 0 {
+                SHAPES[0]
+            } else {
+                SHAPES[next() as usize % SHAPES.len()]
+            };
+            let start = square * square_width + offset;
+            for cell in line.iter_mut().skip(start).take(square_width - 1) {
+                *cell = shape;
+            }
+        }
+        output.push_str(line.iter().collect::<String>().trim_end());
+        output.push('\n');
+    }
+    output
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+
+    fn command(args: &[&str]) -> RespData {
+        RespData::array(args.iter().copied())
+    }
+
+    #[test]
+    fn test_echo() {
+        let mut handler = CommandHandler::from(HashMap::new());
+
+        let test_cases = [
+            (
+                "Valid ECHO",
+                command(&["ECHO", "hello"]),
+                RespData::BulkString("hello".to_string()),
+            ),
+            (
+                "Not enough arguments",
+                command(&["ECHO"]),
+                RespData::Error("wrong number of arguments for 'echo' command".to_string()),
+            ),
+            (
+                "Too many arguments",
+                command(&["ECHO", "a", "b"]),
+                RespData::Error("wrong number of arguments for 'echo' command".to_string()),
+            ),
+        ];
+
+        for (name, input, expected_output) in test_cases {
+            let result = handler.handle(&input);
+            assert_eq!(result, expected_output, "{}", name);
+        }
+    }
+
+    #[test]
+    fn test_time() {
+        let mut handler = CommandHandler::from(HashMap::new());
+
+        let RespData::Array(result) = handler.time(&command(&["TIME"])) else {
+            panic!("TIME did not return an array");
+        };
+        let [RespData::BulkString(secs), RespData::BulkString(micros)] = result.as_slice() else {
+            panic!("TIME did not return two bulk strings");
+        };
+        assert!(secs.parse::<u64>().unwrap() > 1_600_000_000);
+        assert!(micros.parse::<u32>().unwrap() < 1_000_000);
+        assert_eq!(
+            handler.time(&command(&["TIME", "now"])),
+            RespData::Error("wrong number of arguments for 'time' command".to_string())
+        );
+    }
+
+    #[test]
+    fn test_hello() {
+        let config = crate::config::Config {
+            requirepass: Some("s3cret".to_string()),
+            ..Default::default()
+        };
+        let mut handler = CommandHandler::new(
+            HashMap::new(),
+            std::sync::Arc::new(std::sync::RwLock::new(config)),
+        );
+        let id = handler.register_client(
+            "127.0.0.1:5001".parse().unwrap(),
+            "127.0.0.1:6379".parse().unwrap(),
+            7,
+            None,
+        );
+        let bulk = |value: &str| RespData::BulkString(value.to_string());
+        let fields = |proto: i64| {
+            vec![
+                (bulk("server"), bulk("redis")),
+                (bulk("version"), bulk(env!("CARGO_PKG_VERSION"))),
+                (bulk("proto"), RespData::Integer(proto)),
+                (bulk("id"), RespData::Integer(id as i64)),
+                (bulk("mode"), bulk("standalone")),
+                (bulk("role"), bulk("master")),
+                (bulk("modules"), RespData::Array(vec![])),
+            ]
+        };
+
+        let test_cases = [
+            (
+                "Not authenticated",
+                command(&["HELLO", "3"]),
+                errors::hello_no_auth(),
+            ),
+            (
+                "Wrong password",
+                command(&["HELLO", "3", "AUTH", "default", "guess"]),
+                errors::wrong_pass(),
+            ),
+            (
+                "Unsupported version",
+                command(&["HELLO", "4"]),
+                RespData::Error("-NOPROTO unsupported protocol version".to_string()),
+            ),
+            (
+                "Version not a number",
+                command(&["HELLO", "three"]),
+                RespData::Error("Protocol version is not an integer or out of range".to_string()),
+            ),
+            (
+                "Unknown option",
+                command(&["HELLO", "3", "FAST"]),
+                RespData::Error("Syntax error in HELLO option 'FAST'".to_string()),
+            ),
+            (
+                "Missing password",
+                command(&["HELLO", "3", "AUTH", "default"]),
+                RespData::Error("Syntax error in HELLO option 'AUTH'".to_string()),
+            ),
+            (
+                "RESP3 with AUTH and SETNAME",
+                command(&[
+                    "HELLO", "3", "AUTH", "default", "s3cret", "SETNAME", "cache",
+                ]),
+                RespData::Map(fields(3)),
+            ),
+            (
+                "Without a version",
+                command(&["HELLO"]),
+                RespData::Map(fields(3)),
+            ),
+            (
+                "Back to RESP2",
+                command(&["HELLO", "2"]),
+                RespData::Array(
+                    fields(2)
+                        .into_iter()
+                        .flat_map(|(field, value)| [field, value])
+                        .collect(),
+                ),
+            ),
+        ];
+
+        for (name, input, expected) in test_cases {
+            assert_eq!(
+                handler.handle_client(id, &input),
+                Some(expected),
+                "{}",
+                name
+            );
+        }
+        assert_eq!(
+            handler.handle_client(id, &command(&["CLIENT", "GETNAME"])),
+            Some(bulk("cache"))
+        );
+    }
+
+    #[test]
+    fn test_quit_and_reset() {
+        let mut handler = CommandHandler::from(HashMap::new());
+        let id = handler.register_client(
+            "127.0.0.1:5001".parse().unwrap(),
+            "127.0.0.1:6379".parse().unwrap(),
+            7,
+            None,
+        );
+
+        handler.handle_client(id, &command(&["CLIENT", "SETNAME", "worker"]));
+        handler.handle_client(id, &command(&["CLIENT", "NO-TOUCH", "on"]));
+        assert_eq!(
+            handler.handle_client(id, &command(&["RESET"])),
+            Some(RespData::SimpleString("RESET".to_string()))
+        );
+        assert_eq!(
+            handler.handle_client(id, &command(&["CLIENT", "GETNAME"])),
+            Some(RespData::Null)
+        );
+        assert!(!handler.clients[&id].no_touch);
+
+        assert!(!handler.client_closing(id));
+        assert_eq!(
+            handler.handle_client(id, &command(&["QUIT"])),
+            Some(RespData::SimpleString("OK".to_string()))
+        );
+        assert!(handler.client_closing(id));
+    }
+
+    #[test]
+    fn test_cluster_commands() {
+        let mut handler = CommandHandler::from(HashMap::new());
+
+        let test_cases = [
+            (
+                "READONLY",
+                command(&["READONLY"]),
+                RespData::Error("This instance has cluster support disabled".to_string()),
+            ),
+            (
+                "READWRITE",
+                command(&["readwrite"]),
+                RespData::Error("This instance has cluster support disabled".to_string()),
+            ),
+            (
+                "Too many arguments",
+                command(&["READONLY", "now"]),
+                RespData::Error("wrong number of arguments for 'readonly' command".to_string()),
+            ),
+            (
+                "CLUSTER FAILOVER",
+                command(&["CLUSTER", "FAILOVER", "TAKEOVER"]),
+                RespData::Error("This instance has cluster support disabled".to_string()),
+            ),
+            (
+                "CLUSTER HELP",
+                command(&["cluster", "help"]),
+                RespData::Error("This instance has cluster support disabled".to_string()),
+            ),
+            (
+                "CLUSTER without a subcommand",
+                command(&["CLUSTER"]),
+                RespData::Error("wrong number of arguments for 'cluster' command".to_string()),
+            ),
+        ];
+
+        for (name, input, expected_output) in test_cases {
+            let result = handler.handle(&input);
+            assert_eq!(result, expected_output, "{}", name);
+        }
+    }
+
+    #[test]
+    fn test_lolwut() {
+        let mut handler = CommandHandler::from(HashMap::new());
+
+        let RespData::BulkString(art) = handler.lolwut(&command(&["LOLWUT"])) else {
+            panic!("LOLWUT did not return a bulk string");
+        };
+        assert!(art.contains("Georg Nees - schotter"));
+        assert_eq!(
+            handler.lolwut(&command(&["LOLWUT"])),
+            RespData::BulkString(art)
+        );
+        assert!(matches!(
+            handler.lolwut(&command(&["LOLWUT", "VERSION", "5"])),
+            RespData::BulkString(_)
+        ));
+        assert_eq!(
+            handler.lolwut(&command(&["LOLWUT", "VERSION", "five"])),
+            RespData::Error("value is not an integer or out of range".to_string())
+        );
+    }
+}
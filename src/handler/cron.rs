@@ -0,0 +1,228 @@
+//! Periodic background work, run `hz` times a second like Redis's `serverCron`.
+
+use super::events::KeyEventKind;
+use super::evict::xorshift64_star;
+use super::CommandHandler;
+use std::time::{Duration, Instant};
+
+/// Keys with a TTL sampled per round of active expiry.
+const ACTIVE_EXPIRE_KEYS_PER_LOOP: usize = 20;
+/// Percentage of each tick active expiry may spend.
+const ACTIVE_EXPIRE_CYCLE_PERCENT: u32 = 25;
+/// How long each tick may spend moving keyspace entries to resized tables.
+const ACTIVE_REHASH_BUDGET: Duration = Duration::from_millis(1);
+/// How often the command rate is sampled for `instantaneous_ops_per_sec`.
+const OPS_SAMPLE_PERIOD: Duration = Duration::from_millis(100);
+/// How often idle clients are looked for when `timeout` is set.
+const CLIENT_TIMEOUT_PERIOD: Duration = Duration::from_secs(1);
+/// How long to wait before retrying an automatic save that failed.
+const SAVE_RETRY_DELAY: Duration = Duration::from_secs(5);
+
+impl CommandHandler {
+    /// How long to wait between runs of `server_cron`.
+    pub fn cron_interval(&self) -> Duration {
+        Duration::from_secs(1) / self.config.read().unwrap().hz
+    }
+
+    /// Runs one round of the periodic tasks: expiring keys nobody asks for, evicting keys if
+    /// the dataset is over `maxmemory`, moving keyspace resizes along, sampling stats, closing
+    /// idle clients, saving once a save point is reached and backing the snapshot up.
+    pub fn server_cron(&mut self) {
+        let interval = self.cron_interval();
+        // Keys are left alone until the whole dataset is in.
+        if !self.is_loading() {
+            if self.active_expire_enabled {
+                self.active_expire_cycle(interval * ACTIVE_EXPIRE_CYCLE_PERCENT / 100);
+            }
+            self.perform_evictions();
+        }
+        self.flush_broadcasts();
+        self.db.background_work(ACTIVE_REHASH_BUDGET);
+        self.access_times.rehash_for(ACTIVE_REHASH_BUDGET);
+        self.lfu_counters.rehash_for(ACTIVE_REHASH_BUDGET);
+        if self.run_with_period(OPS_SAMPLE_PERIOD, interval) {
+            self.stats.track_instantaneous_ops();
+        }
+        if self.run_with_period(CLIENT_TIMEOUT_PERIOD, interval) {
+            let closed = self.close_idle_clients();
+            if closed > 0 {
+                log_verbose!("Closed {closed} idle clients");
+            }
+        }
+        self.save_if_needed();
+        self.backup_if_needed();
+        self.cron_loops += 1;
+    }
+
+    /// Whether a task meant to run every `period` is due this tick.
+    fn run_with_period(&self, period: Duration, interval: Duration) -> bool {
+        let ticks = (period.as_millis() / interval.as_millis().max(1)) as u64;
+        ticks <= 1 || self.cron_loops.is_multiple_of(ticks)
+    }
+
+    /// Deletes expired keys that haven't been accessed, so they don't linger in memory. Samples
+    /// keys with a TTL and keeps going for as long as more than a quarter of each sample had
+    /// expired, or until `budget` runs out.
+    fn active_expire_cycle(&mut self, budget: Duration) {
+        let start = Instant::now();
+        while self.db.expires_len() > 0 {
+            let now = self.clock.now();
+            let mut random = || xorshift64_star(&mut self.random_state);
+            let mut expired: Vec<String> = (0..ACTIVE_EXPIRE_KEYS_PER_LOOP)
+                .filter_map(|_| self.db.random_expiring(&mut random))
+                .filter(|(_, deadline)| *deadline <= now)
+                .map(|(key, _)| key.clone())
+                .collect();
+            expired.sort_unstable();
+            expired.dedup();
+            for key in &expired {
+                self.delete_key(key);
+                self.stats.expired_keys += 1;
+                self.notify_key_event(KeyEventKind::Expired, key);
+            }
+            if expired.len() * 4 <= ACTIVE_EXPIRE_KEYS_PER_LOOP || start.elapsed() >= budget {
+                break;
+            }
+        }
+    }
+
+    /// Saves the dataset if any `save` point's change and time thresholds have been reached.
+    /// After a failure it waits a while before trying again.
+    fn save_if_needed(&mut self) {
+        let since_save = self.last_save.elapsed().unwrap_or_default();
+        let due = self
+            .config
+            .read()
+            .unwrap()
+            .save
+            .iter()
+            .find(|point| {
+                self.dirty >= point.changes && since_save >= Duration::from_secs(point.seconds)
+            })
+            .copied();
+        let Some(point) = due else {
+            return;
+        };
+        if self
+            .last_save_failure
+            .is_some_and(|failure| failure.elapsed() < SAVE_RETRY_DELAY)
+        {
+            return;
+        }
+        log_notice!(
+            "{} changes in {} seconds. Saving...",
+            point.changes,
+            point.seconds
+        );
+        match self.save_rdb() {
+            Ok(()) => {
+                log_notice!("DB saved on disk");
+                self.last_save_failure = None;
+            }
+            Err(e) => {
+                log_warning!("Error saving DB on disk: {e}");
+                self.last_save_failure = Some(Instant::now());
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::{Config, SavePoint};
+    use crate::handler::clock::MockClock;
+    use crate::resp::RespData;
+    use std::collections::HashMap;
+    use std::sync::{Arc, RwLock};
+    use std::{fs, process};
+
+    fn command(args: &[&str]) -> RespData {
+        RespData::array(args.iter().copied())
+    }
+
+    #[test]
+    fn test_run_with_period() {
+        let mut handler = CommandHandler::from(HashMap::new());
+        let interval = Duration::from_millis(100);
+
+        let test_cases = [
+            ("Every tick", 0, Duration::from_millis(100), true),
+            ("Shorter than a tick", 3, Duration::from_millis(10), true),
+            ("Due", 20, Duration::from_secs(1), true),
+            ("Not due", 21, Duration::from_secs(1), false),
+        ];
+
+        for (name, cron_loops, period, expected) in test_cases {
+            handler.cron_loops = cron_loops;
+            assert_eq!(
+                handler.run_with_period(period, interval),
+                expected,
+                "{}",
+                name
+            );
+        }
+    }
+
+    #[test]
+    fn test_active_expire() {
+        let clock = Arc::new(MockClock::new());
+        let mut handler = CommandHandler::from(HashMap::new());
+        handler.clock = clock.clone();
+        for i in 0..100 {
+            handler.handle(&command(&["SET", &format!("short{i}"), "v", "PX", "1"]));
+        }
+        for i in 0..10 {
+            handler.handle(&command(&["SET", &format!("long{i}"), "v", "EX", "100"]));
+        }
+        handler.handle(&command(&["SET", "persistent", "v"]));
+        clock.advance(Duration::from_millis(5));
+
+        handler.server_cron();
+
+        // Sampling stops once few of the sampled keys turn out to be expired, so a handful
+        // may be left for later ticks.
+        assert!(handler.stats.expired_keys > 80);
+        assert_eq!(handler.db.len() as u64, 111 - handler.stats.expired_keys);
+        assert!((0..10).all(|i| handler.db.contains_key(&format!("long{i}"))));
+        assert!(handler.db.contains_key("persistent"));
+        assert_eq!(handler.cron_loops, 1);
+    }
+
+    #[test]
+    fn test_save_points() {
+        let dir = std::env::temp_dir().join(format!("redis-cron-{}", process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let config = Config {
+            dir: dir.clone(),
+            save: vec![SavePoint {
+                seconds: 0,
+                changes: 2,
+            }],
+            ..Config::default()
+        };
+        let mut handler = CommandHandler::new(HashMap::new(), Arc::new(RwLock::new(config)));
+
+        handler.handle(&command(&["SET", "a", "1"]));
+        handler.server_cron();
+        assert_eq!(handler.dirty, 1);
+        assert!(!dir.join("dump.rdb").exists());
+
+        handler.handle(&command(&["SET", "b", "2"]));
+        handler.server_cron();
+        assert_eq!(handler.dirty, 0);
+        assert!(dir.join("dump.rdb").exists());
+
+        // A failed save isn't retried on the very next tick.
+        handler.config.write().unwrap().dir = dir.join("missing");
+        handler.handle(&command(&["SET", "c", "3"]));
+        handler.handle(&command(&["SET", "d", "4"]));
+        handler.server_cron();
+        let failure = handler.last_save_failure.unwrap();
+        handler.server_cron();
+        assert_eq!(handler.last_save_failure, Some(failure));
+        assert_eq!(handler.dirty, 2);
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
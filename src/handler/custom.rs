@@ -0,0 +1,215 @@
+//! Commands registered by code embedding the server, on top of the built-in ones. A custom
+//! command reaches the dataset through a `Keyspace`, which runs built-in commands on its
+//! behalf so memory accounting, key events and client tracking stay right.
+
+use super::{command_table, errors, is_write_command, CommandHandler, ReplyStreaming};
+use crate::resp::RespData;
+use std::sync::Arc;
+
+type CustomCommandFn = dyn Fn(&mut Keyspace, &[String]) -> RespData + Send + Sync;
+
+/// A command to add with `CommandHandler::register_command`.
+#[derive(Clone)]
+pub struct CustomCommand {
+    name: String,
+    arity: i32,
+    run: Arc<CustomCommandFn>,
+}
+
+impl CustomCommand {
+    /// A command called `name`, taking `arity` arguments counting the name itself, or at
+    /// least `-arity` if it is negative, as COMMAND INFO reports it. `run` is given the
+    /// arguments after the name.
+    pub fn new(
+        name: &str,
+        arity: i32,
+        run: impl Fn(&mut Keyspace, &[String]) -> RespData + Send + Sync + 'static,
+    ) -> Self {
+        Self {
+            name: name.to_lowercase(),
+            arity,
+            run: Arc::new(run),
+        }
+    }
+
+    fn accepts(&self, args: usize) -> bool {
+        match self.arity {
+            arity if arity >= 0 => args == arity as usize,
+            arity => args >= arity.unsigned_abs() as usize,
+        }
+    }
+}
+
+/// The dataset as seen by a custom command. Keys aren't checked against the client's ACL key
+/// patterns, and reads through it don't make the client track the keys.
+pub struct Keyspace<'a> {
+    handler: &'a mut CommandHandler,
+}
+
+impl Keyspace<'_> {
+    /// Runs the built-in command made of `args` and returns its reply.
+    pub fn call(&mut self, args: &[&str]) -> RespData {
+        let resp = RespData::array(args.iter().copied());
+        let name = args
+            .first()
+            .map(|name| name.to_lowercase())
+            .unwrap_or_default();
+        // Only built-in commands, so a custom command can't recurse into itself.
+        let Some(reply) =
+            command_table::lookup(&name).and_then(|_| self.handler.execute(&name, &resp))
+        else {
+            return errors::unknown_command(&name, &[]);
+        };
+        if is_write_command(&name) && !matches!(reply, RespData::Error(_)) {
+            self.handler.dirty += 1;
+            self.handler.invalidate_keys(&name, &resp);
+        }
+        reply
+    }
+
+    /// The string at `key`, or the error reply if it holds another type.
+    pub fn get(&mut self, key: &str) -> Result<Option<String>, RespData> {
+        match self.call(&["GET", key]) {
+            RespData::BulkString(value) => Ok(Some(value)),
+            RespData::Error(e) => Err(RespData::Error(e)),
+            _ => Ok(None),
+        }
+    }
+
+    pub fn set(&mut self, key: &str, value: &str) {
+        self.call(&["SET", key, value]);
+    }
+
+    /// Deletes `key`, returning whether it existed.
+    pub fn del(&mut self, key: &str) -> bool {
+        self.call(&["DEL", key]) == RespData::Integer(1)
+    }
+}
+
+impl CommandHandler {
+    /// Adds `command`, which clients can then run like any other. Fails if a built-in or an
+    /// already registered command has the same name.
+    pub fn register_command(&mut self, command: CustomCommand) -> Result<(), String> {
+        if command_table::lookup(&command.name).is_some()
+            || self.custom_commands.contains_key(&command.name)
+        {
+            return Err(format!("command '{}' already exists", command.name));
+        }
+        self.custom_commands.insert(command.name.clone(), command);
+        Ok(())
+    }
+
+    /// Runs a registered command, returning `None` if there is none called `name`.
+    pub(super) fn execute_custom(&mut self, name: &str, resp: &RespData) -> Option<RespData> {
+        let command = self.custom_commands.get(name)?.clone();
+        let RespData::Array(arr) = resp else {
+            return Some(errors::wrong_arity(name));
+        };
+        if !command.accepts(arr.len()) {
+            return Some(errors::wrong_arity(name));
+        }
+        let mut args = Vec::with_capacity(arr.len() - 1);
+        for arg in &arr[1..] {
+            let RespData::BulkString(arg) = arg else {
+                return Some(errors::syntax_error());
+            };
+            args.push(arg.clone());
+        }
+        // The command gets the replies of the commands it calls as values, not streamed.
+        self.reply_streaming = ReplyStreaming::Off;
+        Some((command.run)(&mut Keyspace { handler: self }, &args))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+
+    fn command(args: &[&str]) -> RespData {
+        RespData::array(args.iter().copied())
+    }
+
+    #[test]
+    fn test_custom_command() {
+        let mut handler = CommandHandler::from(HashMap::new());
+        // GETDEL key: returns the string at key and deletes it.
+        handler
+            .register_command(CustomCommand::new(
+                "GETDEL",
+                2,
+                |keyspace, args| match keyspace.get(&args[0]) {
+                    Ok(Some(value)) => {
+                        keyspace.del(&args[0]);
+                        RespData::BulkString(value)
+                    }
+                    Ok(None) => RespData::Null,
+                    Err(e) => e,
+                },
+            ))
+            .unwrap();
+        // SETPAIRS key value [key value ...]
+        handler
+            .register_command(CustomCommand::new("setpairs", -3, |keyspace, args| {
+                for pair in args.chunks(2) {
+                    if let [key, value] = pair {
+                        keyspace.set(key, value);
+                    }
+                }
+                RespData::SimpleString("OK".to_string())
+            }))
+            .unwrap();
+
+        let test_cases = [
+            (
+                "Too few arguments",
+                command(&["setpairs", "a"]),
+                errors::wrong_arity("setpairs"),
+            ),
+            (
+                "Variadic",
+                command(&["SETPAIRS", "a", "1", "b", "2"]),
+                RespData::SimpleString("OK".to_string()),
+            ),
+            (
+                "Written",
+                command(&["GET", "b"]),
+                RespData::BulkString("2".to_string()),
+            ),
+            (
+                "Exact arity",
+                command(&["getdel", "a", "b"]),
+                errors::wrong_arity("getdel"),
+            ),
+            (
+                "Read and deleted",
+                command(&["getdel", "a"]),
+                RespData::BulkString("1".to_string()),
+            ),
+            ("Gone", command(&["getdel", "a"]), RespData::Null),
+            (
+                "Not a string",
+                command(&["HSET", "h", "f", "v"]),
+                RespData::Integer(1),
+            ),
+            (
+                "Error passed on",
+                command(&["getdel", "h"]),
+                errors::wrong_type(),
+            ),
+        ];
+
+        for (name, input, expected) in test_cases {
+            assert_eq!(handler.handle(&input), expected, "{}", name);
+        }
+
+        assert_eq!(
+            handler.register_command(CustomCommand::new("Get", 2, |_, _| RespData::Null)),
+            Err("command 'get' already exists".to_string())
+        );
+        assert_eq!(
+            handler.register_command(CustomCommand::new("getdel", 2, |_, _| RespData::Null)),
+            Err("command 'getdel' already exists".to_string())
+        );
+    }
+}
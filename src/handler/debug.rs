@@ -0,0 +1,370 @@
+//! DEBUG, the grab bag of commands test suites use to poke at the server's internals. It is
+//! refused unless `enable-debug-command` allows it.
+
+use super::command_table::{self, PROTECTED};
+use super::digest;
+use super::errors;
+use super::evict::xorshift64_star;
+use super::CommandHandler;
+use crate::config::ProtectedAccess;
+use crate::glob::glob_match;
+use crate::rdb;
+use crate::resp::RespData;
+use std::fmt::Write;
+use std::thread;
+use std::time::{Duration, SystemTime};
+
+/// The LRU clock Redis stores per object wraps around at 24 bits.
+const LRU_CLOCK_MAX: u64 = (1 << 24) - 1;
+
+/// Rounds of random patterns and strings STRINGMATCH-LEN throws at the glob matcher.
+const STRINGMATCH_FUZZ_ROUNDS: usize = 100_000;
+const STRINGMATCH_FUZZ_MAX_LEN: u64 = 32;
+/// Pattern syntax is overrepresented so the fuzzer exercises more than literal matching.
+const STRINGMATCH_FUZZ_ALPHABET: &[u8] = b"*?[]^-\\ab";
+
+/// Whether a PROTECTED command may run with the given setting, for a client that is `local`.
+fn protected_allowed(access: ProtectedAccess, local: bool) -> bool {
+    match access {
+        ProtectedAccess::No => false,
+        ProtectedAccess::Yes => true,
+        ProtectedAccess::Local => local,
+    }
+}
+
+/// A fresh 40 character hex replication ID.
+pub(super) fn random_replid(random_state: &mut u64) -> String {
+    let mut replid = String::with_capacity(40);
+    while replid.len() < 40 {
+        write!(replid, "{:016x}", xorshift64_star(random_state)).unwrap();
+    }
+    replid.truncate(40);
+    replid
+}
+
+impl CommandHandler {
+    /// The error to refuse a PROTECTED command with, if the current client may not run it.
+    /// Commands that don't come from a connection count as local.
+    pub(super) fn protected_denied(&self, name: &str) -> Option<RespData> {
+        let spec = command_table::lookup(name)?;
+        if spec.flags & PROTECTED == 0 {
+            return None;
+        }
+        let access = self.config.read().unwrap().enable_debug_command;
+        let local = self
+            .current_client()
+            .is_none_or(|client| client.addr.ip().is_loopback());
+        if protected_allowed(access, local) {
+            return None;
+        }
+        Some(RespData::Error(format!(
+            "{} command not allowed. If the enable-debug-command option is set to \"local\", \
+             you can run it from a local connection, otherwise you need to set this option in \
+             the configuration file, and then restart the server.",
+            name.to_uppercase()
+        )))
+    }
+
+    pub(super) fn debug(&mut self, resp: &RespData) -> RespData {
+        let RespData::Array(arr) = resp else {
+            return errors::syntax_error();
+        };
+        let Some(RespData::BulkString(subcommand)) = arr.get(1) else {
+            return errors::wrong_arity("debug");
+        };
+        let args: Vec<&str> = arr[2..]
+            .iter()
+            .filter_map(|arg| match arg {
+                RespData::BulkString(arg) => Some(arg.as_str()),
+                _ => None,
+            })
+            .collect();
+
+        match (subcommand.to_uppercase().as_str(), args.as_slice()) {
+            ("SLEEP", [seconds]) => {
+                let Some(duration) = seconds
+                    .parse::<f64>()
+                    .ok()
+                    .and_then(|seconds| Duration::try_from_secs_f64(seconds).ok())
+                else {
+                    return RespData::Error("value is not a valid float".to_string());
+                };
+                // The handler lock is held throughout, so every other client waits too.
+                thread::sleep(duration);
+                RespData::SimpleString("OK".to_string())
+            }
+            ("OBJECT", [key]) => self.debug_object(key),
+            ("SET-ACTIVE-EXPIRE", [enabled]) => match *enabled {
+                "0" | "1" => {
+                    self.active_expire_enabled = *enabled == "1";
+                    RespData::SimpleString("OK".to_string())
+                }
+                _ => errors::not_an_integer(),
+            },
+            ("QUICKACK", [enabled]) => match *enabled {
+                "0" | "1" => self.debug_quickack(*enabled == "1"),
+                _ => errors::not_an_integer(),
+            },
+            ("STRINGMATCH-LEN", []) => {
+                self.stringmatch_fuzz();
+                RespData::SimpleString("Apparently Redis did not crash: test passed".to_string())
+            }
+            ("PANIC", []) => {
+                let now = SystemTime::now()
+                    .duration_since(SystemTime::UNIX_EPOCH)
+                    .unwrap_or_default();
+                panic!("DEBUG PANIC called at Unix time {}", now.as_secs());
+            }
+            ("CHANGE-REPL-ID", []) => {
+                self.replid = random_replid(&mut self.random_state);
+                RespData::SimpleString("OK".to_string())
+            }
+            ("JMAP", []) => RespData::BulkString(self.heap_map()),
+            ("HOTKEYS", args) => self.debug_hotkeys(args),
+            ("BIGKEYS", []) => RespData::BulkString(self.big_keys_report()),
+            ("DIGEST", []) => RespData::SimpleString(digest::to_hex(&self.dataset_digest())),
+            ("DIGEST-VALUE", keys) => RespData::Array(
+                keys.iter()
+                    .map(|key| RespData::SimpleString(digest::to_hex(&self.value_digest(key))))
+                    .collect(),
+            ),
+            (
+                "SLEEP" | "OBJECT" | "SET-ACTIVE-EXPIRE" | "QUICKACK" | "STRINGMATCH-LEN" | "PANIC"
+                | "CHANGE-REPL-ID" | "JMAP" | "DIGEST" | "BIGKEYS",
+                _,
+            ) => errors::wrong_arity(&format!("debug|{}", subcommand.to_lowercase())),
+            _ => errors::unknown_subcommand(subcommand, "debug"),
+        }
+    }
+
+    /// DEBUG OBJECT key: low level details of the value at `key`, in Redis's format.
+    fn debug_object(&mut self, key: &str) -> RespData {
+        self.expire_if_needed(key);
+        let Some(value) = self.db.get(key) else {
+            return RespData::Error("no such key".to_string());
+        };
+        let idle = self.object_idle_time(key);
+        let lru = SystemTime::now()
+            .checked_sub(idle)
+            .and_then(|accessed| accessed.duration_since(SystemTime::UNIX_EPOCH).ok())
+            .map_or(0, |since_epoch| since_epoch.as_secs() & LRU_CLOCK_MAX);
+        RespData::SimpleString(format!(
+            "Value at:{:p} refcount:1 encoding:{} serializedlength:{} lru:{lru} \
+             lru_seconds_idle:{}",
+            value,
+            super::object::encoding(value),
+            rdb::serialized_length(value),
+            idle.as_secs()
+        ))
+    }
+
+    /// DEBUG QUICKACK 0|1: toggles TCP_QUICKACK on the current connection.
+    fn debug_quickack(&self, enabled: bool) -> RespData {
+        #[cfg(target_os = "linux")]
+        {
+            if let Some(stream) = self.current_client().and_then(|c| c.stream.as_ref()) {
+                if let Err(e) = socket2::SockRef::from(stream).set_tcp_quickack(enabled) {
+                    return RespData::Error(format!("Failed to set TCP_QUICKACK: {e}"));
+                }
+            }
+            RespData::SimpleString("OK".to_string())
+        }
+        #[cfg(not(target_os = "linux"))]
+        {
+            let _ = enabled;
+            RespData::Error("TCP_QUICKACK is not supported on this platform".to_string())
+        }
+    }
+
+    /// Matches random patterns against random strings, which only fails by panicking or
+    /// hanging the server.
+    fn stringmatch_fuzz(&mut self) {
+        let random_text = |state: &mut u64| {
+            let len = xorshift64_star(state) % (STRINGMATCH_FUZZ_MAX_LEN + 1);
+            (0..len)
+                .map(|_| {
+                    let idx = xorshift64_star(state) as usize % STRINGMATCH_FUZZ_ALPHABET.len();
+                    STRINGMATCH_FUZZ_ALPHABET[idx] as char
+                })
+                .collect::<String>()
+        };
+        for _ in 0..STRINGMATCH_FUZZ_ROUNDS {
+            let pattern = random_text(&mut self.random_state);
+            let string = random_text(&mut self.random_state);
+            glob_match(&pattern, &string);
+        }
+    }
+
+    /// DEBUG JMAP: a summary of the heap, split into the dataset and what the allocator holds.
+    fn heap_map(&self) -> String {
+        let mut out = String::new();
+        writeln!(out, "allocator:{}", crate::allocator::name()).unwrap();
+        writeln!(out, "keys:{}", self.db.len()).unwrap();
+        writeln!(out, "keys_with_expiry:{}", self.db.expires_len()).unwrap();
+        writeln!(out, "dataset_bytes:{}", self.used_memory).unwrap();
+        writeln!(out, "dataset_peak_bytes:{}", self.used_memory_peak).unwrap();
+        writeln!(
+            out,
+            "lazyfree_pending_objects:{}",
+            self.lazyfree.pending_objects()
+        )
+        .unwrap();
+        if let Some(stats) = crate::allocator::stats() {
+            writeln!(out, "allocator_allocated:{}", stats.allocated).unwrap();
+            writeln!(out, "allocator_active:{}", stats.active).unwrap();
+            writeln!(out, "allocator_resident:{}", stats.resident).unwrap();
+        }
+        out
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::Config;
+    use std::collections::HashMap;
+    use std::sync::{Arc, RwLock};
+    use std::time::Instant;
+
+    fn command(args: &[&str]) -> RespData {
+        RespData::array(args.iter().copied())
+    }
+
+    fn debug_handler() -> CommandHandler {
+        let config = Config {
+            enable_debug_command: ProtectedAccess::Yes,
+            ..Config::default()
+        };
+        CommandHandler::new(HashMap::new(), Arc::new(RwLock::new(config)))
+    }
+
+    #[test]
+    fn test_protected_allowed() {
+        let test_cases = [
+            ("Disabled", ProtectedAccess::No, true, false),
+            ("Enabled for everyone", ProtectedAccess::Yes, false, true),
+            (
+                "Local only, local client",
+                ProtectedAccess::Local,
+                true,
+                true,
+            ),
+            (
+                "Local only, remote client",
+                ProtectedAccess::Local,
+                false,
+                false,
+            ),
+        ];
+
+        for (name, access, local, expected) in test_cases {
+            assert_eq!(protected_allowed(access, local), expected, "{}", name);
+        }
+    }
+
+    #[test]
+    fn test_debug() {
+        let mut handler = debug_handler();
+        handler.handle(&command(&["SET", "str", "hello"]));
+        handler.handle(&command(&["SET", "num", "12345"]));
+
+        let test_cases = [
+            (
+                "Sleep",
+                command(&["DEBUG", "SLEEP", "0"]),
+                RespData::SimpleString("OK".to_string()),
+            ),
+            (
+                "Sleep needs a number",
+                command(&["DEBUG", "SLEEP", "long"]),
+                RespData::Error("value is not a valid float".to_string()),
+            ),
+            (
+                "Object of a missing key",
+                command(&["DEBUG", "OBJECT", "missing"]),
+                RespData::Error("no such key".to_string()),
+            ),
+            (
+                "Digest of each key",
+                command(&["DEBUG", "DIGEST-VALUE", "missing"]),
+                RespData::Array(vec![RespData::SimpleString("0".repeat(40))]),
+            ),
+            (
+                "Stringmatch fuzzer",
+                command(&["DEBUG", "STRINGMATCH-LEN"]),
+                RespData::SimpleString("Apparently Redis did not crash: test passed".to_string()),
+            ),
+            (
+                "Wrong arity",
+                command(&["DEBUG", "CHANGE-REPL-ID", "now"]),
+                RespData::Error(
+                    "wrong number of arguments for 'debug|change-repl-id' command".to_string(),
+                ),
+            ),
+            (
+                "Unknown subcommand",
+                command(&["DEBUG", "SEGFAULT"]),
+                RespData::Error("unknown subcommand 'SEGFAULT'. Try DEBUG HELP.".to_string()),
+            ),
+        ];
+
+        for (name, input, expected) in test_cases {
+            assert_eq!(handler.handle(&input), expected, "{}", name);
+        }
+
+        let test_cases = [
+            (
+                "Embedded string",
+                "str",
+                "encoding:embstr serializedlength:6 ",
+            ),
+            ("Integer", "num", "encoding:int serializedlength:3 "),
+        ];
+        for (name, key, expected) in test_cases {
+            let RespData::SimpleString(details) =
+                handler.handle(&command(&["DEBUG", "OBJECT", key]))
+            else {
+                panic!("{}: DEBUG OBJECT did not reply with a status", name);
+            };
+            assert!(details.starts_with("Value at:0x"), "{}", name);
+            assert!(details.contains(expected), "{}: {}", name, details);
+        }
+
+        let replid = handler.replid.clone();
+        handler.handle(&command(&["DEBUG", "CHANGE-REPL-ID"]));
+        assert_ne!(handler.replid, replid);
+        assert_eq!(handler.replid.len(), 40);
+    }
+
+    #[test]
+    fn test_set_active_expire() {
+        let mut handler = debug_handler();
+        handler.handle(&command(&["SET", "key", "value"]));
+        handler
+            .db
+            .set_expire("key", Instant::now() - Duration::from_secs(1));
+
+        handler.handle(&command(&["DEBUG", "SET-ACTIVE-EXPIRE", "0"]));
+        handler.server_cron();
+        assert!(handler.db.contains_key("key"));
+
+        handler.handle(&command(&["DEBUG", "SET-ACTIVE-EXPIRE", "1"]));
+        handler.server_cron();
+        assert!(!handler.db.contains_key("key"));
+    }
+
+    #[test]
+    #[should_panic(expected = "DEBUG PANIC called at Unix time")]
+    fn test_debug_panic() {
+        debug_handler().handle(&command(&["DEBUG", "PANIC"]));
+    }
+
+    #[test]
+    fn test_disabled_by_default() {
+        let mut handler = CommandHandler::from(HashMap::new());
+        let RespData::Error(message) = handler.handle(&command(&["DEBUG", "SLEEP", "0"])) else {
+            panic!("DEBUG was not refused");
+        };
+        assert!(message.starts_with("DEBUG command not allowed."));
+    }
+}
@@ -0,0 +1,131 @@
+//! Order-independent SHA-1 digests of the dataset, computed exactly like Redis's DEBUG DIGEST
+//! so two servers, or a server before and after a restart, can be checked for holding the same
+//! data.
+
+use super::{CommandHandler, RedisValue};
+use crate::sha1::sha1;
+
+/// Object type codes Redis mixes into each value's digest.
+const OBJ_STRING: u32 = 0;
+const OBJ_HASH: u32 = 4;
+const OBJ_MODULE: u32 = 5;
+
+type Digest = [u8; 20];
+
+/// XORs the hash of `data` into `digest`, so the order things are added in doesn't matter.
+fn xor_digest(digest: &mut Digest, data: &[u8]) {
+    for (byte, hashed) in digest.iter_mut().zip(sha1(data)) {
+        *byte ^= hashed;
+    }
+}
+
+/// Adds `data` to `digest` in a way that depends on the order things are added in.
+fn mix_digest(digest: &mut Digest, data: &[u8]) {
+    xor_digest(digest, data);
+    *digest = sha1(digest);
+}
+
+pub(super) fn to_hex(digest: &Digest) -> String {
+    digest.iter().map(|byte| format!("{byte:02x}")).collect()
+}
+
+impl CommandHandler {
+    /// The digest of every key, its value and whether it has a TTL. All zeroes when empty.
+    pub(super) fn dataset_digest(&self) -> Digest {
+        let mut digest = [0; 20];
+        if self.db.is_empty() {
+            return digest;
+        }
+        // Only database 0 exists.
+        mix_digest(&mut digest, &0u32.to_be_bytes());
+        self.db.for_each(&mut |key, value| {
+            let mut key_digest = [0; 20];
+            mix_digest(&mut key_digest, key.as_bytes());
+            self.xor_value_digest(&mut key_digest, key, value);
+            xor_digest(&mut digest, &key_digest);
+        });
+        digest
+    }
+
+    /// The digest of the value at `key`, all zeroes if there is none.
+    pub(super) fn value_digest(&mut self, key: &str) -> Digest {
+        let mut digest = [0; 20];
+        self.expire_if_needed(key);
+        if let Some(value) = self.db.get(key) {
+            self.xor_value_digest(&mut digest, key, value);
+        }
+        digest
+    }
+
+    fn xor_value_digest(&self, digest: &mut Digest, key: &str, value: &RedisValue) {
+        match value {
+            RedisValue::String(s) => {
+                mix_digest(digest, &OBJ_STRING.to_be_bytes());
+                mix_digest(digest, s.as_str().as_bytes());
+            }
+            RedisValue::Hash(map) => {
+                mix_digest(digest, &OBJ_HASH.to_be_bytes());
+                for (field, value) in map {
+                    let mut element = [0; 20];
+                    mix_digest(&mut element, field.as_bytes());
+                    mix_digest(&mut element, value.as_bytes());
+                    xor_digest(digest, &element);
+                }
+            }
+            RedisValue::Json(doc) => {
+                mix_digest(digest, &OBJ_MODULE.to_be_bytes());
+                mix_digest(digest, doc.to_string().as_bytes());
+            }
+            RedisValue::Bloom(filter) => {
+                mix_digest(digest, &OBJ_MODULE.to_be_bytes());
+                mix_digest(digest, &filter.to_bytes());
+            }
+            RedisValue::CountMin(sketch) => {
+                mix_digest(digest, &OBJ_MODULE.to_be_bytes());
+                mix_digest(digest, &sketch.to_bytes());
+            }
+            RedisValue::TopK(topk) => {
+                mix_digest(digest, &OBJ_MODULE.to_be_bytes());
+                mix_digest(digest, &topk.to_bytes());
+            }
+            RedisValue::TimeSeries(series) => {
+                mix_digest(digest, &OBJ_MODULE.to_be_bytes());
+                mix_digest(digest, &series.to_bytes());
+            }
+        }
+        if self.db.expire_at(key).is_some() {
+            xor_digest(digest, b"!!expire!!");
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::resp::RespData;
+    use std::collections::HashMap;
+
+    fn command(args: &[&str]) -> RespData {
+        RespData::array(args.iter().copied())
+    }
+
+    #[test]
+    fn test_dataset_digest() {
+        let mut first = CommandHandler::from(HashMap::new());
+        let mut second = CommandHandler::from(HashMap::new());
+        assert_eq!(first.dataset_digest(), [0; 20]);
+
+        first.handle(&command(&["SET", "a", "1"]));
+        first.handle(&command(&["HSET", "h", "f1", "v1", "f2", "v2"]));
+        second.handle(&command(&["HSET", "h", "f2", "v2", "f1", "v1"]));
+        second.handle(&command(&["SET", "a", "1"]));
+        assert_ne!(first.dataset_digest(), [0; 20]);
+        assert_eq!(first.dataset_digest(), second.dataset_digest());
+
+        second.handle(&command(&["EXPIRE", "a", "100"]));
+        assert_ne!(first.dataset_digest(), second.dataset_digest());
+        assert_eq!(first.value_digest("h"), second.value_digest("h"));
+        assert_ne!(first.value_digest("a"), second.value_digest("a"));
+        assert_eq!(first.value_digest("missing"), [0; 20]);
+    }
+}
@@ -0,0 +1,215 @@
+//! The error replies shared by many commands. They are worded exactly like Redis's, down to
+//! the byte, because client libraries pattern-match on them.
+
+use crate::resp::RespData;
+
+/// How much of each argument the unknown command error echoes back.
+const ECHOED_ARGS_LEN: usize = 128;
+
+pub(super) fn syntax_error() -> RespData {
+    RespData::Error("syntax error".to_string())
+}
+
+pub(super) fn not_an_integer() -> RespData {
+    RespData::Error("value is not an integer or out of range".to_string())
+}
+
+pub(super) fn wrong_type() -> RespData {
+    RespData::Error(
+        "-WRONGTYPE Operation against a key holding the wrong kind of value".to_string(),
+    )
+}
+
+/// The reply to commands other than AUTH and HELLO before a client has authenticated.
+pub(super) fn no_auth() -> RespData {
+    RespData::Error("-NOAUTH Authentication required.".to_string())
+}
+
+/// The reply to HELLO without AUTH before a client has authenticated.
+pub(super) fn hello_no_auth() -> RespData {
+    RespData::Error(
+        "-NOAUTH HELLO must be called with the client already authenticated, otherwise the \
+         HELLO <proto> AUTH <user> <pass> option can be used to authenticate the client and \
+         select the RESP protocol version at the same time"
+            .to_string(),
+    )
+}
+
+/// The same for an unknown user as for a wrong password, so users can't be probed for.
+pub(super) fn wrong_pass() -> RespData {
+    RespData::Error("-WRONGPASS invalid username-password pair or user is disabled.".to_string())
+}
+
+/// The reply to commands that may grow memory once `maxmemory` is reached.
+pub(super) fn oom() -> RespData {
+    RespData::Error("-OOM command not allowed when used memory > 'maxmemory'.".to_string())
+}
+
+/// `command` is the lowercase name of the command given the TTL.
+pub(super) fn invalid_expire_time(command: &str) -> RespData {
+    RespData::Error(format!("invalid expire time in '{command}' command"))
+}
+
+/// The reply to the cluster commands, as there is no cluster mode.
+pub(super) fn cluster_disabled() -> RespData {
+    RespData::Error("This instance has cluster support disabled".to_string())
+}
+
+/// `command` is the lowercase name, with the subcommand after a `|` for container commands.
+pub(super) fn wrong_arity(command: &str) -> RespData {
+    RespData::Error(format!("wrong number of arguments for '{command}' command"))
+}
+
+/// Whether `reply` was built by `wrong_arity`, so the call can be counted as rejected.
+pub(super) fn is_wrong_arity(reply: &RespData) -> bool {
+    matches!(reply, RespData::Error(e) if e.starts_with("wrong number of arguments for '"))
+}
+
+/// Echoes the command name and the start of its arguments back, like Redis does.
+pub(super) fn unknown_command(name: &str, args: &[RespData]) -> RespData {
+    let mut echoed = String::new();
+    for arg in args {
+        if echoed.len() >= ECHOED_ARGS_LEN {
+            break;
+        }
+        let arg = match arg {
+            RespData::BulkString(s) | RespData::SimpleString(s) => s.clone(),
+            RespData::Integer(n) => n.to_string(),
+            _ => String::new(),
+        };
+        let room = ECHOED_ARGS_LEN - echoed.len();
+        echoed.push_str(&format!("'{}' ", truncate(&arg, room)));
+    }
+    RespData::Error(format!(
+        "unknown command '{}', with args beginning with: {echoed}",
+        truncate(name, ECHOED_ARGS_LEN)
+    ))
+}
+
+/// `command` is the container command, such as OBJECT.
+pub(super) fn unknown_subcommand(subcommand: &str, command: &str) -> RespData {
+    RespData::Error(format!(
+        "unknown subcommand '{}'. Try {} HELP.",
+        truncate(subcommand, ECHOED_ARGS_LEN),
+        command.to_uppercase()
+    ))
+}
+
+/// The longest prefix of `s` no longer than `max` bytes that doesn't split a character.
+fn truncate(s: &str, max: usize) -> &str {
+    if s.len() <= max {
+        return s;
+    }
+    let mut end = max;
+    while !s.is_char_boundary(end) {
+        end -= 1;
+    }
+    &s[..end]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::util::assert_format_repr;
+
+    #[test]
+    fn test_errors_on_the_wire() {
+        let bulk = |s: &str| RespData::BulkString(s.to_string());
+        let long = "x".repeat(200);
+
+        let test_cases = [
+            (
+                "Syntax error",
+                syntax_error(),
+                "-ERR syntax error\r\n".to_string(),
+            ),
+            (
+                "Not an integer",
+                not_an_integer(),
+                "-ERR value is not an integer or out of range\r\n".to_string(),
+            ),
+            (
+                "Wrong type",
+                wrong_type(),
+                "-WRONGTYPE Operation against a key holding the wrong kind of value\r\n"
+                    .to_string(),
+            ),
+            (
+                "Wrong arity",
+                wrong_arity("get"),
+                "-ERR wrong number of arguments for 'get' command\r\n".to_string(),
+            ),
+            (
+                "Wrong arity of a subcommand",
+                wrong_arity("object|freq"),
+                "-ERR wrong number of arguments for 'object|freq' command\r\n".to_string(),
+            ),
+            (
+                "No auth",
+                no_auth(),
+                "-NOAUTH Authentication required.\r\n".to_string(),
+            ),
+            (
+                "HELLO without auth",
+                hello_no_auth(),
+                "-NOAUTH HELLO must be called with the client already authenticated, \
+                 otherwise the HELLO <proto> AUTH <user> <pass> option can be used to \
+                 authenticate the client and select the RESP protocol version at the same \
+                 time\r\n"
+                    .to_string(),
+            ),
+            (
+                "Wrong password",
+                wrong_pass(),
+                "-WRONGPASS invalid username-password pair or user is disabled.\r\n".to_string(),
+            ),
+            (
+                "Out of memory",
+                oom(),
+                "-OOM command not allowed when used memory > 'maxmemory'.\r\n".to_string(),
+            ),
+            (
+                "Invalid expire time",
+                invalid_expire_time("set"),
+                "-ERR invalid expire time in 'set' command\r\n".to_string(),
+            ),
+            (
+                "Unknown command",
+                unknown_command("foo", &[bulk("bar"), bulk("baz")]),
+                "-ERR unknown command 'foo', with args beginning with: 'bar' 'baz' \r\n"
+                    .to_string(),
+            ),
+            (
+                "Unknown command without args",
+                unknown_command("foo", &[]),
+                "-ERR unknown command 'foo', with args beginning with: \r\n".to_string(),
+            ),
+            (
+                "Unknown command with long args",
+                unknown_command("foo", &[bulk(&long), bulk("more")]),
+                format!(
+                    "-ERR unknown command 'foo', with args beginning with: '{}' \r\n",
+                    &long[..128]
+                ),
+            ),
+            (
+                "Unknown command with CR and LF",
+                unknown_command("foo\r\n+OK\r", &[bulk("a\r\nb")]),
+                "-ERR unknown command 'foo  +OK ', with args beginning with: 'a  b' \r\n"
+                    .to_string(),
+            ),
+            (
+                "Unknown subcommand",
+                unknown_subcommand("NOPE", "object"),
+                "-ERR unknown subcommand 'NOPE'. Try OBJECT HELP.\r\n".to_string(),
+            ),
+        ];
+
+        for (name, error, expected) in test_cases {
+            let mut buffer = Vec::new();
+            error.write(&mut buffer).unwrap();
+            assert_eq!(String::from_utf8(buffer).unwrap(), expected, "{}", name);
+        }
+        assert_format_repr(&syntax_error(), b"-ERR syntax error\r\n");
+    }
+}
@@ -0,0 +1,141 @@
+//! Keyspace event callbacks for code embedding the server: a hook registered with
+//! `on_key_event` is called whenever a key is written, deleted, expires or is evicted. Unlike
+//! Redis keyspace notifications this doesn't go through Pub/Sub, and is always enabled.
+
+use super::CommandHandler;
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum KeyEventKind {
+    /// The key was created or its value modified.
+    Set,
+    /// The key was deleted by a command, like DEL or EXPIRE with a TTL in the past.
+    Deleted,
+    /// The key was removed because its TTL ran out.
+    Expired,
+    /// The key was removed to bring memory usage under `maxmemory`.
+    Evicted,
+}
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub struct KeyEvent<'a> {
+    pub kind: KeyEventKind,
+    pub key: &'a str,
+}
+
+pub type KeyEventCallback = Box<dyn Fn(&KeyEvent) + Send>;
+
+impl CommandHandler {
+    /// Registers `callback` to be called on every key event. It runs while the handler is in
+    /// use, so it must not call back into it. Flushing the whole dataset doesn't raise an
+    /// event per key.
+    pub fn on_key_event(&mut self, callback: impl Fn(&KeyEvent) + Send + 'static) {
+        self.key_event_callbacks.push(Box::new(callback));
+    }
+
+    pub(super) fn notify_key_event(&mut self, kind: KeyEventKind, key: &str) {
+        self.reindex_key(key);
+        let event = KeyEvent { kind, key };
+        for callback in &self.key_event_callbacks {
+            callback(&event);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::clock::MockClock;
+    use super::*;
+    use crate::resp::RespData;
+    use std::collections::HashMap;
+    use std::sync::{Arc, Mutex};
+    use std::time::Duration;
+
+    fn command(args: &[&str]) -> RespData {
+        RespData::array(args.iter().copied())
+    }
+
+    #[test]
+    fn test_on_key_event() {
+        let mut handler = CommandHandler::from(HashMap::new());
+        let clock = Arc::new(MockClock::new());
+        handler.clock = clock.clone();
+        let events = Arc::new(Mutex::new(Vec::new()));
+        let seen = Arc::clone(&events);
+        handler.on_key_event(move |event| {
+            seen.lock()
+                .unwrap()
+                .push((event.kind, event.key.to_string()));
+        });
+
+        let test_cases = [
+            (
+                "Set",
+                command(&["SET", "a", "1"]),
+                vec![(KeyEventKind::Set, "a")],
+            ),
+            (
+                "Modified",
+                command(&["APPEND", "a", "2"]),
+                vec![(KeyEventKind::Set, "a")],
+            ),
+            (
+                "Hash field",
+                command(&["HSET", "h", "f", "v"]),
+                vec![(KeyEventKind::Set, "h")],
+            ),
+            ("Failed write", command(&["INCR", "h"]), vec![]),
+            ("Read", command(&["GET", "a"]), vec![]),
+            (
+                "Deleted",
+                command(&["DEL", "a", "missing"]),
+                vec![(KeyEventKind::Deleted, "a")],
+            ),
+            (
+                "TTL in the past",
+                command(&["EXPIRE", "h", "0"]),
+                vec![(KeyEventKind::Deleted, "h")],
+            ),
+            (
+                "Given a TTL",
+                command(&["SET", "b", "1", "PX", "10"]),
+                vec![(KeyEventKind::Set, "b")],
+            ),
+        ];
+
+        for (name, input, expected) in test_cases {
+            events.lock().unwrap().clear();
+            handler.handle(&input);
+            let expected: Vec<_> = expected
+                .into_iter()
+                .map(|(kind, key)| (kind, key.to_string()))
+                .collect();
+            assert_eq!(*events.lock().unwrap(), expected, "{}", name);
+        }
+
+        events.lock().unwrap().clear();
+        clock.advance(Duration::from_millis(20));
+        handler.handle(&command(&["GET", "b"]));
+        assert_eq!(
+            *events.lock().unwrap(),
+            [(KeyEventKind::Expired, "b".to_string())]
+        );
+
+        events.lock().unwrap().clear();
+        handler.handle(&command(&["SET", "c", "1"]));
+        handler.handle(&command(&[
+            "CONFIG",
+            "SET",
+            "maxmemory-policy",
+            "allkeys-random",
+        ]));
+        handler.handle(&command(&["CONFIG", "SET", "maxmemory", "1"]));
+        handler.server_cron();
+        assert_eq!(
+            *events.lock().unwrap(),
+            [
+                (KeyEventKind::Set, "c".to_string()),
+                (KeyEventKind::Evicted, "c".to_string())
+            ]
+        );
+    }
+}
@@ -0,0 +1,432 @@
+//! Key eviction once the dataset grows past `maxmemory`.
+
+use super::events::KeyEventKind;
+use super::lfu::LFU_INIT_VAL;
+use super::CommandHandler;
+use crate::config::MaxmemoryPolicy;
+use std::time::Duration;
+
+impl CommandHandler {
+    /// Evicts keys according to `maxmemory-policy` until the dataset fits in `maxmemory`.
+    /// Returns false if it is still over the limit because there was nothing left to evict.
+    pub(super) fn perform_evictions(&mut self) -> bool {
+        let (maxmemory, policy, samples, lazy) = {
+            let config = self.config.read().unwrap();
+            (
+                config.maxmemory,
+                config.maxmemory_policy,
+                config.maxmemory_samples,
+                config.lazyfree_lazy_eviction,
+            )
+        };
+        if maxmemory == 0 {
+            return true;
+        }
+        while self.used_memory > maxmemory {
+            let Some(key) = self.eviction_candidate(policy, samples) else {
+                return false;
+            };
+            let value = self.delete_key(&key);
+            self.stats.evicted_keys += 1;
+            self.notify_key_event(KeyEventKind::Evicted, &key);
+            if let (Some(value), true) = (value, lazy) {
+                self.lazyfree.free_value(value);
+            }
+        }
+        true
+    }
+
+    /// Picks the best key to evict under `policy`: the idlest for LRU, the least used for LFU
+    /// and the soonest to expire for volatile-ttl. Each call samples `samples` random keys into
+    /// the eviction pool, which remembers the best candidates seen across calls, so this
+    /// approximates those orderings closely without keeping the keys sorted.
+    fn eviction_candidate(&mut self, policy: MaxmemoryPolicy, samples: usize) -> Option<String> {
+        match policy {
+            MaxmemoryPolicy::NoEviction => None,
+            MaxmemoryPolicy::AllkeysRandom | MaxmemoryPolicy::VolatileRandom => {
+                self.sample_keys(policy, 1).pop()
+            }
+            _ => {
+                if self.eviction_pool.policy != Some(policy) {
+                    // Scores under different policies don't compare.
+                    self.eviction_pool = EvictionPool {
+                        policy: Some(policy),
+                        ..EvictionPool::default()
+                    };
+                }
+                for key in self.sample_keys(policy, samples) {
+                    let score = self.eviction_score(&key, policy);
+                    self.eviction_pool.insert(key, score);
+                }
+                // Pooled keys may have been deleted, or lost their TTL, since they were sampled.
+                while let Some(key) = self.eviction_pool.pop_best() {
+                    let exists = if policy.is_volatile() {
+                        self.db.expire_at(&key).is_some()
+                    } else {
+                        self.db.contains_key(&key)
+                    };
+                    if exists {
+                        return Some(key);
+                    }
+                }
+                None
+            }
+        }
+    }
+
+    /// Up to `samples` random keys that `policy` may evict.
+    fn sample_keys(&mut self, policy: MaxmemoryPolicy, samples: usize) -> Vec<String> {
+        let volatile = policy.is_volatile();
+        let mut random = || xorshift64_star(&mut self.random_state);
+        (0..samples)
+            .filter_map(|_| {
+                if volatile {
+                    self.db.random_expiring(&mut random).map(|(key, _)| key)
+                } else {
+                    self.db.random_key(&mut random)
+                }
+            })
+            .cloned()
+            .collect()
+    }
+
+    /// How good a candidate `key` is for eviction under `policy`; higher goes first.
+    fn eviction_score(&self, key: &str, policy: MaxmemoryPolicy) -> u128 {
+        match policy {
+            MaxmemoryPolicy::AllkeysLfu | MaxmemoryPolicy::VolatileLfu => {
+                let decay_time = self.config.read().unwrap().lfu_decay_time;
+                (u8::MAX - self.key_frequency(key, decay_time)) as u128
+            }
+            MaxmemoryPolicy::VolatileTtl => {
+                let remaining = self.db.expire_at(key).map_or(Duration::ZERO, |deadline| {
+                    deadline.saturating_duration_since(self.clock.now())
+                });
+                u128::MAX - remaining.as_millis()
+            }
+            _ => self.idle_time(key).as_millis(),
+        }
+    }
+
+    /// How long `key` has gone without being accessed. Keys that were never touched, such as
+    /// ones loaded from disk, count as idle forever.
+    fn idle_time(&self, key: &str) -> Duration {
+        self.access_times.get(key).map_or(Duration::MAX, |time| {
+            self.clock.now().saturating_duration_since(*time)
+        })
+    }
+
+    /// The current LFU counter of `key`. Keys that were never touched count as new.
+    pub(super) fn key_frequency(&self, key: &str, decay_time: u64) -> u8 {
+        self.lfu_counters
+            .get(key)
+            .map_or(LFU_INIT_VAL, |counter| counter.value(decay_time))
+    }
+
+    pub(super) fn next_random(&mut self) -> u64 {
+        xorshift64_star(&mut self.random_state)
+    }
+
+    /// A random number uniformly distributed in [0, 1).
+    pub(super) fn random_fraction(&mut self) -> f64 {
+        (self.next_random() >> 11) as f64 / (1u64 << 53) as f64
+    }
+}
+
+/// How many eviction candidates are remembered between evictions.
+const EVICTION_POOL_SIZE: usize = 16;
+
+/// The best eviction candidates sampled so far, like Redis's `EvictionPoolLRU`.
+#[derive(Default)]
+pub(super) struct EvictionPool {
+    /// The policy the scores were computed under.
+    policy: Option<MaxmemoryPolicy>,
+    /// Candidates sorted by ascending score, so the best one is last.
+    entries: Vec<(u128, String)>,
+}
+
+impl EvictionPool {
+    /// Adds a candidate, unless it is already pooled or the pool is full of better ones.
+    fn insert(&mut self, key: String, score: u128) {
+        if self.entries.iter().any(|(_, pooled)| *pooled == key) {
+            return;
+        }
+        let mut position = self.entries.partition_point(|(pooled, _)| *pooled < score);
+        if self.entries.len() == EVICTION_POOL_SIZE {
+            if position == 0 {
+                return;
+            }
+            self.entries.remove(0);
+            position -= 1;
+        }
+        self.entries.insert(position, (score, key));
+    }
+
+    fn pop_best(&mut self) -> Option<String> {
+        self.entries.pop().map(|(_, key)| key)
+    }
+}
+
+/// xorshift64*, which is plenty for the sampling and other random choices the server makes.
+pub(super) fn xorshift64_star(state: &mut u64) -> u64 {
+    let mut x = *state;
+    x ^= x >> 12;
+    x ^= x << 25;
+    x ^= x >> 27;
+    *state = x;
+    x.wrapping_mul(0x2545_f491_4f6c_dd1d)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::Config;
+    use crate::handler::errors;
+    use crate::resp::RespData;
+    use std::collections::HashMap;
+    use std::sync::{Arc, RwLock};
+
+    fn command(args: &[&str]) -> RespData {
+        RespData::array(args.iter().copied())
+    }
+
+    /// A handler holding `keys`, each idle for as many seconds as its position in the list.
+    fn handler_with_keys(policy: MaxmemoryPolicy, keys: &[(&str, bool)]) -> CommandHandler {
+        let config = Config {
+            maxmemory_policy: policy,
+            // Enough samples that the idlest key is practically always among them.
+            maxmemory_samples: 64,
+            ..Config::default()
+        };
+        let mut handler = CommandHandler::new(HashMap::new(), Arc::new(RwLock::new(config)));
+        for (age, (key, volatile)) in keys.iter().enumerate() {
+            if *volatile {
+                handler.handle(&command(&["SET", key, "v", "EX", "100"]));
+            } else {
+                handler.handle(&command(&["SET", key, "v"]));
+            }
+            let accessed = handler.clock.now() - Duration::from_secs(age as u64);
+            handler.access_times.insert(key.to_string(), accessed);
+        }
+        handler
+    }
+
+    fn set_maxmemory(handler: &CommandHandler, keys: u64) {
+        let per_key = handler.key_memory("a");
+        handler.config.write().unwrap().maxmemory = per_key * keys;
+    }
+
+    fn remaining_keys(handler: &CommandHandler) -> Vec<String> {
+        let mut keys = Vec::new();
+        handler.db.for_each(&mut |key, _| keys.push(key.clone()));
+        keys.sort_unstable();
+        keys
+    }
+
+    #[test]
+    fn test_allkeys_lru() {
+        let keys = [("a", false), ("b", true), ("c", false), ("d", false)];
+        let mut handler = handler_with_keys(MaxmemoryPolicy::AllkeysLru, &keys);
+        set_maxmemory(&handler, 2);
+
+        assert_eq!(
+            handler.handle(&command(&["PING"])),
+            RespData::SimpleString("PONG".to_string())
+        );
+        assert_eq!(remaining_keys(&handler), vec!["a", "b"]);
+        assert_eq!(handler.stats.evicted_keys, 2);
+    }
+
+    #[test]
+    fn test_volatile_lru() {
+        let keys = [("a", true), ("b", false), ("c", true), ("d", true)];
+        let mut handler = handler_with_keys(MaxmemoryPolicy::VolatileLru, &keys);
+
+        set_maxmemory(&handler, 3);
+        assert!(handler.perform_evictions());
+        assert_eq!(remaining_keys(&handler), vec!["a", "b", "c"]);
+
+        // Only keys with a TTL may go, so the limit can't be met once they are gone.
+        handler.config.write().unwrap().maxmemory = 1;
+        assert!(!handler.perform_evictions());
+        assert_eq!(remaining_keys(&handler), vec!["b"]);
+        assert_eq!(handler.stats.evicted_keys, 3);
+    }
+
+    #[test]
+    fn test_allkeys_lfu() {
+        let keys = [("a", false), ("b", false), ("c", false), ("d", false)];
+        let mut handler = handler_with_keys(MaxmemoryPolicy::AllkeysLfu, &keys);
+        // The most recently used key is the least frequently used one.
+        for _ in 0..200 {
+            handler.handle(&command(&["GET", "b"]));
+            handler.handle(&command(&["GET", "c"]));
+            handler.handle(&command(&["GET", "d"]));
+        }
+        handler.handle(&command(&["GET", "a"]));
+        set_maxmemory(&handler, 3);
+
+        assert!(handler.perform_evictions());
+        assert_eq!(remaining_keys(&handler), vec!["b", "c", "d"]);
+    }
+
+    #[test]
+    fn test_volatile_ttl() {
+        let keys = [("a", false), ("b", true), ("c", true), ("d", true)];
+        let mut handler = handler_with_keys(MaxmemoryPolicy::VolatileTtl, &keys);
+        handler.handle(&command(&["EXPIRE", "b", "10"]));
+        handler.handle(&command(&["EXPIRE", "d", "1000"]));
+        set_maxmemory(&handler, 3);
+
+        assert!(handler.perform_evictions());
+        assert_eq!(remaining_keys(&handler), vec!["a", "c", "d"]);
+    }
+
+    #[test]
+    fn test_random() {
+        let test_cases = [
+            ("allkeys-random", MaxmemoryPolicy::AllkeysRandom, true, 0),
+            ("volatile-random", MaxmemoryPolicy::VolatileRandom, false, 2),
+        ];
+
+        for (name, policy, fits, expected_left) in test_cases {
+            let keys = [("a", false), ("b", true), ("c", false), ("d", true)];
+            let mut handler = handler_with_keys(policy, &keys);
+            handler.config.write().unwrap().maxmemory = 1;
+
+            assert_eq!(handler.perform_evictions(), fits, "{}", name);
+            assert_eq!(handler.db.len(), expected_left, "{}", name);
+            assert!(handler.db.expires_len() == 0, "{}", name);
+        }
+    }
+
+    #[test]
+    fn test_seeded_random_eviction() {
+        let survivors = |seed| {
+            let config = Config {
+                maxmemory_policy: MaxmemoryPolicy::AllkeysRandom,
+                seed: Some(seed),
+                ..Config::default()
+            };
+            let mut handler = CommandHandler::new(HashMap::new(), Arc::new(RwLock::new(config)));
+            for i in 0..100 {
+                handler.handle(&command(&["SET", &format!("key:{i}"), "v"]));
+            }
+            let half = handler.used_memory / 2;
+            handler.config.write().unwrap().maxmemory = half;
+            assert!(handler.perform_evictions());
+            remaining_keys(&handler)
+        };
+
+        assert!((1..100).contains(&survivors(7).len()));
+        assert_eq!(survivors(7), survivors(7));
+        assert_ne!(survivors(7), survivors(8));
+    }
+
+    #[test]
+    fn test_noeviction() {
+        let keys = [("a", false), ("b", true)];
+        let mut handler = handler_with_keys(MaxmemoryPolicy::NoEviction, &keys);
+        handler.config.write().unwrap().maxmemory = 1;
+
+        assert!(!handler.perform_evictions());
+        assert_eq!(remaining_keys(&handler), vec!["a", "b"]);
+        assert_eq!(handler.stats.evicted_keys, 0);
+    }
+
+    #[test]
+    fn test_denyoom() {
+        let keys = [("a", false), ("b", false)];
+        let mut handler = handler_with_keys(MaxmemoryPolicy::NoEviction, &keys);
+        set_maxmemory(&handler, 1);
+
+        let test_cases = [
+            ("Write refused", command(&["SET", "c", "v"]), errors::oom()),
+            (
+                "Hash write refused",
+                command(&["HSET", "h", "f", "v"]),
+                errors::oom(),
+            ),
+            (
+                "Read still works",
+                command(&["GET", "a"]),
+                RespData::BulkString("v".to_string()),
+            ),
+            (
+                "Writes that don't grow memory still work",
+                command(&["EXPIRE", "a", "100"]),
+                RespData::Integer(1),
+            ),
+            (
+                "Switch to a volatile policy",
+                command(&["CONFIG", "SET", "maxmemory-policy", "volatile-lru"]),
+                RespData::SimpleString("OK".to_string()),
+            ),
+            (
+                "Eviction frees enough",
+                command(&["SET", "c", "v"]),
+                RespData::SimpleString("OK".to_string()),
+            ),
+            (
+                "Eviction can't free enough",
+                command(&["SET", "d", "v"]),
+                errors::oom(),
+            ),
+        ];
+
+        for (name, input, expected) in test_cases {
+            assert_eq!(handler.handle(&input), expected, "{}", name);
+        }
+        assert_eq!(remaining_keys(&handler), vec!["b", "c"]);
+        assert_eq!(handler.stats.errors["OOM"], 3);
+    }
+
+    #[test]
+    fn test_no_maxmemory() {
+        let keys = [("a", false), ("b", false)];
+        let mut handler = handler_with_keys(MaxmemoryPolicy::AllkeysLru, &keys);
+
+        assert!(handler.perform_evictions());
+        assert_eq!(remaining_keys(&handler), vec!["a", "b"]);
+    }
+
+    #[test]
+    fn test_eviction_pool() {
+        let mut pool = EvictionPool::default();
+        for score in [5, 1, 9, 3] {
+            pool.insert(format!("k{score}"), score);
+        }
+        pool.insert("k9".to_string(), 100);
+        assert_eq!(pool.entries.len(), 4);
+
+        // Once full, only candidates better than the worst one get in.
+        for score in 10..(10 + EVICTION_POOL_SIZE as u128) {
+            pool.insert(format!("k{score}"), score);
+        }
+        assert_eq!(pool.entries.len(), EVICTION_POOL_SIZE);
+        pool.insert("k0".to_string(), 0);
+        assert!(!pool.entries.iter().any(|(_, key)| key == "k0"));
+
+        let best: Vec<String> = (0..3).filter_map(|_| pool.pop_best()).collect();
+        assert_eq!(best, vec!["k25", "k24", "k23"]);
+    }
+
+    #[test]
+    fn test_pool_across_evictions() {
+        let keys: Vec<(String, bool)> = (0..50).map(|i| (format!("k{i:02}"), false)).collect();
+        let keys: Vec<(&str, bool)> = keys.iter().map(|(key, v)| (key.as_str(), *v)).collect();
+        let mut handler = handler_with_keys(MaxmemoryPolicy::AllkeysLru, &keys);
+        // A pooled key deleted since it was sampled is skipped over.
+        handler.eviction_pool.policy = Some(MaxmemoryPolicy::AllkeysLru);
+        handler.eviction_pool.insert("gone".to_string(), u128::MAX);
+        handler.config.write().unwrap().maxmemory = handler.key_memory("k00") * 45;
+
+        assert!(handler.perform_evictions());
+        assert_eq!(handler.db.len(), 45);
+        assert_eq!(handler.stats.evicted_keys, 5);
+        assert!(!handler
+            .eviction_pool
+            .entries
+            .iter()
+            .any(|(_, key)| key == "gone"));
+    }
+}
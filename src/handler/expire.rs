@@ -0,0 +1,300 @@
+use super::events::KeyEventKind;
+use super::persistence::unix_time_ms;
+use super::{errors, CommandHandler};
+use crate::resp::RespData;
+use std::time::{Duration, Instant};
+
+impl CommandHandler {
+    /// Deletes `key` if its TTL has passed, returning whether it was expired. Commands call this
+    /// before touching a key, so it is also where the storage is asked to load it.
+    pub(super) fn expire_if_needed(&mut self, key: &str) -> bool {
+        self.db.load(key);
+        match self.db.expire_at(key) {
+            Some(deadline) if deadline <= self.clock.now() => {
+                self.delete_key(key);
+                self.stats.expired_keys += 1;
+                self.notify_key_event(KeyEventKind::Expired, key);
+                true
+            }
+            _ => false,
+        }
+    }
+
+    /// Remaining time to live of `key`: `None` if the key does not exist, `Some(None)` if it
+    /// exists without a TTL.
+    fn remaining_ttl(&mut self, key: &str) -> Option<Option<Duration>> {
+        self.expire_if_needed(key);
+        if !self.db.contains_key(key) {
+            return None;
+        }
+        let now = self.clock.now();
+        Some(
+            self.db
+                .expire_at(key)
+                .map(|deadline| deadline.saturating_duration_since(now)),
+        )
+    }
+
+    /// When a key given `ttl` now expires, or `None` if that is too far off. Like Redis, the
+    /// deadline has to fit in signed milliseconds since the epoch.
+    pub(super) fn deadline_after(&self, ttl: Duration) -> Option<Instant> {
+        let ttl_ms = i64::try_from(ttl.as_millis()).ok()?;
+        (unix_time_ms() as i64).checked_add(ttl_ms)?;
+        self.clock.now().checked_add(ttl)
+    }
+
+    /// EXPIRE and PEXPIRE, named `command`, with `amount` in the given `unit`.
+    pub(super) fn generic_expire(
+        &mut self,
+        command: &str,
+        key: &str,
+        amount: i64,
+        unit: fn(u64) -> Duration,
+    ) -> RespData {
+        let deadline = if amount > 0 {
+            match self.deadline_after(unit(amount as u64)) {
+                Some(deadline) => Some(deadline),
+                None => return errors::invalid_expire_time(command),
+            }
+        } else {
+            None
+        };
+        self.expire_if_needed(key);
+        if !self.db.contains_key(key) {
+            return RespData::Integer(0);
+        }
+
+        // A non-positive TTL deletes the key straight away, as in Redis.
+        let Some(deadline) = deadline else {
+            self.delete_key(key);
+            self.notify_key_event(KeyEventKind::Deleted, key);
+            return RespData::Integer(1);
+        };
+        self.db.set_expire(key, deadline);
+        RespData::Integer(1)
+    }
+
+    pub(super) fn ttl(&mut self, key: &str) -> RespData {
+        self.generic_ttl(key, |ttl| (ttl.as_millis() as i64 + 500) / 1000)
+    }
+
+    pub(super) fn pttl(&mut self, key: &str) -> RespData {
+        self.generic_ttl(key, |ttl| ttl.as_millis() as i64)
+    }
+
+    fn generic_ttl(&mut self, key: &str, convert: fn(Duration) -> i64) -> RespData {
+        match self.remaining_ttl(key) {
+            None => RespData::Integer(-2),
+            Some(None) => RespData::Integer(-1),
+            Some(Some(ttl)) => RespData::Integer(convert(ttl)),
+        }
+    }
+
+    pub(super) fn persist(&mut self, key: &str) -> RespData {
+        self.expire_if_needed(key);
+        match self.db.persist(key) {
+            Some(_) => RespData::Integer(1),
+            None => RespData::Integer(0),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::handler::clock::{Clock, MockClock};
+    use crate::handler::RedisValue;
+    use std::collections::HashMap;
+    use std::sync::Arc;
+
+    fn create_handler() -> (CommandHandler, Arc<MockClock>) {
+        let clock = Arc::new(MockClock::new());
+        let mut handler = CommandHandler::from(HashMap::new());
+        handler.clock = clock.clone();
+        handler
+            .db
+            .insert("volatile".to_string(), RedisValue::String("value".into()));
+        handler
+            .db
+            .set_expire("volatile", clock.now() + Duration::from_secs(100));
+        handler
+            .db
+            .insert("persistent".to_string(), RedisValue::String("value".into()));
+        handler
+            .db
+            .insert("stale".to_string(), RedisValue::String("value".into()));
+        handler.db.set_expire("stale", clock.now());
+        (handler, clock)
+    }
+
+    fn command(args: &[&str]) -> RespData {
+        RespData::array(args.iter().copied())
+    }
+
+    #[test]
+    fn test_expire() {
+        let (mut handler, _) = create_handler();
+
+        let test_cases = [
+            (
+                "Set a TTL on an existing key",
+                command(&["EXPIRE", "persistent", "50"]),
+                RespData::Integer(1),
+            ),
+            (
+                "Non-existing key",
+                command(&["EXPIRE", "missing", "50"]),
+                RespData::Integer(0),
+            ),
+            (
+                "Already expired key",
+                command(&["EXPIRE", "stale", "50"]),
+                RespData::Integer(0),
+            ),
+            (
+                "Not an integer",
+                command(&["EXPIRE", "persistent", "soon"]),
+                RespData::Error("value is not an integer or out of range".to_string()),
+            ),
+            (
+                "Not enough arguments",
+                command(&["EXPIRE", "persistent"]),
+                RespData::Error("wrong number of arguments for 'expire' command".to_string()),
+            ),
+            (
+                "TTL too large",
+                command(&["EXPIRE", "persistent", "9223372036854775807"]),
+                RespData::Error("invalid expire time in 'expire' command".to_string()),
+            ),
+            (
+                "TTL too large in milliseconds",
+                command(&["PEXPIRE", "persistent", "9223372036854775807"]),
+                RespData::Error("invalid expire time in 'pexpire' command".to_string()),
+            ),
+            (
+                "TTL too large for a missing key",
+                command(&["EXPIRE", "missing", "9223372036854775807"]),
+                RespData::Error("invalid expire time in 'expire' command".to_string()),
+            ),
+            (
+                "Negative TTL deletes the key",
+                command(&["EXPIRE", "volatile", "-1"]),
+                RespData::Integer(1),
+            ),
+        ];
+
+        for (name, input, expected_output) in test_cases {
+            let result = handler.handle(&input);
+            assert_eq!(result, expected_output, "{}", name);
+        }
+        assert!(handler.db.expire_at("persistent").is_some());
+        assert!(!handler.db.contains_key("volatile"));
+        assert_eq!(handler.stats.expired_keys, 1);
+    }
+
+    #[test]
+    fn test_ttl() {
+        let (mut handler, _) = create_handler();
+
+        let test_cases = [
+            (
+                "Key with a TTL",
+                command(&["TTL", "volatile"]),
+                RespData::Integer(100),
+            ),
+            (
+                "Key without a TTL",
+                command(&["TTL", "persistent"]),
+                RespData::Integer(-1),
+            ),
+            (
+                "Non-existing key",
+                command(&["TTL", "missing"]),
+                RespData::Integer(-2),
+            ),
+            (
+                "Expired key",
+                command(&["TTL", "stale"]),
+                RespData::Integer(-2),
+            ),
+            (
+                "Not enough arguments",
+                command(&["TTL"]),
+                RespData::Error("wrong number of arguments for 'ttl' command".to_string()),
+            ),
+        ];
+
+        for (name, input, expected_output) in test_cases {
+            let result = handler.handle(&input);
+            assert_eq!(result, expected_output, "{}", name);
+        }
+
+        assert_eq!(
+            handler.handle(&command(&["PTTL", "volatile"])),
+            RespData::Integer(100_000)
+        );
+    }
+
+    #[test]
+    fn test_ttl_counts_down() {
+        let (mut handler, clock) = create_handler();
+        handler.handle(&command(&["EXPIRE", "persistent", "10"]));
+
+        let test_cases = [
+            ("Nothing has passed", 0, 10_000, 1),
+            ("Rounded to the nearest second", 4_400, 5_600, 1),
+            ("Rounded up", 500, 5_100, 1),
+            ("Last millisecond", 5_099, 1, 1),
+            ("Expired", 1, -2, 0),
+        ];
+
+        for (name, advance_ms, expected_pttl, expected_keys) in test_cases {
+            clock.advance(Duration::from_millis(advance_ms));
+            assert_eq!(
+                handler.handle(&command(&["PTTL", "persistent"])),
+                RespData::Integer(expected_pttl),
+                "{}",
+                name
+            );
+            assert_eq!(
+                handler.db.contains_key("persistent") as i64,
+                expected_keys,
+                "{}",
+                name
+            );
+        }
+        assert_eq!(
+            handler.handle(&command(&["TTL", "volatile"])),
+            RespData::Integer(90)
+        );
+    }
+
+    #[test]
+    fn test_persist() {
+        let (mut handler, _) = create_handler();
+
+        let test_cases = [
+            (
+                "Key with a TTL",
+                command(&["PERSIST", "volatile"]),
+                RespData::Integer(1),
+            ),
+            (
+                "Key without a TTL",
+                command(&["PERSIST", "persistent"]),
+                RespData::Integer(0),
+            ),
+            (
+                "Non-existing key",
+                command(&["PERSIST", "missing"]),
+                RespData::Integer(0),
+            ),
+        ];
+
+        for (name, input, expected_output) in test_cases {
+            let result = handler.handle(&input);
+            assert_eq!(result, expected_output, "{}", name);
+        }
+        assert!(handler.db.expire_at("volatile").is_none());
+    }
+}
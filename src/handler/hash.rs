@@ -0,0 +1,300 @@
+//! Hash values. Like Redis, a small hash is kept as a flat list of its fields in the order
+//! they were added, its listpack encoding, which takes little memory and makes HGETALL reply
+//! in a predictable order. Once it has more than `hash-max-listpack-entries` fields, or a
+//! field or value longer than `hash-max-listpack-value` bytes, it becomes a hash table for
+//! good, and its fields come back in no particular order.
+
+use crate::config::{Config, DEFAULT_HASH_MAX_LISTPACK_ENTRIES, DEFAULT_HASH_MAX_LISTPACK_VALUE};
+use std::collections::{hash_map, HashMap};
+use std::{mem, slice};
+
+/// The `hash-max-listpack-*` thresholds a hash must stay within to remain a listpack.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ListpackLimits {
+    pub max_entries: usize,
+    pub max_value: usize,
+}
+
+impl ListpackLimits {
+    /// Limits no hash goes past, so it stays a listpack.
+    pub const UNLIMITED: Self = Self {
+        max_entries: usize::MAX,
+        max_value: usize::MAX,
+    };
+
+    /// Limits every hash with a field goes past, so it becomes a hash table.
+    pub const NONE: Self = Self {
+        max_entries: 0,
+        max_value: 0,
+    };
+
+    fn fit(&self, field: &str, value: &str) -> bool {
+        field.len() <= self.max_value && value.len() <= self.max_value
+    }
+}
+
+impl Default for ListpackLimits {
+    fn default() -> Self {
+        Self {
+            max_entries: DEFAULT_HASH_MAX_LISTPACK_ENTRIES,
+            max_value: DEFAULT_HASH_MAX_LISTPACK_VALUE,
+        }
+    }
+}
+
+impl From<&Config> for ListpackLimits {
+    fn from(config: &Config) -> Self {
+        Self {
+            max_entries: config.hash_max_listpack_entries,
+            max_value: config.hash_max_listpack_value,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub enum RedisHash {
+    /// The fields in insertion order, found by scanning them.
+    Listpack(Vec<(String, String)>),
+    Table(HashMap<String, String>),
+}
+
+impl Default for RedisHash {
+    fn default() -> Self {
+        RedisHash::Listpack(Vec::new())
+    }
+}
+
+impl RedisHash {
+    /// A hash of `pairs`, a listpack if they fit `limits`. A repeated field keeps its first
+    /// place and its last value.
+    pub fn from_pairs(pairs: Vec<(String, String)>, limits: ListpackLimits) -> Self {
+        let mut hash = RedisHash::default();
+        hash.insert_pairs(pairs, limits);
+        hash
+    }
+
+    pub fn len(&self) -> usize {
+        match self {
+            RedisHash::Listpack(entries) => entries.len(),
+            RedisHash::Table(map) => map.len(),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn get(&self, field: &str) -> Option<&String> {
+        match self {
+            RedisHash::Listpack(entries) => entries
+                .iter()
+                .find(|(existing, _)| existing == field)
+                .map(|(_, value)| value),
+            RedisHash::Table(map) => map.get(field),
+        }
+    }
+
+    /// Sets `field` to `value`, returning whether the field is new. A new field goes last,
+    /// while an existing one keeps its place. Writes go through `insert_pairs` instead, which
+    /// keeps a listpack within its limits.
+    pub fn insert(&mut self, field: String, value: String) -> bool {
+        match self {
+            RedisHash::Listpack(entries) => {
+                match entries.iter_mut().find(|(existing, _)| *existing == field) {
+                    Some((_, old)) => {
+                        *old = value;
+                        false
+                    }
+                    None => {
+                        entries.push((field, value));
+                        true
+                    }
+                }
+            }
+            RedisHash::Table(map) => map.insert(field, value).is_none(),
+        }
+    }
+
+    /// Sets every field of `pairs`, returning how many are new. Like Redis, a listpack becomes
+    /// a hash table as soon as it has too many fields, or before the write if `pairs` alone
+    /// goes past `limits`, so a big write never scans a long list for each field. It never
+    /// turns back, even if the limits are raised.
+    pub fn insert_pairs(&mut self, pairs: Vec<(String, String)>, limits: ListpackLimits) -> usize {
+        if pairs.len() > limits.max_entries
+            || !pairs.iter().all(|(field, value)| limits.fit(field, value))
+        {
+            self.convert();
+        }
+        let mut added = 0;
+        for (field, value) in pairs {
+            if self.insert(field, value) {
+                added += 1;
+                if self.len() > limits.max_entries {
+                    self.convert();
+                }
+            }
+        }
+        added
+    }
+
+    /// The fields and their values, in insertion order for a listpack.
+    pub fn iter(&self) -> Iter<'_> {
+        Iter(match self {
+            RedisHash::Listpack(entries) => IterInner::Listpack(entries.iter()),
+            RedisHash::Table(map) => IterInner::Table(map.iter()),
+        })
+    }
+
+    /// The encoding OBJECT ENCODING reports.
+    pub fn encoding(&self) -> &'static str {
+        match self {
+            RedisHash::Listpack(_) => "listpack",
+            RedisHash::Table(_) => "hashtable",
+        }
+    }
+
+    /// Turns a listpack into a hash table.
+    fn convert(&mut self) {
+        if let RedisHash::Listpack(entries) = self {
+            let entries = mem::take(entries);
+            *self = RedisHash::Table(entries.into_iter().collect());
+        }
+    }
+}
+
+/// Collects fields with the default limits, as `from_pairs` does.
+impl FromIterator<(String, String)> for RedisHash {
+    fn from_iter<I: IntoIterator<Item = (String, String)>>(iter: I) -> Self {
+        RedisHash::from_pairs(iter.into_iter().collect(), ListpackLimits::default())
+    }
+}
+
+/// Hashes are equal if they hold the same fields and values, whatever their encoding and
+/// order.
+impl PartialEq for RedisHash {
+    fn eq(&self, other: &Self) -> bool {
+        self.len() == other.len()
+            && self
+                .iter()
+                .all(|(field, value)| other.get(field) == Some(value))
+    }
+}
+
+impl Eq for RedisHash {}
+
+pub struct Iter<'a>(IterInner<'a>);
+
+enum IterInner<'a> {
+    Listpack(slice::Iter<'a, (String, String)>),
+    Table(hash_map::Iter<'a, String, String>),
+}
+
+impl<'a> Iterator for Iter<'a> {
+    type Item = (&'a String, &'a String);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        match &mut self.0 {
+            IterInner::Listpack(entries) => entries.next().map(|(field, value)| (field, value)),
+            IterInner::Table(map) => map.next(),
+        }
+    }
+}
+
+impl<'a> IntoIterator for &'a RedisHash {
+    type Item = (&'a String, &'a String);
+    type IntoIter = Iter<'a>;
+
+    fn into_iter(self) -> Iter<'a> {
+        self.iter()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn pairs(pairs: &[(&str, &str)]) -> Vec<(String, String)> {
+        pairs
+            .iter()
+            .map(|(field, value)| (field.to_string(), value.to_string()))
+            .collect()
+    }
+
+    fn fields(hash: &RedisHash) -> Vec<&str> {
+        hash.iter().map(|(field, _)| field.as_str()).collect()
+    }
+
+    #[test]
+    fn test_listpack_order() {
+        let limits = ListpackLimits::default();
+        let mut hash = RedisHash::default();
+        for field in ["zebra", "apple", "mango", "apple"] {
+            hash.insert_pairs(pairs(&[(field, &field.to_uppercase())]), limits);
+        }
+        assert_eq!(fields(&hash), ["zebra", "apple", "mango"]);
+        assert_eq!(hash.get("apple").map(String::as_str), Some("APPLE"));
+        assert_eq!(hash.encoding(), "listpack");
+
+        let collected = RedisHash::from_pairs(pairs(&[("b", "1"), ("a", "2"), ("b", "3")]), limits);
+        assert_eq!(fields(&collected), ["b", "a"]);
+        assert_eq!(collected.get("b").map(String::as_str), Some("3"));
+    }
+
+    #[test]
+    fn test_conversion() {
+        let limits = ListpackLimits {
+            max_entries: 2,
+            max_value: 8,
+        };
+        let test_cases = [
+            (
+                "Within the limits",
+                vec![("a", "1"), ("b", "2")],
+                2,
+                "listpack",
+            ),
+            (
+                "Repeated fields within the limits",
+                vec![("a", "1"), ("a", "2")],
+                1,
+                "listpack",
+            ),
+            (
+                "Too many fields",
+                vec![("a", "1"), ("b", "2"), ("c", "3")],
+                3,
+                "hashtable",
+            ),
+            (
+                "Too many fields in one write, even if repeated",
+                vec![("a", "1"), ("a", "2"), ("a", "3")],
+                1,
+                "hashtable",
+            ),
+            ("Long value", vec![("a", "123456789")], 1, "hashtable"),
+            ("Long field", vec![("abcdefghi", "1")], 1, "hashtable"),
+        ];
+
+        for (name, written, len, expected) in test_cases {
+            let hash = RedisHash::from_pairs(pairs(&written), limits);
+            assert_eq!(hash.encoding(), expected, "{}", name);
+            assert_eq!(hash.len(), len, "{}", name);
+        }
+
+        // Fields added one at a time convert the hash once there are too many.
+        let mut hash = RedisHash::default();
+        for field in ["a", "b", "c"] {
+            hash.insert_pairs(pairs(&[(field, "1")]), limits);
+        }
+        assert_eq!(hash.encoding(), "hashtable");
+
+        // Converting is for good, but the hash still compares equal.
+        let listpack = RedisHash::from_pairs(pairs(&[("a", "1")]), ListpackLimits::UNLIMITED);
+        let table = RedisHash::from_pairs(pairs(&[("a", "1")]), ListpackLimits::NONE);
+        let mut written = table.clone();
+        written.insert_pairs(pairs(&[("a", "1")]), ListpackLimits::UNLIMITED);
+        assert_eq!(listpack.encoding(), "listpack");
+        assert_eq!(written.encoding(), "hashtable");
+        assert_eq!(table, listpack);
+    }
+}
@@ -0,0 +1,261 @@
+//! HELP for container commands: the usage lines of every subcommand, framed the way Redis
+//! frames them.
+
+use super::command_table;
+use super::errors;
+use super::CommandHandler;
+use crate::resp::RespData;
+
+const OBJECT_HELP: &[&str] = &[
+    "ENCODING <key>",
+    "    Return the kind of internal representation used in order to store the value",
+    "    associated with a <key>.",
+    "FREQ <key>",
+    "    Return the access frequency index of the <key>. The returned integer is",
+    "    proportional to the logarithm of the recent access frequency of the key.",
+    "IDLETIME <key>",
+    "    Return the idle time of the <key>, that is the approximated number of",
+    "    seconds elapsed since the last access to the key.",
+    "REFCOUNT <key>",
+    "    Return the number of references of the value associated with the specified",
+    "    <key>.",
+];
+
+const CLIENT_HELP: &[&str] = &[
+    "CACHING (YES|NO)",
+    "    Enable/disable tracking of the keys for next command in OPTIN/OPTOUT modes.",
+    "GETNAME",
+    "    Return the name of the current connection.",
+    "GETREDIR",
+    "    Return the client ID we are redirecting to when tracking is enabled.",
+    "ID",
+    "    Return the ID of the current connection.",
+    "INFO",
+    "    Return information about the current client connection.",
+    "KILL <option> <value> [<option> <value> [...]]",
+    "    Kill connections. Options are:",
+    "    * ADDR <ip:port>",
+    "      Kill connection made from <ip:port>",
+    "    * LADDR <ip:port>",
+    "      Kill connection made to <ip:port>",
+    "    * TYPE (NORMAL|MASTER|REPLICA|PUBSUB)",
+    "      Kill connections by type.",
+    "    * USER <username>",
+    "      Kill connections authenticated by <username>.",
+    "    * ID <client-id>",
+    "      Kill connections by client id.",
+    "    * MAXAGE <maxage>",
+    "      Kill connections older than the specified age.",
+    "LIST [options ...]",
+    "    Return information about client connections. Options:",
+    "    * TYPE (NORMAL|MASTER|REPLICA|PUBSUB)",
+    "      Return clients of specified type.",
+    "    * ID <client-id> [<client-id> ...]",
+    "      Return clients with the specified IDs.",
+    "UNPAUSE",
+    "    Stop the current client pause, resuming traffic.",
+    "PAUSE <timeout> [WRITE|ALL]",
+    "    Suspend all, or just write, clients for <timeout> milliseconds.",
+    "REPLY (ON|OFF|SKIP)",
+    "    Control the replies sent to the current connection.",
+    "SETNAME <name>",
+    "    Assign the name <name> to the current connection.",
+    "TRACKING (ON|OFF) [REDIRECT <id>] [BCAST] [PREFIX <prefix> [...]]",
+    "         [OPTIN] [OPTOUT] [NOLOOP]",
+    "    Control server assisted client side caching.",
+    "NO-EVICT (ON|OFF)",
+    "    Protect current client connection from eviction.",
+    "NO-TOUCH (ON|OFF)",
+    "    Will not touch LRU/LFU stats when this mode is on.",
+];
+
+const CONFIG_HELP: &[&str] = &[
+    "GET <pattern>",
+    "    Return parameters matching the glob-like <pattern> and their values.",
+    "SET <directive> <value>",
+    "    Set the configuration <directive> to <value>.",
+    "RESETSTAT",
+    "    Reset statistics reported by the INFO command.",
+];
+
+const MEMORY_HELP: &[&str] = &[
+    "DOCTOR",
+    "    Return memory problems reports.",
+    "STATS",
+    "    Return information about the memory usage of the server.",
+    "USAGE <key> [SAMPLES <count>]",
+    "    Return memory in bytes used by <key> and its value. Nested values are",
+    "    sampled up to <count> times (default: 5, 0 means sample all).",
+];
+
+const LATENCY_HELP: &[&str] = &[
+    "DOCTOR",
+    "    Return a human readable latency analysis report.",
+    "HISTORY <event>",
+    "    Return time-latency samples for the <event> class.",
+    "LATEST",
+    "    Return the latest latency samples for all events.",
+    "RESET [<event> ...]",
+    "    Reset latency data of one or more <event> classes.",
+    "    (default: reset all data for all event classes)",
+    "HISTOGRAM [COMMAND ...]",
+    "    Return a cumulative distribution of latencies in the format of a histogram for the specified command names.",
+    "    If no commands are specified then all histograms are replied.",
+];
+
+const DEBUG_HELP: &[&str] = &[
+    "BIGKEYS",
+    "    Report the biggest keys of each type by size and by memory, like redis-cli --bigkeys.",
+    "CHANGE-REPL-ID",
+    "    Change the replication IDs of the instance.",
+    "    Dangerous: should be used only for testing the replication subsystem.",
+    "DIGEST",
+    "    Output a hex signature representing the current DB content.",
+    "DIGEST-VALUE <key> [<key> ...]",
+    "    Output a hex signature of the values of all the specified keys.",
+    "HOTKEYS [<count>]",
+    "    Show the <count> most accessed keys of each type (default: 10).",
+    "JMAP",
+    "    Show a summary of the heap: the dataset and what the allocator holds.",
+    "OBJECT <key>",
+    "    Show low level info about the <key> and associated value.",
+    "PANIC",
+    "    Crash the server simulating a panic.",
+    "QUICKACK <0|1>",
+    "    Enable or disable TCP_QUICKACK on the current connection.",
+    "SET-ACTIVE-EXPIRE <0|1>",
+    "    Setting it to 0 disables expiring keys in background when they are not",
+    "    accessed (otherwise the Redis behavior). Setting it to 1 reenables back the",
+    "    default.",
+    "SLEEP <seconds>",
+    "    Stop the server for <seconds>. Decimals allowed.",
+    "STRINGMATCH-LEN",
+    "    Run a fuzz tester against the stringmatchlen() function.",
+];
+
+const ACL_HELP: &[&str] = &[
+    "CAT [<category>]",
+    "    List all commands that belong to <category>, or all command categories",
+    "    when no category is specified.",
+    "DELUSER <username> [<username> ...]",
+    "    Delete a list of users.",
+    "GETUSER <username>",
+    "    Get the user's details.",
+    "LIST",
+    "    Show users details in config file format.",
+    "LOAD",
+    "    Reload users from the ACL file.",
+    "SAVE",
+    "    Save the current config to the ACL file.",
+    "SETUSER <username> <attribute> [<attribute> ...]",
+    "    Create or modify a user with the specified attributes.",
+    "USERS",
+    "    List all the registered usernames.",
+    "WHOAMI",
+    "    Return the current connection username.",
+];
+
+/// The usage lines of a container command's subcommands, without the framing.
+fn usage(command: &str) -> Option<&'static [&'static str]> {
+    match command {
+        "object" => Some(OBJECT_HELP),
+        "client" => Some(CLIENT_HELP),
+        "config" => Some(CONFIG_HELP),
+        "memory" => Some(MEMORY_HELP),
+        "latency" => Some(LATENCY_HELP),
+        "acl" => Some(ACL_HELP),
+        "debug" => Some(DEBUG_HELP),
+        _ => None,
+    }
+}
+
+/// The reply to `command HELP`: a header, the usage lines, then HELP's own entry, each as a
+/// status line.
+fn help_reply(command: &str, usage: &[&str]) -> RespData {
+    let header = format!(
+        "{} <subcommand> [<arg> [value] [opt] ...]. Subcommands are:",
+        command.to_uppercase()
+    );
+    let lines = std::iter::once(header)
+        .chain(usage.iter().map(|line| line.to_string()))
+        .chain(["HELP".to_string(), "    Print this help.".to_string()]);
+    RespData::Array(lines.map(RespData::SimpleString).collect())
+}
+
+impl CommandHandler {
+    /// Answers `command HELP` for any container command, so handlers only deal with their
+    /// real subcommands. Returns `None` for anything else.
+    pub(super) fn subcommand_help(&self, name: &str, resp: &RespData) -> Option<RespData> {
+        let RespData::Array(arr) = resp else {
+            return None;
+        };
+        let Some(RespData::BulkString(subcommand)) = arr.get(1) else {
+            return None;
+        };
+        if !subcommand.eq_ignore_ascii_case("help") {
+            return None;
+        }
+        let spec = command_table::lookup(name)?;
+        let usage = usage(spec.name)?;
+        if arr.len() != 2 {
+            return Some(errors::wrong_arity(&format!("{name}|help")));
+        }
+        Some(help_reply(name, usage))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+
+    fn command(args: &[&str]) -> RespData {
+        RespData::array(args.iter().copied())
+    }
+
+    #[test]
+    fn test_subcommand_help() {
+        let mut handler = CommandHandler::from(HashMap::new());
+
+        let test_cases = [
+            (
+                "Help is framed by a header and its own entry",
+                command(&["object", "help"]),
+                help_reply("object", OBJECT_HELP),
+            ),
+            (
+                "Help is case-insensitive",
+                command(&["CONFIG", "Help"]),
+                help_reply("config", CONFIG_HELP),
+            ),
+            (
+                "Help takes no arguments",
+                command(&["memory", "help", "extra"]),
+                RespData::Error("wrong number of arguments for 'memory|help' command".to_string()),
+            ),
+            (
+                "Other subcommands are still unknown",
+                command(&["latency", "nope"]),
+                RespData::Error("unknown subcommand 'nope'. Try LATENCY HELP.".to_string()),
+            ),
+        ];
+
+        for (name, input, expected) in test_cases {
+            assert_eq!(handler.handle(&input), expected, "{}", name);
+        }
+
+        let RespData::Array(lines) = handler.handle(&command(&["acl", "help"])) else {
+            panic!("ACL HELP did not reply with an array");
+        };
+        assert_eq!(
+            lines.first(),
+            Some(&RespData::SimpleString(
+                "ACL <subcommand> [<arg> [value] [opt] ...]. Subcommands are:".to_string()
+            ))
+        );
+        assert_eq!(
+            lines.last(),
+            Some(&RespData::SimpleString("    Print this help.".to_string()))
+        );
+    }
+}
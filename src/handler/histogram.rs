@@ -0,0 +1,171 @@
+use std::time::Duration;
+
+/// Number of linear sub-buckets each power-of-two range is split into.
+const SUB_BUCKETS: u64 = 16;
+const SUB_BUCKET_BITS: u32 = SUB_BUCKETS.trailing_zeros();
+/// Highest power of two tracked; larger samples are clamped into the last bucket.
+const MAX_EXPONENT: u32 = 40;
+const BUCKET_COUNT: usize = ((MAX_EXPONENT - SUB_BUCKET_BITS + 2) as u64 * SUB_BUCKETS) as usize;
+
+/// Log-linear histogram of latencies in microseconds with roughly 6% relative precision.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LatencyHistogram {
+    buckets: Vec<u64>,
+    count: u64,
+}
+
+impl Default for LatencyHistogram {
+    fn default() -> Self {
+        Self {
+            buckets: vec![0; BUCKET_COUNT],
+            count: 0,
+        }
+    }
+}
+
+impl LatencyHistogram {
+    pub fn record(&mut self, duration: Duration) {
+        let usec = (duration.as_micros() as u64).min((1 << (MAX_EXPONENT + 1)) - 1);
+        self.buckets[bucket_index(usec)] += 1;
+        self.count += 1;
+    }
+
+    pub fn count(&self) -> u64 {
+        self.count
+    }
+
+    /// Returns the latency in microseconds below which `percentile` percent of samples fall.
+    pub fn percentile(&self, percentile: f64) -> u64 {
+        if self.count == 0 {
+            return 0;
+        }
+        let target = ((percentile / 100.0) * self.count as f64).ceil().max(1.0) as u64;
+        let mut seen = 0;
+        for (index, count) in self.buckets.iter().enumerate() {
+            seen += count;
+            if seen >= target {
+                return bucket_upper_bound(index);
+            }
+        }
+        bucket_upper_bound(BUCKET_COUNT - 1)
+    }
+
+    /// How many samples were below `usec` microseconds. Exact when `usec` is a power of two,
+    /// and otherwise rounded down to the nearest bucket boundary.
+    pub fn count_below(&self, usec: u64) -> u64 {
+        self.buckets
+            .iter()
+            .enumerate()
+            .take_while(|(index, _)| bucket_upper_bound(*index) <= usec)
+            .map(|(_, count)| count)
+            .sum()
+    }
+
+    /// Cumulative sample counts at power-of-two boundaries, as reported by LATENCY HISTOGRAM.
+    /// Only boundaries at which the cumulative count grows are included.
+    pub fn power_of_two_buckets(&self) -> Vec<(u64, u64)> {
+        let mut result = Vec::new();
+        let mut cumulative = 0;
+        let mut index = 0;
+        for exponent in 0..=MAX_EXPONENT {
+            let boundary = 1u64 << exponent;
+            let previous = cumulative;
+            while index < BUCKET_COUNT && bucket_upper_bound(index) <= boundary {
+                cumulative += self.buckets[index];
+                index += 1;
+            }
+            if cumulative > previous {
+                result.push((boundary, cumulative));
+            }
+            if cumulative == self.count {
+                break;
+            }
+        }
+        result
+    }
+}
+
+fn bucket_index(usec: u64) -> usize {
+    if usec < SUB_BUCKETS {
+        return usec as usize;
+    }
+    let exponent = u64::BITS - 1 - usec.leading_zeros();
+    let sub_bucket = (usec >> (exponent - SUB_BUCKET_BITS)) & (SUB_BUCKETS - 1);
+    ((exponent - SUB_BUCKET_BITS + 1) as u64 * SUB_BUCKETS + sub_bucket) as usize
+}
+
+/// Smallest value that no longer falls into bucket `index`, i.e. its exclusive upper bound.
+fn bucket_upper_bound(index: usize) -> u64 {
+    let index = index as u64;
+    if index < SUB_BUCKETS {
+        return index + 1;
+    }
+    let exponent = (index / SUB_BUCKETS) as u32 + SUB_BUCKET_BITS - 1;
+    let sub_bucket = index % SUB_BUCKETS;
+    (SUB_BUCKETS + sub_bucket + 1) << (exponent - SUB_BUCKET_BITS)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn histogram_of(samples: impl IntoIterator<Item = u64>) -> LatencyHistogram {
+        let mut histogram = LatencyHistogram::default();
+        for usec in samples {
+            histogram.record(Duration::from_micros(usec));
+        }
+        histogram
+    }
+
+    #[test]
+    fn test_bucket_bounds() {
+        for usec in [0, 1, 15, 16, 17, 100, 1000, 123_456, 1 << 40] {
+            let index = bucket_index(usec);
+            assert!(usec < bucket_upper_bound(index), "{usec} below upper bound");
+            if index > 0 {
+                assert!(
+                    usec >= bucket_upper_bound(index - 1),
+                    "{usec} above previous bound"
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn test_percentile() {
+        let histogram = histogram_of(1..=1000);
+
+        assert_eq!(histogram.count(), 1000);
+        let p50 = histogram.percentile(50.0);
+        assert!((500..=540).contains(&p50), "p50 was {p50}");
+        let p99 = histogram.percentile(99.0);
+        assert!((990..=1056).contains(&p99), "p99 was {p99}");
+        assert_eq!(LatencyHistogram::default().percentile(99.0), 0);
+    }
+
+    #[test]
+    fn test_count_below() {
+        let histogram = histogram_of([0, 1, 3, 3, 100, 5000]);
+        let test_cases = [
+            ("Nothing below zero", 0, 0),
+            ("Below one", 1, 1),
+            ("Below four", 4, 4),
+            ("Below 128", 128, 5),
+            ("Everything", 1 << 20, 6),
+        ];
+
+        for (name, usec, expected) in test_cases {
+            assert_eq!(histogram.count_below(usec), expected, "{}", name);
+        }
+    }
+
+    #[test]
+    fn test_power_of_two_buckets() {
+        let histogram = histogram_of([0, 1, 3, 3, 100]);
+
+        assert_eq!(
+            histogram.power_of_two_buckets(),
+            vec![(1, 1), (2, 2), (4, 4), (128, 5)]
+        );
+    }
+}
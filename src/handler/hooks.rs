@@ -0,0 +1,170 @@
+//! Hooks around command execution for code embedding the server, e.g. to add its own
+//! authentication, prefix keys per tenant or log requests. Before hooks see the parsed command
+//! and may rewrite it or answer it themselves; after hooks see the command as run and may
+//! rewrite the reply.
+//!
+//! Hooks are layered in the order they are registered: before hooks run first to last, and
+//! after hooks last to first, so a hook registered first wraps all the others.
+
+use super::client::ClientId;
+use super::CommandHandler;
+use crate::resp::RespData;
+
+/// Who is running a command.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub struct CommandContext<'a> {
+    /// `None` when the command doesn't come from a client connection.
+    pub client: Option<ClientId>,
+    /// The ACL user the client runs commands as.
+    pub user: Option<&'a str>,
+}
+
+pub type BeforeCommandHook = Box<dyn Fn(&CommandContext, &mut RespData) -> Option<RespData> + Send>;
+pub type AfterCommandHook = Box<dyn Fn(&CommandContext, &RespData, &mut RespData) + Send>;
+
+impl CommandHandler {
+    /// Registers `hook` to run before every command. It may rewrite the command, or return a
+    /// reply to send instead of running it, in which case later before hooks are skipped. It
+    /// runs while the handler is in use, so it must not call back into it.
+    pub fn before_command(
+        &mut self,
+        hook: impl Fn(&CommandContext, &mut RespData) -> Option<RespData> + Send + 'static,
+    ) {
+        self.before_command_hooks.push(Box::new(hook));
+    }
+
+    /// Registers `hook` to run after every command, including ones a before hook replied to,
+    /// with the command as it was run and its reply, which it may rewrite.
+    pub fn after_command(
+        &mut self,
+        hook: impl Fn(&CommandContext, &RespData, &mut RespData) + Send + 'static,
+    ) {
+        self.after_command_hooks.push(Box::new(hook));
+    }
+
+    /// Runs `resp` through `run`, wrapped in the registered hooks.
+    pub(super) fn with_command_hooks(
+        &mut self,
+        resp: &RespData,
+        run: impl FnOnce(&mut Self, &RespData) -> RespData,
+    ) -> RespData {
+        if self.before_command_hooks.is_empty() && self.after_command_hooks.is_empty() {
+            return run(self, resp);
+        }
+
+        let mut command = resp.clone();
+        let early_reply = {
+            let context = self.command_context();
+            self.before_command_hooks
+                .iter()
+                .find_map(|hook| hook(&context, &mut command))
+        };
+        let mut reply = match early_reply {
+            Some(reply) => reply,
+            None => run(self, &command),
+        };
+        let context = self.command_context();
+        for hook in self.after_command_hooks.iter().rev() {
+            hook(&context, &command, &mut reply);
+        }
+        reply
+    }
+
+    fn command_context(&self) -> CommandContext<'_> {
+        CommandContext {
+            client: self.current_client,
+            user: self.current_client().map(|client| client.user.as_str()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+    use std::sync::{Arc, Mutex};
+
+    fn command(args: &[&str]) -> RespData {
+        RespData::array(args.iter().copied())
+    }
+
+    #[test]
+    fn test_command_hooks() {
+        let mut handler = CommandHandler::from(HashMap::new());
+        let log = Arc::new(Mutex::new(Vec::new()));
+
+        // Outermost: logs what finally ran and the reply the client gets.
+        let seen = Arc::clone(&log);
+        handler.after_command(move |_, command, reply| {
+            seen.lock()
+                .unwrap()
+                .push(format!("{command:?} -> {reply:?}"));
+        });
+        // Refuses FLUSHALL.
+        handler.before_command(|_, command| {
+            let RespData::Array(args) = command else {
+                return None;
+            };
+            matches!(&args[0], RespData::BulkString(name) if name.eq_ignore_ascii_case("flushall"))
+                .then(|| RespData::Error("-DENIED not allowed".to_string()))
+        });
+        // Puts GET and SET keys in a tenant's namespace.
+        handler.before_command(|context, command| {
+            assert_eq!(context.client, None);
+            if let RespData::Array(args) = command {
+                if let Some(RespData::BulkString(key)) = args.get_mut(1) {
+                    *key = format!("tenant:{key}");
+                }
+            }
+            None
+        });
+        // Innermost: upper cases string replies.
+        handler.after_command(|_, _, reply| {
+            if let RespData::BulkString(value) = reply {
+                *value = value.to_uppercase();
+            }
+        });
+
+        let test_cases = [
+            (
+                "Prefixed write",
+                command(&["SET", "k", "v"]),
+                RespData::SimpleString("OK".to_string()),
+                "Array([BulkString(\"SET\"), BulkString(\"tenant:k\"), BulkString(\"v\")]) -> SimpleString(\"OK\")",
+            ),
+            (
+                "Prefixed read, reply rewritten",
+                command(&["GET", "k"]),
+                RespData::BulkString("V".to_string()),
+                "Array([BulkString(\"GET\"), BulkString(\"tenant:k\")]) -> BulkString(\"V\")",
+            ),
+            (
+                "Answered by a hook",
+                command(&["FLUSHALL"]),
+                RespData::Error("-DENIED not allowed".to_string()),
+                "Array([BulkString(\"FLUSHALL\")]) -> Error(\"-DENIED not allowed\")",
+            ),
+        ];
+
+        for (name, input, expected, logged) in test_cases {
+            assert_eq!(handler.handle(&input), expected, "{}", name);
+            assert_eq!(
+                log.lock().unwrap().pop().as_deref(),
+                Some(logged),
+                "{}",
+                name
+            );
+        }
+
+        handler.before_command_hooks.clear();
+        assert_eq!(
+            handler.handle(&command(&["GET", "tenant:k"])),
+            RespData::BulkString("V".to_string())
+        );
+        assert_eq!(
+            handler.handle(&command(&["GET", "k"])),
+            RespData::Null,
+            "Unprefixed key untouched"
+        );
+    }
+}
@@ -0,0 +1,157 @@
+//! The most frequently accessed keys of each type, ranked by the same logarithmic counters the
+//! LFU eviction policies use, so hotspots show up without sampling traffic from outside.
+
+use super::errors;
+use super::info::info_field;
+use super::lfu::LFU_INIT_VAL;
+use super::CommandHandler;
+use crate::resp::RespData;
+
+/// How many keys of each type INFO hotkeys lists.
+pub(super) const HOTKEYS_PER_TYPE: usize = 10;
+
+impl CommandHandler {
+    /// Up to `per_type` of the most accessed keys of each type along with their access
+    /// frequency, types in alphabetical order and hottest keys first. Keys whose counter is
+    /// still at its initial value are left out, since that says nothing about traffic.
+    pub(super) fn hot_keys(&mut self, per_type: usize) -> Vec<(&'static str, Vec<(String, u8)>)> {
+        let decay_time = self.config.read().unwrap().lfu_decay_time;
+        let candidates: Vec<(String, u8)> = self
+            .lfu_counters
+            .iter()
+            .map(|(key, counter)| (key.clone(), counter.value(decay_time)))
+            .filter(|(_, freq)| *freq > LFU_INIT_VAL)
+            .collect();
+        let mut by_type: Vec<(&'static str, Vec<(String, u8)>)> = Vec::new();
+        for (key, freq) in candidates {
+            // The value may have been spilled out of memory, and its type is needed.
+            self.db.load(&key);
+            let Some(value) = self.db.get(&key) else {
+                continue;
+            };
+            let type_name = value.type_name();
+            let keys = match by_type.iter().position(|(name, _)| *name == type_name) {
+                Some(idx) => &mut by_type[idx].1,
+                None => {
+                    by_type.push((type_name, Vec::new()));
+                    &mut by_type.last_mut().unwrap().1
+                }
+            };
+            keys.push((key, freq));
+        }
+
+        by_type.sort_by_key(|(name, _)| *name);
+        for (_, keys) in &mut by_type {
+            keys.sort_by(|(a_key, a_freq), (b_key, b_freq)| {
+                b_freq.cmp(a_freq).then_with(|| a_key.cmp(b_key))
+            });
+            keys.truncate(per_type);
+        }
+        by_type
+    }
+
+    /// The hotkeys INFO section, written apart from the others since finding the type of a
+    /// key may need to bring its value back into memory.
+    pub(super) fn write_hotkeys_section(&mut self, out: &mut String) {
+        out.push_str("# Hotkeys\r\n");
+        for (type_name, keys) in self.hot_keys(HOTKEYS_PER_TYPE) {
+            for (rank, (key, freq)) in keys.iter().enumerate() {
+                info_field(
+                    out,
+                    &format!("{type_name}_{rank}"),
+                    format!("key={key},freq={freq}"),
+                );
+            }
+        }
+    }
+
+    /// DEBUG HOTKEYS [count]: the hottest keys of each type as `type`, then `key` and
+    /// frequency pairs.
+    pub(super) fn debug_hotkeys(&mut self, args: &[&str]) -> RespData {
+        let per_type = match args {
+            [] => HOTKEYS_PER_TYPE,
+            [count] => match count.parse() {
+                Ok(count) => count,
+                Err(_) => return errors::not_an_integer(),
+            },
+            _ => return errors::wrong_arity("debug|hotkeys"),
+        };
+        RespData::Array(
+            self.hot_keys(per_type)
+                .into_iter()
+                .map(|(type_name, keys)| {
+                    let mut entry = vec![RespData::BulkString(type_name.to_string())];
+                    for (key, freq) in keys {
+                        entry.push(RespData::BulkString(key));
+                        entry.push(RespData::Integer(freq as i64));
+                    }
+                    RespData::Array(entry)
+                })
+                .collect(),
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::Config;
+    use std::collections::HashMap;
+    use std::sync::{Arc, RwLock};
+
+    fn command(args: &[&str]) -> RespData {
+        RespData::array(args.iter().copied())
+    }
+
+    #[test]
+    fn test_hot_keys() {
+        // Every access increments the counter, so frequencies are exact.
+        let config = Config {
+            lfu_log_factor: 0,
+            ..Config::default()
+        };
+        let mut handler = CommandHandler::new(HashMap::new(), Arc::new(RwLock::new(config)));
+        let reads = [("warm", 2), ("hot", 5), ("cold", 0), ("also-warm", 2)];
+        for (key, count) in reads {
+            handler.handle(&command(&["SET", key, "value"]));
+            for _ in 0..count {
+                handler.handle(&command(&["GET", key]));
+            }
+        }
+        handler.handle(&command(&["HSET", "h", "f", "v"]));
+        for _ in 0..3 {
+            handler.handle(&command(&["HGET", "h", "f"]));
+        }
+
+        // The write creates the counter at LFU_INIT_VAL and every read adds one.
+        let test_cases = [
+            (
+                "Ranked by frequency, then by name",
+                10,
+                vec![
+                    ("hash", vec![("h".to_string(), 8)]),
+                    (
+                        "string",
+                        vec![
+                            ("hot".to_string(), 10),
+                            ("also-warm".to_string(), 7),
+                            ("warm".to_string(), 7),
+                        ],
+                    ),
+                ],
+            ),
+            (
+                "Limited per type",
+                1,
+                vec![
+                    ("hash", vec![("h".to_string(), 8)]),
+                    ("string", vec![("hot".to_string(), 10)]),
+                ],
+            ),
+        ];
+
+        for (name, per_type, expected) in test_cases {
+            assert_eq!(handler.hot_keys(per_type), expected, "{}", name);
+        }
+    }
+}
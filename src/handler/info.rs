@@ -0,0 +1,654 @@
+use super::errors;
+use super::CommandHandler;
+use crate::allocator;
+use crate::resp::RespData;
+use std::fmt::Write;
+use std::time::UNIX_EPOCH;
+
+/// All INFO sections in output order, paired with whether they are part of the default set.
+const SECTIONS: [(&str, bool); 12] = [
+    ("server", true),
+    ("clients", true),
+    ("memory", true),
+    ("persistence", true),
+    ("stats", true),
+    ("replication", true),
+    ("cpu", true),
+    ("commandstats", false),
+    ("errorstats", false),
+    ("latencystats", false),
+    ("hotkeys", false),
+    ("keyspace", true),
+];
+
+/// Percentiles reported for each command in INFO latencystats.
+const LATENCY_PERCENTILES: [f64; 3] = [50.0, 99.0, 99.9];
+
+impl CommandHandler {
+    pub(super) fn info(&mut self, resp: &RespData) -> RespData {
+        let RespData::Array(arr) = resp else {
+            return errors::syntax_error();
+        };
+
+        let default_sections = SECTIONS
+            .iter()
+            .filter(|(_, default)| *default)
+            .map(|(name, _)| *name);
+        let all_sections = SECTIONS.iter().map(|(name, _)| *name);
+
+        let mut sections = Vec::new();
+        for arg in &arr[1..] {
+            let RespData::BulkString(section) = arg else {
+                return errors::syntax_error();
+            };
+            match section.to_lowercase().as_str() {
+                "default" => sections.extend(default_sections.clone()),
+                "all" | "everything" => sections.extend(all_sections.clone()),
+                section => sections.extend(all_sections.clone().filter(|name| *name == section)),
+            }
+        }
+        if arr.len() == 1 {
+            sections.extend(default_sections);
+        }
+
+        let mut output = String::new();
+        for section in all_sections {
+            if !sections.contains(&section) {
+                continue;
+            }
+            if !output.is_empty() {
+                output.push_str("\r\n");
+            }
+            match section {
+                "hotkeys" => self.write_hotkeys_section(&mut output),
+                section => self.write_info_section(section, &mut output),
+            }
+        }
+        RespData::BulkString(output)
+    }
+
+    /// The default INFO sections as a JSON object with an object per section, for the HTTP
+    /// `/info` endpoint. Numeric fields become JSON numbers and the rest strings.
+    pub fn info_json(&self) -> String {
+        let mut json = String::from("{");
+        let default_sections = SECTIONS.iter().filter(|(_, default)| *default);
+        for (i, (section, _)) in default_sections.enumerate() {
+            let mut text = String::new();
+            self.write_info_section(section, &mut text);
+            if i > 0 {
+                json.push(',');
+            }
+            write!(json, "{}:{{", json_string(section)).unwrap();
+            let fields = text.lines().filter_map(|line| line.split_once(':'));
+            for (j, (name, value)) in fields.enumerate() {
+                if j > 0 {
+                    json.push(',');
+                }
+                write!(json, "{}:{}", json_string(name), json_value(value)).unwrap();
+            }
+            json.push('}');
+        }
+        json.push('}');
+        json
+    }
+
+    fn write_info_section(&self, section: &str, out: &mut String) {
+        let config = self.config.read().unwrap();
+        match section {
+            "server" => {
+                let uptime = self.stats.uptime_in_seconds();
+                out.push_str("# Server\r\n");
+                info_field(out, "redis_version", env!("CARGO_PKG_VERSION"));
+                info_field(out, "redis_mode", "standalone");
+                info_field(out, "os", std::env::consts::OS);
+                info_field(out, "arch_bits", usize::BITS);
+                info_field(out, "process_id", std::process::id());
+                info_field(out, "tcp_port", config.port);
+                info_field(out, "rng_seed", self.seed);
+                info_field(out, "uptime_in_seconds", uptime);
+                info_field(out, "uptime_in_days", uptime / 86400);
+                info_field(out, "hz", config.hz);
+                let config_file = config
+                    .path
+                    .as_ref()
+                    .map(|path| path.display().to_string())
+                    .unwrap_or_default();
+                info_field(out, "config_file", config_file);
+            }
+            "clients" => {
+                out.push_str("# Clients\r\n");
+                info_field(out, "connected_clients", self.clients.len());
+                info_field(out, "blocked_clients", 0);
+            }
+            "memory" => {
+                // With jemalloc the allocator knows what is really in use; otherwise fall back
+                // to the dataset estimate.
+                let allocator_stats = allocator::stats();
+                let used_memory = allocator_stats.map_or(self.used_memory, |stats| stats.allocated);
+                // The peak is tracked for the dataset estimate, which the allocator total
+                // includes.
+                let used_memory_peak = self.used_memory_peak.max(used_memory);
+                let rss = resident_set_size();
+                out.push_str("# Memory\r\n");
+                info_field(out, "used_memory", used_memory);
+                info_field(out, "used_memory_human", bytes_to_human(used_memory));
+                info_field(out, "used_memory_rss", rss);
+                info_field(out, "used_memory_rss_human", bytes_to_human(rss));
+                info_field(out, "used_memory_peak", used_memory_peak);
+                info_field(
+                    out,
+                    "used_memory_peak_human",
+                    bytes_to_human(used_memory_peak),
+                );
+                info_field(out, "used_memory_dataset", self.used_memory);
+                if let Some(stats) = allocator_stats {
+                    info_field(out, "allocator_allocated", stats.allocated);
+                    info_field(out, "allocator_active", stats.active);
+                    info_field(out, "allocator_resident", stats.resident);
+                    info_field(
+                        out,
+                        "allocator_frag_ratio",
+                        format!("{:.2}", ratio(stats.active, stats.allocated)),
+                    );
+                    info_field(
+                        out,
+                        "allocator_rss_ratio",
+                        format!("{:.2}", ratio(stats.resident, stats.active)),
+                    );
+                }
+                info_field(
+                    out,
+                    "mem_fragmentation_ratio",
+                    format!("{:.2}", ratio(rss, used_memory)),
+                );
+                info_field(out, "mem_allocator", allocator::name());
+                info_field(out, "maxmemory", config.maxmemory);
+                info_field(out, "maxmemory_human", bytes_to_human(config.maxmemory));
+                info_field(out, "maxmemory_policy", config.maxmemory_policy);
+                info_field(
+                    out,
+                    "lazyfree_pending_objects",
+                    self.lazyfree.pending_objects(),
+                );
+                for (name, value) in self.db.info_fields() {
+                    info_field(out, name, value);
+                }
+            }
+            "persistence" => {
+                out.push_str("# Persistence\r\n");
+                info_field(out, "loading", u8::from(self.loading.is_some()));
+                if let Some(loading) = &self.loading {
+                    info_field(out, "loading_start_time", loading.start_time());
+                    info_field(out, "loading_total_bytes", loading.total_bytes());
+                    info_field(out, "loading_loaded_bytes", loading.loaded_bytes());
+                    info_field(
+                        out,
+                        "loading_loaded_perc",
+                        format!("{:.2}", loading.loaded_perc()),
+                    );
+                    info_field(out, "loading_eta_seconds", loading.eta_seconds());
+                }
+                info_field(out, "rdb_changes_since_last_save", self.dirty);
+                let last_save = self
+                    .last_save
+                    .duration_since(UNIX_EPOCH)
+                    .unwrap_or_default()
+                    .as_secs();
+                info_field(out, "rdb_last_save_time", last_save);
+                info_field(out, "rdb_bgsave_in_progress", 0);
+                info_field(out, "aof_enabled", 0);
+            }
+            "stats" => {
+                out.push_str("# Stats\r\n");
+                info_field(
+                    out,
+                    "total_connections_received",
+                    self.stats.total_connections_received,
+                );
+                info_field(
+                    out,
+                    "total_commands_processed",
+                    self.stats.total_commands_processed,
+                );
+                info_field(
+                    out,
+                    "instantaneous_ops_per_sec",
+                    self.stats.instantaneous_ops_per_sec(),
+                );
+                info_field(out, "rejected_connections", self.stats.rejected_connections);
+                info_field(out, "total_error_replies", self.stats.total_error_replies);
+                info_field(out, "expired_keys", self.stats.expired_keys);
+                info_field(out, "evicted_keys", self.stats.evicted_keys);
+                info_field(out, "lazyfreed_objects", self.lazyfree.freed_objects());
+                info_field(out, "keyspace_hits", self.stats.keyspace_hits);
+                info_field(out, "keyspace_misses", self.stats.keyspace_misses);
+            }
+            "replication" => {
+                out.push_str("# Replication\r\n");
+                info_field(out, "role", "master");
+                info_field(out, "connected_slaves", 0);
+                info_field(out, "master_replid", &self.replid);
+                info_field(out, "master_replid2", "0".repeat(40));
+                info_field(out, "master_repl_offset", 0);
+                info_field(out, "second_repl_offset", -1);
+            }
+            "cpu" => {
+                let (sys, user) = cpu_usage();
+                out.push_str("# CPU\r\n");
+                info_field(out, "used_cpu_sys", format!("{sys:.6}"));
+                info_field(out, "used_cpu_user", format!("{user:.6}"));
+            }
+            "commandstats" => {
+                out.push_str("# Commandstats\r\n");
+                let mut commands: Vec<_> = self.stats.commands.iter().collect();
+                commands.sort_by_key(|(name, _)| *name);
+                for (name, stats) in commands {
+                    let value = format!(
+                        "calls={},usec={},usec_per_call={:.2},rejected_calls={},failed_calls={}",
+                        stats.calls,
+                        stats.usec,
+                        stats.usec_per_call(),
+                        stats.rejected_calls,
+                        stats.failed_calls
+                    );
+                    info_field(out, &format!("cmdstat_{name}"), value);
+                }
+            }
+            "errorstats" => {
+                out.push_str("# Errorstats\r\n");
+                let mut errors: Vec<_> = self.stats.errors.iter().collect();
+                errors.sort();
+                for (prefix, count) in errors {
+                    info_field(
+                        out,
+                        &format!("errorstat_{prefix}"),
+                        format!("count={count}"),
+                    );
+                }
+            }
+            "latencystats" => {
+                out.push_str("# Latencystats\r\n");
+                let mut histograms: Vec<_> = self.stats.latency.iter().collect();
+                histograms.sort_by_key(|(name, _)| *name);
+                for (name, histogram) in histograms {
+                    let value = LATENCY_PERCENTILES
+                        .iter()
+                        .map(|p| format!("p{p}={:.3}", histogram.percentile(*p) as f64))
+                        .collect::<Vec<_>>()
+                        .join(",");
+                    info_field(out, &format!("latency_percentiles_usec_{name}"), value);
+                }
+            }
+            "keyspace" => {
+                out.push_str("# Keyspace\r\n");
+                if !self.db.is_empty() {
+                    let keys = self.db.len();
+                    let expires = self.db.expires_len();
+                    let now = self.clock.now();
+                    let total_ttl: u128 = self
+                        .db
+                        .expires()
+                        .map(|(_, deadline)| deadline.saturating_duration_since(now).as_millis())
+                        .sum();
+                    let avg_ttl = if expires == 0 {
+                        0
+                    } else {
+                        total_ttl / expires as u128
+                    };
+                    info_field(
+                        out,
+                        "db0",
+                        format!("keys={keys},expires={expires},avg_ttl={avg_ttl}"),
+                    );
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+pub(super) fn info_field(out: &mut String, name: &str, value: impl std::fmt::Display) {
+    write!(out, "{name}:{value}\r\n").unwrap();
+}
+
+/// `value` as a JSON number if it is one, and as a string otherwise.
+fn json_value(value: &str) -> String {
+    let numeric = value
+        .bytes()
+        .all(|b| b.is_ascii_digit() || b == b'.' || b == b'-');
+    if numeric && value.parse::<f64>().is_ok() {
+        value.to_string()
+    } else {
+        json_string(value)
+    }
+}
+
+fn json_string(value: &str) -> String {
+    let mut out = String::with_capacity(value.len() + 2);
+    out.push('"');
+    for c in value.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            c if c.is_control() => write!(out, "\\u{:04x}", c as u32).unwrap(),
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+    out
+}
+
+/// `numerator / denominator`, or 0 when there is nothing to divide by.
+fn ratio(numerator: u64, denominator: u64) -> f64 {
+    if denominator == 0 {
+        0.0
+    } else {
+        numerator as f64 / denominator as f64
+    }
+}
+
+fn bytes_to_human(bytes: impl Into<u64>) -> String {
+    let bytes = bytes.into() as f64;
+    const UNITS: [(&str, f64); 3] = [
+        ("G", 1024.0 * 1024.0 * 1024.0),
+        ("M", 1024.0 * 1024.0),
+        ("K", 1024.0),
+    ];
+    for (suffix, size) in UNITS {
+        if bytes >= size {
+            return format!("{:.2}{suffix}", bytes / size);
+        }
+    }
+    format!("{bytes}B")
+}
+
+pub(super) fn resident_set_size() -> u64 {
+    // The second field of /proc/self/statm is the resident set size in pages.
+    std::fs::read_to_string("/proc/self/statm")
+        .ok()
+        .and_then(|statm| statm.split_whitespace().nth(1)?.parse::<u64>().ok())
+        .map_or(0, |pages| pages * page_size())
+}
+
+fn page_size() -> u64 {
+    // SAFETY: sysconf has no preconditions.
+    unsafe { libc::sysconf(libc::_SC_PAGESIZE) as u64 }
+}
+
+/// Returns the (system, user) CPU seconds consumed by the process.
+fn cpu_usage() -> (f64, f64) {
+    // SAFETY: getrusage only writes into the zeroed struct we hand it.
+    let usage = unsafe {
+        let mut usage: libc::rusage = std::mem::zeroed();
+        libc::getrusage(libc::RUSAGE_SELF, &mut usage);
+        usage
+    };
+    let seconds = |tv: libc::timeval| tv.tv_sec as f64 + tv.tv_usec as f64 / 1_000_000.0;
+    (seconds(usage.ru_stime), seconds(usage.ru_utime))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::handler::clock::{Clock, MockClock};
+    use crate::handler::RedisValue;
+    use std::collections::HashMap;
+    use std::sync::Arc;
+    use std::time::Duration;
+
+    fn info_sections(output: &RespData) -> Vec<String> {
+        let RespData::BulkString(text) = output else {
+            panic!("INFO did not return a bulk string: {:?}", output);
+        };
+        text.lines()
+            .filter_map(|line| line.strip_prefix("# "))
+            .map(|s| s.to_string())
+            .collect()
+    }
+
+    #[test]
+    fn test_info_sections() {
+        let mut handler = CommandHandler::from(HashMap::new());
+
+        let test_cases = [
+            (
+                "No arguments returns the default sections",
+                vec!["INFO"],
+                vec![
+                    "Server",
+                    "Clients",
+                    "Memory",
+                    "Persistence",
+                    "Stats",
+                    "Replication",
+                    "CPU",
+                    "Keyspace",
+                ],
+            ),
+            ("Single section", vec!["INFO", "memory"], vec!["Memory"]),
+            (
+                "Multiple sections are returned in canonical order",
+                vec!["INFO", "KEYSPACE", "server"],
+                vec!["Server", "Keyspace"],
+            ),
+            (
+                "Non-default sections are only returned when asked for",
+                vec!["INFO", "errorstats"],
+                vec!["Errorstats"],
+            ),
+            ("Unknown section", vec!["INFO", "nonsense"], vec![]),
+        ];
+
+        for (name, args, expected) in test_cases {
+            let input = RespData::array(args);
+            assert_eq!(info_sections(&handler.info(&input)), expected, "{}", name);
+        }
+    }
+
+    #[test]
+    fn test_info_keyspace() {
+        let mut handler = CommandHandler::from(HashMap::new());
+        handler
+            .db
+            .insert("key".to_string(), RedisValue::String("value".into()));
+
+        let result = handler.info(&RespData::Array(vec![
+            RespData::BulkString("INFO".to_string()),
+            RespData::BulkString("keyspace".to_string()),
+        ]));
+
+        assert_eq!(
+            result,
+            RespData::BulkString("# Keyspace\r\ndb0:keys=1,expires=0,avg_ttl=0\r\n".to_string())
+        );
+    }
+
+    #[test]
+    fn test_info_commandstats_and_errorstats() {
+        let mut handler = CommandHandler::from(HashMap::new());
+        let command =
+            |args: &[&str]| RespData::Array(args.iter().map(|arg| RespData::bulk(*arg)).collect());
+        handler.handle(&command(&["PING"]));
+        handler.handle(&command(&["SET", "x", "a"]));
+        handler.handle(&command(&["GET", "x"]));
+        handler.handle(&command(&["GET"]));
+        handler.handle(&command(&["INCR", "x"]));
+
+        let RespData::BulkString(result) = handler.info(&RespData::Array(vec![
+            RespData::BulkString("INFO".to_string()),
+            RespData::BulkString("commandstats".to_string()),
+            RespData::BulkString("errorstats".to_string()),
+        ])) else {
+            panic!("INFO did not return a bulk string");
+        };
+
+        let lines: Vec<&str> = result.lines().collect();
+        assert_eq!(lines[0], "# Commandstats");
+        assert!(lines[1].starts_with("cmdstat_get:calls=1,usec="));
+        assert!(lines[1].ends_with(",rejected_calls=1,failed_calls=0"));
+        assert!(lines[2].starts_with("cmdstat_incr:calls=1,usec="));
+        assert!(lines[2].ends_with(",rejected_calls=0,failed_calls=1"));
+        assert!(lines[3].starts_with("cmdstat_ping:calls=1,usec="));
+        assert!(lines[4].starts_with("cmdstat_set:calls=1,usec="));
+        assert_eq!(lines[6..], ["# Errorstats", "errorstat_ERR:count=2"]);
+    }
+
+    #[test]
+    fn test_info_latencystats() {
+        let mut handler = CommandHandler::from(HashMap::new());
+        handler.handle(&RespData::Array(vec![RespData::BulkString(
+            "PING".to_string(),
+        )]));
+
+        let RespData::BulkString(result) = handler.info(&RespData::Array(vec![
+            RespData::BulkString("INFO".to_string()),
+            RespData::BulkString("latencystats".to_string()),
+        ])) else {
+            panic!("INFO did not return a bulk string");
+        };
+
+        let lines: Vec<&str> = result.lines().collect();
+        assert_eq!(lines[0], "# Latencystats");
+        assert!(lines[1].starts_with("latency_percentiles_usec_ping:p50="));
+        assert!(lines[1].contains(",p99="));
+        assert!(lines[1].contains(",p99.9="));
+    }
+
+    #[test]
+    fn test_info_keyspace_expires() {
+        let clock = Arc::new(MockClock::new());
+        let mut handler = CommandHandler::from(HashMap::new());
+        handler.clock = clock.clone();
+        for key in ["a", "b"] {
+            handler
+                .db
+                .insert(key.to_string(), RedisValue::String("value".into()));
+        }
+        handler
+            .db
+            .set_expire("a", clock.now() + Duration::from_secs(10));
+        clock.advance(Duration::from_millis(2_500));
+
+        let RespData::BulkString(result) = handler.info(&RespData::Array(vec![
+            RespData::BulkString("INFO".to_string()),
+            RespData::BulkString("keyspace".to_string()),
+        ])) else {
+            panic!("INFO did not return a bulk string");
+        };
+
+        assert!(result.starts_with("# Keyspace\r\ndb0:keys=2,expires=1,avg_ttl="));
+        let avg_ttl: u64 = result
+            .trim_end()
+            .rsplit('=')
+            .next()
+            .unwrap()
+            .parse()
+            .unwrap();
+        assert_eq!(avg_ttl, 7_500);
+    }
+
+    #[test]
+    fn test_info_memory() {
+        let mut handler = CommandHandler::from(HashMap::new());
+        handler.handle(&RespData::Array(vec![
+            RespData::BulkString("SET".to_string()),
+            RespData::BulkString("key".to_string()),
+            RespData::BulkString("value".to_string()),
+        ]));
+
+        let RespData::BulkString(result) = handler.info(&RespData::Array(vec![
+            RespData::BulkString("INFO".to_string()),
+            RespData::BulkString("memory".to_string()),
+        ])) else {
+            panic!("INFO did not return a bulk string");
+        };
+        let field = |name: &str| {
+            result
+                .lines()
+                .find_map(|line| line.strip_prefix(&format!("{name}:")))
+                .unwrap_or_else(|| panic!("INFO memory has no {name}"))
+                .to_string()
+        };
+
+        assert_eq!(
+            field("used_memory_dataset"),
+            handler.used_memory.to_string()
+        );
+        assert_eq!(field("mem_allocator"), allocator::name());
+        assert!(field("mem_fragmentation_ratio").parse::<f64>().unwrap() > 0.0);
+        let used_memory: u64 = field("used_memory").parse().unwrap();
+        assert!(field("used_memory_peak").parse::<u64>().unwrap() >= used_memory);
+        assert_eq!(
+            result.contains("allocator_allocated:"),
+            cfg!(feature = "jemalloc")
+        );
+    }
+
+    #[test]
+    fn test_info_hits_and_misses() {
+        let mut handler = CommandHandler::from(HashMap::new());
+        handler
+            .db
+            .insert("key".to_string(), RedisValue::String("value".into()));
+        for key in ["key", "key", "missing"] {
+            handler.handle(&RespData::Array(vec![
+                RespData::BulkString("GET".to_string()),
+                RespData::BulkString(key.to_string()),
+            ]));
+        }
+
+        let RespData::BulkString(result) = handler.info(&RespData::Array(vec![
+            RespData::BulkString("INFO".to_string()),
+            RespData::BulkString("stats".to_string()),
+        ])) else {
+            panic!("INFO did not return a bulk string");
+        };
+
+        assert!(result.contains("keyspace_hits:2\r\n"));
+        assert!(result.contains("keyspace_misses:1\r\n"));
+    }
+
+    #[test]
+    fn test_info_json() {
+        let mut handler = CommandHandler::from(HashMap::new());
+        handler.handle(&RespData::Array(vec![
+            RespData::BulkString("SET".to_string()),
+            RespData::BulkString("key".to_string()),
+            RespData::BulkString("value".to_string()),
+        ]));
+        let json = handler.info_json();
+
+        assert!(
+            json.starts_with("{\"server\":{\"redis_version\":\""),
+            "{json}"
+        );
+        assert!(json.ends_with("\"keyspace\":{\"db0\":\"keys=1,expires=0,avg_ttl=0\"}}"));
+        assert!(json.contains("\"connected_clients\":0,"), "{json}");
+        assert!(!json.contains("\"commandstats\""), "{json}");
+    }
+
+    #[test]
+    fn test_json_value() {
+        let test_cases = [
+            ("Integer", "42", "42"),
+            ("Negative", "-1", "-1"),
+            ("Float", "0.75", "0.75"),
+            ("Version", "0.1.0", "\"0.1.0\""),
+            ("Word", "master", "\"master\""),
+            ("Human size", "1.00M", "\"1.00M\""),
+            ("Quote and backslash", "a\"b\\c", "\"a\\\"b\\\\c\""),
+            ("Control character", "a\tb", "\"a\\u0009b\""),
+        ];
+
+        for (name, input, expected) in test_cases {
+            assert_eq!(json_value(input), expected, "{}", name);
+        }
+    }
+
+    #[test]
+    fn test_bytes_to_human() {
+        assert_eq!(bytes_to_human(512u64), "512B");
+        assert_eq!(bytes_to_human(2048u64), "2.00K");
+        assert_eq!(bytes_to_human(3 * 1024 * 1024u64), "3.00M");
+    }
+}
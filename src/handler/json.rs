@@ -0,0 +1,464 @@
+//! The JSON data type: documents stored whole under a key, and the RedisJSON commands that
+//! read and modify parts of them by path.
+//!
+//! With a JSONPath, commands act on every match and reply with one result per match. With a
+//! legacy path they act on the matches too, but reply for a single one and fail if there is
+//! none, the way RedisJSON does.
+
+use super::command::SetCondition;
+use super::errors;
+use super::events::KeyEventKind;
+use super::{CommandHandler, RedisValue};
+use crate::json::{Json, Number, Path};
+use crate::resp::RespData;
+
+fn missing_key() -> RespData {
+    RespData::Error("could not perform this operation on a key that doesn't exist".to_string())
+}
+
+fn missing_path(path: &Path) -> RespData {
+    RespData::Error(format!("Path '{path}' does not exist"))
+}
+
+fn wrong_path_type(expected: &str, found: &Json) -> RespData {
+    RespData::Error(format!(
+        "-WRONGTYPE wrong type of path value - expected {expected} but found {}",
+        found.type_name()
+    ))
+}
+
+/// The text of what `path` selects in `doc`: the array of every match for a JSONPath, or
+/// the first match for a legacy path.
+fn select_text(doc: &Json, path: &Path, as_jsonpath: bool) -> Result<String, RespData> {
+    let mut values = path
+        .select(doc)
+        .into_iter()
+        .map(|pointer| doc.get(&pointer).unwrap().to_string());
+    if as_jsonpath {
+        return Ok(format!("[{}]", values.collect::<Vec<_>>().join(",")));
+    }
+    values.next().ok_or_else(|| missing_path(path))
+}
+
+impl CommandHandler {
+    /// The document at `key` for reading, or the error reply if it holds another type.
+    fn lookup_json(&mut self, key: &str) -> Result<Option<&Json>, RespData> {
+        match self.lookup_key_read(key) {
+            Some(RedisValue::Json(doc)) => Ok(Some(doc)),
+            Some(_) => Err(errors::wrong_type()),
+            None => Ok(None),
+        }
+    }
+
+    /// Applies `modify` to the document at `key`, which returns the reply and whether it
+    /// changed the document.
+    fn modify_json(
+        &mut self,
+        key: &str,
+        modify: impl FnOnce(&mut Json) -> (RespData, bool),
+    ) -> RespData {
+        self.expire_if_needed(key);
+        let before = self.key_memory(key);
+        let (reply, changed) = match self.db.get_mut(key) {
+            Some(RedisValue::Json(doc)) => modify(doc),
+            Some(_) => return errors::wrong_type(),
+            None => return missing_key(),
+        };
+        self.touch_key(key);
+        if changed {
+            self.account_key_change(key, before);
+            self.notify_key_event(KeyEventKind::Set, key);
+        }
+        reply
+    }
+
+    /// JSON.SET key path value [NX|XX]: sets every match of `path`, or adds the member it
+    /// names to the objects matching the rest of it. A new key can only be set at the root.
+    pub(super) fn json_set(
+        &mut self,
+        key: &str,
+        path: Path,
+        value: Json,
+        condition: Option<SetCondition>,
+    ) -> RespData {
+        self.expire_if_needed(key);
+        match self.db.get(key) {
+            Some(RedisValue::Json(_)) => {}
+            Some(_) => return errors::wrong_type(),
+            None if !path.is_root() => {
+                return RespData::Error("new objects must be created at the root".to_string())
+            }
+            None if condition == Some(SetCondition::Xx) => return RespData::Null,
+            None => {
+                let before = self.key_memory(key);
+                self.db.insert(key.to_string(), RedisValue::Json(value));
+                self.touch_key(key);
+                self.account_key_change(key, before);
+                self.notify_key_event(KeyEventKind::Set, key);
+                return RespData::SimpleString("OK".to_string());
+            }
+        }
+
+        self.modify_json(key, |doc| {
+            let matches = path.select(doc);
+            match condition {
+                Some(SetCondition::Nx) if !matches.is_empty() => return (RespData::Null, false),
+                Some(SetCondition::Xx) if matches.is_empty() => return (RespData::Null, false),
+                _ => {}
+            }
+            if !matches.is_empty() {
+                for pointer in matches {
+                    // A match inside an earlier one may be gone once that one is replaced.
+                    if let Some(target) = doc.get_mut(&pointer) {
+                        *target = value.clone();
+                    }
+                }
+                return (RespData::SimpleString("OK".to_string()), true);
+            }
+            match path.parents(doc) {
+                Some((parents, name)) if !parents.is_empty() => {
+                    for pointer in parents {
+                        if let Some(Json::Object(members)) = doc.get_mut(&pointer) {
+                            members.push((name.to_string(), value.clone()));
+                        }
+                    }
+                    (RespData::SimpleString("OK".to_string()), true)
+                }
+                _ => (RespData::Null, false),
+            }
+        })
+    }
+
+    /// JSON.GET key [path ...]: the document, or what the paths select in it. With several
+    /// paths, an object from each path to its result.
+    pub(super) fn json_get(&mut self, key: &str, paths: &[Path]) -> RespData {
+        let doc = match self.lookup_json(key) {
+            Ok(Some(doc)) => doc,
+            Ok(None) => return RespData::Null,
+            Err(e) => return e,
+        };
+        let as_jsonpath = paths.iter().any(|path| !path.is_legacy());
+        let text = match paths {
+            [] => Ok(doc.to_string()),
+            [path] => select_text(doc, path, as_jsonpath),
+            paths => paths
+                .iter()
+                .map(|path| {
+                    let name = Json::String(path.to_string());
+                    select_text(doc, path, as_jsonpath).map(|text| format!("{name}:{text}"))
+                })
+                .collect::<Result<Vec<_>, _>>()
+                .map(|members| format!("{{{}}}", members.join(","))),
+        };
+        text.map_or_else(|e| e, RespData::BulkString)
+    }
+
+    /// JSON.DEL key [path]: deletes every match of `path`, and the key itself for the root.
+    pub(super) fn json_del(&mut self, key: &str, path: &Path) -> RespData {
+        self.expire_if_needed(key);
+        match self.db.get(key) {
+            Some(RedisValue::Json(_)) => {}
+            Some(_) => return errors::wrong_type(),
+            None => return RespData::Integer(0),
+        }
+        if path.is_root() {
+            self.delete_key(key);
+            self.notify_key_event(KeyEventKind::Deleted, key);
+            return RespData::Integer(1);
+        }
+
+        self.modify_json(key, |doc| {
+            let mut pointers = path.select(doc);
+            // Removing from the end first keeps the array indexes of the rest valid.
+            pointers.sort();
+            pointers.dedup();
+            let deleted = pointers
+                .iter()
+                .rev()
+                .filter(|pointer| doc.remove(pointer).is_some())
+                .count();
+            (RespData::Integer(deleted as i64), deleted > 0)
+        })
+    }
+
+    /// JSON.TYPE key [path]: the type of the value at `path`.
+    pub(super) fn json_type(&mut self, key: &str, path: &Path) -> RespData {
+        let doc = match self.lookup_json(key) {
+            Ok(Some(doc)) => doc,
+            Ok(None) => return RespData::Null,
+            Err(e) => return e,
+        };
+        let mut types = path
+            .select(doc)
+            .into_iter()
+            .map(|pointer| doc.get(&pointer).unwrap().type_name());
+        if path.is_legacy() {
+            return types.next().map_or(RespData::Null, |type_name| {
+                RespData::SimpleString(type_name.to_string())
+            });
+        }
+        RespData::array(types)
+    }
+
+    /// JSON.NUMINCRBY key path number: adds `increment` to every number matching `path`,
+    /// replying with the new values as JSON.
+    pub(super) fn json_numincrby(&mut self, key: &str, path: &Path, increment: Number) -> RespData {
+        self.modify_json(key, |doc| {
+            let pointers = path.select(doc);
+            let mut results = Vec::with_capacity(pointers.len());
+            for pointer in &pointers {
+                let result = match doc.get(pointer).unwrap() {
+                    Json::Number(n) => match n.checked_add(increment) {
+                        Some(sum) => Json::Number(sum),
+                        None => {
+                            return (RespData::Error("result is not a number".to_string()), false)
+                        }
+                    },
+                    other if path.is_legacy() => {
+                        return (wrong_path_type("a number", other), false)
+                    }
+                    _ => Json::Null,
+                };
+                results.push(result);
+            }
+            if path.is_legacy() && results.is_empty() {
+                return (missing_path(path), false);
+            }
+
+            // Nothing is changed until every match is known to be a number.
+            let mut changed = false;
+            for (pointer, result) in pointers.iter().zip(&results) {
+                if let Json::Number(_) = result {
+                    *doc.get_mut(pointer).unwrap() = result.clone();
+                    changed = true;
+                }
+            }
+            let reply = if path.is_legacy() {
+                results.pop().unwrap().to_string()
+            } else {
+                Json::Array(results).to_string()
+            };
+            (RespData::BulkString(reply), changed)
+        })
+    }
+
+    /// JSON.ARRAPPEND key path value [value ...]: appends the values to every array matching
+    /// `path`, replying with their new lengths.
+    pub(super) fn json_arrappend(&mut self, key: &str, path: &Path, values: &[Json]) -> RespData {
+        self.modify_json(key, |doc| {
+            let pointers = path.select(doc);
+            let mut lengths = Vec::with_capacity(pointers.len());
+            for pointer in &pointers {
+                match doc.get_mut(pointer).unwrap() {
+                    Json::Array(items) => {
+                        items.extend_from_slice(values);
+                        lengths.push(RespData::Integer(items.len() as i64));
+                    }
+                    other if path.is_legacy() => {
+                        return (wrong_path_type("an array", other), !lengths.is_empty())
+                    }
+                    _ => lengths.push(RespData::Null),
+                }
+            }
+            let changed = lengths.iter().any(|len| *len != RespData::Null);
+            if !path.is_legacy() {
+                return (RespData::Array(lengths), changed);
+            }
+            match lengths.pop() {
+                Some(len) => (len, changed),
+                None => (missing_path(path), false),
+            }
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+
+    fn command(args: &[&str]) -> RespData {
+        RespData::array(args.iter().copied())
+    }
+
+    fn error(message: &str) -> RespData {
+        RespData::Error(message.to_string())
+    }
+
+    #[test]
+    fn test_json_commands() {
+        let mut handler = CommandHandler::from(HashMap::new());
+        handler.handle(&command(&["SET", "string", "x"]));
+
+        let test_cases = [
+            (
+                "Create a document",
+                command(&[
+                    "JSON.SET",
+                    "doc",
+                    "$",
+                    r#"{"name":"ada","tags":["math"],"stats":{"visits":1,"score":1.5}}"#,
+                ]),
+                RespData::SimpleString("OK".to_string()),
+            ),
+            (
+                "New key below the root",
+                command(&["JSON.SET", "other", "$.a", "1"]),
+                error("new objects must be created at the root"),
+            ),
+            (
+                "Invalid JSON",
+                command(&["JSON.SET", "other", "$", "{nope}"]),
+                error("invalid JSON: expected member name at offset 1"),
+            ),
+            (
+                "Invalid path",
+                command(&["JSON.GET", "doc", "$["]),
+                error("invalid JSON path '$['"),
+            ),
+            (
+                "Get the whole document",
+                command(&["JSON.GET", "doc"]),
+                RespData::bulk(
+                    r#"{"name":"ada","tags":["math"],"stats":{"visits":1,"score":1.5}}"#,
+                ),
+            ),
+            (
+                "Get with a JSONPath",
+                command(&["JSON.GET", "doc", "$.stats.*"]),
+                RespData::bulk("[1,1.5]"),
+            ),
+            (
+                "Get with a legacy path",
+                command(&["JSON.GET", "doc", ".name"]),
+                RespData::bulk(r#""ada""#),
+            ),
+            (
+                "Get several paths",
+                command(&["JSON.GET", "doc", ".name", ".stats.visits"]),
+                RespData::bulk(r#"{".name":"ada",".stats.visits":1}"#),
+            ),
+            (
+                "Get a missing legacy path",
+                command(&["JSON.GET", "doc", ".missing"]),
+                error("Path '.missing' does not exist"),
+            ),
+            (
+                "Get a missing key",
+                command(&["JSON.GET", "missing"]),
+                RespData::Null,
+            ),
+            (
+                "Get another type",
+                command(&["JSON.GET", "string"]),
+                errors::wrong_type(),
+            ),
+            (
+                "Set an existing path",
+                command(&["JSON.SET", "doc", "$.name", r#""grace""#]),
+                RespData::SimpleString("OK".to_string()),
+            ),
+            (
+                "Add a member",
+                command(&["JSON.SET", "doc", "$.stats.level", "3"]),
+                RespData::SimpleString("OK".to_string()),
+            ),
+            (
+                "NX on an existing path",
+                command(&["JSON.SET", "doc", "$.name", "1", "NX"]),
+                RespData::Null,
+            ),
+            (
+                "XX on a missing path",
+                command(&["JSON.SET", "doc", "$.nickname", "1", "XX"]),
+                RespData::Null,
+            ),
+            (
+                "Parent missing",
+                command(&["JSON.SET", "doc", "$.a.b", "1"]),
+                RespData::Null,
+            ),
+            (
+                "Increment with a JSONPath",
+                command(&["JSON.NUMINCRBY", "doc", "$.stats.*", "2"]),
+                RespData::bulk("[3,3.5,5]"),
+            ),
+            (
+                "Increment with a legacy path",
+                command(&["JSON.NUMINCRBY", "doc", ".stats.visits", "0.5"]),
+                RespData::bulk("3.5"),
+            ),
+            (
+                "Increment a string",
+                command(&["JSON.NUMINCRBY", "doc", ".name", "1"]),
+                error("-WRONGTYPE wrong type of path value - expected a number but found string"),
+            ),
+            (
+                "Increment by something else",
+                command(&["JSON.NUMINCRBY", "doc", ".stats.visits", "\"1\""]),
+                error("increment must be a number"),
+            ),
+            (
+                "Increment a missing key",
+                command(&["JSON.NUMINCRBY", "missing", "$", "1"]),
+                error("could not perform this operation on a key that doesn't exist"),
+            ),
+            (
+                "Append with a JSONPath",
+                command(&["JSON.ARRAPPEND", "doc", "$..tags", r#""code""#, "1"]),
+                RespData::Array(vec![RespData::Integer(3)]),
+            ),
+            (
+                "Append to a non-array",
+                command(&["JSON.ARRAPPEND", "doc", "$.name", "1"]),
+                RespData::Array(vec![RespData::Null]),
+            ),
+            (
+                "Append with a legacy path to a non-array",
+                command(&["JSON.ARRAPPEND", "doc", ".name", "1"]),
+                error("-WRONGTYPE wrong type of path value - expected an array but found string"),
+            ),
+            (
+                "Types",
+                command(&["JSON.TYPE", "doc", "$.tags[*]"]),
+                RespData::array(["string", "string", "integer"]),
+            ),
+            (
+                "Type of the root",
+                command(&["JSON.TYPE", "doc"]),
+                RespData::SimpleString("object".to_string()),
+            ),
+            (
+                "Delete matches",
+                command(&["JSON.DEL", "doc", "$.tags[*]"]),
+                RespData::Integer(3),
+            ),
+            (
+                "Delete nothing",
+                command(&["JSON.DEL", "doc", "$.missing"]),
+                RespData::Integer(0),
+            ),
+            (
+                "Document after the changes",
+                command(&["JSON.GET", "doc"]),
+                RespData::bulk(
+                    r#"{"name":"grace","tags":[],"stats":{"visits":3.5,"score":3.5,"level":5}}"#,
+                ),
+            ),
+            (
+                "Delete the root",
+                command(&["JSON.DEL", "doc"]),
+                RespData::Integer(1),
+            ),
+            (
+                "Key is gone",
+                command(&["TYPE", "doc"]),
+                RespData::SimpleString("none".to_string()),
+            ),
+        ];
+
+        for (name, input, expected) in test_cases {
+            assert_eq!(handler.handle(&input), expected, "{}", name);
+        }
+    }
+}
@@ -0,0 +1,325 @@
+use super::command::{FlushMode, ScanOptions};
+use super::events::KeyEventKind;
+use super::CommandHandler;
+use crate::glob::glob_match;
+use crate::resp::RespData;
+use std::mem;
+
+/// Keys SCAN aims to return per call when no COUNT is given.
+const DEFAULT_SCAN_COUNT: usize = 10;
+/// Buckets SCAN visits per key asked for before giving up on filling the reply, so a sparse
+/// table doesn't make a single call walk all of it.
+const SCAN_BUCKETS_PER_KEY: usize = 10;
+
+impl CommandHandler {
+    /// DEL key [key ...]. Frees the values in the background if `lazyfree-lazy-user-del` is on.
+    pub(super) fn del(&mut self, keys: &[String]) -> RespData {
+        let lazy = self.config.read().unwrap().lazyfree_lazy_user_del;
+        self.delete_keys(keys, lazy)
+    }
+
+    /// UNLINK key [key ...]: like DEL, but large values are freed in the background.
+    pub(super) fn unlink(&mut self, keys: &[String]) -> RespData {
+        self.delete_keys(keys, true)
+    }
+
+    fn delete_keys(&mut self, keys: &[String], lazy: bool) -> RespData {
+        let mut deleted = 0;
+        for key in keys {
+            if self.expire_if_needed(key) {
+                continue;
+            }
+            if let Some(value) = self.delete_key(key) {
+                self.notify_key_event(KeyEventKind::Deleted, key);
+                deleted += 1;
+                if lazy {
+                    self.lazyfree.free_value(value);
+                }
+            }
+        }
+        RespData::Integer(deleted)
+    }
+
+    /// SCAN cursor [MATCH pattern] [COUNT count] [TYPE type]: a batch of keys and the cursor
+    /// to pass to the next call, which is 0 once the whole keyspace has been visited.
+    pub(super) fn scan(&mut self, mut cursor: u64, options: ScanOptions) -> RespData {
+        let pattern = options.pattern.as_deref();
+        let count = options.count.unwrap_or(DEFAULT_SCAN_COUNT);
+        let type_filter = options.type_name;
+        let mut keys = Vec::new();
+        let mut buckets_left = count * SCAN_BUCKETS_PER_KEY;
+        loop {
+            cursor = self.db.scan(cursor, &mut |key, value| {
+                if pattern.is_none_or(|pattern| glob_match(pattern, key))
+                    && type_filter
+                        .as_deref()
+                        .is_none_or(|type_name| value.type_name() == type_name)
+                {
+                    keys.push(key.clone());
+                }
+            });
+            buckets_left -= 1;
+            if cursor == 0 || keys.len() >= count || buckets_left == 0 {
+                break;
+            }
+        }
+        // Keys past their TTL are only removed now, so the scan above didn't skip any live ones.
+        keys.retain(|key| !self.expire_if_needed(key));
+
+        RespData::Array(vec![
+            RespData::BulkString(cursor.to_string()),
+            RespData::Array(keys.into_iter().map(RespData::BulkString).collect()),
+        ])
+    }
+
+    /// TYPE key: the type of the value at `key`, or `none` if there is no such key.
+    pub(super) fn type_of(&mut self, key: &str) -> RespData {
+        self.expire_if_needed(key);
+        let type_name = self.db.get(key).map_or("none", |value| value.type_name());
+        RespData::SimpleString(type_name.to_string())
+    }
+
+    /// FLUSHDB and FLUSHALL [ASYNC|SYNC]: removes every key, freeing them in the background with
+    /// ASYNC. There is a single database, so the two are the same.
+    pub(super) fn flush(&mut self, mode: Option<FlushMode>) -> RespData {
+        let lazy = match mode {
+            Some(mode) => mode == FlushMode::Async,
+            None => self.config.read().unwrap().lazyfree_lazy_user_flush,
+        };
+
+        let keys = self.db.len() as u64;
+        let garbage = (
+            self.db.take(),
+            mem::take(&mut self.access_times),
+            mem::take(&mut self.lfu_counters),
+        );
+        self.used_memory = 0;
+        self.invalidate_all();
+        self.rebuild_search_indexes();
+        if lazy {
+            self.lazyfree.free_later(Box::new(garbage), keys);
+        }
+        RespData::SimpleString("OK".to_string())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::handler::clock::MockClock;
+    use std::collections::HashMap;
+    use std::sync::Arc;
+    use std::thread;
+    use std::time::{Duration, Instant};
+
+    fn command(args: &[&str]) -> RespData {
+        RespData::array(args.iter().copied())
+    }
+
+    /// Runs SCAN to completion with the given options, returning every key it replied with.
+    fn scan_all(handler: &mut CommandHandler, options: &[&str]) -> Vec<String> {
+        let mut keys = Vec::new();
+        let mut cursor = "0".to_string();
+        loop {
+            let mut args = vec!["SCAN", &cursor];
+            args.extend(options);
+            let RespData::Array(reply) = handler.handle(&command(&args)) else {
+                panic!("SCAN did not reply with an array");
+            };
+            let [RespData::BulkString(next), RespData::Array(batch)] = reply.as_slice() else {
+                panic!("unexpected SCAN reply {reply:?}");
+            };
+            for key in batch {
+                let RespData::BulkString(key) = key else {
+                    panic!("SCAN replied with a non-string key");
+                };
+                keys.push(key.clone());
+            }
+            if next == "0" {
+                keys.sort();
+                return keys;
+            }
+            cursor = next.clone();
+        }
+    }
+
+    fn handler_with_big_hash() -> CommandHandler {
+        let mut handler = CommandHandler::from(HashMap::new());
+        let fields: Vec<String> = (0..200).map(|i| i.to_string()).collect();
+        let mut hset = vec!["HSET", "big"];
+        for field in &fields {
+            hset.extend([field.as_str(), "v"]);
+        }
+        handler.handle(&command(&hset));
+        handler.handle(&command(&["SET", "a", "1"]));
+        handler.handle(&command(&["SET", "b", "2"]));
+        handler
+    }
+
+    fn wait_for_lazyfree(handler: &CommandHandler) {
+        let deadline = Instant::now() + Duration::from_secs(5);
+        while handler.lazyfree.pending_objects() > 0 {
+            assert!(Instant::now() < deadline, "lazyfree thread is stuck");
+            thread::sleep(Duration::from_millis(1));
+        }
+    }
+
+    #[test]
+    fn test_scan() {
+        let mut handler = handler_with_big_hash();
+        for i in 0..100 {
+            handler.handle(&command(&["SET", &format!("key:{i:03}"), "v"]));
+        }
+        handler.handle(&command(&["SET", "gone", "v"]));
+        handler
+            .db
+            .set_expire("gone", Instant::now() - Duration::from_secs(1));
+
+        let every_key: Vec<String> = (0..100).map(|i| format!("key:{i:03}")).collect();
+        let test_cases = [
+            (
+                "Every key exactly once",
+                vec![],
+                [
+                    vec!["a".to_string(), "b".to_string(), "big".to_string()],
+                    every_key.clone(),
+                ]
+                .concat(),
+            ),
+            ("Match", vec!["MATCH", "key:09*"], every_key[90..].to_vec()),
+            ("Type", vec!["TYPE", "hash"], vec!["big".to_string()]),
+            (
+                "Large count",
+                vec!["COUNT", "1000", "MATCH", "?"],
+                vec!["a".to_string(), "b".to_string()],
+            ),
+        ];
+
+        for (name, options, expected) in test_cases {
+            assert_eq!(scan_all(&mut handler, &options), expected, "{}", name);
+        }
+        assert!(!handler.db.contains_key("gone"));
+
+        let test_cases = [
+            (
+                "Bad cursor",
+                command(&["SCAN", "abc"]),
+                RespData::Error("invalid cursor".to_string()),
+            ),
+            (
+                "Zero count",
+                command(&["SCAN", "0", "COUNT", "0"]),
+                RespData::Error("syntax error".to_string()),
+            ),
+            (
+                "Option without a value",
+                command(&["SCAN", "0", "MATCH"]),
+                RespData::Error("syntax error".to_string()),
+            ),
+        ];
+
+        for (name, input, expected) in test_cases {
+            assert_eq!(handler.handle(&input), expected, "{}", name);
+        }
+    }
+
+    #[test]
+    fn test_del_and_unlink() {
+        let mut handler = handler_with_big_hash();
+
+        let test_cases = [
+            (
+                "DEL existing and missing keys",
+                command(&["DEL", "a", "missing"]),
+                RespData::Integer(1),
+            ),
+            (
+                "UNLINK a big value",
+                command(&["UNLINK", "big", "b"]),
+                RespData::Integer(2),
+            ),
+            (
+                "Nothing left",
+                command(&["DEL", "a", "b"]),
+                RespData::Integer(0),
+            ),
+            (
+                "Missing key",
+                command(&["UNLINK"]),
+                RespData::Error("wrong number of arguments for 'unlink' command".to_string()),
+            ),
+        ];
+
+        for (name, input, expected) in test_cases {
+            assert_eq!(handler.handle(&input), expected, "{}", name);
+        }
+        assert!(handler.db.is_empty());
+        assert_eq!(handler.used_memory, 0);
+        wait_for_lazyfree(&handler);
+        // Only the big hash was worth freeing in the background.
+        assert_eq!(handler.lazyfree.freed_objects(), 1);
+    }
+
+    #[test]
+    fn test_type() {
+        let clock = Arc::new(MockClock::new());
+        let mut handler = CommandHandler::from(HashMap::new());
+        handler.clock = clock.clone();
+        handler.handle(&command(&["SET", "s", "value"]));
+        handler.handle(&command(&["HSET", "h", "f", "v"]));
+        handler.handle(&command(&["SET", "gone", "value", "PX", "1"]));
+        clock.advance(Duration::from_millis(5));
+
+        let test_cases = [
+            ("String", command(&["TYPE", "s"]), "string"),
+            ("Hash", command(&["TYPE", "h"]), "hash"),
+            ("Missing key", command(&["TYPE", "missing"]), "none"),
+            ("Expired key", command(&["TYPE", "gone"]), "none"),
+        ];
+
+        for (name, input, expected) in test_cases {
+            assert_eq!(
+                handler.handle(&input),
+                RespData::SimpleString(expected.to_string()),
+                "{}",
+                name
+            );
+        }
+        assert_eq!(
+            handler.handle(&command(&["TYPE"])),
+            RespData::Error("wrong number of arguments for 'type' command".to_string())
+        );
+    }
+
+    #[test]
+    fn test_flush() {
+        let test_cases = [
+            ("Sync by default", command(&["FLUSHALL"]), 0),
+            ("Explicit sync", command(&["FLUSHDB", "SYNC"]), 0),
+            ("Async", command(&["FLUSHALL", "async"]), 3),
+        ];
+
+        for (name, input, expected_freed) in test_cases {
+            let mut handler = handler_with_big_hash();
+            handler.handle(&command(&["EXPIRE", "a", "100"]));
+            assert_eq!(
+                handler.handle(&input),
+                RespData::SimpleString("OK".to_string()),
+                "{}",
+                name
+            );
+            assert!(handler.db.is_empty(), "{}", name);
+            assert!(handler.db.expires_len() == 0, "{}", name);
+            assert_eq!(handler.used_memory, 0, "{}", name);
+            wait_for_lazyfree(&handler);
+            assert_eq!(handler.lazyfree.freed_objects(), expected_freed, "{}", name);
+        }
+
+        let mut handler = handler_with_big_hash();
+        assert_eq!(
+            handler.handle(&command(&["FLUSHALL", "LATER"])),
+            RespData::Error("syntax error".to_string())
+        );
+        assert_eq!(handler.db.len(), 3);
+    }
+}
@@ -0,0 +1,391 @@
+use super::errors;
+use super::CommandHandler;
+use crate::resp::RespData;
+use std::collections::{HashMap, VecDeque};
+use std::fmt::Write;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// Number of samples kept per event, matching Redis' LATENCY_TS_LEN.
+const HISTORY_LEN: usize = 160;
+
+/// Latency spikes recorded for one event class.
+#[derive(Default)]
+struct LatencyEvent {
+    /// `(unix seconds, milliseconds)` samples, oldest first.
+    samples: VecDeque<(u64, u64)>,
+    max: u64,
+}
+
+/// Records events (slow commands, expire cycles, ...) that took longer than
+/// `latency-monitor-threshold`, for LATENCY LATEST/HISTORY/DOCTOR.
+#[derive(Default)]
+pub struct LatencyMonitor {
+    events: HashMap<String, LatencyEvent>,
+}
+
+impl LatencyMonitor {
+    /// Records `duration` under `event` if it reaches `threshold_ms`. A zero threshold disables
+    /// monitoring.
+    pub fn sample(&mut self, event: &str, duration: Duration, threshold_ms: u64) {
+        let ms = duration.as_millis() as u64;
+        if threshold_ms == 0 || ms < threshold_ms {
+            return;
+        }
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_secs();
+
+        let event = self.events.entry(event.to_string()).or_default();
+        event.max = event.max.max(ms);
+        // Samples in the same second are merged, keeping the worst one.
+        match event.samples.back_mut() {
+            Some((time, latency)) if *time == now => *latency = (*latency).max(ms),
+            _ => {
+                if event.samples.len() == HISTORY_LEN {
+                    event.samples.pop_front();
+                }
+                event.samples.push_back((now, ms));
+            }
+        }
+    }
+}
+
+impl CommandHandler {
+    pub(super) fn latency(&mut self, resp: &RespData) -> RespData {
+        let RespData::Array(arr) = resp else {
+            return errors::syntax_error();
+        };
+
+        let Some(RespData::BulkString(subcommand)) = arr.get(1) else {
+            return errors::wrong_arity("latency");
+        };
+
+        match subcommand.to_uppercase().as_str() {
+            "HISTOGRAM" => self.latency_histogram(&arr[2..]),
+            "LATEST" => {
+                if arr.len() != 2 {
+                    return errors::wrong_arity("latency|latest");
+                }
+                self.latency_latest()
+            }
+            "HISTORY" => {
+                let [_, _, RespData::BulkString(event)] = arr.as_slice() else {
+                    return errors::wrong_arity("latency|history");
+                };
+                self.latency_history(event)
+            }
+            "RESET" => self.latency_reset(&arr[2..]),
+            "DOCTOR" => {
+                if arr.len() != 2 {
+                    return errors::wrong_arity("latency|doctor");
+                }
+                RespData::BulkString(self.latency_doctor())
+            }
+            _ => errors::unknown_subcommand(subcommand, "latency"),
+        }
+    }
+
+    /// LATENCY LATEST: the most recent spike and the all time maximum of each event.
+    fn latency_latest(&self) -> RespData {
+        let mut names: Vec<_> = self.latency_monitor.events.keys().collect();
+        names.sort();
+
+        let mut result = Vec::new();
+        for name in names {
+            let event = &self.latency_monitor.events[name];
+            let Some((time, latency)) = event.samples.back() else {
+                continue;
+            };
+            result.push(RespData::Array(vec![
+                RespData::BulkString(name.clone()),
+                RespData::Integer(*time as i64),
+                RespData::Integer(*latency as i64),
+                RespData::Integer(event.max as i64),
+            ]));
+        }
+        RespData::Array(result)
+    }
+
+    /// LATENCY HISTORY event: every recorded `[timestamp, latency]` pair for an event.
+    fn latency_history(&self, event: &str) -> RespData {
+        let Some(event) = self.latency_monitor.events.get(event) else {
+            return RespData::Array(vec![]);
+        };
+        RespData::Array(
+            event
+                .samples
+                .iter()
+                .map(|(time, latency)| {
+                    RespData::Array(vec![
+                        RespData::Integer(*time as i64),
+                        RespData::Integer(*latency as i64),
+                    ])
+                })
+                .collect(),
+        )
+    }
+
+    /// LATENCY RESET [event ...]: drops the given events, or all of them, returning how many
+    /// were removed.
+    fn latency_reset(&mut self, args: &[RespData]) -> RespData {
+        if args.is_empty() {
+            let removed = self.latency_monitor.events.len();
+            self.latency_monitor.events.clear();
+            return RespData::Integer(removed as i64);
+        }
+
+        let mut removed = 0;
+        for arg in args {
+            let RespData::BulkString(event) = arg else {
+                return errors::syntax_error();
+            };
+            if self.latency_monitor.events.remove(event).is_some() {
+                removed += 1;
+            }
+        }
+        RespData::Integer(removed)
+    }
+
+    /// LATENCY DOCTOR: a human readable summary of the recorded spikes.
+    fn latency_doctor(&self) -> String {
+        if self.latency_monitor.events.is_empty() {
+            return "Dave, no latency spike was observed during the lifetime of this Redis \
+                    instance, not in the slightest bit. I honestly think you ought to sleep \
+                    tonight.\n"
+                .to_string();
+        }
+
+        let mut names: Vec<_> = self.latency_monitor.events.keys().collect();
+        names.sort();
+
+        let mut report = String::from(
+            "Dave, I have observed latency spikes in this Redis instance. \
+             You don't mind talking about it, do you Dave?\n\n",
+        );
+        for (idx, name) in names.into_iter().enumerate() {
+            let event = &self.latency_monitor.events[name];
+            let count = event.samples.len() as u64;
+            let avg = event.samples.iter().map(|(_, ms)| ms).sum::<u64>() / count.max(1);
+            let period = match (event.samples.front(), event.samples.back()) {
+                (Some((first, _)), Some((last, _))) if count > 1 => (last - first) / (count - 1),
+                _ => 0,
+            };
+            writeln!(
+                report,
+                "{}. {name}: {count} latency spikes (average {avg}ms, period {period} sec). \
+                 Worst all time event {}ms.",
+                idx + 1,
+                event.max
+            )
+            .unwrap();
+        }
+
+        let threshold = self.config.read().unwrap().latency_monitor_threshold;
+        write!(
+            report,
+            "\nI have a few advices for you:\n\n- Check for slow commands with \
+             LATENCY HISTOGRAM and INFO commandstats, and lower the latency-monitor-threshold \
+             (currently {threshold}ms) if you want to catch smaller spikes.\n"
+        )
+        .unwrap();
+        report
+    }
+
+    /// LATENCY HISTOGRAM [command ...]: cumulative power-of-two latency buckets per command.
+    fn latency_histogram(&self, args: &[RespData]) -> RespData {
+        let mut names = Vec::new();
+        for arg in args {
+            let RespData::BulkString(name) = arg else {
+                return errors::syntax_error();
+            };
+            names.push(name.to_lowercase());
+        }
+        if names.is_empty() {
+            names = self.stats.latency.keys().cloned().collect();
+        }
+        names.sort();
+        names.dedup();
+
+        let mut result = Vec::new();
+        for name in names {
+            let Some(histogram) = self.stats.latency.get(&name) else {
+                continue;
+            };
+            let buckets = histogram
+                .power_of_two_buckets()
+                .into_iter()
+                .flat_map(|(bucket, count)| {
+                    [
+                        RespData::Integer(bucket as i64),
+                        RespData::Integer(count as i64),
+                    ]
+                })
+                .collect();
+            result.push(RespData::BulkString(name));
+            result.push(RespData::Array(vec![
+                RespData::BulkString("calls".to_string()),
+                RespData::Integer(histogram.count() as i64),
+                RespData::BulkString("histogram_usec".to_string()),
+                RespData::Array(buckets),
+            ]));
+        }
+        RespData::Array(result)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn command(args: &[&str]) -> RespData {
+        RespData::array(args.iter().copied())
+    }
+
+    #[test]
+    fn test_latency_monitor_sample() {
+        let mut monitor = LatencyMonitor::default();
+
+        monitor.sample("command", Duration::from_millis(50), 0);
+        monitor.sample("command", Duration::from_millis(5), 10);
+        assert!(monitor.events.is_empty());
+
+        monitor.sample("command", Duration::from_millis(20), 10);
+        monitor.sample("command", Duration::from_millis(15), 10);
+        let event = &monitor.events["command"];
+        assert_eq!(event.max, 20);
+        // Both samples landed in the same second, so they were merged.
+        assert_eq!(event.samples.len(), 1);
+        assert_eq!(event.samples[0].1, 20);
+    }
+
+    #[test]
+    fn test_latency_events() {
+        let mut handler = CommandHandler::from(HashMap::new());
+        handler
+            .latency_monitor
+            .sample("command", Duration::from_millis(250), 100);
+        handler
+            .latency_monitor
+            .sample("expire-cycle", Duration::from_millis(120), 100);
+
+        let RespData::Array(latest) = handler.latency(&command(&["LATENCY", "LATEST"])) else {
+            panic!("LATENCY LATEST did not return an array");
+        };
+        assert_eq!(latest.len(), 2);
+        let RespData::Array(entry) = &latest[0] else {
+            panic!("Unexpected LATENCY LATEST entry");
+        };
+        assert_eq!(entry[0], RespData::BulkString("command".to_string()));
+        assert_eq!(entry[2..], [RespData::Integer(250), RespData::Integer(250)]);
+
+        let RespData::Array(history) =
+            handler.latency(&command(&["LATENCY", "HISTORY", "command"]))
+        else {
+            panic!("LATENCY HISTORY did not return an array");
+        };
+        assert_eq!(history.len(), 1);
+        assert_eq!(
+            handler.latency(&command(&["LATENCY", "HISTORY", "fork"])),
+            RespData::Array(vec![])
+        );
+
+        let RespData::BulkString(report) = handler.latency(&command(&["LATENCY", "DOCTOR"])) else {
+            panic!("LATENCY DOCTOR did not return a bulk string");
+        };
+        assert!(report.contains("1. command: 1 latency spikes"));
+        assert!(report.contains("2. expire-cycle: 1 latency spikes"));
+
+        let test_cases = [
+            (
+                "Reset a single event",
+                command(&["LATENCY", "RESET", "expire-cycle", "fork"]),
+                RespData::Integer(1),
+            ),
+            (
+                "Reset everything",
+                command(&["LATENCY", "RESET"]),
+                RespData::Integer(1),
+            ),
+            (
+                "Nothing left to reset",
+                command(&["LATENCY", "RESET"]),
+                RespData::Integer(0),
+            ),
+            (
+                "History needs an event",
+                command(&["LATENCY", "HISTORY"]),
+                RespData::Error(
+                    "wrong number of arguments for 'latency|history' command".to_string(),
+                ),
+            ),
+        ];
+        for (name, input, expected_output) in test_cases {
+            assert_eq!(handler.latency(&input), expected_output, "{}", name);
+        }
+
+        let RespData::BulkString(report) = handler.latency(&command(&["LATENCY", "DOCTOR"])) else {
+            panic!("LATENCY DOCTOR did not return a bulk string");
+        };
+        assert!(report.starts_with("Dave, no latency spike was observed"));
+    }
+
+    #[test]
+    fn test_slow_commands_are_sampled() {
+        let mut handler = CommandHandler::from(HashMap::new());
+        handler.config.write().unwrap().latency_monitor_threshold = 1;
+        handler.handle(&command(&["PING"]));
+        assert!(handler.latency_monitor.events.is_empty());
+
+        handler.config.write().unwrap().latency_monitor_threshold = 0;
+        handler.handle(&command(&["PING"]));
+        assert!(handler.latency_monitor.events.is_empty());
+    }
+
+    #[test]
+    fn test_latency_histogram() {
+        let mut handler = CommandHandler::from(HashMap::new());
+        for _ in 0..3 {
+            handler.handle(&RespData::Array(vec![RespData::BulkString(
+                "PING".to_string(),
+            )]));
+        }
+
+        let test_cases = [
+            (
+                "Histogram for a command that was never called",
+                vec!["LATENCY", "HISTOGRAM", "get"],
+                Some(0),
+            ),
+            (
+                "Histogram for a specific command",
+                vec!["LATENCY", "HISTOGRAM", "PING"],
+                Some(1),
+            ),
+            (
+                "Histogram for all commands",
+                vec!["LATENCY", "HISTOGRAM"],
+                Some(1),
+            ),
+            ("Unknown subcommand", vec!["LATENCY", "NOPE"], None),
+        ];
+
+        for (name, args, expected_commands) in test_cases {
+            let input = RespData::array(args);
+            match (handler.latency(&input), expected_commands) {
+                (RespData::Array(result), Some(commands)) => {
+                    assert_eq!(result.len(), commands * 2, "{}", name);
+                    if commands > 0 {
+                        assert_eq!(result[0], RespData::BulkString("ping".to_string()));
+                        let RespData::Array(details) = &result[1] else {
+                            panic!("Unexpected histogram for {}", name);
+                        };
+                        assert_eq!(details[1], RespData::Integer(3), "{}", name);
+                    }
+                }
+                (RespData::Error(_), None) => {}
+                (result, _) => panic!("Unexpected result for {}: {:?}", name, result),
+            }
+        }
+    }
+}
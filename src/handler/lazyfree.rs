@@ -0,0 +1,126 @@
+//! Freeing large values on a background thread, so dropping a huge hash doesn't stall every
+//! other client.
+
+use super::RedisValue;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::mpsc::{self, Sender};
+use std::sync::Arc;
+use std::thread;
+
+/// Values made of at most this many allocations are cheaper to drop right away than to hand
+/// over to the background thread.
+const LAZYFREE_THRESHOLD: usize = 64;
+
+/// Roughly how many allocations dropping `value` involves.
+fn free_effort(value: &RedisValue) -> usize {
+    match value {
+        RedisValue::String(_) => 1,
+        RedisValue::Hash(map) => map.len(),
+        RedisValue::Json(doc) => doc.allocated_size() / std::mem::size_of::<crate::json::Json>(),
+        RedisValue::Bloom(filter) => filter.layer_count(),
+        RedisValue::CountMin(_) => 1,
+        RedisValue::TopK(topk) => topk.len() + 1,
+        RedisValue::TimeSeries(series) => series.len(),
+    }
+}
+
+struct Job {
+    garbage: Box<dyn Send>,
+    objects: u64,
+}
+
+pub(super) struct LazyFree {
+    sender: Sender<Job>,
+    /// Objects handed to the background thread that it hasn't dropped yet.
+    pending: Arc<AtomicU64>,
+    /// Objects the background thread has dropped since startup.
+    freed: Arc<AtomicU64>,
+}
+
+impl LazyFree {
+    /// Starts the background thread, which exits once the `LazyFree` is dropped.
+    pub fn new() -> Self {
+        let (sender, receiver) = mpsc::channel::<Job>();
+        let pending = Arc::new(AtomicU64::new(0));
+        let freed = Arc::new(AtomicU64::new(0));
+        let (thread_pending, thread_freed) = (Arc::clone(&pending), Arc::clone(&freed));
+        thread::Builder::new()
+            .name("lazyfree".to_string())
+            .spawn(move || {
+                for job in receiver {
+                    drop(job.garbage);
+                    thread_pending.fetch_sub(job.objects, Ordering::Relaxed);
+                    thread_freed.fetch_add(job.objects, Ordering::Relaxed);
+                }
+            })
+            .expect("failed to spawn the lazyfree thread");
+        Self {
+            sender,
+            pending,
+            freed,
+        }
+    }
+
+    /// Frees a value, in the background if it is big enough to be worth it.
+    pub fn free_value(&self, value: RedisValue) {
+        if free_effort(&value) > LAZYFREE_THRESHOLD {
+            self.free_later(Box::new(value), 1);
+        }
+    }
+
+    /// Hands `garbage`, made up of `objects` values, to the background thread.
+    pub fn free_later(&self, garbage: Box<dyn Send>, objects: u64) {
+        self.pending.fetch_add(objects, Ordering::Relaxed);
+        if let Err(mpsc::SendError(job)) = self.sender.send(Job { garbage, objects }) {
+            // The thread is gone, so free it here rather than leak it.
+            self.pending.fetch_sub(job.objects, Ordering::Relaxed);
+        }
+    }
+
+    pub fn pending_objects(&self) -> u64 {
+        self.pending.load(Ordering::Relaxed)
+    }
+
+    pub fn freed_objects(&self) -> u64 {
+        self.freed.load(Ordering::Relaxed)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::handler::RedisHash;
+    use std::time::{Duration, Instant};
+
+    fn wait_until_idle(lazyfree: &LazyFree) {
+        let deadline = Instant::now() + Duration::from_secs(5);
+        while lazyfree.pending_objects() > 0 {
+            assert!(Instant::now() < deadline, "lazyfree thread is stuck");
+            thread::sleep(Duration::from_millis(1));
+        }
+    }
+
+    #[test]
+    fn test_free_value() {
+        let lazyfree = LazyFree::new();
+        let big_hash: RedisHash = (0..1000)
+            .map(|i| (i.to_string(), "v".to_string()))
+            .collect();
+
+        let test_cases = [
+            ("Small string", RedisValue::String("v".into()), 0),
+            ("Big hash", RedisValue::Hash(big_hash), 1),
+        ];
+
+        for (name, value, expected) in test_cases {
+            let before = lazyfree.freed_objects();
+            lazyfree.free_value(value);
+            wait_until_idle(&lazyfree);
+            assert_eq!(lazyfree.freed_objects() - before, expected, "{}", name);
+        }
+
+        lazyfree.free_later(Box::new(vec![0u8; 1024]), 10);
+        wait_until_idle(&lazyfree);
+        assert_eq!(lazyfree.freed_objects(), 11);
+    }
+}
@@ -0,0 +1,114 @@
+//! The logarithmic access frequency counter behind the LFU eviction policies.
+//!
+//! Like Redis, each key gets an 8-bit counter that grows ever more slowly the higher it gets, so
+//! it can tell apart keys hit a handful of times from ones hit millions of times, and that is
+//! decremented for every `lfu-decay-time` minutes the key goes unused.
+
+use std::time::Instant;
+
+/// Counter value of a freshly created key, so new keys aren't evicted straight away.
+pub(super) const LFU_INIT_VAL: u8 = 5;
+
+#[derive(Debug, Clone, Copy)]
+pub(super) struct LfuCounter {
+    counter: u8,
+    /// When the counter was last decayed or incremented.
+    last_update: Instant,
+}
+
+impl LfuCounter {
+    pub fn new() -> Self {
+        Self {
+            counter: LFU_INIT_VAL,
+            last_update: Instant::now(),
+        }
+    }
+
+    /// The counter after decaying it by one for every `decay_time` minutes since its last update.
+    pub fn value(&self, decay_time: u64) -> u8 {
+        if decay_time == 0 {
+            return self.counter;
+        }
+        let periods = self.last_update.elapsed().as_secs() / (decay_time * 60);
+        let periods = u8::try_from(periods).unwrap_or(u8::MAX);
+        self.counter.saturating_sub(periods)
+    }
+
+    /// Records an access. The counter is incremented with probability
+    /// `1 / ((counter - LFU_INIT_VAL) * log_factor + 1)`, where `random` is uniform in [0, 1).
+    pub fn touch(&mut self, log_factor: u32, decay_time: u64, random: f64) {
+        let counter = self.value(decay_time);
+        let base = counter.saturating_sub(LFU_INIT_VAL) as f64;
+        let probability = 1.0 / (base * log_factor as f64 + 1.0);
+        self.counter = if counter < u8::MAX && random < probability {
+            counter + 1
+        } else {
+            counter
+        };
+        self.last_update = Instant::now();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    #[test]
+    fn test_touch() {
+        let test_cases = [
+            (
+                "New keys always increment",
+                LFU_INIT_VAL,
+                10,
+                0.99,
+                LFU_INIT_VAL + 1,
+            ),
+            ("Likely increment", 6, 10, 0.05, 7),
+            ("Unlikely increment", 6, 10, 0.5, 6),
+            ("Zero log factor always increments", 100, 0, 0.99, 101),
+            ("Saturated counter", u8::MAX, 0, 0.0, u8::MAX),
+        ];
+
+        for (name, start, log_factor, random, expected) in test_cases {
+            let mut counter = LfuCounter::new();
+            counter.counter = start;
+            counter.touch(log_factor, 1, random);
+            assert_eq!(counter.value(1), expected, "{}", name);
+        }
+    }
+
+    #[test]
+    fn test_counter_grows_logarithmically() {
+        // With the default log factor the counter roughly follows the table in redis.conf.
+        let test_cases = [
+            ("100 hits", 100, 10),
+            ("1K hits", 1_000, 19),
+            ("10K hits", 10_000, 50),
+            ("100K hits", 100_000, 148),
+            ("1M hits saturate", 1_000_000, u8::MAX),
+        ];
+
+        for (name, hits, expected) in test_cases {
+            let mut counter = LfuCounter::new();
+            // An evenly spread sequence stands in for the random numbers.
+            for i in 0..hits {
+                counter.touch(10, 0, (i as f64 * 0.618_033_988_749_895).fract());
+            }
+            assert_eq!(counter.value(0), expected, "{}", name);
+        }
+    }
+
+    #[test]
+    fn test_decay() {
+        let mut counter = LfuCounter::new();
+        counter.counter = 10;
+        counter.last_update = Instant::now() - Duration::from_secs(3 * 60 + 1);
+
+        assert_eq!(counter.value(0), 10);
+        assert_eq!(counter.value(1), 7);
+        assert_eq!(counter.value(2), 9);
+        counter.touch(10, 1, 0.99);
+        assert_eq!(counter.value(1), 7);
+    }
+}
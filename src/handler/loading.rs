@@ -0,0 +1,238 @@
+//! Loading the RDB file at startup without looking hung. The file is read on a thread of its
+//! own and its keys stored a batch at a time, so clients connecting meanwhile are answered
+//! with -LOADING for most commands, and INFO persistence tells how far along the load is.
+
+use super::command_table::{self, LOADING};
+use super::persistence::unix_time_ms;
+use super::{CommandHandler, ListpackLimits};
+use crate::rdb::{self, Entry, RdbError};
+use crate::resp::RespData;
+use std::fs::File;
+use std::io::{self, BufReader, Read};
+use std::mem;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Keys read from the file before they are stored, taking the handler lock once per batch.
+const LOAD_BATCH: usize = 1024;
+
+/// A load in progress.
+pub(super) struct Loading {
+    started: SystemTime,
+    total_bytes: u64,
+    loaded_bytes: Arc<AtomicU64>,
+}
+
+impl Loading {
+    /// When the load started, in seconds since the epoch.
+    pub(super) fn start_time(&self) -> u64 {
+        self.started
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_secs()
+    }
+
+    pub(super) fn total_bytes(&self) -> u64 {
+        self.total_bytes
+    }
+
+    pub(super) fn loaded_bytes(&self) -> u64 {
+        self.loaded_bytes.load(Ordering::Relaxed)
+    }
+
+    /// How much of the file has been read, as a percentage.
+    pub(super) fn loaded_perc(&self) -> f64 {
+        if self.total_bytes == 0 {
+            return 100.0;
+        }
+        self.loaded_bytes() as f64 * 100.0 / self.total_bytes as f64
+    }
+
+    /// The seconds left if the rest of the file is read as fast as the start was, or 1 until
+    /// anything has been read, like Redis reports it.
+    pub(super) fn eta_seconds(&self) -> u64 {
+        let loaded = self.loaded_bytes();
+        if loaded == 0 {
+            return 1;
+        }
+        let elapsed = self.started.elapsed().unwrap_or_default().as_secs_f64();
+        let left = self.total_bytes.saturating_sub(loaded) as f64;
+        (elapsed * left / loaded as f64) as u64
+    }
+}
+
+/// The RDB file opened by `CommandHandler::start_loading`, to be read by `run`.
+pub struct RdbLoader {
+    file: File,
+    limits: ListpackLimits,
+    loaded_bytes: Arc<AtomicU64>,
+}
+
+impl RdbLoader {
+    /// Reads the file into `handler`, which is only locked to store each batch of keys, and
+    /// returns the number of keys loaded. Keys are stored as they are read, so a corrupt file
+    /// leaves part of it loaded; the handler stops loading either way.
+    pub fn run(self, handler: &Mutex<CommandHandler>) -> Result<usize, RdbError> {
+        let mut reader = BufReader::new(Progress {
+            inner: self.file,
+            read: self.loaded_bytes,
+        });
+        let mut batch = Vec::with_capacity(LOAD_BATCH);
+        let result = rdb::load_each(&mut reader, self.limits, |entry| {
+            batch.push(entry);
+            if batch.len() == LOAD_BATCH {
+                handler.lock().unwrap().restore_batch(mem::take(&mut batch));
+            }
+        });
+        let mut handler = handler.lock().unwrap();
+        handler.finish_loading(batch);
+        result.map(|()| handler.db.len())
+    }
+}
+
+/// Counts the bytes read through it.
+struct Progress<R> {
+    inner: R,
+    read: Arc<AtomicU64>,
+}
+
+impl<R: Read> Read for Progress<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let n = self.inner.read(buf)?;
+        self.read.fetch_add(n as u64, Ordering::Relaxed);
+        Ok(n)
+    }
+}
+
+impl CommandHandler {
+    /// Opens the configured RDB file and empties the dataset to load it into. Until the
+    /// returned loader has run, only commands flagged LOADING are accepted. Returns `None`,
+    /// without loading anything, if there is no file.
+    pub fn start_loading(&mut self) -> io::Result<Option<RdbLoader>> {
+        let (path, limits) = {
+            let config = self.config.read().unwrap();
+            (config.rdb_path(), ListpackLimits::from(&*config))
+        };
+        let file = match File::open(&path) {
+            Ok(file) => file,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(None),
+            Err(e) => return Err(e),
+        };
+        let loaded_bytes = Arc::new(AtomicU64::new(0));
+        self.loading = Some(Loading {
+            started: SystemTime::now(),
+            total_bytes: file.metadata()?.len(),
+            loaded_bytes: Arc::clone(&loaded_bytes),
+        });
+        self.clear_dataset();
+        Ok(Some(RdbLoader {
+            file,
+            limits,
+            loaded_bytes,
+        }))
+    }
+
+    /// Whether the dataset is still being loaded at startup.
+    pub fn is_loading(&self) -> bool {
+        self.loading.is_some()
+    }
+
+    /// The error to refuse `command` with, if it can't run until loading is done.
+    pub(super) fn loading_denied(&self, command: &str) -> Option<RespData> {
+        let allowed = command_table::lookup(command).is_some_and(|spec| spec.flags & LOADING != 0);
+        (self.loading.is_some() && !allowed)
+            .then(|| RespData::Error("-LOADING Redis is loading the dataset in memory".to_string()))
+    }
+
+    fn restore_batch(&mut self, entries: Vec<Entry>) {
+        let now = self.clock.now();
+        let now_ms = unix_time_ms();
+        for entry in entries {
+            self.restore_entry(entry, now, now_ms);
+        }
+    }
+
+    /// Stores the keys left over from the last batch and opens the dataset to every command.
+    fn finish_loading(&mut self, entries: Vec<Entry>) {
+        self.restore_batch(entries);
+        self.recompute_used_memory();
+        self.rebuild_search_indexes();
+        self.dirty = 0;
+        self.loading = None;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::Config;
+    use std::collections::HashMap;
+    use std::fs;
+    use std::process;
+    use std::sync::RwLock;
+
+    fn command(args: &[&str]) -> RespData {
+        RespData::array(args.iter().copied())
+    }
+
+    fn info_field(handler: &mut CommandHandler, name: &str) -> String {
+        let RespData::BulkString(info) = handler.handle(&command(&["INFO", "persistence"])) else {
+            panic!("INFO didn't reply with a bulk string");
+        };
+        info.lines()
+            .find_map(|line| line.strip_prefix(&format!("{name}:")))
+            .unwrap_or_default()
+            .to_string()
+    }
+
+    #[test]
+    fn test_loading() {
+        let dir = std::env::temp_dir().join(format!("redis-loading-{}", process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let config = Arc::new(RwLock::new(Config {
+            dir: dir.clone(),
+            ..Config::default()
+        }));
+        let mut saved = CommandHandler::new(HashMap::new(), Arc::clone(&config));
+        for i in 0..LOAD_BATCH + 1 {
+            saved.handle(&command(&["SET", &format!("key{i}"), "value"]));
+        }
+        saved.handle(&command(&["SAVE"]));
+
+        let mut handler = CommandHandler::new(HashMap::new(), config);
+        let loader = handler.start_loading().unwrap().unwrap();
+        let loading = RespData::Error("-LOADING Redis is loading the dataset in memory".into());
+
+        let test_cases = [
+            (
+                "Ping",
+                command(&["PING"]),
+                RespData::SimpleString("PONG".into()),
+            ),
+            ("Read", command(&["GET", "key0"]), loading.clone()),
+            ("Write", command(&["SET", "key0", "other"]), loading.clone()),
+            ("Save", command(&["SAVE"]), loading),
+        ];
+
+        for (name, input, expected) in test_cases {
+            assert_eq!(handler.handle(&input), expected, "{}", name);
+        }
+        assert_eq!(info_field(&mut handler, "loading"), "1");
+        assert_eq!(info_field(&mut handler, "loading_loaded_bytes"), "0");
+        assert_eq!(info_field(&mut handler, "loading_loaded_perc"), "0.00");
+
+        let handler = Mutex::new(handler);
+        assert_eq!(loader.run(&handler).unwrap(), LOAD_BATCH + 1);
+        let mut handler = handler.into_inner().unwrap();
+        assert!(!handler.is_loading());
+        assert_eq!(info_field(&mut handler, "loading"), "0");
+        assert_eq!(info_field(&mut handler, "loading_total_bytes"), "");
+        assert_eq!(
+            handler.handle(&command(&["GET", "key0"])),
+            RespData::bulk("value")
+        );
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,351 @@
+//! Approximate accounting of the memory held by the dataset, and the MEMORY command.
+
+use super::errors;
+use super::info::resident_set_size;
+use super::{CommandHandler, RedisValue};
+use crate::resp::RespData;
+
+/// Rough fixed cost of a key: its dict entry plus the key and value object headers.
+const KEY_OVERHEAD: u64 = 64;
+/// Rough fixed cost of a single hash field.
+const FIELD_OVERHEAD: u64 = 32;
+
+/// Number of container elements MEMORY USAGE looks at unless told otherwise.
+const DEFAULT_USAGE_SAMPLES: usize = 5;
+/// Below this much memory MEMORY DOCTOR has nothing meaningful to say.
+const DOCTOR_MIN_MEMORY: u64 = 5 * 1024 * 1024;
+
+/// Estimated number of bytes used by `key` and its value.
+pub(super) fn entry_size(key: &str, value: &RedisValue) -> u64 {
+    sampled_entry_size(key, value, 0)
+}
+
+/// Like `entry_size`, but only measures up to `samples` elements of a container and
+/// extrapolates from their average size. 0 measures every element.
+fn sampled_entry_size(key: &str, value: &RedisValue, samples: usize) -> u64 {
+    let value_size = match value {
+        RedisValue::String(s) => s.allocated_size() as u64,
+        RedisValue::Hash(map) => {
+            let field_size = |(field, value): (&String, &String)| {
+                (field.len() + value.len()) as u64 + FIELD_OVERHEAD
+            };
+            if samples == 0 || map.len() <= samples {
+                map.iter().map(field_size).sum()
+            } else {
+                let sampled: u64 = map.iter().take(samples).map(field_size).sum();
+                sampled * map.len() as u64 / samples as u64
+            }
+        }
+        RedisValue::Json(doc) => doc.allocated_size() as u64,
+        RedisValue::Bloom(filter) => filter.allocated_size() as u64,
+        RedisValue::CountMin(sketch) => sketch.allocated_size() as u64,
+        RedisValue::TopK(topk) => topk.allocated_size() as u64,
+        RedisValue::TimeSeries(series) => series.allocated_size() as u64,
+    };
+    KEY_OVERHEAD + key.len() as u64 + value_size
+}
+
+impl CommandHandler {
+    /// Estimated number of bytes used by `key`, or 0 if it doesn't exist.
+    pub(super) fn key_memory(&self, key: &str) -> u64 {
+        self.db.get(key).map_or(0, |value| entry_size(key, value))
+    }
+
+    /// Updates the dataset size after `key` was written, given what it used beforehand.
+    pub(super) fn account_key_change(&mut self, key: &str, before: u64) {
+        let after = self.key_memory(key);
+        self.used_memory = self.used_memory.saturating_sub(before) + after;
+        self.used_memory_peak = self.used_memory_peak.max(self.used_memory);
+    }
+
+    /// Recomputes the dataset size from scratch, after the keyspace was replaced wholesale.
+    pub(super) fn recompute_used_memory(&mut self) {
+        let mut used_memory = 0;
+        self.db
+            .for_each(&mut |key, value| used_memory += entry_size(key, value));
+        self.used_memory = used_memory;
+        self.used_memory_peak = self.used_memory_peak.max(self.used_memory);
+    }
+
+    pub(super) fn memory(&mut self, resp: &RespData) -> RespData {
+        let RespData::Array(arr) = resp else {
+            return errors::syntax_error();
+        };
+        let Some(RespData::BulkString(subcommand)) = arr.get(1) else {
+            return errors::wrong_arity("memory");
+        };
+
+        match subcommand.to_uppercase().as_str() {
+            "USAGE" => self.memory_usage(&arr[2..]),
+            "STATS" if arr.len() == 2 => self.memory_stats(),
+            "DOCTOR" if arr.len() == 2 => RespData::BulkString(self.memory_doctor()),
+            "STATS" | "DOCTOR" => {
+                errors::wrong_arity(&format!("memory|{}", subcommand.to_lowercase()))
+            }
+            _ => errors::unknown_subcommand(subcommand, "memory"),
+        }
+    }
+
+    /// MEMORY USAGE key [SAMPLES count]: bytes used by a key, sampling `count` elements of
+    /// containers (all of them if 0).
+    fn memory_usage(&mut self, args: &[RespData]) -> RespData {
+        let (key, samples) = match args {
+            [RespData::BulkString(key)] => (key, DEFAULT_USAGE_SAMPLES),
+            [RespData::BulkString(key), RespData::BulkString(option), RespData::BulkString(count)]
+                if option.eq_ignore_ascii_case("SAMPLES") =>
+            {
+                let Ok(count) = count.parse::<usize>() else {
+                    return errors::not_an_integer();
+                };
+                (key, count)
+            }
+            [] => return errors::wrong_arity("memory|usage"),
+            _ => return errors::syntax_error(),
+        };
+
+        self.expire_if_needed(key);
+        match self.db.get(key) {
+            Some(value) => RespData::Integer(sampled_entry_size(key, value, samples) as i64),
+            None => RespData::Null,
+        }
+    }
+
+    /// MEMORY STATS: a breakdown of where the memory goes, as name/value pairs.
+    fn memory_stats(&self) -> RespData {
+        let keys = self.db.len() as u64;
+        let overhead = keys * KEY_OVERHEAD;
+        let dataset = self.used_memory.saturating_sub(overhead);
+        let percentage = |part: u64, whole: u64| {
+            let percentage = if whole == 0 {
+                0.0
+            } else {
+                part as f64 * 100.0 / whole as f64
+            };
+            RespData::BulkString(format!("{percentage:.2}"))
+        };
+
+        let stats = [
+            (
+                "peak.allocated",
+                RespData::Integer(self.used_memory_peak as i64),
+            ),
+            (
+                "total.allocated",
+                RespData::Integer(self.used_memory as i64),
+            ),
+            ("overhead.total", RespData::Integer(overhead as i64)),
+            ("keys.count", RespData::Integer(keys as i64)),
+            (
+                "keys.bytes-per-key",
+                RespData::Integer(self.used_memory.checked_div(keys).unwrap_or(0) as i64),
+            ),
+            ("dataset.bytes", RespData::Integer(dataset as i64)),
+            ("dataset.percentage", percentage(dataset, self.used_memory)),
+            (
+                "peak.percentage",
+                percentage(self.used_memory, self.used_memory_peak),
+            ),
+        ];
+        RespData::Array(
+            stats
+                .into_iter()
+                .flat_map(|(name, value)| [RespData::BulkString(name.to_string()), value])
+                .collect(),
+        )
+    }
+
+    /// MEMORY DOCTOR: a human readable report of likely memory problems.
+    fn memory_doctor(&self) -> String {
+        if self.used_memory < DOCTOR_MIN_MEMORY {
+            return "Hi Sam, this instance is empty or is using very little memory, my issues \
+                    detector can't be used in these conditions. Please, leave for your mission on \
+                    Earth and fill it with some data. The new Sam and I will be back to our \
+                    programming as soon as I finished rebooting."
+                .to_string();
+        }
+
+        let mut issues = Vec::new();
+        if self.used_memory_peak as f64 > self.used_memory as f64 * 1.5 {
+            issues.push(
+                " * Peak memory: In the past this instance used more than 150% the memory that \
+                 is currently using. The allocator is normally not able to release memory after \
+                 a peak, so you can expect to see a big fragmentation ratio, however this is \
+                 actually harmless and is only due to the memory peak, and if the Redis instance \
+                 Resident Set Size (RSS) is currently bigger than expected, the memory will be \
+                 used as soon as you fill the Redis instance with more data.",
+            );
+        }
+        let fragmentation = resident_set_size() as f64 / self.used_memory as f64;
+        if fragmentation > 1.4 {
+            issues.push(
+                " * High fragmentation: This instance has a memory fragmentation greater than \
+                 1.4 (this means that the Resident Set Size of the Redis process is much larger \
+                 than the sum of the logical allocations Redis performed).",
+            );
+        }
+        let maxmemory = self.config.read().unwrap().maxmemory;
+        if maxmemory > 0 && self.used_memory > maxmemory {
+            issues.push(
+                " * Over maxmemory: The dataset is bigger than maxmemory and could not be \
+                 evicted back under it, so writes that need memory are being refused. Check \
+                 maxmemory-policy.",
+            );
+        }
+
+        if issues.is_empty() {
+            return "Hi Sam, I can't find any memory issue in your instance. I can only account \
+                    for what occurs on this base."
+                .to_string();
+        }
+        format!(
+            "Sam, I detected a few issues in this Redis instance memory implants:\n\n{}\n\n\
+             I'm here to keep you safe, Sam. I want to help you.",
+            issues.join("\n\n")
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+
+    fn command(args: &[&str]) -> RespData {
+        RespData::array(args.iter().copied())
+    }
+
+    #[test]
+    fn test_used_memory_tracking() {
+        let mut handler = CommandHandler::from(HashMap::new());
+        // Short strings are stored inline, so only a long one allocates.
+        let long = "x".repeat(100);
+        let string_size = KEY_OVERHEAD + 3;
+        let hash_size = KEY_OVERHEAD + 4 + 2 * FIELD_OVERHEAD + 4;
+
+        let test_cases = [
+            (
+                "Set a string",
+                command(&["SET", "key", &long]),
+                string_size + 100,
+            ),
+            (
+                "Overwrite with an inline value",
+                command(&["SET", "key", "v"]),
+                string_size,
+            ),
+            (
+                "Create a hash",
+                command(&["HSET", "hash", "a", "1", "b", "2"]),
+                string_size + hash_size,
+            ),
+            (
+                "Update a hash field",
+                command(&["HSET", "hash", "a", "100"]),
+                string_size + hash_size + 2,
+            ),
+            (
+                "Wrong type leaves the size alone",
+                command(&["HSET", "key", "a", "1"]),
+                string_size + hash_size + 2,
+            ),
+        ];
+
+        for (name, input, expected) in test_cases {
+            handler.handle(&input);
+            assert_eq!(handler.used_memory, expected, "{}", name);
+        }
+
+        handler.delete_key("hash");
+        assert_eq!(handler.used_memory, string_size);
+        handler.recompute_used_memory();
+        assert_eq!(handler.used_memory, string_size);
+    }
+
+    #[test]
+    fn test_memory_usage() {
+        let mut handler = CommandHandler::from(HashMap::new());
+        handler.handle(&command(&["SET", "key", "value"]));
+        let mut hset = vec!["HSET", "hash"];
+        let fields: Vec<String> = (0..10).map(|i| format!("f{i}")).collect();
+        for field in &fields {
+            hset.extend([field.as_str(), "v"]);
+        }
+        handler.handle(&command(&hset));
+        let hash_size = KEY_OVERHEAD + 4 + 10 * (FIELD_OVERHEAD + 3);
+
+        let test_cases = [
+            (
+                "String",
+                command(&["MEMORY", "USAGE", "key"]),
+                RespData::Integer((KEY_OVERHEAD + 3) as i64),
+            ),
+            (
+                "Sampled hash",
+                command(&["MEMORY", "USAGE", "hash"]),
+                RespData::Integer(hash_size as i64),
+            ),
+            (
+                "Every field",
+                command(&["MEMORY", "USAGE", "hash", "samples", "0"]),
+                RespData::Integer(hash_size as i64),
+            ),
+            (
+                "Missing key",
+                command(&["MEMORY", "USAGE", "missing"]),
+                RespData::Null,
+            ),
+            (
+                "Bad sample count",
+                command(&["MEMORY", "USAGE", "key", "SAMPLES", "many"]),
+                RespData::Error("value is not an integer or out of range".to_string()),
+            ),
+            (
+                "Unknown option",
+                command(&["MEMORY", "USAGE", "key", "FAST", "1"]),
+                RespData::Error("syntax error".to_string()),
+            ),
+            (
+                "Unknown subcommand",
+                command(&["MEMORY", "NOPE"]),
+                RespData::Error("unknown subcommand 'NOPE'. Try MEMORY HELP.".to_string()),
+            ),
+        ];
+
+        for (name, input, expected) in test_cases {
+            assert_eq!(handler.handle(&input), expected, "{}", name);
+        }
+    }
+
+    #[test]
+    fn test_memory_stats_and_doctor() {
+        let mut handler = CommandHandler::from(HashMap::new());
+        handler.handle(&command(&["SET", "a", "abcd"]));
+        handler.handle(&command(&["SET", "b", "abcd"]));
+        handler.delete_key("b");
+
+        let RespData::Array(stats) = handler.handle(&command(&["MEMORY", "STATS"])) else {
+            panic!("MEMORY STATS did not return an array");
+        };
+        let stat = |name: &str| {
+            let position = stats
+                .iter()
+                .position(|item| *item == RespData::BulkString(name.to_string()))
+                .unwrap();
+            stats[position + 1].clone()
+        };
+        let key_size = (KEY_OVERHEAD + 1) as i64;
+        assert_eq!(stat("peak.allocated"), RespData::Integer(2 * key_size));
+        assert_eq!(stat("total.allocated"), RespData::Integer(key_size));
+        assert_eq!(stat("keys.count"), RespData::Integer(1));
+        assert_eq!(stat("dataset.bytes"), RespData::Integer(1));
+        assert_eq!(
+            stat("peak.percentage"),
+            RespData::BulkString("50.00".to_string())
+        );
+
+        let RespData::BulkString(report) = handler.handle(&command(&["MEMORY", "DOCTOR"])) else {
+            panic!("MEMORY DOCTOR did not return a bulk string");
+        };
+        assert!(report.contains("using very little memory"));
+    }
+}
@@ -0,0 +1,339 @@
+//! The server's counters and gauges in the Prometheus text exposition format, served over HTTP
+//! on `metrics-port` so the server can be scraped without an external exporter.
+
+use super::info::resident_set_size;
+use super::CommandHandler;
+use crate::allocator;
+use std::fmt::{Display, Write};
+
+/// Upper bounds of the command latency histogram buckets, in microseconds. Powers of two so
+/// the counts are exact.
+const LATENCY_BUCKETS_USEC: [u64; 11] = [
+    1 << 2,
+    1 << 4,
+    1 << 6,
+    1 << 8,
+    1 << 10,
+    1 << 12,
+    1 << 14,
+    1 << 16,
+    1 << 18,
+    1 << 20,
+    1 << 22,
+];
+
+impl CommandHandler {
+    /// Every metric, rendered in the Prometheus text format.
+    pub fn metrics(&self) -> String {
+        let mut out = String::new();
+        // Like INFO, with jemalloc the allocator knows what is really in use.
+        let used_memory = allocator::stats().map_or(self.used_memory, |stats| stats.allocated);
+        let stats = &self.stats;
+        let unlabelled = [
+            (
+                "uptime_seconds",
+                "gauge",
+                "Seconds since the server started.",
+                stats.uptime_in_seconds(),
+            ),
+            (
+                "connected_clients",
+                "gauge",
+                "Client connections currently open.",
+                self.clients.len() as u64,
+            ),
+            (
+                "connections_received_total",
+                "counter",
+                "Client connections accepted.",
+                stats.total_connections_received,
+            ),
+            (
+                "rejected_connections_total",
+                "counter",
+                "Client connections refused because of maxclients.",
+                stats.rejected_connections,
+            ),
+            (
+                "commands_processed_total",
+                "counter",
+                "Commands received, whether or not they ran.",
+                stats.total_commands_processed,
+            ),
+            (
+                "instantaneous_ops_per_sec",
+                "gauge",
+                "Commands per second, averaged over the last few cron ticks.",
+                stats.instantaneous_ops_per_sec(),
+            ),
+            (
+                "keys",
+                "gauge",
+                "Keys in the keyspace.",
+                self.db.len() as u64,
+            ),
+            (
+                "expiring_keys",
+                "gauge",
+                "Keys with a TTL.",
+                self.db.expires_len() as u64,
+            ),
+            (
+                "keyspace_hits_total",
+                "counter",
+                "Lookups of keys that existed.",
+                stats.keyspace_hits,
+            ),
+            (
+                "keyspace_misses_total",
+                "counter",
+                "Lookups of keys that didn't exist.",
+                stats.keyspace_misses,
+            ),
+            (
+                "expired_keys_total",
+                "counter",
+                "Keys removed on expiry.",
+                stats.expired_keys,
+            ),
+            (
+                "evicted_keys_total",
+                "counter",
+                "Keys evicted because of maxmemory.",
+                stats.evicted_keys,
+            ),
+            (
+                "memory_used_bytes",
+                "gauge",
+                "Bytes allocated by the server.",
+                used_memory,
+            ),
+            (
+                "memory_dataset_bytes",
+                "gauge",
+                "Estimated bytes held by keys and values.",
+                self.used_memory,
+            ),
+            (
+                "memory_rss_bytes",
+                "gauge",
+                "Resident set size of the process.",
+                resident_set_size(),
+            ),
+            (
+                "memory_max_bytes",
+                "gauge",
+                "The maxmemory limit, or 0 without one.",
+                self.config.read().unwrap().maxmemory,
+            ),
+        ];
+        for (name, kind, help, value) in unlabelled {
+            metric(&mut out, name, kind, help);
+            sample(&mut out, name, "", value);
+        }
+
+        let mut commands: Vec<_> = stats.commands.iter().collect();
+        commands.sort_unstable_by_key(|(name, _)| *name);
+        metric(&mut out, "commands_total", "counter", "Calls per command.");
+        for (name, command) in &commands {
+            sample(
+                &mut out,
+                "commands_total",
+                &label("cmd", name),
+                command.calls,
+            );
+        }
+        let help = "Calls per command that replied with an error.";
+        metric(&mut out, "commands_failed_total", "counter", help);
+        for (name, command) in &commands {
+            let labels = label("cmd", name);
+            sample(
+                &mut out,
+                "commands_failed_total",
+                &labels,
+                command.failed_calls,
+            );
+        }
+
+        let help = "Time spent running each command.";
+        metric(&mut out, "command_duration_seconds", "histogram", help);
+        for (name, command) in &commands {
+            let Some(histogram) = stats.latency.get(*name) else {
+                continue;
+            };
+            let cmd = label("cmd", name);
+            for usec in LATENCY_BUCKETS_USEC {
+                let labels = format!("{cmd},le=\"{}\"", usec as f64 / 1e6);
+                let count = histogram.count_below(usec);
+                sample(&mut out, "command_duration_seconds_bucket", &labels, count);
+            }
+            let labels = format!("{cmd},le=\"+Inf\"");
+            let count = histogram.count();
+            sample(&mut out, "command_duration_seconds_bucket", &labels, count);
+            let sum = command.usec as f64 / 1e6;
+            sample(&mut out, "command_duration_seconds_sum", &cmd, sum);
+            sample(&mut out, "command_duration_seconds_count", &cmd, count);
+        }
+
+        let mut errors: Vec<_> = stats.errors.iter().collect();
+        errors.sort_unstable();
+        let help = "Error replies per error code.";
+        metric(&mut out, "errors_total", "counter", help);
+        for (prefix, count) in errors {
+            sample(&mut out, "errors_total", &label("error", prefix), count);
+        }
+        out
+    }
+}
+
+/// Metric names are prefixed like those of the Redis exporter.
+const PREFIX: &str = "redis_";
+
+fn metric(out: &mut String, name: &str, kind: &str, help: &str) {
+    writeln!(out, "# HELP {PREFIX}{name} {help}").unwrap();
+    writeln!(out, "# TYPE {PREFIX}{name} {kind}").unwrap();
+}
+
+fn sample(out: &mut String, name: &str, labels: &str, value: impl Display) {
+    if labels.is_empty() {
+        writeln!(out, "{PREFIX}{name} {value}").unwrap();
+    } else {
+        writeln!(out, "{PREFIX}{name}{{{labels}}} {value}").unwrap();
+    }
+}
+
+/// A `name="value"` label, with the value escaped as the text format requires.
+fn label(name: &str, value: &str) -> String {
+    let value = value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n");
+    format!("{name}=\"{value}\"")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::resp::RespData;
+    use std::collections::HashMap;
+
+    fn command(args: &[&str]) -> RespData {
+        RespData::array(args.iter().copied())
+    }
+
+    fn metric_lines(handler: &CommandHandler, name: &str) -> Vec<String> {
+        handler
+            .metrics()
+            .lines()
+            .filter(|line| {
+                line.strip_prefix(name)
+                    .is_some_and(|rest| rest.starts_with(['{', ' ']))
+            })
+            .map(str::to_string)
+            .collect()
+    }
+
+    #[test]
+    fn test_metrics() {
+        let mut handler = CommandHandler::from(HashMap::new());
+        handler.handle(&command(&["SET", "a", "1", "EX", "100"]));
+        handler.handle(&command(&["SET", "b", "2"]));
+        handler.handle(&command(&["GET", "a"]));
+        handler.handle(&command(&["GET", "missing"]));
+        handler.handle(&command(&["HGET", "a", "field"]));
+
+        let test_cases = [
+            ("Keys", "redis_keys", vec!["redis_keys 2"]),
+            (
+                "Expiring keys",
+                "redis_expiring_keys",
+                vec!["redis_expiring_keys 1"],
+            ),
+            (
+                "Hits",
+                "redis_keyspace_hits_total",
+                vec!["redis_keyspace_hits_total 2"],
+            ),
+            (
+                "Misses",
+                "redis_keyspace_misses_total",
+                vec!["redis_keyspace_misses_total 1"],
+            ),
+            (
+                "Calls by command",
+                "redis_commands_total",
+                vec![
+                    "redis_commands_total{cmd=\"get\"} 2",
+                    "redis_commands_total{cmd=\"hget\"} 1",
+                    "redis_commands_total{cmd=\"set\"} 2",
+                ],
+            ),
+            (
+                "Failures by command",
+                "redis_commands_failed_total",
+                vec![
+                    "redis_commands_failed_total{cmd=\"get\"} 0",
+                    "redis_commands_failed_total{cmd=\"hget\"} 1",
+                    "redis_commands_failed_total{cmd=\"set\"} 0",
+                ],
+            ),
+            (
+                "Errors by code",
+                "redis_errors_total",
+                vec!["redis_errors_total{error=\"WRONGTYPE\"} 1"],
+            ),
+            (
+                "Latency count",
+                "redis_command_duration_seconds_count",
+                vec![
+                    "redis_command_duration_seconds_count{cmd=\"get\"} 2",
+                    "redis_command_duration_seconds_count{cmd=\"hget\"} 1",
+                    "redis_command_duration_seconds_count{cmd=\"set\"} 2",
+                ],
+            ),
+        ];
+
+        for (name, metric, expected) in test_cases {
+            assert_eq!(metric_lines(&handler, metric), expected, "{}", name);
+        }
+    }
+
+    #[test]
+    fn test_latency_histogram() {
+        let mut handler = CommandHandler::from(HashMap::new());
+        handler.handle(&command(&["PING"]));
+
+        let buckets = metric_lines(&handler, "redis_command_duration_seconds_bucket");
+        assert_eq!(buckets.len(), LATENCY_BUCKETS_USEC.len() + 1);
+        assert_eq!(
+            buckets[0],
+            format!(
+                "redis_command_duration_seconds_bucket{{cmd=\"ping\",le=\"0.000004\"}} {}",
+                handler.stats.latency["ping"].count_below(4)
+            )
+        );
+        assert_eq!(
+            buckets.last().unwrap(),
+            "redis_command_duration_seconds_bucket{cmd=\"ping\",le=\"+Inf\"} 1"
+        );
+        // Bucket counts are cumulative.
+        let counts: Vec<u64> = buckets
+            .iter()
+            .map(|line| line.rsplit(' ').next().unwrap().parse().unwrap())
+            .collect();
+        assert!(counts.windows(2).all(|pair| pair[0] <= pair[1]));
+    }
+
+    #[test]
+    fn test_label() {
+        let test_cases = [
+            ("Plain", "get", "cmd=\"get\""),
+            ("Quote", "a\"b", "cmd=\"a\\\"b\""),
+            ("Backslash and newline", "a\\b\nc", "cmd=\"a\\\\b\\nc\""),
+        ];
+
+        for (name, input, expected) in test_cases {
+            assert_eq!(label("cmd", input), expected, "{}", name);
+        }
+    }
+}
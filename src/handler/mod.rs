@@ -0,0 +1,1019 @@
+//! The command engine. A `CommandHandler` owns the dataset and the connected clients, and
+//! runs commands given as parsed `RespData`, returning the reply. The data commands can also be
+//! given as a typed `Command`.
+
+use crate::bloom::BloomFilter;
+use crate::config::Config;
+use crate::dict::Dict;
+use crate::json::Json;
+use crate::resp::RespData;
+use crate::search::SearchIndex;
+use crate::sketch::{CountMinSketch, TopK};
+use crate::telemetry;
+use crate::timeseries::TimeSeries;
+use acl::Acl;
+use audit::AuditLog;
+use backup::Backups;
+use client::{Client, ClientPause};
+use clock::{Clock, SystemClock};
+use events::KeyEventCallback;
+use evict::{xorshift64_star, EvictionPool};
+use hooks::{AfterCommandHook, BeforeCommandHook};
+use latency::LatencyMonitor;
+use lazyfree::LazyFree;
+use lfu::LfuCounter;
+use loading::Loading;
+use ratelimit::TokenBucket;
+use stats::Stats;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::net::IpAddr;
+use std::sync::{Arc, RwLock};
+use std::time::{Instant, SystemTime};
+use streaming::ReplyStreaming;
+
+mod acl;
+mod admin;
+mod audit;
+mod auth;
+mod backup;
+mod bigkeys;
+mod bloom;
+mod client;
+mod clock;
+mod command;
+mod command_table;
+mod connection;
+mod cron;
+mod custom;
+mod debug;
+mod digest;
+mod errors;
+mod events;
+mod evict;
+mod expire;
+mod hash;
+mod help;
+mod histogram;
+mod hooks;
+mod hotkeys;
+mod info;
+mod json;
+mod keyspace;
+mod latency;
+mod lazyfree;
+mod lfu;
+mod loading;
+mod memory;
+mod metrics;
+mod object;
+mod persistence;
+#[cfg(test)]
+mod proptests;
+mod range;
+mod ratelimit;
+mod search;
+mod sketch;
+mod stats;
+mod storage;
+mod streaming;
+mod string;
+mod tiered;
+mod timeseries;
+mod tracking;
+
+pub use client::{ClientId, ClientOutput};
+pub use command::{
+    Command, FlushMode, RangeOptions, ScanOptions, SearchOptions, SetCondition, SetOptions,
+    TsOptions,
+};
+pub use custom::{CustomCommand, Keyspace};
+pub use events::{KeyEvent, KeyEventKind};
+pub use hash::{ListpackLimits, RedisHash};
+pub use hooks::CommandContext;
+pub use loading::RdbLoader;
+pub use stats::StatsSnapshot;
+pub use storage::{MemoryStorage, Storage};
+pub use string::RedisString;
+pub use tiered::TieredStorage;
+
+/// Extracts the command name from a request, which is either a bare string or an array whose
+/// first element is the name.
+fn command_name(resp: &RespData) -> Option<&str> {
+    match resp {
+        RespData::SimpleString(str) => Some(str),
+        RespData::BulkString(str) => Some(str),
+        RespData::Array(arr) => match arr.first() {
+            Some(RespData::BulkString(str)) => Some(str),
+            Some(RespData::SimpleString(str)) => Some(str),
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+/// Whether a command modifies the keyspace, according to its flags in the command table.
+fn is_write_command(name: &str) -> bool {
+    command_table::lookup(name).is_some_and(|spec| spec.is_write())
+}
+
+/// Whether a command may grow memory usage and must be refused while over `maxmemory`.
+fn is_denyoom_command(name: &str) -> bool {
+    command_table::lookup(name).is_some_and(|spec| spec.flags & command_table::DENYOOM != 0)
+}
+
+#[derive(PartialEq)]
+pub enum RedisValue {
+    String(RedisString),
+    Hash(RedisHash),
+    Json(Json),
+    Bloom(BloomFilter),
+    CountMin(CountMinSketch),
+    TopK(TopK),
+    TimeSeries(TimeSeries),
+}
+
+impl RedisValue {
+    /// The type name Redis reports for the value.
+    pub(crate) fn type_name(&self) -> &'static str {
+        match self {
+            RedisValue::String(_) => "string",
+            RedisValue::Hash(_) => "hash",
+            RedisValue::Json(_) => "ReJSON-RL",
+            RedisValue::Bloom(_) => "MBbloom--",
+            RedisValue::CountMin(_) => "CMSk-TYPE",
+            RedisValue::TopK(_) => "TopK-TYPE",
+            RedisValue::TimeSeries(_) => "TSDB-TYPE",
+        }
+    }
+}
+
+pub struct CommandHandler {
+    /// The keyspace and the keys' TTLs.
+    db: Box<dyn Storage>,
+    /// When each key in `db` was last read or written, for LRU bookkeeping.
+    access_times: Dict<String, Instant>,
+    /// How often each key in `db` is accessed, for the LFU eviction policies.
+    lfu_counters: Dict<String, LfuCounter>,
+    /// Estimated bytes held by the keys and values in `db`.
+    used_memory: u64,
+    /// The highest `used_memory` has been since startup.
+    used_memory_peak: u64,
+    lazyfree: LazyFree,
+    /// What `random_state` and the hashing of the default storage were seeded with.
+    seed: u64,
+    /// State of the generator behind every random choice, like sampling eviction candidates.
+    random_state: u64,
+    eviction_pool: EvictionPool,
+    config: Arc<RwLock<Config>>,
+    stats: Stats,
+    latency_monitor: LatencyMonitor,
+    clients: BTreeMap<ClientId, Client>,
+    next_client_id: ClientId,
+    /// The client whose command is being executed, if it came in over a connection.
+    current_client: Option<ClientId>,
+    pause: Option<ClientPause>,
+    acl: Acl,
+    /// Number of writes since the last successful save.
+    dirty: u64,
+    last_save: SystemTime,
+    /// When the last automatic save was attempted, if it failed.
+    last_save_failure: Option<Instant>,
+    backups: Backups,
+    /// Set while the RDB file is being loaded at startup.
+    loading: Option<Loading>,
+    /// Number of times the periodic tasks have run.
+    cron_loops: u64,
+    /// Whether the periodic tasks look for expired keys; DEBUG SET-ACTIVE-EXPIRE turns it off.
+    active_expire_enabled: bool,
+    /// Replication ID of this server's history, as reported by INFO replication.
+    replid: String,
+    shutdown_requested: bool,
+    /// The time that expiry deadlines and access times are measured against.
+    clock: Arc<dyn Clock>,
+    /// Where write commands are recorded, when `audit-log` is set.
+    audit_log: Option<AuditLog>,
+    /// Commands each connected IP address may still send under `ip-rate-limit`.
+    ip_rate_limits: HashMap<IpAddr, TokenBucket>,
+    /// The clients tracking each key, for CLIENT TRACKING.
+    tracking_table: HashMap<String, HashSet<ClientId>>,
+    /// The clients in broadcasting mode registered for each prefix.
+    tracking_prefixes: HashMap<String, HashSet<ClientId>>,
+    /// Keys to broadcast the invalidation of by prefix, with the client that modified each
+    /// one if only one did.
+    pending_broadcasts: HashMap<String, BTreeMap<String, Option<ClientId>>>,
+    /// The full-text indexes made by FT.CREATE, by name.
+    search_indexes: BTreeMap<String, SearchIndex>,
+    /// Whether the reply to the running command may be written straight to the client.
+    reply_streaming: ReplyStreaming,
+    /// Hooks registered with `on_key_event`.
+    key_event_callbacks: Vec<KeyEventCallback>,
+    /// Commands added with `register_command`, by lowercased name.
+    custom_commands: HashMap<String, CustomCommand>,
+    /// Hooks registered with `before_command` and `after_command`.
+    before_command_hooks: Vec<BeforeCommandHook>,
+    after_command_hooks: Vec<AfterCommandHook>,
+}
+
+impl CommandHandler {
+    #[cfg(test)]
+    pub fn from(db: HashMap<String, RedisValue>) -> Self {
+        Self::new(db, Arc::new(RwLock::new(Config::default())))
+    }
+
+    /// A handler serving `db` from memory, with the settings in `config`, which may change
+    /// while it runs.
+    pub fn new(db: HashMap<String, RedisValue>, config: Arc<RwLock<Config>>) -> Self {
+        Self::build(config, |hash_seed| {
+            let mut storage = MemoryStorage::with_seed(hash_seed);
+            for (key, value) in db {
+                storage.insert(key, value);
+            }
+            Box::new(storage)
+        })
+    }
+
+    /// A handler serving the keys in `storage`.
+    pub fn with_storage(storage: Box<dyn Storage>, config: Arc<RwLock<Config>>) -> Self {
+        Self::build(config, |_| storage)
+    }
+
+    /// Sets up a handler around the storage made by `storage`, given the seed to hash keys
+    /// with.
+    fn build(config: Arc<RwLock<Config>>, storage: impl FnOnce(u64) -> Box<dyn Storage>) -> Self {
+        let seed = config.read().unwrap().seed.unwrap_or_else(|| {
+            SystemTime::now()
+                .duration_since(SystemTime::UNIX_EPOCH)
+                .map_or(0, |elapsed| elapsed.as_nanos() as u64)
+        });
+        // xorshift gets stuck at zero, so make sure the state never is.
+        let mut random_state = seed.max(1);
+        let hash_seed = xorshift64_star(&mut random_state);
+        let replid = debug::random_replid(&mut random_state);
+        let mut handler = Self {
+            db: storage(hash_seed),
+            access_times: Dict::with_seed(hash_seed),
+            lfu_counters: Dict::with_seed(hash_seed),
+            used_memory: 0,
+            used_memory_peak: 0,
+            lazyfree: LazyFree::new(),
+            seed,
+            random_state,
+            eviction_pool: EvictionPool::default(),
+            config,
+            stats: Stats::default(),
+            latency_monitor: LatencyMonitor::default(),
+            clients: BTreeMap::new(),
+            next_client_id: 0,
+            current_client: None,
+            pause: None,
+            acl: Acl::default(),
+            dirty: 0,
+            last_save: SystemTime::now(),
+            last_save_failure: None,
+            backups: Backups::default(),
+            loading: None,
+            cron_loops: 0,
+            active_expire_enabled: true,
+            replid,
+            shutdown_requested: false,
+            clock: Arc::new(SystemClock),
+            audit_log: None,
+            ip_rate_limits: HashMap::new(),
+            tracking_table: HashMap::new(),
+            tracking_prefixes: HashMap::new(),
+            pending_broadcasts: HashMap::new(),
+            search_indexes: BTreeMap::new(),
+            reply_streaming: ReplyStreaming::Off,
+            key_event_callbacks: Vec::new(),
+            custom_commands: HashMap::new(),
+            before_command_hooks: Vec::new(),
+            after_command_hooks: Vec::new(),
+        };
+        handler.recompute_used_memory();
+        handler
+    }
+
+    /// Runs a command outside of any client connection and returns its reply.
+    pub fn handle(&mut self, resp: &RespData) -> RespData {
+        let span = telemetry::command_span();
+        if !span.is_disabled() {
+            if let Some(name) = command_name(resp) {
+                telemetry::record_command(&span, name, self.key_count(name, resp));
+            }
+        }
+        let reply = span.in_scope(|| self.with_command_hooks(resp, Self::dispatch));
+        self.flush_broadcasts();
+        telemetry::record_reply(&span, &reply);
+        reply
+    }
+
+    /// How many keys `resp` names, for the command's span.
+    fn key_count(&self, name: &str, resp: &RespData) -> usize {
+        match resp {
+            RespData::Array(args) => acl::keys_of(&name.to_lowercase(), args).len(),
+            _ => 0,
+        }
+    }
+
+    fn dispatch(&mut self, resp: &RespData) -> RespData {
+        self.stats.total_commands_processed += 1;
+
+        let Some(cmd) = command_name(resp) else {
+            return self.reject(None, errors::unknown_command("", &[]));
+        };
+        let args = match resp {
+            RespData::Array(arr) => &arr[1..],
+            _ => &[],
+        };
+
+        let Some(name) = self.resolve_command_name(&cmd.to_lowercase()) else {
+            return self.reject(None, errors::unknown_command(cmd, args));
+        };
+        self.touch_current_client(&name, resp);
+        if let Some(denied) = self.protected_denied(&name) {
+            return self.reject(Some(&name), denied);
+        }
+        if self.auth_required(&name) {
+            return self.reject(Some(&name), errors::no_auth());
+        }
+        if !Self::acl_exempt(&name) {
+            if let Some(denied) = self.acl_denied(&name, resp) {
+                return self.reject(Some(&name), denied);
+            }
+        }
+        if let Some(denied) = self.loading_denied(&name) {
+            return self.reject(Some(&name), denied);
+        }
+        if !self.perform_evictions() && is_denyoom_command(&name) {
+            return self.reject(Some(&name), errors::oom());
+        }
+        let start = Instant::now();
+        let Some(reply) = self
+            .subcommand_help(&name, resp)
+            .or_else(|| self.execute(&name, resp))
+        else {
+            return self.reject(None, errors::unknown_command(cmd, args));
+        };
+        if errors::is_wrong_arity(&reply) {
+            return self.reject(Some(&name), reply);
+        }
+        let duration = start.elapsed();
+        self.stats.record_call(&name, duration, &reply);
+        self.track_keys(&name, resp);
+        if is_write_command(&name) {
+            if !matches!(reply, RespData::Error(_)) {
+                self.dirty += 1;
+                self.invalidate_keys(&name, resp);
+            }
+            self.audit(&name, resp, &reply);
+        }
+        let threshold = self.config.read().unwrap().latency_monitor_threshold;
+        self.latency_monitor.sample("command", duration, threshold);
+        reply
+    }
+
+    /// Maps the name a client used to the command it refers to, taking `rename-command` into
+    /// account. Returns `None` for commands that were renamed away or disabled.
+    fn resolve_command_name(&self, name: &str) -> Option<String> {
+        let config = self.config.read().unwrap();
+        if let Some((original, _)) = config
+            .rename_commands
+            .iter()
+            .find(|(_, new_name)| !new_name.is_empty() && *new_name == name)
+        {
+            return Some(original.clone());
+        }
+        if config.rename_commands.contains_key(name) {
+            return None;
+        }
+        Some(name.to_string())
+    }
+
+    /// Runs a command by its lowercased name, returning `None` if no such command exists.
+    fn execute(&mut self, name: &str, resp: &RespData) -> Option<RespData> {
+        let args = match resp {
+            RespData::Array(arr) => &arr[1..],
+            _ => &[],
+        };
+        if let Some(command) = Command::parse(name, args) {
+            return Some(command.map_or_else(|error| error, |command| self.run(command)));
+        }
+        let reply = match name {
+            "auth" => self.auth(resp),
+            "hello" => self.hello(resp),
+            "time" => self.time(resp),
+            "quit" => self.quit(),
+            "reset" => self.reset(resp),
+            "lolwut" => self.lolwut(resp),
+            "readonly" | "readwrite" => self.cluster_read_mode(name, resp),
+            "cluster" => self.cluster(resp),
+            "info" => self.info(resp),
+            "object" => self.object(resp),
+            "debug" => self.debug(resp),
+            "memory" => self.memory(resp),
+            "client" => self.client(resp),
+            "config" => self.config(resp),
+            "latency" => self.latency(resp),
+            "acl" => self.acl(resp),
+            "save" => self.save(resp),
+            "shutdown" => self.shutdown(resp),
+            _ => return self.execute_custom(name, resp),
+        };
+        Some(reply)
+    }
+
+    /// Records an error reply produced before any command ran, as a rejected call of `name`
+    /// when the command is known.
+    fn reject(&mut self, name: Option<&str>, reply: RespData) -> RespData {
+        match name {
+            Some(name) => self.stats.record_rejected_call(name, &reply),
+            None => self.stats.record_error(&reply),
+        }
+        reply
+    }
+
+    /// Looks up a key for reading, expiring it first if needed and updating the hit/miss stats.
+    fn lookup_key_read(&mut self, key: &str) -> Option<&RedisValue> {
+        self.expire_if_needed(key);
+        if self.db.contains_key(key) {
+            self.stats.keyspace_hits += 1;
+            self.touch_key(key);
+        } else {
+            self.stats.keyspace_misses += 1;
+        }
+        self.db.get(key)
+    }
+
+    /// Records an access to `key`, unless the current client opted out with CLIENT NO-TOUCH.
+    fn touch_key(&mut self, key: &str) {
+        if self.current_client_no_touch() {
+            return;
+        }
+        match self.access_times.get_mut(key) {
+            Some(time) => *time = self.clock.now(),
+            None => {
+                self.access_times.insert(key.to_string(), self.clock.now());
+            }
+        }
+        let (log_factor, decay_time) = {
+            let config = self.config.read().unwrap();
+            (config.lfu_log_factor, config.lfu_decay_time)
+        };
+        let random = self.random_fraction();
+        match self.lfu_counters.get_mut(key) {
+            Some(counter) => counter.touch(log_factor, decay_time, random),
+            None => {
+                self.lfu_counters.insert(key.to_string(), LfuCounter::new());
+            }
+        }
+    }
+
+    /// Removes a key along with its TTL and access metadata, invalidating it for the clients
+    /// tracking it.
+    fn delete_key(&mut self, key: &str) -> Option<RedisValue> {
+        self.invalidate_key(key);
+        self.access_times.remove(key);
+        self.lfu_counters.remove(key);
+        let value = self.db.remove(key)?;
+        self.used_memory = self
+            .used_memory
+            .saturating_sub(memory::entry_size(key, &value));
+        Some(value)
+    }
+
+    fn set(&mut self, key: &str, value: &str, options: SetOptions) -> RespData {
+        let deadline = match options.expire.map(|ttl| self.deadline_after(ttl)) {
+            Some(None) => return errors::invalid_expire_time("set"),
+            deadline => deadline.flatten(),
+        };
+        self.expire_if_needed(key);
+        let before = self.key_memory(key);
+        let old = self
+            .db
+            .insert(key.to_string(), RedisValue::String(RedisString::new(value)));
+        self.account_key_change(key, before);
+        self.notify_key_event(KeyEventKind::Set, key);
+        if let Some(old) = old {
+            if self.config.read().unwrap().lazyfree_lazy_server_del {
+                self.lazyfree.free_value(old);
+            }
+        }
+        self.touch_key(key);
+        match deadline {
+            Some(deadline) => self.db.set_expire(key, deadline),
+            None if !options.keep_ttl => {
+                self.db.persist(key);
+            }
+            None => {}
+        }
+        RespData::SimpleString("OK".to_string())
+    }
+
+    fn get(&mut self, key: &str) -> RespData {
+        self.lookup_key_read(key)
+            .map_or(RespData::Null, |value| match value {
+                RedisValue::String(value) => RespData::BulkString(value.to_string()),
+                _ => errors::wrong_type(),
+            })
+    }
+
+    fn hset(&mut self, hash_key: &str, pairs: Vec<(String, String)>) -> RespData {
+        self.expire_if_needed(hash_key);
+        self.touch_key(hash_key);
+        let before = self.key_memory(hash_key);
+        let limits = ListpackLimits::from(&*self.config.read().unwrap());
+        let hash_map = match self.db.get_mut(hash_key) {
+            Some(RedisValue::Hash(map)) => map,
+            None => {
+                self.db
+                    .insert(hash_key.to_string(), RedisValue::Hash(RedisHash::default()));
+                if let RedisValue::Hash(map) = self.db.get_mut(hash_key).unwrap() {
+                    map
+                } else {
+                    unreachable!()
+                }
+            }
+            _ => {
+                return errors::wrong_type();
+            }
+        };
+
+        let new_fields_count = hash_map.insert_pairs(pairs, limits);
+        self.account_key_change(hash_key, before);
+        self.notify_key_event(KeyEventKind::Set, hash_key);
+
+        RespData::Integer(new_fields_count as i64)
+    }
+
+    fn hget(&mut self, hash_key: &str, field: &str) -> RespData {
+        match self.lookup_key_read(hash_key) {
+            Some(RedisValue::Hash(map)) => map
+                .get(field)
+                .map_or(RespData::Null, |value| RespData::BulkString(value.clone())),
+            Some(_) => errors::wrong_type(),
+            None => RespData::Null,
+        }
+    }
+
+    fn hlen(&mut self, hash_key: &str) -> RespData {
+        match self.lookup_key_read(hash_key) {
+            Some(RedisValue::Hash(map)) => RespData::Integer(map.len() as i64),
+            Some(_) => errors::wrong_type(),
+            None => RespData::Integer(0),
+        }
+    }
+
+    fn hgetall(&mut self, hash_key: &str) -> RespData {
+        let may_stream = self.reply_streaming == ReplyStreaming::Allowed;
+        match self.lookup_key_read(hash_key) {
+            Some(RedisValue::Hash(map)) => {
+                if may_stream && map.len() >= streaming::STREAMED_REPLY_MIN_LEN {
+                    self.reply_streaming = ReplyStreaming::Hash(hash_key.to_string());
+                    return RespData::Array(Vec::new());
+                }
+                let mut result = Vec::new();
+                for (field, value) in map {
+                    result.push(RespData::BulkString(field.clone()));
+                    result.push(RespData::BulkString(value.clone()));
+                }
+                RespData::Array(result)
+            }
+            Some(_) => errors::wrong_type(),
+            None => RespData::Array(vec![]),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::resp::RespData;
+    use std::collections::HashMap;
+
+    fn create_empty_handler() -> CommandHandler {
+        CommandHandler::from(HashMap::new())
+    }
+
+    fn command(args: &[&str]) -> RespData {
+        RespData::array(args.iter().copied())
+    }
+
+    #[test]
+    fn test_ping() {
+        let mut handler = create_empty_handler();
+
+        let result = handler.run(Command::Ping);
+
+        assert_eq!(result, RespData::SimpleString("PONG".to_string()));
+    }
+
+    #[test]
+    fn test_rename_command() {
+        let mut config = Config::default();
+        config
+            .rename_commands
+            .insert("ping".to_string(), "knock".to_string());
+        config
+            .rename_commands
+            .insert("time".to_string(), String::new());
+        let mut handler = CommandHandler::new(HashMap::new(), Arc::new(RwLock::new(config)));
+
+        let test_cases = [
+            (
+                "Renamed command under its new name",
+                "KNOCK",
+                RespData::SimpleString("PONG".to_string()),
+            ),
+            (
+                "Renamed command under its old name",
+                "PING",
+                RespData::Error("unknown command 'PING', with args beginning with: ".to_string()),
+            ),
+            (
+                "Disabled command",
+                "TIME",
+                RespData::Error("unknown command 'TIME', with args beginning with: ".to_string()),
+            ),
+        ];
+
+        for (name, input, expected) in test_cases {
+            let input = RespData::Array(vec![RespData::BulkString(input.to_string())]);
+            assert_eq!(handler.handle(&input), expected, "{}", name);
+        }
+        assert_eq!(handler.stats.commands["ping"].calls, 1);
+    }
+
+    #[test]
+    fn test_set() {
+        let mut handler = create_empty_handler();
+
+        let test_cases = [
+            (
+                "Valid SET command",
+                RespData::Array(vec![
+                    RespData::BulkString("SET".to_string()),
+                    RespData::BulkString("key1".to_string()),
+                    RespData::BulkString("value1".to_string()),
+                ]),
+                RespData::SimpleString("OK".to_string()),
+            ),
+            (
+                "Not enough arguments",
+                RespData::Array(vec![
+                    RespData::BulkString("SET".to_string()),
+                    RespData::BulkString("key1".to_string()),
+                ]),
+                RespData::Error("wrong number of arguments for 'set' command".to_string()),
+            ),
+            (
+                "Too many arguments",
+                RespData::Array(vec![
+                    RespData::BulkString("SET".to_string()),
+                    RespData::BulkString("key1".to_string()),
+                    RespData::BulkString("value1".to_string()),
+                    RespData::BulkString("value2".to_string()),
+                ]),
+                RespData::Error("syntax error".to_string()),
+            ),
+            (
+                "Valid SET with an expiry",
+                RespData::Array(vec![
+                    RespData::BulkString("SET".to_string()),
+                    RespData::BulkString("key2".to_string()),
+                    RespData::BulkString("value2".to_string()),
+                    RespData::BulkString("EX".to_string()),
+                    RespData::BulkString("100".to_string()),
+                ]),
+                RespData::SimpleString("OK".to_string()),
+            ),
+            (
+                "Invalid expire time",
+                RespData::Array(vec![
+                    RespData::BulkString("SET".to_string()),
+                    RespData::BulkString("key2".to_string()),
+                    RespData::BulkString("value2".to_string()),
+                    RespData::BulkString("PX".to_string()),
+                    RespData::BulkString("0".to_string()),
+                ]),
+                RespData::Error("invalid expire time in 'set' command".to_string()),
+            ),
+            (
+                "Expire time too large",
+                RespData::Array(vec![
+                    RespData::BulkString("SET".to_string()),
+                    RespData::BulkString("key3".to_string()),
+                    RespData::BulkString("value3".to_string()),
+                    RespData::BulkString("EX".to_string()),
+                    RespData::BulkString("9223372036854775807".to_string()),
+                ]),
+                RespData::Error("invalid expire time in 'set' command".to_string()),
+            ),
+            (
+                "Conflicting expiry options",
+                RespData::Array(vec![
+                    RespData::BulkString("SET".to_string()),
+                    RespData::BulkString("key2".to_string()),
+                    RespData::BulkString("value2".to_string()),
+                    RespData::BulkString("EX".to_string()),
+                    RespData::BulkString("100".to_string()),
+                    RespData::BulkString("KEEPTTL".to_string()),
+                ]),
+                RespData::Error("syntax error".to_string()),
+            ),
+        ];
+
+        for (name, input, expected_output) in test_cases {
+            let result = handler.handle(&input);
+            assert_eq!(result, expected_output, "{}", name);
+        }
+        assert!(handler.db.expire_at("key1").is_none());
+        assert!(handler.db.expire_at("key2").is_some());
+        assert!(!handler.db.contains_key("key3"));
+    }
+
+    #[test]
+    fn test_get() {
+        let mut handler = create_empty_handler();
+
+        handler.db.insert(
+            "existing_key".to_string(),
+            RedisValue::String("existing_value".into()),
+        );
+        handler.db.insert(
+            "hash_key".to_string(),
+            RedisValue::Hash(RedisHash::default()),
+        );
+
+        let test_cases = [
+            (
+                "Valid GET for existing key",
+                RespData::Array(vec![
+                    RespData::BulkString("GET".to_string()),
+                    RespData::BulkString("existing_key".to_string()),
+                ]),
+                RespData::BulkString("existing_value".to_string()),
+            ),
+            (
+                "Valid GET for non-existing key",
+                RespData::Array(vec![
+                    RespData::BulkString("GET".to_string()),
+                    RespData::BulkString("non_existing_key".to_string()),
+                ]),
+                RespData::Null,
+            ),
+            (
+                "GET for a hash",
+                RespData::Array(vec![
+                    RespData::BulkString("GET".to_string()),
+                    RespData::BulkString("hash_key".to_string()),
+                ]),
+                errors::wrong_type(),
+            ),
+            (
+                "Not enough arguments",
+                RespData::Array(vec![RespData::BulkString("GET".to_string())]),
+                RespData::Error("wrong number of arguments for 'get' command".to_string()),
+            ),
+            (
+                "Too many arguments",
+                RespData::Array(vec![
+                    RespData::BulkString("GET".to_string()),
+                    RespData::BulkString("key1".to_string()),
+                    RespData::BulkString("key2".to_string()),
+                ]),
+                RespData::Error("wrong number of arguments for 'get' command".to_string()),
+            ),
+        ];
+
+        for (name, input, expected_output) in test_cases {
+            let result = handler.handle(&input);
+            assert_eq!(result, expected_output, "{}", name);
+        }
+    }
+
+    #[test]
+    fn test_hset() {
+        let mut handler = create_empty_handler();
+
+        let mut initial_hash = RedisHash::default();
+        initial_hash.insert("field1".to_string(), "value1".to_string());
+        handler
+            .db
+            .insert("existing_hash".to_string(), RedisValue::Hash(initial_hash));
+        handler.db.insert(
+            "string_key".to_string(),
+            RedisValue::String("string_value".into()),
+        );
+
+        let test_cases = [
+            (
+                "Valid HSET create a new hash",
+                RespData::Array(vec![
+                    RespData::BulkString("HSET".to_string()),
+                    RespData::BulkString("new_hash".to_string()),
+                    RespData::BulkString("field1".to_string()),
+                    RespData::BulkString("value1".to_string()),
+                ]),
+                RespData::Integer(1), // New field
+            ),
+            (
+                "Valid HSET adding new field to existing hash",
+                RespData::Array(vec![
+                    RespData::BulkString("HSET".to_string()),
+                    RespData::BulkString("existing_hash".to_string()),
+                    RespData::BulkString("field2".to_string()),
+                    RespData::BulkString("value2".to_string()),
+                ]),
+                RespData::Integer(1), // New field
+            ),
+            (
+                "Valid HSET updating existing field",
+                RespData::Array(vec![
+                    RespData::BulkString("HSET".to_string()),
+                    RespData::BulkString("existing_hash".to_string()),
+                    RespData::BulkString("field1".to_string()),
+                    RespData::BulkString("new_value".to_string()),
+                ]),
+                RespData::Integer(0), // Existing field
+            ),
+            (
+                "Not enough arguments",
+                RespData::Array(vec![
+                    RespData::BulkString("HSET".to_string()),
+                    RespData::BulkString("hash".to_string()),
+                    RespData::BulkString("field".to_string()),
+                ]),
+                RespData::Error("wrong number of arguments for 'hset' command".to_string()),
+            ),
+            (
+                "Invalid key value pair arguments",
+                RespData::Array(vec![
+                    RespData::BulkString("HSET".to_string()),
+                    RespData::BulkString("hash".to_string()),
+                    RespData::BulkString("field1".to_string()),
+                    RespData::BulkString("value1".to_string()),
+                    RespData::BulkString("field2".to_string()),
+                ]),
+                RespData::Error("wrong number of arguments for 'hset' command".to_string()),
+            ),
+        ];
+
+        for (name, input, expected_output) in test_cases {
+            let result = handler.handle(&input);
+            assert_eq!(result, expected_output, "{}", name);
+        }
+    }
+
+    #[test]
+    fn test_hget() {
+        let mut handler = create_empty_handler();
+
+        // Set up some test data in the DB
+        let mut test_hash = RedisHash::default();
+        test_hash.insert("existing_field".to_string(), "field_value".to_string());
+        handler
+            .db
+            .insert("existing_hash".to_string(), RedisValue::Hash(test_hash));
+        handler.db.insert(
+            "string_key".to_string(),
+            RedisValue::String("string_value".into()),
+        );
+
+        // Test cases with different inputs
+        let test_cases = [
+            (
+                "Valid HGET for existing hash and field",
+                RespData::Array(vec![
+                    RespData::BulkString("HGET".to_string()),
+                    RespData::BulkString("existing_hash".to_string()),
+                    RespData::BulkString("existing_field".to_string()),
+                ]),
+                RespData::BulkString("field_value".to_string()),
+            ),
+            (
+                "Valid HGET for existing hash but non-existing field",
+                RespData::Array(vec![
+                    RespData::BulkString("HGET".to_string()),
+                    RespData::BulkString("existing_hash".to_string()),
+                    RespData::BulkString("non_existing_field".to_string()),
+                ]),
+                RespData::Null,
+            ),
+            (
+                "HGET for non-existing hash",
+                RespData::Array(vec![
+                    RespData::BulkString("HGET".to_string()),
+                    RespData::BulkString("non_existing_hash".to_string()),
+                    RespData::BulkString("field".to_string()),
+                ]),
+                RespData::Null,
+            ),
+            (
+                "Not enough arguments",
+                RespData::Array(vec![
+                    RespData::BulkString("HGET".to_string()),
+                    RespData::BulkString("hash".to_string()),
+                ]),
+                RespData::Error("wrong number of arguments for 'hget' command".to_string()),
+            ),
+        ];
+
+        for (name, input, expected_output) in test_cases {
+            let result = handler.handle(&input);
+            assert_eq!(result, expected_output, "{}", name);
+        }
+    }
+
+    #[test]
+    fn test_hlen() {
+        let mut handler = create_empty_handler();
+        handler.handle(&command(&["HSET", "hash", "a", "1", "b", "2"]));
+        handler.handle(&command(&["SET", "string_key", "value"]));
+
+        let test_cases = [
+            (
+                "Existing hash",
+                command(&["HLEN", "hash"]),
+                RespData::Integer(2),
+            ),
+            (
+                "Missing key",
+                command(&["HLEN", "missing"]),
+                RespData::Integer(0),
+            ),
+            (
+                "Wrong type",
+                command(&["HLEN", "string_key"]),
+                RespData::Error(
+                    "-WRONGTYPE Operation against a key holding the wrong kind of value"
+                        .to_string(),
+                ),
+            ),
+            (
+                "Wrong number of arguments",
+                command(&["HLEN"]),
+                RespData::Error("wrong number of arguments for 'hlen' command".to_string()),
+            ),
+        ];
+
+        for (name, input, expected) in test_cases {
+            assert_eq!(handler.handle(&input), expected, "{}", name);
+        }
+    }
+
+    #[test]
+    fn test_hgetall() {
+        let mut handler = create_empty_handler();
+
+        handler.handle(&RespData::array(["HSET", "hash_key", "field2", "value2"]));
+        handler.handle(&RespData::array(["HSET", "hash_key", "field1", "value1"]));
+        handler.handle(&RespData::array(["HSET", "hash_key", "field2", "updated"]));
+
+        handler.db.insert(
+            "string_key".to_string(),
+            RedisValue::String("some_string".into()),
+        );
+
+        let test_cases = [
+            (
+                "Valid HGETALL for existing hash",
+                RespData::Array(vec![
+                    RespData::BulkString("HGETALL".to_string()),
+                    RespData::BulkString("hash_key".to_string()),
+                ]),
+                // A small hash replies in the order its fields were added.
+                RespData::Array(vec![
+                    RespData::BulkString("field2".to_string()),
+                    RespData::BulkString("updated".to_string()),
+                    RespData::BulkString("field1".to_string()),
+                    RespData::BulkString("value1".to_string()),
+                ]),
+            ),
+            (
+                "HGETALL for non-existing hash",
+                RespData::Array(vec![
+                    RespData::BulkString("HGETALL".to_string()),
+                    RespData::BulkString("non_existing_key".to_string()),
+                ]),
+                RespData::Array(vec![]),
+            ),
+            (
+                "HGETALL for a string",
+                RespData::Array(vec![
+                    RespData::BulkString("HGETALL".to_string()),
+                    RespData::BulkString("string_key".to_string()),
+                ]),
+                errors::wrong_type(),
+            ),
+            (
+                "Not enough arguments",
+                RespData::Array(vec![RespData::BulkString("HGETALL".to_string())]),
+                RespData::Error("wrong number of arguments for 'hgetall' command".to_string()),
+            ),
+        ];
+
+        for (name, input, expected) in test_cases {
+            assert_eq!(handler.handle(&input), expected, "{}", name);
+        }
+    }
+}
@@ -0,0 +1,291 @@
+use super::errors;
+use super::{CommandHandler, RedisValue};
+use crate::resp::RespData;
+use std::time::Duration;
+
+/// The encoding Redis would report for `value`.
+pub(super) fn encoding(value: &RedisValue) -> &'static str {
+    match value {
+        RedisValue::String(s) => s.encoding(),
+        RedisValue::Hash(hash) => hash.encoding(),
+        _ => "raw",
+    }
+}
+
+impl CommandHandler {
+    /// OBJECT ENCODING|FREQ|IDLETIME|REFCOUNT key: inspects a key without counting as an
+    /// access to it.
+    pub(super) fn object(&mut self, resp: &RespData) -> RespData {
+        let RespData::Array(arr) = resp else {
+            return errors::syntax_error();
+        };
+        let Some(RespData::BulkString(name)) = arr.get(1) else {
+            return errors::wrong_arity("object");
+        };
+        let subcommand = name.to_lowercase();
+        if !matches!(
+            subcommand.as_str(),
+            "encoding" | "freq" | "idletime" | "refcount"
+        ) {
+            return errors::unknown_subcommand(name, "object");
+        }
+        let [_, _, RespData::BulkString(key)] = arr.as_slice() else {
+            return errors::wrong_arity(&format!("object|{subcommand}"));
+        };
+
+        let config = self.config.read().unwrap();
+        let (lfu, decay_time) = (config.maxmemory_policy.is_lfu(), config.lfu_decay_time);
+        drop(config);
+        match subcommand.as_str() {
+            "freq" if !lfu => {
+                return RespData::Error(
+                    "An LFU maxmemory policy is not selected, access frequency not tracked. \
+                     Please note that when switching between policies at runtime LRU and LFU \
+                     data will take some time to adjust."
+                        .to_string(),
+                )
+            }
+            "idletime" if lfu => {
+                return RespData::Error(
+                    "An LRU maxmemory policy is not selected, access time not tracked. \
+                     Please note that when switching between policies at runtime LRU and LFU \
+                     data will take some time to adjust."
+                        .to_string(),
+                )
+            }
+            _ => {}
+        }
+
+        self.expire_if_needed(key);
+        let Some(value) = self.db.get(key) else {
+            return RespData::Null;
+        };
+        match subcommand.as_str() {
+            "encoding" => RespData::BulkString(encoding(value).to_string()),
+            "freq" => RespData::Integer(self.key_frequency(key, decay_time) as i64),
+            "idletime" => RespData::Integer(self.object_idle_time(key).as_secs() as i64),
+            // Values are never shared between keys.
+            _ => RespData::Integer(1),
+        }
+    }
+
+    /// How long `key` has gone without being accessed. Keys loaded from disk haven't been
+    /// touched since the server started.
+    pub(super) fn object_idle_time(&self, key: &str) -> Duration {
+        let accessed = self.access_times.get(key).unwrap_or(&self.stats.start_time);
+        self.clock.now().saturating_duration_since(*accessed)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::handler::lfu::LFU_INIT_VAL;
+    use std::collections::HashMap;
+    use std::time::{Duration, Instant};
+
+    fn command(args: &[&str]) -> RespData {
+        RespData::array(args.iter().copied())
+    }
+
+    #[test]
+    fn test_object_freq() {
+        let mut handler = CommandHandler::from(HashMap::new());
+        handler.handle(&command(&["SET", "key", "value"]));
+
+        let test_cases = [
+            (
+                "Not an LFU policy",
+                command(&["OBJECT", "FREQ", "key"]),
+                RespData::Error(
+                    "An LFU maxmemory policy is not selected, access frequency not tracked. \
+                     Please note that when switching between policies at runtime LRU and LFU \
+                     data will take some time to adjust."
+                        .to_string(),
+                ),
+            ),
+            (
+                "Switch to LFU",
+                command(&["CONFIG", "SET", "maxmemory-policy", "allkeys-lfu"]),
+                RespData::SimpleString("OK".to_string()),
+            ),
+            (
+                "New key",
+                command(&["OBJECT", "FREQ", "key"]),
+                RespData::Integer(LFU_INIT_VAL as i64),
+            ),
+            (
+                "Read bumps the counter",
+                command(&["GET", "key"]),
+                RespData::BulkString("value".to_string()),
+            ),
+            (
+                "Frequency after a read",
+                command(&["OBJECT", "FREQ", "key"]),
+                RespData::Integer(LFU_INIT_VAL as i64 + 1),
+            ),
+            (
+                "Missing key",
+                command(&["OBJECT", "FREQ", "missing"]),
+                RespData::Null,
+            ),
+            (
+                "Wrong number of arguments",
+                command(&["OBJECT", "FREQ"]),
+                RespData::Error("wrong number of arguments for 'object|freq' command".to_string()),
+            ),
+            (
+                "Unknown subcommand",
+                command(&["OBJECT", "NOPE", "key"]),
+                RespData::Error("unknown subcommand 'NOPE'. Try OBJECT HELP.".to_string()),
+            ),
+        ];
+
+        for (name, input, expected) in test_cases {
+            assert_eq!(handler.handle(&input), expected, "{}", name);
+        }
+    }
+
+    #[test]
+    fn test_object_encoding() {
+        let mut handler = CommandHandler::from(HashMap::new());
+        handler.handle(&command(&["SET", "int", "12345"]));
+        handler.handle(&command(&["SET", "short", "hello"]));
+        handler.handle(&command(&["SET", "long", &"x".repeat(45)]));
+        handler.handle(&command(&["HSET", "small", "field", "value"]));
+        handler.handle(&command(&["HSET", "wide", "field", &"x".repeat(65)]));
+
+        let test_cases = [
+            ("Integer", "int", RespData::BulkString("int".to_string())),
+            (
+                "Short string",
+                "short",
+                RespData::BulkString("embstr".to_string()),
+            ),
+            (
+                "Long string",
+                "long",
+                RespData::BulkString("raw".to_string()),
+            ),
+            (
+                "Small hash",
+                "small",
+                RespData::BulkString("listpack".to_string()),
+            ),
+            (
+                "Hash with a long value",
+                "wide",
+                RespData::BulkString("hashtable".to_string()),
+            ),
+            ("Missing key", "missing", RespData::Null),
+        ];
+
+        for (name, key, expected) in test_cases {
+            assert_eq!(
+                handler.handle(&command(&["OBJECT", "ENCODING", key])),
+                expected,
+                "{}",
+                name
+            );
+        }
+
+        // Like Redis, a hash is converted as it is written, and never back.
+        handler.handle(&command(&[
+            "CONFIG",
+            "SET",
+            "hash-max-listpack-entries",
+            "1",
+        ]));
+        handler.handle(&command(&[
+            "CONFIG",
+            "SET",
+            "hash-max-listpack-value",
+            "100",
+        ]));
+        assert_eq!(
+            handler.handle(&command(&["OBJECT", "ENCODING", "small"])),
+            RespData::bulk("listpack"),
+            "Over the thresholds until written"
+        );
+        handler.handle(&command(&["HSET", "other", "field", "value"]));
+        handler.handle(&command(&["HSET", "small", "another", "value"]));
+        handler.handle(&command(&["HSET", "wide", "field", "short"]));
+        let test_cases = [
+            ("Written under the thresholds", "other", "listpack"),
+            ("Over the entries threshold", "small", "hashtable"),
+            ("Back under the thresholds", "wide", "hashtable"),
+        ];
+        for (name, key, expected) in test_cases {
+            assert_eq!(
+                handler.handle(&command(&["OBJECT", "ENCODING", key])),
+                RespData::bulk(expected),
+                "{}",
+                name
+            );
+        }
+    }
+
+    #[test]
+    fn test_object_refcount_and_idletime() {
+        let mut handler = CommandHandler::from(HashMap::new());
+        handler.handle(&command(&["SET", "key", "value"]));
+        handler.handle(&command(&["SET", "shared", "100"]));
+        handler
+            .access_times
+            .insert("key".to_string(), Instant::now() - Duration::from_secs(30));
+
+        let test_cases = [
+            (
+                "Refcount",
+                command(&["OBJECT", "REFCOUNT", "key"]),
+                RespData::Integer(1),
+            ),
+            (
+                "Refcount of a small integer",
+                command(&["OBJECT", "REFCOUNT", "shared"]),
+                RespData::Integer(1),
+            ),
+            (
+                "Idle time",
+                command(&["OBJECT", "IDLETIME", "key"]),
+                RespData::Integer(30),
+            ),
+            (
+                "Inspecting doesn't reset the idle time",
+                command(&["OBJECT", "IDLETIME", "key"]),
+                RespData::Integer(30),
+            ),
+            (
+                "Missing key",
+                command(&["OBJECT", "IDLETIME", "missing"]),
+                RespData::Null,
+            ),
+            (
+                "Wrong number of arguments",
+                command(&["OBJECT", "REFCOUNT", "key", "extra"]),
+                RespData::Error(
+                    "wrong number of arguments for 'object|refcount' command".to_string(),
+                ),
+            ),
+            (
+                "Switch to LFU",
+                command(&["CONFIG", "SET", "maxmemory-policy", "allkeys-lfu"]),
+                RespData::SimpleString("OK".to_string()),
+            ),
+            (
+                "Not an LRU policy",
+                command(&["OBJECT", "IDLETIME", "key"]),
+                RespData::Error(
+                    "An LRU maxmemory policy is not selected, access time not tracked. \
+                     Please note that when switching between policies at runtime LRU and LFU \
+                     data will take some time to adjust."
+                        .to_string(),
+                ),
+            ),
+        ];
+
+        for (name, input, expected) in test_cases {
+            assert_eq!(handler.handle(&input), expected, "{}", name);
+        }
+    }
+}
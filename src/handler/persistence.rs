@@ -0,0 +1,308 @@
+use super::errors;
+use super::{CommandHandler, ListpackLimits};
+use crate::rdb::{self, Entry, RdbError};
+use crate::resp::RespData;
+use std::fs::{self, File};
+use std::io::{self, BufReader, BufWriter, Write};
+use std::process;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+pub(super) fn unix_time_ms() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_millis() as u64
+}
+
+impl CommandHandler {
+    /// Writes the dataset to the configured RDB file, going through a temporary file so a
+    /// failed save never clobbers the previous snapshot.
+    pub(super) fn save_rdb(&mut self) -> io::Result<()> {
+        let path = self.config.read().unwrap().rdb_path();
+        let tmp = path.with_file_name(format!("temp-{}.rdb", process::id()));
+
+        let mut writer = BufWriter::new(File::create(&tmp)?);
+        let result = self
+            .write_snapshot(&mut writer)
+            .and_then(|()| writer.into_inner().map_err(|e| e.into_error()))
+            .and_then(|file| file.sync_all())
+            .and_then(|()| fs::rename(&tmp, &path));
+        if result.is_err() {
+            let _ = fs::remove_file(&tmp);
+        }
+        result?;
+
+        self.dirty = 0;
+        self.last_save = SystemTime::now();
+        Ok(())
+    }
+
+    /// Writes the dataset to `writer` a key at a time, since the storage may not hold every
+    /// value in memory.
+    fn write_snapshot(&self, writer: impl Write) -> io::Result<()> {
+        let now = self.clock.now();
+        let now_ms = unix_time_ms();
+        let mut out = rdb::Writer::new(writer, self.db.len(), self.db.expires_len())?;
+        let mut result = Ok(());
+        self.db.for_each(&mut |key, value| {
+            if result.is_ok() {
+                let expire_at_ms = self.db.expire_at(key).map(|deadline| {
+                    now_ms + deadline.saturating_duration_since(now).as_millis() as u64
+                });
+                result = out.write_entry(key, value, expire_at_ms);
+            }
+        });
+        result?;
+        out.finish()
+    }
+
+    /// Replaces the dataset with the contents of the configured RDB file, if there is one.
+    /// Keys whose expiry time has already passed are dropped. Returns the number of keys loaded.
+    /// Keys are stored as they are read, so a corrupt file leaves part of it loaded.
+    pub fn load_rdb(&mut self) -> Result<usize, RdbError> {
+        let path = self.config.read().unwrap().rdb_path();
+        let file = match File::open(&path) {
+            Ok(file) => file,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(0),
+            Err(e) => return Err(e.into()),
+        };
+        self.clear_dataset();
+        let now = self.clock.now();
+        let now_ms = unix_time_ms();
+        let limits = ListpackLimits::from(&*self.config.read().unwrap());
+        rdb::load_each(&mut BufReader::new(file), limits, |entry| {
+            self.restore_entry(entry, now, now_ms)
+        })?;
+        self.recompute_used_memory();
+        self.rebuild_search_indexes();
+        self.dirty = 0;
+        Ok(self.db.len())
+    }
+
+    /// The dataset as an RDB snapshot, for applications embedding the server to checkpoint it
+    /// without going through the filesystem.
+    pub fn snapshot(&self) -> Vec<u8> {
+        let mut data = Vec::new();
+        self.write_snapshot(&mut data)
+            .expect("writing to a Vec cannot fail");
+        data
+    }
+
+    /// Replaces the dataset with a snapshot taken by `snapshot`, or read from an RDB file, and
+    /// returns the number of keys restored. Keys whose expiry time has passed are dropped. The
+    /// whole snapshot is checked first, so on error the dataset is left untouched.
+    pub fn restore(&mut self, mut data: &[u8]) -> Result<usize, RdbError> {
+        let limits = ListpackLimits::from(&*self.config.read().unwrap());
+        let entries = rdb::load(&mut data, limits)?;
+        let replaced = self.db.len();
+        self.clear_dataset();
+        let now = self.clock.now();
+        let now_ms = unix_time_ms();
+        for entry in entries {
+            self.restore_entry(entry, now, now_ms);
+        }
+        self.recompute_used_memory();
+        self.rebuild_search_indexes();
+        self.dirty += (replaced + self.db.len()) as u64;
+        Ok(self.db.len())
+    }
+
+    /// Empties the dataset ahead of loading a snapshot, forgetting what was known about keys.
+    pub(super) fn clear_dataset(&mut self) {
+        self.db.clear();
+        self.access_times.clear();
+        self.lfu_counters.clear();
+        self.invalidate_all();
+    }
+
+    /// Stores a key read from a snapshot, unless it has expired since.
+    pub(super) fn restore_entry(&mut self, entry: Entry, now: Instant, now_ms: u64) {
+        if let Some(expire_at_ms) = entry.expire_at_ms {
+            if expire_at_ms <= now_ms {
+                return;
+            }
+            let ttl = Duration::from_millis(expire_at_ms - now_ms);
+            self.db.set_expire(&entry.key, now + ttl);
+        }
+        self.db.insert(entry.key, entry.value);
+    }
+
+    /// Whether a SHUTDOWN command has completed and the process should exit.
+    pub fn shutdown_requested(&self) -> bool {
+        self.shutdown_requested
+    }
+
+    /// Runs the steps before the process exits: saves the dataset when `save` asks for it (or,
+    /// if `None`, when save points are configured) and disconnects every client other than the
+    /// current one. Returns false, leaving the server running, if the save failed and `force`
+    /// is not set. A dataset still being loaded is never saved, as it would replace the full
+    /// snapshot with part of it.
+    pub fn prepare_shutdown(&mut self, save: Option<bool>, force: bool) -> bool {
+        let save = !self.is_loading()
+            && save.unwrap_or_else(|| !self.config.read().unwrap().save.is_empty());
+        if save {
+            if let Err(e) = self.save_rdb() {
+                log_warning!("Error trying to save the DB, can't exit: {e}");
+                if !force {
+                    return false;
+                }
+            }
+        }
+
+        let current = self.current_client;
+        self.kill_clients(|client| Some(client.id) != current);
+        self.shutdown_requested = true;
+        true
+    }
+
+    pub(super) fn save(&mut self, resp: &RespData) -> RespData {
+        if matches!(resp, RespData::Array(arr) if arr.len() != 1) {
+            return errors::wrong_arity("save");
+        }
+        match self.save_rdb() {
+            Ok(()) => RespData::SimpleString("OK".to_string()),
+            Err(e) => RespData::Error(format!("Error saving DB on disk: {e}")),
+        }
+    }
+
+    /// SHUTDOWN [NOSAVE|SAVE] [NOW] [FORCE] [ABORT]: see `prepare_shutdown`.
+    pub(super) fn shutdown(&mut self, resp: &RespData) -> RespData {
+        let RespData::Array(arr) = resp else {
+            return errors::syntax_error();
+        };
+
+        let (mut nosave, mut save, mut force, mut abort) = (false, false, false, false);
+        for arg in &arr[1..] {
+            let RespData::BulkString(arg) = arg else {
+                return errors::syntax_error();
+            };
+            match arg.to_uppercase().as_str() {
+                "NOSAVE" => nosave = true,
+                "SAVE" => save = true,
+                // There are no replicas to wait for, so shutting down is always immediate.
+                "NOW" => {}
+                "FORCE" => force = true,
+                "ABORT" => abort = true,
+                _ => return errors::syntax_error(),
+            }
+        }
+        if (nosave && save) || (abort && arr.len() != 2) {
+            return errors::syntax_error();
+        }
+        if abort {
+            return RespData::Error("No shutdown in progress.".to_string());
+        }
+
+        let save = match (nosave, save) {
+            (true, _) => Some(false),
+            (_, true) => Some(true),
+            _ => None,
+        };
+        if !self.prepare_shutdown(save, force) {
+            return RespData::Error("Errors trying to SHUTDOWN. Check logs.".to_string());
+        }
+        RespData::SimpleString("OK".to_string())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::Config;
+    use crate::handler::RedisValue;
+    use std::collections::HashMap;
+    use std::path::PathBuf;
+    use std::sync::{Arc, RwLock};
+
+    fn command(args: &[&str]) -> RespData {
+        RespData::array(args.iter().copied())
+    }
+
+    fn create_handler(dir: PathBuf) -> CommandHandler {
+        let config = Config {
+            dir,
+            ..Config::default()
+        };
+        CommandHandler::new(HashMap::new(), Arc::new(RwLock::new(config)))
+    }
+
+    #[test]
+    fn test_save_and_load() {
+        let dir = std::env::temp_dir().join(format!("redis-save-{}", process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let mut handler = create_handler(dir.clone());
+        handler.handle(&command(&["SET", "greeting", "hello"]));
+        handler.handle(&command(&["SET", "session", "abc", "EX", "100"]));
+        handler.handle(&command(&["HSET", "user", "name", "ada"]));
+        assert_eq!(handler.dirty, 3);
+
+        assert_eq!(
+            handler.save(&command(&["SAVE"])),
+            RespData::SimpleString("OK".to_string())
+        );
+        assert_eq!(handler.dirty, 0);
+
+        let mut restored = create_handler(dir.clone());
+        assert_eq!(restored.load_rdb().unwrap(), 3);
+        assert_eq!(
+            restored.handle(&command(&["GET", "greeting"])),
+            RespData::BulkString("hello".to_string())
+        );
+        assert!(matches!(
+            restored.handle(&command(&["TTL", "session"])),
+            RespData::Integer(99 | 100)
+        ));
+        assert!(matches!(restored.db.get("user"), Some(RedisValue::Hash(_))));
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_shutdown() {
+        let dir = std::env::temp_dir().join(format!("redis-shutdown-{}", process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let mut handler = create_handler(dir.join("missing"));
+
+        let test_cases = [
+            (
+                "Conflicting save options",
+                command(&["SHUTDOWN", "NOSAVE", "SAVE"]),
+                RespData::Error("syntax error".to_string()),
+            ),
+            (
+                "Unknown option",
+                command(&["SHUTDOWN", "LATER"]),
+                RespData::Error("syntax error".to_string()),
+            ),
+            (
+                "Abort without a shutdown in progress",
+                command(&["SHUTDOWN", "ABORT"]),
+                RespData::Error("No shutdown in progress.".to_string()),
+            ),
+            (
+                "Save fails",
+                command(&["SHUTDOWN", "SAVE"]),
+                RespData::Error("Errors trying to SHUTDOWN. Check logs.".to_string()),
+            ),
+        ];
+
+        for (name, input, expected) in test_cases {
+            assert_eq!(handler.shutdown(&input), expected, "{}", name);
+            assert!(!handler.shutdown_requested(), "{}", name);
+        }
+
+        assert_eq!(
+            handler.shutdown(&command(&["SHUTDOWN", "SAVE", "NOW", "FORCE"])),
+            RespData::SimpleString("OK".to_string())
+        );
+        assert!(handler.shutdown_requested());
+
+        let mut handler = create_handler(dir.clone());
+        handler.handle(&command(&["SET", "key", "value"]));
+        handler.shutdown(&command(&["SHUTDOWN"]));
+        assert!(handler.shutdown_requested());
+        assert!(dir.join("dump.rdb").exists());
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
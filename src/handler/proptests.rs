@@ -0,0 +1,344 @@
+//! Property-based tests: proptest generates random command sequences for each data type, runs
+//! them through a `CommandHandler`, and checks every reply against a plain model of the
+//! keyspace. After each sequence the read commands are checked against each other, e.g. that
+//! HGETALL agrees with HGET and HLEN.
+
+use super::{errors, CommandHandler};
+use crate::resp::RespData;
+use proptest::collection::vec;
+use proptest::prelude::*;
+use std::collections::{BTreeMap, HashMap};
+
+/// Few keys and fields, so sequences keep running into existing ones.
+const KEYS: &[&str] = &["k0", "k1", "k2"];
+const FIELDS: &[&str] = &["f0", "f1", "f2", "f3"];
+
+#[derive(Debug, Clone)]
+enum Op {
+    Set(&'static str, String),
+    Get(&'static str),
+    Append(&'static str, String),
+    Strlen(&'static str),
+    GetRange(&'static str, i64, i64),
+    SetRange(&'static str, usize, String),
+    IncrBy(&'static str, i64),
+    Del(Vec<&'static str>),
+    HSet(&'static str, Vec<(&'static str, String)>),
+    HGet(&'static str, &'static str),
+    HLen(&'static str),
+    HGetAll(&'static str),
+}
+
+impl Op {
+    fn args(&self) -> Vec<String> {
+        match self {
+            Op::Set(key, value) => vec!["SET".into(), key.to_string(), value.clone()],
+            Op::Get(key) => vec!["GET".into(), key.to_string()],
+            Op::Append(key, value) => vec!["APPEND".into(), key.to_string(), value.clone()],
+            Op::Strlen(key) => vec!["STRLEN".into(), key.to_string()],
+            Op::GetRange(key, start, end) => vec![
+                "GETRANGE".into(),
+                key.to_string(),
+                start.to_string(),
+                end.to_string(),
+            ],
+            Op::SetRange(key, offset, value) => vec![
+                "SETRANGE".into(),
+                key.to_string(),
+                offset.to_string(),
+                value.clone(),
+            ],
+            Op::IncrBy(key, delta) => vec!["INCRBY".into(), key.to_string(), delta.to_string()],
+            Op::Del(keys) => {
+                let mut args = vec!["DEL".into()];
+                args.extend(keys.iter().map(|key| key.to_string()));
+                args
+            }
+            Op::HSet(key, pairs) => {
+                let mut args = vec!["HSET".into(), key.to_string()];
+                for (field, value) in pairs {
+                    args.extend([field.to_string(), value.clone()]);
+                }
+                args
+            }
+            Op::HGet(key, field) => vec!["HGET".into(), key.to_string(), field.to_string()],
+            Op::HLen(key) => vec!["HLEN".into(), key.to_string()],
+            Op::HGetAll(key) => vec!["HGETALL".into(), key.to_string()],
+        }
+    }
+}
+
+fn key() -> impl Strategy<Value = &'static str> {
+    prop::sample::select(KEYS)
+}
+
+/// Values that are sometimes integers, so INCRBY both succeeds and fails.
+fn value() -> impl Strategy<Value = String> {
+    prop_oneof!["[a-c]{0,3}", (-100i64..100).prop_map(|n| n.to_string())]
+}
+
+fn string_op() -> impl Strategy<Value = Op> {
+    prop_oneof![
+        (key(), value()).prop_map(|(k, v)| Op::Set(k, v)),
+        key().prop_map(Op::Get),
+        (key(), value()).prop_map(|(k, v)| Op::Append(k, v)),
+        key().prop_map(Op::Strlen),
+        (key(), -6i64..6, -6i64..6).prop_map(|(k, start, end)| Op::GetRange(k, start, end)),
+        (key(), 0usize..8, "[a-c]{0,3}").prop_map(|(k, offset, v)| Op::SetRange(k, offset, v)),
+        (
+            key(),
+            prop_oneof![-100i64..100, Just(i64::MAX), Just(i64::MIN + 1)]
+        )
+            .prop_map(|(k, delta)| Op::IncrBy(k, delta)),
+        vec(key(), 1..3).prop_map(Op::Del),
+    ]
+}
+
+fn hash_op() -> impl Strategy<Value = Op> {
+    let field = || prop::sample::select(FIELDS);
+    prop_oneof![
+        (key(), vec((field(), value()), 1..4)).prop_map(|(k, pairs)| Op::HSet(k, pairs)),
+        (key(), field()).prop_map(|(k, f)| Op::HGet(k, f)),
+        key().prop_map(Op::HLen),
+        key().prop_map(Op::HGetAll),
+        vec(key(), 1..3).prop_map(Op::Del),
+    ]
+}
+
+#[derive(Debug)]
+enum Value {
+    String(String),
+    Hash(BTreeMap<String, String>),
+}
+
+/// The keyspace as the commands should see it.
+#[derive(Debug, Default)]
+struct Model {
+    db: HashMap<String, Value>,
+}
+
+/// GETRANGE's indices: negative ones count from the end, and the end is clamped to the string.
+fn get_range(s: &str, start: i64, end: i64) -> String {
+    let len = s.len() as i64;
+    let start = if start < 0 {
+        (len + start).max(0)
+    } else {
+        start
+    };
+    let end = if end < 0 { len + end } else { end.min(len - 1) };
+    if start > end || start >= len {
+        return String::new();
+    }
+    s[start as usize..=end as usize].to_string()
+}
+
+impl Model {
+    fn string(&self, key: &str) -> Result<Option<&String>, RespData> {
+        match self.db.get(key) {
+            Some(Value::String(s)) => Ok(Some(s)),
+            Some(Value::Hash(_)) => Err(errors::wrong_type()),
+            None => Ok(None),
+        }
+    }
+
+    fn hash(&self, key: &str) -> Result<Option<&BTreeMap<String, String>>, RespData> {
+        match self.db.get(key) {
+            Some(Value::Hash(hash)) => Ok(Some(hash)),
+            Some(Value::String(_)) => Err(errors::wrong_type()),
+            None => Ok(None),
+        }
+    }
+
+    /// Applies `op` and returns the reply it should get.
+    fn apply(&mut self, op: &Op) -> Result<RespData, RespData> {
+        Ok(match op {
+            Op::Set(key, value) => {
+                self.db
+                    .insert(key.to_string(), Value::String(value.clone()));
+                RespData::SimpleString("OK".to_string())
+            }
+            Op::Get(key) => self
+                .string(key)?
+                .map_or(RespData::Null, |s| RespData::BulkString(s.clone())),
+            Op::Append(key, value) => {
+                let s = format!("{}{value}", self.string(key)?.map_or("", |s| s));
+                let len = s.len();
+                self.db.insert(key.to_string(), Value::String(s));
+                RespData::Integer(len as i64)
+            }
+            Op::Strlen(key) => RespData::Integer(self.string(key)?.map_or(0, |s| s.len()) as i64),
+            Op::GetRange(key, start, end) => {
+                RespData::BulkString(get_range(self.string(key)?.map_or("", |s| s), *start, *end))
+            }
+            Op::SetRange(key, offset, value) => {
+                let mut bytes = self.string(key)?.cloned().unwrap_or_default().into_bytes();
+                if value.is_empty() {
+                    return Ok(RespData::Integer(bytes.len() as i64));
+                }
+                let end = offset + value.len();
+                if bytes.len() < end {
+                    bytes.resize(end, 0);
+                }
+                bytes[*offset..end].copy_from_slice(value.as_bytes());
+                let len = bytes.len();
+                let s = String::from_utf8(bytes).unwrap();
+                self.db.insert(key.to_string(), Value::String(s));
+                RespData::Integer(len as i64)
+            }
+            Op::IncrBy(key, delta) => {
+                let current = match self.string(key)? {
+                    None => 0,
+                    // Only the canonical form of a number counts as an integer.
+                    Some(s) => s
+                        .parse::<i64>()
+                        .ok()
+                        .filter(|n| n.to_string() == *s)
+                        .ok_or_else(errors::not_an_integer)?,
+                };
+                let result = current.checked_add(*delta).ok_or_else(|| {
+                    RespData::Error("increment or decrement would overflow".to_string())
+                })?;
+                self.db
+                    .insert(key.to_string(), Value::String(result.to_string()));
+                RespData::Integer(result)
+            }
+            Op::Del(keys) => {
+                let mut deleted = 0;
+                for key in keys {
+                    deleted += self.db.remove(*key).is_some() as i64;
+                }
+                RespData::Integer(deleted)
+            }
+            Op::HSet(key, pairs) => {
+                let mut hash = self.hash(key)?.cloned().unwrap_or_default();
+                let mut added = 0;
+                for (field, value) in pairs {
+                    added += hash.insert(field.to_string(), value.clone()).is_none() as i64;
+                }
+                self.db.insert(key.to_string(), Value::Hash(hash));
+                RespData::Integer(added)
+            }
+            Op::HGet(key, field) => self
+                .hash(key)?
+                .and_then(|hash| hash.get(*field))
+                .map_or(RespData::Null, |v| RespData::BulkString(v.clone())),
+            Op::HLen(key) => RespData::Integer(self.hash(key)?.map_or(0, |h| h.len()) as i64),
+            Op::HGetAll(key) => pairs_reply(self.hash(key)?.cloned().unwrap_or_default()),
+        })
+    }
+}
+
+fn pairs_reply(pairs: BTreeMap<String, String>) -> RespData {
+    RespData::Array(
+        pairs
+            .into_iter()
+            .flat_map(|(field, value)| [RespData::BulkString(field), RespData::BulkString(value)])
+            .collect(),
+    )
+}
+
+/// The fields and values of an HGETALL reply, whatever their order.
+fn hgetall_pairs(reply: RespData) -> Option<BTreeMap<String, String>> {
+    let RespData::Array(items) = reply else {
+        return None;
+    };
+    items
+        .chunks(2)
+        .map(|pair| match pair {
+            [RespData::BulkString(field), RespData::BulkString(value)] => {
+                Some((field.clone(), value.clone()))
+            }
+            _ => None,
+        })
+        .collect()
+}
+
+fn run(handler: &mut CommandHandler, args: &[String]) -> RespData {
+    handler.handle(&RespData::Array(
+        args.iter().cloned().map(RespData::BulkString).collect(),
+    ))
+}
+
+fn run_ops(ops: &[Op]) -> Result<(), TestCaseError> {
+    let mut handler = CommandHandler::from(HashMap::new());
+    let mut model = Model::default();
+    for op in ops {
+        let mut reply = run(&mut handler, &op.args());
+        if let Op::HGetAll(_) = op {
+            if let Some(pairs) = hgetall_pairs(reply.clone()) {
+                reply = pairs_reply(pairs);
+            }
+        }
+        let expected = model.apply(op).unwrap_or_else(|e| e);
+        prop_assert_eq!(reply, expected, "{:?}", op);
+    }
+    check_invariants(&mut handler, &model)
+}
+
+/// Checks the read commands agree with each other and with the model for every key.
+fn check_invariants(handler: &mut CommandHandler, model: &Model) -> Result<(), TestCaseError> {
+    let mut command = |args: &[&str]| {
+        let args: Vec<String> = args.iter().map(|arg| arg.to_string()).collect();
+        run(handler, &args)
+    };
+    for key in KEYS {
+        let type_name = command(&["TYPE", key]);
+        match model.db.get(*key) {
+            None => prop_assert_eq!(type_name, RespData::SimpleString("none".to_string())),
+            Some(Value::String(s)) => {
+                prop_assert_eq!(type_name, RespData::SimpleString("string".to_string()));
+                prop_assert_eq!(command(&["GET", key]), RespData::BulkString(s.clone()));
+                prop_assert_eq!(command(&["STRLEN", key]), RespData::Integer(s.len() as i64));
+            }
+            Some(Value::Hash(hash)) => {
+                prop_assert_eq!(type_name, RespData::SimpleString("hash".to_string()));
+                let pairs = hgetall_pairs(command(&["HGETALL", key]));
+                prop_assert_eq!(pairs.as_ref(), Some(hash));
+                prop_assert_eq!(
+                    command(&["HLEN", key]),
+                    RespData::Integer(hash.len() as i64)
+                );
+                for field in FIELDS {
+                    let expected = hash
+                        .get(*field)
+                        .map_or(RespData::Null, |v| RespData::BulkString(v.clone()));
+                    prop_assert_eq!(command(&["HGET", key, field]), expected);
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+proptest! {
+    #[test]
+    fn test_strings_match_model(ops in vec(string_op(), 1..40)) {
+        run_ops(&ops)?;
+    }
+
+    #[test]
+    fn test_hashes_match_model(ops in vec(hash_op(), 1..40)) {
+        run_ops(&ops)?;
+    }
+
+    #[test]
+    fn test_mixed_types_match_model(ops in vec(prop_oneof![string_op(), hash_op()], 1..40)) {
+        run_ops(&ops)?;
+    }
+}
+
+#[test]
+fn test_get_range() {
+    let test_cases = [
+        ("Whole string", "hello", 0, -1, "hello"),
+        ("Negative start", "hello", -3, -1, "llo"),
+        ("End past the string", "hello", 1, 100, "ello"),
+        ("Start after end", "hello", 3, 1, ""),
+        ("Both before the start", "hello", -10, -8, ""),
+        ("Empty string", "", 0, -1, ""),
+    ];
+
+    for (name, s, start, end, expected) in test_cases {
+        assert_eq!(get_range(s, start, end), expected, "{}", name);
+    }
+}
@@ -0,0 +1,71 @@
+//! Start/stop index handling shared by the commands that take a range of positions.
+
+/// Resolves the inclusive `start`..=`stop` range of a sequence of `len` elements the way Redis
+/// does: negative indices count from the end, out of range indices are clamped, and a range
+/// that selects nothing yields `None`.
+pub(super) fn resolve_range(start: i64, stop: i64, len: usize) -> Option<(usize, usize)> {
+    let len = len as i64;
+    let start = if start < 0 {
+        (start + len).max(0)
+    } else {
+        start
+    };
+    let stop = if stop < 0 {
+        stop + len
+    } else {
+        stop.min(len - 1)
+    };
+    if start > stop || start >= len {
+        return None;
+    }
+    Some((start as usize, stop as usize))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_resolve_range() {
+        let test_cases = [
+            ("Whole range", 0, 4, 5, Some((0, 4))),
+            ("Whole range with negatives", 0, -1, 5, Some((0, 4))),
+            ("Middle", 1, 3, 5, Some((1, 3))),
+            ("Single element", 2, 2, 5, Some((2, 2))),
+            ("Both negative", -3, -2, 5, Some((2, 3))),
+            ("Stop past the end", 3, 100, 5, Some((3, 4))),
+            ("Start before the beginning", -100, 1, 5, Some((0, 1))),
+            ("Both out of range", -100, 100, 5, Some((0, 4))),
+            ("Start after stop", 3, 1, 5, None),
+            ("Negative start after stop", -1, -2, 5, None),
+            ("Start past the end", 5, 10, 5, None),
+            ("Stop before the beginning", 0, -6, 5, None),
+            ("Stop at the first element", 0, -5, 5, Some((0, 0))),
+            ("Empty sequence", 0, -1, 0, None),
+            ("Empty sequence, zero range", 0, 0, 0, None),
+            ("Extreme indices", i64::MIN, i64::MAX, 3, Some((0, 2))),
+        ];
+
+        for (name, start, stop, len, expected) in test_cases {
+            assert_eq!(resolve_range(start, stop, len), expected, "{}", name);
+        }
+    }
+
+    #[test]
+    fn test_resolve_range_matches_slicing() {
+        // Compare against a straightforward reference for every small combination.
+        for len in 0..6usize {
+            for start in -8i64..8 {
+                for stop in -8i64..8 {
+                    let normalize = |i: i64| if i < 0 { len as i64 + i } else { i };
+                    let (from, to) = (normalize(start).max(0), normalize(stop));
+                    let expected: Vec<i64> = (from..=to).filter(|i| *i < len as i64).collect();
+                    let actual: Vec<i64> = resolve_range(start, stop, len)
+                        .map(|(from, to)| (from as i64..=to as i64).collect())
+                        .unwrap_or_default();
+                    assert_eq!(actual, expected, "start {start} stop {stop} len {len}");
+                }
+            }
+        }
+    }
+}
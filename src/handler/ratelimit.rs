@@ -0,0 +1,159 @@
+//! Rate limiting of client commands, per connection with `client-rate-limit` and per source IP
+//! with `ip-rate-limit`, so that one noisy tenant can't starve the others. Both are token
+//! buckets refilling at the configured rate and holding at most a second's worth of commands.
+
+use super::client::ClientId;
+use super::CommandHandler;
+use crate::resp::RespData;
+use std::net::IpAddr;
+use std::time::Instant;
+
+pub struct TokenBucket {
+    tokens: f64,
+    refilled: Instant,
+}
+
+impl TokenBucket {
+    /// A full bucket.
+    pub fn new(rate: u64, now: Instant) -> Self {
+        Self {
+            tokens: rate as f64,
+            refilled: now,
+        }
+    }
+
+    /// Takes a token if one is left after refilling at `rate` tokens a second.
+    fn try_take(&mut self, rate: u64, now: Instant) -> bool {
+        let elapsed = now.saturating_duration_since(self.refilled).as_secs_f64();
+        self.tokens = (self.tokens + elapsed * rate as f64).min(rate as f64);
+        self.refilled = now;
+        if self.tokens < 1.0 {
+            return false;
+        }
+        self.tokens -= 1.0;
+        true
+    }
+}
+
+impl CommandHandler {
+    /// The error to refuse the client's next command with, if it is over either rate limit.
+    /// A command refused by one limit doesn't use up the other.
+    pub(super) fn rate_limited(&mut self, id: ClientId) -> Option<RespData> {
+        let (client_limit, ip_limit) = {
+            let config = self.config.read().unwrap();
+            (config.client_rate_limit, config.ip_rate_limit)
+        };
+        if client_limit == 0 && ip_limit == 0 {
+            return None;
+        }
+        let now = self.clock.now();
+        let client = self.clients.get_mut(&id)?;
+        let ip = client.addr.ip();
+
+        if client_limit > 0 {
+            let bucket = client
+                .rate_limit
+                .get_or_insert_with(|| TokenBucket::new(client_limit, now));
+            if !bucket.try_take(client_limit, now) {
+                return Some(RespData::Error(format!(
+                    "-THROTTLED client exceeded {client_limit} commands per second"
+                )));
+            }
+        }
+        if ip_limit > 0 {
+            let bucket = self
+                .ip_rate_limits
+                .entry(ip)
+                .or_insert_with(|| TokenBucket::new(ip_limit, now));
+            if !bucket.try_take(ip_limit, now) {
+                return Some(RespData::Error(format!(
+                    "-THROTTLED {ip} exceeded {ip_limit} commands per second"
+                )));
+            }
+        }
+        None
+    }
+
+    /// Forgets the IP's bucket once its last client has gone.
+    pub(super) fn release_ip_rate_limit(&mut self, ip: IpAddr) {
+        if !self.clients.values().any(|client| client.addr.ip() == ip) {
+            self.ip_rate_limits.remove(&ip);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::clock::MockClock;
+    use super::*;
+    use std::collections::HashMap;
+    use std::sync::Arc;
+    use std::time::Duration;
+
+    fn command(args: &[&str]) -> RespData {
+        RespData::array(args.iter().copied())
+    }
+
+    #[test]
+    fn test_token_bucket() {
+        let start = Instant::now();
+        let mut bucket = TokenBucket::new(2, start);
+        let test_cases = [
+            ("First token", 0, true),
+            ("Second token", 0, true),
+            ("Empty", 0, false),
+            ("Half refilled", 250, false),
+            ("Refilled one", 500, true),
+            ("Capped at the rate", 10_000, true),
+            ("Second after the cap", 10_000, true),
+            ("Empty again", 10_000, false),
+        ];
+
+        for (name, elapsed_ms, expected) in test_cases {
+            let now = start + Duration::from_millis(elapsed_ms);
+            assert_eq!(bucket.try_take(2, now), expected, "{}", name);
+        }
+    }
+
+    #[test]
+    fn test_rate_limited() {
+        let mut handler = CommandHandler::from(HashMap::new());
+        let clock = Arc::new(MockClock::new());
+        handler.clock = clock.clone();
+        handler.handle(&command(&["CONFIG", "SET", "client-rate-limit", "2"]));
+        handler.handle(&command(&["CONFIG", "SET", "ip-rate-limit", "3"]));
+        let laddr = "127.0.0.1:6379".parse().unwrap();
+        let a = handler.register_client("10.0.0.1:5000".parse().unwrap(), laddr, 7, None);
+        let b = handler.register_client("10.0.0.1:5001".parse().unwrap(), laddr, 8, None);
+        let other = handler.register_client("10.0.0.2:5000".parse().unwrap(), laddr, 9, None);
+
+        let client_throttled =
+            RespData::Error("-THROTTLED client exceeded 2 commands per second".to_string());
+        let ip_throttled =
+            RespData::Error("-THROTTLED 10.0.0.1 exceeded 3 commands per second".to_string());
+        let pong = RespData::SimpleString("PONG".to_string());
+        let test_cases = [
+            ("First from a", a, pong.clone()),
+            ("Second from a", a, pong.clone()),
+            ("Over the client limit", a, client_throttled),
+            ("First from b", b, pong.clone()),
+            ("Over the IP limit", b, ip_throttled),
+            ("Another IP", other, pong.clone()),
+        ];
+        for (name, id, expected) in test_cases {
+            assert_eq!(
+                handler.handle_client(id, &command(&["PING"])),
+                Some(expected),
+                "{}",
+                name
+            );
+        }
+
+        clock.advance(Duration::from_secs(1));
+        assert_eq!(handler.handle_client(b, &command(&["PING"])), Some(pong));
+        handler.unregister_client(a);
+        assert_eq!(handler.ip_rate_limits.len(), 2);
+        handler.unregister_client(b);
+        assert_eq!(handler.ip_rate_limits.len(), 1);
+    }
+}
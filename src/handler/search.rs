@@ -0,0 +1,476 @@
+//! The FT.* commands, for full-text and vector search over the hashes under some key
+//! prefixes. Every
+//! write, deletion and expiry of a key goes through `notify_key_event`, which keeps the
+//! indexes up to date.
+
+use super::command::SearchOptions;
+use super::{CommandHandler, RedisValue};
+use crate::resp::RespData;
+use crate::search::{Query, SchemaField, SearchIndex};
+
+/// How many results FT.SEARCH replies with without LIMIT.
+const DEFAULT_LIMIT: usize = 10;
+
+impl CommandHandler {
+    /// FT.CREATE index [ON HASH] [PREFIX count prefix ...] SCHEMA field {TEXT [WEIGHT weight]
+    /// | VECTOR algorithm count attribute value ...} ...: creates an index, adding the hashes
+    /// already under its prefixes.
+    pub(super) fn ft_create(
+        &mut self,
+        index: &str,
+        prefixes: Vec<String>,
+        fields: Vec<SchemaField>,
+    ) -> RespData {
+        if self.search_indexes.contains_key(index) {
+            return RespData::Error("Index already exists".to_string());
+        }
+        let mut search_index = SearchIndex::new(prefixes, fields);
+        self.db.for_each(&mut |key, value| {
+            if let RedisValue::Hash(hash) = value {
+                if search_index.covers(key) {
+                    search_index.insert(key, hash);
+                }
+            }
+        });
+        self.search_indexes.insert(index.to_string(), search_index);
+        RespData::SimpleString("OK".to_string())
+    }
+
+    /// FT.SEARCH index query [NOCONTENT] [WITHSCORES] [LIMIT offset num] [PARAMS count name
+    /// value ...] [DIALECT dialect]: how many hashes match, then a page of their keys, the
+    /// best first, each followed by its score with WITHSCORES and its fields unless
+    /// NOCONTENT. The fields of a KNN query's results start with their distance.
+    pub(super) fn ft_search(
+        &mut self,
+        index: &str,
+        query: &str,
+        options: &SearchOptions,
+    ) -> RespData {
+        let Some(search_index) = self.search_indexes.get(index) else {
+            return RespData::Error(format!("{index}: no such index"));
+        };
+        let query = match Query::parse(query, &options.params) {
+            Ok(query) => query,
+            Err(e) => return RespData::Error(e),
+        };
+        let results = match search_index.search(&query) {
+            Ok(results) => results,
+            Err(e) => return RespData::Error(e),
+        };
+        let results: Vec<_> = results
+            .into_iter()
+            .map(|(key, score)| (key.to_string(), score))
+            .collect();
+        // An expired key leaves the indexes once it is noticed, which may be only now.
+        let results: Vec<_> = results
+            .into_iter()
+            .filter(|(key, _)| !self.expire_if_needed(key))
+            .collect();
+
+        let (offset, count) = options.limit.unwrap_or((0, DEFAULT_LIMIT));
+        let mut reply = vec![RespData::Integer(results.len() as i64)];
+        for (key, score) in results.into_iter().skip(offset).take(count) {
+            reply.push(RespData::BulkString(key.clone()));
+            if options.with_scores {
+                reply.push(RespData::BulkString(score.to_string()));
+            }
+            if options.no_content {
+                continue;
+            }
+            let mut fields = Vec::new();
+            if let Some(score_field) = query.score_field() {
+                fields.push(RespData::bulk(score_field));
+                fields.push(RespData::BulkString(score.to_string()));
+            }
+            if let Some(RedisValue::Hash(hash)) = self.lookup_key_read(&key) {
+                let mut pairs: Vec<_> = hash.iter().collect();
+                pairs.sort();
+                for (field, value) in pairs {
+                    fields.push(RespData::BulkString(field.clone()));
+                    fields.push(RespData::BulkString(value.clone()));
+                }
+            }
+            reply.push(RespData::Array(fields));
+        }
+        RespData::Array(reply)
+    }
+
+    /// Brings the indexes up to date after `key` was written or removed.
+    pub(super) fn reindex_key(&mut self, key: &str) {
+        let hash = match self.db.get(key) {
+            Some(RedisValue::Hash(hash)) => Some(hash),
+            _ => None,
+        };
+        for index in self.search_indexes.values_mut() {
+            match hash {
+                Some(hash) if index.covers(key) => index.insert(key, hash),
+                _ => index.remove(key),
+            }
+        }
+    }
+
+    /// Indexes every hash again, after the keyspace was flushed or replaced wholesale.
+    pub(super) fn rebuild_search_indexes(&mut self) {
+        let indexes = &mut self.search_indexes;
+        for index in indexes.values_mut() {
+            index.clear();
+        }
+        self.db.for_each(&mut |key, value| {
+            if let RedisValue::Hash(hash) = value {
+                for index in indexes.values_mut() {
+                    if index.covers(key) {
+                        index.insert(key, hash);
+                    }
+                }
+            }
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::clock::MockClock;
+    use super::*;
+    use std::collections::HashMap;
+    use std::sync::Arc;
+    use std::time::Duration;
+
+    fn command(args: &[&str]) -> RespData {
+        RespData::array(args.iter().copied())
+    }
+
+    fn error(message: &str) -> RespData {
+        RespData::Error(message.to_string())
+    }
+
+    fn ok() -> RespData {
+        RespData::SimpleString("OK".to_string())
+    }
+
+    /// An FT.SEARCH reply with NOCONTENT.
+    fn keys(total: i64, keys: &[&str]) -> RespData {
+        let mut reply = vec![RespData::Integer(total)];
+        reply.extend(keys.iter().copied().map(RespData::bulk));
+        RespData::Array(reply)
+    }
+
+    #[test]
+    fn test_search_commands() {
+        let mut handler = CommandHandler::from(HashMap::new());
+        let clock = Arc::new(MockClock::new());
+        handler.clock = clock.clone();
+        handler.handle(&command(&[
+            "HSET",
+            "post:1",
+            "title",
+            "Hello Redis",
+            "views",
+            "3",
+        ]));
+        handler.handle(&command(&["HSET", "user:1", "title", "Redis fan"]));
+
+        let test_cases = [
+            (
+                "Create an index",
+                command(&[
+                    "FT.CREATE",
+                    "posts",
+                    "ON",
+                    "HASH",
+                    "PREFIX",
+                    "1",
+                    "post:",
+                    "SCHEMA",
+                    "title",
+                    "TEXT",
+                    "WEIGHT",
+                    "2",
+                    "body",
+                    "TEXT",
+                ]),
+                ok(),
+            ),
+            (
+                "Create it again",
+                command(&["FT.CREATE", "posts", "SCHEMA", "title", "TEXT"]),
+                error("Index already exists"),
+            ),
+            (
+                "Existing hashes are indexed",
+                command(&["FT.SEARCH", "posts", "redis"]),
+                RespData::Array(vec![
+                    RespData::Integer(1),
+                    RespData::bulk("post:1"),
+                    RespData::array(["title", "Hello Redis", "views", "3"]),
+                ]),
+            ),
+            (
+                "HSET indexes a hash",
+                command(&[
+                    "HSET",
+                    "post:2",
+                    "title",
+                    "Streams",
+                    "body",
+                    "Redis streams",
+                ]),
+                RespData::Integer(2),
+            ),
+            (
+                "Ranked by field weight",
+                command(&["FT.SEARCH", "posts", "redis", "NOCONTENT"]),
+                keys(2, &["post:1", "post:2"]),
+            ),
+            (
+                "With scores",
+                command(&[
+                    "FT.SEARCH",
+                    "posts",
+                    "@title:stream*",
+                    "NOCONTENT",
+                    "WITHSCORES",
+                ]),
+                RespData::Array(vec![
+                    RespData::Integer(1),
+                    RespData::bulk("post:2"),
+                    RespData::bulk((2.0 * 3f64.ln()).to_string()),
+                ]),
+            ),
+            (
+                "A page",
+                command(&[
+                    "FT.SEARCH",
+                    "posts",
+                    "redis",
+                    "NOCONTENT",
+                    "LIMIT",
+                    "1",
+                    "5",
+                ]),
+                keys(2, &["post:2"]),
+            ),
+            (
+                "HSET reindexes a hash",
+                command(&["HSET", "post:1", "title", "Goodbye"]),
+                RespData::Integer(0),
+            ),
+            (
+                "Old words are gone",
+                command(&["FT.SEARCH", "posts", "redis", "NOCONTENT"]),
+                keys(1, &["post:2"]),
+            ),
+            (
+                "Deleting a hash",
+                command(&["DEL", "post:2"]),
+                RespData::Integer(1),
+            ),
+            (
+                "Removes it",
+                command(&["FT.SEARCH", "posts", "redis", "NOCONTENT"]),
+                keys(0, &[]),
+            ),
+            ("Replacing a hash", command(&["SET", "post:1", "x"]), ok()),
+            (
+                "Removes it too",
+                command(&["FT.SEARCH", "posts", "*", "NOCONTENT"]),
+                keys(0, &[]),
+            ),
+            (
+                "Unknown field",
+                command(&["FT.SEARCH", "posts", "@views:3"]),
+                error("Unknown field 'views'"),
+            ),
+            (
+                "Unknown index",
+                command(&["FT.SEARCH", "missing", "redis"]),
+                error("missing: no such index"),
+            ),
+            (
+                "Unsupported field type",
+                command(&["FT.CREATE", "other", "SCHEMA", "views", "NUMERIC"]),
+                error("Invalid field type for field `views`"),
+            ),
+            (
+                "No schema",
+                command(&["FT.CREATE", "other", "PREFIX", "1", "post:"]),
+                error("Fields arguments are missing"),
+            ),
+        ];
+
+        for (name, input, expected) in test_cases {
+            assert_eq!(handler.handle(&input), expected, "{}", name);
+        }
+
+        handler.handle(&command(&["HSET", "post:3", "title", "Redis"]));
+        handler.handle(&command(&["PEXPIRE", "post:3", "100"]));
+        clock.advance(Duration::from_millis(200));
+        assert_eq!(
+            handler.handle(&command(&["FT.SEARCH", "posts", "redis", "NOCONTENT"])),
+            keys(0, &[]),
+            "Expired hashes don't match"
+        );
+
+        handler.handle(&command(&["HSET", "post:4", "title", "Redis"]));
+        handler.handle(&command(&["FLUSHALL"]));
+        assert_eq!(
+            handler.handle(&command(&["FT.SEARCH", "posts", "*", "NOCONTENT"])),
+            keys(0, &[]),
+            "Flushing empties the indexes"
+        );
+        handler.handle(&command(&["HSET", "post:5", "title", "Redis"]));
+        assert_eq!(
+            handler.handle(&command(&["FT.SEARCH", "posts", "redis", "NOCONTENT"])),
+            keys(1, &["post:5"]),
+            "But keeps them"
+        );
+    }
+
+    #[test]
+    fn test_vector_search_commands() {
+        let mut handler = CommandHandler::from(HashMap::new());
+        handler.handle(&command(&[
+            "HSET",
+            "item:1",
+            "name",
+            "red apple",
+            "vec",
+            "1,0",
+        ]));
+        handler.handle(&command(&[
+            "HSET",
+            "item:2",
+            "name",
+            "green apple",
+            "vec",
+            "0,1",
+        ]));
+        handler.handle(&command(&[
+            "HSET", "item:3", "name", "red car", "vec", "2,0",
+        ]));
+
+        let test_cases = [
+            (
+                "Create a vector index",
+                command(&[
+                    "FT.CREATE",
+                    "items",
+                    "PREFIX",
+                    "1",
+                    "item:",
+                    "SCHEMA",
+                    "name",
+                    "TEXT",
+                    "vec",
+                    "VECTOR",
+                    "HNSW",
+                    "8",
+                    "TYPE",
+                    "FLOAT32",
+                    "DIM",
+                    "2",
+                    "DISTANCE_METRIC",
+                    "L2",
+                    "M",
+                    "16",
+                ]),
+                ok(),
+            ),
+            (
+                "Nearest neighbours",
+                command(&[
+                    "FT.SEARCH",
+                    "items",
+                    "*=>[KNN 2 @vec $v]",
+                    "PARAMS",
+                    "2",
+                    "v",
+                    "1,0",
+                    "DIALECT",
+                    "2",
+                ]),
+                RespData::Array(vec![
+                    RespData::Integer(2),
+                    RespData::bulk("item:1"),
+                    RespData::array(["__vec_score", "0", "name", "red apple", "vec", "1,0"]),
+                    RespData::bulk("item:3"),
+                    RespData::array(["__vec_score", "1", "name", "red car", "vec", "2,0"]),
+                ]),
+            ),
+            (
+                "Filtered nearest neighbours",
+                command(&[
+                    "FT.SEARCH",
+                    "items",
+                    "@name:green=>[KNN 2 @vec $v AS distance]",
+                    "PARAMS",
+                    "2",
+                    "v",
+                    "1,0",
+                    "NOCONTENT",
+                ]),
+                keys(1, &["item:2"]),
+            ),
+            (
+                "Missing parameter",
+                command(&["FT.SEARCH", "items", "*=>[KNN 2 @vec $v]"]),
+                error("No such parameter `v`"),
+            ),
+            (
+                "Wrong dimensions",
+                command(&["FT.SEARCH", "items", "*=>[KNN 2 @vec 1,2,3]"]),
+                error("query vector has 3 dimensions, expected 2"),
+            ),
+            (
+                "Missing attribute",
+                command(&[
+                    "FT.CREATE",
+                    "other",
+                    "SCHEMA",
+                    "vec",
+                    "VECTOR",
+                    "FLAT",
+                    "4",
+                    "TYPE",
+                    "FLOAT32",
+                    "DIM",
+                    "2",
+                ]),
+                error(
+                    "Missing mandatory parameter: cannot create FLAT index without specifying \
+                     DISTANCE_METRIC argument",
+                ),
+            ),
+            (
+                "Unsupported type",
+                command(&[
+                    "FT.CREATE",
+                    "other",
+                    "SCHEMA",
+                    "vec",
+                    "VECTOR",
+                    "FLAT",
+                    "6",
+                    "TYPE",
+                    "FLOAT64",
+                    "DIM",
+                    "2",
+                    "DISTANCE_METRIC",
+                    "L2",
+                ]),
+                error(
+                    "Bad arguments for vector similarity FLAT index: only FLOAT32 vectors are \
+                     supported",
+                ),
+            ),
+            (
+                "Unknown algorithm",
+                command(&["FT.CREATE", "other", "SCHEMA", "vec", "VECTOR", "IVF", "0"]),
+                error("Bad arguments for vector similarity algorithm: IVF"),
+            ),
+        ];
+
+        for (name, input, expected) in test_cases {
+            assert_eq!(handler.handle(&input), expected, "{}", name);
+        }
+    }
+}
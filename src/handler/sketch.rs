@@ -0,0 +1,280 @@
+//! The Count-Min Sketch and Top-K data types and their RedisBloom commands, for estimating
+//! item frequencies and finding the heavy hitters.
+
+use super::errors;
+use super::events::KeyEventKind;
+use super::evict::xorshift64_star;
+use super::{CommandHandler, RedisValue};
+use crate::resp::RespData;
+use crate::sketch::{CountMinSketch, TopK};
+
+/// A count as an integer reply, which can only hold up to `i64::MAX`.
+fn count_reply(count: u64) -> RespData {
+    RespData::Integer(i64::try_from(count).unwrap_or(i64::MAX))
+}
+
+fn missing_key(prefix: &str) -> RespData {
+    RespData::Error(format!("{prefix}: key does not exist"))
+}
+
+impl CommandHandler {
+    /// Stores a new, empty sketch at `key`, which must not exist yet. `prefix` starts the
+    /// error messages, as in RedisBloom.
+    fn create_sketch(
+        &mut self,
+        key: &str,
+        sketch: Result<RedisValue, String>,
+        prefix: &str,
+    ) -> RespData {
+        self.expire_if_needed(key);
+        if self.db.get(key).is_some() {
+            return RespData::Error(format!("{prefix}: key already exists"));
+        }
+        let sketch = match sketch {
+            Ok(sketch) => sketch,
+            Err(e) => return RespData::Error(format!("{prefix}: {e}")),
+        };
+        let before = self.key_memory(key);
+        self.db.insert(key.to_string(), sketch);
+        self.touch_key(key);
+        self.account_key_change(key, before);
+        self.notify_key_event(KeyEventKind::Set, key);
+        RespData::SimpleString("OK".to_string())
+    }
+
+    /// Counts as a write to `key` after a sketch stored there changed.
+    fn sketch_changed(&mut self, key: &str, before: u64) {
+        self.touch_key(key);
+        self.account_key_change(key, before);
+        self.notify_key_event(KeyEventKind::Set, key);
+    }
+
+    /// CMS.INITBYDIM key width depth: creates a sketch with `depth` rows of `width` counters.
+    pub(super) fn cms_initbydim(&mut self, key: &str, width: u32, depth: u32) -> RespData {
+        let sketch = CountMinSketch::new(width, depth).map(RedisValue::CountMin);
+        self.create_sketch(key, sketch, "CMS")
+    }
+
+    /// CMS.INCRBY key item increment [item increment ...]: adds to the counts of the items,
+    /// replying with their new estimates.
+    pub(super) fn cms_incrby(&mut self, key: &str, items: &[(String, u64)]) -> RespData {
+        self.expire_if_needed(key);
+        let before = self.key_memory(key);
+        let counts = match self.db.get_mut(key) {
+            Some(RedisValue::CountMin(sketch)) => items
+                .iter()
+                .map(|(item, increment)| count_reply(sketch.increment(item.as_bytes(), *increment)))
+                .collect(),
+            Some(_) => return errors::wrong_type(),
+            None => return missing_key("CMS"),
+        };
+        self.sketch_changed(key, before);
+        RespData::Array(counts)
+    }
+
+    /// CMS.QUERY key item [item ...]: the estimated counts of the items.
+    pub(super) fn cms_query(&mut self, key: &str, items: &[String]) -> RespData {
+        match self.lookup_key_read(key) {
+            Some(RedisValue::CountMin(sketch)) => RespData::Array(
+                items
+                    .iter()
+                    .map(|item| count_reply(sketch.count(item.as_bytes())))
+                    .collect(),
+            ),
+            Some(_) => errors::wrong_type(),
+            None => missing_key("CMS"),
+        }
+    }
+
+    /// TOPK.RESERVE key topk [width depth decay]: creates an empty Top-K tracking the `k`
+    /// most frequent items.
+    pub(super) fn topk_reserve(
+        &mut self,
+        key: &str,
+        k: u32,
+        width: u32,
+        depth: u32,
+        decay: f64,
+    ) -> RespData {
+        let topk = TopK::new(k, width, depth, decay).map(RedisValue::TopK);
+        self.create_sketch(key, topk, "TopK")
+    }
+
+    /// TOPK.ADD key item [item ...]: counts the items, replying with the item each one pushed
+    /// out of the top, or null.
+    pub(super) fn topk_add(&mut self, key: &str, items: &[String]) -> RespData {
+        self.expire_if_needed(key);
+        let before = self.key_memory(key);
+        let expelled = match self.db.get_mut(key) {
+            Some(RedisValue::TopK(topk)) => {
+                let mut random = || xorshift64_star(&mut self.random_state);
+                items
+                    .iter()
+                    .map(|item| match topk.add(item, &mut random) {
+                        Some(expelled) => RespData::BulkString(expelled),
+                        None => RespData::Null,
+                    })
+                    .collect()
+            }
+            Some(_) => return errors::wrong_type(),
+            None => return missing_key("TopK"),
+        };
+        self.sketch_changed(key, before);
+        RespData::Array(expelled)
+    }
+
+    /// TOPK.LIST key [WITHCOUNT]: the top items, the most frequent first, each followed by its
+    /// estimated count with WITHCOUNT.
+    pub(super) fn topk_list(&mut self, key: &str, with_count: bool) -> RespData {
+        let topk = match self.lookup_key_read(key) {
+            Some(RedisValue::TopK(topk)) => topk,
+            Some(_) => return errors::wrong_type(),
+            None => return missing_key("TopK"),
+        };
+        let mut reply = Vec::new();
+        for (item, count) in topk.list() {
+            reply.push(RespData::bulk(item));
+            if with_count {
+                reply.push(count_reply(count));
+            }
+        }
+        RespData::Array(reply)
+    }
+
+    /// TOPK.QUERY key item [item ...]: 1 for each item that is in the top, 0 for the rest.
+    pub(super) fn topk_query(&mut self, key: &str, items: &[String]) -> RespData {
+        match self.lookup_key_read(key) {
+            Some(RedisValue::TopK(topk)) => {
+                RespData::array(items.iter().map(|item| topk.contains(item) as i64))
+            }
+            Some(_) => errors::wrong_type(),
+            None => missing_key("TopK"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+
+    fn command(args: &[&str]) -> RespData {
+        RespData::array(args.iter().copied())
+    }
+
+    fn error(message: &str) -> RespData {
+        RespData::Error(message.to_string())
+    }
+
+    fn ok() -> RespData {
+        RespData::SimpleString("OK".to_string())
+    }
+
+    #[test]
+    fn test_sketch_commands() {
+        let mut handler = CommandHandler::from(HashMap::new());
+        handler.handle(&command(&["SET", "string", "x"]));
+
+        let test_cases = [
+            (
+                "Create a sketch",
+                command(&["CMS.INITBYDIM", "counts", "1000", "5"]),
+                ok(),
+            ),
+            (
+                "Create it again",
+                command(&["CMS.INITBYDIM", "counts", "1000", "5"]),
+                error("CMS: key already exists"),
+            ),
+            (
+                "Invalid width",
+                command(&["CMS.INITBYDIM", "other", "0", "5"]),
+                error("CMS: invalid width"),
+            ),
+            (
+                "Too large",
+                command(&["CMS.INITBYDIM", "other", "4000000000", "4000000000"]),
+                error("CMS: invalid dimensions"),
+            ),
+            (
+                "Increment",
+                command(&["CMS.INCRBY", "counts", "a", "5", "b", "1", "a", "2"]),
+                RespData::array([5, 1, 7]),
+            ),
+            (
+                "Increment by something else",
+                command(&["CMS.INCRBY", "counts", "a", "-1"]),
+                error("CMS: Cannot parse number"),
+            ),
+            (
+                "Increment a missing key",
+                command(&["CMS.INCRBY", "missing", "a", "1"]),
+                error("CMS: key does not exist"),
+            ),
+            (
+                "Query",
+                command(&["CMS.QUERY", "counts", "a", "b", "c"]),
+                RespData::array([7, 1, 0]),
+            ),
+            (
+                "Query another type",
+                command(&["CMS.QUERY", "string", "a"]),
+                errors::wrong_type(),
+            ),
+            (
+                "Create a Top-K",
+                command(&["TOPK.RESERVE", "top", "2"]),
+                ok(),
+            ),
+            (
+                "Invalid decay",
+                command(&["TOPK.RESERVE", "other", "2", "8", "7", "1.5"]),
+                error("TopK: invalid decay value. must be '<= 1' & '> 0'"),
+            ),
+            (
+                "Add items",
+                command(&["TOPK.ADD", "top", "a", "b", "a"]),
+                RespData::Array(vec![RespData::Null, RespData::Null, RespData::Null]),
+            ),
+            (
+                "Push an item out",
+                command(&["TOPK.ADD", "top", "c", "c", "c"]),
+                RespData::Array(vec![RespData::Null, RespData::bulk("b"), RespData::Null]),
+            ),
+            (
+                "List",
+                command(&["TOPK.LIST", "top"]),
+                RespData::array(["c", "a"]),
+            ),
+            (
+                "List with counts",
+                command(&["TOPK.LIST", "top", "WITHCOUNT"]),
+                RespData::Array(vec![
+                    RespData::bulk("c"),
+                    RespData::Integer(3),
+                    RespData::bulk("a"),
+                    RespData::Integer(2),
+                ]),
+            ),
+            (
+                "Query",
+                command(&["TOPK.QUERY", "top", "a", "b"]),
+                RespData::array([1, 0]),
+            ),
+            (
+                "Query a missing key",
+                command(&["TOPK.QUERY", "missing", "a"]),
+                error("TopK: key does not exist"),
+            ),
+            (
+                "Type names",
+                command(&["TYPE", "top"]),
+                RespData::SimpleString("TopK-TYPE".to_string()),
+            ),
+        ];
+
+        for (name, input, expected) in test_cases {
+            assert_eq!(handler.handle(&input), expected, "{}", name);
+        }
+    }
+}
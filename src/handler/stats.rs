@@ -0,0 +1,223 @@
+use super::histogram::LatencyHistogram;
+use super::CommandHandler;
+use crate::resp::RespData;
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+
+/// Call counters for a single command, reported by INFO commandstats.
+#[derive(Debug, Default, PartialEq, Eq, Clone)]
+pub struct CommandStats {
+    pub calls: u64,
+    pub usec: u64,
+    pub rejected_calls: u64,
+    pub failed_calls: u64,
+}
+
+impl CommandStats {
+    pub fn usec_per_call(&self) -> f64 {
+        if self.calls == 0 {
+            0.0
+        } else {
+            self.usec as f64 / self.calls as f64
+        }
+    }
+}
+
+/// A copy of the main counters and gauges, for code embedding the server.
+#[derive(Debug, Default, PartialEq, Eq, Clone, Copy)]
+pub struct StatsSnapshot {
+    pub uptime: Duration,
+    pub connected_clients: usize,
+    pub total_connections_received: u64,
+    pub rejected_connections: u64,
+    pub total_commands_processed: u64,
+    pub keys: usize,
+    pub used_memory: u64,
+}
+
+/// How many recent samples the instantaneous metrics average over.
+const METRIC_SAMPLES: usize = 16;
+
+/// Server-wide counters reported by INFO.
+pub struct Stats {
+    pub start_time: Instant,
+    pub total_connections_received: u64,
+    pub rejected_connections: u64,
+    pub total_commands_processed: u64,
+    pub total_error_replies: u64,
+    pub keyspace_hits: u64,
+    pub keyspace_misses: u64,
+    pub expired_keys: u64,
+    pub evicted_keys: u64,
+    pub commands: HashMap<String, CommandStats>,
+    pub latency: HashMap<String, LatencyHistogram>,
+    pub errors: HashMap<String, u64>,
+    /// Commands per second over recent sampling periods, as a ring buffer.
+    ops_samples: [u64; METRIC_SAMPLES],
+    next_ops_sample: usize,
+    /// When the last sample was taken, and `total_commands_processed` at the time.
+    last_ops_sample: (Instant, u64),
+}
+
+impl Default for Stats {
+    fn default() -> Self {
+        Self {
+            start_time: Instant::now(),
+            total_connections_received: 0,
+            rejected_connections: 0,
+            total_commands_processed: 0,
+            total_error_replies: 0,
+            keyspace_hits: 0,
+            keyspace_misses: 0,
+            expired_keys: 0,
+            evicted_keys: 0,
+            commands: HashMap::new(),
+            latency: HashMap::new(),
+            errors: HashMap::new(),
+            ops_samples: [0; METRIC_SAMPLES],
+            next_ops_sample: 0,
+            last_ops_sample: (Instant::now(), 0),
+        }
+    }
+}
+
+impl Stats {
+    pub fn uptime_in_seconds(&self) -> u64 {
+        self.start_time.elapsed().as_secs()
+    }
+
+    /// Records a completed call of `name`, counting it as failed if it replied with an error.
+    pub fn record_call(&mut self, name: &str, duration: Duration, reply: &RespData) {
+        let stats = self.commands.entry(name.to_string()).or_default();
+        stats.calls += 1;
+        stats.usec += duration.as_micros() as u64;
+        self.latency
+            .entry(name.to_string())
+            .or_default()
+            .record(duration);
+        if matches!(reply, RespData::Error(_)) {
+            stats.failed_calls += 1;
+            self.record_error(reply);
+        }
+    }
+
+    /// Records a call of `name` refused before it ran, which counts as neither a call nor a
+    /// failure.
+    pub fn record_rejected_call(&mut self, name: &str, reply: &RespData) {
+        self.commands
+            .entry(name.to_string())
+            .or_default()
+            .rejected_calls += 1;
+        self.record_error(reply);
+    }
+
+    pub fn record_error(&mut self, reply: &RespData) {
+        let Some(prefix) = error_prefix(reply) else {
+            return;
+        };
+        self.total_error_replies += 1;
+        *self.errors.entry(prefix).or_default() += 1;
+    }
+
+    /// Samples the command rate since the previous call.
+    pub fn track_instantaneous_ops(&mut self) {
+        let (last_time, last_commands) = self.last_ops_sample;
+        let elapsed_ms = last_time.elapsed().as_millis() as u64;
+        let commands = self.total_commands_processed;
+        let rate = match elapsed_ms {
+            0 => 0,
+            ms => commands.saturating_sub(last_commands) * 1000 / ms,
+        };
+        self.ops_samples[self.next_ops_sample] = rate;
+        self.next_ops_sample = (self.next_ops_sample + 1) % METRIC_SAMPLES;
+        self.last_ops_sample = (Instant::now(), commands);
+    }
+
+    /// Commands per second, averaged over the recent samples.
+    pub fn instantaneous_ops_per_sec(&self) -> u64 {
+        self.ops_samples.iter().sum::<u64>() / METRIC_SAMPLES as u64
+    }
+
+    /// Clears the counters that CONFIG RESETSTAT resets.
+    pub fn reset(&mut self) {
+        self.total_connections_received = 0;
+        self.rejected_connections = 0;
+        self.total_commands_processed = 0;
+        self.total_error_replies = 0;
+        self.keyspace_hits = 0;
+        self.keyspace_misses = 0;
+        self.expired_keys = 0;
+        self.evicted_keys = 0;
+        self.commands.clear();
+        self.latency.clear();
+        self.errors.clear();
+        self.ops_samples = [0; METRIC_SAMPLES];
+        self.last_ops_sample = (Instant::now(), 0);
+    }
+}
+
+impl CommandHandler {
+    pub fn stats_snapshot(&self) -> StatsSnapshot {
+        StatsSnapshot {
+            uptime: self.stats.start_time.elapsed(),
+            connected_clients: self.clients.len(),
+            total_connections_received: self.stats.total_connections_received,
+            rejected_connections: self.stats.rejected_connections,
+            total_commands_processed: self.stats.total_commands_processed,
+            keys: self.db.len(),
+            used_memory: self.used_memory,
+        }
+    }
+}
+
+/// Returns the error code of an error reply as it appears on the wire, e.g. `ERR`.
+pub(super) fn error_prefix(reply: &RespData) -> Option<String> {
+    let RespData::Error(_) = reply else {
+        return None;
+    };
+    let mut buffer = Vec::new();
+    reply.write(&mut buffer).ok()?;
+    let line = String::from_utf8_lossy(&buffer[1..]);
+    line.split_whitespace().next().map(|s| s.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_record_call() {
+        let mut stats = Stats::default();
+
+        stats.record_call(
+            "get",
+            Duration::from_micros(10),
+            &RespData::BulkString("value".to_string()),
+        );
+        stats.record_call(
+            "get",
+            Duration::from_micros(20),
+            &RespData::Error("syntax error".to_string()),
+        );
+
+        assert_eq!(
+            stats.commands["get"],
+            CommandStats {
+                calls: 2,
+                usec: 30,
+                rejected_calls: 0,
+                failed_calls: 1,
+            }
+        );
+        assert_eq!(stats.commands["get"].usec_per_call(), 15.0);
+        assert_eq!(stats.latency["get"].count(), 2);
+        assert_eq!(stats.errors["ERR"], 1);
+        assert_eq!(stats.total_error_replies, 1);
+
+        stats.reset();
+        assert!(stats.commands.is_empty());
+        assert!(stats.latency.is_empty());
+        assert!(stats.errors.is_empty());
+        assert_eq!(stats.total_error_replies, 0);
+    }
+}
@@ -0,0 +1,240 @@
+//! Where the keyspace lives. The handler only goes through the `Storage` trait, so code
+//! embedding the server can swap the in-memory default for another backend, e.g. one writing
+//! through to an embedded on-disk database.
+//!
+//! The handler hands out references to stored values and modifies them in place, so a backend
+//! must keep the values it serves in memory; one persisting elsewhere has to treat `get_mut`
+//! as a write. It may keep other values elsewhere as long as it brings them back in `load`,
+//! like `TieredStorage` does.
+
+use super::RedisValue;
+use crate::dict::Dict;
+use std::time::{Duration, Instant};
+
+pub trait Storage: Send {
+    fn len(&self) -> usize;
+
+    fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Called before `key` is read or written, so a backend keeping some values elsewhere can
+    /// bring its value into memory for `get` and `get_mut`.
+    fn load(&mut self, _key: &str) {}
+
+    fn get(&self, key: &str) -> Option<&RedisValue>;
+
+    fn get_mut(&mut self, key: &str) -> Option<&mut RedisValue>;
+
+    fn contains_key(&self, key: &str) -> bool {
+        self.get(key).is_some()
+    }
+
+    /// Stores `value` at `key`, keeping its TTL if it had one, and returns the old value.
+    fn insert(&mut self, key: String, value: RedisValue) -> Option<RedisValue>;
+
+    /// Removes `key` along with its TTL.
+    fn remove(&mut self, key: &str) -> Option<RedisValue>;
+
+    /// Calls `visit` on every key and its value.
+    fn for_each(&self, visit: &mut dyn FnMut(&String, &RedisValue));
+
+    /// A key picked with the help of `random`, or `None` if there are none.
+    fn random_key(&self, random: &mut dyn FnMut() -> u64) -> Option<&String>;
+
+    /// Calls `visit` on some of the keys, starting from `cursor`, and returns the cursor to
+    /// continue from, or 0 once every key has been visited. Like SCAN, a key present for the
+    /// whole iteration is visited at least once.
+    fn scan(&self, cursor: u64, visit: &mut dyn FnMut(&String, &RedisValue)) -> u64;
+
+    /// When `key` expires, if it has a TTL.
+    fn expire_at(&self, key: &str) -> Option<Instant>;
+
+    fn set_expire(&mut self, key: &str, deadline: Instant);
+
+    /// Removes the TTL of `key`, returning the deadline it had.
+    fn persist(&mut self, key: &str) -> Option<Instant>;
+
+    /// How many keys have a TTL.
+    fn expires_len(&self) -> usize;
+
+    fn expires(&self) -> Box<dyn Iterator<Item = (&String, Instant)> + '_>;
+
+    /// A key with a TTL picked with the help of `random`, and its deadline.
+    fn random_expiring(&self, random: &mut dyn FnMut() -> u64) -> Option<(&String, Instant)>;
+
+    fn clear(&mut self);
+
+    /// Empties the storage and returns what it held, to be dropped, possibly on another
+    /// thread.
+    fn take(&mut self) -> Box<dyn Send>;
+
+    /// Does incremental housekeeping, like rehashing, for up to `budget`.
+    fn background_work(&mut self, _budget: Duration) {}
+
+    /// Figures about the storage to add to INFO's memory section.
+    fn info_fields(&self) -> Vec<(&'static str, u64)> {
+        Vec::new()
+    }
+}
+
+/// The default storage: the keyspace and the TTLs in a pair of incrementally rehashed dicts.
+pub struct MemoryStorage {
+    db: Dict<String, RedisValue>,
+    /// Absolute expiry deadlines for the keys in `db` that have a TTL.
+    expires: Dict<String, Instant>,
+}
+
+impl MemoryStorage {
+    /// Storage whose keys hash the same way in every run with the same seed.
+    pub fn with_seed(seed: u64) -> Self {
+        Self {
+            db: Dict::with_seed(seed),
+            expires: Dict::with_seed(seed),
+        }
+    }
+}
+
+impl Storage for MemoryStorage {
+    fn len(&self) -> usize {
+        self.db.len()
+    }
+
+    fn get(&self, key: &str) -> Option<&RedisValue> {
+        self.db.get(key)
+    }
+
+    fn get_mut(&mut self, key: &str) -> Option<&mut RedisValue> {
+        self.db.get_mut(key)
+    }
+
+    fn contains_key(&self, key: &str) -> bool {
+        self.db.contains_key(key)
+    }
+
+    fn insert(&mut self, key: String, value: RedisValue) -> Option<RedisValue> {
+        self.db.insert(key, value)
+    }
+
+    fn remove(&mut self, key: &str) -> Option<RedisValue> {
+        self.expires.remove(key);
+        self.db.remove(key)
+    }
+
+    fn for_each(&self, visit: &mut dyn FnMut(&String, &RedisValue)) {
+        for (key, value) in &self.db {
+            visit(key, value);
+        }
+    }
+
+    fn random_key(&self, random: &mut dyn FnMut() -> u64) -> Option<&String> {
+        self.db.random_entry(random).map(|(key, _)| key)
+    }
+
+    fn scan(&self, cursor: u64, visit: &mut dyn FnMut(&String, &RedisValue)) -> u64 {
+        self.db.scan(cursor, visit)
+    }
+
+    fn expire_at(&self, key: &str) -> Option<Instant> {
+        self.expires.get(key).copied()
+    }
+
+    fn set_expire(&mut self, key: &str, deadline: Instant) {
+        self.expires.insert(key.to_string(), deadline);
+    }
+
+    fn persist(&mut self, key: &str) -> Option<Instant> {
+        self.expires.remove(key)
+    }
+
+    fn expires_len(&self) -> usize {
+        self.expires.len()
+    }
+
+    fn expires(&self) -> Box<dyn Iterator<Item = (&String, Instant)> + '_> {
+        Box::new(self.expires.iter().map(|(key, deadline)| (key, *deadline)))
+    }
+
+    fn random_expiring(&self, random: &mut dyn FnMut() -> u64) -> Option<(&String, Instant)> {
+        self.expires
+            .random_entry(random)
+            .map(|(key, deadline)| (key, *deadline))
+    }
+
+    fn clear(&mut self) {
+        self.db.clear();
+        self.expires.clear();
+    }
+
+    fn take(&mut self) -> Box<dyn Send> {
+        Box::new((self.db.take(), self.expires.take()))
+    }
+
+    fn background_work(&mut self, budget: Duration) {
+        self.db.rehash_for(budget);
+        self.expires.rehash_for(budget);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::Config;
+    use crate::handler::CommandHandler;
+    use crate::resp::RespData;
+    use std::sync::{Arc, RwLock};
+
+    fn string(value: &str) -> RedisValue {
+        RedisValue::String(value.into())
+    }
+
+    #[test]
+    fn test_memory_storage() {
+        let mut storage = MemoryStorage::with_seed(0);
+        let deadline = Instant::now() + Duration::from_secs(10);
+        storage.insert("a".to_string(), string("1"));
+        storage.insert("b".to_string(), string("2"));
+        storage.set_expire("a", deadline);
+
+        storage.insert("a".to_string(), string("3"));
+        assert_eq!(
+            storage.expire_at("a"),
+            Some(deadline),
+            "TTL kept on overwrite"
+        );
+        assert_eq!(storage.expires_len(), 1);
+        assert!(storage.remove("a").is_some());
+        assert_eq!(storage.expire_at("a"), None, "TTL removed with the key");
+        assert_eq!(storage.expires_len(), 0);
+
+        let mut visited = Vec::new();
+        let mut cursor = 0;
+        loop {
+            cursor = storage.scan(cursor, &mut |key, _| visited.push(key.clone()));
+            if cursor == 0 {
+                break;
+            }
+        }
+        assert_eq!(visited, ["b"]);
+
+        storage.take();
+        assert!(storage.is_empty());
+    }
+
+    #[test]
+    fn test_with_storage() {
+        let mut storage = MemoryStorage::with_seed(0);
+        storage.insert("greeting".to_string(), string("hello"));
+        let config = Arc::new(RwLock::new(Config::default()));
+        let mut handler = CommandHandler::with_storage(Box::new(storage), config);
+
+        let get = RespData::Array(vec![
+            RespData::BulkString("GET".to_string()),
+            RespData::BulkString("greeting".to_string()),
+        ]);
+        assert_eq!(
+            handler.handle(&get),
+            RespData::BulkString("hello".to_string())
+        );
+    }
+}
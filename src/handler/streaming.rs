@@ -0,0 +1,141 @@
+//! Replies serialized straight from the dataset, rather than built as a `RespData` first.
+//! HGETALL over a big hash would otherwise hold a copy of every field and value until the
+//! reply is written. The bytes are left with the client for its connection to write once the
+//! handler lock is released, so a slow reader never holds up other clients. The serialized
+//! reply is still held in full until it is written, since the hash may change as soon as the
+//! lock is released.
+
+use super::client::ReplyMode;
+use super::{ClientId, CommandHandler, RedisHash, RedisValue};
+use crate::resp;
+use std::io::{self, Write};
+use std::mem;
+
+/// Fewest fields a hash needs for HGETALL to stream its reply.
+pub(super) const STREAMED_REPLY_MIN_LEN: usize = 1024;
+
+/// Whether the reply to the running command may be streamed, and what to stream.
+#[derive(Debug, PartialEq, Eq)]
+pub(super) enum ReplyStreaming {
+    Off,
+    /// The command came from a client whose connection takes streamed replies.
+    Allowed,
+    /// The reply is the hash at this key, to be written once the command has finished.
+    Hash(String),
+}
+
+impl CommandHandler {
+    /// Replies to `id` may be streamed if it has a connection to write them, wants replies, and
+    /// no command hook could look at or replace them.
+    pub(super) fn reply_streaming_for(&self, id: ClientId) -> ReplyStreaming {
+        let hooked = !self.before_command_hooks.is_empty() || !self.after_command_hooks.is_empty();
+        let streamable = self
+            .clients
+            .get(&id)
+            .is_some_and(|client| client.output.is_some() && client.reply_mode == ReplyMode::On);
+        if streamable && !hooked {
+            ReplyStreaming::Allowed
+        } else {
+            ReplyStreaming::Off
+        }
+    }
+
+    /// Serializes the reply the command just run left to be streamed, if any, and returns
+    /// whether it did. The bytes wait for `take_streamed_reply`.
+    pub(super) fn write_streamed_reply(&mut self, id: ClientId) -> bool {
+        let ReplyStreaming::Hash(key) =
+            mem::replace(&mut self.reply_streaming, ReplyStreaming::Off)
+        else {
+            return false;
+        };
+        let (Some(RedisValue::Hash(map)), Some(client)) =
+            (self.db.get(&key), self.clients.get_mut(&id))
+        else {
+            return false;
+        };
+        write_hash(&mut client.streamed_reply, map).is_ok()
+    }
+
+    /// Takes the reply streamed to `id` by its last command, for its connection to write.
+    pub fn take_streamed_reply(&mut self, id: ClientId) -> Option<Vec<u8>> {
+        let client = self.clients.get_mut(&id)?;
+        (!client.streamed_reply.is_empty()).then(|| mem::take(&mut client.streamed_reply))
+    }
+}
+
+/// Writes the fields and values of `map` as HGETALL replies them.
+fn write_hash(output: &mut impl Write, map: &RedisHash) -> io::Result<()> {
+    resp::write_array_header(output, map.len() * 2)?;
+    for (field, value) in map {
+        resp::write_bulk_string(output, field)?;
+        resp::write_bulk_string(output, value)?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::handler::ClientOutput;
+    use crate::resp::{Resp, RespData};
+    use std::collections::HashMap;
+
+    fn command(args: &[&str]) -> RespData {
+        RespData::array(args.iter().copied())
+    }
+
+    #[test]
+    fn test_streamed_hgetall() {
+        let mut handler = CommandHandler::from(HashMap::new());
+        let id = handler.register_client(
+            "127.0.0.1:5001".parse().unwrap(),
+            "127.0.0.1:6379".parse().unwrap(),
+            7,
+            None,
+        );
+        handler.set_client_output(id, ClientOutput::default());
+        handler.handle(&command(&["HSET", "small", "f", "v"]));
+        for i in 0..STREAMED_REPLY_MIN_LEN {
+            let field = format!("field{i}");
+            handler.handle(&command(&["HSET", "big", &field, "value"]));
+        }
+
+        let test_cases = [
+            (
+                "Small hash",
+                command(&["HGETALL", "small"]),
+                Some(RespData::array(["f", "v"])),
+            ),
+            ("Big hash", command(&["HGETALL", "big"]), None),
+            (
+                "Missing key",
+                command(&["HGETALL", "missing"]),
+                Some(RespData::Array(vec![])),
+            ),
+        ];
+
+        for (name, input, expected) in test_cases {
+            assert_eq!(handler.handle_client(id, &input), expected, "{}", name);
+        }
+
+        let written = handler.take_streamed_reply(id).unwrap();
+        assert_eq!(handler.take_streamed_reply(id), None);
+        let RespData::Array(items) = Resp::new(written.as_slice()).read().unwrap() else {
+            panic!("HGETALL didn't stream an array");
+        };
+        assert_eq!(items.len(), STREAMED_REPLY_MIN_LEN * 2);
+        assert!(items
+            .chunks(2)
+            .all(|pair| pair[1] == RespData::bulk("value")));
+
+        // Replies a hook might change are built in full.
+        handler.after_command(|_, _, _| {});
+        let RespData::Array(items) = handler
+            .handle_client(id, &command(&["HGETALL", "big"]))
+            .unwrap()
+        else {
+            panic!("HGETALL didn't reply with an array");
+        };
+        assert_eq!(items.len(), STREAMED_REPLY_MIN_LEN * 2);
+    }
+}
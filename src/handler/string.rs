@@ -0,0 +1,634 @@
+//! String values and the commands that modify them in place.
+//!
+//! Like Redis, strings are stored in one of three encodings: integers in their canonical form
+//! as an actual `i64`, short strings inline without a separate heap allocation, and everything
+//! else as a plain `String`. Modifying a string in place turns it into a raw one.
+//!
+//! Unlike Redis, there is no pool of shared integer objects: int and embstr values live inside
+//! the value slot itself, so identical small values cost no allocation to begin with, and no
+//! value is ever shared between keys.
+
+use super::errors;
+use super::events::KeyEventKind;
+use super::range::resolve_range;
+use super::{CommandHandler, RedisValue};
+use crate::resp::RespData;
+use std::borrow::Cow;
+use std::fmt;
+
+/// Strings up to this long are stored inline.
+const EMBSTR_SIZE_LIMIT: usize = 44;
+
+// Inline strings must not make every value in the keyspace bigger, so some other kind of
+// value has to be what sets the size of a value slot.
+const _: () = assert!(std::mem::size_of::<RedisString>() < std::mem::size_of::<RedisValue>());
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum RedisString {
+    Int(i64),
+    Embstr(EmbStr),
+    Raw(String),
+}
+
+/// A short string stored inline.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct EmbStr {
+    len: u8,
+    bytes: [u8; EMBSTR_SIZE_LIMIT],
+}
+
+impl EmbStr {
+    fn new(s: &str) -> Self {
+        let mut bytes = [0; EMBSTR_SIZE_LIMIT];
+        bytes[..s.len()].copy_from_slice(s.as_bytes());
+        Self {
+            len: s.len() as u8,
+            bytes,
+        }
+    }
+
+    fn as_str(&self) -> &str {
+        // Always a copy of a valid `&str`.
+        std::str::from_utf8(&self.bytes[..self.len as usize]).unwrap()
+    }
+}
+
+/// `s` as an integer, if it is the canonical way of writing one, so converting back gives
+/// the same string.
+fn parse_canonical_int(s: &str) -> Option<i64> {
+    if s.len() > 20 {
+        return None;
+    }
+    let n: i64 = s.parse().ok()?;
+    (n.to_string() == s).then_some(n)
+}
+
+impl RedisString {
+    /// Stores `s` in the most compact encoding that fits it.
+    pub fn new(s: &str) -> Self {
+        if let Some(n) = parse_canonical_int(s) {
+            RedisString::Int(n)
+        } else if s.len() <= EMBSTR_SIZE_LIMIT {
+            RedisString::Embstr(EmbStr::new(s))
+        } else {
+            RedisString::Raw(s.to_string())
+        }
+    }
+
+    pub fn as_str(&self) -> Cow<'_, str> {
+        match self {
+            RedisString::Int(n) => Cow::Owned(n.to_string()),
+            RedisString::Embstr(s) => Cow::Borrowed(s.as_str()),
+            RedisString::Raw(s) => Cow::Borrowed(s),
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        match self {
+            RedisString::Int(n) => n.to_string().len(),
+            RedisString::Embstr(s) => s.len as usize,
+            RedisString::Raw(s) => s.len(),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// The value as an integer, without parsing when it is int-encoded.
+    pub fn as_int(&self) -> Option<i64> {
+        match self {
+            RedisString::Int(n) => Some(*n),
+            RedisString::Embstr(s) => parse_canonical_int(s.as_str()),
+            RedisString::Raw(s) => parse_canonical_int(s),
+        }
+    }
+
+    /// Bytes allocated on top of the object itself, which inline encodings don't need.
+    pub fn allocated_size(&self) -> usize {
+        match self {
+            RedisString::Int(_) | RedisString::Embstr(_) => 0,
+            RedisString::Raw(s) => s.capacity(),
+        }
+    }
+
+    pub fn encoding(&self) -> &'static str {
+        match self {
+            RedisString::Int(_) => "int",
+            RedisString::Embstr(_) => "embstr",
+            RedisString::Raw(_) => "raw",
+        }
+    }
+
+    /// Converts the value to the raw encoding so it can be modified in place.
+    fn make_raw(&mut self) -> &mut String {
+        if !matches!(self, RedisString::Raw(_)) {
+            *self = RedisString::Raw(self.as_str().into_owned());
+        }
+        match self {
+            RedisString::Raw(s) => s,
+            _ => unreachable!(),
+        }
+    }
+}
+
+impl fmt::Display for RedisString {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.as_str())
+    }
+}
+
+impl From<&str> for RedisString {
+    fn from(s: &str) -> Self {
+        Self::new(s)
+    }
+}
+
+impl From<String> for RedisString {
+    fn from(s: String) -> Self {
+        if s.len() > EMBSTR_SIZE_LIMIT {
+            // Keep the allocation rather than copying it.
+            RedisString::Raw(s)
+        } else {
+            Self::new(&s)
+        }
+    }
+}
+
+/// Fails with Redis's error if a string of `len` bytes would be longer than `max_len`.
+fn check_string_length(len: usize, max_len: usize) -> Result<(), RespData> {
+    if len > max_len {
+        return Err(RespData::Error(
+            "string exceeds maximum allowed size (proto-max-bulk-len)".to_string(),
+        ));
+    }
+    Ok(())
+}
+
+impl CommandHandler {
+    pub(super) fn append(&mut self, key: &str, suffix: &str) -> RespData {
+        self.expire_if_needed(key);
+        if !self.db.contains_key(key) {
+            // Like SET, a new key gets the most compact encoding.
+            return self.modify_string(key, RedisString::new(suffix), |_| Ok(()));
+        }
+        let max_len = self.max_string_len();
+        self.modify_string(key, RedisString::new(""), |s| {
+            check_string_length(s.len() + suffix.len(), max_len)?;
+            s.make_raw().push_str(suffix);
+            Ok(())
+        })
+    }
+
+    pub(super) fn strlen(&mut self, key: &str) -> RespData {
+        match self.lookup_key_read(key) {
+            Some(RedisValue::String(s)) => RespData::Integer(s.len() as i64),
+            Some(_) => errors::wrong_type(),
+            None => RespData::Integer(0),
+        }
+    }
+
+    pub(super) fn getrange(&mut self, key: &str, start: i64, end: i64) -> RespData {
+        let s = match self.lookup_key_read(key) {
+            Some(RedisValue::String(s)) => s.as_str(),
+            Some(_) => return errors::wrong_type(),
+            None => return RespData::BulkString(String::new()),
+        };
+        let Some((start, end)) = resolve_range(start, end, s.len()) else {
+            return RespData::BulkString(String::new());
+        };
+        // Offsets are in bytes, so a range may cut a multibyte character in half.
+        RespData::BulkString(String::from_utf8_lossy(&s.as_bytes()[start..=end]).into_owned())
+    }
+
+    pub(super) fn setrange(&mut self, key: &str, offset: usize, value: &str) -> RespData {
+        if let Err(e) =
+            check_string_length(offset.saturating_add(value.len()), self.max_string_len())
+        {
+            return e;
+        }
+
+        // Setting nothing neither creates the key nor changes its encoding.
+        self.expire_if_needed(key);
+        if value.is_empty() {
+            return match self.db.get(key) {
+                Some(RedisValue::String(s)) => RespData::Integer(s.len() as i64),
+                Some(_) => errors::wrong_type(),
+                None => RespData::Integer(0),
+            };
+        }
+
+        self.modify_string(key, RedisString::new(""), |s| {
+            let raw = s.make_raw();
+            let mut bytes = std::mem::take(raw).into_bytes();
+            let end = offset + value.len();
+            if bytes.len() < end {
+                bytes.resize(end, 0);
+            }
+            bytes[offset..end].copy_from_slice(value.as_bytes());
+            // Values are UTF-8, so overwriting part of a multibyte character mangles it.
+            *raw = String::from_utf8(bytes)
+                .unwrap_or_else(|e| String::from_utf8_lossy(e.as_bytes()).into_owned());
+            Ok(())
+        })
+    }
+
+    /// DECRBY, which fails where negating `decrement` would overflow.
+    pub(super) fn decrby(&mut self, key: &str, decrement: i64) -> RespData {
+        match decrement.checked_neg() {
+            Some(delta) => self.incrby(key, delta),
+            None => RespData::Error("decrement would overflow".to_string()),
+        }
+    }
+
+    /// INCR and friends, adding `delta` to the integer at `key`.
+    pub(super) fn incrby(&mut self, key: &str, delta: i64) -> RespData {
+        let mut result = 0;
+        let reply = self.modify_string(key, RedisString::Int(0), |s| {
+            let current = s.as_int().ok_or_else(errors::not_an_integer)?;
+            result = current.checked_add(delta).ok_or_else(|| {
+                RespData::Error("increment or decrement would overflow".to_string())
+            })?;
+            *s = RedisString::Int(result);
+            Ok(())
+        });
+        match reply {
+            RespData::Error(_) => reply,
+            _ => RespData::Integer(result),
+        }
+    }
+
+    fn max_string_len(&self) -> usize {
+        let max = self.config.read().unwrap().proto_max_bulk_len;
+        usize::try_from(max).unwrap_or(usize::MAX)
+    }
+
+    /// Applies `modify` to the string at `key`, or to `missing` if there is no such key, and
+    /// replies with the resulting length. Nothing is stored if `modify` fails.
+    fn modify_string(
+        &mut self,
+        key: &str,
+        missing: RedisString,
+        modify: impl FnOnce(&mut RedisString) -> Result<(), RespData>,
+    ) -> RespData {
+        self.expire_if_needed(key);
+        let before = self.key_memory(key);
+        let len = match self.db.get_mut(key) {
+            Some(RedisValue::String(s)) => {
+                if let Err(e) = modify(s) {
+                    return e;
+                }
+                s.len()
+            }
+            Some(_) => return errors::wrong_type(),
+            None => {
+                let mut s = missing;
+                if let Err(e) = modify(&mut s) {
+                    return e;
+                }
+                let len = s.len();
+                self.db.insert(key.to_string(), RedisValue::String(s));
+                len
+            }
+        };
+        self.touch_key(key);
+        self.account_key_change(key, before);
+        self.notify_key_event(KeyEventKind::Set, key);
+        RespData::Integer(len as i64)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+
+    fn command(args: &[&str]) -> RespData {
+        RespData::array(args.iter().copied())
+    }
+
+    fn encoding_of(handler: &CommandHandler, key: &str) -> &'static str {
+        match handler.db.get(key) {
+            Some(RedisValue::String(s)) => s.encoding(),
+            _ => panic!("{key} is not a string"),
+        }
+    }
+
+    #[test]
+    fn test_new() {
+        let test_cases = [
+            ("Integer", "12345", "int"),
+            ("Negative integer", "-7", "int"),
+            ("Leading zero", "007", "embstr"),
+            ("Plus sign", "+1", "embstr"),
+            ("Too big for i64", "99999999999999999999", "embstr"),
+            ("Empty", "", "embstr"),
+            ("Short", "hello", "embstr"),
+            ("Longest inline", &"x".repeat(44), "embstr"),
+            ("Too long to inline", &"x".repeat(45), "raw"),
+        ];
+
+        for (name, input, expected) in test_cases {
+            let s = RedisString::new(input);
+            assert_eq!(s.encoding(), expected, "{}", name);
+            assert_eq!(s.as_str(), input, "{}", name);
+            assert_eq!(s.len(), input.len(), "{}", name);
+        }
+    }
+
+    #[test]
+    fn test_append() {
+        let mut handler = CommandHandler::from(HashMap::new());
+        handler.handle(&command(&["HSET", "hash", "f", "v"]));
+
+        let test_cases = [
+            (
+                "New key",
+                command(&["APPEND", "key", "12"]),
+                RespData::Integer(2),
+                "int",
+            ),
+            (
+                "Existing key",
+                command(&["APPEND", "key", "34"]),
+                RespData::Integer(4),
+                "raw",
+            ),
+            (
+                "Wrong type",
+                command(&["APPEND", "hash", "x"]),
+                RespData::Error(
+                    "-WRONGTYPE Operation against a key holding the wrong kind of value"
+                        .to_string(),
+                ),
+                "raw",
+            ),
+            (
+                "Wrong number of arguments",
+                command(&["APPEND", "key"]),
+                RespData::Error("wrong number of arguments for 'append' command".to_string()),
+                "raw",
+            ),
+        ];
+
+        for (name, input, expected, encoding) in test_cases {
+            assert_eq!(handler.handle(&input), expected, "{}", name);
+            assert_eq!(encoding_of(&handler, "key"), encoding, "{}", name);
+        }
+        assert_eq!(
+            handler.handle(&command(&["GET", "key"])),
+            RespData::BulkString("1234".to_string())
+        );
+
+        handler.config.write().unwrap().proto_max_bulk_len = 5;
+        assert_eq!(
+            handler.handle(&command(&["APPEND", "key", "56"])),
+            RespData::Error("string exceeds maximum allowed size (proto-max-bulk-len)".to_string())
+        );
+        assert_eq!(
+            handler.handle(&command(&["GET", "key"])),
+            RespData::BulkString("1234".to_string())
+        );
+    }
+
+    #[test]
+    fn test_strlen() {
+        let mut handler = CommandHandler::from(HashMap::new());
+        handler.handle(&command(&["SET", "key", "hello"]));
+        handler.handle(&command(&["SET", "number", "-12345"]));
+        handler.handle(&command(&["HSET", "hash", "f", "v"]));
+
+        let test_cases = [
+            (
+                "Raw string",
+                command(&["STRLEN", "key"]),
+                RespData::Integer(5),
+            ),
+            (
+                "Int encoded",
+                command(&["STRLEN", "number"]),
+                RespData::Integer(6),
+            ),
+            (
+                "Missing key",
+                command(&["STRLEN", "missing"]),
+                RespData::Integer(0),
+            ),
+            (
+                "Wrong type",
+                command(&["STRLEN", "hash"]),
+                RespData::Error(
+                    "-WRONGTYPE Operation against a key holding the wrong kind of value"
+                        .to_string(),
+                ),
+            ),
+            (
+                "Wrong number of arguments",
+                command(&["STRLEN", "key", "extra"]),
+                RespData::Error("wrong number of arguments for 'strlen' command".to_string()),
+            ),
+        ];
+
+        for (name, input, expected) in test_cases {
+            assert_eq!(handler.handle(&input), expected, "{}", name);
+        }
+    }
+
+    #[test]
+    fn test_getrange() {
+        let mut handler = CommandHandler::from(HashMap::new());
+        handler.handle(&command(&["SET", "key", "This is a string"]));
+        handler.handle(&command(&["SET", "number", "12345"]));
+        handler.handle(&command(&["HSET", "hash", "f", "v"]));
+
+        let test_cases = [
+            ("Prefix", command(&["GETRANGE", "key", "0", "3"]), "This"),
+            ("Suffix", command(&["GETRANGE", "key", "-3", "-1"]), "ing"),
+            (
+                "Whole",
+                command(&["GETRANGE", "key", "0", "-1"]),
+                "This is a string",
+            ),
+            (
+                "Past the end",
+                command(&["GETRANGE", "key", "10", "100"]),
+                "string",
+            ),
+            (
+                "Start after end",
+                command(&["GETRANGE", "key", "5", "2"]),
+                "",
+            ),
+            (
+                "Int encoded",
+                command(&["GETRANGE", "number", "1", "2"]),
+                "23",
+            ),
+            (
+                "Missing key",
+                command(&["GETRANGE", "missing", "0", "-1"]),
+                "",
+            ),
+        ];
+
+        for (name, input, expected) in test_cases {
+            assert_eq!(
+                handler.handle(&input),
+                RespData::BulkString(expected.to_string()),
+                "{}",
+                name
+            );
+        }
+
+        let error_cases = [
+            (
+                "Wrong type",
+                command(&["GETRANGE", "hash", "0", "1"]),
+                "-WRONGTYPE Operation against a key holding the wrong kind of value",
+            ),
+            (
+                "Not an integer",
+                command(&["GETRANGE", "key", "a", "1"]),
+                "value is not an integer or out of range",
+            ),
+            (
+                "Wrong number of arguments",
+                command(&["GETRANGE", "key", "0"]),
+                "wrong number of arguments for 'getrange' command",
+            ),
+        ];
+
+        for (name, input, expected) in error_cases {
+            assert_eq!(
+                handler.handle(&input),
+                RespData::Error(expected.to_string()),
+                "{}",
+                name
+            );
+        }
+    }
+
+    #[test]
+    fn test_setrange() {
+        let mut handler = CommandHandler::from(HashMap::new());
+        handler.handle(&command(&["SET", "key", "Hello World"]));
+
+        let test_cases = [
+            (
+                "Overwrite",
+                command(&["SETRANGE", "key", "6", "Redis"]),
+                RespData::Integer(11),
+                "key",
+                Some("Hello Redis"),
+            ),
+            (
+                "Pad a new key",
+                command(&["SETRANGE", "padded", "3", "x"]),
+                RespData::Integer(4),
+                "padded",
+                Some("\0\0\0x"),
+            ),
+            (
+                "Empty value on a missing key",
+                command(&["SETRANGE", "missing", "5", ""]),
+                RespData::Integer(0),
+                "missing",
+                None,
+            ),
+            (
+                "Negative offset",
+                command(&["SETRANGE", "key", "-1", "x"]),
+                RespData::Error("offset is out of range".to_string()),
+                "key",
+                Some("Hello Redis"),
+            ),
+            (
+                "Too long",
+                command(&["SETRANGE", "key", "536870911", "xx"]),
+                RespData::Error(
+                    "string exceeds maximum allowed size (proto-max-bulk-len)".to_string(),
+                ),
+                "key",
+                Some("Hello Redis"),
+            ),
+        ];
+
+        for (name, input, expected, key, value) in test_cases {
+            assert_eq!(handler.handle(&input), expected, "{}", name);
+            let expected_value = value.map_or(RespData::Null, |v| RespData::BulkString(v.into()));
+            assert_eq!(
+                handler.handle(&command(&["GET", key])),
+                expected_value,
+                "{}",
+                name
+            );
+        }
+        assert_eq!(encoding_of(&handler, "key"), "raw");
+    }
+
+    #[test]
+    fn test_incr() {
+        let mut handler = CommandHandler::from(HashMap::new());
+        handler.handle(&command(&["SET", "text", "abc"]));
+        handler.handle(&command(&["SET", "max", &i64::MAX.to_string()]));
+        handler.handle(&command(&["SET", "counter", "10", "EX", "100"]));
+
+        let test_cases = [
+            ("INCR", command(&["INCR", "counter"]), RespData::Integer(11)),
+            (
+                "DECR a missing key",
+                command(&["DECR", "missing"]),
+                RespData::Integer(-1),
+            ),
+            (
+                "INCRBY",
+                command(&["INCRBY", "counter", "-20"]),
+                RespData::Integer(-9),
+            ),
+            (
+                "DECRBY",
+                command(&["DECRBY", "counter", "1"]),
+                RespData::Integer(-10),
+            ),
+            (
+                "Not an integer",
+                command(&["INCR", "text"]),
+                RespData::Error("value is not an integer or out of range".to_string()),
+            ),
+            (
+                "Bad increment",
+                command(&["INCRBY", "counter", "1.5"]),
+                RespData::Error("value is not an integer or out of range".to_string()),
+            ),
+            (
+                "Overflow",
+                command(&["INCR", "max"]),
+                RespData::Error("increment or decrement would overflow".to_string()),
+            ),
+            (
+                "Negating the decrement overflows",
+                command(&["DECRBY", "counter", &i64::MIN.to_string()]),
+                RespData::Error("decrement would overflow".to_string()),
+            ),
+            (
+                "Wrong number of arguments",
+                command(&["INCR", "counter", "1"]),
+                RespData::Error("wrong number of arguments for 'incr' command".to_string()),
+            ),
+            (
+                "The TTL is kept",
+                command(&["TTL", "counter"]),
+                RespData::Integer(100),
+            ),
+        ];
+
+        for (name, input, expected) in test_cases {
+            assert_eq!(handler.handle(&input), expected, "{}", name);
+        }
+        assert_eq!(encoding_of(&handler, "counter"), "int");
+        assert_eq!(
+            handler.handle(&command(&["GET", "counter"])),
+            RespData::BulkString("-10".to_string())
+        );
+    }
+}
@@ -0,0 +1,622 @@
+//! Storage for datasets bigger than memory. Recently used values stay in memory, up to a
+//! budget, and the rest spill to a file, from which `load` brings a value back when a command
+//! needs it. Keys and TTLs always stay in memory, so it is the values that may outgrow it.
+//!
+//! Which values spill is approximated LRU, like eviction: the least recently used of a few
+//! sampled values goes first.
+//!
+//! The file extends memory rather than persisting anything: it is emptied when opened and
+//! never synced. Promoted and overwritten values leave their old copy behind as garbage, which
+//! is reclaimed by rewriting the file once it is mostly garbage.
+
+use super::evict::xorshift64_star;
+use super::memory::entry_size;
+use super::{RedisValue, Storage};
+use crate::dict::Dict;
+use crate::rdb;
+use std::fs::{self, File, OpenOptions};
+use std::io;
+use std::mem;
+use std::os::unix::fs::FileExt;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+
+/// How many values in memory are sampled to pick the one to spill.
+const SPILL_SAMPLES: usize = 8;
+/// The file isn't rewritten while it has less garbage than this, however much of it that is.
+const MIN_COMPACT_GARBAGE: u64 = 1024 * 1024;
+/// Set in SCAN cursors once the values in memory have been visited and the spilled ones are
+/// next. Dict cursors never use the top bit.
+const COLD_CURSOR: u64 = 1 << 63;
+
+struct HotValue {
+    value: RedisValue,
+    /// What `value` was measured at, as counted in `hot_bytes`.
+    size: u64,
+    last_access: u64,
+}
+
+/// Where a spilled value is in the file.
+#[derive(Clone, Copy)]
+struct ColdValue {
+    offset: u64,
+    len: u64,
+}
+
+/// A storage keeping values in memory up to a budget and spilling the rest to a file.
+///
+/// `get` only sees values in memory, while `contains_key` and `len` count spilled ones too.
+/// Commands reach every value, since they `load` a key before using it. A SCAN may miss a key
+/// whose value is spilled or loaded while it runs.
+pub struct TieredStorage {
+    hot: Dict<String, HotValue>,
+    cold: Dict<String, ColdValue>,
+    /// Absolute expiry deadlines for the keys that have a TTL, in memory or not.
+    expires: Dict<String, Instant>,
+    /// Bytes of values in memory, as estimated for `used_memory`.
+    hot_bytes: u64,
+    max_hot_bytes: u64,
+    /// Keys handed out by `get_mut`, whose values may have changed size since measured.
+    resized: Vec<String>,
+    /// Counts accesses, telling which values were used least recently.
+    ticks: u64,
+    random_state: u64,
+    path: PathBuf,
+    file: File,
+    file_len: u64,
+    /// Bytes of the file not belonging to any spilled value.
+    garbage: u64,
+}
+
+impl TieredStorage {
+    /// Storage keeping about `max_hot_bytes` of values in memory and spilling the rest to
+    /// `path`, which is created or emptied, and removed when the storage is dropped.
+    pub fn open(path: impl Into<PathBuf>, max_hot_bytes: u64) -> io::Result<Self> {
+        let path = path.into();
+        let file = open_empty(&path)?;
+        Ok(Self {
+            hot: Dict::new(),
+            cold: Dict::new(),
+            expires: Dict::new(),
+            hot_bytes: 0,
+            max_hot_bytes,
+            resized: Vec::new(),
+            ticks: 0,
+            random_state: 0x2545_f491_4f6c_dd1d,
+            path,
+            file,
+            file_len: 0,
+            garbage: 0,
+        })
+    }
+
+    /// How many values are in memory.
+    pub fn hot_len(&self) -> usize {
+        self.hot.len()
+    }
+
+    /// How many values are spilled to the file.
+    pub fn cold_len(&self) -> usize {
+        self.cold.len()
+    }
+
+    fn tick(&mut self) -> u64 {
+        self.ticks += 1;
+        self.ticks
+    }
+
+    fn insert_hot(&mut self, key: String, value: RedisValue) -> Option<RedisValue> {
+        let size = entry_size(&key, &value);
+        let last_access = self.tick();
+        self.hot_bytes += size;
+        let old = self.hot.insert(
+            key,
+            HotValue {
+                value,
+                size,
+                last_access,
+            },
+        )?;
+        self.hot_bytes -= old.size;
+        Some(old.value)
+    }
+
+    fn read_cold(&self, cold: ColdValue) -> io::Result<RedisValue> {
+        let mut data = vec![0; cold.len as usize];
+        self.file.read_exact_at(&mut data, cold.offset)?;
+        rdb::decode_value(&data)
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e.to_string()))
+    }
+
+    /// Reads a spilled value, logging why if it can't, in which case it is lost.
+    fn read_cold_logged(&self, key: &str, cold: ColdValue) -> Option<RedisValue> {
+        self.read_cold(cold)
+            .inspect_err(|e| {
+                log_warning!("Can't read the value of '{key}' from the tiered storage file: {e}")
+            })
+            .ok()
+    }
+
+    /// Takes the spilled value at `key` out of the file.
+    fn take_cold(&mut self, key: &str) -> Option<RedisValue> {
+        let cold = self.cold.remove(key)?;
+        self.garbage += cold.len;
+        let value = self.read_cold_logged(key, cold);
+        if value.is_none() {
+            self.expires.remove(key);
+        }
+        value
+    }
+
+    /// Brings the value at `key` into memory, if it exists, and marks it as just used.
+    fn promote(&mut self, key: &str) {
+        let ticks = self.tick();
+        if let Some(hot) = self.hot.get_mut(key) {
+            hot.last_access = ticks;
+        } else if let Some(value) = self.take_cold(key) {
+            self.insert_hot(key.to_string(), value);
+        }
+    }
+
+    /// Measures again the values handed out by `get_mut`.
+    fn measure_resized(&mut self) {
+        for key in mem::take(&mut self.resized) {
+            if let Some(hot) = self.hot.get_mut(&key) {
+                let size = entry_size(&key, &hot.value);
+                self.hot_bytes = self.hot_bytes - hot.size + size;
+                hot.size = size;
+            }
+        }
+    }
+
+    /// Spills values until the ones in memory fit the budget, sparing `keep`, which is about
+    /// to be used.
+    fn spill(&mut self, keep: Option<&str>) {
+        self.measure_resized();
+        while self.hot_bytes > self.max_hot_bytes {
+            let Some(key) = self.spill_candidate(keep) else {
+                return;
+            };
+            if let Err(e) = self.spill_key(key) {
+                log_warning!("Can't write to the tiered storage file: {e}");
+                return;
+            }
+        }
+    }
+
+    /// The least recently used of a few sampled keys in memory other than `keep`.
+    fn spill_candidate(&mut self, keep: Option<&str>) -> Option<String> {
+        let mut best: Option<(&String, u64)> = None;
+        for _ in 0..SPILL_SAMPLES {
+            let (key, hot) = self
+                .hot
+                .random_entry(|| xorshift64_star(&mut self.random_state))?;
+            if Some(key.as_str()) != keep
+                && best.is_none_or(|(_, last_access)| hot.last_access < last_access)
+            {
+                best = Some((key, hot.last_access));
+            }
+        }
+        best.map(|(key, _)| key.clone())
+    }
+
+    fn spill_key(&mut self, key: String) -> io::Result<()> {
+        let Some(hot) = self.hot.get(&key) else {
+            return Ok(());
+        };
+        let data = rdb::encode_value(&hot.value);
+        self.file.write_all_at(&data, self.file_len)?;
+        let cold = ColdValue {
+            offset: self.file_len,
+            len: data.len() as u64,
+        };
+        self.file_len += cold.len;
+        if let Some(hot) = self.hot.remove(&key) {
+            self.hot_bytes -= hot.size;
+        }
+        self.cold.insert(key, cold);
+        Ok(())
+    }
+
+    /// Rewrites the file without its garbage once that is most of it.
+    fn compact_if_needed(&mut self) {
+        if self.garbage < MIN_COMPACT_GARBAGE || self.garbage * 2 < self.file_len {
+            return;
+        }
+        if let Err(e) = self.compact() {
+            log_warning!("Can't compact the tiered storage file: {e}");
+        }
+    }
+
+    /// Copies the spilled values to a new file, which then replaces the old one.
+    fn compact(&mut self) -> io::Result<()> {
+        let tmp = self.path.with_extension("compact");
+        let result = self.copy_cold(&tmp);
+        if result.is_err() {
+            let _ = fs::remove_file(&tmp);
+        }
+        let (file, offsets) = result?;
+        fs::rename(&tmp, &self.path)?;
+        for (key, offset) in offsets {
+            if let Some(cold) = self.cold.get_mut(&key) {
+                cold.offset = offset;
+            }
+        }
+        self.file = file;
+        self.file_len -= self.garbage;
+        self.garbage = 0;
+        Ok(())
+    }
+
+    /// Writes the spilled values back to back in a new file at `path`, returning it along with
+    /// each value's new offset.
+    fn copy_cold(&self, path: &Path) -> io::Result<(File, Vec<(String, u64)>)> {
+        let file = open_empty(path)?;
+        let mut offsets = Vec::with_capacity(self.cold.len());
+        let mut offset = 0;
+        for (key, cold) in &self.cold {
+            let mut data = vec![0; cold.len as usize];
+            self.file.read_exact_at(&mut data, cold.offset)?;
+            file.write_all_at(&data, offset)?;
+            offsets.push((key.clone(), offset));
+            offset += cold.len;
+        }
+        Ok((file, offsets))
+    }
+
+    /// Forgets the spilled values and empties the file.
+    fn clear_cold(&mut self) {
+        self.cold.clear();
+        self.file_len = 0;
+        self.garbage = 0;
+        if let Err(e) = self.file.set_len(0) {
+            log_warning!("Can't truncate the tiered storage file: {e}");
+        }
+    }
+}
+
+fn open_empty(path: &Path) -> io::Result<File> {
+    OpenOptions::new()
+        .read(true)
+        .write(true)
+        .create(true)
+        .truncate(true)
+        .open(path)
+}
+
+impl Storage for TieredStorage {
+    fn len(&self) -> usize {
+        self.hot.len() + self.cold.len()
+    }
+
+    fn load(&mut self, key: &str) {
+        self.promote(key);
+        self.spill(Some(key));
+    }
+
+    fn get(&self, key: &str) -> Option<&RedisValue> {
+        self.hot.get(key).map(|hot| &hot.value)
+    }
+
+    fn get_mut(&mut self, key: &str) -> Option<&mut RedisValue> {
+        if !self.hot.contains_key(key) {
+            self.load(key);
+        }
+        let hot = self.hot.get_mut(key)?;
+        self.resized.push(key.to_string());
+        Some(&mut hot.value)
+    }
+
+    fn contains_key(&self, key: &str) -> bool {
+        self.hot.contains_key(key) || self.cold.contains_key(key)
+    }
+
+    fn insert(&mut self, key: String, value: RedisValue) -> Option<RedisValue> {
+        let cold = self.take_cold(&key);
+        let old = self.insert_hot(key.clone(), value).or(cold);
+        self.spill(Some(&key));
+        old
+    }
+
+    fn remove(&mut self, key: &str) -> Option<RedisValue> {
+        self.expires.remove(key);
+        match self.hot.remove(key) {
+            Some(hot) => {
+                self.hot_bytes -= hot.size;
+                Some(hot.value)
+            }
+            None => self.take_cold(key),
+        }
+    }
+
+    fn for_each(&self, visit: &mut dyn FnMut(&String, &RedisValue)) {
+        for (key, hot) in &self.hot {
+            visit(key, &hot.value);
+        }
+        for (key, cold) in &self.cold {
+            if let Some(value) = self.read_cold_logged(key, *cold) {
+                visit(key, &value);
+            }
+        }
+    }
+
+    fn random_key(&self, random: &mut dyn FnMut() -> u64) -> Option<&String> {
+        let len = self.len() as u64;
+        if len == 0 {
+            return None;
+        }
+        // Picking the tier by how many keys each holds keeps every key equally likely.
+        if random() % len < self.hot.len() as u64 {
+            self.hot.random_entry(random).map(|(key, _)| key)
+        } else {
+            self.cold.random_entry(random).map(|(key, _)| key)
+        }
+    }
+
+    fn scan(&self, cursor: u64, visit: &mut dyn FnMut(&String, &RedisValue)) -> u64 {
+        if cursor & COLD_CURSOR == 0 {
+            let next = self.hot.scan(cursor, |key, hot| visit(key, &hot.value));
+            return match next {
+                0 if !self.cold.is_empty() => COLD_CURSOR,
+                next => next,
+            };
+        }
+        let next = self.cold.scan(cursor & !COLD_CURSOR, |key, cold| {
+            if let Some(value) = self.read_cold_logged(key, *cold) {
+                visit(key, &value);
+            }
+        });
+        match next {
+            0 => 0,
+            next => next | COLD_CURSOR,
+        }
+    }
+
+    fn expire_at(&self, key: &str) -> Option<Instant> {
+        self.expires.get(key).copied()
+    }
+
+    fn set_expire(&mut self, key: &str, deadline: Instant) {
+        self.expires.insert(key.to_string(), deadline);
+    }
+
+    fn persist(&mut self, key: &str) -> Option<Instant> {
+        self.expires.remove(key)
+    }
+
+    fn expires_len(&self) -> usize {
+        self.expires.len()
+    }
+
+    fn expires(&self) -> Box<dyn Iterator<Item = (&String, Instant)> + '_> {
+        Box::new(self.expires.iter().map(|(key, deadline)| (key, *deadline)))
+    }
+
+    fn random_expiring(&self, random: &mut dyn FnMut() -> u64) -> Option<(&String, Instant)> {
+        self.expires
+            .random_entry(random)
+            .map(|(key, deadline)| (key, *deadline))
+    }
+
+    fn clear(&mut self) {
+        self.hot.clear();
+        self.expires.clear();
+        self.hot_bytes = 0;
+        self.resized.clear();
+        self.clear_cold();
+    }
+
+    fn take(&mut self) -> Box<dyn Send> {
+        let taken = Box::new((self.hot.take(), self.expires.take()));
+        self.hot_bytes = 0;
+        self.resized.clear();
+        self.clear_cold();
+        taken
+    }
+
+    /// Also spills values that outgrew memory in place and compacts the file, which isn't
+    /// bounded by `budget`.
+    fn background_work(&mut self, budget: Duration) {
+        self.spill(None);
+        self.compact_if_needed();
+        self.hot.rehash_for(budget);
+        self.cold.rehash_for(budget);
+        self.expires.rehash_for(budget);
+    }
+
+    fn info_fields(&self) -> Vec<(&'static str, u64)> {
+        vec![
+            ("tiered_hot_keys", self.hot.len() as u64),
+            ("tiered_hot_bytes", self.hot_bytes),
+            ("tiered_cold_keys", self.cold.len() as u64),
+            ("tiered_file_bytes", self.file_len),
+            ("tiered_file_garbage_bytes", self.garbage),
+        ]
+    }
+}
+
+impl Drop for TieredStorage {
+    fn drop(&mut self) {
+        let _ = fs::remove_file(&self.path);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::Config;
+    use crate::handler::CommandHandler;
+    use crate::resp::RespData;
+    use std::collections::HashMap;
+    use std::process;
+    use std::sync::{Arc, RwLock};
+
+    fn command(args: &[&str]) -> RespData {
+        RespData::array(args.iter().copied())
+    }
+
+    fn temp_path(name: &str) -> PathBuf {
+        std::env::temp_dir().join(format!("redis-tiered-{name}-{}.dat", process::id()))
+    }
+
+    fn string(value: &str) -> RedisValue {
+        RedisValue::String(value.into())
+    }
+
+    fn text(value: Option<&RedisValue>) -> Option<String> {
+        match value? {
+            RedisValue::String(s) => Some(s.to_string()),
+            _ => None,
+        }
+    }
+
+    fn value_budget(values: u64) -> u64 {
+        entry_size("key:00", &string("value:00")) * values
+    }
+
+    #[test]
+    fn test_tiered_storage() {
+        let mut storage = TieredStorage::open(temp_path("storage"), value_budget(10)).unwrap();
+        let deadline = Instant::now() + Duration::from_secs(10);
+        for i in 0..100 {
+            storage.insert(format!("key:{i:02}"), string(&format!("value:{i:02}")));
+        }
+        storage.set_expire("key:00", deadline);
+        assert_eq!(storage.len(), 100);
+        assert_eq!(storage.hot_len(), 10);
+        assert_eq!(storage.cold_len(), 90);
+
+        assert!(storage.get("key:00").is_none(), "Spilled, so not in memory");
+        assert!(storage.contains_key("key:00"));
+        storage.load("key:00");
+        assert_eq!(text(storage.get("key:00")).as_deref(), Some("value:00"));
+        assert_eq!(storage.expire_at("key:00"), Some(deadline));
+        assert_eq!(storage.hot_len(), 10, "Another value spilled to make room");
+
+        *storage.get_mut("key:01").unwrap() = string(&"x".repeat(200));
+        storage.background_work(Duration::ZERO);
+        assert!(storage.hot_bytes <= storage.max_hot_bytes);
+
+        assert_eq!(
+            text(storage.remove("key:02").as_ref()).as_deref(),
+            Some("value:02")
+        );
+        let old = storage.insert("key:03".to_string(), string("new"));
+        assert_eq!(text(old.as_ref()).as_deref(), Some("value:03"));
+
+        let mut visited = Vec::new();
+        let mut cursor = 0;
+        loop {
+            cursor = storage.scan(cursor, &mut |key, _| visited.push(key.clone()));
+            if cursor == 0 {
+                break;
+            }
+        }
+        visited.sort();
+        let expected: Vec<_> = (0..100)
+            .filter(|i| *i != 2)
+            .map(|i| format!("key:{i:02}"))
+            .collect();
+        assert_eq!(visited, expected);
+
+        let mut values = HashMap::new();
+        storage.for_each(&mut |key, value| {
+            values.insert(key.clone(), text(Some(value)).unwrap());
+        });
+        assert_eq!(values.len(), 99);
+        assert_eq!(values["key:03"], "new");
+        assert_eq!(values["key:99"], "value:99");
+
+        let file_len = storage.file_len;
+        storage.compact().unwrap();
+        assert!(storage.file_len < file_len);
+        assert_eq!(storage.garbage, 0);
+        storage.load("key:98");
+        assert_eq!(text(storage.get("key:98")).as_deref(), Some("value:98"));
+
+        storage.take();
+        assert!(storage.is_empty());
+        assert_eq!(storage.file_len, 0);
+    }
+
+    #[test]
+    fn test_tiered_handler() {
+        let config = Arc::new(RwLock::new(Config::default()));
+        let storage = TieredStorage::open(temp_path("handler"), value_budget(5)).unwrap();
+        let mut tiered = CommandHandler::with_storage(Box::new(storage), Arc::clone(&config));
+        let mut memory = CommandHandler::new(HashMap::new(), config);
+
+        for handler in [&mut tiered, &mut memory] {
+            for i in 0..50 {
+                let key = format!("key:{i:02}");
+                handler.handle(&command(&["SET", &key, &format!("value:{i:02}")]));
+                handler.handle(&command(&["HSET", &format!("hash:{i:02}"), "f", &key]));
+            }
+            handler.handle(&command(&["APPEND", "key:00", "!"]));
+            handler.handle(&command(&["HSET", "hash:01", "g", "2"]));
+            handler.handle(&command(&["DEL", "key:02"]));
+            handler.handle(&command(&["EXPIRE", "key:03", "100"]));
+        }
+        let fields = tiered.db.info_fields();
+        let (_, cold_keys) = fields
+            .iter()
+            .find(|(name, _)| *name == "tiered_cold_keys")
+            .unwrap();
+        assert!(*cold_keys >= 90, "{cold_keys} values spilled");
+        assert_eq!(tiered.used_memory, memory.used_memory);
+        assert_eq!(tiered.dataset_digest(), memory.dataset_digest());
+
+        let test_cases = [
+            (
+                "Spilled string",
+                command(&["GET", "key:00"]),
+                RespData::BulkString("value:00!".to_string()),
+            ),
+            (
+                "Spilled hash",
+                command(&["HGET", "hash:01", "g"]),
+                RespData::BulkString("2".to_string()),
+            ),
+            ("Deleted", command(&["GET", "key:02"]), RespData::Null),
+            (
+                "TTL kept",
+                command(&["TTL", "key:03"]),
+                RespData::Integer(100),
+            ),
+            (
+                "Type of a spilled value",
+                command(&["TYPE", "hash:40"]),
+                RespData::SimpleString("hash".to_string()),
+            ),
+        ];
+
+        for (name, input, expected) in test_cases {
+            assert_eq!(tiered.handle(&input), expected, "{}", name);
+        }
+    }
+
+    #[test]
+    fn test_tiered_hot_keys() {
+        // Every access increments the counter, so frequencies are exact.
+        let config = Config {
+            lfu_log_factor: 0,
+            ..Config::default()
+        };
+        let storage = TieredStorage::open(temp_path("hotkeys"), value_budget(5)).unwrap();
+        let mut handler =
+            CommandHandler::with_storage(Box::new(storage), Arc::new(RwLock::new(config)));
+        handler.handle(&command(&["SET", "hot", "value"]));
+        for _ in 0..3 {
+            handler.handle(&command(&["GET", "hot"]));
+        }
+        for i in 0..20 {
+            handler.handle(&command(&["SET", &format!("key:{i:02}"), "value"]));
+        }
+        assert!(handler.db.get("hot").is_none(), "Spilled, so not in memory");
+
+        assert_eq!(
+            handler.hot_keys(10),
+            vec![("string", vec![("hot".to_string(), 8)])]
+        );
+    }
+}
@@ -0,0 +1,364 @@
+//! The time series data type and its RedisTimeSeries commands, for keeping lightweight
+//! metrics without a separate module.
+
+use super::command::{RangeOptions, TsOptions};
+use super::errors;
+use super::events::KeyEventKind;
+use super::{CommandHandler, RedisValue};
+use crate::resp::RespData;
+use crate::timeseries::{DuplicatePolicy, LabelFilter, TimeSeries};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+pub(super) fn tsdb_error(message: &str) -> RespData {
+    RespData::Error(format!("TSDB: {message}"))
+}
+
+fn new_series(options: TsOptions) -> TimeSeries {
+    TimeSeries::new(
+        options.retention.unwrap_or(0),
+        options.duplicate_policy.unwrap_or_default(),
+        options.labels,
+    )
+}
+
+/// The samples of a range query, each a timestamp and its value as a simple string.
+fn samples_reply(series: &TimeSeries, from: u64, to: u64, options: &RangeOptions) -> RespData {
+    let mut samples = series.range(from, to, options.aggregation);
+    if let Some(count) = options.count {
+        samples.truncate(count);
+    }
+    RespData::Array(
+        samples
+            .into_iter()
+            .map(|(timestamp, value)| {
+                RespData::Array(vec![
+                    RespData::Integer(timestamp as i64),
+                    RespData::SimpleString(value.to_string()),
+                ])
+            })
+            .collect(),
+    )
+}
+
+impl CommandHandler {
+    /// TS.CREATE key [RETENTION ms] [DUPLICATE_POLICY policy] [LABELS label value ...]:
+    /// creates an empty series.
+    pub(super) fn ts_create(&mut self, key: &str, options: TsOptions) -> RespData {
+        self.expire_if_needed(key);
+        if self.db.get(key).is_some() {
+            return tsdb_error("key already exists");
+        }
+        let before = self.key_memory(key);
+        self.db
+            .insert(key.to_string(), RedisValue::TimeSeries(new_series(options)));
+        self.touch_key(key);
+        self.account_key_change(key, before);
+        self.notify_key_event(KeyEventKind::Set, key);
+        RespData::SimpleString("OK".to_string())
+    }
+
+    /// TS.ADD key timestamp|* value [RETENTION ms] [DUPLICATE_POLICY policy]
+    /// [ON_DUPLICATE policy] [LABELS label value ...]: adds a sample at `timestamp`, or now
+    /// for `*`, creating the series with the options if there is none.
+    pub(super) fn ts_add(
+        &mut self,
+        key: &str,
+        timestamp: Option<u64>,
+        value: f64,
+        options: TsOptions,
+        on_duplicate: Option<DuplicatePolicy>,
+    ) -> RespData {
+        let timestamp = timestamp.unwrap_or_else(|| {
+            let since_epoch = SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .unwrap_or_default();
+            since_epoch.as_millis() as u64
+        });
+        self.expire_if_needed(key);
+        let before = self.key_memory(key);
+        match self.db.get(key) {
+            Some(RedisValue::TimeSeries(_)) => {}
+            Some(_) => return errors::wrong_type(),
+            None => {
+                self.db
+                    .insert(key.to_string(), RedisValue::TimeSeries(new_series(options)));
+            }
+        }
+        let Some(RedisValue::TimeSeries(series)) = self.db.get_mut(key) else {
+            unreachable!("the key holds a series");
+        };
+        // A new series always takes its first sample, so a failure changed nothing.
+        if let Err(e) = series.add(timestamp, value, on_duplicate) {
+            return tsdb_error(&e);
+        }
+
+        self.touch_key(key);
+        self.account_key_change(key, before);
+        self.notify_key_event(KeyEventKind::Set, key);
+        RespData::Integer(timestamp as i64)
+    }
+
+    /// TS.RANGE key from to [COUNT count] [AGGREGATION aggregator bucket]: the samples from
+    /// `from` to `to`, oldest first.
+    pub(super) fn ts_range(
+        &mut self,
+        key: &str,
+        from: u64,
+        to: u64,
+        options: &RangeOptions,
+    ) -> RespData {
+        match self.lookup_key_read(key) {
+            Some(RedisValue::TimeSeries(series)) => samples_reply(series, from, to, options),
+            Some(_) => errors::wrong_type(),
+            None => tsdb_error("the key does not exist"),
+        }
+    }
+
+    /// TS.MRANGE from to [WITHLABELS] [COUNT count] [AGGREGATION aggregator bucket] FILTER
+    /// filter ...: the samples of every series matching all the filters, each as its key, its
+    /// labels with WITHLABELS, and its samples, ordered by key.
+    pub(super) fn ts_mrange(
+        &mut self,
+        from: u64,
+        to: u64,
+        options: &RangeOptions,
+        filters: &[LabelFilter],
+    ) -> RespData {
+        let mut keys = Vec::new();
+        self.db.for_each(&mut |key, value| {
+            if let RedisValue::TimeSeries(series) = value {
+                if filters.iter().all(|filter| filter.matches(series)) {
+                    keys.push(key.clone());
+                }
+            }
+        });
+        keys.retain(|key| !self.expire_if_needed(key));
+        keys.sort();
+
+        let mut reply = Vec::with_capacity(keys.len());
+        for key in keys {
+            let Some(RedisValue::TimeSeries(series)) = self.lookup_key_read(&key) else {
+                continue;
+            };
+            let labels = if options.with_labels {
+                series
+                    .labels()
+                    .iter()
+                    .map(|(name, value)| RespData::array([name, value]))
+                    .collect()
+            } else {
+                Vec::new()
+            };
+            let samples = samples_reply(series, from, to, options);
+            reply.push(RespData::Array(vec![
+                RespData::BulkString(key),
+                RespData::Array(labels),
+                samples,
+            ]));
+        }
+        RespData::Array(reply)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+
+    fn command(args: &[&str]) -> RespData {
+        RespData::array(args.iter().copied())
+    }
+
+    fn samples(samples: &[(i64, &str)]) -> RespData {
+        RespData::Array(
+            samples
+                .iter()
+                .map(|(timestamp, value)| {
+                    RespData::Array(vec![
+                        RespData::Integer(*timestamp),
+                        RespData::SimpleString(value.to_string()),
+                    ])
+                })
+                .collect(),
+        )
+    }
+
+    #[test]
+    fn test_timeseries_commands() {
+        let mut handler = CommandHandler::from(HashMap::new());
+        handler.handle(&command(&["SET", "string", "x"]));
+
+        let test_cases = [
+            (
+                "Create a series",
+                command(&[
+                    "TS.CREATE",
+                    "temp:eu",
+                    "RETENTION",
+                    "1000",
+                    "LABELS",
+                    "metric",
+                    "temp",
+                    "area",
+                    "eu",
+                ]),
+                RespData::SimpleString("OK".to_string()),
+            ),
+            (
+                "Create it again",
+                command(&["TS.CREATE", "temp:eu"]),
+                tsdb_error("key already exists"),
+            ),
+            (
+                "Add a sample",
+                command(&["TS.ADD", "temp:eu", "1000", "20.5"]),
+                RespData::Integer(1000),
+            ),
+            (
+                "Add a duplicate",
+                command(&["TS.ADD", "temp:eu", "1000", "21"]),
+                tsdb_error(
+                    "Error at upsert, update is not supported when DUPLICATE_POLICY is set \
+                     to BLOCK mode",
+                ),
+            ),
+            (
+                "Add a duplicate with a policy",
+                command(&["TS.ADD", "temp:eu", "1000", "21", "ON_DUPLICATE", "max"]),
+                RespData::Integer(1000),
+            ),
+            (
+                "Add more samples",
+                command(&["TS.ADD", "temp:eu", "1500", "23"]),
+                RespData::Integer(1500),
+            ),
+            (
+                "Add a sample older than the retention",
+                command(&["TS.ADD", "temp:eu", "100", "1"]),
+                tsdb_error("Timestamp is older than retention"),
+            ),
+            (
+                "Add creates a series",
+                command(&[
+                    "TS.ADD", "temp:us", "1200", "30", "LABELS", "metric", "temp", "area", "us",
+                ]),
+                RespData::Integer(1200),
+            ),
+            (
+                "Invalid value",
+                command(&["TS.ADD", "temp:us", "1300", "warm"]),
+                tsdb_error("invalid value"),
+            ),
+            (
+                "Range",
+                command(&["TS.RANGE", "temp:eu", "-", "+"]),
+                samples(&[(1000, "21"), (1500, "23")]),
+            ),
+            (
+                "Range with a count",
+                command(&["TS.RANGE", "temp:eu", "0", "2000", "COUNT", "1"]),
+                samples(&[(1000, "21")]),
+            ),
+            (
+                "Range with an aggregation",
+                command(&[
+                    "TS.RANGE",
+                    "temp:eu",
+                    "-",
+                    "+",
+                    "AGGREGATION",
+                    "avg",
+                    "10000",
+                ]),
+                samples(&[(0, "22")]),
+            ),
+            (
+                "Unknown aggregation",
+                command(&[
+                    "TS.RANGE",
+                    "temp:eu",
+                    "-",
+                    "+",
+                    "AGGREGATION",
+                    "median",
+                    "10",
+                ]),
+                tsdb_error("Unknown aggregation type"),
+            ),
+            (
+                "Range of a missing key",
+                command(&["TS.RANGE", "missing", "-", "+"]),
+                tsdb_error("the key does not exist"),
+            ),
+            (
+                "Range of another type",
+                command(&["TS.RANGE", "string", "-", "+"]),
+                errors::wrong_type(),
+            ),
+            (
+                "Multiple ranges",
+                command(&[
+                    "TS.MRANGE",
+                    "-",
+                    "+",
+                    "AGGREGATION",
+                    "max",
+                    "1000",
+                    "FILTER",
+                    "metric=temp",
+                ]),
+                RespData::Array(vec![
+                    RespData::Array(vec![
+                        RespData::bulk("temp:eu"),
+                        RespData::Array(Vec::new()),
+                        samples(&[(1000, "23")]),
+                    ]),
+                    RespData::Array(vec![
+                        RespData::bulk("temp:us"),
+                        RespData::Array(Vec::new()),
+                        samples(&[(1000, "30")]),
+                    ]),
+                ]),
+            ),
+            (
+                "Multiple ranges with labels",
+                command(&[
+                    "TS.MRANGE",
+                    "-",
+                    "+",
+                    "WITHLABELS",
+                    "FILTER",
+                    "metric=temp",
+                    "area!=eu",
+                ]),
+                RespData::Array(vec![RespData::Array(vec![
+                    RespData::bulk("temp:us"),
+                    RespData::Array(vec![
+                        RespData::array(["metric", "temp"]),
+                        RespData::array(["area", "us"]),
+                    ]),
+                    samples(&[(1200, "30")]),
+                ])]),
+            ),
+            (
+                "Only negative filters",
+                command(&["TS.MRANGE", "-", "+", "FILTER", "area!=eu"]),
+                tsdb_error("please provide at least one matcher"),
+            ),
+            (
+                "No filters",
+                command(&["TS.MRANGE", "-", "+"]),
+                tsdb_error("missing FILTER argument"),
+            ),
+            (
+                "Type name",
+                command(&["TYPE", "temp:eu"]),
+                RespData::SimpleString("TSDB-TYPE".to_string()),
+            ),
+        ];
+
+        for (name, input, expected) in test_cases {
+            assert_eq!(handler.handle(&input), expected, "{}", name);
+        }
+    }
+}
@@ -0,0 +1,873 @@
+//! Server-assisted client-side caching with CLIENT TRACKING. In the default mode the server
+//! remembers which keys each tracking client has read and tells it when one of them changes,
+//! so the client can drop it from its cache. In broadcasting mode (BCAST) nothing is
+//! remembered per key: the client hears about every key matching one of its prefixes. RESP3
+//! clients get `invalidate` push messages on their own connection, while RESP2 clients have
+//! them sent to another one with REDIRECT.
+
+use super::acl;
+use super::client::ClientId;
+use super::command_table::{self, READONLY};
+use super::errors;
+use super::CommandHandler;
+use crate::resp::RespData;
+use std::mem;
+
+/// The channel Redis publishes invalidations on for RESP2 connections.
+const INVALIDATE_CHANNEL: &str = "__redis__:invalidate";
+
+/// How a client asked to be tracked.
+pub struct Tracking {
+    /// The client that receives the invalidations instead, if any.
+    pub redirect: Option<ClientId>,
+    /// Don't send invalidations for keys the client modified itself.
+    pub noloop: bool,
+    /// Invalidate every key matching `prefixes` instead of the keys the client read.
+    pub bcast: bool,
+    /// The prefixes registered in broadcasting mode; an empty one matches every key.
+    pub prefixes: Vec<String>,
+    /// Only track keys read by the command right after CLIENT CACHING YES.
+    pub optin: bool,
+    /// Track keys unless read by the command right after CLIENT CACHING NO.
+    pub optout: bool,
+    /// What CLIENT CACHING asked for the next command.
+    pub caching: Option<bool>,
+}
+
+impl CommandHandler {
+    /// CLIENT TRACKING ON|OFF [REDIRECT client-id] [PREFIX prefix [PREFIX prefix ...]] [BCAST]
+    /// [OPTIN] [OPTOUT] [NOLOOP]. Turning tracking on again changes the options, but not the
+    /// mode, and adds to the prefixes already registered.
+    pub(super) fn client_tracking(&mut self, args: &[RespData]) -> RespData {
+        let [RespData::BulkString(mode), options @ ..] = args else {
+            return errors::wrong_arity("client|tracking");
+        };
+        let on = match mode.to_uppercase().as_str() {
+            "ON" => true,
+            "OFF" => false,
+            _ => return errors::syntax_error(),
+        };
+        let mut tracking = Tracking {
+            redirect: None,
+            noloop: false,
+            bcast: false,
+            prefixes: vec![],
+            optin: false,
+            optout: false,
+            caching: None,
+        };
+        let mut options = options.iter();
+        while let Some(option) = options.next() {
+            let RespData::BulkString(option) = option else {
+                return errors::syntax_error();
+            };
+            match option.to_uppercase().as_str() {
+                "REDIRECT" => {
+                    let Some(RespData::BulkString(id)) = options.next() else {
+                        return errors::syntax_error();
+                    };
+                    let Ok(id) = id.parse::<ClientId>() else {
+                        return errors::not_an_integer();
+                    };
+                    tracking.redirect = Some(id);
+                }
+                "PREFIX" => {
+                    let Some(RespData::BulkString(prefix)) = options.next() else {
+                        return errors::syntax_error();
+                    };
+                    tracking.prefixes.push(prefix.clone());
+                }
+                "NOLOOP" => tracking.noloop = true,
+                "BCAST" => tracking.bcast = true,
+                "OPTIN" => tracking.optin = true,
+                "OPTOUT" => tracking.optout = true,
+                _ => return errors::syntax_error(),
+            }
+        }
+
+        let Some(id) = self.current_client else {
+            return RespData::SimpleString("OK".to_string());
+        };
+        if !on {
+            self.disable_tracking(id);
+        } else if let Some(error) = self.check_tracking(id, &tracking) {
+            return error;
+        } else {
+            self.enable_tracking(id, tracking);
+        }
+        RespData::SimpleString("OK".to_string())
+    }
+
+    /// The error for turning tracking on for `id` with these options, if they don't go
+    /// together or with how the client is tracked already.
+    fn check_tracking(&self, id: ClientId, tracking: &Tracking) -> Option<RespData> {
+        let error = |message: String| Some(RespData::Error(message));
+        let current = self
+            .clients
+            .get(&id)
+            .and_then(|client| client.tracking.as_ref());
+        if let Some(current) = current {
+            if current.bcast != tracking.bcast {
+                return error(
+                    "You can't switch BCAST mode on/off before disabling tracking for this \
+                     client, and then re-enabling it with a different mode."
+                        .to_string(),
+                );
+            }
+            if (tracking.optin && current.optout) || (tracking.optout && current.optin) {
+                return error(
+                    "You can't switch OPTIN/OPTOUT mode before disabling tracking for this \
+                     client, and then re-enabling it with a different mode."
+                        .to_string(),
+                );
+            }
+        }
+        if !tracking.prefixes.is_empty() && !tracking.bcast {
+            return error("PREFIX option requires BCAST mode to be enabled".to_string());
+        }
+        if tracking.bcast && (tracking.optin || tracking.optout) {
+            return error("OPTIN and OPTOUT are not compatible with BCAST".to_string());
+        }
+        if tracking.optin && tracking.optout {
+            return error("You can't use both OPTIN and OPTOUT".to_string());
+        }
+        if tracking
+            .redirect
+            .is_some_and(|redirect| !self.clients.contains_key(&redirect))
+        {
+            return error("The client ID you want redirect to does not exist".to_string());
+        }
+
+        // Overlapping prefixes would invalidate the same key twice.
+        let registered = current.map_or(&[][..], |current| &current.prefixes[..]);
+        for (i, prefix) in tracking.prefixes.iter().enumerate() {
+            let overlaps = |other: &String| prefix.starts_with(other) || other.starts_with(prefix);
+            if let Some(other) = registered.iter().find(|other| overlaps(other)) {
+                return error(format!(
+                    "Prefix '{prefix}' overlaps with an existing prefix '{other}'. Prefixes for \
+                     a single client must not overlap."
+                ));
+            }
+            if let Some(other) = tracking.prefixes[i + 1..]
+                .iter()
+                .find(|other| overlaps(other))
+            {
+                return error(format!(
+                    "Prefix '{prefix}' overlaps with another provided prefix '{other}'. \
+                     Prefixes for a single client must not overlap."
+                ));
+            }
+        }
+        None
+    }
+
+    fn enable_tracking(&mut self, id: ClientId, mut tracking: Tracking) {
+        let Some(client) = self.clients.get_mut(&id) else {
+            return;
+        };
+        let registered = client
+            .tracking
+            .take()
+            .map_or_else(Vec::new, |current| current.prefixes);
+        if tracking.bcast && tracking.prefixes.is_empty() && registered.is_empty() {
+            tracking.prefixes.push(String::new());
+        }
+        for prefix in &tracking.prefixes {
+            self.tracking_prefixes
+                .entry(prefix.clone())
+                .or_default()
+                .insert(id);
+        }
+        tracking.prefixes.splice(0..0, registered);
+        client.tracking = Some(tracking);
+    }
+
+    /// CLIENT CACHING YES|NO: whether the keys read by the next command are tracked, in OPTIN
+    /// and OPTOUT mode respectively.
+    pub(super) fn client_caching(&mut self, args: &[RespData]) -> RespData {
+        let [RespData::BulkString(value)] = args else {
+            return errors::wrong_arity("client|caching");
+        };
+        let Some(tracking) = self
+            .current_client_mut()
+            .and_then(|client| client.tracking.as_mut())
+            .filter(|tracking| tracking.optin || tracking.optout)
+        else {
+            return RespData::Error(
+                "CLIENT CACHING can be called only when the client is in tracking mode with \
+                 OPTIN or OPTOUT mode enabled"
+                    .to_string(),
+            );
+        };
+        match value.to_uppercase().as_str() {
+            "YES" if tracking.optin => tracking.caching = Some(true),
+            "YES" => {
+                return RespData::Error(
+                    "CLIENT CACHING YES is only valid when tracking is enabled in OPTIN mode."
+                        .to_string(),
+                )
+            }
+            "NO" if tracking.optout => tracking.caching = Some(false),
+            "NO" => {
+                return RespData::Error(
+                    "CLIENT CACHING NO is only valid when tracking is enabled in OPTOUT mode."
+                        .to_string(),
+                )
+            }
+            _ => return errors::syntax_error(),
+        }
+        RespData::SimpleString("OK".to_string())
+    }
+
+    /// Turns tracking off for a client and forgets the keys it read and its prefixes.
+    pub(super) fn disable_tracking(&mut self, id: ClientId) {
+        if let Some(client) = self.clients.get_mut(&id) {
+            client.tracking = None;
+        }
+        for table in [&mut self.tracking_table, &mut self.tracking_prefixes] {
+            table.retain(|_, clients| {
+                clients.remove(&id);
+                !clients.is_empty()
+            });
+        }
+    }
+
+    /// Remembers the keys read by `name` if the current client is tracking them, and uses up
+    /// what CLIENT CACHING asked for unless that was the command.
+    pub(super) fn track_keys(&mut self, name: &str, resp: &RespData) {
+        let Some(id) = self.current_client else {
+            return;
+        };
+        let Some(tracking) = self
+            .clients
+            .get_mut(&id)
+            .and_then(|client| client.tracking.as_mut())
+        else {
+            return;
+        };
+        let RespData::Array(args) = resp else {
+            return;
+        };
+        let caching = match args.get(1) {
+            Some(RespData::BulkString(sub)) if name == "client" => {
+                sub.eq_ignore_ascii_case("caching")
+            }
+            _ => false,
+        };
+        if caching {
+            return;
+        }
+        let track = if tracking.bcast {
+            false
+        } else if tracking.optin {
+            tracking.caching == Some(true)
+        } else if tracking.optout {
+            tracking.caching != Some(false)
+        } else {
+            true
+        };
+        tracking.caching = None;
+        let readonly = command_table::lookup(name).is_some_and(|spec| spec.flags & READONLY != 0);
+        if !track || !readonly {
+            return;
+        }
+        for key in acl::keys_of(name, args) {
+            self.tracking_table
+                .entry(key.to_string())
+                .or_default()
+                .insert(id);
+        }
+    }
+
+    /// Invalidates the keys modified by the write command `name`.
+    pub(super) fn invalidate_keys(&mut self, name: &str, resp: &RespData) {
+        if self.tracking_table.is_empty() && self.tracking_prefixes.is_empty() {
+            return;
+        }
+        let RespData::Array(args) = resp else {
+            return;
+        };
+        for key in acl::keys_of(name, args) {
+            self.invalidate_key(key);
+        }
+    }
+
+    /// Tells every client tracking `key` that it changed, after which it is no longer tracked
+    /// until read again. Clients broadcasting on a prefix of the key are told once the command
+    /// is done, by `flush_broadcasts`, so a key modified twice is only sent once.
+    pub(super) fn invalidate_key(&mut self, key: &str) {
+        if let Some(clients) = self.tracking_table.remove(key) {
+            for id in clients {
+                let noloop = self.clients.get(&id).is_some_and(|client| {
+                    client
+                        .tracking
+                        .as_ref()
+                        .is_some_and(|tracking| tracking.noloop)
+                });
+                if !(noloop && self.current_client == Some(id)) {
+                    self.send_invalidation(id, Some(vec![key.to_string()]));
+                }
+            }
+        }
+        for prefix in self.tracking_prefixes.keys() {
+            if !key.starts_with(prefix.as_str()) {
+                continue;
+            }
+            let keys = self.pending_broadcasts.entry(prefix.clone()).or_default();
+            // Only a key modified by a single client can be left out for it under NOLOOP.
+            match keys.get_mut(key) {
+                Some(modified_by) if *modified_by != self.current_client => *modified_by = None,
+                Some(_) => {}
+                None => {
+                    keys.insert(key.to_string(), self.current_client);
+                }
+            }
+        }
+    }
+
+    /// Sends the invalidations gathered for clients in broadcasting mode, one message per
+    /// prefix.
+    pub(super) fn flush_broadcasts(&mut self) {
+        if self.pending_broadcasts.is_empty() {
+            return;
+        }
+        for (prefix, keys) in mem::take(&mut self.pending_broadcasts) {
+            let Some(subscribers) = self.tracking_prefixes.get(&prefix) else {
+                continue;
+            };
+            for &id in subscribers {
+                let noloop = self.clients.get(&id).is_some_and(|client| {
+                    client
+                        .tracking
+                        .as_ref()
+                        .is_some_and(|tracking| tracking.noloop)
+                });
+                let keys: Vec<String> = keys
+                    .iter()
+                    .filter(|(_, modified_by)| !(noloop && **modified_by == Some(id)))
+                    .map(|(key, _)| key.clone())
+                    .collect();
+                if !keys.is_empty() {
+                    self.send_invalidation(id, Some(keys));
+                }
+            }
+        }
+    }
+
+    /// Tells every tracking client to drop its whole cache, after the dataset was flushed.
+    pub(super) fn invalidate_all(&mut self) {
+        self.tracking_table.clear();
+        self.pending_broadcasts.clear();
+        let tracking: Vec<ClientId> = self
+            .clients
+            .values()
+            .filter(|client| client.tracking.is_some())
+            .map(|client| client.id)
+            .collect();
+        for id in tracking {
+            self.send_invalidation(id, None);
+        }
+    }
+
+    /// Sends the invalidation of `keys`, or of every key, meant for the tracking client `id`.
+    fn send_invalidation(&self, id: ClientId, keys: Option<Vec<String>>) {
+        let Some(client) = self.clients.get(&id) else {
+            return;
+        };
+        let Some(tracking) = &client.tracking else {
+            return;
+        };
+        let keys = keys.map_or(RespData::Null, |keys| {
+            RespData::Array(keys.into_iter().map(RespData::BulkString).collect())
+        });
+        let target = tracking.redirect.unwrap_or(id);
+        let message = match self.clients.get(&target) {
+            Some(target) if target.resp == 3 => {
+                RespData::Push(vec![RespData::BulkString("invalidate".to_string()), keys])
+            }
+            // There is no Pub/Sub, so the connection is taken to be subscribed to the channel.
+            Some(_) if tracking.redirect.is_some() => RespData::Array(vec![
+                RespData::BulkString("message".to_string()),
+                RespData::BulkString(INVALIDATE_CHANNEL.to_string()),
+                keys,
+            ]),
+            // A RESP2 connection can't be sent anything it didn't ask for.
+            Some(_) => return,
+            None if client.resp == 3 => {
+                self.push(
+                    id,
+                    &RespData::Push(vec![
+                        RespData::BulkString("tracking-redir-broken".to_string()),
+                        RespData::Integer(target as i64),
+                    ]),
+                );
+                return;
+            }
+            None => return,
+        };
+        self.push(target, &message);
+    }
+
+    /// Queues a message for a client between its replies.
+    fn push(&self, id: ClientId, message: &RespData) {
+        if let Some(output) = self
+            .clients
+            .get(&id)
+            .and_then(|client| client.output.as_ref())
+        {
+            output.push(message);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::handler::ClientOutput;
+    use std::collections::HashMap;
+
+    /// Keeps what is pushed to a client where the test can read it.
+    struct Buffer(ClientOutput);
+
+    impl Buffer {
+        fn take(&self) -> String {
+            String::from_utf8(self.0.take()).unwrap()
+        }
+    }
+
+    fn command(args: &[&str]) -> RespData {
+        RespData::array(args.iter().copied())
+    }
+
+    /// Registers a client whose pushed messages end up in the returned buffer.
+    fn connect(handler: &mut CommandHandler, port: u16) -> (ClientId, Buffer) {
+        let id = handler.register_client(
+            format!("127.0.0.1:{port}").parse().unwrap(),
+            "127.0.0.1:6379".parse().unwrap(),
+            7,
+            None,
+        );
+        let output = ClientOutput::default();
+        handler.set_client_output(id, output.clone());
+        (id, Buffer(output))
+    }
+
+    #[test]
+    fn test_client_tracking() {
+        let mut handler = CommandHandler::from(HashMap::new());
+        let (id, _) = connect(&mut handler, 5001);
+
+        let test_cases = [
+            (
+                "Getredir when off",
+                command(&["CLIENT", "GETREDIR"]),
+                RespData::Integer(-1),
+            ),
+            (
+                "On",
+                command(&["CLIENT", "TRACKING", "on"]),
+                RespData::SimpleString("OK".to_string()),
+            ),
+            (
+                "Getredir without redirect",
+                command(&["CLIENT", "GETREDIR"]),
+                RespData::Integer(0),
+            ),
+            (
+                "Redirect",
+                command(&[
+                    "CLIENT",
+                    "TRACKING",
+                    "on",
+                    "REDIRECT",
+                    &id.to_string(),
+                    "NOLOOP",
+                ]),
+                RespData::SimpleString("OK".to_string()),
+            ),
+            (
+                "Getredir with redirect",
+                command(&["CLIENT", "GETREDIR"]),
+                RespData::Integer(id as i64),
+            ),
+            (
+                "Redirect to an unknown client",
+                command(&["CLIENT", "TRACKING", "on", "REDIRECT", "99"]),
+                RespData::Error("The client ID you want redirect to does not exist".to_string()),
+            ),
+            (
+                "Switching to broadcasting",
+                command(&["CLIENT", "TRACKING", "on", "BCAST"]),
+                RespData::Error(
+                    "You can't switch BCAST mode on/off before disabling tracking for this \
+                     client, and then re-enabling it with a different mode."
+                        .to_string(),
+                ),
+            ),
+            (
+                "Prefix without broadcasting",
+                command(&["CLIENT", "TRACKING", "on", "PREFIX", "user:"]),
+                RespData::Error("PREFIX option requires BCAST mode to be enabled".to_string()),
+            ),
+            (
+                "Opting in and out",
+                command(&["CLIENT", "TRACKING", "on", "OPTIN", "OPTOUT"]),
+                RespData::Error("You can't use both OPTIN and OPTOUT".to_string()),
+            ),
+            (
+                "Caching without opting in or out",
+                command(&["CLIENT", "CACHING", "yes"]),
+                RespData::Error(
+                    "CLIENT CACHING can be called only when the client is in tracking mode with \
+                     OPTIN or OPTOUT mode enabled"
+                        .to_string(),
+                ),
+            ),
+            (
+                "Unknown option",
+                command(&["CLIENT", "TRACKING", "on", "FAST"]),
+                errors::syntax_error(),
+            ),
+            (
+                "Missing mode",
+                command(&["CLIENT", "TRACKING"]),
+                RespData::Error(
+                    "wrong number of arguments for 'client|tracking' command".to_string(),
+                ),
+            ),
+            (
+                "Off",
+                command(&["CLIENT", "TRACKING", "off"]),
+                RespData::SimpleString("OK".to_string()),
+            ),
+            (
+                "Getredir after off",
+                command(&["CLIENT", "GETREDIR"]),
+                RespData::Integer(-1),
+            ),
+        ];
+
+        for (name, input, expected) in test_cases {
+            assert_eq!(
+                handler.handle_client(id, &input),
+                Some(expected),
+                "{}",
+                name
+            );
+        }
+    }
+
+    #[test]
+    fn test_invalidation() {
+        let mut handler = CommandHandler::from(HashMap::new());
+        let (resp3, resp3_out) = connect(&mut handler, 5001);
+        let (resp2, resp2_out) = connect(&mut handler, 5002);
+        let (redirected, redirected_out) = connect(&mut handler, 5003);
+        let (writer, _) = connect(&mut handler, 5004);
+        handler.handle_client(resp3, &command(&["HELLO", "3"]));
+        handler.handle_client(resp3, &command(&["CLIENT", "TRACKING", "on"]));
+        handler.handle_client(resp2, &command(&["CLIENT", "TRACKING", "on"]));
+        let redirect = redirected.to_string();
+        let redirect_args = ["CLIENT", "TRACKING", "on", "REDIRECT", &redirect, "NOLOOP"];
+        handler.handle_client(resp2, &command(&redirect_args));
+        resp3_out.take();
+
+        let test_cases = [
+            (
+                "Unread key",
+                vec![(writer, command(&["SET", "a", "1"]))],
+                "",
+                "",
+            ),
+            (
+                "Read, then written",
+                vec![
+                    (resp3, command(&["GET", "a"])),
+                    (resp2, command(&["GET", "a"])),
+                    (writer, command(&["SET", "a", "2"])),
+                ],
+                ">2\r\n$10\r\ninvalidate\r\n*1\r\n$1\r\na\r\n",
+                "*3\r\n$7\r\nmessage\r\n$20\r\n__redis__:invalidate\r\n*1\r\n$1\r\na\r\n",
+            ),
+            (
+                "Only invalidated once",
+                vec![(writer, command(&["SET", "a", "3"]))],
+                "",
+                "",
+            ),
+            (
+                "Failed write",
+                vec![
+                    (resp3, command(&["GET", "a"])),
+                    (writer, command(&["HSET", "a", "f", "v"])),
+                ],
+                "",
+                "",
+            ),
+            (
+                "Own write",
+                vec![(resp3, command(&["DEL", "a"]))],
+                ">2\r\n$10\r\ninvalidate\r\n*1\r\n$1\r\na\r\n",
+                "",
+            ),
+            (
+                "Own write with NOLOOP",
+                vec![
+                    (resp2, command(&["GET", "b"])),
+                    (resp2, command(&["SET", "b", "1"])),
+                ],
+                "",
+                "",
+            ),
+            (
+                "Flush",
+                vec![(writer, command(&["FLUSHALL"]))],
+                ">2\r\n$10\r\ninvalidate\r\n$-1\r\n",
+                "*3\r\n$7\r\nmessage\r\n$20\r\n__redis__:invalidate\r\n$-1\r\n",
+            ),
+        ];
+
+        for (name, commands, expected_resp3, expected_redirected) in test_cases {
+            for (id, input) in commands {
+                handler.handle_client(id, &input);
+            }
+            assert_eq!(resp3_out.take(), expected_resp3, "{}", name);
+            assert_eq!(redirected_out.take(), expected_redirected, "{}", name);
+            assert_eq!(resp2_out.take(), "", "{}", name);
+        }
+
+        handler.handle_client(resp3, &command(&redirect_args));
+        handler.unregister_client(redirected);
+        handler.handle_client(resp3, &command(&["GET", "c"]));
+        handler.handle_client(resp2, &command(&["GET", "c"]));
+        handler.handle_client(writer, &command(&["SET", "c", "1"]));
+        assert_eq!(
+            resp3_out.take(),
+            ">2\r\n$21\r\ntracking-redir-broken\r\n:3\r\n"
+        );
+        assert_eq!(resp2_out.take(), "");
+        assert!(handler.tracking_table.is_empty());
+    }
+
+    #[test]
+    fn test_broadcast() {
+        let mut handler = CommandHandler::from(HashMap::new());
+        let (prefixed, prefixed_out) = connect(&mut handler, 5001);
+        let (all, all_out) = connect(&mut handler, 5002);
+        let (writer, _) = connect(&mut handler, 5003);
+        for id in [prefixed, all] {
+            handler.handle_client(id, &command(&["HELLO", "3"]));
+        }
+        let on = |options: &[&str]| {
+            let mut args = vec!["CLIENT", "TRACKING", "on", "BCAST"];
+            args.extend(options);
+            command(&args)
+        };
+        assert_eq!(
+            handler.handle_client(
+                prefixed,
+                &on(&["PREFIX", "user:", "PREFIX", "session:", "NOLOOP"])
+            ),
+            Some(RespData::SimpleString("OK".to_string()))
+        );
+        assert_eq!(
+            handler.handle_client(prefixed, &on(&["PREFIX", "user:1"])),
+            Some(RespData::Error(
+                "Prefix 'user:1' overlaps with an existing prefix 'user:'. Prefixes for a single \
+                 client must not overlap."
+                    .to_string()
+            ))
+        );
+        assert_eq!(
+            handler.handle_client(all, &on(&["PREFIX", "a", "PREFIX", "ab"])),
+            Some(RespData::Error(
+                "Prefix 'a' overlaps with another provided prefix 'ab'. Prefixes for a single \
+                 client must not overlap."
+                    .to_string()
+            ))
+        );
+        assert_eq!(
+            handler.handle_client(all, &on(&["OPTIN"])),
+            Some(RespData::Error(
+                "OPTIN and OPTOUT are not compatible with BCAST".to_string()
+            ))
+        );
+        handler.handle_client(all, &on(&[]));
+        prefixed_out.take();
+        all_out.take();
+
+        let invalidate = |key: &str| {
+            format!(
+                ">2\r\n$10\r\ninvalidate\r\n*1\r\n${}\r\n{key}\r\n",
+                key.len()
+            )
+        };
+        let test_cases = [
+            (
+                "Matching prefix",
+                writer,
+                command(&["SET", "user:1", "a"]),
+                true,
+            ),
+            (
+                "Invalidated again",
+                writer,
+                command(&["SET", "user:1", "b"]),
+                true,
+            ),
+            (
+                "Second prefix",
+                writer,
+                command(&["DEL", "session:1"]),
+                true,
+            ),
+            ("Other key", writer, command(&["SET", "other", "a"]), false),
+            (
+                "Own write with NOLOOP",
+                prefixed,
+                command(&["SET", "user:2", "a"]),
+                false,
+            ),
+        ];
+
+        for (name, id, input, matches_prefix) in test_cases {
+            let key = match &input {
+                RespData::Array(args) => match &args[1] {
+                    RespData::BulkString(key) => key.clone(),
+                    _ => unreachable!(),
+                },
+                _ => unreachable!(),
+            };
+            handler.handle_client(id, &input);
+            let expected = if matches_prefix {
+                invalidate(&key)
+            } else {
+                String::new()
+            };
+            assert_eq!(prefixed_out.take(), expected, "{}", name);
+            assert_eq!(all_out.take(), invalidate(&key), "{}", name);
+        }
+
+        handler.handle_client(writer, &command(&["DEL", "user:3", "user:1"]));
+        assert_eq!(
+            prefixed_out.take(),
+            ">2\r\n$10\r\ninvalidate\r\n*2\r\n$6\r\nuser:1\r\n$6\r\nuser:3\r\n"
+        );
+
+        handler.handle_client(prefixed, &command(&["GET", "user:1"]));
+        assert!(handler.tracking_table.is_empty());
+        handler.handle_client(prefixed, &command(&["CLIENT", "TRACKING", "off"]));
+        assert_eq!(handler.tracking_prefixes.len(), 1);
+    }
+
+    #[test]
+    fn test_optin_optout() {
+        let mut handler = CommandHandler::from(HashMap::new());
+        let (optin, _) = connect(&mut handler, 5001);
+        let (optout, _) = connect(&mut handler, 5002);
+        handler.handle_client(optin, &command(&["CLIENT", "TRACKING", "on", "OPTIN"]));
+        handler.handle_client(optout, &command(&["CLIENT", "TRACKING", "on", "OPTOUT"]));
+
+        let ok = RespData::SimpleString("OK".to_string());
+        let test_cases = [
+            (
+                "Opted in, not asked",
+                optin,
+                command(&["GET", "a"]),
+                RespData::Null,
+                None,
+            ),
+            (
+                "Asking",
+                optin,
+                command(&["CLIENT", "CACHING", "yes"]),
+                ok.clone(),
+                None,
+            ),
+            (
+                "Asked",
+                optin,
+                command(&["GET", "b"]),
+                RespData::Null,
+                Some(optin),
+            ),
+            (
+                "Only once",
+                optin,
+                command(&["GET", "c"]),
+                RespData::Null,
+                None,
+            ),
+            (
+                "Opting out when opted in",
+                optin,
+                command(&["CLIENT", "CACHING", "no"]),
+                RespData::Error(
+                    "CLIENT CACHING NO is only valid when tracking is enabled in OPTOUT mode."
+                        .to_string(),
+                ),
+                None,
+            ),
+            (
+                "Switching to opting out",
+                optin,
+                command(&["CLIENT", "TRACKING", "on", "OPTOUT"]),
+                RespData::Error(
+                    "You can't switch OPTIN/OPTOUT mode before disabling tracking for this \
+                     client, and then re-enabling it with a different mode."
+                        .to_string(),
+                ),
+                None,
+            ),
+            (
+                "Opted out, not asked",
+                optout,
+                command(&["GET", "d"]),
+                RespData::Null,
+                Some(optout),
+            ),
+            (
+                "Asking not to",
+                optout,
+                command(&["CLIENT", "CACHING", "no"]),
+                ok.clone(),
+                None,
+            ),
+            (
+                "Asked not to",
+                optout,
+                command(&["GET", "e"]),
+                RespData::Null,
+                None,
+            ),
+            (
+                "Tracked again",
+                optout,
+                command(&["GET", "f"]),
+                RespData::Null,
+                Some(optout),
+            ),
+        ];
+
+        for (name, id, input, expected, tracked_by) in test_cases {
+            assert_eq!(
+                handler.handle_client(id, &input),
+                Some(expected),
+                "{}",
+                name
+            );
+            let key = match &input {
+                RespData::Array(args) => match &args[1] {
+                    RespData::BulkString(key) => key.clone(),
+                    _ => unreachable!(),
+                },
+                _ => unreachable!(),
+            };
+            let tracking: Vec<ClientId> = handler
+                .tracking_table
+                .get(&key)
+                .map_or_else(Vec::new, |clients| clients.iter().copied().collect());
+            assert_eq!(tracking, Vec::from_iter(tracked_by), "{}", name);
+        }
+    }
+}
@@ -0,0 +1,354 @@
+//! The pool of threads doing socket I/O with `io-threads` above 1. Each connection is handed
+//! to one of them in turn, which reads its socket, splits the input into commands and parses
+//! them, and writes out its replies, all without blocking. The connection's own thread only
+//! runs its commands, under the handler lock like any other, so execution stays
+//! single-threaded.
+
+use crate::config::Config;
+use crate::handler::ClientOutput;
+use crate::resp::{Framer, Resp, RespData};
+use std::io::{self, ErrorKind, Read, Write};
+use std::net::TcpStream;
+use std::os::unix::io::AsRawFd;
+use std::os::unix::net::UnixStream;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::mpsc::{self, Receiver, Sender, TryRecvError};
+use std::sync::{Arc, RwLock};
+use std::thread;
+
+/// How many parsed commands a connection may have waiting to run before its socket is no
+/// longer read.
+const READ_AHEAD: usize = 1024;
+
+/// How many bytes of replies a connection may have waiting to be written before its socket is
+/// no longer read, so a client that doesn't read its replies can't make them pile up.
+const MAX_PENDING_OUTPUT: usize = 1024 * 1024;
+
+/// Most bytes read from a socket at a time, so one busy connection can't starve the others.
+const READ_CHUNK: usize = 64 * 1024;
+
+/// How much longer than `proto-max-bulk-len` a command's input may grow, for the lines around
+/// its bulk strings, before the connection is dropped.
+pub(crate) const MAX_INPUT_SLACK: usize = 1024 * 1024;
+
+pub(crate) struct IoThreads {
+    threads: Vec<IoThread>,
+    /// The thread the next connection goes to.
+    next: AtomicUsize,
+}
+
+/// Where a thread of the pool takes new connections from, and how it is woken.
+struct IoThread {
+    sockets: Sender<Socket>,
+    waker: Arc<UnixStream>,
+}
+
+impl IoThreads {
+    pub(crate) fn spawn(count: usize, config: Arc<RwLock<Config>>) -> io::Result<Self> {
+        let threads = (0..count)
+            .map(|_| {
+                let (woken, waker) = UnixStream::pair()?;
+                woken.set_nonblocking(true)?;
+                waker.set_nonblocking(true)?;
+                let (sockets, receiver) = mpsc::channel();
+                let config = Arc::clone(&config);
+                thread::spawn(move || run(receiver, woken, &config));
+                Ok(IoThread {
+                    sockets,
+                    waker: Arc::new(waker),
+                })
+            })
+            .collect::<io::Result<_>>()?;
+        Ok(Self {
+            threads,
+            next: AtomicUsize::new(0),
+        })
+    }
+
+    /// Hands the I/O of `stream` to the next thread in turn.
+    pub(crate) fn add(&self, stream: &TcpStream) -> io::Result<PooledConnection> {
+        let thread = &self.threads[self.next.fetch_add(1, Ordering::Relaxed) % self.threads.len()];
+        let stream = stream.try_clone()?;
+        stream.set_nonblocking(true)?;
+        let shared = Arc::new(Shared {
+            queued: AtomicUsize::new(0),
+            closed: AtomicBool::new(false),
+            waker: Arc::clone(&thread.waker),
+        });
+        let waker = Arc::clone(&thread.waker);
+        let output = ClientOutput::new(move || wake(&waker));
+        let (commands, receiver) = mpsc::channel();
+        let socket = Socket {
+            stream,
+            input: Vec::new(),
+            framer: Framer::default(),
+            commands: Some(commands),
+            shared: Arc::clone(&shared),
+            output: output.clone(),
+            pending: Vec::new(),
+            written: 0,
+        };
+        thread
+            .sockets
+            .send(socket)
+            .map_err(|_| io::Error::other("I/O thread exited"))?;
+        wake(&thread.waker);
+        Ok(PooledConnection {
+            commands: receiver,
+            shared,
+            output,
+        })
+    }
+}
+
+impl Drop for IoThreads {
+    /// Lets the threads exit once the connections they serve are closed.
+    fn drop(&mut self) {
+        for IoThread { sockets, waker } in self.threads.drain(..) {
+            drop(sockets);
+            wake(&waker);
+        }
+    }
+}
+
+/// A connection served by the pool, as seen from the thread running its commands.
+pub(crate) struct PooledConnection {
+    commands: Receiver<io::Result<RespData>>,
+    shared: Arc<Shared>,
+    output: ClientOutput,
+}
+
+impl PooledConnection {
+    /// Waits for the next command.
+    pub(crate) fn read(&self) -> io::Result<RespData> {
+        let data = self
+            .commands
+            .recv()
+            .map_err(|_| io::Error::from(ErrorKind::UnexpectedEof))?;
+        // The socket was left unread while the queue was full.
+        if self.shared.queued.fetch_sub(1, Ordering::Relaxed) == READ_AHEAD {
+            wake(&self.shared.waker);
+        }
+        data
+    }
+
+    /// Whether another command has already been parsed, so the reply to this one can wait to
+    /// be written with the next.
+    pub(crate) fn pending(&self) -> bool {
+        self.shared.queued.load(Ordering::Relaxed) > 0
+    }
+
+    pub(crate) fn output(&self) -> &ClientOutput {
+        &self.output
+    }
+}
+
+impl Drop for PooledConnection {
+    /// Closes the socket once the replies queued so far are written.
+    fn drop(&mut self) {
+        self.shared.closed.store(true, Ordering::Release);
+        wake(&self.shared.waker);
+    }
+}
+
+/// The state of a connection shared by its two threads.
+struct Shared {
+    /// Commands parsed and not yet taken by the connection's thread.
+    queued: AtomicUsize,
+    /// Set once the connection's thread is done with it.
+    closed: AtomicBool,
+    waker: Arc<UnixStream>,
+}
+
+/// A connection as the I/O thread serving it sees it.
+struct Socket {
+    stream: TcpStream,
+    /// Read and not yet parsed.
+    input: Vec<u8>,
+    /// How far into the command at the start of `input` framing has got.
+    framer: Framer,
+    /// Where parsed commands go, until the input ends.
+    commands: Option<Sender<io::Result<RespData>>>,
+    shared: Arc<Shared>,
+    output: ClientOutput,
+    /// Taken from `output`, of which the first `written` bytes are written.
+    pending: Vec<u8>,
+    written: usize,
+}
+
+impl Socket {
+    /// The descriptor to poll and what for. A socket that is neither read nor written is left
+    /// out, since a hung up one would keep waking the thread.
+    fn poll_events(&self, max_input: usize) -> (i32, i16) {
+        let mut events = 0;
+        if self.wants_input(max_input) {
+            events |= libc::POLLIN;
+        }
+        if !self.pending.is_empty() {
+            events |= libc::POLLOUT;
+        }
+        let idle = events == 0 && self.commands.is_none();
+        (if idle { -1 } else { self.stream.as_raw_fd() }, events)
+    }
+
+    /// Whether there is room for another command to be parsed.
+    fn wants_commands(&self) -> bool {
+        self.commands.is_some()
+            && !self.shared.closed.load(Ordering::Relaxed)
+            && self.shared.queued.load(Ordering::Relaxed) < READ_AHEAD
+            && self.pending.len() - self.written < MAX_PENDING_OUTPUT
+    }
+
+    /// Whether the socket should be read, which stops once the command being read is too
+    /// long to ever be parsed.
+    fn wants_input(&self, max_input: usize) -> bool {
+        self.wants_commands() && self.input.len() < max_input
+    }
+
+    fn read(&mut self) {
+        let len = self.input.len();
+        self.input.resize(len + READ_CHUNK, 0);
+        let read = (&self.stream).read(&mut self.input[len..]);
+        self.input.truncate(len + *read.as_ref().unwrap_or(&0));
+        match read {
+            Ok(0) => self.end_input(),
+            Ok(_) => {}
+            Err(e) if matches!(e.kind(), ErrorKind::WouldBlock | ErrorKind::Interrupted) => {}
+            Err(_) => self.end_input(),
+        }
+    }
+
+    /// Parses the complete commands read so far onto the connection's queue, as long as there
+    /// is room on it.
+    fn parse(&mut self, max_bulk_len: u64, max_input: usize) {
+        let mut start = 0;
+        while self.wants_commands() {
+            let data = match self.framer.frame_len(&self.input[start..], max_bulk_len) {
+                Ok(Some(len)) => {
+                    let mut resp = Resp::new(&self.input[start..start + len]);
+                    resp.set_max_bulk_len(max_bulk_len);
+                    start += len;
+                    let data = resp.read();
+                    if data.is_ok() {
+                        log_debug!("Raw data: {:?}", resp.raw_data);
+                    }
+                    data
+                }
+                // A command that can't fit is dropped along with the connection.
+                Ok(None) if self.input.len() - start >= max_input => {
+                    Err(io::Error::new(ErrorKind::InvalidData, "too big request"))
+                }
+                Ok(None) => break,
+                Err(e) => Err(e),
+            };
+            let failed = data.is_err();
+            self.shared.queued.fetch_add(1, Ordering::Relaxed);
+            if let Some(commands) = &self.commands {
+                let _ = commands.send(data);
+            }
+            if failed {
+                self.end_input();
+            }
+        }
+        self.input.drain(..start);
+    }
+
+    /// Writes as much of the output as the socket takes without blocking.
+    fn write(&mut self) {
+        let queued = self.output.take();
+        if self.pending.is_empty() {
+            self.pending = queued;
+        } else {
+            self.pending.extend_from_slice(&queued);
+        }
+        while self.written < self.pending.len() {
+            match (&self.stream).write(&self.pending[self.written..]) {
+                Ok(0) => return self.broken(),
+                Ok(written) => self.written += written,
+                Err(e) if e.kind() == ErrorKind::Interrupted => {}
+                Err(e) if e.kind() == ErrorKind::WouldBlock => return,
+                Err(_) => return self.broken(),
+            }
+        }
+        self.pending.clear();
+        self.written = 0;
+    }
+
+    /// Stops reading, which lets the connection's thread finish once it has run the commands
+    /// already parsed.
+    fn end_input(&mut self) {
+        self.commands = None;
+    }
+
+    /// Gives up on a socket that can't be written to.
+    fn broken(&mut self) {
+        self.end_input();
+        self.pending = Vec::new();
+        self.written = 0;
+    }
+}
+
+/// Serves the sockets handed over on `receiver` until it is closed and they all are too.
+fn run(receiver: Receiver<Socket>, woken: UnixStream, config: &RwLock<Config>) {
+    let mut sockets: Vec<Socket> = Vec::new();
+    let mut accepting = true;
+    loop {
+        while accepting {
+            match receiver.try_recv() {
+                Ok(socket) => sockets.push(socket),
+                Err(TryRecvError::Empty) => break,
+                Err(TryRecvError::Disconnected) => accepting = false,
+            }
+        }
+        if !accepting && sockets.is_empty() {
+            return;
+        }
+
+        let max_bulk_len = config.read().unwrap().proto_max_bulk_len;
+        let max_input = usize::try_from(max_bulk_len)
+            .unwrap_or(usize::MAX)
+            .saturating_add(MAX_INPUT_SLACK);
+        let mut fds: Vec<_> = [(woken.as_raw_fd(), libc::POLLIN)]
+            .into_iter()
+            .chain(sockets.iter().map(|socket| socket.poll_events(max_input)))
+            .map(|(fd, events)| libc::pollfd {
+                fd,
+                events,
+                revents: 0,
+            })
+            .collect();
+        // SAFETY: poll only writes to the `revents` of the descriptors it is given.
+        if unsafe { libc::poll(fds.as_mut_ptr(), fds.len() as libc::nfds_t, -1) } < 0 {
+            continue;
+        }
+        if fds[0].revents != 0 {
+            // Empty the pipe so the next poll blocks again.
+            let mut buf = [0; 64];
+            while matches!((&woken).read(&mut buf), Ok(n) if n > 0) {}
+        }
+
+        let mut revents = fds[1..].iter().map(|fd| fd.revents);
+        sockets.retain_mut(|socket| {
+            // Checked before writing, so everything queued before the connection's thread
+            // finished is written below.
+            let closed = socket.shared.closed.load(Ordering::Acquire);
+            let revents = revents.next().unwrap_or(0);
+            if revents & (libc::POLLIN | libc::POLLHUP | libc::POLLERR) != 0 {
+                if socket.wants_input(max_input) {
+                    socket.read();
+                } else if revents & (libc::POLLHUP | libc::POLLERR) != 0 {
+                    socket.broken();
+                }
+            }
+            socket.parse(max_bulk_len, max_input);
+            socket.write();
+            !(closed && socket.pending.is_empty())
+        });
+    }
+}
+
+/// Wakes the thread polling the other end of `waker`. A full pipe means it is already due to
+/// wake.
+fn wake(waker: &UnixStream) {
+    let _ = (&*waker).write(&[0]);
+}
@@ -0,0 +1,790 @@
+//! JSON documents for the JSON.* commands: parsing and compact serialization, and the path
+//! syntax RedisJSON accepts for selecting parts of a document.
+//!
+//! Paths come in two flavours. JSONPath ones start with `$`, may match any number of values
+//! and get their results as arrays. Legacy ones start with `.` or a bare member name, and
+//! stand for a single value. Both support `.name`, `['name']`, `[index]` with negative indexes
+//! counting from the end, `*` and `[*]` wildcards, and `..` recursive descent.
+
+use std::fmt::{self, Write};
+
+/// How deeply a parsed document may nest, like RedisJSON's limit.
+const MAX_DEPTH: usize = 128;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Json {
+    Null,
+    Bool(bool),
+    Number(Number),
+    String(String),
+    Array(Vec<Json>),
+    /// Members in insertion order, which serialization keeps.
+    Object(Vec<(String, Json)>),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Number {
+    Int(i64),
+    Float(f64),
+}
+
+impl Number {
+    /// The sum, staying an integer unless either side is a float or it would overflow.
+    /// `None` if the sum isn't a finite number.
+    pub fn checked_add(self, other: Number) -> Option<Number> {
+        if let (Number::Int(a), Number::Int(b)) = (self, other) {
+            if let Some(sum) = a.checked_add(b) {
+                return Some(Number::Int(sum));
+            }
+        }
+        let sum = self.as_f64() + other.as_f64();
+        sum.is_finite().then_some(Number::Float(sum))
+    }
+
+    fn as_f64(self) -> f64 {
+        match self {
+            Number::Int(n) => n as f64,
+            Number::Float(n) => n,
+        }
+    }
+}
+
+impl fmt::Display for Number {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Number::Int(n) => write!(f, "{n}"),
+            // Debug formatting keeps the `.0` that marks a whole float as one.
+            Number::Float(n) => write!(f, "{n:?}"),
+        }
+    }
+}
+
+/// One step from a value to one of its children.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Step {
+    Member(String),
+    Index(usize),
+}
+
+impl Json {
+    pub fn parse(text: &str) -> Result<Json, String> {
+        let mut parser = Parser {
+            bytes: text.as_bytes(),
+            pos: 0,
+        };
+        let value = parser.value(0)?;
+        parser.skip_whitespace();
+        if parser.pos != parser.bytes.len() {
+            return Err(parser.error("trailing characters"));
+        }
+        Ok(value)
+    }
+
+    /// The type name RedisJSON's JSON.TYPE reports.
+    pub fn type_name(&self) -> &'static str {
+        match self {
+            Json::Null => "null",
+            Json::Bool(_) => "boolean",
+            Json::Number(Number::Int(_)) => "integer",
+            Json::Number(Number::Float(_)) => "number",
+            Json::String(_) => "string",
+            Json::Array(_) => "array",
+            Json::Object(_) => "object",
+        }
+    }
+
+    /// The value of the member `name`, if this is an object with one.
+    pub fn member(&self, name: &str) -> Option<&Json> {
+        match self {
+            Json::Object(members) => members
+                .iter()
+                .find(|(member, _)| member == name)
+                .map(|(_, value)| value),
+            _ => None,
+        }
+    }
+
+    fn child(&self, step: &Step) -> Option<&Json> {
+        match (self, step) {
+            (Json::Array(items), Step::Index(index)) => items.get(*index),
+            (_, Step::Member(name)) => self.member(name),
+            _ => None,
+        }
+    }
+
+    fn child_mut(&mut self, step: &Step) -> Option<&mut Json> {
+        match (self, step) {
+            (Json::Array(items), Step::Index(index)) => items.get_mut(*index),
+            (Json::Object(members), Step::Member(name)) => members
+                .iter_mut()
+                .find(|(member, _)| member == name)
+                .map(|(_, value)| value),
+            _ => None,
+        }
+    }
+
+    /// The value `pointer` leads to from here.
+    pub fn get(&self, pointer: &[Step]) -> Option<&Json> {
+        pointer
+            .iter()
+            .try_fold(self, |value, step| value.child(step))
+    }
+
+    pub fn get_mut(&mut self, pointer: &[Step]) -> Option<&mut Json> {
+        pointer
+            .iter()
+            .try_fold(self, |value, step| value.child_mut(step))
+    }
+
+    /// Removes the value `pointer` leads to, which must not be the root, returning it.
+    pub fn remove(&mut self, pointer: &[Step]) -> Option<Json> {
+        let (last, parent) = pointer.split_last()?;
+        match (self.get_mut(parent)?, last) {
+            (Json::Array(items), Step::Index(index)) if *index < items.len() => {
+                Some(items.remove(*index))
+            }
+            (Json::Object(members), Step::Member(name)) => {
+                let position = members.iter().position(|(member, _)| member == name)?;
+                Some(members.remove(position).1)
+            }
+            _ => None,
+        }
+    }
+
+    /// Roughly how many bytes the value takes up in memory.
+    pub fn allocated_size(&self) -> usize {
+        let node = std::mem::size_of::<Json>();
+        node + match self {
+            Json::String(s) => s.len(),
+            Json::Array(items) => items.iter().map(Json::allocated_size).sum(),
+            Json::Object(members) => members
+                .iter()
+                .map(|(name, value)| name.len() + value.allocated_size())
+                .sum(),
+            _ => 0,
+        }
+    }
+}
+
+/// Compact JSON, without any whitespace.
+impl fmt::Display for Json {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Json::Null => f.write_str("null"),
+            Json::Bool(b) => write!(f, "{b}"),
+            Json::Number(n) => write!(f, "{n}"),
+            Json::String(s) => write_string(f, s),
+            Json::Array(items) => {
+                f.write_char('[')?;
+                for (i, item) in items.iter().enumerate() {
+                    if i > 0 {
+                        f.write_char(',')?;
+                    }
+                    write!(f, "{item}")?;
+                }
+                f.write_char(']')
+            }
+            Json::Object(members) => {
+                f.write_char('{')?;
+                for (i, (name, value)) in members.iter().enumerate() {
+                    if i > 0 {
+                        f.write_char(',')?;
+                    }
+                    write_string(f, name)?;
+                    write!(f, ":{value}")?;
+                }
+                f.write_char('}')
+            }
+        }
+    }
+}
+
+fn write_string(f: &mut impl Write, s: &str) -> fmt::Result {
+    f.write_char('"')?;
+    for c in s.chars() {
+        match c {
+            '"' => f.write_str("\\\"")?,
+            '\\' => f.write_str("\\\\")?,
+            '\n' => f.write_str("\\n")?,
+            '\r' => f.write_str("\\r")?,
+            '\t' => f.write_str("\\t")?,
+            '\u{8}' => f.write_str("\\b")?,
+            '\u{c}' => f.write_str("\\f")?,
+            c if c < ' ' => write!(f, "\\u{:04x}", c as u32)?,
+            c => f.write_char(c)?,
+        }
+    }
+    f.write_char('"')
+}
+
+struct Parser<'a> {
+    bytes: &'a [u8],
+    pos: usize,
+}
+
+impl Parser<'_> {
+    fn error(&self, message: &str) -> String {
+        format!("{message} at offset {}", self.pos)
+    }
+
+    fn skip_whitespace(&mut self) {
+        while matches!(self.bytes.get(self.pos), Some(b' ' | b'\t' | b'\n' | b'\r')) {
+            self.pos += 1;
+        }
+    }
+
+    fn peek(&self) -> Option<u8> {
+        self.bytes.get(self.pos).copied()
+    }
+
+    /// Consumes `expected` if it comes next.
+    fn eat(&mut self, expected: u8) -> bool {
+        self.skip_whitespace();
+        let found = self.peek() == Some(expected);
+        if found {
+            self.pos += 1;
+        }
+        found
+    }
+
+    fn literal(&mut self, word: &str, value: Json) -> Result<Json, String> {
+        if !self.bytes[self.pos..].starts_with(word.as_bytes()) {
+            return Err(self.error("expected value"));
+        }
+        self.pos += word.len();
+        Ok(value)
+    }
+
+    fn value(&mut self, depth: usize) -> Result<Json, String> {
+        if depth > MAX_DEPTH {
+            return Err(self.error("nesting too deep"));
+        }
+        self.skip_whitespace();
+        match self.peek() {
+            Some(b'{') => self.object(depth),
+            Some(b'[') => self.array(depth),
+            Some(b'"') => self.string().map(Json::String),
+            Some(b't') => self.literal("true", Json::Bool(true)),
+            Some(b'f') => self.literal("false", Json::Bool(false)),
+            Some(b'n') => self.literal("null", Json::Null),
+            Some(b'-' | b'0'..=b'9') => self.number().map(Json::Number),
+            _ => Err(self.error("expected value")),
+        }
+    }
+
+    fn array(&mut self, depth: usize) -> Result<Json, String> {
+        self.pos += 1;
+        let mut items = Vec::new();
+        if self.eat(b']') {
+            return Ok(Json::Array(items));
+        }
+        loop {
+            items.push(self.value(depth + 1)?);
+            if self.eat(b']') {
+                return Ok(Json::Array(items));
+            }
+            if !self.eat(b',') {
+                return Err(self.error("expected ',' or ']'"));
+            }
+        }
+    }
+
+    fn object(&mut self, depth: usize) -> Result<Json, String> {
+        self.pos += 1;
+        let mut members: Vec<(String, Json)> = Vec::new();
+        if self.eat(b'}') {
+            return Ok(Json::Object(members));
+        }
+        loop {
+            self.skip_whitespace();
+            if self.peek() != Some(b'"') {
+                return Err(self.error("expected member name"));
+            }
+            let name = self.string()?;
+            if !self.eat(b':') {
+                return Err(self.error("expected ':'"));
+            }
+            let value = self.value(depth + 1)?;
+            // A repeated name keeps its first position and its last value.
+            match members.iter_mut().find(|(member, _)| *member == name) {
+                Some((_, existing)) => *existing = value,
+                None => members.push((name, value)),
+            }
+            if self.eat(b'}') {
+                return Ok(Json::Object(members));
+            }
+            if !self.eat(b',') {
+                return Err(self.error("expected ',' or '}'"));
+            }
+        }
+    }
+
+    fn string(&mut self) -> Result<String, String> {
+        self.pos += 1;
+        let mut s = String::new();
+        loop {
+            let start = self.pos;
+            while !matches!(self.peek(), None | Some(b'"' | b'\\' | ..=0x1f)) {
+                self.pos += 1;
+            }
+            // The input is a str and runs stop at ASCII bytes, so this is valid UTF-8.
+            s.push_str(std::str::from_utf8(&self.bytes[start..self.pos]).unwrap());
+            match self.peek() {
+                Some(b'"') => {
+                    self.pos += 1;
+                    return Ok(s);
+                }
+                Some(b'\\') => {
+                    self.pos += 1;
+                    s.push(self.escape()?);
+                }
+                Some(_) => return Err(self.error("control character in string")),
+                None => return Err(self.error("unterminated string")),
+            }
+        }
+    }
+
+    fn escape(&mut self) -> Result<char, String> {
+        let Some(c) = self.peek() else {
+            return Err(self.error("unterminated string"));
+        };
+        self.pos += 1;
+        Ok(match c {
+            b'"' => '"',
+            b'\\' => '\\',
+            b'/' => '/',
+            b'b' => '\u{8}',
+            b'f' => '\u{c}',
+            b'n' => '\n',
+            b'r' => '\r',
+            b't' => '\t',
+            b'u' => {
+                let high = self.hex4()?;
+                let code = if (0xd800..0xdc00).contains(&high) {
+                    if !self.bytes[self.pos..].starts_with(b"\\u") {
+                        return Err(self.error("unpaired surrogate"));
+                    }
+                    self.pos += 2;
+                    let low = self.hex4()?;
+                    if !(0xdc00..0xe000).contains(&low) {
+                        return Err(self.error("unpaired surrogate"));
+                    }
+                    0x10000 + ((high - 0xd800) << 10) + (low - 0xdc00)
+                } else {
+                    high
+                };
+                char::from_u32(code).ok_or_else(|| self.error("unpaired surrogate"))?
+            }
+            _ => return Err(self.error("invalid escape")),
+        })
+    }
+
+    fn hex4(&mut self) -> Result<u32, String> {
+        let digits = self
+            .bytes
+            .get(self.pos..self.pos + 4)
+            .and_then(|digits| std::str::from_utf8(digits).ok())
+            .and_then(|digits| u32::from_str_radix(digits, 16).ok())
+            .ok_or_else(|| self.error("invalid unicode escape"))?;
+        self.pos += 4;
+        Ok(digits)
+    }
+
+    fn number(&mut self) -> Result<Number, String> {
+        let start = self.pos;
+        let digits = |parser: &mut Self| {
+            let from = parser.pos;
+            while matches!(parser.peek(), Some(b'0'..=b'9')) {
+                parser.pos += 1;
+            }
+            parser.pos > from
+        };
+        if self.peek() == Some(b'-') {
+            self.pos += 1;
+        }
+        if !digits(self) {
+            return Err(self.error("invalid number"));
+        }
+        let mut integer = true;
+        if self.peek() == Some(b'.') {
+            self.pos += 1;
+            integer = false;
+            if !digits(self) {
+                return Err(self.error("invalid number"));
+            }
+        }
+        if matches!(self.peek(), Some(b'e' | b'E')) {
+            self.pos += 1;
+            integer = false;
+            if matches!(self.peek(), Some(b'+' | b'-')) {
+                self.pos += 1;
+            }
+            if !digits(self) {
+                return Err(self.error("invalid number"));
+            }
+        }
+        let text = std::str::from_utf8(&self.bytes[start..self.pos]).unwrap();
+        if integer {
+            if let Ok(n) = text.parse() {
+                return Ok(Number::Int(n));
+            }
+        }
+        match text.parse::<f64>() {
+            Ok(n) if n.is_finite() => Ok(Number::Float(n)),
+            _ => Err(self.error("number out of range")),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Segment {
+    Member(String),
+    /// An array index, negative ones counting from the end.
+    Index(i64),
+    Wildcard,
+    /// The value itself and everything below it, for `..`.
+    Descendants,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Path {
+    text: String,
+    legacy: bool,
+    segments: Vec<Segment>,
+}
+
+impl Path {
+    /// The root path the commands default to, in the legacy syntax.
+    pub fn root() -> Path {
+        Path {
+            text: ".".to_string(),
+            legacy: true,
+            segments: Vec::new(),
+        }
+    }
+
+    pub fn parse(text: &str) -> Result<Path, String> {
+        let invalid = || format!("invalid JSON path '{text}'");
+        let (legacy, rest) = match text.strip_prefix('$') {
+            Some(rest) => (false, rest.to_string()),
+            None if text == "." => (true, String::new()),
+            None if text.starts_with(['.', '[']) => (true, text.to_string()),
+            // A legacy path may start with a bare member name.
+            None => (true, format!(".{text}")),
+        };
+
+        let mut segments = Vec::new();
+        let mut pos = 0;
+        while pos < rest.len() {
+            if rest[pos..].starts_with("..") {
+                segments.push(Segment::Descendants);
+                pos += 2;
+                if rest[pos..].starts_with('[') {
+                    continue;
+                }
+            } else if rest[pos..].starts_with('.') {
+                pos += 1;
+            } else if rest[pos..].starts_with('[') {
+                let close = pos + rest[pos..].find(']').ok_or_else(invalid)?;
+                let inner = rest[pos + 1..close].trim();
+                segments.push(if inner == "*" {
+                    Segment::Wildcard
+                } else if let Some(name) = quoted(inner) {
+                    Segment::Member(name.to_string())
+                } else {
+                    Segment::Index(inner.parse().map_err(|_| invalid())?)
+                });
+                pos = close + 1;
+                continue;
+            } else {
+                return Err(invalid());
+            }
+            // What follows a dot: a wildcard or a member name.
+            let end = rest[pos..]
+                .find(['.', '['])
+                .map_or(rest.len(), |end| pos + end);
+            segments.push(match &rest[pos..end] {
+                "" => return Err(invalid()),
+                "*" => Segment::Wildcard,
+                name => Segment::Member(name.to_string()),
+            });
+            pos = end;
+        }
+        if segments.last() == Some(&Segment::Descendants) {
+            return Err(invalid());
+        }
+        Ok(Path {
+            text: text.to_string(),
+            legacy,
+            segments,
+        })
+    }
+
+    /// Whether this is a legacy path, standing for a single value, rather than a JSONPath.
+    pub fn is_legacy(&self) -> bool {
+        self.legacy
+    }
+
+    pub fn is_root(&self) -> bool {
+        self.segments.is_empty()
+    }
+
+    /// Pointers to every value in `doc` the path matches, in document order.
+    pub fn select(&self, doc: &Json) -> Vec<Vec<Step>> {
+        select(doc, vec![Vec::new()], &self.segments)
+    }
+
+    /// Where a member would go for JSON.SET to create it: pointers to the objects matching
+    /// everything but the last segment, which has to name a member, along with that name.
+    pub fn parents(&self, doc: &Json) -> Option<(Vec<Vec<Step>>, &str)> {
+        let (Segment::Member(name), parent) = self.segments.split_last()? else {
+            return None;
+        };
+        let parents = select(doc, vec![Vec::new()], parent)
+            .into_iter()
+            .filter(|pointer| matches!(doc.get(pointer), Some(Json::Object(_))))
+            .collect();
+        Some((parents, name))
+    }
+}
+
+impl fmt::Display for Path {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(&self.text)
+    }
+}
+
+/// The text inside matching single or double quotes.
+fn quoted(s: &str) -> Option<&str> {
+    ['\'', '"'].into_iter().find_map(|quote| {
+        s.strip_prefix(quote)
+            .and_then(|s| s.strip_suffix(quote))
+            .filter(|_| s.len() >= 2)
+    })
+}
+
+fn select(doc: &Json, mut pointers: Vec<Vec<Step>>, segments: &[Segment]) -> Vec<Vec<Step>> {
+    for segment in segments {
+        let mut next = Vec::new();
+        for pointer in pointers {
+            let Some(value) = doc.get(&pointer) else {
+                continue;
+            };
+            match segment {
+                Segment::Member(name) => {
+                    if value.member(name).is_some() {
+                        next.push(with_step(&pointer, Step::Member(name.clone())));
+                    }
+                }
+                Segment::Index(index) => {
+                    if let Json::Array(items) = value {
+                        let index = if *index < 0 {
+                            items.len() as i64 + index
+                        } else {
+                            *index
+                        };
+                        if (0..items.len() as i64).contains(&index) {
+                            next.push(with_step(&pointer, Step::Index(index as usize)));
+                        }
+                    }
+                }
+                Segment::Wildcard => next.extend(
+                    children(value)
+                        .into_iter()
+                        .map(|step| with_step(&pointer, step)),
+                ),
+                Segment::Descendants => descendants(value, pointer, &mut next),
+            }
+        }
+        pointers = next;
+    }
+    pointers
+}
+
+fn with_step(pointer: &[Step], step: Step) -> Vec<Step> {
+    let mut pointer = pointer.to_vec();
+    pointer.push(step);
+    pointer
+}
+
+fn children(value: &Json) -> Vec<Step> {
+    match value {
+        Json::Array(items) => (0..items.len()).map(Step::Index).collect(),
+        Json::Object(members) => members
+            .iter()
+            .map(|(name, _)| Step::Member(name.clone()))
+            .collect(),
+        _ => Vec::new(),
+    }
+}
+
+/// Adds `pointer` and the pointers to everything below `value`, which it leads to.
+fn descendants(value: &Json, pointer: Vec<Step>, out: &mut Vec<Vec<Step>>) {
+    let steps = children(value);
+    out.push(pointer.clone());
+    for step in steps {
+        let child = value.child(&step).unwrap();
+        descendants(child, with_step(&pointer, step), out);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_and_serialize() {
+        let test_cases = [
+            (
+                "Scalars",
+                "[1, -2.5, 1e3, true, null]",
+                "[1,-2.5,1000.0,true,null]",
+            ),
+            (
+                "Nested object keeps its order",
+                r#" { "b": {"c": []}, "a": "x" } "#,
+                r#"{"b":{"c":[]},"a":"x"}"#,
+            ),
+            (
+                "Escapes",
+                r#""tab\t quote\" \u00e9 \ud83d\ude00 \u0001""#,
+                "\"tab\\t quote\\\" é 😀 \\u0001\"",
+            ),
+            (
+                "Repeated member",
+                r#"{"a":1,"b":2,"a":3}"#,
+                r#"{"a":3,"b":2}"#,
+            ),
+            (
+                "Integer too big for i64",
+                "18446744073709551616",
+                "1.8446744073709552e19",
+            ),
+        ];
+
+        for (name, input, expected) in test_cases {
+            assert_eq!(
+                Json::parse(input).unwrap().to_string(),
+                expected,
+                "{}",
+                name
+            );
+        }
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        let test_cases = [
+            ("Empty", "", "expected value at offset 0"),
+            ("Trailing comma", "[1,]", "expected value at offset 3"),
+            (
+                "Trailing characters",
+                "{} x",
+                "trailing characters at offset 3",
+            ),
+            (
+                "Unterminated string",
+                "\"abc",
+                "unterminated string at offset 4",
+            ),
+            ("Bare word", "nope", "expected value at offset 0"),
+            (
+                "Lone surrogate",
+                r#""\ud83d""#,
+                "unpaired surrogate at offset 7",
+            ),
+        ];
+
+        for (name, input, expected) in test_cases {
+            assert_eq!(Json::parse(input), Err(expected.to_string()), "{}", name);
+        }
+        let deep = "[".repeat(MAX_DEPTH + 2);
+        assert!(Json::parse(&deep)
+            .unwrap_err()
+            .starts_with("nesting too deep"));
+    }
+
+    #[test]
+    fn test_select() {
+        let doc = Json::parse(
+            r#"{"store":{"books":[{"title":"a","price":8},{"title":"b","price":12}],"price":3}}"#,
+        )
+        .unwrap();
+        let test_cases = [
+            ("Root", "$", vec![doc.to_string()]),
+            ("Legacy root", ".", vec![doc.to_string()]),
+            ("Legacy bare name", "store.price", vec!["3".to_string()]),
+            (
+                "Bracketed member and negative index",
+                "$['store'].books[-1].title",
+                vec![r#""b""#.to_string()],
+            ),
+            (
+                "Wildcard",
+                "$.store.books[*].price",
+                vec!["8".to_string(), "12".to_string()],
+            ),
+            (
+                "Recursive descent",
+                "$..price",
+                vec!["3".to_string(), "8".to_string(), "12".to_string()],
+            ),
+            ("Missing member", "$.store.pens", vec![]),
+            ("Index out of range", "$.store.books[2]", vec![]),
+        ];
+
+        for (name, path, expected) in test_cases {
+            let path = Path::parse(path).unwrap();
+            let mut found: Vec<String> = path
+                .select(&doc)
+                .iter()
+                .map(|pointer| doc.get(pointer).unwrap().to_string())
+                .collect();
+            // `..` visits members in document order, then descends.
+            found.sort();
+            let mut expected = expected;
+            expected.sort();
+            assert_eq!(found, expected, "{}", name);
+        }
+
+        for invalid in ["$.", "$[1", "$..", "$x", "$['a']b", ".a..", "$[one]"] {
+            assert!(Path::parse(invalid).is_err(), "{}", invalid);
+        }
+        assert!(Path::parse("$.a").is_ok_and(|path| !path.is_legacy()));
+        assert!(Path::parse(".a").is_ok_and(|path| path.is_legacy()));
+    }
+
+    #[test]
+    fn test_number_add() {
+        let test_cases = [
+            (
+                "Integers",
+                Number::Int(2),
+                Number::Int(3),
+                Some(Number::Int(5)),
+            ),
+            (
+                "Float",
+                Number::Int(2),
+                Number::Float(0.5),
+                Some(Number::Float(2.5)),
+            ),
+            (
+                "Overflow turns into a float",
+                Number::Int(i64::MAX),
+                Number::Int(1),
+                Some(Number::Float(i64::MAX as f64 + 1.0)),
+            ),
+            (
+                "Infinite",
+                Number::Float(f64::MAX),
+                Number::Float(f64::MAX),
+                None,
+            ),
+        ];
+
+        for (name, a, b, expected) in test_cases {
+            assert_eq!(a.checked_add(b), expected, "{}", name);
+        }
+    }
+}
@@ -0,0 +1,74 @@
+//! A Redis-compatible server written from scratch, usable as a library as well as through the
+//! `redis-from-scratch` binary.
+//!
+//! - [`resp`](mod@resp) parses and serializes the RESP wire protocol, and [`resp!`] builds
+//!   commands.
+//! - [`handler`] holds the dataset and runs commands, given as parsed [`resp::RespData`].
+//! - [`server`] serves a [`handler::CommandHandler`] over TCP, as configured by [`config`].
+//! - [`embedded`] runs commands on a shared handler from within the process.
+//! - [`json`] parses the documents and paths of the JSON data type, [`bloom`] holds the
+//!   scalable Bloom filters of the BF.* commands, [`sketch`] the frequency sketches of the
+//!   CMS.* and TOPK.* commands, [`timeseries`] the series of the TS.* commands, and
+//!   [`search`] the full-text and vector indexes of the FT.* commands.
+//! - `codec`, with the `tokio` feature, frames RESP for `tokio_util::codec`.
+//! - The `serde` feature derives serde traits for `RespData` and adds `resp::to_resp` and
+//!   `resp::from_resp`, mapping Rust types to and from RESP.
+//!
+//! The command engine can be driven directly, without a socket:
+//!
+//! ```
+//! use redis_from_scratch::config::Config;
+//! use redis_from_scratch::handler::CommandHandler;
+//! use redis_from_scratch::resp;
+//! use redis_from_scratch::resp::RespData;
+//! use std::collections::HashMap;
+//! use std::sync::{Arc, RwLock};
+//!
+//! let config = Arc::new(RwLock::new(Config::default()));
+//! let mut handler = CommandHandler::new(HashMap::new(), config);
+//! handler.handle(&resp!["SET", "greeting", "hello"]);
+//! assert_eq!(
+//!     handler.handle(&resp!["GET", "greeting"]),
+//!     RespData::bulk("hello")
+//! );
+//! ```
+
+// First, so that its macros are visible in the modules below.
+#[macro_use]
+pub mod logging;
+
+mod allocator;
+pub mod bloom;
+pub mod check_aof;
+pub mod check_rdb;
+#[cfg(feature = "tokio")]
+pub mod codec;
+#[cfg(test)]
+mod compat;
+pub mod config;
+pub mod crash;
+pub mod daemon;
+mod dict;
+pub mod diff_rdb;
+pub mod embedded;
+#[cfg(test)]
+mod end_to_end;
+mod glob;
+#[cfg(test)]
+mod golden;
+pub mod handler;
+mod io_threads;
+pub mod json;
+mod metrics;
+pub mod rdb;
+pub mod resp;
+pub mod search;
+pub mod server;
+mod sha1;
+pub mod sketch;
+pub mod telemetry;
+#[cfg(test)]
+mod test_server;
+pub mod timeseries;
+#[cfg(test)]
+mod util;
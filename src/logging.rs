@@ -0,0 +1,217 @@
+//! Server logging in the Redis format: `pid:role timestamp level message`, where the role is
+//! `M` for the main process and the level is one of `.` (debug), `-` (verbose), `*` (notice) or
+//! `#` (warning). Lines below `loglevel` are dropped, and the rest go to stdout or, when
+//! `logfile` is set, are appended to that file.
+//!
+//! Log with the `log_debug!`, `log_verbose!`, `log_notice!` and `log_warning!` macros, which take
+//! `format!` arguments.
+
+use crate::config::{Config, LogLevel};
+use std::fmt;
+use std::fs::{File, OpenOptions};
+use std::io::{self, Write};
+use std::sync::Mutex;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// The server is always the main process; Redis uses `C` for forked children.
+const ROLE: char = 'M';
+
+pub struct Logger {
+    level: LogLevel,
+    /// The log file, or `None` to write to stdout.
+    file: Option<File>,
+}
+
+impl Logger {
+    const fn new() -> Self {
+        Self {
+            level: LogLevel::Notice,
+            file: None,
+        }
+    }
+
+    pub fn enabled(&self, level: LogLevel) -> bool {
+        level >= self.level
+    }
+
+    fn write(&mut self, level: LogLevel, message: fmt::Arguments) {
+        if !self.enabled(level) {
+            return;
+        }
+        let line = format_line(level, &timestamp(SystemTime::now()), message);
+        match &mut self.file {
+            // Losing a log line is better than taking the server down over it.
+            Some(file) => {
+                let _ = file.write_all(line.as_bytes());
+            }
+            // print! rather than writing to stdout directly, so tests capture the output.
+            None => print!("{line}"),
+        }
+    }
+}
+
+static LOGGER: Mutex<Logger> = Mutex::new(Logger::new());
+
+/// Sets the level and opens the log file from `config`, at startup.
+pub fn init(config: &Config) -> io::Result<()> {
+    let file = match &config.logfile {
+        Some(path) => Some(OpenOptions::new().create(true).append(true).open(path)?),
+        None => None,
+    };
+    let mut logger = LOGGER.lock().unwrap();
+    logger.level = config.loglevel;
+    logger.file = file;
+    Ok(())
+}
+
+/// Picks up a `loglevel` changed with CONFIG SET or a config reload. The log file can only be
+/// chosen at startup, like in Redis.
+pub fn set_level(level: LogLevel) {
+    LOGGER.lock().unwrap().level = level;
+}
+
+pub fn log(level: LogLevel, message: fmt::Arguments) {
+    LOGGER.lock().unwrap().write(level, message);
+}
+
+fn format_line(level: LogLevel, timestamp: &str, message: fmt::Arguments) -> String {
+    let marker = match level {
+        LogLevel::Debug => '.',
+        LogLevel::Verbose => '-',
+        LogLevel::Notice => '*',
+        LogLevel::Warning => '#',
+    };
+    format!(
+        "{}:{ROLE} {timestamp} {marker} {message}\n",
+        std::process::id()
+    )
+}
+
+/// `time` in local time, formatted like `16 Oct 2026 09:41:07.123`.
+fn timestamp(time: SystemTime) -> String {
+    const MONTHS: [&str; 12] = [
+        "Jan", "Feb", "Mar", "Apr", "May", "Jun", "Jul", "Aug", "Sep", "Oct", "Nov", "Dec",
+    ];
+    let since_epoch = time.duration_since(UNIX_EPOCH).unwrap_or_default();
+    let seconds = since_epoch.as_secs() as libc::time_t;
+    // SAFETY: localtime_r only writes to the tm it is given.
+    let tm = unsafe {
+        let mut tm = std::mem::zeroed::<libc::tm>();
+        libc::localtime_r(&seconds, &mut tm);
+        tm
+    };
+    format!(
+        "{:02} {} {} {:02}:{:02}:{:02}.{:03}",
+        tm.tm_mday,
+        MONTHS[tm.tm_mon.clamp(0, 11) as usize],
+        tm.tm_year + 1900,
+        tm.tm_hour,
+        tm.tm_min,
+        tm.tm_sec,
+        since_epoch.subsec_millis()
+    )
+}
+
+#[macro_export]
+macro_rules! log_debug {
+    ($($arg:tt)*) => {
+        $crate::logging::log($crate::config::LogLevel::Debug, format_args!($($arg)*))
+    };
+}
+
+#[macro_export]
+macro_rules! log_verbose {
+    ($($arg:tt)*) => {
+        $crate::logging::log($crate::config::LogLevel::Verbose, format_args!($($arg)*))
+    };
+}
+
+#[macro_export]
+macro_rules! log_notice {
+    ($($arg:tt)*) => {
+        $crate::logging::log($crate::config::LogLevel::Notice, format_args!($($arg)*))
+    };
+}
+
+#[macro_export]
+macro_rules! log_warning {
+    ($($arg:tt)*) => {
+        $crate::logging::log($crate::config::LogLevel::Warning, format_args!($($arg)*))
+    };
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use std::time::Duration;
+
+    #[test]
+    fn test_format_line() {
+        let pid = std::process::id();
+        let test_cases = [
+            ("Debug", LogLevel::Debug, format!("{pid}:M ts . hello 1\n")),
+            (
+                "Verbose",
+                LogLevel::Verbose,
+                format!("{pid}:M ts - hello 1\n"),
+            ),
+            (
+                "Notice",
+                LogLevel::Notice,
+                format!("{pid}:M ts * hello 1\n"),
+            ),
+            (
+                "Warning",
+                LogLevel::Warning,
+                format!("{pid}:M ts # hello 1\n"),
+            ),
+        ];
+
+        for (name, level, expected) in test_cases {
+            assert_eq!(
+                format_line(level, "ts", format_args!("hello {}", 1)),
+                expected,
+                "{}",
+                name
+            );
+        }
+    }
+
+    #[test]
+    fn test_timestamp() {
+        let time = UNIX_EPOCH + Duration::from_millis(1_700_000_000_042);
+        let timestamp = timestamp(time);
+
+        // The date and hour depend on the local time zone, but not the shape or the millis.
+        let parts: Vec<&str> = timestamp.split(' ').collect();
+        assert_eq!(parts.len(), 4, "{timestamp}");
+        assert_eq!(parts[0].len(), 2, "{timestamp}");
+        assert_eq!(parts[1], "Nov", "{timestamp}");
+        assert_eq!(parts[2], "2023", "{timestamp}");
+        assert!(parts[3].ends_with(":20.042"), "{timestamp}");
+    }
+
+    #[test]
+    fn test_levels_and_logfile() {
+        let path = std::env::temp_dir().join(format!("redis-log-test-{}.log", std::process::id()));
+        let mut logger = Logger {
+            level: LogLevel::Verbose,
+            file: Some(File::create(&path).unwrap()),
+        };
+
+        logger.write(LogLevel::Debug, format_args!("dropped"));
+        logger.write(LogLevel::Verbose, format_args!("kept"));
+        logger.level = LogLevel::Warning;
+        logger.write(LogLevel::Notice, format_args!("dropped too"));
+        logger.write(LogLevel::Warning, format_args!("kept too"));
+
+        let contents = fs::read_to_string(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+        let messages: Vec<&str> = contents
+            .lines()
+            .map(|line| line.split_once(" - ").or(line.split_once(" # ")).unwrap().1)
+            .collect();
+        assert_eq!(messages, ["kept", "kept too"]);
+    }
+}
@@ -1,38 +1,171 @@
 use std::collections::HashMap;
-use std::io::BufWriter;
-use std::str;
-use std::{io::BufReader, net};
+use std::io;
+use std::path::Path;
+use std::sync::{Arc, RwLock};
+use std::{env, process, thread};
 
-use handler::CommandHandler;
-
-mod handler;
-mod resp;
-mod util;
-
-const ADDR: &str = "0.0.0.0:6379";
+use redis_from_scratch::config::{Config, PARAMETERS};
+use redis_from_scratch::handler::CommandHandler;
+use redis_from_scratch::{
+    check_aof, check_rdb, crash, daemon, diff_rdb, log_notice, log_warning, logging, server,
+    telemetry,
+};
+use signal_hook::consts::SIGHUP;
+use signal_hook::iterator::Signals;
 
 fn main() {
-    let listener = net::TcpListener::bind(ADDR).unwrap();
-
-    println!("Listening on {}", ADDR);
+    let args: Vec<String> = env::args().skip(1).collect();
+    if let Some(mode @ ("--check-rdb" | "--check-aof" | "--diff-rdb")) =
+        args.first().map(String::as_str)
+    {
+        process::exit(run_check(mode, &args[1..]));
+    }
 
-    let db = HashMap::new();
-    let mut cmd_handler = CommandHandler::from(db);
+    // With --bigkeys the snapshot is analyzed instead of served.
+    let (flags, paths): (Vec<String>, Vec<String>) =
+        args.into_iter().partition(|arg| arg.starts_with("--"));
+    if let Some(flag) = flags.iter().find(|flag| *flag != "--bigkeys") {
+        eprintln!("Unknown option {flag}");
+        process::exit(1);
+    }
+    let config = match paths.first() {
+        Some(path) => Config::load(Path::new(&path)).unwrap_or_else(|e| {
+            eprintln!("Failed to load config file {path}: {e}");
+            process::exit(1);
+        }),
+        None => Config::default(),
+    };
+    let config = Arc::new(RwLock::new(config));
+    if !flags.is_empty() {
+        print_big_keys(config);
+        return;
+    }
+    if let Err(e) = logging::init(&config.read().unwrap()) {
+        eprintln!("Can't open the log file: {e}");
+        process::exit(1);
+    }
+    start_daemon(&config.read().unwrap());
+    crash::install();
+    daemon::supervise(config.read().unwrap().supervised);
+    spawn_reload_handler(Arc::clone(&config));
+    let otlp_endpoint = config.read().unwrap().otlp_endpoint.clone();
+    if let Some(endpoint) = otlp_endpoint {
+        match telemetry::init(&endpoint) {
+            Ok(()) => log_notice!("Exporting traces to {endpoint}"),
+            Err(e) => log_warning!("Not exporting traces to {endpoint}: {e}"),
+        }
+    }
 
-    for stream in listener.incoming() {
-        println!("Connection established");
-        let stream = stream.unwrap();
-        let reader = BufReader::new(&stream);
-        let mut writer = BufWriter::new(&stream);
+    if let Err(e) = server::run(config) {
+        log_warning!("Server error: {e}");
+        daemon::remove_pidfile();
+        process::exit(1);
+    }
+}
 
-        let mut resp = resp::Resp::new(reader);
-        let data = resp.read().unwrap();
+/// Runs `--check-rdb`, `--check-aof` or `--diff-rdb` with the arguments after the mode,
+/// returning the process exit code.
+fn run_check(mode: &str, args: &[String]) -> i32 {
+    let mut stdout = io::stdout();
+    let result = match (mode, args) {
+        ("--check-rdb", [path]) => check_rdb::check_rdb(Path::new(path), &mut stdout),
+        ("--check-aof", [path]) => {
+            check_aof::check_aof(Path::new(path), false, &mut io::stdin().lock(), &mut stdout)
+        }
+        ("--check-aof", [flag, path]) if flag == "--fix" => {
+            check_aof::check_aof(Path::new(path), true, &mut io::stdin().lock(), &mut stdout)
+        }
+        ("--diff-rdb", [first, second]) => diff_rdb::diff_rdb(
+            Path::new(first),
+            Path::new(second),
+            diff_rdb::FULL_SAMPLE,
+            &mut stdout,
+        ),
+        ("--diff-rdb", [flag, sample, first, second]) if flag == "--sample" => {
+            match sample.parse() {
+                Ok(sample @ 1..=diff_rdb::FULL_SAMPLE) => {
+                    diff_rdb::diff_rdb(Path::new(first), Path::new(second), sample, &mut stdout)
+                }
+                _ => {
+                    eprintln!("The sample must be a percentage between 1 and 100");
+                    return 1;
+                }
+            }
+        }
+        ("--diff-rdb", _) => {
+            eprintln!(
+                "Usage: redis-from-scratch --diff-rdb [--sample <percent>] <first.rdb> <second.rdb>"
+            );
+            return 1;
+        }
+        ("--check-rdb", _) => {
+            eprintln!("Usage: redis-from-scratch --check-rdb <file.rdb>");
+            return 1;
+        }
+        _ => {
+            eprintln!("Usage: redis-from-scratch --check-aof [--fix] <file.aof>");
+            return 1;
+        }
+    };
+    match result {
+        Ok(true) => 0,
+        Ok(false) => 1,
+        Err(e) => {
+            eprintln!("{e}");
+            1
+        }
+    }
+}
 
-        println!("Raw data: {:?}", resp.raw_data);
-        println!("Parsed data: {:?}", data);
+/// Loads the configured RDB file and prints its biggest keys.
+fn print_big_keys(config: Arc<RwLock<Config>>) {
+    let mut handler = CommandHandler::new(HashMap::new(), config);
+    if let Err(e) = handler.load_rdb() {
+        eprintln!("Failed loading the RDB file: {e}");
+        process::exit(1);
+    }
+    print!("{}", handler.big_keys_report());
+}
 
-        let response = cmd_handler.handle(&data);
-        println!("Response: {:?}", response);
-        response.write(&mut writer).unwrap();
+/// Detaches from the terminal with `daemonize yes` and writes the pidfile. Runs before any
+/// thread is started.
+fn start_daemon(config: &Config) {
+    if config.daemonize {
+        if let Err(e) = daemon::daemonize() {
+            log_warning!("Failed to daemonize: {e}");
+            process::exit(1);
+        }
+    }
+    let pidfile = match &config.pidfile {
+        Some(path) => path.as_path(),
+        None if config.daemonize => Path::new(daemon::DEFAULT_PIDFILE),
+        None => return,
+    };
+    // Like Redis, a server that can't write its pidfile still runs.
+    if let Err(e) = daemon::write_pidfile(pidfile) {
+        log_warning!("Failed to write PID file {}: {e}", pidfile.display());
     }
 }
+
+/// Re-reads the config file whenever the process receives SIGHUP.
+fn spawn_reload_handler(config: Arc<RwLock<Config>>) {
+    let mut signals = Signals::new([SIGHUP]).unwrap();
+    thread::spawn(move || {
+        for _ in signals.forever() {
+            let mut config = config.write().unwrap();
+            let before = config.clone();
+            match config.reload() {
+                Ok(()) => {
+                    logging::set_level(config.loglevel);
+                    // Only the names, so secrets such as requirepass stay out of the log.
+                    let changed: Vec<&str> = PARAMETERS
+                        .into_iter()
+                        .filter(|name| config.get(name) != before.get(name))
+                        .collect();
+                    log_notice!("Configuration reloaded, changed: [{}]", changed.join(", "));
+                }
+                Err(e) => log_warning!("Failed to reload configuration, keeping old values: {e}"),
+            }
+        }
+    });
+}
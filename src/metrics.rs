@@ -0,0 +1,133 @@
+//! A minimal HTTP listener on `metrics-port`, for the load balancers, probes and scrapers that
+//! can't speak RESP: `/healthz` answers like PING, `/metrics` serves `CommandHandler::metrics`
+//! for Prometheus, and `/info` is a read-only JSON view of INFO. Every response closes the
+//! connection, so only the request line is looked at.
+
+use std::io::{BufRead, BufReader, Write};
+use std::net::{TcpListener, TcpStream};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Mutex;
+use std::time::Duration;
+
+use crate::handler::CommandHandler;
+
+/// How long a client may take to send its request before it is dropped, since requests are
+/// served one at a time.
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Serves requests until `stopping` is set and the listener has accepted one more connection.
+pub fn accept_loop(listener: TcpListener, handler: &Mutex<CommandHandler>, stopping: &AtomicBool) {
+    for stream in listener.incoming() {
+        if stopping.load(Ordering::Relaxed) {
+            return;
+        }
+        let result = stream.and_then(|stream| serve_request(&stream, handler));
+        if let Err(e) = result {
+            log_warning!("Metrics request failed: {e}");
+        }
+    }
+}
+
+fn serve_request(stream: &TcpStream, handler: &Mutex<CommandHandler>) -> std::io::Result<()> {
+    stream.set_read_timeout(Some(REQUEST_TIMEOUT))?;
+    let mut request_line = String::new();
+    BufReader::new(stream).read_line(&mut request_line)?;
+    let (status, content_type, body) = match route(&request_line) {
+        // Taking the lock shows commands can run, which is what PING would tell a client.
+        Ok(Endpoint::Health) => match handler.lock() {
+            Ok(_) => ("200 OK", TEXT, "OK\n".to_string()),
+            Err(_) => ("503 Service Unavailable", TEXT, "Unavailable\n".to_string()),
+        },
+        Ok(Endpoint::Metrics) => ("200 OK", METRICS, handler.lock().unwrap().metrics()),
+        Ok(Endpoint::Info) => ("200 OK", JSON, handler.lock().unwrap().info_json()),
+        Err((status, body)) => (status, TEXT, body.to_string()),
+    };
+
+    let mut writer = stream;
+    write!(
+        writer,
+        "HTTP/1.1 {status}\r\n\
+         Content-Type: {content_type}\r\n\
+         Content-Length: {}\r\n\
+         Connection: close\r\n\r\n{body}",
+        body.len()
+    )?;
+    writer.flush()
+}
+
+const TEXT: &str = "text/plain; charset=utf-8";
+const METRICS: &str = "text/plain; version=0.0.4; charset=utf-8";
+const JSON: &str = "application/json";
+
+#[derive(Debug, PartialEq, Eq)]
+enum Endpoint {
+    /// `/healthz`, for load balancers and liveness probes.
+    Health,
+    /// `/metrics`, for Prometheus.
+    Metrics,
+    /// `/info`, the default INFO sections as JSON.
+    Info,
+}
+
+/// The endpoint `request_line` asks for, e.g. `GET /metrics HTTP/1.1`, or the status line and
+/// body to refuse it with.
+fn route(request_line: &str) -> Result<Endpoint, (&'static str, &'static str)> {
+    let mut parts = request_line.split_whitespace();
+    let (Some(method), Some(target)) = (parts.next(), parts.next()) else {
+        return Err(("400 Bad Request", "Bad request\n"));
+    };
+    let endpoint = match target.split('?').next().unwrap_or_default() {
+        "/healthz" => Endpoint::Health,
+        "/metrics" => Endpoint::Metrics,
+        "/info" => Endpoint::Info,
+        _ => {
+            return Err((
+                "404 Not Found",
+                "Not found, try /healthz, /metrics or /info\n",
+            ))
+        }
+    };
+    if method != "GET" {
+        return Err(("405 Method Not Allowed", "Method not allowed\n"));
+    }
+    Ok(endpoint)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_route() {
+        let test_cases = [
+            ("Health", "GET /healthz HTTP/1.1\r\n", Ok(Endpoint::Health)),
+            (
+                "Metrics",
+                "GET /metrics HTTP/1.1\r\n",
+                Ok(Endpoint::Metrics),
+            ),
+            ("Info", "GET /info HTTP/1.1\r\n", Ok(Endpoint::Info)),
+            (
+                "Query string",
+                "GET /metrics?x=1 HTTP/1.1\r\n",
+                Ok(Endpoint::Metrics),
+            ),
+            ("Other path", "GET / HTTP/1.1\r\n", Err("404 Not Found")),
+            (
+                "Other method",
+                "POST /info HTTP/1.1\r\n",
+                Err("405 Method Not Allowed"),
+            ),
+            ("Empty", "", Err("400 Bad Request")),
+        ];
+
+        for (name, input, expected) in test_cases {
+            assert_eq!(
+                route(input).map_err(|(status, _)| status),
+                expected,
+                "{}",
+                name
+            );
+        }
+    }
+}
@@ -0,0 +1,636 @@
+//! Reading and writing of the RDB snapshot format.
+//!
+//! Only the value types this server supports are handled: plain strings and hashes, with
+//! optional millisecond expiry times. JSON documents, Bloom filters, sketches and time series
+//! are stored under type codes of our own, which Redis itself can't load: documents as their
+//! text, and the rest in the binary form of their `to_bytes`.
+
+use crate::bloom::BloomFilter;
+use crate::handler::{ListpackLimits, RedisHash, RedisValue};
+use crate::json::Json;
+use crate::sketch::{CountMinSketch, TopK};
+use crate::timeseries::TimeSeries;
+use std::fmt;
+use std::io::{self, Read, Write};
+
+const MAGIC: &[u8] = b"REDIS";
+const VERSION: u32 = 11;
+
+const OPCODE_AUX: u8 = 0xFA;
+const OPCODE_RESIZEDB: u8 = 0xFB;
+const OPCODE_EXPIRETIME_MS: u8 = 0xFC;
+const OPCODE_EXPIRETIME: u8 = 0xFD;
+const OPCODE_SELECTDB: u8 = 0xFE;
+const OPCODE_EOF: u8 = 0xFF;
+
+const TYPE_STRING: u8 = 0;
+const TYPE_HASH: u8 = 4;
+/// Not Redis types: its modules store these as module values, which we don't support.
+const TYPE_JSON: u8 = 200;
+const TYPE_BLOOM: u8 = 201;
+const TYPE_CMS: u8 = 202;
+const TYPE_TOPK: u8 = 203;
+const TYPE_TIMESERIES: u8 = 204;
+/// Only written by `encode_value`, so a listpack encoded hash reads back as one.
+const TYPE_HASH_LISTPACK: u8 = 205;
+
+const ENC_INT8: u8 = 0;
+const ENC_INT16: u8 = 1;
+const ENC_INT32: u8 = 2;
+
+/// A key read from or written to a snapshot, with its absolute expiry in unix milliseconds.
+pub struct Entry {
+    pub key: String,
+    pub value: RedisValue,
+    pub expire_at_ms: Option<u64>,
+}
+
+#[derive(Debug)]
+pub enum RdbError {
+    Io(io::Error),
+    Format(String),
+    Checksum { expected: u64, actual: u64 },
+}
+
+impl fmt::Display for RdbError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RdbError::Io(e) => write!(f, "{e}"),
+            RdbError::Format(message) => write!(f, "invalid RDB file: {message}"),
+            RdbError::Checksum { expected, actual } => write!(
+                f,
+                "RDB checksum mismatch: expected {expected:016x}, got {actual:016x}"
+            ),
+        }
+    }
+}
+
+impl From<io::Error> for RdbError {
+    fn from(e: io::Error) -> Self {
+        RdbError::Io(e)
+    }
+}
+
+/// Writes a complete snapshot of `entries` to `writer`.
+pub fn dump<'a>(
+    entries: impl Iterator<Item = (&'a String, &'a RedisValue, Option<u64>)>,
+    writer: &mut impl Write,
+) -> io::Result<()> {
+    let entries: Vec<_> = entries.collect();
+    let expires = entries.iter().filter(|(_, _, exp)| exp.is_some()).count();
+    let mut out = Writer::new(writer, entries.len(), expires)?;
+    for (key, value, expire_at_ms) in entries {
+        out.write_entry(key, value, expire_at_ms)?;
+    }
+    out.finish()
+}
+
+/// Writes a snapshot one key at a time, for datasets that can't be collected up front.
+pub struct Writer<W: Write> {
+    out: Crc64Writer<W>,
+}
+
+impl<W: Write> Writer<W> {
+    /// Writes the header of a snapshot of `keys` keys, `expires` of which have a TTL. The
+    /// counts are only a hint for sizing tables when loading.
+    pub fn new(writer: W, keys: usize, expires: usize) -> io::Result<Self> {
+        let mut out = Crc64Writer::new(writer);
+
+        out.write_all(MAGIC)?;
+        write!(out, "{VERSION:04}")?;
+        write_aux(&mut out, "redis-ver", env!("CARGO_PKG_VERSION"))?;
+        write_aux(&mut out, "redis-bits", &usize::BITS.to_string())?;
+
+        out.write_all(&[OPCODE_SELECTDB])?;
+        write_length(&mut out, 0)?;
+
+        out.write_all(&[OPCODE_RESIZEDB])?;
+        write_length(&mut out, keys as u64)?;
+        write_length(&mut out, expires as u64)?;
+        Ok(Self { out })
+    }
+
+    pub fn write_entry(
+        &mut self,
+        key: &str,
+        value: &RedisValue,
+        expire_at_ms: Option<u64>,
+    ) -> io::Result<()> {
+        if let Some(ms) = expire_at_ms {
+            self.out.write_all(&[OPCODE_EXPIRETIME_MS])?;
+            self.out.write_all(&ms.to_le_bytes())?;
+        }
+        self.out.write_all(&[value_type(value)])?;
+        write_string(&mut self.out, key)?;
+        write_object(&mut self.out, value)
+    }
+
+    /// Writes the end of the snapshot and its checksum, and flushes the writer.
+    pub fn finish(mut self) -> io::Result<()> {
+        self.out.write_all(&[OPCODE_EOF])?;
+        let checksum = self.out.crc;
+        self.out.inner.write_all(&checksum.to_le_bytes())?;
+        self.out.inner.flush()
+    }
+}
+
+/// How many bytes `value` takes up in a snapshot, not counting its key and type.
+pub fn serialized_length(value: &RedisValue) -> usize {
+    let mut buffer = Vec::new();
+    write_object(&mut buffer, value).expect("writing to a Vec cannot fail");
+    buffer.len()
+}
+
+/// `value` with its type, encoded as in a snapshot, and with its encoding.
+pub fn encode_value(value: &RedisValue) -> Vec<u8> {
+    let value_type = match value {
+        RedisValue::Hash(RedisHash::Listpack(_)) => TYPE_HASH_LISTPACK,
+        _ => value_type(value),
+    };
+    let mut buffer = vec![value_type];
+    write_object(&mut buffer, value).expect("writing to a Vec cannot fail");
+    buffer
+}
+
+/// Reads back a value written by `encode_value`.
+pub fn decode_value(mut data: &[u8]) -> Result<RedisValue, RdbError> {
+    match read_u8(&mut data)? {
+        TYPE_HASH_LISTPACK => read_object(&mut data, TYPE_HASH, ListpackLimits::UNLIMITED),
+        TYPE_HASH => read_object(&mut data, TYPE_HASH, ListpackLimits::NONE),
+        value_type => read_object(&mut data, value_type, ListpackLimits::default()),
+    }
+}
+
+/// Reads every key from a snapshot, verifying its checksum. Hashes within `limits` are
+/// listpack encoded.
+pub fn load(reader: &mut impl Read, limits: ListpackLimits) -> Result<Vec<Entry>, RdbError> {
+    let mut entries = Vec::new();
+    load_each(reader, limits, |entry| entries.push(entry))?;
+    Ok(entries)
+}
+
+/// Reads a snapshot, handing each key to `visit` as soon as it is read. The checksum is only
+/// verified at the end, so on error some keys may already have been visited.
+pub fn load_each(
+    reader: &mut impl Read,
+    limits: ListpackLimits,
+    mut visit: impl FnMut(Entry),
+) -> Result<(), RdbError> {
+    let mut input = Crc64Reader::new(reader);
+
+    let mut header = [0; 9];
+    input.read_exact(&mut header)?;
+    if &header[..5] != MAGIC {
+        return Err(RdbError::Format("wrong signature".to_string()));
+    }
+    let version: u32 = std::str::from_utf8(&header[5..])
+        .ok()
+        .and_then(|v| v.parse().ok())
+        .ok_or_else(|| RdbError::Format("unreadable version".to_string()))?;
+    if version > VERSION {
+        return Err(RdbError::Format(format!(
+            "can't handle RDB format version {version}"
+        )));
+    }
+
+    let mut expire_at_ms = None;
+    loop {
+        let opcode = read_u8(&mut input)?;
+        match opcode {
+            OPCODE_AUX => {
+                read_string(&mut input)?;
+                read_string(&mut input)?;
+            }
+            OPCODE_SELECTDB => {
+                let db = read_length(&mut input)?;
+                if db != 0 {
+                    return Err(RdbError::Format(format!(
+                        "only database 0 is supported, found {db}"
+                    )));
+                }
+            }
+            OPCODE_RESIZEDB => {
+                read_length(&mut input)?;
+                read_length(&mut input)?;
+            }
+            OPCODE_EXPIRETIME_MS => {
+                let mut buf = [0; 8];
+                input.read_exact(&mut buf)?;
+                expire_at_ms = Some(u64::from_le_bytes(buf));
+            }
+            OPCODE_EXPIRETIME => {
+                let mut buf = [0; 4];
+                input.read_exact(&mut buf)?;
+                expire_at_ms = Some(u32::from_le_bytes(buf) as u64 * 1000);
+            }
+            OPCODE_EOF => break,
+            value_type => {
+                let key = read_string(&mut input)?;
+                let value = read_object(&mut input, value_type, limits)?;
+                visit(Entry {
+                    key,
+                    value,
+                    expire_at_ms: expire_at_ms.take(),
+                });
+            }
+        }
+    }
+
+    let actual = input.crc;
+    let mut buf = [0; 8];
+    input.inner.read_exact(&mut buf)?;
+    let expected = u64::from_le_bytes(buf);
+    // A zero checksum means the writer had checksums disabled.
+    if expected != 0 && expected != actual {
+        return Err(RdbError::Checksum { expected, actual });
+    }
+    Ok(())
+}
+
+fn value_type(value: &RedisValue) -> u8 {
+    match value {
+        RedisValue::String(_) => TYPE_STRING,
+        RedisValue::Hash(_) => TYPE_HASH,
+        RedisValue::Json(_) => TYPE_JSON,
+        RedisValue::Bloom(_) => TYPE_BLOOM,
+        RedisValue::CountMin(_) => TYPE_CMS,
+        RedisValue::TopK(_) => TYPE_TOPK,
+        RedisValue::TimeSeries(_) => TYPE_TIMESERIES,
+    }
+}
+
+fn write_object(out: &mut impl Write, value: &RedisValue) -> io::Result<()> {
+    match value {
+        RedisValue::String(s) => write_string(out, &s.as_str()),
+        RedisValue::Hash(map) => {
+            write_length(out, map.len() as u64)?;
+            for (field, value) in map {
+                write_string(out, field)?;
+                write_string(out, value)?;
+            }
+            Ok(())
+        }
+        RedisValue::Json(doc) => write_string(out, &doc.to_string()),
+        RedisValue::Bloom(filter) => write_bytes(out, &filter.to_bytes()),
+        RedisValue::CountMin(sketch) => write_bytes(out, &sketch.to_bytes()),
+        RedisValue::TopK(topk) => write_bytes(out, &topk.to_bytes()),
+        RedisValue::TimeSeries(series) => write_bytes(out, &series.to_bytes()),
+    }
+}
+
+fn read_object(
+    input: &mut impl Read,
+    value_type: u8,
+    limits: ListpackLimits,
+) -> Result<RedisValue, RdbError> {
+    match value_type {
+        TYPE_STRING => Ok(RedisValue::String(read_string(input)?.into())),
+        TYPE_HASH => {
+            let len = read_length(input)?;
+            let pairs = (0..len)
+                .map(|_| Ok((read_string(input)?, read_string(input)?)))
+                .collect::<Result<_, RdbError>>()?;
+            Ok(RedisValue::Hash(RedisHash::from_pairs(pairs, limits)))
+        }
+        TYPE_JSON => Json::parse(&read_string(input)?)
+            .map(RedisValue::Json)
+            .map_err(|e| RdbError::Format(format!("invalid JSON document: {e}"))),
+        TYPE_BLOOM => BloomFilter::from_bytes(&read_bytes(input)?)
+            .map(RedisValue::Bloom)
+            .map_err(RdbError::Format),
+        TYPE_CMS => CountMinSketch::from_bytes(&read_bytes(input)?)
+            .map(RedisValue::CountMin)
+            .map_err(RdbError::Format),
+        TYPE_TOPK => TopK::from_bytes(&read_bytes(input)?)
+            .map(RedisValue::TopK)
+            .map_err(RdbError::Format),
+        TYPE_TIMESERIES => TimeSeries::from_bytes(&read_bytes(input)?)
+            .map(RedisValue::TimeSeries)
+            .map_err(RdbError::Format),
+        other => Err(RdbError::Format(format!("unsupported value type {other}"))),
+    }
+}
+
+fn write_aux(out: &mut impl Write, key: &str, value: &str) -> io::Result<()> {
+    out.write_all(&[OPCODE_AUX])?;
+    write_string(out, key)?;
+    write_string(out, value)
+}
+
+fn write_length(out: &mut impl Write, len: u64) -> io::Result<()> {
+    if len < 1 << 6 {
+        out.write_all(&[len as u8])
+    } else if len < 1 << 14 {
+        out.write_all(&[0x40 | (len >> 8) as u8, len as u8])
+    } else if len <= u32::MAX as u64 {
+        out.write_all(&[0x80])?;
+        out.write_all(&(len as u32).to_be_bytes())
+    } else {
+        out.write_all(&[0x81])?;
+        out.write_all(&len.to_be_bytes())
+    }
+}
+
+fn write_string(out: &mut impl Write, s: &str) -> io::Result<()> {
+    // Small integers are stored in their compact integer encoding, like Redis does.
+    if let Ok(n) = s.parse::<i32>() {
+        if n.to_string() == s {
+            return match n {
+                n if i8::try_from(n).is_ok() => out.write_all(&[0xC0 | ENC_INT8, n as i8 as u8]),
+                n if i16::try_from(n).is_ok() => {
+                    out.write_all(&[0xC0 | ENC_INT16])?;
+                    out.write_all(&(n as i16).to_le_bytes())
+                }
+                n => {
+                    out.write_all(&[0xC0 | ENC_INT32])?;
+                    out.write_all(&n.to_le_bytes())
+                }
+            };
+        }
+    }
+    write_bytes(out, s.as_bytes())
+}
+
+fn write_bytes(out: &mut impl Write, bytes: &[u8]) -> io::Result<()> {
+    write_length(out, bytes.len() as u64)?;
+    out.write_all(bytes)
+}
+
+fn read_u8(input: &mut impl Read) -> io::Result<u8> {
+    let mut buf = [0; 1];
+    input.read_exact(&mut buf)?;
+    Ok(buf[0])
+}
+
+/// A length prefix, or a special string encoding when the top two bits are set.
+enum Length {
+    Len(u64),
+    Encoded(u8),
+}
+
+fn read_length_or_encoding(input: &mut impl Read) -> io::Result<Length> {
+    let first = read_u8(input)?;
+    Ok(match first >> 6 {
+        0 => Length::Len((first & 0x3F) as u64),
+        1 => Length::Len((((first & 0x3F) as u64) << 8) | read_u8(input)? as u64),
+        2 if first == 0x80 => {
+            let mut buf = [0; 4];
+            input.read_exact(&mut buf)?;
+            Length::Len(u32::from_be_bytes(buf) as u64)
+        }
+        2 => {
+            let mut buf = [0; 8];
+            input.read_exact(&mut buf)?;
+            Length::Len(u64::from_be_bytes(buf))
+        }
+        _ => Length::Encoded(first & 0x3F),
+    })
+}
+
+fn read_length(input: &mut impl Read) -> Result<u64, RdbError> {
+    match read_length_or_encoding(input)? {
+        Length::Len(len) => Ok(len),
+        Length::Encoded(_) => Err(RdbError::Format("unexpected string encoding".to_string())),
+    }
+}
+
+fn read_exact_len(input: &mut impl Read, len: u64) -> Result<Vec<u8>, RdbError> {
+    let mut buf = Vec::new();
+    input.take(len).read_to_end(&mut buf)?;
+    if buf.len() as u64 != len {
+        return Err(io::Error::from(io::ErrorKind::UnexpectedEof).into());
+    }
+    Ok(buf)
+}
+
+/// A length-prefixed binary string, which unlike `read_string` needn't be UTF-8.
+fn read_bytes(input: &mut impl Read) -> Result<Vec<u8>, RdbError> {
+    let len = read_length(input)?;
+    read_exact_len(input, len)
+}
+
+fn read_string(input: &mut impl Read) -> Result<String, RdbError> {
+    match read_length_or_encoding(input)? {
+        Length::Len(len) => String::from_utf8(read_exact_len(input, len)?)
+            .map_err(|_| RdbError::Format("non UTF-8 string".to_string())),
+        Length::Encoded(ENC_INT8) => Ok((read_u8(input)? as i8).to_string()),
+        Length::Encoded(ENC_INT16) => {
+            let mut buf = [0; 2];
+            input.read_exact(&mut buf)?;
+            Ok(i16::from_le_bytes(buf).to_string())
+        }
+        Length::Encoded(ENC_INT32) => {
+            let mut buf = [0; 4];
+            input.read_exact(&mut buf)?;
+            Ok(i32::from_le_bytes(buf).to_string())
+        }
+        Length::Encoded(other) => Err(RdbError::Format(format!(
+            "unsupported string encoding {other}"
+        ))),
+    }
+}
+
+/// CRC-64/Jones as used by Redis for RDB checksums (reflected, polynomial 0xad93d23594c935a9).
+pub fn crc64(mut crc: u64, data: &[u8]) -> u64 {
+    const POLY: u64 = 0x95ac_9329_ac4b_c9b5;
+    for byte in data {
+        crc ^= *byte as u64;
+        for _ in 0..8 {
+            crc = if crc & 1 == 1 {
+                (crc >> 1) ^ POLY
+            } else {
+                crc >> 1
+            };
+        }
+    }
+    crc
+}
+
+struct Crc64Writer<W> {
+    inner: W,
+    crc: u64,
+}
+
+impl<W: Write> Crc64Writer<W> {
+    fn new(inner: W) -> Self {
+        Self { inner, crc: 0 }
+    }
+}
+
+impl<W: Write> Write for Crc64Writer<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let written = self.inner.write(buf)?;
+        self.crc = crc64(self.crc, &buf[..written]);
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+struct Crc64Reader<R> {
+    inner: R,
+    crc: u64,
+}
+
+impl<R: Read> Crc64Reader<R> {
+    fn new(inner: R) -> Self {
+        Self { inner, crc: 0 }
+    }
+}
+
+impl<R: Read> Read for Crc64Reader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let read = self.inner.read(buf)?;
+        self.crc = crc64(self.crc, &buf[..read]);
+        Ok(read)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::timeseries::DuplicatePolicy;
+
+    #[test]
+    fn test_crc64() {
+        // Check value from Redis' crc64.c self test.
+        assert_eq!(crc64(0, b"123456789"), 0xe9c6d914c4b8d9ca);
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let mut hash = RedisHash::default();
+        hash.insert("field".to_string(), "value".to_string());
+        hash.insert("count".to_string(), "-40000".to_string());
+        let long = "x".repeat(20_000);
+        let mut filter = BloomFilter::new(0.01, 100, Some(2)).unwrap();
+        filter.insert(b"item").unwrap();
+        let mut sketch = CountMinSketch::new(100, 3).unwrap();
+        sketch.increment(b"item", 4);
+        let labels = vec![("area".to_string(), "eu".to_string())];
+        let mut series = TimeSeries::new(60_000, DuplicatePolicy::Last, labels);
+        series.add(1000, 20.5, None).unwrap();
+        series.add(2000, -3.0, None).unwrap();
+        let data = [
+            ("small".to_string(), RedisValue::String("7".into()), None),
+            (
+                "padded".to_string(),
+                RedisValue::String("007".into()),
+                Some(1_700_000_000_000),
+            ),
+            (
+                "long".to_string(),
+                RedisValue::String(long.clone().into()),
+                None,
+            ),
+            ("hash".to_string(), RedisValue::Hash(hash.clone()), None),
+            (
+                "doc".to_string(),
+                RedisValue::Json(Json::parse(r#"{"tags":["a"],"n":1.5}"#).unwrap()),
+                None,
+            ),
+            ("seen".to_string(), RedisValue::Bloom(filter), None),
+            ("counts".to_string(), RedisValue::CountMin(sketch), None),
+            ("temp".to_string(), RedisValue::TimeSeries(series), None),
+        ];
+
+        let mut buffer = Vec::new();
+        dump(data.iter().map(|(k, v, exp)| (k, v, *exp)), &mut buffer).unwrap();
+        assert!(buffer.starts_with(b"REDIS0011"));
+
+        let entries = load(&mut buffer.as_slice(), ListpackLimits::default()).unwrap();
+        assert_eq!(entries.len(), 8);
+        for (entry, (key, value, expire_at_ms)) in entries.iter().zip(&data) {
+            assert_eq!(&entry.key, key);
+            assert_eq!(entry.expire_at_ms, *expire_at_ms);
+            match (&entry.value, value) {
+                (RedisValue::String(a), RedisValue::String(b)) => assert_eq!(a, b),
+                (RedisValue::Hash(a), RedisValue::Hash(b)) => assert_eq!(a, b),
+                (RedisValue::Json(a), RedisValue::Json(b)) => assert_eq!(a, b),
+                (RedisValue::Bloom(a), RedisValue::Bloom(b)) => assert_eq!(a, b),
+                (RedisValue::CountMin(a), RedisValue::CountMin(b)) => assert_eq!(a, b),
+                (RedisValue::TimeSeries(a), RedisValue::TimeSeries(b)) => assert_eq!(a, b),
+                _ => panic!("Value type changed for {}", key),
+            }
+        }
+    }
+
+    #[test]
+    fn test_hash_encoding() {
+        let pairs = vec![
+            ("c".to_string(), "1".to_string()),
+            ("a".to_string(), "2".to_string()),
+            ("b".to_string(), "3".to_string()),
+        ];
+        let listpack = RedisValue::Hash(RedisHash::from_pairs(
+            pairs.clone(),
+            ListpackLimits::default(),
+        ));
+        let mut buffer = Vec::new();
+        let key = "hash".to_string();
+        dump([(&key, &listpack, None)].into_iter(), &mut buffer).unwrap();
+
+        let test_cases = [
+            ("Within the limits", ListpackLimits::default(), "listpack"),
+            (
+                "Past the configured limits",
+                ListpackLimits {
+                    max_entries: 2,
+                    max_value: 64,
+                },
+                "hashtable",
+            ),
+        ];
+        for (name, limits, expected) in test_cases {
+            let entries = load(&mut buffer.as_slice(), limits).unwrap();
+            let RedisValue::Hash(hash) = &entries[0].value else {
+                panic!("Value type changed for {}", name);
+            };
+            assert_eq!(hash.encoding(), expected, "{}", name);
+        }
+        let entries = load(&mut buffer.as_slice(), ListpackLimits::default()).unwrap();
+        let RedisValue::Hash(hash) = &entries[0].value else {
+            panic!("Value type changed");
+        };
+        let fields: Vec<&String> = hash.iter().map(|(field, _)| field).collect();
+        assert_eq!(fields, ["c", "a", "b"]);
+
+        // A single value reads back with the encoding it had, whatever the limits.
+        let table = RedisValue::Hash(RedisHash::from_pairs(pairs, ListpackLimits::NONE));
+        for (value, expected) in [(listpack, "listpack"), (table, "hashtable")] {
+            let Ok(RedisValue::Hash(hash)) = decode_value(&encode_value(&value)) else {
+                panic!("Hash not decoded");
+            };
+            assert_eq!(hash.encoding(), expected);
+        }
+    }
+
+    #[test]
+    fn test_load_errors() {
+        let mut buffer = Vec::new();
+        let value = RedisValue::String("value".into());
+        let key = "key".to_string();
+        dump([(&key, &value, None)].into_iter(), &mut buffer).unwrap();
+
+        let test_cases = [
+            ("Wrong signature", b"RADIS0011".to_vec()),
+            ("Truncated", buffer[..buffer.len() - 4].to_vec()),
+            ("Corrupted", {
+                let mut corrupted = buffer.clone();
+                let idx = corrupted.len() - 12;
+                corrupted[idx] ^= 0xFF;
+                corrupted
+            }),
+        ];
+
+        for (name, input) in test_cases {
+            assert!(
+                load(&mut input.as_slice(), ListpackLimits::default()).is_err(),
+                "{}",
+                name
+            );
+        }
+    }
+}
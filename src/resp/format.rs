@@ -0,0 +1,789 @@
+//! A serde data format over `RespData`, for moving typed values in and out of the server.
+//! Structs and maps become RESP maps, sequences and tuples arrays, strings and floats bulk
+//! strings, integers and booleans integers, and `None` and `()` nulls. Enum variants other
+//! than unit ones become a single-entry map from the variant name to its contents.
+//!
+//! Reading is lenient in the ways Redis replies need: numbers may come as bulk strings, and a
+//! struct or map may come as a flat array of fields and values, like HGETALL's RESP2 reply.
+//! An error reply fails with its message.
+
+use super::RespData;
+use serde::de::value::{MapDeserializer, SeqDeserializer, StringDeserializer};
+use serde::de::{self, DeserializeOwned, DeserializeSeed, IntoDeserializer, Visitor};
+use serde::ser::{self, Serialize};
+use std::fmt;
+
+/// Why a value couldn't be converted, or the message of an error reply read with `from_resp`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Error(String);
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+impl std::error::Error for Error {}
+
+impl ser::Error for Error {
+    fn custom<T: fmt::Display>(msg: T) -> Self {
+        Error(msg.to_string())
+    }
+}
+
+impl de::Error for Error {
+    fn custom<T: fmt::Display>(msg: T) -> Self {
+        Error(msg.to_string())
+    }
+}
+
+/// Converts `value` to RESP.
+pub fn to_resp<T: Serialize + ?Sized>(value: &T) -> Result<RespData, Error> {
+    value.serialize(Serializer)
+}
+
+/// Reads a `T` out of `resp`, such as a command's reply.
+pub fn from_resp<T: DeserializeOwned>(resp: RespData) -> Result<T, Error> {
+    T::deserialize(Deserializer(resp))
+}
+
+/// `value` as the contents of `variant`.
+fn tagged(variant: &str, value: RespData) -> RespData {
+    RespData::Map(vec![(RespData::bulk(variant), value)])
+}
+
+struct Serializer;
+
+impl ser::Serializer for Serializer {
+    type Ok = RespData;
+    type Error = Error;
+    type SerializeSeq = SeqSerializer;
+    type SerializeTuple = SeqSerializer;
+    type SerializeTupleStruct = SeqSerializer;
+    type SerializeTupleVariant = SeqSerializer;
+    type SerializeMap = MapSerializer;
+    type SerializeStruct = MapSerializer;
+    type SerializeStructVariant = MapSerializer;
+
+    fn serialize_bool(self, v: bool) -> Result<RespData, Error> {
+        Ok(RespData::Integer(v as i64))
+    }
+
+    fn serialize_i8(self, v: i8) -> Result<RespData, Error> {
+        self.serialize_i64(v.into())
+    }
+
+    fn serialize_i16(self, v: i16) -> Result<RespData, Error> {
+        self.serialize_i64(v.into())
+    }
+
+    fn serialize_i32(self, v: i32) -> Result<RespData, Error> {
+        self.serialize_i64(v.into())
+    }
+
+    fn serialize_i64(self, v: i64) -> Result<RespData, Error> {
+        Ok(RespData::Integer(v))
+    }
+
+    fn serialize_u8(self, v: u8) -> Result<RespData, Error> {
+        self.serialize_i64(v.into())
+    }
+
+    fn serialize_u16(self, v: u16) -> Result<RespData, Error> {
+        self.serialize_i64(v.into())
+    }
+
+    fn serialize_u32(self, v: u32) -> Result<RespData, Error> {
+        self.serialize_i64(v.into())
+    }
+
+    fn serialize_u64(self, v: u64) -> Result<RespData, Error> {
+        i64::try_from(v)
+            .map(RespData::Integer)
+            .map_err(|_| Error(format!("integer {v} out of range")))
+    }
+
+    fn serialize_f32(self, v: f32) -> Result<RespData, Error> {
+        Ok(RespData::bulk(v.to_string()))
+    }
+
+    fn serialize_f64(self, v: f64) -> Result<RespData, Error> {
+        Ok(RespData::bulk(v.to_string()))
+    }
+
+    fn serialize_char(self, v: char) -> Result<RespData, Error> {
+        Ok(RespData::bulk(v.to_string()))
+    }
+
+    fn serialize_str(self, v: &str) -> Result<RespData, Error> {
+        Ok(RespData::bulk(v))
+    }
+
+    /// Bulk strings are text here, so bytes that aren't UTF-8 are replaced.
+    fn serialize_bytes(self, v: &[u8]) -> Result<RespData, Error> {
+        Ok(RespData::bulk(String::from_utf8_lossy(v)))
+    }
+
+    fn serialize_none(self) -> Result<RespData, Error> {
+        Ok(RespData::Null)
+    }
+
+    fn serialize_some<T: Serialize + ?Sized>(self, value: &T) -> Result<RespData, Error> {
+        value.serialize(self)
+    }
+
+    fn serialize_unit(self) -> Result<RespData, Error> {
+        Ok(RespData::Null)
+    }
+
+    fn serialize_unit_struct(self, _name: &'static str) -> Result<RespData, Error> {
+        Ok(RespData::Null)
+    }
+
+    fn serialize_unit_variant(
+        self,
+        _name: &'static str,
+        _index: u32,
+        variant: &'static str,
+    ) -> Result<RespData, Error> {
+        Ok(RespData::bulk(variant))
+    }
+
+    fn serialize_newtype_struct<T: Serialize + ?Sized>(
+        self,
+        _name: &'static str,
+        value: &T,
+    ) -> Result<RespData, Error> {
+        value.serialize(self)
+    }
+
+    fn serialize_newtype_variant<T: Serialize + ?Sized>(
+        self,
+        _name: &'static str,
+        _index: u32,
+        variant: &'static str,
+        value: &T,
+    ) -> Result<RespData, Error> {
+        Ok(tagged(variant, to_resp(value)?))
+    }
+
+    fn serialize_seq(self, len: Option<usize>) -> Result<SeqSerializer, Error> {
+        Ok(SeqSerializer {
+            items: Vec::with_capacity(len.unwrap_or(0)),
+            variant: None,
+        })
+    }
+
+    fn serialize_tuple(self, len: usize) -> Result<SeqSerializer, Error> {
+        self.serialize_seq(Some(len))
+    }
+
+    fn serialize_tuple_struct(
+        self,
+        _name: &'static str,
+        len: usize,
+    ) -> Result<SeqSerializer, Error> {
+        self.serialize_seq(Some(len))
+    }
+
+    fn serialize_tuple_variant(
+        self,
+        _name: &'static str,
+        _index: u32,
+        variant: &'static str,
+        len: usize,
+    ) -> Result<SeqSerializer, Error> {
+        Ok(SeqSerializer {
+            items: Vec::with_capacity(len),
+            variant: Some(variant),
+        })
+    }
+
+    fn serialize_map(self, len: Option<usize>) -> Result<MapSerializer, Error> {
+        Ok(MapSerializer {
+            entries: Vec::with_capacity(len.unwrap_or(0)),
+            key: None,
+            variant: None,
+        })
+    }
+
+    fn serialize_struct(self, _name: &'static str, len: usize) -> Result<MapSerializer, Error> {
+        self.serialize_map(Some(len))
+    }
+
+    fn serialize_struct_variant(
+        self,
+        _name: &'static str,
+        _index: u32,
+        variant: &'static str,
+        len: usize,
+    ) -> Result<MapSerializer, Error> {
+        Ok(MapSerializer {
+            entries: Vec::with_capacity(len),
+            key: None,
+            variant: Some(variant),
+        })
+    }
+}
+
+struct SeqSerializer {
+    items: Vec<RespData>,
+    /// The enum variant the items are the fields of, if any.
+    variant: Option<&'static str>,
+}
+
+impl SeqSerializer {
+    fn push<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), Error> {
+        self.items.push(to_resp(value)?);
+        Ok(())
+    }
+
+    fn finish(self) -> RespData {
+        let array = RespData::Array(self.items);
+        match self.variant {
+            Some(variant) => tagged(variant, array),
+            None => array,
+        }
+    }
+}
+
+impl ser::SerializeSeq for SeqSerializer {
+    type Ok = RespData;
+    type Error = Error;
+
+    fn serialize_element<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), Error> {
+        self.push(value)
+    }
+
+    fn end(self) -> Result<RespData, Error> {
+        Ok(self.finish())
+    }
+}
+
+impl ser::SerializeTuple for SeqSerializer {
+    type Ok = RespData;
+    type Error = Error;
+
+    fn serialize_element<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), Error> {
+        self.push(value)
+    }
+
+    fn end(self) -> Result<RespData, Error> {
+        Ok(self.finish())
+    }
+}
+
+impl ser::SerializeTupleStruct for SeqSerializer {
+    type Ok = RespData;
+    type Error = Error;
+
+    fn serialize_field<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), Error> {
+        self.push(value)
+    }
+
+    fn end(self) -> Result<RespData, Error> {
+        Ok(self.finish())
+    }
+}
+
+impl ser::SerializeTupleVariant for SeqSerializer {
+    type Ok = RespData;
+    type Error = Error;
+
+    fn serialize_field<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), Error> {
+        self.push(value)
+    }
+
+    fn end(self) -> Result<RespData, Error> {
+        Ok(self.finish())
+    }
+}
+
+struct MapSerializer {
+    entries: Vec<(RespData, RespData)>,
+    /// The key given by `serialize_key`, waiting for its value.
+    key: Option<RespData>,
+    /// The enum variant the entries are the fields of, if any.
+    variant: Option<&'static str>,
+}
+
+impl MapSerializer {
+    fn push_field<T: Serialize + ?Sized>(
+        &mut self,
+        field: &'static str,
+        value: &T,
+    ) -> Result<(), Error> {
+        self.entries.push((RespData::bulk(field), to_resp(value)?));
+        Ok(())
+    }
+
+    fn finish(self) -> RespData {
+        let map = RespData::Map(self.entries);
+        match self.variant {
+            Some(variant) => tagged(variant, map),
+            None => map,
+        }
+    }
+}
+
+impl ser::SerializeMap for MapSerializer {
+    type Ok = RespData;
+    type Error = Error;
+
+    fn serialize_key<T: Serialize + ?Sized>(&mut self, key: &T) -> Result<(), Error> {
+        self.key = Some(to_resp(key)?);
+        Ok(())
+    }
+
+    fn serialize_value<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), Error> {
+        let key = self
+            .key
+            .take()
+            .ok_or_else(|| Error("map value without a key".to_string()))?;
+        self.entries.push((key, to_resp(value)?));
+        Ok(())
+    }
+
+    fn end(self) -> Result<RespData, Error> {
+        Ok(self.finish())
+    }
+}
+
+impl ser::SerializeStruct for MapSerializer {
+    type Ok = RespData;
+    type Error = Error;
+
+    fn serialize_field<T: Serialize + ?Sized>(
+        &mut self,
+        field: &'static str,
+        value: &T,
+    ) -> Result<(), Error> {
+        self.push_field(field, value)
+    }
+
+    fn end(self) -> Result<RespData, Error> {
+        Ok(self.finish())
+    }
+}
+
+impl ser::SerializeStructVariant for MapSerializer {
+    type Ok = RespData;
+    type Error = Error;
+
+    fn serialize_field<T: Serialize + ?Sized>(
+        &mut self,
+        field: &'static str,
+        value: &T,
+    ) -> Result<(), Error> {
+        self.push_field(field, value)
+    }
+
+    fn end(self) -> Result<RespData, Error> {
+        Ok(self.finish())
+    }
+}
+
+struct Deserializer(RespData);
+
+impl IntoDeserializer<'_, Error> for Deserializer {
+    type Deserializer = Self;
+
+    fn into_deserializer(self) -> Self {
+        self
+    }
+}
+
+fn seq_access(items: Vec<RespData>) -> SeqDeserializer<impl Iterator<Item = Deserializer>, Error> {
+    SeqDeserializer::new(items.into_iter().map(Deserializer))
+}
+
+fn map_access(
+    entries: Vec<(RespData, RespData)>,
+) -> MapDeserializer<'static, impl Iterator<Item = (Deserializer, Deserializer)>, Error> {
+    MapDeserializer::new(
+        entries
+            .into_iter()
+            .map(|(key, value)| (Deserializer(key), Deserializer(value))),
+    )
+}
+
+/// The error for finding `value` where `expected` should be; an error reply's own message.
+fn mismatch(value: RespData, expected: &str) -> Error {
+    match value {
+        RespData::Error(message) => Error(message),
+        value => Error(format!("expected {expected}, found {value:?}")),
+    }
+}
+
+impl Deserializer {
+    fn integer(self) -> Result<i64, Error> {
+        match self.0 {
+            RespData::Integer(n) => Ok(n),
+            RespData::BulkString(s) | RespData::SimpleString(s) => s
+                .parse()
+                .map_err(|_| Error(format!("expected an integer, found '{s}'"))),
+            value => Err(mismatch(value, "an integer")),
+        }
+    }
+
+    fn float(self) -> Result<f64, Error> {
+        match self.0 {
+            RespData::Integer(n) => Ok(n as f64),
+            RespData::BulkString(s) | RespData::SimpleString(s) => s
+                .parse()
+                .map_err(|_| Error(format!("expected a number, found '{s}'"))),
+            value => Err(mismatch(value, "a number")),
+        }
+    }
+
+    fn string(self) -> Result<String, Error> {
+        match self.0 {
+            RespData::BulkString(s) | RespData::SimpleString(s) => Ok(s),
+            RespData::Integer(n) => Ok(n.to_string()),
+            value => Err(mismatch(value, "a string")),
+        }
+    }
+
+    /// The entries of a map, or of a flat array of alternating keys and values.
+    fn entries(self) -> Result<Vec<(RespData, RespData)>, Error> {
+        match self.0 {
+            RespData::Map(entries) => Ok(entries),
+            RespData::Array(items) if items.len() % 2 == 0 => {
+                let mut items = items.into_iter();
+                let mut entries = Vec::with_capacity(items.len() / 2);
+                while let (Some(key), Some(value)) = (items.next(), items.next()) {
+                    entries.push((key, value));
+                }
+                Ok(entries)
+            }
+            value => Err(mismatch(value, "a map")),
+        }
+    }
+}
+
+macro_rules! deserialize_integer {
+    ($($method:ident)*) => {
+        $(
+            fn $method<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+                visitor.visit_i64(self.integer()?)
+            }
+        )*
+    };
+}
+
+impl<'de> de::Deserializer<'de> for Deserializer {
+    type Error = Error;
+
+    fn deserialize_any<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        match self.0 {
+            RespData::BulkString(s) | RespData::SimpleString(s) => visitor.visit_string(s),
+            RespData::Integer(n) => visitor.visit_i64(n),
+            RespData::Null => visitor.visit_unit(),
+            RespData::Array(items) | RespData::Push(items) => {
+                let mut seq = seq_access(items);
+                let value = visitor.visit_seq(&mut seq)?;
+                seq.end()?;
+                Ok(value)
+            }
+            RespData::Map(entries) => {
+                let mut map = map_access(entries);
+                let value = visitor.visit_map(&mut map)?;
+                map.end()?;
+                Ok(value)
+            }
+            value @ RespData::Error(_) => Err(mismatch(value, "a value")),
+        }
+    }
+
+    deserialize_integer! {
+        deserialize_i8 deserialize_i16 deserialize_i32 deserialize_i64
+        deserialize_u8 deserialize_u16 deserialize_u32 deserialize_u64
+    }
+
+    fn deserialize_bool<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        match self.integer()? {
+            0 => visitor.visit_bool(false),
+            1 => visitor.visit_bool(true),
+            n => Err(Error(format!("expected 0 or 1, found {n}"))),
+        }
+    }
+
+    fn deserialize_f32<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        visitor.visit_f64(self.float()?)
+    }
+
+    fn deserialize_f64<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        visitor.visit_f64(self.float()?)
+    }
+
+    fn deserialize_char<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        visitor.visit_string(self.string()?)
+    }
+
+    fn deserialize_str<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        visitor.visit_string(self.string()?)
+    }
+
+    fn deserialize_string<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        visitor.visit_string(self.string()?)
+    }
+
+    fn deserialize_identifier<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        visitor.visit_string(self.string()?)
+    }
+
+    fn deserialize_bytes<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        visitor.visit_byte_buf(self.string()?.into_bytes())
+    }
+
+    fn deserialize_byte_buf<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        visitor.visit_byte_buf(self.string()?.into_bytes())
+    }
+
+    fn deserialize_option<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        match self.0 {
+            RespData::Null => visitor.visit_none(),
+            value => visitor.visit_some(Deserializer(value)),
+        }
+    }
+
+    fn deserialize_newtype_struct<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        visitor: V,
+    ) -> Result<V::Value, Error> {
+        visitor.visit_newtype_struct(self)
+    }
+
+    fn deserialize_map<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        let mut map = map_access(self.entries()?);
+        let value = visitor.visit_map(&mut map)?;
+        map.end()?;
+        Ok(value)
+    }
+
+    fn deserialize_struct<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        _fields: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value, Error> {
+        self.deserialize_map(visitor)
+    }
+
+    fn deserialize_enum<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        _variants: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value, Error> {
+        match self.0 {
+            RespData::BulkString(variant) | RespData::SimpleString(variant) => {
+                let variant: StringDeserializer<Error> = variant.into_deserializer();
+                visitor.visit_enum(variant)
+            }
+            RespData::Map(entries) if entries.len() == 1 => {
+                let (variant, value) = entries.into_iter().next().unwrap();
+                visitor.visit_enum(Enum { variant, value })
+            }
+            value => Err(mismatch(value, "an enum variant")),
+        }
+    }
+
+    serde::forward_to_deserialize_any! {
+        i128 u128 unit unit_struct seq tuple tuple_struct ignored_any
+    }
+}
+
+/// A variant with contents, read from a single-entry map.
+struct Enum {
+    variant: RespData,
+    value: RespData,
+}
+
+impl<'de> de::EnumAccess<'de> for Enum {
+    type Error = Error;
+    type Variant = Deserializer;
+
+    fn variant_seed<S: DeserializeSeed<'de>>(
+        self,
+        seed: S,
+    ) -> Result<(S::Value, Deserializer), Error> {
+        let variant = seed.deserialize(Deserializer(self.variant))?;
+        Ok((variant, Deserializer(self.value)))
+    }
+}
+
+impl<'de> de::VariantAccess<'de> for Deserializer {
+    type Error = Error;
+
+    fn unit_variant(self) -> Result<(), Error> {
+        match self.0 {
+            RespData::Null => Ok(()),
+            value => Err(mismatch(value, "a unit variant")),
+        }
+    }
+
+    fn newtype_variant_seed<S: DeserializeSeed<'de>>(self, seed: S) -> Result<S::Value, Error> {
+        seed.deserialize(self)
+    }
+
+    fn tuple_variant<V: Visitor<'de>>(self, _len: usize, visitor: V) -> Result<V::Value, Error> {
+        de::Deserializer::deserialize_seq(self, visitor)
+    }
+
+    fn struct_variant<V: Visitor<'de>>(
+        self,
+        _fields: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value, Error> {
+        de::Deserializer::deserialize_map(self, visitor)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::handler::CommandHandler;
+    use serde::Deserialize;
+    use std::collections::{BTreeMap, HashMap};
+
+    #[derive(Debug, PartialEq, serde::Serialize, Deserialize)]
+    struct User {
+        name: String,
+        age: u8,
+        admin: bool,
+        score: f64,
+        tags: Vec<String>,
+        nickname: Option<String>,
+    }
+
+    #[derive(Debug, PartialEq, serde::Serialize, Deserialize)]
+    enum Shape {
+        Empty,
+        Circle(u32),
+        Rect(u32, u32),
+        Named { name: String },
+    }
+
+    #[test]
+    fn test_to_resp() {
+        let user = User {
+            name: "ada".to_string(),
+            age: 36,
+            admin: true,
+            score: 1.5,
+            tags: vec!["math".to_string()],
+            nickname: None,
+        };
+        let test_cases = [
+            (
+                "Struct",
+                to_resp(&user),
+                Ok(RespData::Map(vec![
+                    (RespData::bulk("name"), RespData::bulk("ada")),
+                    (RespData::bulk("age"), RespData::Integer(36)),
+                    (RespData::bulk("admin"), RespData::Integer(1)),
+                    (RespData::bulk("score"), RespData::bulk("1.5")),
+                    (RespData::bulk("tags"), crate::resp!["math"]),
+                    (RespData::bulk("nickname"), RespData::Null),
+                ])),
+            ),
+            (
+                "Unit variant",
+                to_resp(&Shape::Empty),
+                Ok(RespData::bulk("Empty")),
+            ),
+            (
+                "Tuple variant",
+                to_resp(&Shape::Rect(2, 3)),
+                Ok(RespData::Map(vec![(
+                    RespData::bulk("Rect"),
+                    crate::resp![2, 3],
+                )])),
+            ),
+            (
+                "Out of range",
+                to_resp(&u64::MAX),
+                Err(Error(format!("integer {} out of range", u64::MAX))),
+            ),
+        ];
+
+        for (name, actual, expected) in test_cases {
+            assert_eq!(actual, expected, "{}", name);
+        }
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let user = User {
+            name: "ada".to_string(),
+            age: 36,
+            admin: false,
+            score: -0.25,
+            tags: vec!["math".to_string(), "engines".to_string()],
+            nickname: Some("countess".to_string()),
+        };
+        assert_eq!(from_resp::<User>(to_resp(&user).unwrap()), Ok(user));
+
+        let shapes = vec![
+            Shape::Empty,
+            Shape::Circle(1),
+            Shape::Rect(2, 3),
+            Shape::Named {
+                name: "blob".to_string(),
+            },
+        ];
+        assert_eq!(
+            from_resp::<Vec<Shape>>(to_resp(&shapes).unwrap()),
+            Ok(shapes)
+        );
+
+        let map = BTreeMap::from([(1, "one".to_string()), (2, "two".to_string())]);
+        assert_eq!(from_resp(to_resp(&map).unwrap()), Ok(map));
+
+        let resp = RespData::Push(vec![RespData::Error("-ERR x".to_string()), RespData::Null]);
+        assert_eq!(from_resp(to_resp(&resp).unwrap()), Ok(resp));
+    }
+
+    #[test]
+    fn test_from_reply() {
+        #[derive(Debug, PartialEq, Deserialize)]
+        struct Profile {
+            name: String,
+            visits: u32,
+            email: Option<String>,
+        }
+
+        let mut handler = CommandHandler::from(HashMap::new());
+        handler.handle(&crate::resp!["HSET", "user", "name", "ada", "visits", "7"]);
+        assert_eq!(
+            from_resp(handler.handle(&crate::resp!["HGETALL", "user"])),
+            Ok(Profile {
+                name: "ada".to_string(),
+                visits: 7,
+                email: None,
+            })
+        );
+        assert_eq!(
+            from_resp::<u32>(handler.handle(&crate::resp!["HLEN", "user"])),
+            Ok(2)
+        );
+        assert_eq!(
+            from_resp::<Option<String>>(handler.handle(&crate::resp!["GET", "missing"])),
+            Ok(None)
+        );
+        assert_eq!(
+            from_resp::<String>(handler.handle(&crate::resp!["GET", "user"])),
+            Err(Error(
+                "-WRONGTYPE Operation against a key holding the wrong kind of value".to_string()
+            ))
+        );
+        assert_eq!(
+            from_resp::<u8>(RespData::Integer(300)),
+            Err(Error(
+                "invalid value: integer `300`, expected u8".to_string()
+            ))
+        );
+    }
+}
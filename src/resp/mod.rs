@@ -0,0 +1,894 @@
+//! The RESP wire protocol: `RespData` values, their serialization with `RespData::write`,
+//! and `Resp`, which parses them from a stream. With the `serde` feature, `to_resp` and
+//! `from_resp` convert typed values to and from `RespData`.
+
+use std::borrow::Cow;
+use std::io::prelude::*;
+use std::io::BufReader;
+
+#[cfg(feature = "serde")]
+mod format;
+
+#[cfg(feature = "serde")]
+pub use format::{from_resp, to_resp, Error};
+
+const BULK_STRING: char = '$';
+const SIMPLE_STRING: char = '+';
+const ERROR: char = '-';
+const INTEGER: char = ':';
+const ARRAY: char = '*';
+const MAP: char = '%';
+const PUSH: char = '>';
+// RESP3 types that are read, but never written.
+const NULL: char = '_';
+const BOOLEAN: char = '#';
+const DOUBLE: char = ',';
+const BIG_NUMBER: char = '(';
+const BULK_ERROR: char = '!';
+const VERBATIM_STRING: char = '=';
+const SET: char = '~';
+const ATTRIBUTE: char = '|';
+const LINE_TERMINATORS: &str = "\r\n";
+
+/// Most elements room is made for before an aggregate's elements are read.
+const MAX_PREALLOCATED_ITEMS: usize = 1024;
+
+/// Longest line a value may have, with its terminator, as Redis limits inline requests and
+/// the count lines of multibulk ones.
+const MAX_LINE_LEN: usize = 64 * 1024;
+
+/// Most elements an aggregate may declare, the limit Redis puts on a multibulk request.
+const MAX_AGGREGATE_LEN: i64 = i32::MAX as i64;
+
+/// How deeply aggregates may nest, so a peer can't exhaust the stack of the reading thread.
+const MAX_NESTING_DEPTH: usize = 128;
+
+fn invalid_data(message: &str) -> std::io::Error {
+    std::io::Error::new(std::io::ErrorKind::InvalidData, message)
+}
+
+#[derive(Debug, PartialEq, Eq, Clone, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum RespData {
+    SimpleString(String),
+    Error(String),
+    Integer(i64),
+    BulkString(String),
+    Array(Vec<RespData>),
+    Null,
+    /// A RESP3 map, only sent to clients that switched to RESP3 with HELLO.
+    Map(Vec<(RespData, RespData)>),
+    /// A RESP3 out-of-band message, such as a client tracking invalidation.
+    Push(Vec<RespData>),
+}
+
+impl RespData {
+    /// A bulk string, the type of command arguments and of most string replies.
+    pub fn bulk(s: impl Into<String>) -> Self {
+        RespData::BulkString(s.into())
+    }
+
+    pub fn array<T: Into<RespData>>(items: impl IntoIterator<Item = T>) -> Self {
+        RespData::Array(items.into_iter().map(Into::into).collect())
+    }
+
+    pub fn write(&self, buf: &mut impl Write) -> Result<(), std::io::Error> {
+        match self {
+            RespData::SimpleString(s) => {
+                buf.write_all(&[SIMPLE_STRING as u8])?;
+                write!(buf, "{s}{LINE_TERMINATORS}")
+            }
+            RespData::Error(e) => {
+                buf.write_all(&[ERROR as u8])?;
+                // Like Redis, so a client's own arguments echoed back can't end the line early
+                // and smuggle a fake reply in after it.
+                let e = if e.contains(['\r', '\n']) {
+                    Cow::Owned(e.replace(['\r', '\n'], " "))
+                } else {
+                    Cow::Borrowed(e.as_str())
+                };
+                // As in Redis, an error starting with '-' carries its own code instead of ERR.
+                match e.strip_prefix(ERROR) {
+                    Some(e) => write!(buf, "{e}{LINE_TERMINATORS}"),
+                    None => write!(buf, "ERR {e}{LINE_TERMINATORS}"),
+                }
+            }
+            RespData::Integer(n) => {
+                buf.write_all(&[INTEGER as u8])?;
+                write!(buf, "{n}{LINE_TERMINATORS}")
+            }
+            RespData::BulkString(s) => write_bulk_string(buf, s),
+            RespData::Array(arr) => {
+                write_array_header(buf, arr.len())?;
+                for item in arr {
+                    item.write(buf)?;
+                }
+                Ok(())
+            }
+            RespData::Null => {
+                write!(buf, "$-1{LINE_TERMINATORS}")
+            }
+            RespData::Map(entries) => {
+                buf.write_all(&[MAP as u8])?;
+                write!(buf, "{len}{LINE_TERMINATORS}", len = entries.len())?;
+                for (key, value) in entries {
+                    key.write(buf)?;
+                    value.write(buf)?;
+                }
+                Ok(())
+            }
+            RespData::Push(items) => {
+                buf.write_all(&[PUSH as u8])?;
+                write!(buf, "{len}{LINE_TERMINATORS}", len = items.len())?;
+                for item in items {
+                    item.write(buf)?;
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+/// Writes the header of an array of `len` items, which must follow it, for replies written
+/// piece by piece rather than built as a `RespData` first.
+pub fn write_array_header(buf: &mut impl Write, len: usize) -> Result<(), std::io::Error> {
+    buf.write_all(&[ARRAY as u8])?;
+    write!(buf, "{len}{LINE_TERMINATORS}")
+}
+
+/// Writes `s` as a bulk string, like `RespData::BulkString` but without owning it.
+pub fn write_bulk_string(buf: &mut impl Write, s: &str) -> Result<(), std::io::Error> {
+    buf.write_all(&[BULK_STRING as u8])?;
+    write!(
+        buf,
+        "{len}{LINE_TERMINATORS}{s}{LINE_TERMINATORS}",
+        len = s.len()
+    )
+}
+
+/// Strings convert to bulk strings, as command arguments are sent.
+impl From<&str> for RespData {
+    fn from(s: &str) -> Self {
+        RespData::BulkString(s.to_string())
+    }
+}
+
+impl From<&String> for RespData {
+    fn from(s: &String) -> Self {
+        RespData::BulkString(s.clone())
+    }
+}
+
+impl From<String> for RespData {
+    fn from(s: String) -> Self {
+        RespData::BulkString(s)
+    }
+}
+
+impl From<i64> for RespData {
+    fn from(n: i64) -> Self {
+        RespData::Integer(n)
+    }
+}
+
+impl From<Vec<RespData>> for RespData {
+    fn from(items: Vec<RespData>) -> Self {
+        RespData::Array(items)
+    }
+}
+
+/// Builds a `RespData::Array`, converting each element with `RespData::from`, so strings
+/// become bulk strings and integers integers.
+///
+/// ```
+/// use redis_from_scratch::resp;
+/// use redis_from_scratch::resp::RespData;
+///
+/// let key = String::from("counter");
+/// assert_eq!(
+///     resp!["INCRBY", &key, 5],
+///     RespData::Array(vec![
+///         RespData::BulkString("INCRBY".to_string()),
+///         RespData::BulkString("counter".to_string()),
+///         RespData::Integer(5),
+///     ])
+/// );
+/// ```
+#[macro_export]
+macro_rules! resp {
+    ($($item:expr),* $(,)?) => {
+        $crate::resp::RespData::Array(vec![$($crate::resp::RespData::from($item)),*])
+    };
+}
+
+/// Default limit on the length of a bulk string, like Redis's `proto-max-bulk-len`.
+pub(crate) const DEFAULT_MAX_BULK_LEN: u64 = 512 * 1024 * 1024;
+
+pub struct Resp<R: Read> {
+    reader: BufReader<R>,
+    pub raw_data: String,
+    lines: Vec<String>,
+    /// Bulk strings declared longer than this are rejected before reading them.
+    max_bulk_len: u64,
+    /// How many aggregates the value being read is nested in.
+    depth: usize,
+}
+
+impl<R: Read> Resp<R> {
+    pub fn new(input: R) -> Self {
+        Resp {
+            reader: BufReader::new(input),
+            raw_data: String::new(),
+            lines: Vec::new(),
+            max_bulk_len: DEFAULT_MAX_BULK_LEN,
+            depth: 0,
+        }
+    }
+
+    pub fn set_max_bulk_len(&mut self, max: u64) {
+        self.max_bulk_len = max;
+    }
+
+    /// Whether input is already buffered, so `read` may not have to wait for more.
+    pub fn has_buffered(&self) -> bool {
+        !self.reader.buffer().is_empty()
+    }
+
+    /// Reads the next complete value, discarding the raw data buffered for the previous one.
+    pub fn read(&mut self) -> Result<RespData, std::io::Error> {
+        self.raw_data.clear();
+        self.lines.clear();
+        self.depth = 0;
+        self.read_value()
+    }
+
+    fn read_value(&mut self) -> Result<RespData, std::io::Error> {
+        if self.depth >= MAX_NESTING_DEPTH {
+            return Err(invalid_data("too deeply nested"));
+        }
+        self.depth += 1;
+        let value = self.read_value_nested();
+        self.depth -= 1;
+        value
+    }
+
+    /// Reads a value, with `depth` counting it among the values it is nested in.
+    fn read_value_nested(&mut self) -> Result<RespData, std::io::Error> {
+        let line = self.read_line()?;
+        let Some(kind) = line.chars().next() else {
+            return Err(invalid_data("invalid RESP type"));
+        };
+        let rest = &line[kind.len_utf8()..];
+
+        match kind {
+            SIMPLE_STRING => Ok(RespData::SimpleString(rest.to_string())),
+            // Keep the code, so writing the error back out reproduces it exactly.
+            ERROR => Ok(RespData::Error(format!("{ERROR}{rest}"))),
+            INTEGER => Ok(RespData::Integer(self.read_integer(rest)?)),
+            BULK_STRING => Ok(self
+                .read_bulk(rest)?
+                .map_or(RespData::Null, RespData::BulkString)),
+            ARRAY | SET => Ok(self
+                .read_items(rest)?
+                .map_or(RespData::Null, RespData::Array)),
+            PUSH => Ok(self
+                .read_items(rest)?
+                .map_or(RespData::Null, RespData::Push)),
+            MAP => Ok(self
+                .read_entries(rest)?
+                .map_or(RespData::Null, RespData::Map)),
+            // The other RESP3 types are read as the RESP2 reply Redis sends in their place.
+            NULL => Ok(RespData::Null),
+            BOOLEAN => match rest {
+                "t" => Ok(RespData::Integer(1)),
+                "f" => Ok(RespData::Integer(0)),
+                _ => Err(invalid_data("invalid boolean")),
+            },
+            DOUBLE | BIG_NUMBER => Ok(RespData::BulkString(rest.to_string())),
+            BULK_ERROR => Ok(self
+                .read_bulk(rest)?
+                .map_or(RespData::Null, |e| RespData::Error(format!("{ERROR}{e}")))),
+            VERBATIM_STRING => Ok(self.read_bulk(rest)?.map_or(RespData::Null, |s| {
+                // Drop the format, as in `txt:`.
+                match s.get(3..4) {
+                    Some(":") => RespData::BulkString(s[4..].to_string()),
+                    _ => RespData::BulkString(s),
+                }
+            })),
+            // Attributes describe the value that follows them, which is all that is kept.
+            ATTRIBUTE => {
+                self.read_entries(rest)?;
+                self.read_value()
+            }
+            _ => Err(invalid_data("invalid RESP type")),
+        }
+    }
+
+    /// Reads the data of a bulk string, or of the RESP3 types framed like one, given the
+    /// length on its first line. Returns `None` for a negative length, which means null.
+    fn read_bulk(&mut self, len: &str) -> Result<Option<String>, std::io::Error> {
+        let len = self.read_integer(len)?;
+        if len < 0 {
+            return Ok(None);
+        }
+        if len as u64 > self.max_bulk_len {
+            return Err(invalid_data("invalid bulk length"));
+        }
+        // Read by length rather than by line, since the string may contain line breaks.
+        let mut data = vec![0; len as usize + LINE_TERMINATORS.len()];
+        self.reader.read_exact(&mut data)?;
+        if !data.ends_with(LINE_TERMINATORS.as_bytes()) {
+            return Err(invalid_data("bulk string not terminated by CRLF"));
+        }
+        data.truncate(len as usize);
+        let data = String::from_utf8_lossy(&data).into_owned();
+        self.raw_data.push_str(&data);
+        self.raw_data.push_str(LINE_TERMINATORS);
+        Ok(Some(data))
+    }
+
+    /// Reads the elements of an aggregate, given their count. Returns `None` for a negative
+    /// count, which means null.
+    fn read_items(&mut self, len: &str) -> Result<Option<Vec<RespData>>, std::io::Error> {
+        let Some(len) = self.read_aggregate_len(len)? else {
+            return Ok(None);
+        };
+        // The count comes from the peer, so it only bounds how much room is made up front.
+        let mut items = Vec::with_capacity(len.min(MAX_PREALLOCATED_ITEMS));
+        for _ in 0..len {
+            items.push(self.read_value()?);
+        }
+        Ok(Some(items))
+    }
+
+    /// Like `read_items`, for a map or an attribute given its number of key/value pairs.
+    fn read_entries(
+        &mut self,
+        len: &str,
+    ) -> Result<Option<Vec<(RespData, RespData)>>, std::io::Error> {
+        let Some(len) = self.read_aggregate_len(len)? else {
+            return Ok(None);
+        };
+        let mut entries = Vec::with_capacity(len.min(MAX_PREALLOCATED_ITEMS));
+        for _ in 0..len {
+            entries.push((self.read_value()?, self.read_value()?));
+        }
+        Ok(Some(entries))
+    }
+
+    /// The element count of an aggregate, or `None` for a negative one, which means null.
+    fn read_aggregate_len(&mut self, len: &str) -> Result<Option<usize>, std::io::Error> {
+        let len = self.read_integer(len)?;
+        if len > MAX_AGGREGATE_LEN {
+            return Err(invalid_data("invalid multibulk length"));
+        }
+        Ok(usize::try_from(len).ok())
+    }
+
+    pub fn read_line(&mut self) -> Result<String, std::io::Error> {
+        let mut line = String::new();
+        let len = (&mut self.reader)
+            .take(MAX_LINE_LEN as u64)
+            .read_line(&mut line)?;
+        if len == 0 {
+            return Err(std::io::ErrorKind::UnexpectedEof.into());
+        }
+        if len == MAX_LINE_LEN && !line.ends_with('\n') {
+            return Err(line_too_long(line.as_bytes()));
+        }
+        self.raw_data.push_str(&line);
+        // Only the terminator goes, since spaces may be part of a simple string or an error.
+        let content = line.strip_suffix('\n').unwrap_or(&line);
+        let content = content.strip_suffix('\r').unwrap_or(content).to_string();
+        self.lines.push(line);
+        Ok(content)
+    }
+
+    pub fn read_integer(&mut self, line: &str) -> Result<i64, std::io::Error> {
+        let num = line
+            .trim()
+            .parse::<i64>()
+            .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidData, e))?;
+        Ok(num)
+    }
+}
+
+/// Finds where values end in input that arrives a piece at a time, walking the framing
+/// without building the values. It picks up where the last call left off, so a value is
+/// walked once however many pieces it comes in.
+#[derive(Debug, Default, Clone)]
+pub(crate) struct Framer {
+    /// How far into the value the walk has got, always at the start of a line or the end of
+    /// a bulk string, which may be past the input so far.
+    pos: usize,
+    /// Values still to be walked over, counting the elements of the aggregates seen so far,
+    /// or `None` before the walk starts.
+    pending: Option<u64>,
+}
+
+impl Framer {
+    /// The length of the value at the start of `buf`, or `None` if it isn't all there yet.
+    /// `buf` must start where it did on the last call, unless that call returned a length.
+    pub(crate) fn frame_len(
+        &mut self,
+        buf: &[u8],
+        max_bulk_len: u64,
+    ) -> std::io::Result<Option<usize>> {
+        let pending = self.pending.get_or_insert(1);
+        loop {
+            if self.pos > buf.len() {
+                return Ok(None);
+            }
+            if *pending == 0 {
+                break;
+            }
+            let rest = &buf[self.pos..];
+            let Some(end) = rest.iter().position(|&byte| byte == b'\n') else {
+                if rest.len() >= MAX_LINE_LEN {
+                    return Err(line_too_long(rest));
+                }
+                return Ok(None);
+            };
+            if end >= MAX_LINE_LEN {
+                return Err(line_too_long(rest));
+            }
+            let line = &rest[..end];
+            self.pos += end + 1;
+            *pending -= 1;
+            let elements = match line.first() {
+                Some(b'+' | b'-' | b':' | b'_' | b'#' | b',' | b'(') => 0,
+                Some(b'$' | b'!' | b'=') => {
+                    let len = parse_length(&line[1..])?;
+                    if len < 0 {
+                        continue;
+                    }
+                    if len as u64 > max_bulk_len {
+                        return Err(invalid_data("invalid bulk length"));
+                    }
+                    self.pos = self.pos.saturating_add(len as usize).saturating_add(2);
+                    0
+                }
+                Some(b'*' | b'>' | b'~') => parse_aggregate_len(&line[1..])?,
+                Some(b'%') => 2 * parse_aggregate_len(&line[1..])?,
+                // An attribute is followed by the value it describes.
+                Some(b'|') => 2 * parse_aggregate_len(&line[1..])? + 1,
+                _ => return Err(invalid_data("invalid RESP type")),
+            };
+            *pending = pending
+                .checked_add(elements)
+                .ok_or_else(|| invalid_data("invalid multibulk length"))?;
+        }
+        self.pending = None;
+        Ok(Some(std::mem::take(&mut self.pos)))
+    }
+}
+
+/// The error for a line over `MAX_LINE_LEN`, worded like Redis's for the kind of line.
+fn line_too_long(line: &[u8]) -> std::io::Error {
+    invalid_data(match line.first() {
+        Some(b'*') => "too big mbulk count string",
+        Some(b'$') => "too big bulk count string",
+        _ => "too big inline request",
+    })
+}
+
+/// An aggregate's element count, with null counting as none.
+fn parse_aggregate_len(digits: &[u8]) -> std::io::Result<u64> {
+    let len = parse_length(digits)?;
+    if len > MAX_AGGREGATE_LEN {
+        return Err(invalid_data("invalid multibulk length"));
+    }
+    Ok(len.max(0) as u64)
+}
+
+fn parse_length(digits: &[u8]) -> std::io::Result<i64> {
+    std::str::from_utf8(digits)
+        .ok()
+        .and_then(|digits| digits.trim().parse().ok())
+        .ok_or_else(|| invalid_data("invalid length"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::util::assert_format_repr;
+    use std::io::ErrorKind;
+
+    #[test]
+    fn test_simple_string_write_to_buf() {
+        assert_format_repr(&RespData::SimpleString("OK".into()), b"+OK\r\n");
+    }
+
+    #[test]
+    fn test_error_write_to_buf() {
+        assert_format_repr(
+            &RespData::Error("Invalid command".to_string()),
+            b"-ERR Invalid command\r\n",
+        );
+        assert_format_repr(
+            &RespData::Error("-NOAUTH Authentication required.".to_string()),
+            b"-NOAUTH Authentication required.\r\n",
+        );
+    }
+
+    #[test]
+    fn test_integer_write_to_buf() {
+        assert_format_repr(&RespData::Integer(123), b":123\r\n");
+        assert_format_repr(&RespData::Integer(-123), b":-123\r\n");
+    }
+
+    #[test]
+    fn test_bulk_string_write_to_buf() {
+        assert_format_repr(
+            &RespData::BulkString("hello".to_string()),
+            b"$5\r\nhello\r\n",
+        );
+        assert_format_repr(&RespData::BulkString("".to_string()), b"$0\r\n\r\n");
+    }
+
+    #[test]
+    fn test_array_write_to_buf() {
+        assert_format_repr(
+            &RespData::Array(vec![
+                RespData::SimpleString("OK".to_string()),
+                RespData::Integer(123),
+                RespData::BulkString("hello".to_string()),
+            ]),
+            b"*3\r\n+OK\r\n:123\r\n$5\r\nhello\r\n",
+        );
+        assert_format_repr(&RespData::Array(vec![]), b"*0\r\n");
+    }
+
+    #[test]
+    fn test_null_write_to_buf() {
+        assert_format_repr(&RespData::Null, b"$-1\r\n");
+    }
+
+    #[test]
+    fn test_map_write_to_buf() {
+        assert_format_repr(
+            &RespData::Map(vec![(
+                RespData::BulkString("proto".to_string()),
+                RespData::Integer(3),
+            )]),
+            b"%1\r\n$5\r\nproto\r\n:3\r\n",
+        );
+    }
+
+    #[test]
+    fn test_push_write_to_buf() {
+        assert_format_repr(
+            &RespData::Push(vec![
+                RespData::BulkString("invalidate".to_string()),
+                RespData::Array(vec![RespData::BulkString("key".to_string())]),
+            ]),
+            b">2\r\n$10\r\ninvalidate\r\n*1\r\n$3\r\nkey\r\n",
+        );
+    }
+
+    #[test]
+    fn test_conversions() {
+        let key = "k".to_string();
+        let test_cases = [
+            (
+                "From a str",
+                RespData::from("v"),
+                RespData::BulkString("v".to_string()),
+            ),
+            (
+                "From a String",
+                RespData::from(key.clone()),
+                RespData::BulkString("k".to_string()),
+            ),
+            ("From an integer", RespData::from(-3), RespData::Integer(-3)),
+            (
+                "From a Vec",
+                RespData::from(vec![RespData::Null]),
+                RespData::Array(vec![RespData::Null]),
+            ),
+            (
+                "Bulk",
+                RespData::bulk(&key),
+                RespData::BulkString("k".to_string()),
+            ),
+            (
+                "Array",
+                RespData::array(["GET", "k"]),
+                RespData::Array(vec![
+                    RespData::BulkString("GET".to_string()),
+                    RespData::BulkString("k".to_string()),
+                ]),
+            ),
+            ("Empty macro", crate::resp![], RespData::Array(vec![])),
+            (
+                "Macro",
+                crate::resp!["SET", &key, 1, crate::resp![RespData::Null],],
+                RespData::Array(vec![
+                    RespData::BulkString("SET".to_string()),
+                    RespData::BulkString("k".to_string()),
+                    RespData::Integer(1),
+                    RespData::Array(vec![RespData::Null]),
+                ]),
+            ),
+        ];
+
+        for (name, actual, expected) in test_cases {
+            assert_eq!(actual, expected, "{}", name);
+        }
+    }
+
+    #[test]
+    fn test_read() {
+        let test_cases = [
+            (
+                "Simple string",
+                &b"+OK\r\n"[..],
+                RespData::SimpleString("OK".to_string()),
+            ),
+            (
+                "Error keeps its code",
+                &b"-WRONGTYPE bad\r\n"[..],
+                RespData::Error("-WRONGTYPE bad".to_string()),
+            ),
+            (
+                "Simple string with leading and trailing spaces",
+                &b"+  OK \r\n"[..],
+                RespData::SimpleString("  OK ".to_string()),
+            ),
+            (
+                "Error with a trailing space",
+                &b"-ERR bad \r\n"[..],
+                RespData::Error("-ERR bad ".to_string()),
+            ),
+            ("Integer", &b":-42\r\n"[..], RespData::Integer(-42)),
+            (
+                "Bulk string with a line break",
+                &b"$8\r\na\r\nb c\r\n\r\n"[..],
+                RespData::BulkString("a\r\nb c\r\n".to_string()),
+            ),
+            ("Null bulk string", &b"$-1\r\n"[..], RespData::Null),
+            ("Null array", &b"*-1\r\n"[..], RespData::Null),
+            (
+                "Nested array",
+                &b"*2\r\n*1\r\n:1\r\n$0\r\n\r\n"[..],
+                RespData::Array(vec![
+                    RespData::Array(vec![RespData::Integer(1)]),
+                    RespData::BulkString(String::new()),
+                ]),
+            ),
+            (
+                "Map",
+                &b"%1\r\n+proto\r\n:3\r\n"[..],
+                RespData::Map(vec![(
+                    RespData::SimpleString("proto".to_string()),
+                    RespData::Integer(3),
+                )]),
+            ),
+            (
+                "Push",
+                &b">1\r\n$3\r\nkey\r\n"[..],
+                RespData::Push(vec![RespData::BulkString("key".to_string())]),
+            ),
+        ];
+
+        for (name, input, expected) in test_cases {
+            let mut resp = Resp::new(input);
+            assert_eq!(resp.read().unwrap(), expected, "{}", name);
+            if expected != RespData::Null {
+                let mut written = Vec::new();
+                expected.write(&mut written).unwrap();
+                assert_eq!(written, input, "{}: round trip", name);
+            }
+        }
+    }
+
+    #[test]
+    fn test_read_nested() {
+        let mut nested = RespData::Integer(1);
+        for _ in 0..64 {
+            nested = RespData::Array(vec![
+                RespData::Error("-WRONGTYPE bad".to_string()),
+                nested,
+                RespData::Map(vec![(RespData::bulk("k"), RespData::Null)]),
+            ]);
+        }
+        let mut written = Vec::new();
+        nested.write(&mut written).unwrap();
+
+        let mut resp = Resp::new(written.as_slice());
+        assert_eq!(resp.read().unwrap(), nested);
+        assert_eq!(resp.raw_data.as_bytes(), written.as_slice());
+    }
+
+    #[test]
+    fn test_read_resp3() {
+        let test_cases = [
+            ("Null", &b"_\r\n"[..], RespData::Null),
+            ("True", &b"#t\r\n"[..], RespData::Integer(1)),
+            ("False", &b"#f\r\n"[..], RespData::Integer(0)),
+            (
+                "Double",
+                &b",3.14\r\n"[..],
+                RespData::BulkString("3.14".to_string()),
+            ),
+            (
+                "Big number",
+                &b"(3492890328409238509324850943850943825024385\r\n"[..],
+                RespData::BulkString("3492890328409238509324850943850943825024385".to_string()),
+            ),
+            (
+                "Bulk error keeps its code",
+                &b"!21\r\nSYNTAX invalid syntax\r\n"[..],
+                RespData::Error("-SYNTAX invalid syntax".to_string()),
+            ),
+            (
+                "Verbatim string loses its format",
+                &b"=15\r\ntxt:Some string\r\n"[..],
+                RespData::BulkString("Some string".to_string()),
+            ),
+            (
+                "Set",
+                &b"~2\r\n+a\r\n#t\r\n"[..],
+                RespData::Array(vec![
+                    RespData::SimpleString("a".to_string()),
+                    RespData::Integer(1),
+                ]),
+            ),
+            (
+                "Attribute before a nested value",
+                &b"*2\r\n|1\r\n+key-popularity\r\n%0\r\n:1\r\n:2\r\n"[..],
+                RespData::Array(vec![RespData::Integer(1), RespData::Integer(2)]),
+            ),
+        ];
+
+        for (name, input, expected) in test_cases {
+            let mut resp = Resp::new(input);
+            assert_eq!(resp.read().unwrap(), expected, "{}", name);
+            assert!(resp.read().is_err(), "{}: trailing data", name);
+        }
+    }
+
+    #[test]
+    fn test_read_errors() {
+        let test_cases = [
+            ("Unknown type", &b"?what\r\n"[..], ErrorKind::InvalidData),
+            ("Empty line", &b"\r\n"[..], ErrorKind::InvalidData),
+            ("Bad length", &b"*x\r\n"[..], ErrorKind::InvalidData),
+            ("Bad boolean", &b"#maybe\r\n"[..], ErrorKind::InvalidData),
+            (
+                "Huge declared array",
+                &b"*9223372036854775807\r\n:1\r\n"[..],
+                ErrorKind::InvalidData,
+            ),
+            (
+                "Huge declared map",
+                &b"%2147483648\r\n"[..],
+                ErrorKind::InvalidData,
+            ),
+            (
+                "Array at the limit",
+                &b"*2147483647\r\n:1\r\n"[..],
+                ErrorKind::UnexpectedEof,
+            ),
+            (
+                "Truncated bulk string",
+                &b"$5\r\nab"[..],
+                ErrorKind::UnexpectedEof,
+            ),
+            (
+                "Bulk string without CRLF",
+                &b"$3\r\nfooXY"[..],
+                ErrorKind::InvalidData,
+            ),
+        ];
+
+        for (name, input, kind) in test_cases {
+            let mut resp = Resp::new(input);
+            assert_eq!(resp.read().unwrap_err().kind(), kind, "{}", name);
+        }
+
+        let mut line = b"*".to_vec();
+        line.resize(MAX_LINE_LEN, b'1');
+        let error = Resp::new(&line[..]).read().unwrap_err();
+        assert_eq!(error.to_string(), "too big mbulk count string");
+    }
+
+    #[test]
+    fn test_max_nesting_depth() {
+        let nested =
+            |depth: usize, header: &str| format!("{}:1\r\n", header.repeat(depth - 1)).into_bytes();
+        let test_cases = [
+            ("At the limit", nested(MAX_NESTING_DEPTH, "*1\r\n"), None),
+            (
+                "Arrays too deep",
+                nested(MAX_NESTING_DEPTH + 1, "*1\r\n"),
+                Some(ErrorKind::InvalidData),
+            ),
+            (
+                "Far too deep",
+                nested(1_000_000, "*1\r\n"),
+                Some(ErrorKind::InvalidData),
+            ),
+            (
+                "Attributes too deep",
+                nested(1_000_000, "|1\r\n+key\r\n+value\r\n"),
+                Some(ErrorKind::InvalidData),
+            ),
+        ];
+
+        for (name, input, expected) in test_cases {
+            let result = Resp::new(&input[..]).read();
+            assert_eq!(result.err().map(|e| e.kind()), expected, "{}", name);
+        }
+    }
+
+    #[test]
+    fn test_framer() {
+        let long_line = |header: &str| {
+            let mut line = header.as_bytes().to_vec();
+            line.resize(MAX_LINE_LEN, b'1');
+            line
+        };
+        let test_cases = [
+            ("Array", b"*2\r\n$3\r\nGET\r\n$1\r\nk\r\n".to_vec(), Ok(20)),
+            ("Bulk string", b"$5\r\nhello\r\n".to_vec(), Ok(11)),
+            ("Map", b"%1\r\n+a\r\n:1\r\n".to_vec(), Ok(12)),
+            (
+                "Count line too long",
+                long_line("*"),
+                Err("too big mbulk count string"),
+            ),
+            (
+                "Length line too long",
+                long_line("$"),
+                Err("too big bulk count string"),
+            ),
+            (
+                "Other line too long",
+                long_line("+"),
+                Err("too big inline request"),
+            ),
+        ];
+
+        for (name, input, expected) in test_cases {
+            // Fed a piece at a time, a value is found once its last byte is in.
+            let mut framer = Framer::default();
+            let mut result = Ok(None);
+            let step = (input.len() / 64).max(1);
+            for end in (1..input.len()).step_by(step).chain([input.len()]) {
+                result = framer
+                    .frame_len(&input[..end], 5)
+                    .map_err(|e| e.to_string());
+                if end < input.len() {
+                    assert!(matches!(result, Ok(None)), "{}: at {}", name, end);
+                }
+            }
+            assert_eq!(result, expected.map(Some).map_err(String::from), "{}", name);
+        }
+
+        // Once a value is found the next one starts at the front of what follows.
+        let mut framer = Framer::default();
+        assert_eq!(framer.frame_len(b":1\r\n:22\r\n", 5).unwrap(), Some(4));
+        assert_eq!(framer.frame_len(b":22\r\n", 5).unwrap(), Some(5));
+    }
+
+    #[test]
+    fn test_max_bulk_len() {
+        let test_cases = [
+            ("Within the limit", &b"*1\r\n$5\r\nhello\r\n"[..], true),
+            ("Over the limit", &b"*1\r\n$6\r\nhello!\r\n"[..], false),
+            ("Bare bulk string over the limit", &b"$100\r\n"[..], false),
+        ];
+
+        for (name, input, ok) in test_cases {
+            let mut resp = Resp::new(input);
+            resp.set_max_bulk_len(5);
+            let result = resp.read();
+            assert_eq!(result.is_ok(), ok, "{}", name);
+            if let Err(e) = result {
+                assert_eq!(e.kind(), std::io::ErrorKind::InvalidData, "{}", name);
+                assert_eq!(e.to_string(), "invalid bulk length", "{}", name);
+            }
+        }
+    }
+}
@@ -0,0 +1,661 @@
+//! Full-text and vector search over hashes for the FT.* commands, a small subset of
+//! RediSearch: an index covers the hashes under some key prefixes, keeping an inverted index
+//! of the words in their TEXT fields and the embeddings in their VECTOR fields. It answers
+//! queries of words and word prefixes ranked by TF-IDF, and K nearest neighbour queries.
+//!
+//! Vectors are searched exhaustively whether the index asks for FLAT or HNSW, so results are
+//! exact. Bulk strings are UTF-8 here, so a vector is written as text, its components
+//! separated by commas, rather than as the raw FLOAT32 bytes RediSearch takes.
+//!
+//! Indexes live only in memory. Snapshots don't store them, so after a restart they must be
+//! created again, which indexes the hashes already there.
+
+use crate::handler::RedisHash;
+use std::collections::{BTreeMap, HashMap};
+
+/// The most words a prefix query expands to, as RediSearch's default MAXEXPANSIONS.
+const MAX_EXPANSIONS: usize = 200;
+
+/// A field of an index's schema.
+#[derive(Debug, Clone, PartialEq)]
+pub enum SchemaField {
+    Text(TextField),
+    Vector(VectorField),
+}
+
+impl SchemaField {
+    pub fn name(&self) -> &str {
+        match self {
+            SchemaField::Text(field) => &field.name,
+            SchemaField::Vector(field) => &field.name,
+        }
+    }
+}
+
+/// A TEXT field. Matches in it count `weight` times as much.
+#[derive(Debug, Clone, PartialEq)]
+pub struct TextField {
+    pub name: String,
+    pub weight: f64,
+}
+
+/// A VECTOR field of FLOAT32 embeddings with `dim` components.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct VectorField {
+    pub name: String,
+    pub algorithm: VectorAlgorithm,
+    pub dim: usize,
+    pub metric: DistanceMetric,
+}
+
+/// The index structure a VECTOR field asks for. Both are searched exhaustively.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum VectorAlgorithm {
+    Flat,
+    Hnsw,
+}
+
+impl VectorAlgorithm {
+    /// Parses an algorithm name, in any case.
+    pub fn parse(name: &str) -> Option<Self> {
+        match name.to_uppercase().as_str() {
+            "FLAT" => Some(VectorAlgorithm::Flat),
+            "HNSW" => Some(VectorAlgorithm::Hnsw),
+            _ => None,
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            VectorAlgorithm::Flat => "FLAT",
+            VectorAlgorithm::Hnsw => "HNSW",
+        }
+    }
+}
+
+/// How far apart two vectors are.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DistanceMetric {
+    /// The squared Euclidean distance, as in RediSearch.
+    L2,
+    /// One minus the cosine of the angle between them.
+    Cosine,
+}
+
+impl DistanceMetric {
+    /// Parses a metric name, in any case.
+    pub fn parse(name: &str) -> Option<Self> {
+        match name.to_uppercase().as_str() {
+            "L2" => Some(DistanceMetric::L2),
+            "COSINE" => Some(DistanceMetric::Cosine),
+            _ => None,
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            DistanceMetric::L2 => "L2",
+            DistanceMetric::Cosine => "COSINE",
+        }
+    }
+
+    fn distance(self, a: &[f32], b: &[f32]) -> f64 {
+        let pairs = a.iter().zip(b).map(|(&a, &b)| (a as f64, b as f64));
+        match self {
+            DistanceMetric::L2 => pairs.map(|(a, b)| (a - b) * (a - b)).sum(),
+            DistanceMetric::Cosine => {
+                let (mut dot, mut norm_a, mut norm_b) = (0.0, 0.0, 0.0);
+                for (a, b) in pairs {
+                    dot += a * b;
+                    norm_a += a * a;
+                    norm_b += b * b;
+                }
+                if norm_a == 0.0 || norm_b == 0.0 {
+                    return 1.0;
+                }
+                1.0 - dot / (norm_a.sqrt() * norm_b.sqrt())
+            }
+        }
+    }
+}
+
+/// Parses a vector written as its components separated by commas, like `0.5,-1,2`.
+pub fn parse_vector(text: &str) -> Option<Vec<f32>> {
+    text.split(',')
+        .map(|component| match component.trim().parse::<f32>() {
+            Ok(component) if component.is_finite() => Some(component),
+            _ => None,
+        })
+        .collect()
+}
+
+/// The lowercased words of `text`: its runs of letters, digits and underscores.
+fn words(text: &str) -> impl Iterator<Item = String> + '_ {
+    text.split(|c: char| !c.is_alphanumeric() && c != '_')
+        .filter(|word| !word.is_empty())
+        .map(str::to_lowercase)
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct SearchIndex {
+    /// The key prefixes of the hashes indexed, which are all of them if empty.
+    prefixes: Vec<String>,
+    text_fields: Vec<TextField>,
+    vector_fields: Vec<VectorField>,
+    /// For each word, the documents containing it and how often, per text field.
+    postings: BTreeMap<String, HashMap<String, Vec<u32>>>,
+    /// For each vector field, the documents with a valid vector in it.
+    vectors: Vec<HashMap<String, Vec<f32>>>,
+    /// The words of each document, to remove it by.
+    documents: HashMap<String, Vec<String>>,
+}
+
+impl SearchIndex {
+    pub fn new(prefixes: Vec<String>, schema: Vec<SchemaField>) -> Self {
+        let mut text_fields = Vec::new();
+        let mut vector_fields = Vec::new();
+        for field in schema {
+            match field {
+                SchemaField::Text(field) => text_fields.push(field),
+                SchemaField::Vector(field) => vector_fields.push(field),
+            }
+        }
+        SearchIndex {
+            prefixes,
+            text_fields,
+            vectors: vec![HashMap::new(); vector_fields.len()],
+            vector_fields,
+            postings: BTreeMap::new(),
+            documents: HashMap::new(),
+        }
+    }
+
+    /// Whether the hash at `key` belongs in the index.
+    pub fn covers(&self, key: &str) -> bool {
+        self.prefixes.is_empty() || self.prefixes.iter().any(|p| key.starts_with(p.as_str()))
+    }
+
+    /// How many documents are indexed.
+    pub fn len(&self) -> usize {
+        self.documents.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.documents.is_empty()
+    }
+
+    /// Indexes the hash at `key`, replacing what was indexed for it before. A vector that
+    /// doesn't parse or has the wrong number of components is left out.
+    pub fn insert(&mut self, key: &str, hash: &RedisHash) {
+        self.remove(key);
+        let mut counts: HashMap<String, Vec<u32>> = HashMap::new();
+        for (i, field) in self.text_fields.iter().enumerate() {
+            let Some(text) = hash.get(&field.name) else {
+                continue;
+            };
+            for word in words(text) {
+                counts
+                    .entry(word)
+                    .or_insert_with(|| vec![0; self.text_fields.len()])[i] += 1;
+            }
+        }
+        let mut document = Vec::with_capacity(counts.len());
+        for (word, count) in counts {
+            self.postings
+                .entry(word.clone())
+                .or_default()
+                .insert(key.to_string(), count);
+            document.push(word);
+        }
+        for (field, vectors) in self.vector_fields.iter().zip(&mut self.vectors) {
+            let vector = hash.get(&field.name).and_then(|text| parse_vector(text));
+            if let Some(vector) = vector.filter(|vector| vector.len() == field.dim) {
+                vectors.insert(key.to_string(), vector);
+            }
+        }
+        self.documents.insert(key.to_string(), document);
+    }
+
+    /// Drops `key` from the index, if it was there.
+    pub fn remove(&mut self, key: &str) {
+        let Some(words) = self.documents.remove(key) else {
+            return;
+        };
+        for word in words {
+            if let Some(documents) = self.postings.get_mut(&word) {
+                documents.remove(key);
+                if documents.is_empty() {
+                    self.postings.remove(&word);
+                }
+            }
+        }
+        for vectors in &mut self.vectors {
+            vectors.remove(key);
+        }
+    }
+
+    /// Drops every document, keeping the schema.
+    pub fn clear(&mut self) {
+        self.postings.clear();
+        self.vectors.iter_mut().for_each(HashMap::clear);
+        self.documents.clear();
+    }
+
+    /// The documents matching `query` with their scores, the best first and ties by key. For
+    /// a KNN query the score is the distance, and the nearest come first.
+    pub fn search(&self, query: &Query) -> Result<Vec<(&str, f64)>, String> {
+        let matches = self.filter(&query.filter)?;
+        let Some(knn) = &query.knn else {
+            return Ok(matches);
+        };
+
+        let i = self
+            .vector_fields
+            .iter()
+            .position(|field| field.name == knn.field)
+            .ok_or_else(|| format!("'{}' is not a vector field", knn.field))?;
+        let field = &self.vector_fields[i];
+        if knn.vector.len() != field.dim {
+            return Err(format!(
+                "query vector has {} dimensions, expected {}",
+                knn.vector.len(),
+                field.dim
+            ));
+        }
+        let mut nearest: Vec<_> = matches
+            .into_iter()
+            .filter_map(|(key, _)| {
+                let vector = self.vectors[i].get(key)?;
+                Some((key, field.metric.distance(&knn.vector, vector)))
+            })
+            .collect();
+        nearest.sort_by(|a, b| a.1.total_cmp(&b.1).then(a.0.cmp(b.0)));
+        nearest.truncate(knn.k);
+        Ok(nearest)
+    }
+
+    /// The documents matching the words of a query, the best first.
+    fn filter(&self, filter: &Filter) -> Result<Vec<(&str, f64)>, String> {
+        let terms = match filter {
+            Filter::All => {
+                let mut all: Vec<_> = self
+                    .documents
+                    .keys()
+                    .map(|key| (key.as_str(), 1.0))
+                    .collect();
+                all.sort_by(|a, b| a.0.cmp(b.0));
+                return Ok(all);
+            }
+            Filter::Terms(terms) => terms,
+        };
+
+        let mut results: Option<HashMap<&str, f64>> = None;
+        for term in terms {
+            let field = match &term.field {
+                Some(name) => Some(
+                    self.text_fields
+                        .iter()
+                        .position(|field| field.name == *name)
+                        .ok_or_else(|| format!("Unknown field '{name}'"))?,
+                ),
+                None => None,
+            };
+            let scores = self.term_scores(term, field);
+            results = Some(match results {
+                None => scores,
+                Some(mut results) => {
+                    results.retain(|key, _| scores.contains_key(key));
+                    for (key, score) in &mut results {
+                        *score += scores[key];
+                    }
+                    results
+                }
+            });
+        }
+
+        let mut results: Vec<_> = results.unwrap_or_default().into_iter().collect();
+        results.sort_by(|a, b| b.1.total_cmp(&a.1).then(a.0.cmp(b.0)));
+        Ok(results)
+    }
+
+    /// The documents matching a single term, scored by the weighted frequency of the words
+    /// it matches times how rare they are.
+    fn term_scores(&self, term: &Term, field: Option<usize>) -> HashMap<&str, f64> {
+        let matches: Vec<_> = if term.prefix {
+            self.postings
+                .range(term.word.clone()..)
+                .take_while(|(word, _)| word.starts_with(term.word.as_str()))
+                .take(MAX_EXPANSIONS)
+                .collect()
+        } else {
+            self.postings
+                .get_key_value(&term.word)
+                .into_iter()
+                .collect()
+        };
+
+        let mut scores = HashMap::new();
+        for (_, documents) in matches {
+            let idf = (1.0 + self.documents.len() as f64 / documents.len() as f64).ln();
+            for (key, counts) in documents {
+                let frequency: f64 = self
+                    .text_fields
+                    .iter()
+                    .zip(counts)
+                    .enumerate()
+                    .filter(|(i, _)| field.is_none_or(|field| field == *i))
+                    .map(|(_, (field, count))| field.weight * *count as f64)
+                    .sum();
+                if frequency > 0.0 {
+                    *scores.entry(key.as_str()).or_insert(0.0) += frequency * idf;
+                }
+            }
+        }
+        scores
+    }
+}
+
+/// A parsed FT.SEARCH query: words to match, optionally followed by `=>[KNN ...]` to pick
+/// the nearest of the matches to a vector.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Query {
+    filter: Filter,
+    knn: Option<Knn>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Filter {
+    /// `*`, every document.
+    All,
+    /// Words that must all match.
+    Terms(Vec<Term>),
+}
+
+/// A query word, like `hello`, a prefix like `hel*`, and either restricted to one field
+/// with `@title:hello`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Term {
+    field: Option<String>,
+    word: String,
+    prefix: bool,
+}
+
+/// `[KNN k @field $vector [AS name]]`: the `k` documents nearest to `vector`, replied with
+/// their distance in the field `score_field`.
+#[derive(Debug, Clone, PartialEq)]
+struct Knn {
+    k: usize,
+    field: String,
+    vector: Vec<f32>,
+    score_field: String,
+}
+
+fn syntax_error() -> String {
+    "Syntax error".to_string()
+}
+
+impl Query {
+    /// Parses a query, taking the values of its `$name` parameters from `params`.
+    pub fn parse(text: &str, params: &[(String, String)]) -> Result<Self, String> {
+        let (filter, knn) = match text.split_once("=>") {
+            Some((filter, knn)) => (filter, Some(Knn::parse(knn, params)?)),
+            None => (text, None),
+        };
+        let filter = filter.trim();
+        let filter = filter
+            .strip_prefix('(')
+            .and_then(|filter| filter.strip_suffix(')'))
+            .unwrap_or(filter);
+        Ok(Query {
+            filter: Filter::parse(filter)?,
+            knn,
+        })
+    }
+
+    /// The field a KNN query replies with each document's distance in.
+    pub fn score_field(&self) -> Option<&str> {
+        self.knn.as_ref().map(|knn| knn.score_field.as_str())
+    }
+}
+
+impl Filter {
+    fn parse(text: &str) -> Result<Self, String> {
+        if text.trim() == "*" {
+            return Ok(Filter::All);
+        }
+        let mut terms = Vec::new();
+        for token in text.split_whitespace() {
+            let (field, token) = match token.strip_prefix('@') {
+                Some(token) => match token.split_once(':') {
+                    Some((field, token)) if !field.is_empty() => (Some(field.to_string()), token),
+                    _ => return Err(syntax_error()),
+                },
+                None => (None, token),
+            };
+            let (token, prefix) = match token.strip_suffix('*') {
+                Some(token) => (token, true),
+                None => (token, false),
+            };
+            // A token like `e-mail` holds several words, of which only the last is a prefix.
+            let words: Vec<_> = words(token).collect();
+            if prefix && words.is_empty() {
+                return Err(syntax_error());
+            }
+            let last = words.len().saturating_sub(1);
+            terms.extend(words.into_iter().enumerate().map(|(i, word)| Term {
+                field: field.clone(),
+                word,
+                prefix: prefix && i == last,
+            }));
+        }
+        Ok(Filter::Terms(terms))
+    }
+}
+
+impl Knn {
+    fn parse(text: &str, params: &[(String, String)]) -> Result<Self, String> {
+        let inner = text
+            .trim()
+            .strip_prefix('[')
+            .and_then(|text| text.strip_suffix(']'))
+            .ok_or_else(syntax_error)?;
+        let tokens: Vec<_> = inner.split_whitespace().collect();
+        let (k, field, vector, score_field) = match tokens[..] {
+            [knn, k, field, vector] if knn.eq_ignore_ascii_case("KNN") => (k, field, vector, None),
+            [knn, k, field, vector, as_, name]
+                if knn.eq_ignore_ascii_case("KNN") && as_.eq_ignore_ascii_case("AS") =>
+            {
+                (k, field, vector, Some(name))
+            }
+            _ => return Err(syntax_error()),
+        };
+        let param = |value: &str| match value.strip_prefix('$') {
+            Some(name) => params
+                .iter()
+                .find(|(param, _)| param == name)
+                .map(|(_, value)| value.clone())
+                .ok_or_else(|| format!("No such parameter `{name}`")),
+            None => Ok(value.to_string()),
+        };
+        let field = field.strip_prefix('@').ok_or_else(syntax_error)?;
+        Ok(Knn {
+            k: param(k)?
+                .parse()
+                .map_err(|_| "Invalid value for KNN k".to_string())?,
+            field: field.to_string(),
+            vector: parse_vector(&param(vector)?).ok_or_else(|| "Invalid vector".to_string())?,
+            score_field: score_field.map_or_else(|| format!("__{field}_score"), str::to_string),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn hash(pairs: &[(&str, &str)]) -> RedisHash {
+        pairs
+            .iter()
+            .map(|(field, value)| (field.to_string(), value.to_string()))
+            .collect()
+    }
+
+    fn index() -> SearchIndex {
+        let field = |name: &str, weight| {
+            SchemaField::Text(TextField {
+                name: name.to_string(),
+                weight,
+            })
+        };
+        let mut index = SearchIndex::new(
+            vec!["doc:".to_string()],
+            vec![field("title", 2.0), field("body", 1.0)],
+        );
+        index.insert(
+            "doc:1",
+            &hash(&[("title", "Redis streams"), ("body", "Streams are logs")]),
+        );
+        index.insert(
+            "doc:2",
+            &hash(&[
+                ("title", "Search"),
+                ("body", "Searching redis hashes, fast"),
+            ]),
+        );
+        index.insert(
+            "doc:3",
+            &hash(&[("body", "Bloom filters"), ("other", "redis")]),
+        );
+        index
+    }
+
+    fn search<'a>(index: &'a SearchIndex, query: &str) -> Vec<&'a str> {
+        let params = [("v".to_string(), "1,0".to_string())];
+        let query = Query::parse(query, &params).unwrap();
+        index
+            .search(&query)
+            .unwrap()
+            .into_iter()
+            .map(|(key, _)| key)
+            .collect()
+    }
+
+    #[test]
+    fn test_search() {
+        let mut index = index();
+        let test_cases = [
+            (
+                "A word, ranked by the field it's in",
+                "redis",
+                vec!["doc:1", "doc:2"],
+            ),
+            ("In any case", "REDIS", vec!["doc:1", "doc:2"]),
+            ("All words must match", "redis fast", vec!["doc:2"]),
+            ("A prefix", "search*", vec!["doc:2"]),
+            ("A prefix of several words", "stream*", vec!["doc:1"]),
+            ("In a field", "@body:redis", vec!["doc:2"]),
+            ("Unindexed fields don't count", "filters", vec!["doc:3"]),
+            ("No match", "redis bloom", vec![]),
+            ("Only punctuation", "--", vec![]),
+            ("Everything", "*", vec!["doc:1", "doc:2", "doc:3"]),
+        ];
+        for (name, query, expected) in test_cases {
+            assert_eq!(search(&index, query), expected, "{}", name);
+        }
+
+        let unknown = Query::parse("@missing:redis", &[]).unwrap();
+        assert_eq!(
+            index.search(&unknown),
+            Err("Unknown field 'missing'".to_string())
+        );
+        assert_eq!(Query::parse("@:redis", &[]), Err(syntax_error()));
+
+        index.insert("doc:1", &hash(&[("title", "Lists")]));
+        assert_eq!(search(&index, "redis"), ["doc:2"]);
+        index.remove("doc:2");
+        assert!(search(&index, "redis").is_empty());
+        assert_eq!(index.len(), 2);
+        assert!(!index.postings.contains_key("hashes"));
+        assert!(index.covers("doc:9"));
+        assert!(!index.covers("user:1"));
+    }
+
+    #[test]
+    fn test_vector_search() {
+        let field = |name: &str, metric| {
+            SchemaField::Vector(VectorField {
+                name: name.to_string(),
+                algorithm: VectorAlgorithm::Hnsw,
+                dim: 2,
+                metric,
+            })
+        };
+        let mut index = SearchIndex::new(
+            Vec::new(),
+            vec![
+                SchemaField::Text(TextField {
+                    name: "tag".to_string(),
+                    weight: 1.0,
+                }),
+                field("l2", DistanceMetric::L2),
+                field("cos", DistanceMetric::Cosine),
+            ],
+        );
+        index.insert("a", &hash(&[("tag", "red"), ("l2", "1,0"), ("cos", "1,0")]));
+        index.insert("b", &hash(&[("tag", "red"), ("l2", "3,0"), ("cos", "0,2")]));
+        index.insert(
+            "c",
+            &hash(&[("tag", "blue"), ("l2", "0,1"), ("cos", "5,5")]),
+        );
+        index.insert("d", &hash(&[("l2", "1,2,3"), ("cos", "one,two")]));
+
+        let test_cases = [
+            ("By L2", "*=>[KNN 3 @l2 $v]", vec!["a", "c", "b"]),
+            ("The k nearest", "*=>[KNN 1 @l2 $v]", vec!["a"]),
+            ("By cosine", "*=>[KNN 3 @cos $v]", vec!["a", "c", "b"]),
+            ("Filtered", "(red)=>[KNN 3 @cos $v]", vec!["a", "b"]),
+            (
+                "Filtered by a field",
+                "@tag:blue=>[KNN 3 @l2 $v]",
+                vec!["c"],
+            ),
+            ("Literal", "*=>[KNN 1 @l2 0,1]", vec!["c"]),
+        ];
+        for (name, query, expected) in test_cases {
+            assert_eq!(search(&index, query), expected, "{}", name);
+        }
+
+        let query = Query::parse("*=>[KNN 3 @l2 $v AS dist]", &[("v".into(), "2,0".into())]);
+        let query = query.unwrap();
+        assert_eq!(query.score_field(), Some("dist"));
+        assert_eq!(
+            index.search(&query),
+            Ok(vec![("a", 1.0), ("b", 1.0), ("c", 5.0)])
+        );
+        let query = Query::parse("*=>[KNN 2 @cos $v]", &[("v".into(), "1,1".into())]).unwrap();
+        assert_eq!(query.score_field(), Some("__cos_score"));
+        let (key, distance) = index.search(&query).unwrap()[0];
+        assert_eq!(key, "c");
+        assert!(distance.abs() < 1e-9);
+
+        let error = |query, params: &[(String, String)]| {
+            Query::parse(query, params).and_then(|query| index.search(&query).map(|_| ()))
+        };
+        assert_eq!(
+            error("*=>[KNN 1 @l2 $missing]", &[]),
+            Err("No such parameter `missing`".to_string())
+        );
+        assert_eq!(
+            error("*=>[KNN 1 @tag 1,0]", &[]),
+            Err("'tag' is not a vector field".to_string())
+        );
+        assert_eq!(
+            error("*=>[KNN 1 @l2 1,0,0]", &[]),
+            Err("query vector has 3 dimensions, expected 2".to_string())
+        );
+        assert_eq!(error("*=>[KNN 1 l2 1,0]", &[]), Err(syntax_error()));
+
+        index.remove("a");
+        assert_eq!(search(&index, "*=>[KNN 1 @l2 $v]"), ["c"]);
+    }
+}
@@ -0,0 +1,902 @@
+//! The TCP server: accepts connections on the configured addresses and runs each one's
+//! commands through a shared `CommandHandler`, one thread per connection. With `io-threads`
+//! above 1, a pool of that many threads does the connections' socket I/O instead.
+
+use std::collections::HashMap;
+use std::io::{ErrorKind, Read, Write};
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr, TcpListener, TcpStream};
+use std::os::unix::io::AsRawFd;
+use std::os::unix::net::UnixStream;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex, RwLock};
+use std::thread::JoinHandle;
+use std::time::{Duration, Instant};
+use std::{process, slice, thread};
+
+use crate::config::Config;
+use crate::crash;
+use crate::daemon;
+use crate::embedded::EmbeddedClient;
+use crate::handler::{
+    ClientId, ClientOutput, CommandHandler, CustomCommand, RdbLoader, StatsSnapshot, Storage,
+    TieredStorage,
+};
+use crate::io_threads::{IoThreads, PooledConnection};
+use crate::metrics;
+use crate::resp::{Resp, RespData};
+use crate::telemetry;
+use signal_hook::consts::{SIGINT, SIGTERM};
+use signal_hook::iterator::Signals;
+use socket2::{Domain, SockRef, Socket, TcpKeepalive, Type};
+
+/// How often a command held back by CLIENT PAUSE checks whether it may run.
+const PAUSE_POLL_INTERVAL: Duration = Duration::from_millis(10);
+
+pub fn run(config: Arc<RwLock<Config>>) -> std::io::Result<()> {
+    let server = start(config)?;
+    spawn_shutdown_handler(Arc::clone(&server.handler))?;
+    server.wait_until_loaded();
+    daemon::notify_ready();
+    server.wait();
+    Ok(())
+}
+
+/// A server started by `start` or a `ServerBuilder`, accepting connections on background
+/// threads.
+pub struct Server {
+    pub handler: Arc<Mutex<CommandHandler>>,
+    addrs: Vec<SocketAddr>,
+    metrics_addrs: Vec<SocketAddr>,
+    stopping: Arc<AtomicBool>,
+    /// How many connection threads are still running.
+    connections: Arc<AtomicUsize>,
+    accept_threads: Vec<JoinHandle<()>>,
+}
+
+impl Server {
+    pub fn builder() -> ServerBuilder {
+        ServerBuilder::default()
+    }
+
+    /// The address of the first listener.
+    pub fn local_addr(&self) -> SocketAddr {
+        self.addrs[0]
+    }
+
+    /// The addresses of every listener, in the order of `bind`.
+    pub fn local_addrs(&self) -> &[SocketAddr] {
+        &self.addrs
+    }
+
+    /// Where metrics are served, if `metrics-port` is set.
+    pub fn metrics_addr(&self) -> Option<SocketAddr> {
+        self.metrics_addrs.first().copied()
+    }
+
+    pub fn stats(&self) -> StatsSnapshot {
+        self.handler.lock().unwrap().stats_snapshot()
+    }
+
+    /// A client running commands in this process, without going through a socket.
+    pub fn client(&self) -> EmbeddedClient {
+        EmbeddedClient::new(Arc::clone(&self.handler))
+    }
+
+    /// Stops accepting connections and running the cron, without waiting. Open connections
+    /// are left alone; `shutdown` closes them.
+    pub fn stop(&self) {
+        if self.stopping.swap(true, Ordering::Relaxed) {
+            return;
+        }
+        // The accept loops only see the flag once they accept another connection.
+        for addr in self.addrs.iter().chain(&self.metrics_addrs) {
+            let ip = match addr.ip() {
+                IpAddr::V4(ip) if ip.is_unspecified() => IpAddr::V4(Ipv4Addr::LOCALHOST),
+                IpAddr::V6(ip) if ip.is_unspecified() => IpAddr::V6(Ipv6Addr::LOCALHOST),
+                ip => ip,
+            };
+            let _ = TcpStream::connect(SocketAddr::new(ip, addr.port()));
+        }
+    }
+
+    /// Stops the server and waits for it to drain: the command in flight completes, then every
+    /// connection is closed and its thread has exited. Unlike SHUTDOWN the process keeps
+    /// running and nothing is saved.
+    pub fn shutdown(self) {
+        self.stop();
+        let handler = Arc::clone(&self.handler);
+        let connections = Arc::clone(&self.connections);
+        self.wait();
+        handler.lock().unwrap().disconnect_clients();
+        while connections.load(Ordering::Relaxed) > 0 {
+            thread::sleep(PAUSE_POLL_INTERVAL);
+        }
+    }
+
+    /// Blocks until the RDB file has been loaded. Connections are accepted before that, but
+    /// most commands are refused with -LOADING.
+    pub fn wait_until_loaded(&self) {
+        while self.handler.lock().unwrap().is_loading() {
+            thread::sleep(PAUSE_POLL_INTERVAL);
+        }
+    }
+
+    /// Blocks until every listener has stopped accepting connections.
+    pub fn wait(self) {
+        for thread in self.accept_threads {
+            let _ = thread.join();
+        }
+    }
+}
+
+/// Configures and starts a server within the process, e.g. as a test fixture:
+///
+/// ```no_run
+/// use redis_from_scratch::server::Server;
+///
+/// let server = Server::builder().maxclients(16).spawn().unwrap();
+/// let addr = server.local_addr();
+/// // ... connect to addr ...
+/// server.shutdown();
+/// ```
+///
+/// Unlike a `Config` read from a file, the server only listens on 127.0.0.1 unless told
+/// otherwise, picks a free port, and doesn't snapshot.
+pub struct ServerBuilder {
+    config: Config,
+    /// Whether `bind` is still the default, to be replaced rather than added to.
+    default_bind: bool,
+    commands: Vec<CustomCommand>,
+    storage: Option<Box<dyn Storage>>,
+}
+
+impl Default for ServerBuilder {
+    fn default() -> Self {
+        Self {
+            config: Config {
+                bind: vec!["127.0.0.1".to_string()],
+                port: 0,
+                save: Vec::new(),
+                ..Config::default()
+            },
+            default_bind: true,
+            commands: Vec::new(),
+            storage: None,
+        }
+    }
+}
+
+impl ServerBuilder {
+    /// Listens on `addr`, given as in the `bind` directive, e.g. "::1" or "*". May be called
+    /// more than once.
+    pub fn bind(mut self, addr: &str) -> Self {
+        if self.default_bind {
+            self.config.bind.clear();
+            self.default_bind = false;
+        }
+        self.config.bind.push(addr.to_string());
+        self
+    }
+
+    /// The port to listen on, or 0 for one the OS picks.
+    pub fn port(mut self, port: u16) -> Self {
+        self.config.port = port;
+        self
+    }
+
+    pub fn maxclients(mut self, maxclients: usize) -> Self {
+        self.config.maxclients = maxclients;
+        self
+    }
+
+    /// Where the RDB file is loaded from and saved to.
+    pub fn dir(mut self, dir: impl Into<PathBuf>) -> Self {
+        self.config.dir = dir.into();
+        self
+    }
+
+    /// Changes any other setting.
+    pub fn config(mut self, configure: impl FnOnce(&mut Config)) -> Self {
+        configure(&mut self.config);
+        self
+    }
+
+    /// Adds a command on top of the built-in ones.
+    pub fn command(mut self, command: CustomCommand) -> Self {
+        self.commands.push(command);
+        self
+    }
+
+    /// Keeps the keyspace in `storage` instead of in memory. An RDB file in `dir` is still
+    /// loaded into it at startup, replacing what it held.
+    pub fn storage(mut self, storage: Box<dyn Storage>) -> Self {
+        self.storage = Some(storage);
+        self
+    }
+
+    pub fn spawn(mut self) -> std::io::Result<Server> {
+        let listeners = if self.config.port == 0 {
+            bind_ephemeral(&mut self.config)?
+        } else {
+            bind_listeners(&self.config, self.config.port)?
+        };
+        start_with(
+            Arc::new(RwLock::new(self.config)),
+            listeners,
+            self.commands,
+            self.storage,
+        )
+    }
+}
+
+/// Binds the configured addresses and starts serving while the RDB file loads in the
+/// background. The accept loops and the cron run until the server is stopped.
+pub fn start(config: Arc<RwLock<Config>>) -> std::io::Result<Server> {
+    let listeners = {
+        let config = config.read().unwrap();
+        bind_listeners(&config, config.port)?
+    };
+    start_with(config, listeners, Vec::new(), None)
+}
+
+/// Starts serving on `listeners`, already bound to the configured addresses.
+fn start_with(
+    config: Arc<RwLock<Config>>,
+    listeners: Vec<TcpListener>,
+    commands: Vec<CustomCommand>,
+    storage: Option<Box<dyn Storage>>,
+) -> std::io::Result<Server> {
+    let metrics_listeners = {
+        let config = config.read().unwrap();
+        bind_listeners(&config, config.metrics_port)?
+    };
+    if listeners.is_empty() {
+        return Err(std::io::Error::other(
+            "Configured to not listen anywhere, exiting.",
+        ));
+    }
+    let addrs = local_addrs(&listeners)?;
+    let metrics_addrs = local_addrs(&metrics_listeners)?;
+
+    let tiered_storage = {
+        let config = config.read().unwrap();
+        (config.tiered_storage_memory > 0)
+            .then(|| (config.tiered_storage_path(), config.tiered_storage_memory))
+    };
+    let mut handler = match (storage, tiered_storage) {
+        (Some(storage), _) => CommandHandler::with_storage(storage, Arc::clone(&config)),
+        (None, Some((path, memory))) => {
+            let storage = TieredStorage::open(&path, memory).map_err(|e| {
+                std::io::Error::other(format!("Can't open {}: {e}", path.display()))
+            })?;
+            CommandHandler::with_storage(Box::new(storage), Arc::clone(&config))
+        }
+        (None, None) => CommandHandler::new(HashMap::new(), Arc::clone(&config)),
+    };
+    for command in commands {
+        handler
+            .register_command(command)
+            .map_err(|e| std::io::Error::new(ErrorKind::InvalidInput, e))?;
+    }
+    handler.load_acl_file().map_err(|e| {
+        std::io::Error::other(format!("Aborting startup because of ACL errors: {e}"))
+    })?;
+    let loader = handler
+        .start_loading()
+        .map_err(|e| std::io::Error::other(format!("Failed loading the RDB file: {e}")))?;
+    handler
+        .open_audit_log()
+        .map_err(|e| std::io::Error::other(format!("Can't open the audit log: {e}")))?;
+    let handler = Arc::new(Mutex::new(handler));
+    let stopping = Arc::new(AtomicBool::new(false));
+    let connections = Arc::new(AtomicUsize::new(0));
+    let io_threads = match config.read().unwrap().io_threads {
+        1 => None,
+        count => Some(Arc::new(IoThreads::spawn(count, Arc::clone(&config))?)),
+    };
+    spawn_server_cron(Arc::clone(&handler), Arc::clone(&stopping));
+
+    let mut accept_threads: Vec<_> = listeners
+        .into_iter()
+        .map(|listener| {
+            let handler = Arc::clone(&handler);
+            let config = Arc::clone(&config);
+            let stopping = Arc::clone(&stopping);
+            let connections = Arc::clone(&connections);
+            let io_threads = io_threads.clone();
+            thread::spawn(move || {
+                accept_loop(
+                    listener,
+                    handler,
+                    config,
+                    &stopping,
+                    connections,
+                    io_threads,
+                )
+            })
+        })
+        .collect();
+    accept_threads.extend(metrics_listeners.into_iter().map(|listener| {
+        let handler = Arc::clone(&handler);
+        let stopping = Arc::clone(&stopping);
+        thread::spawn(move || metrics::accept_loop(listener, &handler, &stopping))
+    }));
+    if let Some(loader) = loader {
+        spawn_loader(loader, Arc::clone(&handler));
+    }
+    Ok(Server {
+        handler,
+        addrs,
+        metrics_addrs,
+        stopping,
+        connections,
+        accept_threads,
+    })
+}
+
+fn local_addrs(listeners: &[TcpListener]) -> std::io::Result<Vec<SocketAddr>> {
+    listeners.iter().map(TcpListener::local_addr).collect()
+}
+
+/// Creates a socket listening on `port` for every bind address. Returns no listeners when the
+/// port is 0, which disables it.
+fn bind_listeners(config: &Config, port: u16) -> std::io::Result<Vec<TcpListener>> {
+    if port == 0 {
+        return Ok(vec![]);
+    }
+    bind_addresses(config, &config.bind, port)
+}
+
+/// Like `bind_listeners`, on a port the OS picks when binding the first address, which
+/// `config.port` is then set to. The listeners are kept, so no one else can take the port.
+fn bind_ephemeral(config: &mut Config) -> std::io::Result<Vec<TcpListener>> {
+    let mut listeners: Vec<TcpListener> = Vec::new();
+    for entry in &config.bind {
+        let port = match listeners.first() {
+            Some(listener) => listener.local_addr()?.port(),
+            None => 0,
+        };
+        listeners.extend(bind_addresses(config, slice::from_ref(entry), port)?);
+    }
+    if let Some(listener) = listeners.first() {
+        config.port = listener.local_addr()?.port();
+    }
+    Ok(listeners)
+}
+
+/// Creates a socket listening on `port` for each of the `entries` of `bind`.
+fn bind_addresses(
+    config: &Config,
+    entries: &[String],
+    port: u16,
+) -> std::io::Result<Vec<TcpListener>> {
+    let mut listeners = Vec::new();
+    for entry in entries {
+        let (ip, optional) = parse_bind_address(entry).ok_or_else(|| {
+            std::io::Error::new(
+                ErrorKind::InvalidInput,
+                format!("Invalid bind address '{entry}'"),
+            )
+        })?;
+        let addr = SocketAddr::new(ip, port);
+        match bind_listener(addr, config) {
+            Ok(listener) => {
+                log_notice!("Listening on {}", listener.local_addr().unwrap_or(addr));
+                listeners.push(listener);
+            }
+            // Skip optional addresses the host doesn't have, e.g. IPv6 on an IPv4-only machine.
+            Err(e)
+                if optional
+                    && matches!(
+                        e.raw_os_error(),
+                        Some(libc::EADDRNOTAVAIL | libc::EAFNOSUPPORT | libc::EPROTONOSUPPORT)
+                    ) =>
+            {
+                log_warning!("Skipping optional bind address {addr}: {e}");
+            }
+            Err(e) => {
+                return Err(std::io::Error::new(
+                    e.kind(),
+                    format!("Could not create server TCP listening socket {addr}: {e}"),
+                ))
+            }
+        }
+    }
+    Ok(listeners)
+}
+
+/// Parses a `bind` entry into an address and whether it is optional (prefixed with `-`).
+fn parse_bind_address(entry: &str) -> Option<(IpAddr, bool)> {
+    let (entry, optional) = match entry.strip_prefix('-') {
+        Some(entry) => (entry, true),
+        None => (entry, false),
+    };
+    let ip = match entry {
+        "*" => IpAddr::V4(Ipv4Addr::UNSPECIFIED),
+        "::*" => IpAddr::V6(Ipv6Addr::UNSPECIFIED),
+        entry => entry
+            .trim_start_matches('[')
+            .trim_end_matches(']')
+            .parse()
+            .ok()?,
+    };
+    Some((ip, optional))
+}
+
+fn bind_listener(addr: SocketAddr, config: &Config) -> std::io::Result<TcpListener> {
+    let socket = Socket::new(Domain::for_address(addr), Type::STREAM, None)?;
+    socket.set_reuse_address(true)?;
+    socket.set_reuse_port(config.tcp_reuseport)?;
+    if addr.is_ipv6() {
+        // Otherwise `::` would also claim the IPv4 port and clash with a `*` listener.
+        socket.set_only_v6(true)?;
+    }
+    socket.bind(&addr.into())?;
+    socket.listen(config.tcp_backlog)?;
+    Ok(socket.into())
+}
+
+/// Applies the configured TCP options to an accepted client connection.
+fn configure_client_socket(stream: &TcpStream, config: &Config) -> std::io::Result<()> {
+    stream.set_nodelay(config.tcp_nodelay)?;
+    let socket = SockRef::from(stream);
+    if config.tcp_keepalive == 0 {
+        return socket.set_keepalive(false);
+    }
+    // Like Redis, probe every third of the keepalive period and give up after three probes.
+    let time = Duration::from_secs(config.tcp_keepalive);
+    let keepalive = TcpKeepalive::new()
+        .with_time(time)
+        .with_interval((time / 3).max(Duration::from_secs(1)))
+        .with_retries(3);
+    socket.set_tcp_keepalive(&keepalive)
+}
+
+fn accept_loop(
+    listener: TcpListener,
+    handler: Arc<Mutex<CommandHandler>>,
+    config: Arc<RwLock<Config>>,
+    stopping: &AtomicBool,
+    connections: Arc<AtomicUsize>,
+    io_threads: Option<Arc<IoThreads>>,
+) {
+    for stream in listener.incoming() {
+        if stopping.load(Ordering::Relaxed) {
+            return;
+        }
+        let stream = match stream {
+            Ok(stream) => stream,
+            Err(e) => {
+                log_warning!("Failed to accept connection: {e}");
+                continue;
+            }
+        };
+        if !handler.lock().unwrap().accepting_clients() {
+            let _ = (&stream).write_all(b"-ERR max number of clients reached\r\n");
+            continue;
+        }
+        if let Err(e) = configure_client_socket(&stream, &config.read().unwrap()) {
+            log_warning!("Failed to configure client socket: {e}");
+        }
+        let (Ok(addr), Ok(laddr)) = (stream.peer_addr(), stream.local_addr()) else {
+            continue;
+        };
+        log_verbose!("Accepted {addr}");
+        // Registered before the thread starts, so once the loop exits every connection it
+        // accepted can be found and killed.
+        let id = handler.lock().unwrap().register_client(
+            addr,
+            laddr,
+            stream.as_raw_fd(),
+            stream.try_clone().ok(),
+        );
+        crash::client_connected();
+        connections.fetch_add(1, Ordering::Relaxed);
+        let handler = Arc::clone(&handler);
+        let config = Arc::clone(&config);
+        let connections = Arc::clone(&connections);
+        let io_threads = io_threads.clone();
+        thread::spawn(move || {
+            let _span = telemetry::connection_span(id, addr).entered();
+            if let Err(e) = serve_client(id, &stream, &handler, &config, io_threads.as_deref()) {
+                log_verbose!("Connection error: {e}");
+            }
+            handler.lock().unwrap().unregister_client(id);
+            crash::client_disconnected();
+            connections.fetch_sub(1, Ordering::Relaxed);
+        });
+    }
+}
+
+/// Loads the RDB file in the background, exiting like Redis if it can't be read.
+fn spawn_loader(loader: RdbLoader, handler: Arc<Mutex<CommandHandler>>) {
+    thread::spawn(move || {
+        let start = Instant::now();
+        match loader.run(&handler) {
+            Ok(keys) => log_notice!(
+                "DB loaded from disk: {keys} keys, {:.3} seconds",
+                start.elapsed().as_secs_f64()
+            ),
+            Err(e) => {
+                log_warning!("Failed loading the RDB file: {e}");
+                process::exit(1);
+            }
+        }
+    });
+}
+
+/// Runs the handler's periodic tasks `hz` times a second until `stopping` is set.
+fn spawn_server_cron(handler: Arc<Mutex<CommandHandler>>, stopping: Arc<AtomicBool>) {
+    thread::spawn(move || {
+        while !stopping.load(Ordering::Relaxed) {
+            let interval = handler.lock().unwrap().cron_interval();
+            thread::sleep(interval);
+            handler.lock().unwrap().server_cron();
+        }
+    });
+}
+
+/// Shuts the server down gracefully on SIGTERM or SIGINT. Taking the handler lock waits for
+/// the command in flight to finish, and the process exits without releasing it so no further
+/// command runs. If the final save fails the server keeps running, like Redis does.
+fn spawn_shutdown_handler(handler: Arc<Mutex<CommandHandler>>) -> std::io::Result<()> {
+    let mut signals = Signals::new([SIGTERM, SIGINT])?;
+    thread::spawn(move || {
+        for signal in signals.forever() {
+            let name = if signal == SIGTERM {
+                "SIGTERM"
+            } else {
+                "SIGINT"
+            };
+            log_warning!("Received {name} scheduling shutdown...");
+            daemon::notify_stopping();
+            let mut handler = handler.lock().unwrap();
+            if handler.prepare_shutdown(None, false) {
+                exit_after_shutdown();
+            }
+            log_warning!("{name} received but errors trying to shut down the server, check the logs for more information");
+        }
+    });
+    Ok(())
+}
+
+fn exit_after_shutdown() -> ! {
+    telemetry::shutdown();
+    daemon::remove_pidfile();
+    log_warning!("Redis is now ready to exit, bye bye...");
+    process::exit(0)
+}
+
+/// Reads and executes commands from a single connection until the peer hangs up.
+fn serve_client(
+    id: ClientId,
+    stream: &TcpStream,
+    handler: &Mutex<CommandHandler>,
+    config: &RwLock<Config>,
+    io_threads: Option<&IoThreads>,
+) -> std::io::Result<()> {
+    let mut connection = match io_threads {
+        Some(io_threads) => Connection::Pooled(io_threads.add(stream)?),
+        None => Connection::inline(stream)?,
+    };
+    // The handler queues messages like key invalidations there too, in between replies.
+    handler
+        .lock()
+        .unwrap()
+        .set_client_output(id, connection.output().clone());
+
+    loop {
+        let data = match connection.read(config) {
+            Ok(data) => data,
+            Err(e) if e.kind() == ErrorKind::UnexpectedEof => return Ok(()),
+            // Like Redis, report the protocol error before dropping the connection.
+            Err(e) if e.kind() == ErrorKind::InvalidData => {
+                let error = RespData::Error(format!("Protocol error: {e}"));
+                connection.output().reply(&error);
+                connection.flush()?;
+                return Err(e);
+            }
+            Err(e) => return Err(e),
+        };
+
+        log_debug!("Parsed data: {:?}", data);
+        crash::record_command(&data);
+
+        let (response, streamed) = run_command(handler, &data, |handler| {
+            let response = handler.handle_client(id, &data);
+            (response, handler.take_streamed_reply(id))
+        });
+        log_debug!("Response: {:?}", response);
+        let closing = handler.lock().unwrap().client_closing(id);
+        if let Some(response) = response {
+            connection.output().reply(&response);
+        }
+        if let Some(streamed) = streamed {
+            connection.output().append(streamed);
+        }
+        if closing || !connection.pending() {
+            connection.flush()?;
+        }
+        if closing {
+            return Ok(());
+        }
+    }
+}
+
+/// A connection as the thread running its commands sees it.
+enum Connection<'a> {
+    /// Read, parsed and written on the connection's thread, in between commands.
+    Inline {
+        stream: &'a TcpStream,
+        resp: Resp<&'a TcpStream>,
+        output: ClientOutput,
+        /// Written to when a message is pushed to the client.
+        woken: UnixStream,
+    },
+    /// Read, parsed and written by a thread of the `io-threads` pool.
+    Pooled(PooledConnection),
+}
+
+impl<'a> Connection<'a> {
+    fn inline(stream: &'a TcpStream) -> std::io::Result<Self> {
+        let (woken, waker) = UnixStream::pair()?;
+        woken.set_nonblocking(true)?;
+        waker.set_nonblocking(true)?;
+        // With the pipe full the connection is already due to wake.
+        let output = ClientOutput::new(move || {
+            let _ = (&waker).write(&[0]);
+        });
+        Ok(Connection::Inline {
+            stream,
+            resp: Resp::new(stream),
+            output,
+            woken,
+        })
+    }
+
+    /// Where replies and pushed messages are queued.
+    fn output(&self) -> &ClientOutput {
+        match self {
+            Connection::Inline { output, .. } => output,
+            Connection::Pooled(connection) => connection.output(),
+        }
+    }
+
+    /// Waits for the next command, writing out messages pushed to the client meanwhile.
+    fn read(&mut self, config: &RwLock<Config>) -> std::io::Result<RespData> {
+        match self {
+            Connection::Inline {
+                stream,
+                resp,
+                output,
+                woken,
+            } => {
+                while !resp.has_buffered() && wait_for_input(stream, woken)? {
+                    stream.write_all(&output.take())?;
+                }
+                resp.set_max_bulk_len(config.read().unwrap().proto_max_bulk_len);
+                let data = resp.read()?;
+                log_debug!("Raw data: {:?}", resp.raw_data);
+                Ok(data)
+            }
+            Connection::Pooled(connection) => connection.read(),
+        }
+    }
+
+    /// Whether another command has already been parsed, so the reply to this one can wait to
+    /// be written with the next.
+    fn pending(&self) -> bool {
+        match self {
+            Connection::Inline { .. } => false,
+            Connection::Pooled(connection) => connection.pending(),
+        }
+    }
+
+    /// Writes out what is queued, or has the pool write it.
+    fn flush(&mut self) -> std::io::Result<()> {
+        match self {
+            Connection::Inline { stream, output, .. } => stream.write_all(&output.take()),
+            Connection::Pooled(connection) => {
+                connection.output().wake();
+                Ok(())
+            }
+        }
+    }
+}
+
+/// Blocks until `stream` has input or was closed, returning false, or until `woken` is
+/// written to, returning true.
+fn wait_for_input(stream: &TcpStream, woken: &UnixStream) -> std::io::Result<bool> {
+    let mut fds = [stream.as_raw_fd(), woken.as_raw_fd()].map(|fd| libc::pollfd {
+        fd,
+        events: libc::POLLIN,
+        revents: 0,
+    });
+    // SAFETY: poll only writes to the `revents` of the descriptors it is given.
+    while unsafe { libc::poll(fds.as_mut_ptr(), fds.len() as libc::nfds_t, -1) } < 0 {
+        let e = std::io::Error::last_os_error();
+        if e.kind() != ErrorKind::Interrupted {
+            return Err(e);
+        }
+    }
+    if fds[1].revents == 0 {
+        return Ok(false);
+    }
+    // Empty the pipe so the next wait blocks again.
+    let mut buf = [0; 64];
+    while matches!((&*woken).read(&mut buf), Ok(n) if n > 0) {}
+    Ok(true)
+}
+
+/// Runs `command` through `run` under the handler lock, once CLIENT PAUSE lets it. Exits the
+/// process if the command was a SHUTDOWN.
+pub(crate) fn run_command<T>(
+    handler: &Mutex<CommandHandler>,
+    command: &RespData,
+    run: impl FnOnce(&mut CommandHandler) -> T,
+) -> T {
+    let mut handler = loop {
+        let mut handler = handler.lock().unwrap();
+        match handler.pause_remaining(command) {
+            Some(remaining) => {
+                drop(handler);
+                thread::sleep(remaining.min(PAUSE_POLL_INTERVAL));
+            }
+            None => break handler,
+        }
+    };
+    let result = run(&mut handler);
+    // Exit while still holding the lock so no command runs after SHUTDOWN.
+    if handler.shutdown_requested() {
+        daemon::notify_stopping();
+        exit_after_shutdown();
+    }
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Read;
+
+    #[test]
+    fn test_parse_bind_address() {
+        let test_cases = [
+            ("All IPv4 interfaces", "*", Some(("0.0.0.0", false))),
+            ("All IPv6 interfaces, optional", "-::*", Some(("::", true))),
+            ("IPv4 literal", "127.0.0.1", Some(("127.0.0.1", false))),
+            ("IPv6 literal", "::1", Some(("::1", false))),
+            ("Bracketed IPv6 literal", "[::1]", Some(("::1", false))),
+            ("Not an address", "localhost", None),
+        ];
+
+        for (name, input, expected) in test_cases {
+            let expected = expected.map(|(ip, optional)| (ip.parse().unwrap(), optional));
+            assert_eq!(parse_bind_address(input), expected, "{}", name);
+        }
+    }
+
+    #[test]
+    fn test_bind_listeners() {
+        let config = |bind: &str| Config {
+            bind: vec![bind.to_string()],
+            ..Config::default()
+        };
+        assert!(bind_listeners(&config("*"), 0).unwrap().is_empty());
+
+        let listeners = bind_listeners(&config("127.0.0.1"), free_port()).unwrap();
+        let port = listeners[0].local_addr().unwrap().port();
+        let err = bind_listeners(&config("127.0.0.1"), port).unwrap_err();
+        assert!(err
+            .to_string()
+            .starts_with("Could not create server TCP listening socket 127.0.0.1:"));
+        assert!(bind_listeners(&config("nonsense"), port).is_err());
+    }
+
+    #[test]
+    fn test_bind_ephemeral() {
+        let mut config = Config {
+            bind: vec!["127.0.0.1".to_string(), "-::1".to_string()],
+            port: 0,
+            ..Config::default()
+        };
+        let listeners = bind_ephemeral(&mut config).unwrap();
+        assert_ne!(config.port, 0);
+        for listener in &listeners {
+            assert_eq!(listener.local_addr().unwrap().port(), config.port);
+        }
+        // The port stays taken for as long as the listeners are kept.
+        assert!(bind_listeners(&config, config.port).is_err());
+    }
+
+    #[test]
+    fn test_configure_client_socket() {
+        let config = Config {
+            bind: vec!["127.0.0.1".to_string()],
+            port: free_port(),
+            tcp_keepalive: 60,
+            ..Config::default()
+        };
+        let listener = bind_listeners(&config, config.port).unwrap().remove(0);
+        let client = TcpStream::connect(listener.local_addr().unwrap()).unwrap();
+        let (stream, _) = listener.accept().unwrap();
+
+        configure_client_socket(&stream, &config).unwrap();
+        let socket = SockRef::from(&stream);
+        assert!(stream.nodelay().unwrap());
+        assert!(socket.keepalive().unwrap());
+        assert_eq!(
+            socket.tcp_keepalive_time().unwrap(),
+            Duration::from_secs(60)
+        );
+        assert_eq!(
+            socket.tcp_keepalive_interval().unwrap(),
+            Duration::from_secs(20)
+        );
+        drop(client);
+    }
+
+    #[test]
+    fn test_builder() {
+        let server = Server::builder()
+            .maxclients(1)
+            .config(|config| config.requirepass = Some("secret".to_string()))
+            .command(CustomCommand::new(
+                "double",
+                2,
+                |keyspace, args| match args[0].parse::<i64>() {
+                    Ok(n) => RespData::Integer(n * 2),
+                    Err(_) => keyspace.call(&["INCR", "errors"]),
+                },
+            ))
+            .spawn()
+            .unwrap();
+        let addr = server.local_addr();
+        assert_eq!(addr.ip(), IpAddr::V4(Ipv4Addr::LOCALHOST));
+        assert_eq!(server.metrics_addr(), None);
+
+        let mut client = TcpStream::connect(addr).unwrap();
+        client
+            .write_all(b"*2\r\n$4\r\nAUTH\r\n$6\r\nsecret\r\n")
+            .unwrap();
+        let mut reply = [0; 5];
+        client.read_exact(&mut reply).unwrap();
+        assert_eq!(&reply, b"+OK\r\n");
+
+        let stats = server.stats();
+        assert_eq!(stats.connected_clients, 1);
+        assert_eq!(stats.total_connections_received, 1);
+        assert_eq!(stats.total_commands_processed, 1);
+        assert_eq!(
+            server.client().command(&["CONFIG", "GET", "maxclients"]),
+            RespData::Array(vec![
+                RespData::BulkString("maxclients".to_string()),
+                RespData::BulkString("1".to_string()),
+            ])
+        );
+        assert_eq!(
+            server.client().command(&["DOUBLE", "21"]),
+            RespData::Integer(42)
+        );
+        assert_eq!(
+            server.client().command(&["double", "x"]),
+            RespData::Integer(1)
+        );
+
+        // The connection is closed and its thread has exited by the time shutdown returns.
+        let connections = Arc::clone(&server.connections);
+        server.shutdown();
+        assert_eq!(connections.load(Ordering::Relaxed), 0);
+        assert_eq!(client.read(&mut [0; 1]).unwrap(), 0);
+        assert!(TcpStream::connect(addr).is_err());
+    }
+
+    fn free_port() -> u16 {
+        TcpListener::bind("127.0.0.1:0")
+            .unwrap()
+            .local_addr()
+            .unwrap()
+            .port()
+    }
+}
@@ -0,0 +1,340 @@
+//! Probabilistic frequency counting for the CMS.* and TOPK.* commands, as in RedisBloom: a
+//! Count-Min Sketch estimating how often each item was seen, and a HeavyKeeper Top-K
+//! tracking the most frequent items. Both take a fixed amount of memory however many
+//! distinct items they see.
+
+use crate::bloom::{murmur64a, Input, MAX_TABLE_BYTES};
+
+/// The column of `item` in row `row` of a `width` wide table.
+fn column(item: &[u8], row: usize, width: usize) -> usize {
+    (murmur64a(item, row as u64) % width as u64) as usize
+}
+
+/// The number of cells in a `width` by `depth` table of `cell_size` byte cells, if it isn't
+/// too large.
+fn table_size(width: u32, depth: u32, cell_size: u64) -> Result<usize, String> {
+    let cells = width as u64 * depth as u64;
+    match cells.checked_mul(cell_size) {
+        Some(bytes) if cells > 0 && bytes <= MAX_TABLE_BYTES => Ok(cells as usize),
+        _ => Err("invalid dimensions".to_string()),
+    }
+}
+
+/// A `depth` by `width` table of counters. An item's count is estimated by the smallest of
+/// its counters, one per row, which may overcount from collisions but never undercounts.
+#[derive(Debug, Clone, PartialEq)]
+pub struct CountMinSketch {
+    width: u32,
+    depth: u32,
+    counters: Vec<u64>,
+}
+
+impl CountMinSketch {
+    pub fn new(width: u32, depth: u32) -> Result<Self, String> {
+        Ok(CountMinSketch {
+            width,
+            depth,
+            counters: vec![0; table_size(width, depth, 8)?],
+        })
+    }
+
+    /// The counters of `item`, one per row.
+    fn cells(&self, item: &[u8]) -> Vec<usize> {
+        let width = self.width as usize;
+        (0..self.depth as usize)
+            .map(|row| row * width + column(item, row, width))
+            .collect()
+    }
+
+    /// Adds `increment` to the count of `item`, returning its new estimate.
+    pub fn increment(&mut self, item: &[u8], increment: u64) -> u64 {
+        let cells = self.cells(item);
+        for &cell in &cells {
+            self.counters[cell] = self.counters[cell].saturating_add(increment);
+        }
+        cells.iter().map(|&cell| self.counters[cell]).min().unwrap()
+    }
+
+    /// The estimated count of `item`.
+    pub fn count(&self, item: &[u8]) -> u64 {
+        self.cells(item)
+            .into_iter()
+            .map(|cell| self.counters[cell])
+            .min()
+            .unwrap()
+    }
+
+    /// How many counters the sketch has, `width` times `depth`.
+    pub fn counters(&self) -> usize {
+        self.counters.len()
+    }
+
+    pub fn allocated_size(&self) -> usize {
+        self.counters.len() * 8
+    }
+
+    /// The sketch in the binary form RDB snapshots store it in.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut out = Vec::with_capacity(8 + self.allocated_size());
+        out.extend(self.width.to_le_bytes());
+        out.extend(self.depth.to_le_bytes());
+        for counter in &self.counters {
+            out.extend(counter.to_le_bytes());
+        }
+        out
+    }
+
+    /// Reads a sketch written by `to_bytes`.
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, String> {
+        let mut input = Input(bytes);
+        let mut sketch = CountMinSketch::new(
+            u32::from_le_bytes(input.take()?),
+            u32::from_le_bytes(input.take()?),
+        )?;
+        for counter in &mut sketch.counters {
+            *counter = u64::from_le_bytes(input.take()?);
+        }
+        if !input.0.is_empty() {
+            return Err("invalid Count-Min Sketch".to_string());
+        }
+        Ok(sketch)
+    }
+}
+
+/// A HeavyKeeper table of fingerprinted counters, and the `k` items with the highest counts
+/// seen so far. A counter taken by another item decays with a probability that shrinks the
+/// larger it is, so frequent items keep their counters while rare ones wash out.
+#[derive(Debug, Clone, PartialEq)]
+pub struct TopK {
+    k: u32,
+    width: u32,
+    depth: u32,
+    decay: f64,
+    buckets: Vec<Bucket>,
+    /// The top items and their counts, in no particular order.
+    top: Vec<(String, u64)>,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+struct Bucket {
+    fingerprint: u64,
+    count: u64,
+}
+
+impl TopK {
+    pub fn new(k: u32, width: u32, depth: u32, decay: f64) -> Result<Self, String> {
+        Ok(TopK {
+            k,
+            width,
+            depth,
+            decay,
+            buckets: vec![Bucket::default(); table_size(width, depth, 16)?],
+            top: Vec::new(),
+        })
+    }
+
+    /// Counts one more occurrence of `item`, returning the item it pushed out of the top `k`,
+    /// if any. `random` gives the random numbers counters decay by.
+    pub fn add(&mut self, item: &str, random: &mut impl FnMut() -> u64) -> Option<String> {
+        let fingerprint = murmur64a(item.as_bytes(), 0xc6a4_a793_5bd1_e995);
+        let position = self.top.iter().position(|(top, _)| top == item);
+        let min_top = match self.top.iter().map(|(_, count)| *count).min() {
+            Some(min) if self.top.len() >= self.k as usize => min,
+            _ => 0,
+        };
+
+        let mut max_count = 0;
+        for row in 0..self.depth as usize {
+            let width = self.width as usize;
+            let bucket = &mut self.buckets[row * width + column(item.as_bytes(), row, width)];
+            if bucket.count == 0 {
+                *bucket = Bucket {
+                    fingerprint,
+                    count: 1,
+                };
+            } else if bucket.fingerprint == fingerprint {
+                // Only items that could make it into the top grow their counters.
+                if position.is_some() || bucket.count <= min_top {
+                    bucket.count += 1;
+                }
+            } else {
+                let chance = self.decay.powf(bucket.count as f64);
+                if (random() as f64 / u64::MAX as f64) < chance {
+                    bucket.count -= 1;
+                    if bucket.count == 0 {
+                        *bucket = Bucket {
+                            fingerprint,
+                            count: 1,
+                        };
+                    }
+                }
+            }
+            if bucket.fingerprint == fingerprint {
+                max_count = max_count.max(bucket.count);
+            }
+        }
+
+        if let Some(position) = position {
+            self.top[position].1 = max_count;
+            return None;
+        }
+        if max_count == 0 {
+            return None;
+        }
+        if self.top.len() < self.k as usize {
+            self.top.push((item.to_string(), max_count));
+            return None;
+        }
+        if max_count <= min_top {
+            return None;
+        }
+        let smallest = self.top.iter().position(|(_, count)| *count == min_top)?;
+        Some(std::mem::replace(&mut self.top[smallest], (item.to_string(), max_count)).0)
+    }
+
+    /// How many items are in the top, at most `k`.
+    pub fn len(&self) -> usize {
+        self.top.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.top.is_empty()
+    }
+
+    /// Whether `item` is currently one of the top `k`.
+    pub fn contains(&self, item: &str) -> bool {
+        self.top.iter().any(|(top, _)| top == item)
+    }
+
+    /// The top items with their estimated counts, the most frequent first.
+    pub fn list(&self) -> Vec<(&str, u64)> {
+        let mut list: Vec<_> = self
+            .top
+            .iter()
+            .map(|(item, count)| (item.as_str(), *count))
+            .collect();
+        list.sort_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(b.0)));
+        list
+    }
+
+    pub fn allocated_size(&self) -> usize {
+        self.buckets.len() * std::mem::size_of::<Bucket>()
+            + self
+                .top
+                .iter()
+                .map(|(item, _)| item.len() + std::mem::size_of::<(String, u64)>())
+                .sum::<usize>()
+    }
+
+    /// The Top-K in the binary form RDB snapshots store it in.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut out = Vec::with_capacity(24 + self.allocated_size());
+        out.extend(self.k.to_le_bytes());
+        out.extend(self.width.to_le_bytes());
+        out.extend(self.depth.to_le_bytes());
+        out.extend(self.decay.to_le_bytes());
+        for bucket in &self.buckets {
+            out.extend(bucket.fingerprint.to_le_bytes());
+            out.extend(bucket.count.to_le_bytes());
+        }
+        out.extend((self.top.len() as u32).to_le_bytes());
+        for (item, count) in &self.top {
+            out.extend((item.len() as u32).to_le_bytes());
+            out.extend(item.as_bytes());
+            out.extend(count.to_le_bytes());
+        }
+        out
+    }
+
+    /// Reads a Top-K written by `to_bytes`.
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, String> {
+        let mut input = Input(bytes);
+        let mut topk = TopK::new(
+            u32::from_le_bytes(input.take()?),
+            u32::from_le_bytes(input.take()?),
+            u32::from_le_bytes(input.take()?),
+            f64::from_le_bytes(input.take()?),
+        )?;
+        for bucket in &mut topk.buckets {
+            bucket.fingerprint = u64::from_le_bytes(input.take()?);
+            bucket.count = u64::from_le_bytes(input.take()?);
+        }
+        let len = u32::from_le_bytes(input.take()?);
+        for _ in 0..len {
+            let item = input.string()?;
+            topk.top.push((item, u64::from_le_bytes(input.take()?)));
+        }
+        if !input.0.is_empty() || topk.top.len() > topk.k as usize {
+            return Err("invalid Top-K".to_string());
+        }
+        Ok(topk)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn random() -> impl FnMut() -> u64 {
+        let mut state = 1u64;
+        move || {
+            state ^= state << 13;
+            state ^= state >> 7;
+            state ^= state << 17;
+            state
+        }
+    }
+
+    #[test]
+    fn test_count_min_sketch() {
+        let mut sketch = CountMinSketch::new(2000, 5).unwrap();
+        assert_eq!(sketch.increment(b"a", 3), 3);
+        assert_eq!(sketch.increment(b"a", 2), 5);
+        for i in 0..1000u32 {
+            sketch.increment(&i.to_le_bytes(), 1);
+        }
+        // Collisions only ever add to a count.
+        assert!(sketch.count(b"a") >= 5);
+        assert!(sketch.count(b"a") < 10);
+        assert_eq!(sketch.count(b"never"), 0);
+
+        let bytes = sketch.to_bytes();
+        assert_eq!(CountMinSketch::from_bytes(&bytes), Ok(sketch));
+        assert!(CountMinSketch::from_bytes(&bytes[..bytes.len() - 1]).is_err());
+        assert!(CountMinSketch::new(0, 5).is_err());
+    }
+
+    #[test]
+    fn test_topk() {
+        let mut topk = TopK::new(3, 50, 4, 0.9).unwrap();
+        let mut random = random();
+        // A few heavy hitters among many items seen once.
+        for i in 0..2000 {
+            let item = match i % 10 {
+                0..=2 => "hot".to_string(),
+                3 | 4 => "warm".to_string(),
+                5 => "mild".to_string(),
+                _ => format!("cold:{i}"),
+            };
+            topk.add(&item, &mut random);
+        }
+        let items: Vec<_> = topk.list().into_iter().map(|(item, _)| item).collect();
+        assert_eq!(items, ["hot", "warm", "mild"]);
+        assert!(topk.contains("hot"));
+        assert!(!topk.contains("cold:6"));
+
+        let bytes = topk.to_bytes();
+        assert_eq!(TopK::from_bytes(&bytes), Ok(topk));
+        assert!(TopK::from_bytes(&bytes[..bytes.len() - 1]).is_err());
+    }
+
+    #[test]
+    fn test_topk_expels_smallest() {
+        let mut topk = TopK::new(1, 8, 7, 0.9).unwrap();
+        let mut random = random();
+        assert_eq!(topk.add("a", &mut random), None);
+        assert_eq!(topk.add("b", &mut random), None);
+        assert_eq!(topk.add("b", &mut random), Some("a".to_string()));
+        assert_eq!(topk.list(), [("b", 2)]);
+    }
+}
@@ -0,0 +1,245 @@
+//! Tracing of connections and commands. The server always emits `tracing` spans, one per
+//! connection with a child per command, which cost next to nothing while nobody listens. Built
+//! with the `otel` feature and given an `otlp-endpoint`, it exports them over OTLP/HTTP so the
+//! server shows up in distributed traces next to its clients.
+
+use crate::resp::RespData;
+use std::net::SocketAddr;
+use tracing::field::Empty;
+use tracing::Span;
+
+/// The span covering a client connection.
+pub fn connection_span(id: u64, peer: SocketAddr) -> Span {
+    tracing::info_span!(
+        "connection",
+        otel.kind = "server",
+        client.id = id,
+        network.peer.address = %peer.ip(),
+        network.peer.port = peer.port(),
+    )
+}
+
+/// The span covering one command. Its details are filled in by `record_command`, so that
+/// nothing is computed for them while the span is disabled.
+pub fn command_span() -> Span {
+    tracing::info_span!(
+        "command",
+        otel.name = Empty,
+        db.system = "redis",
+        db.operation.name = Empty,
+        db.redis.key_count = Empty,
+        otel.status_code = Empty,
+        otel.status_description = Empty,
+        error.type = Empty,
+    )
+}
+
+/// Names `span` after the command, like Redis client instrumentation does.
+pub fn record_command(span: &Span, name: &str, key_count: usize) {
+    let name = name.to_uppercase();
+    span.record("otel.name", name.as_str());
+    span.record("db.operation.name", name.as_str());
+    span.record("db.redis.key_count", key_count);
+}
+
+/// Marks `span` as failed if `reply` is an error.
+pub fn record_reply(span: &Span, reply: &RespData) {
+    let RespData::Error(message) = reply else {
+        return;
+    };
+    let (code, description) = describe_error(message);
+    span.record("otel.status_code", "ERROR");
+    span.record("otel.status_description", description.as_str());
+    span.record("error.type", code);
+}
+
+/// The error code and the full text of an error reply as it goes out on the wire, where a
+/// message without its own `-CODE` prefix is sent as `-ERR message`.
+fn describe_error(message: &str) -> (&str, String) {
+    match message.strip_prefix('-') {
+        Some(line) => (line.split(' ').next().unwrap_or_default(), line.to_string()),
+        None => ("ERR", format!("ERR {message}")),
+    }
+}
+
+#[cfg(feature = "otel")]
+mod otlp {
+    use opentelemetry::trace::TracerProvider as _;
+    use opentelemetry_otlp::{SpanExporter, WithExportConfig};
+    use opentelemetry_sdk::trace::SdkTracerProvider;
+    use opentelemetry_sdk::Resource;
+    use std::sync::OnceLock;
+    use tracing_subscriber::layer::SubscriberExt;
+
+    static PROVIDER: OnceLock<SdkTracerProvider> = OnceLock::new();
+
+    pub fn init(endpoint: &str) -> Result<(), String> {
+        let exporter = SpanExporter::builder()
+            .with_http()
+            .with_endpoint(endpoint)
+            .build()
+            .map_err(|e| e.to_string())?;
+        let provider = SdkTracerProvider::builder()
+            .with_batch_exporter(exporter)
+            .with_resource(
+                Resource::builder()
+                    .with_service_name(env!("CARGO_PKG_NAME"))
+                    .build(),
+            )
+            .build();
+        let layer = tracing_opentelemetry::layer().with_tracer(provider.tracer("server"));
+        tracing::subscriber::set_global_default(tracing_subscriber::registry().with(layer))
+            .map_err(|e| e.to_string())?;
+        let _ = PROVIDER.set(provider);
+        Ok(())
+    }
+
+    pub fn shutdown() {
+        if let Some(provider) = PROVIDER.get() {
+            let _ = provider.shutdown();
+        }
+    }
+}
+
+/// Starts exporting spans to `endpoint`, e.g. `http://localhost:4318/v1/traces`.
+pub fn init(endpoint: &str) -> Result<(), String> {
+    #[cfg(feature = "otel")]
+    return otlp::init(endpoint);
+    #[cfg(not(feature = "otel"))]
+    {
+        let _ = endpoint;
+        Err("this server was built without the otel feature".to_string())
+    }
+}
+
+/// Exports the spans still buffered. Called before the process exits.
+pub fn shutdown() {
+    #[cfg(feature = "otel")]
+    otlp::shutdown();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::handler::CommandHandler;
+    use std::collections::HashMap;
+    use std::sync::{Arc, Mutex};
+    use tracing::field::{Field, Visit};
+    use tracing::span::{Attributes, Id, Record};
+    use tracing::Subscriber;
+    use tracing_subscriber::layer::{Context, Layer, SubscriberExt};
+
+    type Fields = Arc<Mutex<Vec<(String, String)>>>;
+
+    /// Collects the name and every field value of the spans created under it.
+    struct Recorder(Fields);
+
+    impl Visit for Recorder {
+        fn record_str(&mut self, field: &Field, value: &str) {
+            let entry = (field.name().to_string(), value.to_string());
+            self.0.lock().unwrap().push(entry);
+        }
+
+        fn record_debug(&mut self, field: &Field, value: &dyn std::fmt::Debug) {
+            let entry = (field.name().to_string(), format!("{value:?}"));
+            self.0.lock().unwrap().push(entry);
+        }
+    }
+
+    impl<S: Subscriber> Layer<S> for Recorder {
+        fn on_new_span(&self, attrs: &Attributes<'_>, _: &Id, _: Context<'_, S>) {
+            let span = ("span".to_string(), attrs.metadata().name().to_string());
+            self.0.lock().unwrap().push(span);
+            attrs.record(&mut Recorder(Arc::clone(&self.0)));
+        }
+
+        fn on_record(&self, _: &Id, values: &Record<'_>, _: Context<'_, S>) {
+            values.record(&mut Recorder(Arc::clone(&self.0)));
+        }
+    }
+
+    fn command(args: &[&str]) -> RespData {
+        RespData::array(args.iter().copied())
+    }
+
+    #[test]
+    fn test_command_span() {
+        let mut handler = CommandHandler::from(HashMap::new());
+        handler.handle(&command(&["SET", "s", "v"]));
+        let test_cases = [
+            (
+                "Single key",
+                command(&["get", "s"]),
+                vec![("otel.name", "GET"), ("db.redis.key_count", "1")],
+            ),
+            (
+                "Several keys",
+                command(&["DEL", "a", "b", "c"]),
+                vec![("otel.name", "DEL"), ("db.redis.key_count", "3")],
+            ),
+            (
+                "Key in a subcommand",
+                command(&["OBJECT", "ENCODING", "s"]),
+                vec![("otel.name", "OBJECT"), ("db.redis.key_count", "1")],
+            ),
+            (
+                "Error",
+                command(&["HGET", "s", "f"]),
+                vec![
+                    ("db.redis.key_count", "1"),
+                    ("otel.status_code", "ERROR"),
+                    ("error.type", "WRONGTYPE"),
+                ],
+            ),
+        ];
+
+        for (name, input, expected) in test_cases {
+            let fields = Fields::default();
+            let subscriber = tracing_subscriber::registry().with(Recorder(Arc::clone(&fields)));
+            tracing::subscriber::with_default(subscriber, || handler.handle(&input));
+
+            let fields = fields.lock().unwrap();
+            assert_eq!(
+                fields[0],
+                ("span".to_string(), "command".to_string()),
+                "{}",
+                name
+            );
+            assert!(
+                fields.contains(&("db.system".to_string(), "redis".to_string())),
+                "{}",
+                name
+            );
+            for (field, value) in expected {
+                let entry = (field.to_string(), value.to_string());
+                assert!(
+                    fields.contains(&entry),
+                    "{}: {entry:?} not in {fields:?}",
+                    name
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn test_describe_error() {
+        let test_cases = [
+            ("Plain message", "syntax error", ("ERR", "ERR syntax error")),
+            (
+                "Own code",
+                "-WRONGTYPE Operation against a key",
+                ("WRONGTYPE", "WRONGTYPE Operation against a key"),
+            ),
+            ("Code only", "-NOAUTH", ("NOAUTH", "NOAUTH")),
+        ];
+
+        for (name, input, (code, description)) in test_cases {
+            assert_eq!(
+                describe_error(input),
+                (code, description.to_string()),
+                "{}",
+                name
+            );
+        }
+    }
+}
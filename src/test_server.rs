@@ -0,0 +1,102 @@
+//! Runs a real server inside a test: listening on a free port on localhost, with its own
+//! data directory, and shut down when the `TestServer` is dropped so tests don't leak threads
+//! or sockets into each other.
+
+use crate::config::Config;
+use crate::server::Server;
+use std::fs;
+use std::net::{SocketAddr, TcpListener};
+use std::path::PathBuf;
+use std::process;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+/// Numbers the data directories of the servers started by this process.
+static NEXT_ID: AtomicUsize = AtomicUsize::new(0);
+
+pub struct TestServer {
+    pub addr: SocketAddr,
+    /// Where metrics are served, if `metrics_port` was configured.
+    pub metrics_addr: Option<SocketAddr>,
+    dir: PathBuf,
+    server: Option<Server>,
+}
+
+impl TestServer {
+    pub fn start() -> Self {
+        Self::with_config(|_| {})
+    }
+
+    /// Starts a server with the default config as changed by `configure`. Snapshots are off
+    /// unless `configure` adds save points.
+    pub fn with_config(configure: impl FnOnce(&mut Config)) -> Self {
+        let id = NEXT_ID.fetch_add(1, Ordering::Relaxed);
+        let dir = std::env::temp_dir().join(format!("redis-test-server-{}-{id}", process::id()));
+        fs::create_dir_all(&dir).unwrap();
+
+        let mut config = Config {
+            bind: vec!["127.0.0.1".to_string()],
+            port: 0,
+            save: Vec::new(),
+            dir: dir.clone(),
+            ..Config::default()
+        };
+        configure(&mut config);
+        // Port 0 has the builder keep the listener it found a free port with.
+        let server = Server::builder()
+            .config(|defaults| *defaults = config)
+            .spawn()
+            .unwrap();
+        Self {
+            addr: server.local_addr(),
+            metrics_addr: server.metrics_addr(),
+            dir,
+            server: Some(server),
+        }
+    }
+
+    /// A client for this server, authenticating with `password` if given.
+    pub fn client(&self, password: Option<&str>) -> redis::Client {
+        let auth = password.map(|p| format!(":{p}@")).unwrap_or_default();
+        redis::Client::open(format!("redis://{auth}{}/", self.addr)).unwrap()
+    }
+
+    pub fn connection(&self) -> redis::Connection {
+        self.client(None).get_connection().unwrap()
+    }
+}
+
+impl Drop for TestServer {
+    fn drop(&mut self) {
+        if let Some(server) = self.server.take() {
+            server.shutdown();
+        }
+        let _ = fs::remove_dir_all(&self.dir);
+    }
+}
+
+pub fn free_port() -> u16 {
+    TcpListener::bind("127.0.0.1:0")
+        .unwrap()
+        .local_addr()
+        .unwrap()
+        .port()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Read;
+    use std::net::TcpStream;
+
+    #[test]
+    fn test_shutdown() {
+        let server = TestServer::start();
+        let addr = server.addr;
+        let mut client = TcpStream::connect(addr).unwrap();
+        drop(server);
+
+        // Open connections are closed and no new ones are accepted.
+        assert_eq!(client.read(&mut [0; 1]).unwrap(), 0);
+        assert!(TcpStream::connect(addr).is_err());
+    }
+}
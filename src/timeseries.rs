@@ -0,0 +1,425 @@
+//! Time series for the TS.* commands, as in RedisTimeSeries: float samples at millisecond
+//! timestamps, kept for a retention window, with a policy for samples added at a timestamp
+//! that already has one, and labels for TS.MRANGE to select series by.
+
+use crate::bloom::Input;
+use std::collections::BTreeMap;
+use std::fmt;
+
+/// What to do with a sample added at a timestamp that already has one.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum DuplicatePolicy {
+    /// Refuse the new sample.
+    #[default]
+    Block,
+    /// Keep the old sample.
+    First,
+    /// Keep the new sample.
+    Last,
+    Min,
+    Max,
+    /// Add the two together.
+    Sum,
+}
+
+const DUPLICATE_POLICIES: [(DuplicatePolicy, &str); 6] = [
+    (DuplicatePolicy::Block, "BLOCK"),
+    (DuplicatePolicy::First, "FIRST"),
+    (DuplicatePolicy::Last, "LAST"),
+    (DuplicatePolicy::Min, "MIN"),
+    (DuplicatePolicy::Max, "MAX"),
+    (DuplicatePolicy::Sum, "SUM"),
+];
+
+impl DuplicatePolicy {
+    /// Parses a policy name, in any case.
+    pub fn parse(name: &str) -> Option<Self> {
+        DUPLICATE_POLICIES
+            .iter()
+            .find(|(_, policy)| policy.eq_ignore_ascii_case(name))
+            .map(|(policy, _)| *policy)
+    }
+
+    pub fn name(self) -> &'static str {
+        DUPLICATE_POLICIES
+            .iter()
+            .find(|(policy, _)| *policy == self)
+            .unwrap()
+            .1
+    }
+}
+
+/// How the samples of each bucket are combined by a range query's AGGREGATION.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Aggregation {
+    Avg,
+    Min,
+    Max,
+    Sum,
+}
+
+const AGGREGATIONS: [(Aggregation, &str); 4] = [
+    (Aggregation::Avg, "avg"),
+    (Aggregation::Min, "min"),
+    (Aggregation::Max, "max"),
+    (Aggregation::Sum, "sum"),
+];
+
+impl Aggregation {
+    /// Parses an aggregator name, in any case.
+    pub fn parse(name: &str) -> Option<Self> {
+        AGGREGATIONS
+            .iter()
+            .find(|(_, aggregation)| aggregation.eq_ignore_ascii_case(name))
+            .map(|(aggregation, _)| *aggregation)
+    }
+
+    pub fn name(self) -> &'static str {
+        AGGREGATIONS
+            .iter()
+            .find(|(aggregation, _)| *aggregation == self)
+            .unwrap()
+            .1
+    }
+
+    fn apply(self, values: &[f64]) -> f64 {
+        match self {
+            Aggregation::Avg => values.iter().sum::<f64>() / values.len() as f64,
+            Aggregation::Min => values.iter().copied().fold(f64::INFINITY, f64::min),
+            Aggregation::Max => values.iter().copied().fold(f64::NEG_INFINITY, f64::max),
+            Aggregation::Sum => values.iter().sum(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct TimeSeries {
+    /// How many milliseconds before the latest sample to keep samples for, 0 for ever.
+    retention: u64,
+    duplicate_policy: DuplicatePolicy,
+    labels: Vec<(String, String)>,
+    samples: BTreeMap<u64, f64>,
+}
+
+impl TimeSeries {
+    pub fn new(
+        retention: u64,
+        duplicate_policy: DuplicatePolicy,
+        labels: Vec<(String, String)>,
+    ) -> Self {
+        TimeSeries {
+            retention,
+            duplicate_policy,
+            labels,
+            samples: BTreeMap::new(),
+        }
+    }
+
+    pub fn labels(&self) -> &[(String, String)] {
+        &self.labels
+    }
+
+    /// How many samples the series holds.
+    pub fn len(&self) -> usize {
+        self.samples.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.samples.is_empty()
+    }
+
+    /// Adds a sample, settling a duplicate timestamp with `policy`, or the series' own policy
+    /// without one, then drops the samples that fell out of the retention window.
+    pub fn add(
+        &mut self,
+        timestamp: u64,
+        value: f64,
+        policy: Option<DuplicatePolicy>,
+    ) -> Result<(), String> {
+        let latest = self.samples.last_key_value().map(|(latest, _)| *latest);
+        if self.retention > 0
+            && latest.is_some_and(|latest| timestamp.saturating_add(self.retention) < latest)
+        {
+            return Err("Timestamp is older than retention".to_string());
+        }
+        match self.samples.get_mut(&timestamp) {
+            None => {
+                self.samples.insert(timestamp, value);
+            }
+            Some(old) => match policy.unwrap_or(self.duplicate_policy) {
+                DuplicatePolicy::Block => {
+                    return Err("Error at upsert, update is not supported when \
+                                DUPLICATE_POLICY is set to BLOCK mode"
+                        .to_string())
+                }
+                DuplicatePolicy::First => {}
+                DuplicatePolicy::Last => *old = value,
+                DuplicatePolicy::Min => *old = old.min(value),
+                DuplicatePolicy::Max => *old = old.max(value),
+                DuplicatePolicy::Sum => *old += value,
+            },
+        }
+        if self.retention > 0 {
+            let latest = *self.samples.last_key_value().unwrap().0;
+            self.samples = self
+                .samples
+                .split_off(&latest.saturating_sub(self.retention));
+        }
+        Ok(())
+    }
+
+    /// The samples from `from` to `to`, inclusive, oldest first. With an aggregation, one per
+    /// `bucket` milliseconds that has samples, at the bucket's start.
+    pub fn range(
+        &self,
+        from: u64,
+        to: u64,
+        aggregation: Option<(Aggregation, u64)>,
+    ) -> Vec<(u64, f64)> {
+        if from > to {
+            return Vec::new();
+        }
+        let samples = self.samples.range(from..=to).map(|(t, v)| (*t, *v));
+        let Some((aggregation, bucket)) = aggregation else {
+            return samples.collect();
+        };
+        let mut buckets: Vec<(u64, Vec<f64>)> = Vec::new();
+        for (timestamp, value) in samples {
+            let start = timestamp - timestamp % bucket;
+            match buckets.last_mut() {
+                Some((last, values)) if *last == start => values.push(value),
+                _ => buckets.push((start, vec![value])),
+            }
+        }
+        buckets
+            .into_iter()
+            .map(|(start, values)| (start, aggregation.apply(&values)))
+            .collect()
+    }
+
+    /// Roughly how many bytes the series takes up in memory.
+    pub fn allocated_size(&self) -> usize {
+        // A B-tree entry costs about twice its key and value.
+        self.samples.len() * 32
+            + self
+                .labels
+                .iter()
+                .map(|(name, value)| name.len() + value.len())
+                .sum::<usize>()
+    }
+
+    /// The series in the binary form RDB snapshots store it in.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut out = Vec::with_capacity(self.allocated_size() + 16);
+        out.extend(self.retention.to_le_bytes());
+        out.push(
+            DUPLICATE_POLICIES
+                .iter()
+                .position(|(policy, _)| *policy == self.duplicate_policy)
+                .unwrap() as u8,
+        );
+        out.extend((self.labels.len() as u32).to_le_bytes());
+        for (name, value) in &self.labels {
+            for text in [name, value] {
+                out.extend((text.len() as u32).to_le_bytes());
+                out.extend(text.as_bytes());
+            }
+        }
+        out.extend((self.samples.len() as u64).to_le_bytes());
+        for (timestamp, value) in &self.samples {
+            out.extend(timestamp.to_le_bytes());
+            out.extend(value.to_le_bytes());
+        }
+        out
+    }
+
+    /// Reads a series written by `to_bytes`.
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, String> {
+        let mut input = Input(bytes);
+        let retention = u64::from_le_bytes(input.take()?);
+        let [policy] = input.take()?;
+        let (duplicate_policy, _) = *DUPLICATE_POLICIES
+            .get(policy as usize)
+            .ok_or("invalid duplicate policy")?;
+        let mut series = TimeSeries::new(retention, duplicate_policy, Vec::new());
+        for _ in 0..u32::from_le_bytes(input.take()?) {
+            series.labels.push((input.string()?, input.string()?));
+        }
+        for _ in 0..u64::from_le_bytes(input.take()?) {
+            let timestamp = u64::from_le_bytes(input.take()?);
+            series
+                .samples
+                .insert(timestamp, f64::from_le_bytes(input.take()?));
+        }
+        if !input.0.is_empty() {
+            return Err("invalid time series".to_string());
+        }
+        Ok(series)
+    }
+}
+
+/// A TS.MRANGE filter on a series' labels, like `area=eu`, `area!=eu`, `area=(eu,us)`, or
+/// `area=` and `area!=` for a label that is missing or present. A missing label counts as
+/// an empty value.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LabelFilter {
+    label: String,
+    values: Vec<String>,
+    negated: bool,
+}
+
+impl LabelFilter {
+    pub fn parse(text: &str) -> Option<Self> {
+        let (label, negated, values) = match text.split_once("!=") {
+            Some((label, values)) => (label, true, values),
+            None => {
+                let (label, values) = text.split_once('=')?;
+                (label, false, values)
+            }
+        };
+        if label.is_empty() {
+            return None;
+        }
+        let values = match values.strip_prefix('(').and_then(|v| v.strip_suffix(')')) {
+            Some(list) => list.split(',').map(str::to_string).collect(),
+            None => vec![values.to_string()],
+        };
+        Some(LabelFilter {
+            label: label.to_string(),
+            values,
+            negated,
+        })
+    }
+
+    /// Whether the filter only matches series that have the label, which TS.MRANGE needs at
+    /// least one of so it doesn't select nearly everything.
+    pub fn is_positive(&self) -> bool {
+        !self.negated && self.values.iter().all(|value| !value.is_empty())
+    }
+
+    pub fn matches(&self, series: &TimeSeries) -> bool {
+        let value = series
+            .labels
+            .iter()
+            .find(|(name, _)| *name == self.label)
+            .map_or("", |(_, value)| value);
+        self.values.iter().any(|v| v == value) != self.negated
+    }
+}
+
+/// The filter as TS.MRANGE takes it.
+impl fmt::Display for LabelFilter {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let op = if self.negated { "!=" } else { "=" };
+        match self.values.as_slice() {
+            [value] => write!(f, "{}{op}{value}", self.label),
+            values => write!(f, "{}{op}({})", self.label, values.join(",")),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn series(policy: DuplicatePolicy) -> TimeSeries {
+        TimeSeries::new(0, policy, vec![("area".to_string(), "eu".to_string())])
+    }
+
+    #[test]
+    fn test_duplicate_policies() {
+        let test_cases = [
+            ("Block", DuplicatePolicy::Block, None),
+            ("First", DuplicatePolicy::First, Some(1.0)),
+            ("Last", DuplicatePolicy::Last, Some(3.0)),
+            ("Min", DuplicatePolicy::Min, Some(1.0)),
+            ("Max", DuplicatePolicy::Max, Some(3.0)),
+            ("Sum", DuplicatePolicy::Sum, Some(4.0)),
+        ];
+
+        for (name, policy, expected) in test_cases {
+            let mut series = series(policy);
+            series.add(10, 1.0, None).unwrap();
+            let added = series.add(10, 3.0, None);
+            assert_eq!(added.is_ok(), expected.is_some(), "{}", name);
+            assert_eq!(
+                series.range(0, u64::MAX, None),
+                [(10, expected.unwrap_or(1.0))],
+                "{}",
+                name
+            );
+        }
+        // A policy given with the sample overrides the series' own.
+        let mut series = series(DuplicatePolicy::Block);
+        series.add(10, 1.0, None).unwrap();
+        series.add(10, 2.0, Some(DuplicatePolicy::Sum)).unwrap();
+        assert_eq!(series.range(0, 10, None), [(10, 3.0)]);
+    }
+
+    #[test]
+    fn test_retention() {
+        let mut series = TimeSeries::new(100, DuplicatePolicy::Block, Vec::new());
+        for timestamp in [0, 50, 120, 200] {
+            series.add(timestamp, 1.0, None).unwrap();
+        }
+        assert_eq!(series.range(0, u64::MAX, None), [(120, 1.0), (200, 1.0)]);
+        assert_eq!(
+            series.add(99, 1.0, None),
+            Err("Timestamp is older than retention".to_string())
+        );
+        assert!(series.add(100, 1.0, None).is_ok());
+    }
+
+    #[test]
+    fn test_aggregation() {
+        let mut series = series(DuplicatePolicy::Block);
+        for (timestamp, value) in [(0, 1.0), (5, 3.0), (10, 10.0), (25, 2.0), (29, 4.0)] {
+            series.add(timestamp, value, None).unwrap();
+        }
+        let test_cases = [
+            ("avg", Aggregation::Avg, [(0, 2.0), (10, 10.0), (20, 3.0)]),
+            ("min", Aggregation::Min, [(0, 1.0), (10, 10.0), (20, 2.0)]),
+            ("max", Aggregation::Max, [(0, 3.0), (10, 10.0), (20, 4.0)]),
+            ("sum", Aggregation::Sum, [(0, 4.0), (10, 10.0), (20, 6.0)]),
+        ];
+
+        for (name, aggregation, expected) in test_cases {
+            assert_eq!(
+                series.range(0, 100, Some((aggregation, 10))),
+                expected,
+                "{}",
+                name
+            );
+        }
+        assert_eq!(series.range(5, 10, None), [(5, 3.0), (10, 10.0)]);
+        assert!(series.range(10, 5, None).is_empty());
+
+        let bytes = series.to_bytes();
+        assert_eq!(TimeSeries::from_bytes(&bytes), Ok(series));
+        assert!(TimeSeries::from_bytes(&bytes[..bytes.len() - 1]).is_err());
+    }
+
+    #[test]
+    fn test_label_filters() {
+        let eu = series(DuplicatePolicy::Block);
+        let test_cases = [
+            ("Equal", "area=eu", true),
+            ("Not equal", "area!=eu", false),
+            ("One of", "area=(us,eu)", true),
+            ("None of", "area!=(us,eu)", false),
+            ("Missing", "area=", false),
+            ("Present", "area!=", true),
+            ("Other label missing", "host=", true),
+        ];
+
+        for (name, filter, expected) in test_cases {
+            let parsed = LabelFilter::parse(filter).unwrap();
+            assert_eq!(parsed.matches(&eu), expected, "{}", name);
+            assert_eq!(parsed.to_string(), filter, "{}", name);
+        }
+        assert!(LabelFilter::parse("area=eu").unwrap().is_positive());
+        assert!(!LabelFilter::parse("area!=eu").unwrap().is_positive());
+        assert_eq!(LabelFilter::parse("area"), None);
+        assert_eq!(LabelFilter::parse("=eu"), None);
+    }
+}